@@ -0,0 +1,83 @@
+//! Single ADC conversion started by a button and read in the ADC interrupt
+//!
+//! The button on PA0 (KEY on the WeAct black pill) starts a conversion of PA1 from the EXTI
+//! interrupt, the ADC interrupt then reads the sample without waiting for it.
+
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    adc::{config::AdcConfig, config::SampleTime, config::Sequence, Adc, Event},
+    gpio::{Edge, Input, PA0},
+    pac::{self, interrupt, ADC1},
+    prelude::*,
+};
+
+use core::cell::{Cell, RefCell};
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+
+static BUTTON: Mutex<RefCell<Option<PA0<Input>>>> = Mutex::new(RefCell::new(None));
+static G_ADC: Mutex<RefCell<Option<Adc<ADC1>>>> = Mutex::new(RefCell::new(None));
+static MILLIVOLTS: Mutex<Cell<u16>> = Mutex::new(Cell::new(0));
+
+#[entry]
+fn main() -> ! {
+    let mut dp = pac::Peripherals::take().unwrap();
+    let mut syscfg = dp.SYSCFG.constrain();
+
+    let gpioa = dp.GPIOA.split();
+    let mut button = gpioa.pa0.into_pull_up_input();
+    button.make_interrupt_source(&mut syscfg);
+    button.enable_interrupt(&mut dp.EXTI);
+    button.trigger_on_edge(&mut dp.EXTI, Edge::Falling);
+
+    let pa1 = gpioa.pa1.into_analog();
+    let mut adc = Adc::adc1(dp.ADC1, true, AdcConfig::default());
+    adc.configure_channel(&pa1, Sequence::One, SampleTime::Cycles_480);
+    adc.listen(Event::EndOfConversion);
+
+    let button_interrupt = button.interrupt();
+    cortex_m::interrupt::free(|cs| {
+        BUTTON.borrow(cs).replace(Some(button));
+        G_ADC.borrow(cs).replace(Some(adc));
+    });
+    unsafe {
+        pac::NVIC::unmask(button_interrupt);
+        pac::NVIC::unmask(pac::Interrupt::ADC);
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+        let _millivolts = cortex_m::interrupt::free(|cs| MILLIVOLTS.borrow(cs).get());
+    }
+}
+
+#[interrupt]
+fn EXTI0() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(button) = BUTTON.borrow(cs).borrow_mut().as_mut() {
+            button.clear_interrupt_pending_bit();
+        }
+        if let Some(adc) = G_ADC.borrow(cs).borrow_mut().as_mut() {
+            adc.start_conversion();
+        }
+    });
+}
+
+#[interrupt]
+fn ADC() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(adc) = G_ADC.borrow(cs).borrow_mut().as_mut() {
+            if adc.is_pending(Event::EndOfConversion) {
+                // Reading the sample clears the flag
+                let sample = adc.current_sample();
+                MILLIVOLTS.borrow(cs).set(adc.sample_to_millivolts(sample));
+            }
+        }
+    });
+}
@@ -0,0 +1,82 @@
+//! Flags set in an interrupt handler and polled in the main loop, without critical sections
+//!
+//! This assumes that a LED is connected to pa5 (sck/d13) as is the case on most nucleo board.
+
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    bitband_array,
+    pac::{interrupt, Interrupt, Peripherals, TIM2},
+    prelude::*,
+    timer::{CounterUs, Event},
+};
+
+use core::cell::RefCell;
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+
+const TICK: usize = 0;
+const OVERRUN: usize = 1;
+
+bitband_array!(static FLAGS: [BitFlag; 2]);
+
+static G_TIM: Mutex<RefCell<Option<CounterUs<TIM2>>>> = Mutex::new(RefCell::new(None));
+
+#[interrupt]
+fn TIM2() {
+    static mut TIM: Option<CounterUs<TIM2>> = None;
+
+    let tim = TIM.get_or_insert_with(|| {
+        cortex_m::interrupt::free(|cs| G_TIM.borrow(cs).replace(None).unwrap())
+    });
+    let _ = tim.wait();
+
+    let tick = FLAGS.flag(TICK);
+    if tick.get() {
+        // The main loop missed the previous tick
+        FLAGS.flag(OVERRUN).set();
+    }
+    tick.set();
+}
+
+#[entry]
+fn main() -> ! {
+    let dp = Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.sysclk(16.MHz()).freeze();
+
+    let gpioa = dp.GPIOA.split();
+    let mut led = gpioa.pa5.into_push_pull_output();
+
+    let mut timer = dp.TIM2.counter(&clocks);
+    timer.start(500.millis()).unwrap();
+    timer.listen(Event::Update);
+    cortex_m::interrupt::free(|cs| *G_TIM.borrow(cs).borrow_mut() = Some(timer));
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(Interrupt::TIM2);
+    }
+
+    let tick = FLAGS.flag(TICK);
+    let overrun = FLAGS.flag(OVERRUN);
+    loop {
+        if tick.get() {
+            tick.clear();
+            led.toggle();
+        }
+        if overrun.get() {
+            // Stay on to show the overrun
+            led.set_high();
+            loop {
+                cortex_m::asm::wfi();
+            }
+        }
+        cortex_m::asm::wfi();
+    }
+}
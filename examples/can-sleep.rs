@@ -0,0 +1,64 @@
+//! Puts a CAN node to sleep after 5 s without traffic and wakes it up on bus activity
+//!
+//! Requires a transceiver with a standby pin, e.g. a TJA1042, connected to PB8, PB9 (CAN1)
+//! and its `STB` pin to PB7.
+
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use bxcan::filter::Mask32;
+use bxcan::Fifo;
+use cortex_m_rt::entry;
+use stm32f4xx_hal::{
+    can::{Standby, WakeCause},
+    pac,
+    prelude::*,
+};
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.use_hse(8.MHz()).freeze();
+
+    let gpiob = dp.GPIOB.split();
+    let mut standby = Standby::new(gpiob.pb7.into_push_pull_output()).unwrap();
+
+    let mut can = {
+        let rx = gpiob.pb8.into_alternate::<9>();
+        let tx = gpiob.pb9.into_alternate();
+
+        bxcan::Can::builder(dp.CAN1.can((tx, rx)))
+            // APB1 (PCLK1): 8MHz, Bit rate: 500kBit/s, Sample Point 87.5%
+            .set_bit_timing(0x001c_0000)
+            .enable()
+    };
+    can.modify_filters()
+        .enable_bank(0, Fifo::Fifo0, Mask32::accept_all());
+
+    let mut idle = dp.TIM2.counter_ms(&clocks);
+    idle.start(5.secs()).unwrap();
+
+    loop {
+        if can.receive().is_ok() {
+            // Traffic, restart the idle timeout
+            idle.start(5.secs()).unwrap();
+        }
+
+        if idle.wait().is_ok() {
+            // The controller wakes up by itself on the next start of frame
+            standby.sleep(can.instance(), true).unwrap();
+            while can.instance().is_sleeping() {}
+
+            // Bring the transceiver back to normal mode to receive the following frames
+            standby.wake(can.instance()).unwrap();
+            // The frame that woke the node up was not received
+            let _woken_by_bus = can.instance().wake_cause() == WakeCause::Bus;
+
+            idle.start(5.secs()).unwrap();
+        }
+    }
+}
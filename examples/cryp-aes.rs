@@ -0,0 +1,132 @@
+//! Checks the CRYP processor against the FIPS-197 and NIST SP 800-38A AES test vectors.
+//!
+//! Runs on any STM32F415/F417/F437/F439/F479 board, results are printed over RTT.
+
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    cryp::{Cryp, Key, Mode},
+    pac,
+};
+
+/// Decodes a hexadecimal string
+const fn hex<const N: usize>(s: &str) -> [u8; N] {
+    const fn nibble(c: u8) -> u8 {
+        match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            _ => panic!("invalid hex digit"),
+        }
+    }
+    let s = s.as_bytes();
+    let mut bytes = [0; N];
+    let mut i = 0;
+    while i < N {
+        bytes[i] = nibble(s[2 * i]) << 4 | nibble(s[2 * i + 1]);
+        i += 1;
+    }
+    bytes
+}
+
+/// SP 800-38A key for AES-128
+const KEY_128: [u8; 16] = hex("2b7e151628aed2a6abf7158809cf4f3c");
+/// SP 800-38A plaintext, first two blocks
+const PLAINTEXT: [u8; 32] = hex("6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51");
+
+struct Vector<'a> {
+    name: &'a str,
+    key: Key,
+    mode: Mode,
+    plaintext: &'a [u8],
+    ciphertext: &'a [u8],
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+
+    let dp = pac::Peripherals::take().unwrap();
+    let mut cryp = Cryp::new(dp.CRYP);
+
+    let vectors = [
+        Vector {
+            name: "FIPS-197 C.1 AES-128",
+            key: Key::Aes128(hex("000102030405060708090a0b0c0d0e0f")),
+            mode: Mode::Ecb,
+            plaintext: &hex::<16>("00112233445566778899aabbccddeeff"),
+            ciphertext: &hex::<16>("69c4e0d86a7b0430d8cdb78070b4c55a"),
+        },
+        Vector {
+            name: "FIPS-197 C.2 AES-192",
+            key: Key::Aes192(hex("000102030405060708090a0b0c0d0e0f1011121314151617")),
+            mode: Mode::Ecb,
+            plaintext: &hex::<16>("00112233445566778899aabbccddeeff"),
+            ciphertext: &hex::<16>("dda97ca4864cdfe06eaf70a0ec0d7191"),
+        },
+        Vector {
+            name: "FIPS-197 C.3 AES-256",
+            key: Key::Aes256(hex(
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+            )),
+            mode: Mode::Ecb,
+            plaintext: &hex::<16>("00112233445566778899aabbccddeeff"),
+            ciphertext: &hex::<16>("8ea2b7ca516745bfeafc49904b496089"),
+        },
+        Vector {
+            name: "SP 800-38A F.1.1 ECB-AES128",
+            key: Key::Aes128(KEY_128),
+            mode: Mode::Ecb,
+            plaintext: &PLAINTEXT,
+            ciphertext: &hex::<32>(
+                "3ad77bb40d7a3660a89ecaf32466ef97f5d3d58503b9699de785895a96fdbaaf",
+            ),
+        },
+        Vector {
+            name: "SP 800-38A F.2.1 CBC-AES128",
+            key: Key::Aes128(KEY_128),
+            mode: Mode::Cbc(hex("000102030405060708090a0b0c0d0e0f")),
+            plaintext: &PLAINTEXT,
+            ciphertext: &hex::<32>(
+                "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b2",
+            ),
+        },
+        Vector {
+            name: "SP 800-38A F.5.1 CTR-AES128",
+            key: Key::Aes128(KEY_128),
+            mode: Mode::Ctr(hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff")),
+            plaintext: &PLAINTEXT,
+            ciphertext: &hex::<32>(
+                "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff",
+            ),
+        },
+    ];
+
+    let mut buffer = [0; 32];
+    for vector in &vectors {
+        let output = &mut buffer[..vector.plaintext.len()];
+
+        cryp.encrypt_blocks(&vector.key, vector.mode, vector.plaintext, output)
+            .unwrap();
+        let encrypted = output == vector.ciphertext;
+
+        cryp.decrypt_blocks(&vector.key, vector.mode, vector.ciphertext, output)
+            .unwrap();
+        let decrypted = output == vector.plaintext;
+
+        rprintln!(
+            "{}: encrypt {}, decrypt {}",
+            vector.name,
+            if encrypted { "ok" } else { "FAILED" },
+            if decrypted { "ok" } else { "FAILED" },
+        );
+    }
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
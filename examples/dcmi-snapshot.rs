@@ -0,0 +1,85 @@
+//! Captures a QVGA RGB565 frame from a camera sensor to RAM with the DCMI and DMA2.
+//!
+//! A QVGA RGB565 frame takes 150 KiB, which fits in the internal RAM of the F42x/F43x but
+//! not of the F407/F417, use a crop window or QQVGA on those.
+//!
+//! The sensor (e.g. an OV7670 or OV2640) must already be clocked and configured for
+//! 320 × 240 RGB565 output over SCCB/I2C with its driver, with HSYNC and VSYNC active low
+//! during blanking.
+
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    dcmi::{CaptureMode, Config, Dcmi, DcmiPins},
+    dma::{config::DmaConfig, StreamsTuple, Transfer},
+    pac,
+    prelude::*,
+};
+
+const WORDS: usize = 320 * 240 * 2 / 4;
+
+static mut FRAME: [u32; WORDS] = [0; WORDS];
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+
+    let dp = pac::Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+    let _clocks = rcc.cfgr.use_hse(8.MHz()).sysclk(168.MHz()).freeze();
+
+    let gpioa = dp.GPIOA.split();
+    let gpiob = dp.GPIOB.split();
+    let gpioc = dp.GPIOC.split();
+    let gpioe = dp.GPIOE.split();
+
+    let pins = DcmiPins {
+        data: (
+            gpioc.pc6.into_alternate(),
+            gpioc.pc7.into_alternate(),
+            gpioe.pe0.into_alternate(),
+            gpioe.pe1.into_alternate(),
+            gpioe.pe4.into_alternate(),
+            gpiob.pb6.into_alternate(),
+            gpioe.pe5.into_alternate(),
+            gpioe.pe6.into_alternate(),
+        ),
+        hsync: gpioa.pa4.into_alternate(),
+        vsync: gpiob.pb7.into_alternate(),
+        pixel_clock: gpioa.pa6.into_alternate(),
+    };
+    let dcmi = Dcmi::new(
+        dp.DCMI,
+        pins,
+        Config::default().capture_mode(CaptureMode::Snapshot),
+    )
+    .unwrap();
+
+    // NOTE(unsafe) the only reference to the frame buffer
+    let frame = unsafe { &mut FRAME };
+
+    let streams = StreamsTuple::new(dp.DMA2);
+    let config = DmaConfig::default()
+        .memory_increment(true)
+        .fifo_enable(true);
+    let mut transfer = Transfer::init_peripheral_to_memory(streams.1, dcmi, frame, None, config);
+
+    transfer.start(|dcmi| dcmi.start_capture());
+    while !transfer.is_transfer_complete() {}
+    let (_stream, _dcmi, frame, _) = transfer.release();
+
+    // Print the first pixels, two RGB565 pixels per word
+    for word in &frame[..8] {
+        rprintln!("{:04x} {:04x}", word & 0xffff, word >> 16);
+    }
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
@@ -0,0 +1,95 @@
+//! Debounces three buttons polled from a single 1 kHz TIM7 interrupt
+//!
+//! The buttons connect PE2, PE3 and PE4 to ground. Each button toggles one of the LEDs of
+//! the STM32F4DISCOVERY board (PD12, PD13 and PD14) when pressed.
+
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    gpio::{DebouncedInput, Input, Output, PinState, PD12, PD13, PD14, PE2, PE3, PE4},
+    pac::{interrupt, Interrupt, Peripherals, TIM7},
+    prelude::*,
+    timer::{CounterUs, Event},
+};
+
+use core::cell::RefCell;
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+
+// 20 samples at 1 kHz filter 20 ms of bouncing
+type Button<PIN> = DebouncedInput<PIN, 20>;
+
+struct Buttons {
+    buttons: (Button<PE2<Input>>, Button<PE3<Input>>, Button<PE4<Input>>),
+    leds: (PD12<Output>, PD13<Output>, PD14<Output>),
+    tim: CounterUs<TIM7>,
+}
+
+static G_BUTTONS: Mutex<RefCell<Option<Buttons>>> = Mutex::new(RefCell::new(None));
+
+#[interrupt]
+fn TIM7() {
+    static mut BUTTONS: Option<Buttons> = None;
+
+    let Buttons { buttons, leds, tim } = BUTTONS.get_or_insert_with(|| {
+        cortex_m::interrupt::free(|cs| G_BUTTONS.borrow(cs).replace(None).unwrap())
+    });
+    let _ = tim.wait();
+
+    buttons.0.poll().unwrap();
+    buttons.1.poll().unwrap();
+    buttons.2.poll().unwrap();
+
+    if buttons.0.rising_event() {
+        leds.0.toggle();
+    }
+    if buttons.1.rising_event() {
+        leds.1.toggle();
+    }
+    if buttons.2.rising_event() {
+        leds.2.toggle();
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    let dp = Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.sysclk(48.MHz()).freeze();
+
+    let gpiod = dp.GPIOD.split();
+    let gpioe = dp.GPIOE.split();
+
+    let buttons = (
+        Button::new(gpioe.pe2.into_pull_up_input(), PinState::Low),
+        Button::new(gpioe.pe3.into_pull_up_input(), PinState::Low),
+        Button::new(gpioe.pe4.into_pull_up_input(), PinState::Low),
+    );
+    let leds = (
+        gpiod.pd12.into_push_pull_output(),
+        gpiod.pd13.into_push_pull_output(),
+        gpiod.pd14.into_push_pull_output(),
+    );
+
+    let mut tim = dp.TIM7.counter_us(&clocks);
+    tim.start(1.millis()).unwrap();
+    tim.listen(Event::Update);
+
+    cortex_m::interrupt::free(|cs| {
+        *G_BUTTONS.borrow(cs).borrow_mut() = Some(Buttons { buttons, leds, tim })
+    });
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(Interrupt::TIM7);
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
@@ -0,0 +1,118 @@
+//! Two PDM microphones captured at 16 kHz into a ring buffer of interleaved 16-bit frames
+//!
+//! Both microphones share the clock on PC2 (`DFSDM1_CKOUT`) and the data line on PB1
+//! (`DFSDM1_DATIN1`), the left one driving data on the clock falling edge. Filter 0 and
+//! filter 1 conversions are moved by DMA2 streams 0 and 1 into double buffers, which are
+//! copied to the ring buffer as they complete.
+
+#![no_main]
+#![no_std]
+
+use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
+
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    dfsdm::{Config, Dfsdm, FilterData},
+    dma::{config::DmaConfig, PeripheralToMemory, Stream0, Stream1, StreamsTuple, Transfer},
+    pac::{self, DMA2},
+    prelude::*,
+};
+
+use cortex_m_rt::entry;
+
+/// Samples per DMA buffer, 4 ms
+const BLOCK: usize = 64;
+/// Stereo frames in the ring buffer, 64 ms
+const RING: usize = 16 * BLOCK;
+
+type Buffer = &'static mut [u32; BLOCK];
+type LeftTransfer = Transfer<Stream0<DMA2>, 7, FilterData<0>, PeripheralToMemory, Buffer>;
+type RightTransfer = Transfer<Stream1<DMA2>, 3, FilterData<1>, PeripheralToMemory, Buffer>;
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.use_hse(8.MHz()).sysclk(96.MHz()).freeze();
+
+    let gpiob = dp.GPIOB.split();
+    let gpioc = dp.GPIOC.split();
+    let _clock = gpioc.pc2.into_alternate::<6>();
+    let _data = gpiob.pb1.into_alternate::<6>();
+
+    // 3.2 MHz microphone clock, Sinc3 filter decimating by 200
+    let config = Config::pdm_microphone_at(1, clocks.pclk2(), 16.kHz()).unwrap();
+    rprintln!(
+        "microphone clock {} Hz, {} Hz",
+        config.microphone_clock(clocks.pclk2()).raw(),
+        config.sample_rate(clocks.pclk2()).raw()
+    );
+
+    let mut dfsdm = Dfsdm::stereo(dp.DFSDM, config);
+    dfsdm.enable_dma();
+    let (left_data, right_data) = dfsdm.dma_sources().unwrap();
+
+    let dma_config = DmaConfig::default()
+        .memory_increment(true)
+        .double_buffer(true);
+    let streams = StreamsTuple::new(dp.DMA2);
+    let mut left: LeftTransfer = Transfer::init_peripheral_to_memory(
+        streams.0,
+        left_data,
+        cortex_m::singleton!(: [u32; BLOCK] = [0; BLOCK]).unwrap(),
+        Some(cortex_m::singleton!(: [u32; BLOCK] = [0; BLOCK]).unwrap()),
+        dma_config,
+    );
+    let mut right: RightTransfer = Transfer::init_peripheral_to_memory(
+        streams.1,
+        right_data,
+        cortex_m::singleton!(: [u32; BLOCK] = [0; BLOCK]).unwrap(),
+        Some(cortex_m::singleton!(: [u32; BLOCK] = [0; BLOCK]).unwrap()),
+        dma_config,
+    );
+    left.start(|_| {});
+    right.start(|_| {});
+    dfsdm.start();
+
+    let ring = cortex_m::singleton!(: [i16; 2 * RING] = [0; 2 * RING]).unwrap();
+    let mut write = 0;
+    let mut blocks = 0u32;
+
+    loop {
+        let frames = &mut ring[2 * write..2 * (write + BLOCK)];
+        // NOTE(unsafe) both buffers are copied well within the 4 ms the next ones take
+        while unsafe {
+            left.next_transfer_with(|buf, _| {
+                scale(buf, frames.iter_mut().step_by(2), &config);
+                (buf, ())
+            })
+        }
+        .is_err()
+        {}
+        while unsafe {
+            right.next_transfer_with(|buf, _| {
+                scale(buf, frames.iter_mut().skip(1).step_by(2), &config);
+                (buf, ())
+            })
+        }
+        .is_err()
+        {}
+        write = (write + BLOCK) % RING;
+
+        blocks += 1;
+        if blocks % 250 == 0 {
+            rprintln!("{} s, left {} right {}", blocks / 250, frames[0], frames[1]);
+        }
+    }
+}
+
+/// Scales the data register values of `buf` to 16-bit `samples`
+fn scale<'a>(buf: &[u32; BLOCK], samples: impl Iterator<Item = &'a mut i16>, config: &Config) {
+    for (sample, &data) in samples.zip(buf.iter()) {
+        *sample = config.to_i16(Dfsdm::sample(data));
+    }
+}
@@ -0,0 +1,65 @@
+//! Compares clearing a 240×320 RGB565 framebuffer in software and with the DMA2D.
+//!
+//! Runs on a 32F429IDISCOVERY, results are printed over RTT.
+
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    dma2d::{Dma2d, PixelFormat, Surface},
+    dwt::DwtExt,
+    pac,
+    prelude::*,
+};
+
+const WIDTH: u16 = 240;
+const HEIGHT: u16 = 320;
+
+static mut FRAMEBUFFER: [u16; WIDTH as usize * HEIGHT as usize] =
+    [0; WIDTH as usize * HEIGHT as usize];
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+
+    let dp = pac::Peripherals::take().unwrap();
+    let cp = cortex_m::peripheral::Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.use_hse(8.MHz()).sysclk(168.MHz()).freeze();
+    let dwt = cp.DWT.constrain(cp.DCB, &clocks);
+
+    // NOTE(unsafe) the only reference to the framebuffer
+    let framebuffer = unsafe { &mut FRAMEBUFFER };
+
+    let software = dwt.measure(|| {
+        for pixel in framebuffer.iter_mut() {
+            // Prevent the loop from being turned into a memset
+            unsafe { core::ptr::write_volatile(pixel, 0x001f) };
+        }
+    });
+
+    let mut dma2d = Dma2d::new(dp.DMA2D);
+    let mut screen =
+        Surface::new(&mut framebuffer[..], PixelFormat::Rgb565, WIDTH, HEIGHT, 0).unwrap();
+    let hardware = dwt.measure(|| dma2d.fill_rect(&mut screen, 0xf800).unwrap());
+
+    rprintln!(
+        "software clear: {} cycles, {} us",
+        software.as_ticks(),
+        software.as_micros()
+    );
+    rprintln!(
+        "DMA2D clear: {} cycles, {} us",
+        hardware.as_ticks(),
+        hardware.as_micros()
+    );
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
@@ -0,0 +1,132 @@
+//! TCP echo server on port 7 with smoltcp, at 192.168.1.100/24.
+//!
+//! Uses the RMII pins of the Nucleo-F429ZI and its LAN8742A PHY at MDIO address 0. Other
+//! boards with a LAN8720 or similar PHY only need other pins.
+
+#![no_main]
+#![no_std]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m_rt::{entry, exception};
+use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f4xx_hal as hal;
+
+use smoltcp::iface::{InterfaceBuilder, NeighborCache, SocketStorage};
+use smoltcp::socket::{TcpSocket, TcpSocketBuffer};
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr};
+
+use crate::hal::{
+    ethernet::{Config, Ethernet, LinkChange, Phy, RmiiPins, RxRingEntry, TxRingEntry},
+    pac,
+    prelude::*,
+};
+
+const MAC_ADDRESS: [u8; 6] = [0x02, 0x00, 0x05, 0x06, 0x07, 0x08];
+
+static mut RX_RING: [RxRingEntry; 4] = [RxRingEntry::INIT; 4];
+static mut TX_RING: [TxRingEntry; 2] = [TxRingEntry::INIT; 2];
+
+static MILLIS: AtomicU32 = AtomicU32::new(0);
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+
+    let dp = pac::Peripherals::take().unwrap();
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.use_hse(8.MHz()).sysclk(168.MHz()).freeze();
+    let mut syscfg = dp.SYSCFG.constrain();
+
+    cp.SYST.set_clock_source(SystClkSource::Core);
+    cp.SYST.set_reload(clocks.hclk().raw() / 1000 - 1);
+    cp.SYST.clear_current();
+    cp.SYST.enable_interrupt();
+    cp.SYST.enable_counter();
+
+    let gpioa = dp.GPIOA.split();
+    let gpiob = dp.GPIOB.split();
+    let gpioc = dp.GPIOC.split();
+    let gpiog = dp.GPIOG.split();
+
+    let pins = RmiiPins {
+        ref_clk: gpioa.pa1.into_alternate(),
+        crs_dv: gpioa.pa7.into_alternate(),
+        rxd0: gpioc.pc4.into_alternate(),
+        rxd1: gpioc.pc5.into_alternate(),
+        tx_en: gpiog.pg11.into_alternate(),
+        txd0: gpiog.pg13.into_alternate(),
+        txd1: gpiob.pb13.into_alternate(),
+        mdio: gpioa.pa2.into_alternate(),
+        mdc: gpioc.pc1.into_alternate(),
+    };
+    // NOTE(unsafe) the only references to the rings
+    let (rx_ring, tx_ring) = unsafe { (&mut RX_RING, &mut TX_RING) };
+    let mut eth = Ethernet::new(
+        (dp.ETHERNET_MAC, dp.ETHERNET_DMA),
+        pins,
+        rx_ring,
+        tx_ring,
+        Config::new(MAC_ADDRESS),
+        &mut syscfg,
+        &clocks,
+    )
+    .unwrap();
+
+    let phy = Phy::new(0);
+    phy.reset(&mut eth.mdio());
+    phy.start_autonegotiation(&mut eth.mdio());
+
+    let mut neighbor_storage = [None; 8];
+    let mut ip_addrs = [IpCidr::new(IpAddress::v4(192, 168, 1, 100), 24)];
+    let mut socket_storage: [SocketStorage; 1] = Default::default();
+    let mut iface = InterfaceBuilder::new(eth, &mut socket_storage[..])
+        .hardware_addr(EthernetAddress(MAC_ADDRESS).into())
+        .neighbor_cache(NeighborCache::new(&mut neighbor_storage[..]))
+        .ip_addrs(&mut ip_addrs[..])
+        .finalize();
+
+    let mut rx_storage = [0; 1024];
+    let mut tx_storage = [0; 1024];
+    let socket = TcpSocket::new(
+        TcpSocketBuffer::new(&mut rx_storage[..]),
+        TcpSocketBuffer::new(&mut tx_storage[..]),
+    );
+    let handle = iface.add_socket(socket);
+
+    loop {
+        match iface.device_mut().poll_link(&phy) {
+            Some(LinkChange::Up(link)) => rprintln!("link up: {:?}", link),
+            Some(LinkChange::Down) => rprintln!("link down"),
+            None => {}
+        }
+
+        let now = Instant::from_millis(MILLIS.load(Ordering::Relaxed));
+        if let Err(e) = iface.poll(now) {
+            rprintln!("poll error: {:?}", e);
+        }
+
+        let socket = iface.get_socket::<TcpSocket>(handle);
+        if !socket.is_open() {
+            socket.listen(7).unwrap();
+        }
+        if socket.can_recv() && socket.can_send() {
+            let mut buf = [0; 512];
+            let len = socket.send_capacity() - socket.send_queue();
+            let len = len.min(buf.len());
+            if let Ok(n) = socket.recv_slice(&mut buf[..len]) {
+                socket.send_slice(&buf[..n]).unwrap();
+            }
+        }
+    }
+}
+
+#[exception]
+fn SysTick() {
+    MILLIS.fetch_add(1, Ordering::Relaxed);
+}
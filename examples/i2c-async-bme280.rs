@@ -0,0 +1,105 @@
+//! Reads a BME280 with the interrupt driven async I2C master while a PWM control loop
+//! keeps running at 1 kHz on the same core.
+//!
+//! The BME280 is connected to PB8 (SCL) and PB9 (SDA), the PWM output is on PA8. Both
+//! tasks are polled by `embassy_futures::block_on`, no executor is needed.
+
+#![no_main]
+#![no_std]
+
+use panic_rtt_target as _;
+
+use cortex_m_rt::entry;
+use embassy_futures::{block_on, join::join, yield_now};
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f4xx_hal::{
+    gpio::{OpenDrain, AF4, PB8, PB9},
+    i2c::{self, asynch::AsyncI2c, I2c},
+    pac::{self, interrupt},
+    prelude::*,
+    timer::{CounterUs, PwmChannel},
+};
+
+type Bme280I2c = AsyncI2c<pac::I2C1, (PB8<AF4<OpenDrain>>, PB9<AF4<OpenDrain>>)>;
+
+const BME280_ADDR: u8 = 0x76;
+
+#[interrupt]
+fn I2C1_EV() {
+    i2c::asynch::on_event::<pac::I2C1>();
+}
+
+#[interrupt]
+fn I2C1_ER() {
+    i2c::asynch::on_error::<pac::I2C1>();
+}
+
+async fn bme280(mut i2c: Bme280I2c) {
+    let mut id = [0];
+    i2c.write_read(BME280_ADDR, &[0xd0], &mut id).await.unwrap();
+    rprintln!("BME280 chip id: {:#x}", id[0]);
+
+    // Humidity, temperature and pressure oversampling x1, normal mode
+    i2c.write(BME280_ADDR, &[0xf2, 0b001]).await.unwrap();
+    i2c.write(BME280_ADDR, &[0xf4, 0b001_001_11]).await.unwrap();
+
+    loop {
+        let mut raw = [0; 8];
+        match i2c.write_read(BME280_ADDR, &[0xf7], &mut raw).await {
+            Ok(()) => {
+                let pressure =
+                    u32::from(raw[0]) << 12 | u32::from(raw[1]) << 4 | u32::from(raw[2]) >> 4;
+                let temperature =
+                    u32::from(raw[3]) << 12 | u32::from(raw[4]) << 4 | u32::from(raw[5]) >> 4;
+                let humidity = u32::from(raw[6]) << 8 | u32::from(raw[7]);
+                rprintln!("raw p: {} t: {} h: {}", pressure, temperature, humidity);
+            }
+            Err(e) => rprintln!("I2C error: {:?}", e),
+        }
+    }
+}
+
+async fn control_loop(mut pwm: PwmChannel<pac::TIM1, 0>, mut counter: CounterUs<pac::TIM2>) {
+    let max_duty = pwm.get_max_duty();
+    let mut duty = 0;
+    loop {
+        // The I2C transfer only runs in the interrupts, so every period is serviced
+        if counter.wait().is_ok() {
+            duty = (duty + max_duty / 100) % max_duty;
+            pwm.set_duty(duty);
+        }
+        yield_now().await;
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+
+    let dp = pac::Peripherals::take().unwrap();
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.use_hse(25.MHz()).sysclk(100.MHz()).freeze();
+
+    let gpioa = dp.GPIOA.split();
+    let gpiob = dp.GPIOB.split();
+
+    let mut pwm = dp
+        .TIM1
+        .pwm_hz(gpioa.pa8.into_alternate(), 20.kHz(), &clocks)
+        .split();
+    pwm.enable();
+    let mut counter = dp.TIM2.counter_us(&clocks);
+    counter.start(1.millis()).unwrap();
+
+    let scl = gpiob.pb8.into_alternate().set_open_drain();
+    let sda = gpiob.pb9.into_alternate().set_open_drain();
+    let i2c = I2c::new(dp.I2C1, (scl, sda), 100.kHz(), &clocks).into_async();
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(pac::Interrupt::I2C1_EV);
+        cortex_m::peripheral::NVIC::unmask(pac::Interrupt::I2C1_ER);
+    }
+
+    block_on(join(control_loop(pwm, counter), bme280(i2c)));
+    unreachable!()
+}
@@ -0,0 +1,62 @@
+//! Stack overflow caught by an MPU guard region
+//!
+//! Without the guard, the runaway recursion overwrites the static data below the stack.
+//! With it, the first push into the guard raises MemManage and the LED on PC13 turns on.
+
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    gpio::PinState,
+    mpu::{MpuExt, Region},
+    pac,
+    prelude::*,
+    stack_guard,
+};
+
+use cortex_m::peripheral::scb::Exception;
+use cortex_m_rt::{entry, exception};
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+
+    let gpioc = dp.GPIOC.split();
+    // Left high (off) and set low by the fault handler without using the stack
+    let _led = gpioc.pc13.into_push_pull_output_in_state(PinState::High);
+
+    let guard: Region = stack_guard!(256);
+    let mut mpu = cp.MPU.constrain();
+    mpu.set_region(0, guard);
+    // Everything else keeps the default memory map
+    mpu.enable(true);
+    cp.SCB.enable(Exception::MemoryManagement);
+
+    recurse(0);
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+// Never returns, it runs into the guard
+#[allow(unconditional_recursion)]
+#[inline(never)]
+fn recurse(depth: u32) -> u32 {
+    let frame = [depth; 16];
+    // Keeps the frame alive and the call out of tail position
+    recurse(depth + 1) + unsafe { core::ptr::read_volatile(&frame[0]) }
+}
+
+#[exception]
+fn MemoryManagement() -> ! {
+    // The stack pointer is in the guard region, only touch registers
+    unsafe { (*pac::GPIOC::ptr()).bsrr.write(|w| w.br13().set_bit()) };
+    loop {
+        cortex_m::asm::bkpt();
+    }
+}
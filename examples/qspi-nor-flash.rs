@@ -0,0 +1,81 @@
+//! Erases, writes and reads back a sector of the QSPI NOR flash of the 32F412G-DISCOVERY
+//! board with the `embedded-storage` traits, then reads it in memory-mapped mode.
+//!
+//! The on-board N25Q128A uses the same commands as the Winbond W25Q128.
+
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    pac,
+    prelude::*,
+    qspi::{Bank1, Config, LineMode, Qspi, QspiCommand, QspiFlash, ReadMode},
+};
+
+const OFFSET: u32 = 0x1000;
+const SECTOR_SIZE: u32 = 4096;
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+
+    let dp = pac::Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.use_hse(8.MHz()).sysclk(100.MHz()).freeze();
+
+    let gpiob = dp.GPIOB.split();
+    let gpiof = dp.GPIOF.split();
+    let gpiog = dp.GPIOG.split();
+
+    let pins = (
+        gpiob.pb2.into_alternate(),
+        gpiog.pg6.into_alternate(),
+        gpiof.pf8.into_alternate(),
+        gpiof.pf9.into_alternate(),
+        gpiof.pf7.into_alternate(),
+        gpiof.pf6.into_alternate(),
+    );
+    let mut qspi: Qspi<Bank1, _> = Qspi::new(
+        dp.QUADSPI,
+        pins,
+        Config::new(50.MHz(), 16 * 1024 * 1024),
+        &clocks,
+    )
+    .unwrap();
+
+    let mut id = [0; 3];
+    let read_id = QspiCommand::new()
+        .instruction(0x9f, LineMode::Single)
+        .data_mode(LineMode::Single);
+    qspi.indirect_read(&read_id, &mut id).unwrap();
+    rprintln!("JEDEC ID: {:02x?}", id);
+
+    let mut flash = QspiFlash::new(qspi, ReadMode::Single).unwrap();
+
+    flash.erase(OFFSET, OFFSET + SECTOR_SIZE).unwrap();
+    let mut data = [0; 300];
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    flash.write(OFFSET, &data).unwrap();
+
+    let mut read = [0; 300];
+    flash.read(OFFSET, &mut read).unwrap();
+    rprintln!("indirect read back: {}", read == data);
+
+    let command = flash.read_command();
+    let mapped = flash.qspi().memory_mapped(&command).unwrap();
+    let mut read = [0; 300];
+    mapped.read(OFFSET, &mut read).unwrap();
+    rprintln!("memory-mapped read back: {}", read == data);
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
@@ -0,0 +1,93 @@
+//! 16-bit stereo 48 kHz I2S output to a codec with SAI1 block A and DMA double buffering
+//!
+//! Pins: PE2 MCLK, PE4 FS (LRCK), PE5 SCK (BCLK), PE6 SD. A 1 kHz triangle wave is played,
+//! each half of the double buffer being refilled while the other one is sent.
+
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    dma::{config::DmaConfig, MemoryToPeripheral, Stream1, StreamsTuple, Transfer},
+    pac::{self, DMA2, SAI},
+    prelude::*,
+    sai::{Config, Sai, SubBlock, A},
+};
+
+use cortex_m_rt::entry;
+
+/// One millisecond of stereo frames
+const FRAMES: usize = 48;
+
+type Buffer = &'static mut [u32; 2 * FRAMES];
+type AudioTransfer = Transfer<Stream1<DMA2>, 0, SubBlock<SAI, A>, MemoryToPeripheral, Buffer>;
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+    // 49.152 MHz = 1024 × 48 kHz
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.MHz())
+        .sysclk(168.MHz())
+        .saia_clk(49_152.kHz())
+        .freeze();
+
+    let gpioe = dp.GPIOE.split();
+    let _mclk = gpioe.pe2.into_alternate::<6>();
+    let _fs = gpioe.pe4.into_alternate::<6>();
+    let _sck = gpioe.pe5.into_alternate::<6>();
+    let _sd = gpioe.pe6.into_alternate::<6>();
+
+    let (mut tx, _) = Sai::new(dp.SAI).split();
+    tx.configure(Config::default().sample_rate(48.kHz()), &clocks)
+        .unwrap();
+    tx.enable_dma(true);
+
+    let first = cortex_m::singleton!(: [u32; 2 * FRAMES] = [0; 2 * FRAMES]).unwrap();
+    let second = cortex_m::singleton!(: [u32; 2 * FRAMES] = [0; 2 * FRAMES]).unwrap();
+    fill(first);
+    fill(second);
+
+    let streams = StreamsTuple::new(dp.DMA2);
+    let mut transfer: AudioTransfer = Transfer::init_memory_to_peripheral(
+        streams.1,
+        tx,
+        first,
+        Some(second),
+        DmaConfig::default()
+            .memory_increment(true)
+            .double_buffer(true),
+    );
+    transfer.start(|tx| tx.enable());
+
+    loop {
+        // NOTE(unsafe) the buffer is refilled well within the 1 ms the other one takes
+        let _ = unsafe {
+            transfer.next_transfer_with(|buf, _| {
+                fill(buf);
+                (buf, ())
+            })
+        };
+    }
+}
+
+/// Fills `buf` with 1 ms of a 1 kHz triangle wave on both channels
+fn fill(buf: &mut [u32; 2 * FRAMES]) {
+    for (n, frame) in buf.chunks_exact_mut(2).enumerate() {
+        let phase = (n * 4 * i16::MAX as usize / FRAMES) as i32;
+        let sample = if phase < 2 * i16::MAX as i32 {
+            phase - i16::MAX as i32
+        } else {
+            3 * i16::MAX as i32 - phase
+        };
+        // Samples are aligned on the least significant bit
+        frame[0] = sample as u16 as u32;
+        frame[1] = sample as u16 as u32;
+    }
+}
@@ -0,0 +1,88 @@
+//! Fades four LEDs with software PWM driven by TIM2
+//!
+//! The LEDs are connected to PA0, PA1, PB0 and PB1, pins without a common timer, and fade
+//! in and out with a phase shift between them.
+
+#![no_main]
+#![no_std]
+
+use panic_halt as _;
+
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    pac::{self, interrupt, Interrupt, TIM2},
+    prelude::*,
+    timer::SoftPwm,
+};
+
+use core::cell::RefCell;
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+
+const RESOLUTION: u16 = 256;
+
+static G_PWM: Mutex<RefCell<Option<SoftPwm<TIM2, 4>>>> = Mutex::new(RefCell::new(None));
+
+#[interrupt]
+fn TIM2() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(pwm) = G_PWM.borrow(cs).borrow_mut().as_mut() {
+            pwm.on_interrupt();
+        }
+    });
+}
+
+/// Triangle wave from 0 to 255 and back over 512 steps
+fn fade(step: u16) -> u16 {
+    let step = step % 512;
+    if step < 256 {
+        step
+    } else {
+        511 - step
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+    let cp = cortex_m::peripheral::Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.sysclk(84.MHz()).freeze();
+
+    let gpioa = dp.GPIOA.split();
+    let gpiob = dp.GPIOB.split();
+
+    let pins = [
+        gpioa.pa0.into_push_pull_output().erase(),
+        gpioa.pa1.into_push_pull_output().erase(),
+        gpiob.pb0.into_push_pull_output().erase(),
+        gpiob.pb1.into_push_pull_output().erase(),
+    ];
+    let pwm = SoftPwm::new(dp.TIM2, pins, 100.Hz(), RESOLUTION, &clocks).unwrap();
+
+    cortex_m::interrupt::free(|cs| *G_PWM.borrow(cs).borrow_mut() = Some(pwm));
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(Interrupt::TIM2);
+    }
+
+    let mut delay = cp.SYST.delay(&clocks);
+    let mut step: u16 = 0;
+    loop {
+        let duties = [
+            fade(step),
+            fade(step + 128),
+            fade(step + 256),
+            fade(step + 384),
+        ];
+        cortex_m::interrupt::free(|cs| {
+            if let Some(pwm) = G_PWM.borrow(cs).borrow_mut().as_mut() {
+                pwm.set_duties(duties);
+            }
+        });
+
+        step = (step + 1) % 512;
+        delay.delay_ms(4u32);
+    }
+}
@@ -0,0 +1,72 @@
+//! Locks onto an S/PDIF stream on PD7 (`SPDIF_RX0`) and prints its sample rate
+//!
+//! The receiver is synchronized again whenever the stream is lost, e.g. when the cable is
+//! unplugged or the source changes rate.
+
+#![no_main]
+#![no_std]
+
+use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
+
+use stm32f4xx_hal as hal;
+
+use crate::hal::{
+    pac,
+    prelude::*,
+    spdifrx::{Config, Event, Input, Spdifrx},
+};
+
+use cortex_m_rt::entry;
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.use_hse(8.MHz()).sysclk(180.MHz()).freeze();
+    // The main PLL P and R outputs both divide the VCO by 2, so the SPDIFRX kernel clock
+    // runs at SYSCLK
+    let kernel_clock = clocks.sysclk();
+
+    let gpiod = dp.GPIOD.split();
+    let _rx = gpiod.pd7.into_alternate::<8>();
+
+    let mut spdif = Spdifrx::new(
+        dp.SPDIFRX,
+        Config::default().input(Input::In0),
+        kernel_clock,
+    );
+
+    loop {
+        spdif.synchronize();
+        loop {
+            if spdif.is_pending(Event::SynchronizationDone) {
+                break;
+            }
+            if spdif.is_pending(Event::SynchronizationLost) {
+                // Restarting clears the error
+                spdif.synchronize();
+            }
+        }
+        spdif.clear_interrupt(Event::SynchronizationDone);
+        spdif.receive();
+
+        match spdif.sample_rate() {
+            Some(rate) => rprintln!("locked, {} Hz", rate.raw()),
+            None => rprintln!("locked, unknown rate"),
+        }
+
+        // Drain samples until the stream is lost, which stops the receiver
+        loop {
+            match spdif.read() {
+                Ok(_) | Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(e)) => {
+                    rprintln!("lost: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
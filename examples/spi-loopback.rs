@@ -0,0 +1,59 @@
+//! Loopback test of the SPI in-place transfers.
+//!
+//! Connect MOSI (PA7) to MISO (PA6). Every word sent is received back at the same time,
+//! so after a transfer the buffer holds the words it was sent with. The results are
+//! printed over RTT.
+
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+use embedded_hal_one::spi::{SpiBus, MODE_0};
+use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f4xx_hal as hal;
+
+use crate::hal::{pac, prelude::*, spi::Spi};
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+
+    let dp = pac::Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.use_hse(25.MHz()).sysclk(100.MHz()).freeze();
+
+    let gpioa = dp.GPIOA.split();
+    let mut spi = Spi::new(
+        dp.SPI1,
+        (
+            gpioa.pa5.into_alternate(),
+            gpioa.pa6.into_alternate(),
+            gpioa.pa7.into_alternate(),
+        ),
+        MODE_0,
+        10.MHz(),
+        &clocks,
+    );
+
+    let mut expected = [0; 256];
+    for (i, word) in expected.iter_mut().enumerate() {
+        *word = i as u8;
+    }
+
+    // One word at a time, as required by embedded-hal
+    let mut words = expected;
+    SpiBus::transfer_in_place(&mut spi, &mut words).unwrap();
+    assert_eq!(words, expected);
+    rprintln!("transfer_in_place: ok");
+
+    // The next word is sent before the current one is read
+    let mut words = expected;
+    cortex_m::interrupt::free(|_| spi.transfer_in_place_pipelined(&mut words)).unwrap();
+    assert_eq!(words, expected);
+    rprintln!("transfer_in_place_pipelined: ok");
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
@@ -0,0 +1,82 @@
+//! CDC-ACM serial port example for a self-powered device.
+//!
+//! VBUS must be connected to PA9 (through a voltage divider if required by the board),
+//! so that the device detects when the cable is unplugged and re-enumerates when it's
+//! plugged back in.
+//! Target board: any STM32F4 with a OTG FS peripheral and a 25MHz HSE crystal
+#![no_std]
+#![no_main]
+
+use panic_halt as _;
+
+use cortex_m_rt::entry;
+use stm32f4xx_hal::otg_fs::{UsbBus, VbusSense, USB};
+use stm32f4xx_hal::{pac, prelude::*};
+use usb_device::prelude::*;
+
+static mut EP_MEMORY: [u32; 1024] = [0; 1024];
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+
+    let rcc = dp.RCC.constrain();
+
+    let clocks = rcc
+        .cfgr
+        .use_hse(25.MHz())
+        .sysclk(48.MHz())
+        .require_pll48clk()
+        .freeze();
+
+    let gpioa = dp.GPIOA.split();
+
+    let usb = USB {
+        usb_global: dp.OTG_FS_GLOBAL,
+        usb_device: dp.OTG_FS_DEVICE,
+        usb_pwrclk: dp.OTG_FS_PWRCLK,
+        pin_dm: gpioa.pa11.into_alternate(),
+        pin_dp: gpioa.pa12.into_alternate(),
+        hclk: clocks.hclk(),
+    };
+
+    let usb_bus = UsbBus::new(usb, unsafe { &mut EP_MEMORY });
+
+    let mut serial = usbd_serial::SerialPort::new(&usb_bus);
+
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
+        .manufacturer("Fake company")
+        .product("Serial port")
+        .serial_number("TEST")
+        .device_class(usbd_serial::USB_CLASS_CDC)
+        .self_powered(true)
+        .build();
+
+    // Building the device enables the bus, which turns VBUS sensing off
+    let vbus = VbusSense::new(gpioa.pa9.into_floating_input());
+
+    loop {
+        if !vbus.is_session_valid() {
+            // Cable is unplugged, the core re-enumerates when it's plugged back in
+            continue;
+        }
+
+        if !usb_dev.poll(&mut [&mut serial]) {
+            continue;
+        }
+
+        let mut buf = [0u8; 64];
+
+        if let Ok(count) = serial.read(&mut buf) {
+            let mut write_offset = 0;
+            while write_offset < count {
+                match serial.write(&buf[write_offset..count]) {
+                    Ok(len) if len > 0 => {
+                        write_offset += len;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
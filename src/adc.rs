@@ -1,5 +1,35 @@
 //! Analog to digital converter configuration.
 //! According to CubeMx, all STM32F4 chips use the same ADC IP so this should be correct for all variants.
+//!
+//! Pins in [`Analog`] mode implement the `embedded_hal::adc::Channel` trait of each ADC they're
+//! connected to, so the channel number comes from the pin type:
+//!
+//! ```no_run
+//! use stm32f4xx_hal::{adc::{config::AdcConfig, Adc}, pac, prelude::*};
+//! use embedded_hal::adc::OneShot;
+//!
+//! let dp = pac::Peripherals::take().unwrap();
+//! let gpioa = dp.GPIOA.split();
+//! let mut adc = Adc::adc1(dp.ADC1, true, AdcConfig::default());
+//! let mut pa0 = gpioa.pa0.into_analog();
+//! let sample: u16 = adc.read(&mut pa0).unwrap();
+//! ```
+//!
+//! Pins without an ADC input, or not in analog mode, don't implement `Channel` and are
+//! rejected at compile time:
+//!
+#![cfg_attr(feature = "stm32f429", doc = "```compile_fail,E0277")]
+#![cfg_attr(not(feature = "stm32f429"), doc = "```ignore")]
+//! use stm32f4xx_hal::{adc::{config::AdcConfig, Adc}, pac, prelude::*};
+//! use embedded_hal::adc::OneShot;
+//!
+//! let dp = pac::Peripherals::take().unwrap();
+//! let gpiob = dp.GPIOB.split();
+//! let mut adc = Adc::adc1(dp.ADC1, true, AdcConfig::default());
+//! // PB5 has no ADC input
+//! let mut pb5 = gpiob.pb5.into_analog();
+//! let sample: u16 = adc.read(&mut pb5).unwrap();
+//! ```
 
 #![deny(missing_docs)]
 
@@ -17,6 +47,7 @@ use crate::{
     signature::VDDA_CALIB,
 };
 use core::fmt;
+use core::marker::PhantomData;
 
 /// Vref internal signal, used for calibration
 pub struct Vref;
@@ -27,6 +58,20 @@ pub struct Vbat;
 /// Core temperature internal signal
 pub struct Temperature;
 
+/// ADC interrupt events
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    /// End of a regular conversion or sequence, see [`config::Eoc`]
+    EndOfConversion,
+    /// End of the injected sequence
+    EndOfInjectedConversion,
+    /// Analog watchdog threshold crossed
+    AnalogWatchdog,
+    /// Regular data overwritten before being read
+    Overrun,
+}
+
 macro_rules! adc_pins {
     ($($pin:ty => ($adc:ident, $chan:expr)),+ $(,)*) => {
         $(
@@ -34,10 +79,60 @@ macro_rules! adc_pins {
                 type ID = u8;
                 fn channel() -> u8 { $chan }
             }
+
+            impl AdcChannel<pac::$adc> for $pin {
+                const CHANNEL: u8 = $chan;
+            }
         )+
     };
 }
 
+/// Input connected to `ADC`, with its channel number
+///
+/// The same pin can have different channel numbers on different ADCs.
+pub trait AdcChannel<ADC> {
+    /// Channel number of the input on `ADC`
+    const CHANNEL: u8;
+
+    /// Returns the channel number of the input on `ADC`
+    fn channel(&self) -> u8 {
+        Self::CHANNEL
+    }
+}
+
+/// Analog input of `ADC` with its channel number erased to a runtime value
+///
+/// Allows arrays of different pins, e.g. to configure a scan sequence with
+/// `Adc::configure_sequence`.
+pub struct AnyAnalog<ADC> {
+    channel: u8,
+    _adc: PhantomData<ADC>,
+}
+
+impl<ADC> AnyAnalog<ADC> {
+    /// Erases `input`, which stays in analog mode and can no longer be used otherwise
+    pub fn new<INPUT: AdcChannel<ADC>>(input: INPUT) -> Self {
+        let channel = input.channel();
+        Self {
+            channel,
+            _adc: PhantomData,
+        }
+    }
+
+    /// Returns the channel number of the input
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+}
+
+impl<ADC> fmt::Debug for AnyAnalog<ADC> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnyAnalog")
+            .field("channel", &self.channel)
+            .finish()
+    }
+}
+
 /// Contains types related to ADC configuration
 pub mod config {
     /// The place in the sequence a given channel should be captured
@@ -258,7 +353,15 @@ pub mod config {
         Tim_5_cc_2,
         /// TIM5 compare channel 3
         Tim_5_cc_3,
+        /// TIM8 compare channel 1
+        #[cfg(not(any(feature = "stm32f401", feature = "stm32f410", feature = "stm32f411",)))]
+        Tim_8_cc_1,
+        /// TIM8 trigger out
+        #[cfg(not(any(feature = "stm32f401", feature = "stm32f410", feature = "stm32f411",)))]
+        Tim_8_trgo,
         /// External interupt line 11
+        ///
+        /// The pin must be connected to line 11 with `ExtiPin::make_interrupt_source`.
         Exti_11,
     }
     impl From<ExternalTrigger> for u8 {
@@ -277,6 +380,18 @@ pub mod config {
                 ExternalTrigger::Tim_5_cc_1 => 0b1010,
                 ExternalTrigger::Tim_5_cc_2 => 0b1011,
                 ExternalTrigger::Tim_5_cc_3 => 0b1100,
+                #[cfg(not(any(
+                    feature = "stm32f401",
+                    feature = "stm32f410",
+                    feature = "stm32f411",
+                )))]
+                ExternalTrigger::Tim_8_cc_1 => 0b1101,
+                #[cfg(not(any(
+                    feature = "stm32f401",
+                    feature = "stm32f410",
+                    feature = "stm32f411",
+                )))]
+                ExternalTrigger::Tim_8_trgo => 0b1110,
                 ExternalTrigger::Exti_11 => 0b1111,
             }
         }
@@ -623,6 +738,8 @@ pub struct Adc<ADC> {
     calibrated_vdda: u32,
     /// Exclusive limit for the sample value possible for the configured resolution.
     max_sample: u32,
+    /// CR2 before `start_continuous`, restored by `stop_continuous`
+    continuous_cr2: Option<u32>,
 }
 impl<ADC> fmt::Debug for Adc<ADC> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -634,12 +751,70 @@ impl<ADC> fmt::Debug for Adc<ADC> {
     }
 }
 
+const CR2_CONT: u32 = 1 << 1;
+const CR2_DMA: u32 = 1 << 8;
+const CR2_DDS: u32 = 1 << 9;
+const CR2_EOCS: u32 = 1 << 10;
+const CR2_EXTEN: u32 = 0b11 << 28;
+const CR2_SWSTART: u32 = 1 << 30;
+/// CR2 bits changed by `start_continuous`
+const CR2_CONTINUOUS: u32 = CR2_CONT | CR2_DMA | CR2_DDS | CR2_EOCS | CR2_EXTEN;
+
+/// CR2 converting over and over on software start, without DMA, external trigger or
+/// per conversion EOC, from the current `cr2`
+///
+/// SWSTART is left clear, it is set afterwards to start the conversions.
+fn continuous_cr2(cr2: u32) -> u32 {
+    (cr2 & !(CR2_CONTINUOUS | CR2_SWSTART)) | CR2_CONT
+}
+
+/// CR2 with the bits changed by [`continuous_cr2`] restored from `saved`
+fn restored_cr2(cr2: u32, saved: u32) -> u32 {
+    (cr2 & !(CR2_CONTINUOUS | CR2_SWSTART)) | (saved & CR2_CONTINUOUS)
+}
+
+/// VDDA in millivolts from a VREF `sample` taken with `max_sample` steps, `vref_cal` being
+/// the 12-bit sample of VREF at [`VDDA_CALIB`]
+fn vdda_from_vref(vref_cal: u16, sample: u16, max_sample: u32) -> u32 {
+    let sample = u64::from(sample.max(1)) << 12;
+    (u64::from(VDDA_CALIB) * u64::from(vref_cal) * u64::from(max_sample) / sample) as u32
+}
+
+/// Sample time register of a channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Smpr {
+    /// Channels 10 to 18
+    Smpr1,
+    /// Channels 0 to 9
+    Smpr2,
+}
+
+/// Register and index of the 3-bit sample time field of `channel`
+fn smpr_field(channel: u8) -> (Smpr, u8) {
+    match channel {
+        0..=9 => (Smpr::Smpr2, channel),
+        10..=18 => (Smpr::Smpr1, channel - 10),
+        _ => unimplemented!(),
+    }
+}
+
+/// `smpr` with the sample time field `index` replaced by `st`
+fn replace_sample_time(smpr: u32, index: u8, st: u32) -> u32 {
+    let offset = 3 * u32::from(index);
+    (smpr & !(0b111 << offset)) | ((st & 0b111) << offset)
+}
+
 macro_rules! adc {
     // Note that only ADC1 supports measurement of VREF, VBAT, and the internal temperature sensor.
     (additionals: ADC1 => ($common_type:ident)) => {
         /// Calculates the system VDDA by sampling the internal VREF channel and comparing
         /// the result with the value stored at the factory.
         pub fn calibrate(&mut self) {
+            self.calibrated_vdda = self.measure_vdda();
+        }
+
+        /// VDDA in millivolts from a sample of VREF
+        fn measure_vdda(&mut self) -> u32 {
             self.enable();
 
             let vref_en = self.temperature_and_vref_enabled();
@@ -650,17 +825,48 @@ macro_rules! adc {
             let vref_cal = VrefCal::get().read();
             let vref_samp = self.read(&mut Vref).unwrap(); //This can't actually fail, it's just in a result to satisfy hal trait
 
-            self.calibrated_vdda = (VDDA_CALIB * u32::from(vref_cal)) / u32::from(vref_samp);
+            let vdda = vdda_from_vref(vref_cal, vref_samp, self.max_sample);
             if !vref_en {
                 self.disable_temperature_and_vref();
             }
+            vdda
+        }
+
+        /// Measures VDDA in millivolts, see [`calibrate`](Self::calibrate)
+        ///
+        /// The measured value is used by the following conversions to millivolts, unless
+        /// the reference voltage is set with
+        /// [`AdcConfig::reference_voltage`](config::AdcConfig::reference_voltage).
+        pub fn read_vdda_mv(&mut self) -> u16 {
+            let vdda = self.measure_vdda();
+            if self.config.vdda.is_none() {
+                self.calibrated_vdda = vdda;
+            }
+            vdda as u16
+        }
+
+        /// Converts `pin` in millivolts, scaled by VDDA measured just before
+        ///
+        /// With a reference voltage set with
+        /// [`AdcConfig::reference_voltage`](config::AdcConfig::reference_voltage), VDDA is not
+        /// measured and the reference voltage is used instead.
+        pub fn read_channel_mv<PIN>(&mut self, pin: &mut PIN) -> u16
+        where
+            PIN: embedded_hal::adc::Channel<pac::ADC1, ID = u8>,
+        {
+            if self.config.vdda.is_none() {
+                self.calibrate();
+            }
+            let sample = self.read(pin).unwrap();
+            self.sample_to_millivolts(sample)
         }
 
         /// Enables the vbat internal channel
         pub fn enable_vbat(&self) {
             unsafe {
                 let common = &(*pac::$common_type::ptr());
-                common.ccr.modify(|_, w| w.vbate().set_bit());
+                // ADC_CCR is shared by all ADCs
+                cortex_m::interrupt::free(|_| common.ccr.modify(|_, w| w.vbate().set_bit()));
             }
         }
 
@@ -668,7 +874,7 @@ macro_rules! adc {
         pub fn disable_vbat(&self) {
             unsafe {
                 let common = &(*pac::$common_type::ptr());
-                common.ccr.modify(|_, w| w.vbate().clear_bit());
+                cortex_m::interrupt::free(|_| common.ccr.modify(|_, w| w.vbate().clear_bit()));
             }
         }
 
@@ -679,7 +885,7 @@ macro_rules! adc {
             self.disable_vbat();
             unsafe {
                 let common = &(*pac::$common_type::ptr());
-                common.ccr.modify(|_, w| w.tsvrefe().set_bit());
+                cortex_m::interrupt::free(|_| common.ccr.modify(|_, w| w.tsvrefe().set_bit()));
             }
         }
 
@@ -687,7 +893,7 @@ macro_rules! adc {
         pub fn disable_temperature_and_vref(&mut self) {
             unsafe {
                 let common = &(*pac::$common_type::ptr());
-                common.ccr.modify(|_, w| w.tsvrefe().clear_bit());
+                cortex_m::interrupt::free(|_| common.ccr.modify(|_, w| w.tsvrefe().clear_bit()));
             }
         }
 
@@ -736,6 +942,7 @@ macro_rules! adc {
                         adc_reg: adc,
                         calibrated_vdda: VDDA_CALIB,
                         max_sample: 0,
+                        continuous_cr2: None,
                     };
 
                     //Probably unnecessary to disable the ADC in most cases but it shouldn't do any harm either
@@ -790,7 +997,18 @@ macro_rules! adc {
                     self.adc_reg.cr2.modify(|_, w| w.adon().clear_bit());
                 }
 
+                /// Disables the adc and releases the peripheral
+                ///
+                /// The analog pins are only borrowed for each conversion and stay with the caller.
+                pub fn release(mut self) -> pac::$adc_type {
+                    self.disable();
+                    self.adc_reg
+                }
+
                 /// Starts conversion sequence. Waits for the hardware to indicate it's actually started.
+                ///
+                /// Does not wait for the conversion to end, which can be signalled by
+                /// [`Event::EndOfConversion`].
                 pub fn start_conversion(&mut self) {
                     self.enable();
                     self.clear_end_of_conversion_flag();
@@ -805,7 +1023,10 @@ macro_rules! adc {
                     self.config.clock = clock;
                     unsafe {
                         let common = &(*pac::$common_type::ptr());
-                        common.ccr.modify(|_, w| w.adcpre().bits(clock.into()));
+                        // ADC_CCR is shared by all ADCs
+                        cortex_m::interrupt::free(|_| {
+                            common.ccr.modify(|_, w| w.adcpre().bits(clock.into()))
+                        });
                     }
                 }
 
@@ -897,6 +1118,63 @@ macro_rules! adc {
                     self.adc_reg.sr.modify(|_, w| w.eoc().clear_bit());
                 }
 
+                /// Starts listening for an `event`
+                ///
+                /// [`Event::EndOfConversion`] keeps the per conversion or per sequence
+                /// behaviour set with [`set_end_of_conversion_interrupt`](Self::set_end_of_conversion_interrupt),
+                /// per sequence by default.
+                pub fn listen(&mut self, event: Event) {
+                    match event {
+                        Event::EndOfConversion => {
+                            if self.config.end_of_conversion_interrupt == config::Eoc::Disabled {
+                                self.config.end_of_conversion_interrupt = config::Eoc::Sequence;
+                            }
+                            self.adc_reg.cr1.modify(|_, w| w.eocie().set_bit());
+                        }
+                        Event::EndOfInjectedConversion => self.adc_reg.cr1.modify(|_, w| w.jeocie().set_bit()),
+                        Event::AnalogWatchdog => self.adc_reg.cr1.modify(|_, w| w.awdie().set_bit()),
+                        Event::Overrun => self.adc_reg.cr1.modify(|_, w| w.ovrie().set_bit()),
+                    }
+                }
+
+                /// Stops listening for an `event`
+                pub fn unlisten(&mut self, event: Event) {
+                    match event {
+                        Event::EndOfConversion => {
+                            self.config.end_of_conversion_interrupt = config::Eoc::Disabled;
+                            self.adc_reg.cr1.modify(|_, w| w.eocie().clear_bit());
+                        }
+                        Event::EndOfInjectedConversion => self.adc_reg.cr1.modify(|_, w| w.jeocie().clear_bit()),
+                        Event::AnalogWatchdog => self.adc_reg.cr1.modify(|_, w| w.awdie().clear_bit()),
+                        Event::Overrun => self.adc_reg.cr1.modify(|_, w| w.ovrie().clear_bit()),
+                    }
+                }
+
+                /// Returns `true` if the flag of `event` is set
+                pub fn is_pending(&self, event: Event) -> bool {
+                    let sr = self.adc_reg.sr.read();
+                    match event {
+                        Event::EndOfConversion => sr.eoc().bit_is_set(),
+                        Event::EndOfInjectedConversion => sr.jeoc().bit_is_set(),
+                        Event::AnalogWatchdog => sr.awd().bit_is_set(),
+                        Event::Overrun => sr.ovr().bit_is_set(),
+                    }
+                }
+
+                /// Clears the flag of `event`
+                ///
+                /// Reading the data register with [`current_sample`](Self::current_sample)
+                /// clears [`Event::EndOfConversion`] too. After an overrun, the conversions
+                /// have to be started again.
+                pub fn clear_interrupt(&mut self, event: Event) {
+                    match event {
+                        Event::EndOfConversion => self.adc_reg.sr.modify(|_, w| w.eoc().clear_bit()),
+                        Event::EndOfInjectedConversion => self.adc_reg.sr.modify(|_, w| w.jeoc().clear_bit()),
+                        Event::AnalogWatchdog => self.adc_reg.sr.modify(|_, w| w.awd().clear_bit()),
+                        Event::Overrun => self.adc_reg.sr.modify(|_, w| w.ovr().clear_bit()),
+                    }
+                }
+
                 /// Sets the default sample time that is used for one-shot conversions.
                 /// [configure_channel](#method.configure_channel) and [start_conversion](#method.start_conversion) can be \
                 /// used for configurations where different sampling times are required per channel.
@@ -931,6 +1209,28 @@ macro_rules! adc {
                 where
                     CHANNEL: embedded_hal::adc::Channel<pac::$adc_type, ID=u8>
                 {
+                    self.configure_channel_id(CHANNEL::channel(), sequence, sample_time);
+                }
+
+                /// Configure an erased analog input for sampling, like [configure_channel](#method.configure_channel).
+                pub fn configure_any_channel(&mut self, channel: &AnyAnalog<pac::$adc_type>, sequence: config::Sequence, sample_time: config::SampleTime) {
+                    self.configure_channel_id(channel.channel(), sequence, sample_time);
+                }
+
+                /// Replaces the sequence with `channels`, in order, all sampled for `sample_time`.
+                /// # Panics
+                /// Panics if `channels` is empty or longer than 16.
+                pub fn configure_sequence(&mut self, channels: &[AnyAnalog<pac::$adc_type>], sample_time: config::SampleTime) {
+                    assert!((1..=16).contains(&channels.len()));
+                    self.reset_sequence();
+                    for (i, channel) in channels.iter().enumerate() {
+                        self.configure_channel_id(channel.channel(), (i as u8).into(), sample_time);
+                    }
+                }
+
+                fn configure_channel_id(&mut self, channel: u8, sequence: config::Sequence, sample_time: config::SampleTime) {
+                    self.set_sample_time_id(channel, sample_time);
+
                     //Check the sequence is long enough
                     self.adc_reg.sqr1.modify(|r, w| {
                         let prev: config::Sequence = r.l().bits().into();
@@ -941,8 +1241,6 @@ macro_rules! adc {
                         }
                     });
 
-                    let channel = CHANNEL::channel();
-
                     //Set the channel in the right sequence field
                     match sequence {
                         config::Sequence::One      => self.adc_reg.sqr3.modify(|_, w| unsafe {w.sq1().bits(channel) }),
@@ -962,22 +1260,27 @@ macro_rules! adc {
                         config::Sequence::Fifteen  => self.adc_reg.sqr1.modify(|_, w| unsafe {w.sq15().bits(channel) }),
                         config::Sequence::Sixteen  => self.adc_reg.sqr1.modify(|_, w| unsafe {w.sq16().bits(channel) }),
                     }
+                }
 
-                    fn replace_bits(mut v: u32, offset: u32, width: u32, value: u32) -> u32 {
-                        let mask = !(((1 << width) -1) << (offset * width));
-                        v &= mask;
-                        v |= value << (offset * width);
-                        v
-                    }
+                /// Sets the sample time of a channel, used wherever the channel appears in the sequence.
+                /// Lets high impedance sources sample longer than low impedance ones in the same scan.
+                /// # Arguments
+                /// * `channel` - channel to configure
+                /// * `sample_time` - how long to sample for. See datasheet and ref manual to work out how long you need\
+                /// to sample for at a given ADC clock frequency
+                pub fn set_sample_time<CHANNEL>(&mut self, _channel: &CHANNEL, sample_time: config::SampleTime)
+                where
+                    CHANNEL: embedded_hal::adc::Channel<pac::$adc_type, ID=u8>
+                {
+                    self.set_sample_time_id(CHANNEL::channel(), sample_time);
+                }
 
+                fn set_sample_time_id(&mut self, channel: u8, sample_time: config::SampleTime) {
                     //Set the sample time for the channel
-                    let st = u8::from(sample_time);
-                    let st = u32::from(st);
-                    let ch = u32::from(channel);
-                    match channel {
-                        0..=9   => self.adc_reg.smpr2.modify(|r, w| unsafe { w.bits(replace_bits(r.bits(), ch, 3, st)) }),
-                        10..=18 => self.adc_reg.smpr1.modify(|r, w| unsafe { w.bits(replace_bits(r.bits(), ch-10, 3, st)) }),
-                        _ => unimplemented!(),
+                    let st = u32::from(u8::from(sample_time));
+                    match smpr_field(channel) {
+                        (Smpr::Smpr2, i) => self.adc_reg.smpr2.modify(|r, w| unsafe { w.bits(replace_sample_time(r.bits(), i, st)) }),
+                        (Smpr::Smpr1, i) => self.adc_reg.smpr1.modify(|r, w| unsafe { w.bits(replace_sample_time(r.bits(), i, st)) }),
                     }
                 }
 
@@ -1052,6 +1355,49 @@ macro_rules! adc {
 
                     result
                 }
+
+                /// Starts converting `pin` over and over with the default sample time, each result
+                /// replacing the previous one, until [`stop_continuous`](Self::stop_continuous)
+                ///
+                /// Blocks until the first conversion completes, so [`latest`](Self::latest) always
+                /// returns a valid sample.
+                /// Note that it reconfigures the adc sequence and doesn't restore it
+                pub fn start_continuous<PIN>(&mut self, pin: &PIN)
+                where
+                    PIN: embedded_hal::adc::Channel<pac::$adc_type, ID=u8>
+                {
+                    self.adc_reg.cr1.modify(|_, w| w
+                        .scan().clear_bit() //Disable scan mode
+                    );
+
+                    self.reset_sequence();
+                    self.configure_channel(pin, config::Sequence::One, self.config.default_sample_time);
+                    // Without DMA, trigger or per conversion EOC, so no overrun detection
+                    let cr2 = self.adc_reg.cr2.read().bits();
+                    self.continuous_cr2.get_or_insert(cr2);
+                    self.adc_reg.cr2.write(|w| unsafe { w.bits(continuous_cr2(cr2)) });
+                    self.enable();
+                    self.clear_end_of_conversion_flag();
+                    self.start_conversion();
+
+                    //Wait for the first result
+                    self.wait_for_conversion_sequence();
+                }
+
+                /// Returns the most recent result of the conversions started by
+                /// [`start_continuous`](Self::start_continuous), without starting a conversion
+                pub fn latest(&self) -> u16 {
+                    self.current_sample()
+                }
+
+                /// Stops the conversions started by [`start_continuous`](Self::start_continuous)
+                /// and restores the DMA, trigger, EOC and scan settings
+                pub fn stop_continuous(&mut self) {
+                    let cr2 = self.adc_reg.cr2.read().bits();
+                    let saved = self.continuous_cr2.take().unwrap_or(cr2 & !CR2_CONT);
+                    self.adc_reg.cr2.write(|w| unsafe { w.bits(restored_cr2(cr2, saved)) });
+                    self.set_scan(self.config.scan);
+                }
             }
 
             impl Adc<pac::$adc_type> {
@@ -1084,6 +1430,7 @@ macro_rules! adc {
                 }
             }
 
+            /// DMA reads conversion results from the data register, `ADC_DR`
             unsafe impl PeriAddress for Adc<pac::$adc_type> {
                 #[inline(always)]
                 fn address(&self) -> u32 {
@@ -1646,3 +1993,118 @@ adc_pins!(
     gpio::PF8<Analog> => (ADC3, 6),
     gpio::PF9<Analog> => (ADC3, 7),
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smpr_boundary() {
+        assert_eq!(smpr_field(0), (Smpr::Smpr2, 0));
+        assert_eq!(smpr_field(9), (Smpr::Smpr2, 9));
+        assert_eq!(smpr_field(10), (Smpr::Smpr1, 0));
+        // Vbat, the last channel
+        assert_eq!(smpr_field(18), (Smpr::Smpr1, 8));
+    }
+
+    #[test]
+    #[should_panic]
+    fn smpr_no_channel_19() {
+        smpr_field(19);
+    }
+
+    #[test]
+    fn sample_time_fields() {
+        // SMP9 is bits 27 to 29 of SMPR2, bits 30 and 31 are reserved
+        assert_eq!(replace_sample_time(0, 9, 0b111), 0b111 << 27);
+        assert_eq!(replace_sample_time(u32::MAX, 9, 0b010), !(0b101 << 27));
+        // SMP18 is bits 24 to 26 of SMPR1
+        assert_eq!(replace_sample_time(0, 8, 0b101), 0b101 << 24);
+        assert_eq!(replace_sample_time(0xffff_ffff, 0, 0), 0xffff_fff8);
+        // Sample times don't spill into the next channel
+        assert_eq!(replace_sample_time(0, 1, 0b1111), 0b111 << 3);
+    }
+
+    #[test]
+    fn continuous_sets_cont_only() {
+        // ADON, DMA, DDS, EOCS, ALIGN, EXTEN rising
+        let cr2 = 1 | CR2_DMA | CR2_DDS | CR2_EOCS | (1 << 11) | (0b01 << 28);
+        assert_eq!(continuous_cr2(cr2), 1 | CR2_CONT | (1 << 11));
+    }
+
+    #[test]
+    fn continuous_leaves_swstart_clear() {
+        assert_eq!(continuous_cr2(1 | CR2_SWSTART) & CR2_SWSTART, 0);
+    }
+
+    #[test]
+    fn restore_dma_and_eocs() {
+        let saved = 1 | CR2_DMA | CR2_DDS | CR2_EOCS | (0b10 << 28);
+        let running = continuous_cr2(saved);
+        assert_eq!(restored_cr2(running, saved), saved);
+    }
+
+    #[test]
+    fn restore_clears_cont_and_swstart() {
+        let saved = 1;
+        // SWSTART is read as set until the conversion starts
+        let running = continuous_cr2(saved) | CR2_SWSTART;
+        assert_eq!(restored_cr2(running, saved), 1);
+    }
+
+    #[test]
+    fn restore_keeps_cont_of_continuous_config() {
+        let saved = 1 | CR2_CONT;
+        assert_eq!(restored_cr2(continuous_cr2(saved), saved), saved);
+    }
+
+    #[test]
+    fn vdda_at_calibration_conditions() {
+        assert_eq!(vdda_from_vref(1500, 1500, 1 << 12), VDDA_CALIB);
+        // Same voltage sampled with 10 bits
+        assert_eq!(vdda_from_vref(1500, 375, 1 << 10), VDDA_CALIB);
+    }
+
+    #[test]
+    fn vdda_scales_with_vref_sample() {
+        // A larger VREF sample means a lower VDDA
+        assert_eq!(vdda_from_vref(1500, 1650, 1 << 12), 3000);
+        assert_eq!(vdda_from_vref(1500, 1375, 1 << 12), 3600);
+        // 8 bits, 1650 / 16 truncated to 103
+        assert_eq!(vdda_from_vref(1500, 103, 1 << 8), 3003);
+    }
+
+    #[test]
+    fn extsel_encoding() {
+        use config::ExternalTrigger::*;
+        // RM0090 ADC_CR2 EXTSEL
+        let table = [
+            (Tim_1_cc_1, 0b0000),
+            (Tim_1_cc_2, 0b0001),
+            (Tim_1_cc_3, 0b0010),
+            (Tim_2_cc_2, 0b0011),
+            (Tim_2_cc_3, 0b0100),
+            (Tim_2_cc_4, 0b0101),
+            (Tim_2_trgo, 0b0110),
+            (Tim_3_cc_1, 0b0111),
+            (Tim_3_trgo, 0b1000),
+            (Tim_4_cc_4, 0b1001),
+            (Tim_5_cc_1, 0b1010),
+            (Tim_5_cc_2, 0b1011),
+            (Tim_5_cc_3, 0b1100),
+            #[cfg(not(any(feature = "stm32f401", feature = "stm32f410", feature = "stm32f411",)))]
+            (Tim_8_cc_1, 0b1101),
+            #[cfg(not(any(feature = "stm32f401", feature = "stm32f410", feature = "stm32f411",)))]
+            (Tim_8_trgo, 0b1110),
+            (Exti_11, 0b1111),
+        ];
+        for (trigger, extsel) in table {
+            assert_eq!(u8::from(trigger), extsel, "{:?}", trigger);
+        }
+    }
+
+    #[test]
+    fn vdda_from_zero_sample_does_not_panic() {
+        assert_eq!(vdda_from_vref(1500, 0, 1 << 12), 3300 * 1500);
+    }
+}
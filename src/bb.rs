@@ -4,8 +4,33 @@
 //! Not all peripherals are mapped to the bit-banding alias region, the peripheral bit-band region
 //! is from `0x4000_0000` to `0x400F_FFFF`. Bit-banding allows the manipulation of individual bits
 //! atomically.
+//!
+//! The first MiB of SRAM, from `0x2000_0000`, can be bit-banded too. [`BitArray`] uses it for
+//! flags shared between interrupt handlers and the main loop, each flag being set, cleared or
+//! read with a single store or load and no critical section:
+//!
+//! ```ignore
+//! use stm32f4xx_hal::bitband_array;
+//!
+//! bitband_array!(static EVENTS: [BitFlag; 2]);
+//!
+//! #[interrupt]
+//! fn TIM2() {
+//!     EVENTS.flag(0).set();
+//! }
+//!
+//! // In the main loop
+//! if EVENTS.flag(0).get() {
+//!     EVENTS.flag(0).clear();
+//! }
+//! ```
+//!
+//! The CCM RAM, from `0x1000_0000`, is not bit-bandable. The address of a `static` is only
+//! known once linked, so the region is checked at run time, when [`BitArray::flag`] creates a
+//! flag. The number of flags is checked at compile time.
 
 use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
 
 // Start address of the peripheral memory region capable of being addressed by bit-banding
 const PERI_ADDRESS_START: usize = 0x4000_0000;
@@ -13,6 +38,34 @@ const PERI_ADDRESS_END: usize = 0x400F_FFFF;
 
 const PERI_BIT_BAND_BASE: usize = 0x4200_0000;
 
+// Start address of the SRAM region capable of being addressed by bit-banding
+const SRAM_ADDRESS_START: usize = 0x2000_0000;
+const SRAM_ADDRESS_END: usize = 0x200F_FFFF;
+
+const SRAM_BIT_BAND_BASE: usize = 0x2200_0000;
+
+/// Bit-band alias of `bit` of the peripheral register at `addr`
+///
+/// # Panics
+///
+/// When `addr` is outside of the peripheral bit-band region or `bit` is not lower than 32.
+pub const fn peripheral_alias(addr: usize, bit: u8) -> usize {
+    assert!(addr >= PERI_ADDRESS_START && addr <= PERI_ADDRESS_END);
+    assert!(bit < 32);
+    PERI_BIT_BAND_BASE + (addr - PERI_ADDRESS_START) * 32 + 4 * bit as usize
+}
+
+/// Bit-band alias of `bit` of the SRAM word at `addr`
+///
+/// # Panics
+///
+/// When `addr` is outside of the SRAM bit-band region or `bit` is not lower than 32.
+pub const fn sram_alias(addr: usize, bit: u8) -> usize {
+    assert!(addr >= SRAM_ADDRESS_START && addr <= SRAM_ADDRESS_END);
+    assert!(bit < 32);
+    SRAM_BIT_BAND_BASE + (addr - SRAM_ADDRESS_START) * 32 + 4 * bit as usize
+}
+
 /// Clears the bit on the provided register without modifying other bits.
 ///
 /// # Safety
@@ -40,12 +93,160 @@ pub unsafe fn set<T>(register: *const T, bit: u8) {
 /// Some registers have reserved bits which should not be modified.
 #[inline]
 pub unsafe fn write<T>(register: *const T, bit: u8, set: bool) {
-    let addr = register as usize;
+    let bb_addr = peripheral_alias(register as usize, bit);
+    ptr::write_volatile(bb_addr as *mut u32, if set { 1 } else { 0 });
+}
 
-    assert!((PERI_ADDRESS_START..=PERI_ADDRESS_END).contains(&addr));
-    assert!(bit < 32);
+/// Reads the bit of the provided register.
+///
+/// # Safety
+///
+/// Reading some registers clears flags.
+#[inline]
+pub unsafe fn read<T>(register: *const T, bit: u8) -> bool {
+    let bb_addr = peripheral_alias(register as usize, bit);
+    ptr::read_volatile(bb_addr as *const u32) != 0
+}
 
-    let bit = bit as usize;
-    let bb_addr = (PERI_BIT_BAND_BASE + (addr - PERI_ADDRESS_START) * 32) + 4 * bit;
-    ptr::write_volatile(bb_addr as *mut u32, if set { 1 } else { 0 });
+/// Up to 32 flags backed by one SRAM word, see [`bitband_array!`]
+pub struct BitArray<const N: usize> {
+    word: AtomicU32,
+}
+
+impl<const N: usize> BitArray<N> {
+    /// All flags cleared
+    ///
+    /// # Panics
+    ///
+    /// When `N` is larger than 32, at compile time in a `static`.
+    pub const fn new() -> Self {
+        assert!(N <= 32);
+        Self {
+            word: AtomicU32::new(0),
+        }
+    }
+
+    /// Flag `n`
+    ///
+    /// # Panics
+    ///
+    /// When `n` is out of range, or the array is not in the SRAM bit-band region, such as in
+    /// CCM RAM. Both are checked at run time.
+    #[inline]
+    pub fn flag(&'static self, n: usize) -> BitFlag {
+        assert!(n < N);
+        BitFlag {
+            alias: sram_alias(&self.word as *const _ as usize, n as u8),
+        }
+    }
+
+    /// All flags at once, flag `n` in bit `n`
+    #[inline]
+    pub fn bits(&self) -> u32 {
+        self.word.load(Ordering::Relaxed)
+    }
+}
+
+impl<const N: usize> Default for BitArray<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Single bit of SRAM, set, cleared and read atomically through its bit-band alias
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitFlag {
+    alias: usize,
+}
+
+impl BitFlag {
+    /// Sets the flag
+    #[inline(always)]
+    pub fn set(self) {
+        self.write(true);
+    }
+
+    /// Clears the flag
+    #[inline(always)]
+    pub fn clear(self) {
+        self.write(false);
+    }
+
+    /// Sets or clears the flag
+    #[inline(always)]
+    pub fn write(self, set: bool) {
+        // NOTE(unsafe) the alias was checked to be in the SRAM bit-band region
+        unsafe { ptr::write_volatile(self.alias as *mut u32, set as u32) };
+    }
+
+    /// Reads the flag
+    #[inline(always)]
+    pub fn get(self) -> bool {
+        // NOTE(unsafe) the alias was checked to be in the SRAM bit-band region
+        unsafe { ptr::read_volatile(self.alias as *const u32) != 0 }
+    }
+}
+
+/// Declares a [`BitArray`] of bit-banded flags in a `static`
+///
+/// ```ignore
+/// bitband_array!(pub static FLAGS: [BitFlag; 8]);
+/// ```
+#[macro_export]
+macro_rules! bitband_array {
+    ($(#[$attr:meta])* $vis:vis static $name:ident: [BitFlag; $n:expr] $(;)?) => {
+        $(#[$attr])*
+        $vis static $name: $crate::bb::BitArray<{ $n }> = $crate::bb::BitArray::new();
+    };
+}
+pub use crate::bitband_array;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peripheral_aliases() {
+        // GPIOA_ODR bit 5
+        assert_eq!(peripheral_alias(0x4002_0014, 5), 0x4240_0294);
+        assert_eq!(peripheral_alias(PERI_ADDRESS_START, 0), PERI_BIT_BAND_BASE);
+        assert_eq!(peripheral_alias(0x400F_FFFC, 31), 0x43FF_FFFC);
+    }
+
+    #[test]
+    fn sram_aliases() {
+        assert_eq!(sram_alias(SRAM_ADDRESS_START, 0), SRAM_BIT_BAND_BASE);
+        assert_eq!(sram_alias(0x2000_0004, 31), 0x2200_00FC);
+        assert_eq!(sram_alias(0x200F_FFFC, 31), 0x23FF_FFFC);
+    }
+
+    #[test]
+    fn aliases_are_const() {
+        const ALIAS: usize = sram_alias(0x2000_0010, 1);
+        assert_eq!(ALIAS, 0x2200_0204);
+    }
+
+    #[test]
+    #[should_panic]
+    fn ccm_ram_is_not_bit_banded() {
+        sram_alias(0x1000_0000, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn sram_above_first_mib() {
+        sram_alias(0x2010_0000, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn ahb2_is_not_bit_banded() {
+        peripheral_alias(0x5000_0000, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bit_out_of_range() {
+        peripheral_alias(0x4002_0014, 32);
+    }
 }
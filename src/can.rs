@@ -1,11 +1,353 @@
 //! # Controller Area Network (CAN) Interface
 //!
+//! Frame transmission, reception and filtering are provided by the [`bxcan`] crate.
+//! This module additionally exposes interrupt events, error state and FIFO overrun
+//! accounting, which can be reached through `bxcan::Can::instance()` once the
+//! peripheral has been handed over to `bxcan`.
+//!
+//! The CAN peripherals have four interrupt vectors each:
+//!
+//! - `CANx_TX`: [`Event::TxMailboxEmpty`]
+//! - `CANx_RX0`: [`Event::Fifo0MessagePending`], [`Event::Fifo0Full`], [`Event::Fifo0Overrun`]
+//! - `CANx_RX1`: [`Event::Fifo1MessagePending`], [`Event::Fifo1Full`], [`Event::Fifo1Overrun`]
+//! - `CANx_SCE`: all error and status change events
+#![allow(non_upper_case_globals)]
+
+use core::ops::Deref;
 
 use crate::gpio::{Const, NoPin, PinA, PushPull, SetAlternate};
-use crate::pac::{CAN1, CAN2};
+use crate::pac::{can1, CAN1, CAN2};
 use crate::rcc;
+use crate::time::Hertz;
+use embedded_hal_one::digital::OutputPin;
+
+pub trait Instance:
+    crate::Sealed + Deref<Target = can1::RegisterBlock> + rcc::Enable + rcc::Reset
+{
+}
+
+bitflags::bitflags! {
+    /// Interrupt events
+    ///
+    /// Bit positions match the `CAN_IER` register.
+    pub struct Event: u32 {
+        /// Transmit mailbox empty
+        const TxMailboxEmpty = 1 << 0;
+        /// Message pending in FIFO 0
+        const Fifo0MessagePending = 1 << 1;
+        /// FIFO 0 full
+        const Fifo0Full = 1 << 2;
+        /// FIFO 0 overrun, a message was lost
+        const Fifo0Overrun = 1 << 3;
+        /// Message pending in FIFO 1
+        const Fifo1MessagePending = 1 << 4;
+        /// FIFO 1 full
+        const Fifo1Full = 1 << 5;
+        /// FIFO 1 overrun, a message was lost
+        const Fifo1Overrun = 1 << 6;
+        /// Error warning limit reached (TEC or REC >= 96)
+        const ErrorWarning = 1 << 8;
+        /// Error passive limit reached (TEC or REC > 127)
+        const ErrorPassive = 1 << 9;
+        /// Bus-off state entered (TEC > 255)
+        const BusOff = 1 << 10;
+        /// Last error code updated
+        const LastErrorCode = 1 << 11;
+        /// Error interrupt, must be enabled for any of the error events above to fire
+        const Error = 1 << 15;
+        /// Wakeup on SOF detected in sleep mode
+        const Wakeup = 1 << 16;
+        /// Sleep mode entered
+        const Sleep = 1 << 17;
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Event {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Event({=u32:#b})", self.bits());
+    }
+}
+
+/// Receive FIFO
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Fifo {
+    Fifo0 = 0,
+    Fifo1 = 1,
+}
+
+/// Decoded `LEC` field of the `CAN_ESR` register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum LastErrorCode {
+    NoError = 0,
+    StuffError = 1,
+    FormError = 2,
+    AcknowledgmentError = 3,
+    BitRecessiveError = 4,
+    BitDominantError = 5,
+    CrcError = 6,
+    /// Set by software, see [`Can::clear_last_error_code`]
+    SetBySoftware = 7,
+}
+
+/// What woke the controller up from sleep mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WakeCause {
+    /// Bus activity, the frame whose start of frame woke the controller was lost
+    Bus,
+    /// A call to [`Can::wake`]
+    Software,
+}
+
+/// The controller didn't acknowledge a mode change, e.g. because the bus is stuck dominant
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timeout;
+
+/// Error of the [`Standby`] transitions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StandbyError<E> {
+    /// Setting the standby pin failed
+    Pin(E),
+    /// The controller didn't enter or leave sleep mode
+    Timeout,
+}
+
+impl<E> From<Timeout> for StandbyError<E> {
+    fn from(_: Timeout) -> Self {
+        Self::Timeout
+    }
+}
+
+/// Number of `MSR` polls before a mode change is given up, more than a frame lasts at
+/// 10 kbit/s with the fastest core clock
+const MODE_POLLS: u32 = 1_000_000;
+
+/// Polls `done` until it returns `true`, at most `polls` times
+fn wait_for(polls: u32, mut done: impl FnMut() -> bool) -> Result<(), Timeout> {
+    for _ in 0..polls {
+        if done() {
+            return Ok(());
+        }
+    }
+    Err(Timeout)
+}
+
+/// `CAN_MSR` rc_w1 bits clearing the status flags of `event`
+fn msr_clear_bits(event: Event) -> u32 {
+    let mut msr = 0;
+    if event.intersects(
+        Event::ErrorWarning
+            | Event::ErrorPassive
+            | Event::BusOff
+            | Event::LastErrorCode
+            | Event::Error,
+    ) {
+        msr |= 1 << 2; // ERRI
+    }
+    if event.contains(Event::Wakeup) {
+        msr |= 1 << 3; // WKUI
+    }
+    if event.contains(Event::Sleep) {
+        msr |= 1 << 4; // SLAKI
+    }
+    msr
+}
+
+/// Standby pin of a CAN transceiver, high in standby, e.g. `STB` of a TJA1042
+///
+/// In standby the transceiver still drives `RXD` low on bus activity, which wakes up a
+/// controller put to sleep with automatic wakeup.
+pub struct Standby<PIN> {
+    pin: PIN,
+}
 
-pub trait Instance: crate::Sealed + rcc::Enable + rcc::Reset {}
+impl<PIN: OutputPin> Standby<PIN> {
+    /// Takes the standby pin and puts the transceiver in normal mode
+    pub fn new(mut pin: PIN) -> Result<Self, PIN::Error> {
+        pin.set_low()?;
+        Ok(Self { pin })
+    }
+
+    /// Puts the controller to sleep, then the transceiver in standby
+    ///
+    /// The transceiver stays in normal mode if the controller doesn't enter sleep mode.
+    pub fn sleep<CAN: Instance, PINS>(
+        &mut self,
+        can: &mut Can<CAN, PINS>,
+        auto_wakeup: bool,
+    ) -> Result<(), StandbyError<PIN::Error>> {
+        can.sleep(auto_wakeup)?;
+        self.pin.set_high().map_err(StandbyError::Pin)
+    }
+
+    /// Puts the transceiver in normal mode, then wakes the controller up
+    ///
+    /// Also has to be called after an automatic wakeup, e.g. on [`Event::Wakeup`], the
+    /// controller can't receive through a transceiver in standby.
+    pub fn wake<CAN: Instance, PINS>(
+        &mut self,
+        can: &mut Can<CAN, PINS>,
+    ) -> Result<(), StandbyError<PIN::Error>> {
+        self.pin.set_low().map_err(StandbyError::Pin)?;
+        can.wake()?;
+        Ok(())
+    }
+
+    /// Releases the standby pin
+    pub fn release(self) -> PIN {
+        self.pin
+    }
+}
+
+/// Test modes, set in the `LBKM` and `SILM` bits of the `CAN_BTR` register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TestMode {
+    /// Normal operation on the bus
+    Normal,
+    /// Transmitted frames are received back and also sent on the bus,
+    /// the bus is not listened to
+    Loopback,
+    /// Frames are received from the bus, only recessive bits are sent,
+    /// so no acknowledge or error frame disturbs the bus
+    Silent,
+    /// Transmitted frames are received back, the controller is disconnected
+    /// from the bus, for self-test without any wiring
+    SilentLoopback,
+}
+
+impl TestMode {
+    /// `LBKM` bit value
+    pub const fn loopback(self) -> bool {
+        matches!(self, Self::Loopback | Self::SilentLoopback)
+    }
+
+    /// `SILM` bit value
+    pub const fn silent(self) -> bool {
+        matches!(self, Self::Silent | Self::SilentLoopback)
+    }
+}
+
+/// Bit timing, written to the `CAN_BTR` register
+///
+/// ```ignore
+/// // 500 kbit/s from the 42 MHz APB1 clock, sampled at 87.5 %
+/// let timing = BitTiming::for_bitrate(42.MHz(), 500_000, 0.875).unwrap();
+/// let mut can = bxcan::Can::builder(can)
+///     .set_bit_timing(timing.btr())
+///     .enable();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BitTiming {
+    /// Length of a time quantum in APB1 clock cycles, 1 to 1024
+    pub prescaler: u16,
+    /// Time quanta before the sample point, excluding the sync segment, 1 to 16
+    pub seg1: u8,
+    /// Time quanta after the sample point, 1 to 8
+    pub seg2: u8,
+    /// Resynchronization jump width in time quanta, 1 to 4
+    pub sjw: u8,
+}
+
+/// No bit timing reaches the bitrate within 0.5 %
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BitrateUnachievable;
+
+impl BitTiming {
+    /// Finds the bit timing closest to `bitrate` for the APB1 clock `pclk`, with the
+    /// sample point closest to `sample_point`, a fraction of the bit time such as 0.875
+    ///
+    /// Among the timings with the smallest bitrate error, the one with the closest sample
+    /// point is chosen, then the one with the most time quanta per bit. The jump width
+    /// is the largest allowed, up to 4 quanta.
+    pub fn for_bitrate(
+        pclk: Hertz,
+        bitrate: u32,
+        sample_point: f32,
+    ) -> Result<Self, BitrateUnachievable> {
+        let pclk = pclk.raw();
+        if bitrate == 0 {
+            return Err(BitrateUnachievable);
+        }
+        let sample_point = (sample_point * 1000.) as u32;
+
+        // (bitrate error, sample point error in permille, timing)
+        let mut best: Option<(u32, u32, Self)> = None;
+        // 1 sync quantum + seg1 + seg2
+        for quanta in (4..=25u32).rev() {
+            let per_bit = match bitrate.checked_mul(quanta) {
+                Some(per_bit) => per_bit,
+                None => continue,
+            };
+            // Rounded to the nearest, `pclk + per_bit / 2` could overflow
+            let prescaler = pclk / per_bit + u32::from(pclk % per_bit >= per_bit - per_bit / 2);
+            if !(1..=1024).contains(&prescaler) {
+                continue;
+            }
+            let error = (pclk / (prescaler * quanta)).abs_diff(bitrate);
+
+            let seg2 = ((quanta * (1000 - sample_point.min(1000)) + 500) / 1000).clamp(1, 8);
+            let seg1 = quanta - 1 - seg2;
+            if !(1..=16).contains(&seg1) {
+                continue;
+            }
+            let sample_error = ((1 + seg1) * 1000 / quanta).abs_diff(sample_point);
+
+            let timing = Self {
+                prescaler: prescaler as u16,
+                seg1: seg1 as u8,
+                seg2: seg2 as u8,
+                sjw: seg2.min(4) as u8,
+            };
+            if best.map_or(true, |(e, s, _)| (error, sample_error) < (e, s)) {
+                best = Some((error, sample_error, timing));
+            }
+        }
+
+        match best {
+            Some((error, _, timing)) if error.checked_mul(200).map_or(false, |e| e <= bitrate) => {
+                Ok(timing)
+            }
+            _ => Err(BitrateUnachievable),
+        }
+    }
+
+    /// Value of the timing fields of `CAN_BTR`, as taken by `bxcan::CanConfig::set_bit_timing`
+    pub const fn btr(&self) -> u32 {
+        (self.sjw as u32 - 1) << 24
+            | (self.seg2 as u32 - 1) << 20
+            | (self.seg1 as u32 - 1) << 16
+            | (self.prescaler as u32 - 1)
+    }
+
+    /// Bitrate obtained from the APB1 clock `pclk`
+    pub fn bitrate(&self, pclk: Hertz) -> u32 {
+        pclk.raw() / (u32::from(self.prescaler) * (1 + u32::from(self.seg1) + u32::from(self.seg2)))
+    }
+}
+
+impl From<u8> for LastErrorCode {
+    fn from(lec: u8) -> Self {
+        match lec & 0b111 {
+            0 => Self::NoError,
+            1 => Self::StuffError,
+            2 => Self::FormError,
+            3 => Self::AcknowledgmentError,
+            4 => Self::BitRecessiveError,
+            5 => Self::BitDominantError,
+            6 => Self::CrcError,
+            _ => Self::SetBySoftware,
+        }
+    }
+}
 
 // Implemented by all SPI instances
 impl Instance for CAN1 {}
@@ -94,6 +436,7 @@ impl<CAN: Instance> CanExt for CAN {
 pub struct Can<CAN, PINS> {
     can: CAN,
     pins: PINS,
+    overruns: [u32; 2],
 }
 
 impl<CAN, TX, RX> Can<CAN, (TX, RX)>
@@ -112,7 +455,11 @@ where
 
         pins.set_alt_mode();
 
-        Can { can, pins }
+        Can {
+            can,
+            pins,
+            overruns: [0; 2],
+        }
     }
 
     pub fn release(mut self) -> (CAN, (TX, RX)) {
@@ -142,6 +489,224 @@ where
     }
 }
 
+impl<CAN: Instance, PINS> Can<CAN, PINS> {
+    /// Starts listening for an interrupt `event`
+    ///
+    /// Note, you will also have to enable the corresponding interrupt
+    /// in the NVIC to start receiving events.
+    pub fn listen(&mut self, event: Event) {
+        self.can
+            .ier
+            .modify(|r, w| unsafe { w.bits(r.bits() | event.bits()) });
+    }
+
+    /// Stops listening for an interrupt `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.can
+            .ier
+            .modify(|r, w| unsafe { w.bits(r.bits() & !event.bits()) });
+    }
+
+    /// Clears the status flags associated with `event`.
+    ///
+    /// Message pending events are cleared by reading the frame from the FIFO.
+    /// Clearing an overrun event is accounted in [`Can::overrun_count`].
+    pub fn clear_interrupt(&mut self, event: Event) {
+        if event.intersects(Event::Fifo0Full | Event::Fifo0Overrun) {
+            self.clear_fifo_flags(Fifo::Fifo0, event.contains(Event::Fifo0Full));
+        }
+        if event.intersects(Event::Fifo1Full | Event::Fifo1Overrun) {
+            self.clear_fifo_flags(Fifo::Fifo1, event.contains(Event::Fifo1Full));
+        }
+        if event.contains(Event::TxMailboxEmpty) {
+            // RQCP0..2 are rc_w1; writing them also clears TXOK/ALST/TERR
+            self.can
+                .tsr
+                .write(|w| unsafe { w.bits((1 << 0) | (1 << 8) | (1 << 16)) });
+        }
+        let msr = msr_clear_bits(event);
+        if msr != 0 {
+            self.can.msr.write(|w| unsafe { w.bits(msr) });
+        }
+    }
+
+    fn clear_fifo_flags(&mut self, fifo: Fifo, full: bool) {
+        // FULL and FOVR are rc_w1, RFOM must be kept at 0 to not release a frame
+        let rfr = &self.can.rfr[fifo as usize];
+        if rfr.read().fovr().bit_is_set() {
+            self.overruns[fifo as usize] = self.overruns[fifo as usize].wrapping_add(1);
+        }
+        rfr.write(|w| {
+            w.fovr().clear();
+            if full {
+                w.full().clear();
+            }
+            w
+        });
+    }
+
+    /// Returns the events whose status flags are currently set
+    pub fn get_interrupt(&self) -> Event {
+        let rf0r = self.can.rfr[0].read();
+        let rf1r = self.can.rfr[1].read();
+        let esr = self.can.esr.read();
+        let msr = self.can.msr.read();
+        let tsr = self.can.tsr.read();
+
+        let mut event = Event::empty();
+        event.set(
+            Event::TxMailboxEmpty,
+            tsr.rqcp0().bit_is_set() || tsr.rqcp1().bit_is_set() || tsr.rqcp2().bit_is_set(),
+        );
+        event.set(Event::Fifo0MessagePending, rf0r.fmp().bits() != 0);
+        event.set(Event::Fifo0Full, rf0r.full().bit_is_set());
+        event.set(Event::Fifo0Overrun, rf0r.fovr().bit_is_set());
+        event.set(Event::Fifo1MessagePending, rf1r.fmp().bits() != 0);
+        event.set(Event::Fifo1Full, rf1r.full().bit_is_set());
+        event.set(Event::Fifo1Overrun, rf1r.fovr().bit_is_set());
+        event.set(Event::ErrorWarning, esr.ewgf().bit_is_set());
+        event.set(Event::ErrorPassive, esr.epvf().bit_is_set());
+        event.set(Event::BusOff, esr.boff().bit_is_set());
+        event.set(Event::LastErrorCode, esr.lec().bits() != 0);
+        event.set(Event::Error, msr.erri().bit_is_set());
+        event.set(Event::Wakeup, msr.wkui().bit_is_set());
+        event.set(Event::Sleep, msr.slaki().bit_is_set());
+        event
+    }
+
+    /// Checks both receive FIFOs for an overrun, counts and clears it.
+    ///
+    /// Returns `true` if at least one frame was lost since the last check.
+    pub fn check_overrun(&mut self) -> bool {
+        let before = self.overruns;
+        self.clear_fifo_flags(Fifo::Fifo0, false);
+        self.clear_fifo_flags(Fifo::Fifo1, false);
+        before != self.overruns
+    }
+
+    /// Number of overruns detected on `fifo` since the peripheral was created.
+    ///
+    /// Each overrun means at least one frame was dropped by the hardware.
+    /// Overruns are detected by [`Can::check_overrun`] and [`Can::clear_interrupt`].
+    pub fn overrun_count(&self, fifo: Fifo) -> u32 {
+        self.overruns[fifo as usize]
+    }
+
+    /// Reads the last error code detected on the bus
+    pub fn last_error_code(&self) -> LastErrorCode {
+        self.can.esr.read().lec().bits().into()
+    }
+
+    /// Sets the last error code to [`LastErrorCode::SetBySoftware`], so that
+    /// the next bus error can be distinguished from an old one
+    pub fn clear_last_error_code(&mut self) {
+        self.can.esr.modify(|_, w| w.lec().custom());
+    }
+
+    /// Transmit error counter
+    pub fn transmit_error_count(&self) -> u8 {
+        self.can.esr.read().tec().bits()
+    }
+
+    /// Receive error counter
+    pub fn receive_error_count(&self) -> u8 {
+        self.can.esr.read().rec().bits()
+    }
+
+    /// Returns `true` if the controller is in bus-off state
+    pub fn is_bus_off(&self) -> bool {
+        self.can.esr.read().boff().bit_is_set()
+    }
+
+    /// Configures how the controller leaves the bus-off state.
+    ///
+    /// With `automatic` recovery, the controller rejoins the bus by itself after
+    /// monitoring 128 occurrences of 11 recessive bits. Otherwise [`Can::recover`]
+    /// has to be called.
+    pub fn bus_off_recovery(&mut self, automatic: bool) {
+        self.can.mcr.modify(|_, w| w.abom().bit(automatic));
+    }
+
+    /// Selects loopback and silent test modes.
+    ///
+    /// `CAN_BTR` is only writable in initialization mode, which is entered for the
+    /// update and left again if the controller was running. Call this before handing
+    /// the peripheral to `bxcan`, or use `bxcan`'s own configuration once it owns it.
+    pub fn set_test_mode(&mut self, mode: TestMode) {
+        let mcr = self.can.mcr.read().bits();
+        self.can
+            .mcr
+            .modify(|_, w| w.sleep().clear_bit().inrq().set_bit());
+        while self.can.msr.read().inak().bit_is_clear() {}
+        self.can
+            .btr
+            .modify(|_, w| w.lbkm().bit(mode.loopback()).silm().bit(mode.silent()));
+        self.can.mcr.write(|w| unsafe { w.bits(mcr) });
+    }
+
+    /// Enters sleep mode once the current transfers are done
+    ///
+    /// With `auto_wakeup` the controller leaves sleep mode by itself on the start of frame
+    /// of the next frame on the bus, and [`Event::Wakeup`] is raised. That frame is lost,
+    /// see [`Can::wake_cause`]. Otherwise [`Can::wake`] has to be called.
+    ///
+    /// Returns [`Timeout`] if the controller doesn't acknowledge, the sleep request is then
+    /// left pending.
+    pub fn sleep(&mut self, auto_wakeup: bool) -> Result<(), Timeout> {
+        self.clear_interrupt(Event::Wakeup | Event::Sleep);
+        self.can.mcr.modify(|_, w| {
+            w.awum()
+                .bit(auto_wakeup)
+                .inrq()
+                .clear_bit()
+                .sleep()
+                .set_bit()
+        });
+        wait_for(MODE_POLLS, || self.can.msr.read().slak().bit_is_set())
+    }
+
+    /// Leaves sleep mode and waits until the controller synchronized on the bus, which
+    /// takes 11 recessive bits
+    ///
+    /// Returns [`Timeout`] if the bus doesn't become recessive, the controller then
+    /// leaves sleep mode by itself once it does.
+    pub fn wake(&mut self) -> Result<(), Timeout> {
+        self.can.mcr.modify(|_, w| w.sleep().clear_bit());
+        wait_for(MODE_POLLS, || self.can.msr.read().slak().bit_is_clear())
+    }
+
+    /// Returns `true` if the controller is in sleep mode
+    pub fn is_sleeping(&self) -> bool {
+        self.can.msr.read().slak().bit_is_set()
+    }
+
+    /// Tells whether the last wakeup was caused by the bus, then the frame that caused it
+    /// was not received, or by software
+    ///
+    /// The bus wakeup flag is cleared by [`Can::sleep`] and by clearing [`Event::Wakeup`].
+    pub fn wake_cause(&self) -> WakeCause {
+        if self.can.msr.read().wkui().bit_is_set() {
+            WakeCause::Bus
+        } else {
+            WakeCause::Software
+        }
+    }
+
+    /// Starts recovery from bus-off state when automatic recovery is disabled.
+    ///
+    /// The controller rejoins the bus after monitoring 128 occurrences of
+    /// 11 recessive bits, check [`Can::is_bus_off`] to find out when it's done.
+    ///
+    /// Returns [`Timeout`] if the controller doesn't enter initialization mode, it is
+    /// then left running.
+    pub fn recover(&mut self) -> Result<(), Timeout> {
+        self.can.mcr.modify(|_, w| w.inrq().set_bit());
+        let entered = wait_for(MODE_POLLS, || self.can.msr.read().inak().bit_is_set());
+        self.can.mcr.modify(|_, w| w.inrq().clear_bit());
+        entered
+    }
+}
+
 unsafe impl<PINS> bxcan::Instance for Can<CAN1, PINS> {
     const REGISTERS: *mut bxcan::RegisterBlock = CAN1::ptr() as *mut _;
 }
@@ -155,3 +720,138 @@ unsafe impl<PINS> bxcan::FilterOwner for Can<CAN1, PINS> {
 }
 
 unsafe impl<PINS> bxcan::MasterInstance for Can<CAN1, PINS> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fugit::RateExtU32;
+
+    #[test]
+    fn wait_for_gives_up() {
+        let mut polls = 0;
+        let result = wait_for(100, || {
+            polls += 1;
+            false
+        });
+        assert_eq!(result, Err(Timeout));
+        assert_eq!(polls, 100);
+    }
+
+    #[test]
+    fn wait_for_stops_when_done() {
+        let mut polls = 0;
+        let result = wait_for(100, || {
+            polls += 1;
+            polls == 3
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(polls, 3);
+        assert_eq!(StandbyError::<()>::from(Timeout), StandbyError::Timeout);
+    }
+
+    #[test]
+    fn event_bits_match_ier() {
+        assert_eq!(Event::TxMailboxEmpty.bits(), 1 << 0); // TMEIE
+        assert_eq!(Event::Fifo0Overrun.bits(), 1 << 3); // FOVIE0
+        assert_eq!(Event::Fifo1MessagePending.bits(), 1 << 4); // FMPIE1
+        assert_eq!(Event::BusOff.bits(), 1 << 10); // BOFIE
+        assert_eq!(Event::Error.bits(), 1 << 15); // ERRIE
+        assert_eq!(Event::Sleep.bits(), 1 << 17); // SLKIE
+                                                  // Bits 7, 12-14 and 18-31 are reserved
+        assert_eq!(Event::all().bits() & 0xfffc_7080, 0);
+    }
+
+    #[test]
+    fn msr_clear_bits_of_events() {
+        assert_eq!(msr_clear_bits(Event::empty()), 0);
+        // Flags of FIFOs and mailboxes are not in MSR
+        assert_eq!(
+            msr_clear_bits(Event::Fifo0Overrun | Event::TxMailboxEmpty),
+            0
+        );
+        // All error events share ERRI
+        assert_eq!(msr_clear_bits(Event::BusOff), 1 << 2);
+        assert_eq!(msr_clear_bits(Event::ErrorWarning | Event::Error), 1 << 2);
+        assert_eq!(msr_clear_bits(Event::Wakeup), 1 << 3);
+        assert_eq!(msr_clear_bits(Event::Sleep), 1 << 4);
+        assert_eq!(msr_clear_bits(Event::all()), 0b11100);
+    }
+
+    #[test]
+    fn last_error_code_decoding() {
+        assert_eq!(LastErrorCode::from(0), LastErrorCode::NoError);
+        assert_eq!(LastErrorCode::from(3), LastErrorCode::AcknowledgmentError);
+        assert_eq!(LastErrorCode::from(6), LastErrorCode::CrcError);
+        assert_eq!(LastErrorCode::from(7), LastErrorCode::SetBySoftware);
+        // Only the LEC bits are decoded
+        assert_eq!(LastErrorCode::from(0b1111_0001), LastErrorCode::StuffError);
+    }
+
+    #[test]
+    fn bit_timing_42mhz() {
+        // 84 APB1 cycles per bit, 14 quanta of 6 cycles put the sample point at 85.7 %
+        let timing = BitTiming::for_bitrate(42.MHz(), 500_000, 0.875).unwrap();
+        assert_eq!(
+            timing,
+            BitTiming {
+                prescaler: 6,
+                seg1: 11,
+                seg2: 2,
+                sjw: 2,
+            }
+        );
+        assert_eq!(timing.btr(), 0x011a_0005);
+        assert_eq!(timing.bitrate(42.MHz()), 500_000);
+
+        let timing = BitTiming::for_bitrate(42.MHz(), 1_000_000, 0.75).unwrap();
+        assert_eq!(timing.bitrate(42.MHz()), 1_000_000);
+        assert_eq!((timing.prescaler, timing.seg1, timing.seg2), (2, 15, 5));
+    }
+
+    #[test]
+    fn bit_timing_8mhz() {
+        // 16 quanta of one cycle, sample point exactly at 87.5 %
+        let timing = BitTiming::for_bitrate(8.MHz(), 500_000, 0.875).unwrap();
+        assert_eq!((timing.prescaler, timing.seg1, timing.seg2), (1, 13, 2));
+        assert_eq!(timing.btr(), 0x011c_0000);
+
+        let timing = BitTiming::for_bitrate(8.MHz(), 125_000, 0.875).unwrap();
+        assert_eq!(timing.bitrate(8.MHz()), 125_000);
+        assert_eq!((timing.prescaler, timing.seg1, timing.seg2), (4, 13, 2));
+    }
+
+    #[test]
+    fn bit_timing_unachievable() {
+        assert_eq!(
+            BitTiming::for_bitrate(42.MHz(), 0, 0.875),
+            Err(BitrateUnachievable)
+        );
+        // More than 1024 cycles per quantum
+        assert_eq!(
+            BitTiming::for_bitrate(42.MHz(), 1_000, 0.875),
+            Err(BitrateUnachievable)
+        );
+        // Faster than 4 quanta of one cycle
+        assert_eq!(
+            BitTiming::for_bitrate(8.MHz(), 3_000_000, 0.875),
+            Err(BitrateUnachievable)
+        );
+        // `bitrate * quanta` and the error check must not overflow
+        assert_eq!(
+            BitTiming::for_bitrate(42.MHz(), u32::MAX, 0.875),
+            Err(BitrateUnachievable)
+        );
+        let timing = BitTiming::for_bitrate(u32::MAX.Hz(), 4_000_000, 0.875).unwrap();
+        assert!(timing.bitrate(u32::MAX.Hz()).abs_diff(4_000_000) * 200 <= 4_000_000);
+    }
+
+    #[test]
+    fn test_mode_bits() {
+        // (LBKM, SILM)
+        let bits = |mode: TestMode| (mode.loopback(), mode.silent());
+        assert_eq!(bits(TestMode::Normal), (false, false));
+        assert_eq!(bits(TestMode::Loopback), (true, false));
+        assert_eq!(bits(TestMode::Silent), (false, true));
+        assert_eq!(bits(TestMode::SilentLoopback), (true, true));
+    }
+}
@@ -6,12 +6,77 @@
 //!
 //! It operates word-at-a-time, and takes 4 AHB/HCLK cycles per word
 //! to calculate. This operation stalls the AHB bus for that time.
+//!
+//! [`Crc32::digest`] streams bytes of any length and applies the input/output
+//! reflection and final XOR of the common CRC-32 variants in software.
+//!
+//! ```
+//! use stm32f4xx_hal::crc32::{Config, Crc32};
+//! use stm32f4xx_hal::pac::Peripherals;
+//!
+//! let dp = Peripherals::take().unwrap();
+//! let mut crc = Crc32::new(dp.CRC);
+//! let mut digest = crc.digest(Config::ISO_HDLC);
+//! digest.update(b"1234");
+//! digest.update(b"56789");
+//! assert_eq!(digest.finalize(), 0xCBF4_3926);
+//! ```
 
 use crate::pac::{CRC, RCC};
 use crate::rcc::{Enable, Reset};
 use core::mem::MaybeUninit;
 use core::ptr::copy_nonoverlapping;
 
+/// Polynomial used by the hardware
+const POLY: u32 = 0x04C1_1DB7;
+
+/// Software processing of the data and result around the hardware CRC
+///
+/// The hardware always starts from `0xFFFF_FFFF` and shifts bits in MSB first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Reverse the bits of each input byte
+    pub reflect_in: bool,
+    /// Reverse the bits of the result
+    pub reflect_out: bool,
+    /// Value XORed with the result
+    pub final_xor: u32,
+}
+
+impl Config {
+    /// CRC-32/MPEG-2, the raw hardware result
+    pub const MPEG_2: Self = Self {
+        reflect_in: false,
+        reflect_out: false,
+        final_xor: 0,
+    };
+    /// CRC-32/BZIP2
+    pub const BZIP2: Self = Self {
+        reflect_in: false,
+        reflect_out: false,
+        final_xor: 0xFFFF_FFFF,
+    };
+    /// CRC-32/ISO-HDLC, used by zlib, Ethernet and PNG
+    pub const ISO_HDLC: Self = Self {
+        reflect_in: true,
+        reflect_out: true,
+        final_xor: 0xFFFF_FFFF,
+    };
+    /// CRC-32/JAMCRC
+    pub const JAMCRC: Self = Self {
+        reflect_in: true,
+        reflect_out: true,
+        final_xor: 0,
+    };
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::MPEG_2
+    }
+}
+
 /// A handle to a HAL CRC32 peripheral
 pub struct Crc32 {
     periph: CRC,
@@ -118,6 +183,19 @@ impl Crc32 {
         self.periph.dr.read().bits()
     }
 
+    /// Starts a CRC computation over a stream of bytes
+    ///
+    /// The CRC state is reset.
+    pub fn digest(&mut self, config: Config) -> Digest<'_> {
+        self.init();
+        Digest {
+            crc: self,
+            config,
+            buffer: [0; 4],
+            buffered: 0,
+        }
+    }
+
     /// Consume the HAL peripheral, returning the PAC peripheral
     pub fn release(self) -> CRC {
         unsafe {
@@ -130,3 +208,173 @@ impl Crc32 {
         self.periph
     }
 }
+
+/// Streaming CRC computation, result of [`Crc32::digest`]
+pub struct Digest<'a> {
+    crc: &'a mut Crc32,
+    config: Config,
+    buffer: [u8; 4],
+    buffered: usize,
+}
+
+impl Digest<'_> {
+    /// Feed bytes, of any length
+    ///
+    /// Bytes that don't fill a word are kept until the next update or `finalize`.
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.buffered > 0 {
+            let n = (4 - self.buffered).min(data.len());
+            self.buffer[self.buffered..self.buffered + n].copy_from_slice(&data[..n]);
+            self.buffered += n;
+            data = &data[n..];
+            if self.buffered < 4 {
+                return;
+            }
+            self.write(u32::from_le_bytes(self.buffer));
+            self.buffered = 0;
+        }
+
+        let chunks = data.chunks_exact(4);
+        let remainder = chunks.remainder();
+        for chunk in chunks {
+            self.write(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+        }
+        self.buffer[..remainder.len()].copy_from_slice(remainder);
+        self.buffered = remainder.len();
+    }
+
+    /// Feed words, each one is processed as its 4 bytes in memory (little-endian) order
+    ///
+    /// This is the fast path when no bytes are left over from previous updates.
+    pub fn update_words(&mut self, data: &[u32]) {
+        if self.buffered > 0 {
+            for word in data {
+                self.update(&word.to_le_bytes());
+            }
+        } else {
+            for word in data {
+                self.write(*word);
+            }
+        }
+    }
+
+    /// Returns the CRC of all the data fed since the digest was started or reset
+    pub fn finalize(self) -> u32 {
+        let mut crc = self.crc.periph.dr.read().bits();
+        // The hardware only takes whole words, finish in software
+        for byte in &self.buffer[..self.buffered] {
+            crc = shift_byte(crc, *byte, self.config.reflect_in);
+        }
+        self.config.output(crc)
+    }
+
+    /// Restarts the computation, dropping all the data fed so far
+    pub fn reset(&mut self) {
+        self.crc.init();
+        self.buffered = 0;
+    }
+
+    /// Writes a little-endian word, first byte is shifted in first
+    fn write(&mut self, word: u32) {
+        let word = input_word(word, self.config.reflect_in);
+        self.crc.periph.dr.write(|w| w.bits(word));
+    }
+}
+
+impl Config {
+    /// Final value from the CRC register
+    fn output(&self, crc: u32) -> u32 {
+        let crc = if self.reflect_out {
+            crc.reverse_bits()
+        } else {
+            crc
+        };
+        crc ^ self.final_xor
+    }
+}
+
+/// Data register value for a little-endian word, the hardware shifts in the MSB first
+fn input_word(word: u32, reflect_in: bool) -> u32 {
+    if reflect_in {
+        word.reverse_bits()
+    } else {
+        word.swap_bytes()
+    }
+}
+
+/// Shifts a byte into `crc` in software, as the hardware does for each byte of a word
+fn shift_byte(mut crc: u32, byte: u8, reflect_in: bool) -> u32 {
+    let byte = if reflect_in {
+        byte.reverse_bits()
+    } else {
+        byte
+    };
+    crc ^= (byte as u32) << 24;
+    for _ in 0..8 {
+        crc = if crc & 0x8000_0000 != 0 {
+            (crc << 1) ^ POLY
+        } else {
+            crc << 1
+        };
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hardware CRC of a data register write
+    fn write_dr(crc: u32, word: u32) -> u32 {
+        word.to_be_bytes()
+            .iter()
+            .fold(crc, |crc, &byte| shift_byte(crc, byte, false))
+    }
+
+    /// Same steps as [`Digest`], whole words through the data register and the remaining
+    /// bytes in software
+    fn digest(config: Config, data: &[u8]) -> u32 {
+        let chunks = data.chunks_exact(4);
+        let remainder = chunks.remainder();
+        let mut crc = 0xFFFF_FFFF;
+        for chunk in chunks {
+            let word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            crc = write_dr(crc, input_word(word, config.reflect_in));
+        }
+        for &byte in remainder {
+            crc = shift_byte(crc, byte, config.reflect_in);
+        }
+        config.output(crc)
+    }
+
+    #[test]
+    fn check_values() {
+        // Catalogue of parametrised CRC algorithms, CRC of "123456789"
+        for (config, check) in [
+            (Config::MPEG_2, 0x0376_E6E7),
+            (Config::BZIP2, 0xFC89_1918),
+            (Config::ISO_HDLC, 0xCBF4_3926),
+            (Config::JAMCRC, 0x340B_C6D9),
+        ] {
+            assert_eq!(digest(config, b"123456789"), check);
+        }
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(digest(Config::MPEG_2, b""), 0xFFFF_FFFF);
+        assert_eq!(digest(Config::ISO_HDLC, b""), 0);
+    }
+
+    #[test]
+    fn words_match_bytes() {
+        // A word through the register is its 4 bytes in order
+        for reflect_in in [false, true] {
+            let word = u32::from_le_bytes(*b"1234");
+            let bytes = b"1234"
+                .iter()
+                .fold(0xFFFF_FFFF, |crc, &byte| shift_byte(crc, byte, reflect_in));
+            assert_eq!(write_dr(0xFFFF_FFFF, input_word(word, reflect_in)), bytes);
+        }
+    }
+}
@@ -0,0 +1,377 @@
+//! Cryptographic processor (CRYP)
+//!
+//! AES-128, AES-192 and AES-256 encryption and decryption in ECB, CBC and CTR modes,
+//! available on STM32F415, F417, F437, F439 and F479.
+//!
+//! Data is processed in 16-byte blocks, by the CPU with [`Cryp::encrypt_blocks`] and
+//! [`Cryp::decrypt_blocks`], or by DMA2 stream 6 (input) and stream 5 (output), channel 2.
+//! Bytes are handled in memory order, the processor swaps them in and out of its registers.
+//!
+//! # Example
+//!
+//! AES-128 ECB, FIPS-197 appendix C.1:
+//!
+//! ```
+//! use stm32f4xx_hal::{cryp::{Cryp, Key, Mode}, pac};
+//!
+//! let dp = pac::Peripherals::take().unwrap();
+//! let mut cryp = Cryp::new(dp.CRYP);
+//!
+//! let key = Key::Aes128([
+//!     0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+//!     0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+//! ]);
+//! let plaintext = [
+//!     0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77,
+//!     0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
+//! ];
+//! let mut ciphertext = [0; 16];
+//! cryp.encrypt_blocks(&key, Mode::Ecb, &plaintext, &mut ciphertext).unwrap();
+//! assert_eq!(ciphertext[..4], [0x69, 0xc4, 0xe0, 0xd8]);
+//! ```
+//!
+//! # DMA
+//!
+//! Call [`Cryp::init`], then [`Cryp::dma`] to get the DMA targets. Set up the output transfer
+//! on [`DataOut`] and the input transfer on [`DataIn`], both with 32-bit memory accesses,
+//! start them, then call [`CrypDma::enable`]. Once the output transfer is complete, call
+//! [`CrypDma::disable`] and [`CrypDma::release`] with the targets to get the processor back.
+//!
+//! # RustCrypto
+//!
+//! With the `cipher` feature, [`Cryp::into_cipher`] gives an [`AesCipher`] implementing the
+//! `cipher::BlockEncrypt` and `BlockDecrypt` traits.
+
+use crate::dma::traits::{DMASet, PeriAddress};
+use crate::dma::{MemoryToPeripheral, PeripheralToMemory, Stream5, Stream6};
+use crate::pac::{CRYP, DMA2, RCC};
+use crate::rcc::{Enable, Reset};
+
+#[cfg(feature = "cipher")]
+mod rustcrypto;
+#[cfg(feature = "cipher")]
+pub use rustcrypto::AesCipher;
+
+const CR_ALGODIR: u32 = 1 << 2;
+const CR_ALGOMODE_SHIFT: u32 = 3;
+/// Byte swapping, words are written and read in memory order
+const CR_DATATYPE_BYTES: u32 = 0b10 << 6;
+const CR_KEYSIZE_SHIFT: u32 = 8;
+const CR_FFLUSH: u32 = 1 << 14;
+const CR_CRYPEN: u32 = 1 << 15;
+
+const SR_IFNF: u32 = 1 << 1;
+const SR_OFNE: u32 = 1 << 2;
+const SR_BUSY: u32 = 1 << 4;
+
+const DMACR_DIEN: u32 = 1 << 0;
+const DMACR_DOEN: u32 = 1 << 1;
+
+const ALGOMODE_AES_ECB: u32 = 0b100;
+const ALGOMODE_AES_CBC: u32 = 0b101;
+const ALGOMODE_AES_CTR: u32 = 0b110;
+const ALGOMODE_AES_KEY: u32 = 0b111;
+
+/// AES block size in bytes
+pub const BLOCK_SIZE: usize = 16;
+
+/// AES key
+#[derive(Clone)]
+pub enum Key {
+    Aes128([u8; 16]),
+    Aes192([u8; 24]),
+    Aes256([u8; 32]),
+}
+
+impl Key {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Self::Aes128(key) => key,
+            Self::Aes192(key) => key,
+            Self::Aes256(key) => key,
+        }
+    }
+
+    /// `KEYSIZE` field value
+    fn size(&self) -> u32 {
+        match self {
+            Self::Aes128(_) => 0b00,
+            Self::Aes192(_) => 0b01,
+            Self::Aes256(_) => 0b10,
+        }
+    }
+}
+
+/// Chaining mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Mode {
+    /// Electronic codebook, blocks are processed independently
+    Ecb,
+    /// Cipher block chaining with an initialization vector
+    Cbc([u8; 16]),
+    /// Counter mode, the last 32 bits of the initial counter block are incremented
+    /// for each block
+    Ctr([u8; 16]),
+}
+
+impl Mode {
+    fn algomode(&self) -> u32 {
+        match self {
+            Self::Ecb => ALGOMODE_AES_ECB,
+            Self::Cbc(_) => ALGOMODE_AES_CBC,
+            Self::Ctr(_) => ALGOMODE_AES_CTR,
+        }
+    }
+}
+
+/// Processing direction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    Encrypt,
+    Decrypt,
+}
+
+/// CRYP errors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Input and output differ in length, or aren't a multiple of 16 bytes
+    Length,
+}
+
+/// Cryptographic processor
+pub struct Cryp {
+    cryp: CRYP,
+}
+
+impl Cryp {
+    /// Enables and resets the cryptographic processor
+    pub fn new(cryp: CRYP) -> Self {
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            CRYP::enable(rcc);
+            CRYP::reset(rcc);
+        }
+
+        Self { cryp }
+    }
+
+    /// Loads the key and initialization vector, and prepares the decryption key if needed
+    ///
+    /// Data processed afterwards continues the same chain until the next call.
+    pub fn init(&mut self, key: &Key, mode: Mode, direction: Direction) {
+        self.wait_idle();
+        self.cryp.cr.write(|w| unsafe { w.bits(0) });
+
+        let key_bytes = key.bytes();
+        // Keys are right-aligned in K0LR..K3RR, first byte is the most significant
+        let mut words = [0; 8];
+        for (word, bytes) in words[8 - key_bytes.len() / 4..]
+            .iter_mut()
+            .zip(key_bytes.chunks_exact(4))
+        {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        unsafe {
+            for (key, pair) in self.cryp.key.iter().zip(words.chunks_exact(2)) {
+                key.klr.write(|w| w.bits(pair[0]));
+                key.krr.write(|w| w.bits(pair[1]));
+            }
+        }
+
+        let keysize = key.size() << CR_KEYSIZE_SHIFT;
+        if direction == Direction::Decrypt && !matches!(mode, Mode::Ctr(_)) {
+            self.prepare_decryption_key(keysize);
+        }
+
+        if let Mode::Cbc(iv) | Mode::Ctr(iv) = mode {
+            let iv = |i: usize| u32::from_be_bytes([iv[i], iv[i + 1], iv[i + 2], iv[i + 3]]);
+            unsafe {
+                self.cryp.init[0].ivlr.write(|w| w.bits(iv(0)));
+                self.cryp.init[0].ivrr.write(|w| w.bits(iv(4)));
+                self.cryp.init[1].ivlr.write(|w| w.bits(iv(8)));
+                self.cryp.init[1].ivrr.write(|w| w.bits(iv(12)));
+            }
+        }
+
+        let mut cr = keysize | CR_DATATYPE_BYTES | mode.algomode() << CR_ALGOMODE_SHIFT;
+        if direction == Direction::Decrypt {
+            cr |= CR_ALGODIR;
+        }
+        unsafe {
+            self.cryp.cr.write(|w| w.bits(cr));
+            self.cryp.cr.write(|w| w.bits(cr | CR_FFLUSH));
+            self.cryp.cr.write(|w| w.bits(cr | CR_CRYPEN));
+        }
+    }
+
+    /// ECB and CBC decryption use the last round key, derived from the key
+    fn prepare_decryption_key(&mut self, keysize: u32) {
+        let cr = keysize | ALGOMODE_AES_KEY << CR_ALGOMODE_SHIFT;
+        unsafe {
+            self.cryp.cr.write(|w| w.bits(cr));
+            self.cryp.cr.write(|w| w.bits(cr | CR_CRYPEN));
+        }
+        self.wait_idle();
+    }
+
+    /// Processes blocks with the key and mode of the last [`Cryp::init`]
+    ///
+    /// `input` and `output` must have the same length, a multiple of 16 bytes.
+    pub fn process_blocks(&mut self, input: &[u8], output: &mut [u8]) -> Result<(), Error> {
+        if input.len() != output.len() || input.len() % BLOCK_SIZE != 0 {
+            return Err(Error::Length);
+        }
+        for (input, output) in input
+            .chunks_exact(BLOCK_SIZE)
+            .zip(output.chunks_exact_mut(BLOCK_SIZE))
+        {
+            for word in input.chunks_exact(4) {
+                while self.cryp.sr.read().bits() & SR_IFNF == 0 {}
+                let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                self.cryp.din.write(|w| unsafe { w.bits(word) });
+            }
+            for bytes in output.chunks_exact_mut(4) {
+                while self.cryp.sr.read().bits() & SR_OFNE == 0 {}
+                bytes.copy_from_slice(&self.cryp.dout.read().bits().to_le_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    /// Processes a single block in place, with the key and mode of the last [`Cryp::init`]
+    pub fn process_block(&mut self, block: &mut [u8; BLOCK_SIZE]) {
+        let input = *block;
+        // Lengths are valid
+        self.process_blocks(&input, block).ok();
+    }
+
+    /// Encrypts `input` into `output`, starting a new chain
+    pub fn encrypt_blocks(
+        &mut self,
+        key: &Key,
+        mode: Mode,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        self.init(key, mode, Direction::Encrypt);
+        self.process_blocks(input, output)
+    }
+
+    /// Decrypts `input` into `output`, starting a new chain
+    pub fn decrypt_blocks(
+        &mut self,
+        key: &Key,
+        mode: Mode,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        self.init(key, mode, Direction::Decrypt);
+        self.process_blocks(input, output)
+    }
+
+    /// Returns the current initialization vector, or counter block in CTR mode
+    ///
+    /// The processor is stopped, call [`Cryp::init`] again to go on.
+    pub fn iv(&mut self) -> [u8; BLOCK_SIZE] {
+        self.wait_idle();
+        self.cryp
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !CR_CRYPEN) });
+        let mut iv = [0; BLOCK_SIZE];
+        let init = &self.cryp.init;
+        iv[0..4].copy_from_slice(&init[0].ivlr.read().bits().to_be_bytes());
+        iv[4..8].copy_from_slice(&init[0].ivrr.read().bits().to_be_bytes());
+        iv[8..12].copy_from_slice(&init[1].ivlr.read().bits().to_be_bytes());
+        iv[12..16].copy_from_slice(&init[1].ivrr.read().bits().to_be_bytes());
+        iv
+    }
+
+    /// Hands the processor over to DMA, with the key and mode of the last [`Cryp::init`]
+    ///
+    /// Returns the DMA input and output targets, only one pair exists until
+    /// [`CrypDma::release`].
+    pub fn dma(self) -> (CrypDma, DataIn, DataOut) {
+        (
+            CrypDma { cryp: self },
+            DataIn { _private: () },
+            DataOut { _private: () },
+        )
+    }
+
+    fn wait_idle(&self) {
+        while self.cryp.sr.read().bits() & SR_BUSY != 0 {}
+    }
+
+    /// Disables the processor and its clock, and returns the CRYP peripheral
+    pub fn release(self) -> CRYP {
+        unsafe {
+            self.cryp.cr.write(|w| w.bits(0));
+            self.cryp.dmacr.write(|w| w.bits(0));
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            CRYP::disable(&(*RCC::ptr()));
+        }
+        self.cryp
+    }
+}
+
+/// Processor given to DMA transfers by [`Cryp::dma`]
+pub struct CrypDma {
+    cryp: Cryp,
+}
+
+impl CrypDma {
+    /// Starts requesting input and output DMA transfers
+    pub fn enable(&mut self) {
+        self.cryp
+            .cryp
+            .dmacr
+            .write(|w| unsafe { w.bits(DMACR_DIEN | DMACR_DOEN) });
+    }
+
+    /// Stops DMA requests
+    pub fn disable(&mut self) {
+        self.cryp.cryp.dmacr.write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Stops DMA requests and takes the processor back with its DMA targets
+    pub fn release(mut self, _input: DataIn, _output: DataOut) -> Cryp {
+        self.disable();
+        self.cryp
+    }
+}
+
+/// DMA target of the input FIFO, DMA2 stream 6 channel 2
+pub struct DataIn {
+    _private: (),
+}
+
+/// DMA source of the output FIFO, DMA2 stream 5 channel 2
+pub struct DataOut {
+    _private: (),
+}
+
+unsafe impl PeriAddress for DataIn {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        unsafe { &(*CRYP::ptr()).din as *const _ as u32 }
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl PeriAddress for DataOut {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        unsafe { &(*CRYP::ptr()).dout as *const _ as u32 }
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl DMASet<Stream6<DMA2>, 2, MemoryToPeripheral> for DataIn {}
+unsafe impl DMASet<Stream5<DMA2>, 2, PeripheralToMemory> for DataOut {}
@@ -0,0 +1,98 @@
+//! RustCrypto `cipher` traits
+//!
+//! `BlockEncrypt` and `BlockDecrypt` process single blocks with a key bound to the
+//! processor, which is [`Cryp::into_cipher`]. Block modes and AEAD constructions from
+//! RustCrypto then run on top of the hardware AES.
+//!
+//! ```ignore
+//! use cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt};
+//!
+//! let aes = Cryp::new(dp.CRYP).into_cipher(Key::Aes128(key));
+//! let mut block = GenericArray::from(plaintext);
+//! aes.encrypt_block(&mut block);
+//! aes.decrypt_block(&mut block);
+//! ```
+
+use core::cell::{Cell, RefCell};
+
+use cipher::consts::{U1, U16};
+use cipher::inout::InOut;
+use cipher::{
+    Block, BlockBackend, BlockCipher, BlockClosure, BlockDecrypt, BlockEncrypt, BlockSizeUser,
+    ParBlocksSizeUser,
+};
+
+use super::{Cryp, Direction, Key, Mode};
+
+impl Cryp {
+    /// Binds the processor to `key` for the RustCrypto `cipher` traits
+    pub fn into_cipher(self, key: Key) -> AesCipher {
+        AesCipher {
+            cryp: RefCell::new(self),
+            key,
+            direction: Cell::new(None),
+        }
+    }
+}
+
+/// AES on the processor, implementing `cipher::BlockEncrypt` and `cipher::BlockDecrypt`
+pub struct AesCipher {
+    cryp: RefCell<Cryp>,
+    key: Key,
+    /// Direction the processor is initialized for, the decryption key is only prepared
+    /// when switching to decryption
+    direction: Cell<Option<Direction>>,
+}
+
+impl AesCipher {
+    fn with_backend(&self, direction: Direction, f: impl BlockClosure<BlockSize = U16>) {
+        let mut cryp = self.cryp.borrow_mut();
+        if self.direction.get() != Some(direction) {
+            cryp.init(&self.key, Mode::Ecb, direction);
+            self.direction.set(Some(direction));
+        }
+        f.call(&mut Backend(&mut cryp));
+    }
+
+    /// Returns the processor
+    pub fn release(self) -> Cryp {
+        self.cryp.into_inner()
+    }
+}
+
+impl BlockSizeUser for AesCipher {
+    type BlockSize = U16;
+}
+
+impl BlockCipher for AesCipher {}
+
+impl BlockEncrypt for AesCipher {
+    fn encrypt_with_backend(&self, f: impl BlockClosure<BlockSize = U16>) {
+        self.with_backend(Direction::Encrypt, f);
+    }
+}
+
+impl BlockDecrypt for AesCipher {
+    fn decrypt_with_backend(&self, f: impl BlockClosure<BlockSize = U16>) {
+        self.with_backend(Direction::Decrypt, f);
+    }
+}
+
+/// ECB processing of single blocks
+struct Backend<'a>(&'a mut Cryp);
+
+impl BlockSizeUser for Backend<'_> {
+    type BlockSize = U16;
+}
+
+impl ParBlocksSizeUser for Backend<'_> {
+    type ParBlocksSize = U1;
+}
+
+impl BlockBackend for Backend<'_> {
+    fn proc_block(&mut self, mut block: InOut<'_, '_, Block<Self>>) {
+        let input = *block.get_in();
+        // Lengths are valid
+        self.0.process_blocks(&input, block.get_out()).ok();
+    }
+}
@@ -0,0 +1,658 @@
+//! Digital camera interface (DCMI)
+//!
+//! The DCMI receives data from a parallel camera sensor, clocked by the sensor's `PIXCLK`,
+//! and frames it with the `HSYNC`/`VSYNC` signals or with synchronisation codes embedded in
+//! the data stream. Received data is packed in 32-bit words and read by DMA2 stream 1 or
+//! stream 7, channel 1.
+//!
+//! # Capture
+//!
+//! Configure and enable the DMA transfer first, then call [`Dcmi::start_capture`]. Capture
+//! begins with the next frame. In snapshot mode a single frame is captured and capture stops
+//! by itself. In continuous mode, [`Dcmi::stop_capture`] stops capture at the end of the
+//! current frame, [`Dcmi::is_capturing`] returns `false` once it happened.
+//!
+//! A transfer is limited to 65535 words. Larger frames need double-buffer mode, refilling
+//! the idle buffer in the DMA transfer complete interrupt.
+//!
+//! # Example
+//!
+//! A QVGA RGB565 snapshot, 320 × 240 × 2 bytes, i.e. 38400 words:
+//!
+//! ```ignore
+//! let pins = DcmiPins {
+//!     data: (
+//!         gpioc.pc6.into_alternate(), gpioc.pc7.into_alternate(),
+//!         gpioe.pe0.into_alternate(), gpioe.pe1.into_alternate(),
+//!         gpioe.pe4.into_alternate(), gpiob.pb6.into_alternate(),
+//!         gpioe.pe5.into_alternate(), gpioe.pe6.into_alternate(),
+//!     ),
+//!     hsync: gpioa.pa4.into_alternate(),
+//!     vsync: gpiob.pb7.into_alternate(),
+//!     pixel_clock: gpioa.pa6.into_alternate(),
+//! };
+//! let dcmi = Dcmi::new(dp.DCMI, pins, Config::default().capture_mode(CaptureMode::Snapshot))?;
+//! let streams = StreamsTuple::new(dp.DMA2);
+//! let mut transfer = Transfer::init_peripheral_to_memory(
+//!     streams.1,
+//!     dcmi,
+//!     frame,
+//!     None,
+//!     DmaConfig::default().memory_increment(true).fifo_enable(true),
+//! );
+//! transfer.start(|dcmi| dcmi.start_capture());
+//! while !transfer.is_transfer_complete() {}
+//! ```
+
+use crate::dma::traits::{DMASet, PeriAddress};
+use crate::dma::{PeripheralToMemory, Stream1, Stream7};
+use crate::gpio::NoPin;
+use crate::pac::{DCMI, DMA2, RCC};
+use crate::rcc::{Enable, Reset};
+
+pub use pins::{DataPins, PinData, PinHsync, PinPixelClock, PinVsync, Pins};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+const CR_CAPTURE: u32 = 1 << 0;
+const CR_ENABLE: u32 = 1 << 14;
+
+/// DCMI errors
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The crop window doesn't fit its register fields
+    InvalidCrop,
+    /// Hardware synchronisation is selected but `HSYNC` or `VSYNC` is [`NoPin`]
+    MissingSyncPins,
+    /// Embedded synchronisation is selected with more than 8 data lines
+    EmbeddedSyncWidth,
+}
+
+/// Number of data lines
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataWidth {
+    /// 8-bit data, `D0` to `D7`
+    Bits8 = 0,
+    /// 10-bit data, `D0` to `D9`
+    Bits10 = 1,
+    /// 12-bit data, `D0` to `D11`
+    Bits12 = 2,
+    /// 14-bit data, `D0` to `D13`
+    Bits14 = 3,
+}
+
+/// Capture mode
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CaptureMode {
+    /// Frames are captured until [`Dcmi::stop_capture`]
+    Continuous,
+    /// A single frame is captured
+    Snapshot,
+}
+
+/// Polarity of a synchronisation signal
+///
+/// The active level of `HSYNC` and `VSYNC` is the blanking period: data is only sampled
+/// while both are inactive.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Polarity {
+    /// Active low
+    ActiveLow,
+    /// Active high
+    ActiveHigh,
+}
+
+/// `PIXCLK` edge on which data is sampled
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Edge {
+    /// Falling edge
+    Falling,
+    /// Rising edge
+    Rising,
+}
+
+/// Synchronisation codes embedded in the data stream, as in ITU-R BT.656
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EmbeddedCodes {
+    /// Frame start code
+    pub frame_start: u8,
+    /// Line start code
+    pub line_start: u8,
+    /// Line end code
+    pub line_end: u8,
+    /// Frame end code
+    pub frame_end: u8,
+}
+
+/// Synchronisation mode
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Synchronisation {
+    /// `HSYNC` and `VSYNC` signals, with their polarities
+    Hardware {
+        /// Polarity of `HSYNC`
+        hsync: Polarity,
+        /// Polarity of `VSYNC`
+        vsync: Polarity,
+    },
+    /// Codes embedded in the data, 8-bit data only, see [`Error::EmbeddedSyncWidth`]
+    Embedded(EmbeddedCodes),
+}
+
+/// `CR` bits of the synchronisation mode, and the codes to write in `ESCR`
+fn sync_bits(
+    synchronisation: Synchronisation,
+    width: DataWidth,
+    hardware_sync: bool,
+) -> Result<(u32, Option<EmbeddedCodes>), Error> {
+    match synchronisation {
+        Synchronisation::Hardware { hsync, vsync } => {
+            if !hardware_sync {
+                return Err(Error::MissingSyncPins);
+            }
+            let mut cr = 0;
+            if hsync == Polarity::ActiveHigh {
+                cr |= 1 << 6;
+            }
+            if vsync == Polarity::ActiveHigh {
+                cr |= 1 << 7;
+            }
+            Ok((cr, None))
+        }
+        // The codes are only recognised on 8-bit data
+        Synchronisation::Embedded(_) if width != DataWidth::Bits8 => Err(Error::EmbeddedSyncWidth),
+        Synchronisation::Embedded(codes) => Ok((1 << 4, Some(codes))),
+    }
+}
+
+/// Frames captured in continuous mode
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameRate {
+    /// Every frame
+    All = 0,
+    /// One frame out of 2
+    OneOfTwo = 1,
+    /// One frame out of 4
+    OneOfFour = 2,
+}
+
+/// Crop window, in pixel clocks horizontally and in lines vertically
+///
+/// With 8-bit data and two bytes per pixel (e.g. RGB565), one pixel takes two pixel clocks.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Crop {
+    /// Pixel clocks skipped at the beginning of each line
+    pub x: u16,
+    /// Lines skipped at the beginning of each frame
+    pub y: u16,
+    /// Pixel clocks captured per line
+    pub width: u16,
+    /// Lines captured
+    pub height: u16,
+}
+
+/// Capture configuration
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    capture_mode: CaptureMode,
+    synchronisation: Synchronisation,
+    pixel_clock_edge: Edge,
+    jpeg: bool,
+    crop: Option<Crop>,
+    frame_rate: FrameRate,
+}
+
+impl Default for Config {
+    /// Continuous capture of every frame, `HSYNC` and `VSYNC` active low, data sampled on
+    /// the rising edge
+    fn default() -> Self {
+        Self {
+            capture_mode: CaptureMode::Continuous,
+            synchronisation: Synchronisation::Hardware {
+                hsync: Polarity::ActiveLow,
+                vsync: Polarity::ActiveLow,
+            },
+            pixel_clock_edge: Edge::Rising,
+            jpeg: false,
+            crop: None,
+            frame_rate: FrameRate::All,
+        }
+    }
+}
+
+impl Config {
+    /// Sets the capture mode
+    pub fn capture_mode(mut self, mode: CaptureMode) -> Self {
+        self.capture_mode = mode;
+        self
+    }
+
+    /// Sets the synchronisation mode
+    pub fn synchronisation(mut self, synchronisation: Synchronisation) -> Self {
+        self.synchronisation = synchronisation;
+        self
+    }
+
+    /// Sets the `PIXCLK` edge data is sampled on
+    pub fn pixel_clock_edge(mut self, edge: Edge) -> Self {
+        self.pixel_clock_edge = edge;
+        self
+    }
+
+    /// Compressed data: `HSYNC` is used as data enable and the crop feature is not available
+    pub fn jpeg(mut self, jpeg: bool) -> Self {
+        self.jpeg = jpeg;
+        self
+    }
+
+    /// Captures only a window of each frame
+    pub fn crop(mut self, crop: Crop) -> Self {
+        self.crop = Some(crop);
+        self
+    }
+
+    /// Captures only some frames in continuous mode
+    pub fn frame_rate(mut self, rate: FrameRate) -> Self {
+        self.frame_rate = rate;
+        self
+    }
+}
+
+/// Interrupt events
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// A frame was captured completely
+    FrameComplete = 1 << 0,
+    /// Data was received before the DMA read the previous word
+    Overrun = 1 << 1,
+    /// An embedded synchronisation code came in an unexpected order
+    SyncError = 1 << 2,
+    /// `VSYNC` changed from active to inactive, i.e. a frame starts
+    Vsync = 1 << 3,
+    /// A line was received completely
+    Line = 1 << 4,
+}
+
+/// Pins used by the DCMI
+pub struct DcmiPins<D, HSYNC, VSYNC, PIXCLK> {
+    /// 8, 10, 12 or 14 data pins, `D0` first
+    pub data: D,
+    /// `HSYNC`, can be [`NoPin`] with embedded synchronisation
+    pub hsync: HSYNC,
+    /// `VSYNC`, can be [`NoPin`] with embedded synchronisation
+    pub vsync: VSYNC,
+    /// `PIXCLK`
+    pub pixel_clock: PIXCLK,
+}
+
+/// Camera interface
+pub struct Dcmi<PINS> {
+    dcmi: DCMI,
+    pins: PINS,
+}
+
+impl<PINS: Pins> Dcmi<PINS> {
+    /// Enables the DCMI and applies `config`, capture isn't started
+    pub fn new(dcmi: DCMI, pins: PINS, config: Config) -> Result<Self, Error> {
+        let mut cr = (PINS::WIDTH as u32) << 10
+            | (config.frame_rate as u32) << 8
+            | (config.jpeg as u32) << 3;
+        if config.capture_mode == CaptureMode::Snapshot {
+            cr |= 1 << 1;
+        }
+        if config.pixel_clock_edge == Edge::Rising {
+            cr |= 1 << 5;
+        }
+
+        let crop = match config.crop {
+            Some(crop) if !config.jpeg => {
+                if crop.x > 0x3fff
+                    || crop.y > 0x1fff
+                    || !(1..=0x4000).contains(&crop.width)
+                    || !(1..=0x4000).contains(&crop.height)
+                {
+                    return Err(Error::InvalidCrop);
+                }
+                cr |= 1 << 2;
+                Some(crop)
+            }
+            _ => None,
+        };
+
+        let (sync_cr, codes) = sync_bits(config.synchronisation, PINS::WIDTH, PINS::HARDWARE_SYNC)?;
+        cr |= sync_cr;
+
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            DCMI::enable(rcc);
+            DCMI::reset(rcc);
+
+            if let Some(codes) = codes {
+                dcmi.escr.write(|w| {
+                    w.bits(
+                        u32::from(codes.frame_end) << 24
+                            | u32::from(codes.line_end) << 16
+                            | u32::from(codes.line_start) << 8
+                            | u32::from(codes.frame_start),
+                    )
+                });
+                // All code bits are compared
+                dcmi.esur.write(|w| w.bits(0xffff_ffff));
+            }
+            if let Some(crop) = crop {
+                dcmi.cwstrt
+                    .write(|w| w.bits(u32::from(crop.y) << 16 | u32::from(crop.x)));
+                dcmi.cwsize.write(|w| {
+                    w.bits(u32::from(crop.height - 1) << 16 | u32::from(crop.width - 1))
+                });
+            }
+            dcmi.cr.write(|w| w.bits(cr));
+            dcmi.cr.modify(|r, w| w.bits(r.bits() | CR_ENABLE));
+        }
+
+        Ok(Self { dcmi, pins })
+    }
+}
+
+impl<PINS> Dcmi<PINS> {
+    /// Requests capture from the next frame start
+    ///
+    /// The DMA transfer must already be enabled.
+    pub fn start_capture(&mut self) {
+        self.dcmi
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() | CR_CAPTURE) });
+    }
+
+    /// Stops capture at the end of the current frame in continuous mode
+    pub fn stop_capture(&mut self) {
+        self.dcmi
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !CR_CAPTURE) });
+    }
+
+    /// Returns `true` while capture is requested or a frame is being captured
+    ///
+    /// In snapshot mode this turns `false` after the frame is received.
+    pub fn is_capturing(&self) -> bool {
+        self.dcmi.cr.read().bits() & CR_CAPTURE != 0
+    }
+
+    /// Enables the interrupt for `event`
+    pub fn listen(&mut self, event: Event) {
+        self.dcmi
+            .ier
+            .modify(|r, w| unsafe { w.bits(r.bits() | event as u32) });
+    }
+
+    /// Disables the interrupt for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.dcmi
+            .ier
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(event as u32)) });
+    }
+
+    /// Returns `true` if `event` occurred, whether it's listened to or not
+    pub fn is_pending(&self, event: Event) -> bool {
+        self.dcmi.ris.read().bits() & event as u32 != 0
+    }
+
+    /// Clears the flag of `event`
+    pub fn clear_interrupt(&mut self, event: Event) {
+        unsafe { self.dcmi.icr.write(|w| w.bits(event as u32)) }
+    }
+
+    /// Disables the DCMI and returns the peripheral and the pins
+    pub fn release(self) -> (DCMI, PINS) {
+        unsafe {
+            self.dcmi.cr.write(|w| w.bits(0));
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            DCMI::disable(&(*RCC::ptr()));
+        }
+        (self.dcmi, self.pins)
+    }
+}
+
+unsafe impl<PINS> PeriAddress for Dcmi<PINS> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        &self.dcmi.dr as *const _ as u32
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl<PINS> DMASet<Stream1<DMA2>, 1, PeripheralToMemory> for Dcmi<PINS> {}
+unsafe impl<PINS> DMASet<Stream7<DMA2>, 1, PeripheralToMemory> for Dcmi<PINS> {}
+
+mod pins {
+    use super::{sealed::Sealed, DataWidth, DcmiPins, NoPin};
+
+    /// A pin that can be used for data line `D`
+    pub trait PinData<const D: u8>: Sealed {}
+    /// A pin that can be used for `HSYNC`
+    pub trait PinHsync: Sealed {
+        #[doc(hidden)]
+        const CONNECTED: bool = true;
+    }
+    /// A pin that can be used for `VSYNC`
+    pub trait PinVsync: Sealed {
+        #[doc(hidden)]
+        const CONNECTED: bool = true;
+    }
+    /// A pin that can be used for `PIXCLK`
+    pub trait PinPixelClock: Sealed {}
+
+    impl Sealed for NoPin {}
+    impl PinHsync for NoPin {
+        const CONNECTED: bool = false;
+    }
+    impl PinVsync for NoPin {
+        const CONNECTED: bool = false;
+    }
+
+    /// A set of 8, 10, 12 or 14 data pins, `D0` first
+    pub trait DataPins: Sealed {
+        const WIDTH: DataWidth;
+    }
+
+    macro_rules! data_pins {
+        ($width:ident: $($D:ident => $n:literal),+) => {
+            impl<$($D),+> Sealed for ($($D,)+) where $($D: PinData<$n>),+ {}
+            impl<$($D),+> DataPins for ($($D,)+)
+            where
+                $($D: PinData<$n>),+
+            {
+                const WIDTH: DataWidth = DataWidth::$width;
+            }
+        };
+    }
+
+    data_pins!(Bits8: D0 => 0, D1 => 1, D2 => 2, D3 => 3, D4 => 4, D5 => 5, D6 => 6, D7 => 7);
+    data_pins!(Bits10: D0 => 0, D1 => 1, D2 => 2, D3 => 3, D4 => 4, D5 => 5, D6 => 6, D7 => 7,
+        D8 => 8, D9 => 9);
+    data_pins!(Bits12: D0 => 0, D1 => 1, D2 => 2, D3 => 3, D4 => 4, D5 => 5, D6 => 6, D7 => 7,
+        D8 => 8, D9 => 9, D10 => 10, D11 => 11);
+    data_pins!(Bits14: D0 => 0, D1 => 1, D2 => 2, D3 => 3, D4 => 4, D5 => 5, D6 => 6, D7 => 7,
+        D8 => 8, D9 => 9, D10 => 10, D11 => 11, D12 => 12, D13 => 13);
+
+    /// A set of pins that can be used with [`Dcmi`](super::Dcmi)
+    pub trait Pins: Sealed {
+        /// Data width
+        const WIDTH: DataWidth;
+        /// `true` if both `HSYNC` and `VSYNC` are connected
+        const HARDWARE_SYNC: bool;
+    }
+
+    impl<D, HSYNC, VSYNC, PIXCLK> Sealed for DcmiPins<D, HSYNC, VSYNC, PIXCLK>
+    where
+        D: DataPins,
+        HSYNC: PinHsync,
+        VSYNC: PinVsync,
+        PIXCLK: PinPixelClock,
+    {
+    }
+
+    impl<D, HSYNC, VSYNC, PIXCLK> Pins for DcmiPins<D, HSYNC, VSYNC, PIXCLK>
+    where
+        D: DataPins,
+        HSYNC: PinHsync,
+        VSYNC: PinVsync,
+        PIXCLK: PinPixelClock,
+    {
+        const WIDTH: DataWidth = D::WIDTH;
+        const HARDWARE_SYNC: bool = HSYNC::CONNECTED && VSYNC::CONNECTED;
+    }
+
+    /// Pins available on all models with a DCMI
+    mod common_pins {
+        use super::{PinData, PinHsync, PinPixelClock, PinVsync, Sealed};
+        use crate::gpio::{
+            Pin, AF13, PA10, PA4, PA6, PA9, PB5, PB6, PB7, PB8, PB9, PC10, PC11, PC12, PC6, PC7,
+            PC8, PC9, PD2, PE0, PE1, PE4, PE5, PE6, PF11, PG15,
+        };
+
+        impl<const P: char, const N: u8, MODE> Sealed for Pin<P, N, MODE> {}
+
+        // All DCMI pins use AF13
+
+        impl PinHsync for PA4<AF13> {}
+        impl PinPixelClock for PA6<AF13> {}
+        impl PinVsync for PB7<AF13> {}
+
+        impl PinData<0> for PA9<AF13> {}
+        impl PinData<0> for PC6<AF13> {}
+        impl PinData<1> for PA10<AF13> {}
+        impl PinData<1> for PC7<AF13> {}
+        impl PinData<2> for PC8<AF13> {}
+        impl PinData<2> for PE0<AF13> {}
+        impl PinData<3> for PC9<AF13> {}
+        impl PinData<3> for PE1<AF13> {}
+        impl PinData<4> for PC11<AF13> {}
+        impl PinData<4> for PE4<AF13> {}
+        impl PinData<5> for PB6<AF13> {}
+        impl PinData<6> for PB8<AF13> {}
+        impl PinData<6> for PE5<AF13> {}
+        impl PinData<7> for PB9<AF13> {}
+        impl PinData<7> for PE6<AF13> {}
+        impl PinData<8> for PC10<AF13> {}
+        impl PinData<9> for PC12<AF13> {}
+        impl PinData<10> for PB5<AF13> {}
+        impl PinData<11> for PD2<AF13> {}
+        impl PinData<12> for PF11<AF13> {}
+        impl PinData<13> for PG15<AF13> {}
+    }
+
+    /// Pins on ports H and I
+    #[cfg(feature = "gpioi")]
+    mod port_hi_pins {
+        use super::{PinData, PinHsync, PinVsync};
+        use crate::gpio::{
+            AF13, PH10, PH11, PH12, PH14, PH15, PH8, PH9, PI0, PI1, PI2, PI3, PI4, PI5, PI6, PI7,
+        };
+
+        impl PinHsync for PH8<AF13> {}
+        impl PinVsync for PI5<AF13> {}
+
+        impl PinData<0> for PH9<AF13> {}
+        impl PinData<1> for PH10<AF13> {}
+        impl PinData<2> for PH11<AF13> {}
+        impl PinData<3> for PH12<AF13> {}
+        impl PinData<4> for PH14<AF13> {}
+        impl PinData<5> for PI4<AF13> {}
+        impl PinData<6> for PI6<AF13> {}
+        impl PinData<7> for PI7<AF13> {}
+        impl PinData<8> for PI1<AF13> {}
+        impl PinData<9> for PI2<AF13> {}
+        impl PinData<10> for PI3<AF13> {}
+        impl PinData<11> for PH15<AF13> {}
+        impl PinData<13> for PI0<AF13> {}
+    }
+
+    /// Additional pins of the F427/F429/F437/F439, F446 and F469/F479
+    #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+    mod extra_pins {
+        use super::{PinData, PinVsync};
+        use crate::gpio::{AF13, PD3, PD6, PF10, PG10, PG11, PG6, PG7, PG9};
+
+        impl PinVsync for PG9<AF13> {}
+
+        impl PinData<2> for PG10<AF13> {}
+        impl PinData<3> for PG11<AF13> {}
+        impl PinData<5> for PD3<AF13> {}
+        impl PinData<10> for PD6<AF13> {}
+        impl PinData<11> for PF10<AF13> {}
+        impl PinData<12> for PG6<AF13> {}
+        impl PinData<13> for PG7<AF13> {}
+    }
+
+    #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+    mod extra_port_h_pins {
+        use super::PinData;
+        use crate::gpio::{AF13, PH6, PH7};
+
+        impl PinData<8> for PH6<AF13> {}
+        impl PinData<9> for PH7<AF13> {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODES: EmbeddedCodes = EmbeddedCodes {
+        frame_start: 0xff,
+        line_start: 0x80,
+        line_end: 0x9d,
+        frame_end: 0xb6,
+    };
+
+    #[test]
+    fn embedded_sync_needs_8_bit_data() {
+        let sync = Synchronisation::Embedded(CODES);
+        assert_eq!(
+            sync_bits(sync, DataWidth::Bits8, false),
+            Ok((1 << 4, Some(CODES)))
+        );
+        for width in [DataWidth::Bits10, DataWidth::Bits12, DataWidth::Bits14] {
+            assert_eq!(sync_bits(sync, width, true), Err(Error::EmbeddedSyncWidth));
+        }
+    }
+
+    #[test]
+    fn hardware_sync_polarities() {
+        let sync = |hsync, vsync| Synchronisation::Hardware { hsync, vsync };
+        let low = Polarity::ActiveLow;
+        let high = Polarity::ActiveHigh;
+        assert_eq!(
+            sync_bits(sync(low, low), DataWidth::Bits12, true),
+            Ok((0, None))
+        );
+        assert_eq!(
+            sync_bits(sync(high, low), DataWidth::Bits8, true),
+            Ok((1 << 6, None))
+        );
+        assert_eq!(
+            sync_bits(sync(high, high), DataWidth::Bits8, true),
+            Ok((0b11 << 6, None))
+        );
+        assert_eq!(
+            sync_bits(sync(low, low), DataWidth::Bits8, false),
+            Err(Error::MissingSyncPins)
+        );
+    }
+}
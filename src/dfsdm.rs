@@ -0,0 +1,844 @@
+//! Digital filter for sigma-delta modulators (DFSDM)
+//!
+//! Converts the bitstream of a PDM MEMS microphone, or of any sigma-delta modulator,
+//! to PCM samples on STM32F412, F413 and F423. One channel of DFSDM1 is read by filter 0
+//! in continuous regular conversion mode, or two microphones sharing a data line are read
+//! by filters 0 and 1 with [`Dfsdm::stereo`].
+//!
+//! Channel serial input (`DATINy`) and `CKOUT` pins must be put in the alternate function
+//! mode given in the datasheet before starting conversions. The [`Ckout`] and [`Datin`]
+//! pin functions are implemented by the valid pins through
+//! [`PinA`](crate::gpio::alt::PinA).
+//!
+//! # Example
+//!
+//! ```ignore
+//! let gpiob = dp.GPIOB.split();
+//! let gpioc = dp.GPIOC.split();
+//! let _clock = gpioc.pc2.into_alternate::<6>(); // DFSDM1_CKOUT
+//! let _data = gpiob.pb1.into_alternate::<6>(); // DFSDM1_DATIN1
+//!
+//! // 16 kHz from the APB2 clock
+//! let config = Config::pdm_microphone_at(1, clocks.pclk2(), 16.kHz())?;
+//! let mut dfsdm = Dfsdm::new(dp.DFSDM1, config);
+//! dfsdm.start();
+//! let sample: i32 = block!(dfsdm.read())?;
+//! ```
+//!
+//! # Output scaling
+//!
+//! The data registers hold 24-bit signed values, the filter output is shifted right by
+//! [`Config::data_shift`] to fit. [`Dfsdm::sample`] sign-extends a raw register value and
+//! [`Config::to_i16`] scales a sample to the 16-bit PCM full scale.
+//!
+//! # DMA
+//!
+//! DMA2 stream 0 or 6 reads filter 0 conversions and stream 1 or 4 filter 1 conversions
+//! from [`FilterData`] with 32-bit accesses, after [`Dfsdm::enable_dma`]. Samples are in
+//! the upper 24 bits, see [`Dfsdm::sample`].
+//! [`Dfsdm::dma_sources`] hands each [`FilterData`] out once.
+
+#[cfg(feature = "stm32f412")]
+use crate::pac::{dfsdm as dfsdm1, DFSDM as DFSDM1};
+#[cfg(any(feature = "stm32f413", feature = "stm32f423"))]
+use crate::pac::{dfsdm1, DFSDM1};
+
+use crate::dma::traits::{DMASet, PeriAddress};
+use crate::dma::{PeripheralToMemory, Stream0, Stream1, Stream4, Stream6};
+use crate::pac::{DMA2, RCC};
+use crate::rcc::{Clocks, Enable, Reset};
+use crate::time::Hertz;
+
+/// `RCC_DCKCFGR.CKDFSDM1ASEL`, audio clock of DFSDM1 taken from the APB2 I2S clock
+const DCKCFGR_CKDFSDM1ASEL: u32 = 1 << 15;
+
+/// Bits of the data registers
+const DATA_BITS: u32 = 24;
+
+/// Largest right shift, `DTRBS` is 5 bits
+const MAX_SHIFT: u32 = 31;
+
+/// Number of DFSDM1 channels
+const CHANNELS: u8 = 4;
+
+/// Clock range of PDM microphones, used by [`Config::pdm_microphone_at`]
+const PDM_CLOCK_MIN: u32 = 1_000_000;
+const PDM_CLOCK_MAX: u32 = 3_250_000;
+
+/// `DFSDM1_CKOUT` pin function
+pub struct Ckout;
+impl crate::Sealed for Ckout {}
+
+/// `DFSDM1_DATINy` pin function, the serial data input of channel `N`
+pub struct Datin<const N: u8>;
+impl<const N: u8> crate::Sealed for Datin<N> {}
+
+/// Conversion error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// A conversion was lost because the previous one wasn't read in time
+    Overrun,
+}
+
+/// Invalid configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// The channel doesn't exist
+    Channel,
+    /// The output clock divider is 0
+    ClockDivider,
+    /// The sinc filter oversampling ratio is 0 or over
+    /// [`FilterOrder::max_oversampling`]
+    Oversampling,
+    /// The integrator oversampling ratio isn't in 1..=256
+    Integrator,
+    /// The right shift, given or computed by [`Config::data_shift`], is over 31 bits
+    RightShift,
+    /// The output clock is not running
+    ClockDisabled,
+    /// No divider and oversampling ratio give the sample rate within 0.5 %
+    SampleRate,
+}
+
+/// Serial interface of a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SerialInput {
+    /// SPI, data sampled on clock rising edge
+    SpiRisingEdge = 0b00,
+    /// SPI, data sampled on clock falling edge
+    SpiFallingEdge = 0b01,
+    /// Manchester coded, rising edge is logic 0
+    ManchesterRisingEdge = 0b10,
+    /// Manchester coded, rising edge is logic 1
+    ManchesterFallingEdge = 0b11,
+}
+
+/// Clock of the SPI serial interface, `SPICKSEL`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SpiClock {
+    /// External clock on the `CKINy` pin
+    External = 0b00,
+    /// Internal clock, output on `CKOUT`
+    Ckout = 0b01,
+    /// Internal clock, `CKOUT` / 2, data changing on `CKOUT` falling edge
+    CkoutHalfFalling = 0b10,
+    /// Internal clock, `CKOUT` / 2, data changing on `CKOUT` rising edge
+    CkoutHalfRising = 0b11,
+}
+
+/// Source of the `CKOUT` clock, divided by [`Config::clock_divider`] + 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OutputClock {
+    /// APB2 clock
+    Apb2,
+    /// Audio clock, the I2S clock of the APB1 instances
+    I2sApb1,
+    /// Audio clock, the I2S clock of the APB2 instances
+    I2sApb2,
+}
+
+impl OutputClock {
+    /// Frequency of the clock, `None` when the I2S clock is not enabled
+    pub fn frequency(self, clocks: &Clocks) -> Option<Hertz> {
+        match self {
+            Self::Apb2 => Some(clocks.pclk2()),
+            Self::I2sApb1 => clocks.i2s_apb1_clk(),
+            Self::I2sApb2 => clocks.i2s_apb2_clk(),
+        }
+    }
+}
+
+/// Filter type and order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FilterOrder {
+    FastSinc = 0,
+    Sinc1 = 1,
+    Sinc2 = 2,
+    Sinc3 = 3,
+    Sinc4 = 4,
+    Sinc5 = 5,
+}
+
+impl FilterOrder {
+    /// Largest oversampling ratio, higher orders are limited by the 32-bit filter output
+    pub const fn max_oversampling(self) -> u16 {
+        match self {
+            Self::Sinc4 => 215,
+            Self::Sinc5 => 73,
+            _ => 1024,
+        }
+    }
+}
+
+/// Channel and filter configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Channel number, 0 to 3
+    pub channel: u8,
+    /// Serial interface type
+    pub input: SerialInput,
+    /// Serial interface clock
+    pub clock: SpiClock,
+    /// Source of the `CKOUT` clock
+    pub output_clock: OutputClock,
+    /// `CKOUT` is the output clock divided by `clock_divider + 1`, 1 to 255
+    pub clock_divider: u8,
+    /// Sinc filter order
+    pub order: FilterOrder,
+    /// Sinc filter oversampling ratio, 1 to [`FilterOrder::max_oversampling`]
+    pub oversampling: u16,
+    /// Integrator oversampling ratio, 1 to 256
+    pub integrator: u16,
+    /// Right shift of the filter output, computed by [`data_shift`](Self::data_shift)
+    /// when `None`
+    pub right_shift: Option<u8>,
+}
+
+impl Config {
+    /// SPI input clocked by `CKOUT` from APB2, Sinc3 filter with 64 times oversampling
+    pub fn pdm_microphone(channel: u8, clock_divider: u8) -> Self {
+        Self {
+            channel,
+            input: SerialInput::SpiRisingEdge,
+            clock: SpiClock::Ckout,
+            output_clock: OutputClock::Apb2,
+            clock_divider,
+            order: FilterOrder::Sinc3,
+            oversampling: 64,
+            integrator: 1,
+            right_shift: None,
+        }
+    }
+
+    /// [`pdm_microphone`](Self::pdm_microphone) configuration converting at `sample_rate`
+    /// from an output clock of `clock`
+    ///
+    /// Picks the fastest microphone clock between 1 and 3.25 MHz giving the closest
+    /// sample rate, the achieved rate is returned by [`sample_rate`](Self::sample_rate).
+    /// Fails when it is more than 0.5 % away from `sample_rate`.
+    pub fn pdm_microphone_at(
+        channel: u8,
+        clock: Hertz,
+        sample_rate: Hertz,
+    ) -> Result<Self, ConfigError> {
+        let (clock, rate) = (clock.raw(), sample_rate.raw());
+        if rate == 0 {
+            return Err(ConfigError::SampleRate);
+        }
+
+        let mut best: Option<(u32, u32, u32)> = None;
+        for divider in 2..=256 {
+            let ckout = clock / divider;
+            if !(PDM_CLOCK_MIN..=PDM_CLOCK_MAX).contains(&ckout) {
+                continue;
+            }
+            let oversampling = (clock / divider + rate / 2) / rate;
+            let max = u32::from(FilterOrder::Sinc3.max_oversampling());
+            if !(1..=max).contains(&oversampling) {
+                continue;
+            }
+            let error = (clock / (divider * oversampling)).abs_diff(rate);
+            if best.map_or(true, |(best_error, _, _)| error < best_error) {
+                best = Some((error, divider, oversampling));
+            }
+        }
+
+        match best {
+            Some((error, divider, oversampling)) if error <= rate / 200 => {
+                let config = Self::pdm_microphone(channel, (divider - 1) as u8)
+                    .oversampling(oversampling as u16);
+                config.validate()?;
+                Ok(config)
+            }
+            _ => Err(ConfigError::SampleRate),
+        }
+    }
+
+    /// [`pdm_microphone_at`](Self::pdm_microphone_at) with `CKOUT` taken from
+    /// `output_clock`, such as the audio clock
+    pub fn pdm_microphone_from(
+        channel: u8,
+        output_clock: OutputClock,
+        clocks: &Clocks,
+        sample_rate: Hertz,
+    ) -> Result<Self, ConfigError> {
+        let clock = output_clock
+            .frequency(clocks)
+            .ok_or(ConfigError::ClockDisabled)?;
+        Ok(Self::pdm_microphone_at(channel, clock, sample_rate)?.output_clock(output_clock))
+    }
+
+    pub fn input(mut self, input: SerialInput) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn clock(mut self, clock: SpiClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn output_clock(mut self, output_clock: OutputClock) -> Self {
+        self.output_clock = output_clock;
+        self
+    }
+
+    pub fn order(mut self, order: FilterOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn oversampling(mut self, oversampling: u16) -> Self {
+        self.oversampling = oversampling;
+        self
+    }
+
+    pub fn integrator(mut self, integrator: u16) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    /// Overrides the right shift computed by [`data_shift`](Self::data_shift)
+    ///
+    /// A smaller shift adds gain, a filter output over 24 bits then saturates.
+    pub fn right_shift(mut self, shift: u8) -> Self {
+        self.right_shift = Some(shift);
+        self
+    }
+
+    /// Checks that the values are in range
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.channel >= CHANNELS {
+            Err(ConfigError::Channel)
+        } else if self.clock_divider == 0 {
+            Err(ConfigError::ClockDivider)
+        } else if !(1..=self.order.max_oversampling()).contains(&self.oversampling) {
+            Err(ConfigError::Oversampling)
+        } else if !(1..=256).contains(&self.integrator) {
+            Err(ConfigError::Integrator)
+        } else if self.unclamped_shift() > MAX_SHIFT {
+            Err(ConfigError::RightShift)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// `FLTxFCR.FOSR` value, the oversampling ratio minus one
+    pub const fn fosr(&self) -> u16 {
+        self.oversampling.saturating_sub(1)
+    }
+
+    /// `FLTxFCR.IOSR` value, the integrator oversampling ratio minus one
+    pub const fn iosr(&self) -> u8 {
+        self.integrator.saturating_sub(1) as u8
+    }
+
+    /// `FLTxFCR` value, `FORD` at bit 29, `FOSR` at bit 16 and `IOSR` at bit 0
+    pub const fn fcr(&self) -> u32 {
+        (self.order as u32) << 29 | (self.fosr() as u32) << 16 | self.iosr() as u32
+    }
+
+    /// Bits of the filter output, sign included
+    ///
+    /// The Sinc filter output range is ±`oversampling^order` (±`2 * oversampling^2` for
+    /// FastSinc), multiplied by `integrator`.
+    pub const fn output_bits(&self) -> u32 {
+        let fosr = self.oversampling as u64;
+        let max = match self.order {
+            FilterOrder::FastSinc => 2 * fosr * fosr,
+            order => {
+                let mut max = 1;
+                let mut i = 0;
+                while i < order as u32 {
+                    max *= fosr;
+                    i += 1;
+                }
+                max
+            }
+        } * self.integrator as u64;
+        // log2(max) rounded up, plus sign
+        u64::BITS - max.saturating_sub(1).leading_zeros() + 1
+    }
+
+    /// Right shift (`DTRBS`) keeping the filter output in the 24-bit data register
+    ///
+    /// At most 31, the output of a larger oversampling than
+    /// [`FilterOrder::max_oversampling`] doesn't fit.
+    pub const fn data_shift(&self) -> u32 {
+        let shift = self.unclamped_shift();
+        if shift > MAX_SHIFT {
+            MAX_SHIFT
+        } else {
+            shift
+        }
+    }
+
+    const fn unclamped_shift(&self) -> u32 {
+        match self.right_shift {
+            Some(shift) => shift as u32,
+            None => self.output_bits().saturating_sub(DATA_BITS),
+        }
+    }
+
+    /// Bits of the samples after the right shift, sign included, at most 24
+    pub const fn sample_bits(&self) -> u32 {
+        let bits = self.output_bits().saturating_sub(self.data_shift());
+        if bits > DATA_BITS {
+            DATA_BITS
+        } else {
+            bits
+        }
+    }
+
+    /// Scales a sample returned by [`Dfsdm::read`] or [`Dfsdm::sample`] to 16-bit PCM
+    pub const fn to_i16(&self, sample: i32) -> i16 {
+        let bits = self.sample_bits();
+        if bits > 16 {
+            (sample >> (bits - 16)) as i16
+        } else {
+            (sample << (16 - bits)) as i16
+        }
+    }
+
+    /// `CKOUT` frequency from an output clock of `clock`
+    pub const fn microphone_clock(&self, clock: Hertz) -> Hertz {
+        Hertz::from_raw(clock.raw() / (self.clock_divider as u32 + 1))
+    }
+
+    /// Rate of the conversions from an output clock of `clock`, with the serial interface
+    /// clocked by `CKOUT`
+    pub const fn sample_rate(&self, clock: Hertz) -> Hertz {
+        let ckin = match self.clock {
+            SpiClock::CkoutHalfFalling | SpiClock::CkoutHalfRising => {
+                self.microphone_clock(clock).raw() / 2
+            }
+            _ => self.microphone_clock(clock).raw(),
+        };
+        Hertz::from_raw(ckin / (self.oversampling as u32 * self.integrator as u32))
+    }
+}
+
+/// DFSDM1 with one channel converted by filter 0, or two by filters 0 and 1
+pub struct Dfsdm {
+    dfsdm: DFSDM1,
+    config: Config,
+    stereo: bool,
+    // DMA sources of filter 0 and 1 already handed out
+    dma_taken: [bool; 2],
+}
+
+impl Dfsdm {
+    /// Enables and resets DFSDM1, then configures the channel and filter 0
+    ///
+    /// # Panics
+    ///
+    /// When the configuration is invalid, see [`Config::validate`].
+    pub fn new(dfsdm: DFSDM1, config: Config) -> Self {
+        Self::init(dfsdm, config, false)
+    }
+
+    /// Enables and resets DFSDM1, then configures two microphones sharing the clock and
+    /// the data pin of `config.channel`
+    ///
+    /// The microphone sampled on the `config.input` edge is read by filter 0 on channel
+    /// `config.channel`, the other one by filter 1 on channel `config.channel - 1` with the
+    /// opposite edge. Both filters convert synchronously.
+    ///
+    /// # Panics
+    ///
+    /// When the configuration is invalid, see [`Config::validate`], when `config.channel`
+    /// is 0 or `config.input` is not an SPI input.
+    pub fn stereo(dfsdm: DFSDM1, config: Config) -> Self {
+        assert!(config.channel > 0);
+        Self::init(dfsdm, config, true)
+    }
+
+    fn init(dfsdm: DFSDM1, config: Config, stereo: bool) -> Self {
+        config.validate().unwrap();
+
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            DFSDM1::enable(rcc);
+            DFSDM1::reset(rcc);
+
+            match config.output_clock {
+                OutputClock::I2sApb1 => cortex_m::interrupt::free(|_| {
+                    rcc.dckcfgr
+                        .modify(|r, w| w.bits(r.bits() & !DCKCFGR_CKDFSDM1ASEL))
+                }),
+                OutputClock::I2sApb2 => cortex_m::interrupt::free(|_| {
+                    rcc.dckcfgr
+                        .modify(|r, w| w.bits(r.bits() | DCKCFGR_CKDFSDM1ASEL))
+                }),
+                OutputClock::Apb2 => {}
+            }
+        }
+
+        let this = Self {
+            dfsdm,
+            config,
+            stereo,
+            dma_taken: [false; 2],
+        };
+        // The output clock is configured in channel 0 registers
+        this.dfsdm.ch0.cfgr1.modify(|_, w| unsafe {
+            w.ckoutsrc()
+                .bit(config.output_clock != OutputClock::Apb2)
+                .ckoutdiv()
+                .bits(config.clock_divider)
+        });
+        this.configure_channel(config.channel, config.input, false);
+        this.configure_filter(0, config.channel, false);
+        if stereo {
+            let input = match config.input {
+                SerialInput::SpiRisingEdge => SerialInput::SpiFallingEdge,
+                SerialInput::SpiFallingEdge => SerialInput::SpiRisingEdge,
+                _ => panic!("stereo needs an SPI input"),
+            };
+            this.configure_channel(config.channel - 1, input, true);
+            this.configure_filter(1, config.channel - 1, true);
+        }
+        this
+    }
+
+    /// Sets up `channel`, reading the pins of the next channel with `next_pins`
+    fn configure_channel(&self, channel: u8, input: SerialInput, next_pins: bool) {
+        let ch = channel_regs(&self.dfsdm, channel);
+        ch.cfgr2
+            .write(|w| unsafe { w.dtrbs().bits(self.config.data_shift() as u8) });
+        ch.cfgr1.modify(|_, w| unsafe {
+            w.spicksel()
+                .bits(self.config.clock as u8)
+                .sitp()
+                .bits(input as u8)
+                .chinsel()
+                .bit(next_pins)
+                .chen()
+                .set_bit()
+        });
+    }
+
+    /// Sets up `filter` to continuously convert `channel`, synchronously with filter 0
+    /// when `sync`
+    fn configure_filter(&self, filter: u8, channel: u8, sync: bool) {
+        let flt = filter_regs(&self.dfsdm, filter);
+        let config = &self.config;
+        flt.fcr.write(|w| unsafe {
+            w.ford()
+                .bits(config.order as u8)
+                .fosr()
+                .bits(config.fosr())
+                .iosr()
+                .bits(config.iosr())
+        });
+        flt.cr1.write(|w| unsafe {
+            w.fast()
+                .set_bit()
+                .rcont()
+                .set_bit()
+                .rch()
+                .bits(channel)
+                .rsync()
+                .bit(sync)
+        });
+    }
+
+    /// Configuration in use
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Is filter 1 converting the second microphone?
+    pub fn is_stereo(&self) -> bool {
+        self.stereo
+    }
+
+    /// Starts the output clock and continuous conversions
+    pub fn start(&mut self) {
+        self.dfsdm.ch0.cfgr1.modify(|_, w| w.dfsdmen().set_bit());
+        self.start_filters();
+    }
+
+    /// Enables the filters, filter 1 waiting for the start of filter 0
+    fn start_filters(&self) {
+        if self.stereo {
+            self.dfsdm.flt1.cr1.modify(|_, w| w.dfen().set_bit());
+        }
+        self.dfsdm.flt0.cr1.modify(|_, w| w.dfen().set_bit());
+        self.dfsdm.flt0.cr1.modify(|_, w| w.rswstart().set_bit());
+    }
+
+    /// Stops conversions and the output clock
+    pub fn stop(&mut self) {
+        self.dfsdm.flt0.cr1.modify(|_, w| w.dfen().clear_bit());
+        self.dfsdm.flt1.cr1.modify(|_, w| w.dfen().clear_bit());
+        self.dfsdm.ch0.cfgr1.modify(|_, w| w.dfsdmen().clear_bit());
+    }
+
+    /// Reads a conversion of filter 0, a 24-bit signed value
+    pub fn read(&mut self) -> nb::Result<i32, Error> {
+        self.check(0)?;
+        Ok(Self::sample(self.dfsdm.flt0.rdatar.read().bits()))
+    }
+
+    /// Reads a conversion of both filters
+    ///
+    /// # Panics
+    ///
+    /// When not created with [`stereo`](Self::stereo).
+    pub fn read_stereo(&mut self) -> nb::Result<(i32, i32), Error> {
+        assert!(self.stereo);
+        self.check(0)?;
+        self.check(1)?;
+        Ok((
+            Self::sample(self.dfsdm.flt0.rdatar.read().bits()),
+            Self::sample(self.dfsdm.flt1.rdatar.read().bits()),
+        ))
+    }
+
+    /// Checks that a conversion of `filter` is ready, clearing an overrun
+    fn check(&self, filter: u8) -> nb::Result<(), Error> {
+        let flt = filter_regs(&self.dfsdm, filter);
+        let isr = flt.isr.read();
+        if isr.rovrf().bit_is_set() {
+            flt.icr.write(|w| w.clrrovrf().set_bit());
+            Err(nb::Error::Other(Error::Overrun))
+        } else if isr.reocf().bit_is_set() {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Extracts the 24-bit signed sample of a data register value transferred by DMA
+    pub const fn sample(rdatar: u32) -> i32 {
+        rdatar as i32 >> (32 - DATA_BITS)
+    }
+
+    /// Requests a DMA transfer for each conversion, of both filters in stereo mode
+    ///
+    /// Running conversions are restarted.
+    pub fn enable_dma(&mut self) {
+        self.set_dma(true);
+    }
+
+    /// Stops DMA requests
+    ///
+    /// Running conversions are restarted.
+    pub fn disable_dma(&mut self) {
+        self.set_dma(false);
+    }
+
+    /// Sets `RDMAEN`, only writable while the filters are disabled
+    fn set_dma(&mut self, enable: bool) {
+        let running = self.dfsdm.flt0.cr1.read().dfen().bit_is_set();
+        let filters = if self.stereo { 2 } else { 1 };
+        for filter in 0..filters {
+            let cr1 = &filter_regs(&self.dfsdm, filter).cr1;
+            cr1.modify(|_, w| w.dfen().clear_bit());
+            cr1.modify(|_, w| w.rdmaen().bit(enable));
+        }
+        if running {
+            self.start_filters();
+        }
+    }
+
+    /// Returns the DMA source of filter 0 conversions, `None` when it was already taken
+    pub fn dma_source(&mut self) -> Option<FilterData> {
+        if self.dma_taken[0] {
+            return None;
+        }
+        self.dma_taken[0] = true;
+        Some(FilterData { _private: () })
+    }
+
+    /// Returns the DMA sources of filter 0 and filter 1 conversions, `None` when one of
+    /// them was already taken
+    pub fn dma_sources(&mut self) -> Option<(FilterData<0>, FilterData<1>)> {
+        if self.dma_taken != [false; 2] {
+            return None;
+        }
+        self.dma_taken = [true; 2];
+        Some((FilterData { _private: () }, FilterData { _private: () }))
+    }
+
+    /// Stops conversions, disables the clock and returns the DFSDM1 peripheral
+    pub fn release(mut self) -> DFSDM1 {
+        self.stop();
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            DFSDM1::disable(&(*RCC::ptr()));
+        }
+        self.dfsdm
+    }
+}
+
+/// Registers of `channel`
+fn channel_regs(dfsdm: &dfsdm1::RegisterBlock, channel: u8) -> &dfsdm1::CH {
+    match channel {
+        0 => &dfsdm.ch0,
+        1 => &dfsdm.ch1,
+        2 => &dfsdm.ch2,
+        3 => &dfsdm.ch3,
+        _ => unreachable!(),
+    }
+}
+
+/// Registers of `filter`
+fn filter_regs(dfsdm: &dfsdm1::RegisterBlock, filter: u8) -> &dfsdm1::FLT {
+    match filter {
+        0 => &dfsdm.flt0,
+        1 => &dfsdm.flt1,
+        _ => unreachable!(),
+    }
+}
+
+/// DMA source of filter `F` regular conversions
+pub struct FilterData<const F: u8 = 0> {
+    _private: (),
+}
+
+unsafe impl<const F: u8> PeriAddress for FilterData<F> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        // NOTE(unsafe) only the address of the register is taken
+        let dfsdm = unsafe { &*DFSDM1::ptr() };
+        &filter_regs(dfsdm, F).rdatar as *const _ as u32
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl DMASet<Stream0<DMA2>, 7, PeripheralToMemory> for FilterData<0> {}
+unsafe impl DMASet<Stream6<DMA2>, 3, PeripheralToMemory> for FilterData<0> {}
+unsafe impl DMASet<Stream1<DMA2>, 3, PeripheralToMemory> for FilterData<1> {}
+unsafe impl DMASet<Stream4<DMA2>, 3, PeripheralToMemory> for FilterData<1> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fcr_encodes_order_and_ratios_minus_one() {
+        let config = Config::pdm_microphone(1, 3)
+            .order(FilterOrder::Sinc4)
+            .oversampling(128)
+            .integrator(4);
+        assert_eq!(config.fcr(), 4 << 29 | 127 << 16 | 3);
+
+        let config = config
+            .order(FilterOrder::FastSinc)
+            .oversampling(1024)
+            .integrator(256);
+        assert_eq!(config.fcr(), 1023 << 16 | 255);
+    }
+
+    #[test]
+    fn fcr_does_not_underflow_on_zero_ratios() {
+        let config = Config::pdm_microphone(1, 3).oversampling(0).integrator(0);
+        assert_eq!((config.fosr(), config.iosr()), (0, 0));
+        assert_eq!(config.fcr(), 3 << 29);
+    }
+
+    #[test]
+    fn shift_fits_output_in_24_bits() {
+        // Sinc3, FOSR 64: ±2^18, 19 bits
+        let config = Config::pdm_microphone(1, 3);
+        assert_eq!(config.output_bits(), 19);
+        assert_eq!(config.data_shift(), 0);
+        assert_eq!(config.sample_bits(), 19);
+
+        // Sinc5, FOSR 64: ±2^30, 31 bits
+        let config = config.order(FilterOrder::Sinc5);
+        assert_eq!(config.output_bits(), 31);
+        assert_eq!(config.data_shift(), 7);
+        assert_eq!(config.sample_bits(), 24);
+
+        // FastSinc, FOSR 1024, IOSR 256: ±2^29, 30 bits
+        let config = config
+            .order(FilterOrder::FastSinc)
+            .oversampling(1024)
+            .integrator(256);
+        assert_eq!(config.data_shift(), 6);
+    }
+
+    #[test]
+    fn shift_is_clamped_to_dtrbs() {
+        let config = Config::pdm_microphone(1, 3)
+            .order(FilterOrder::Sinc5)
+            .oversampling(1024)
+            .integrator(256);
+        assert_eq!(config.data_shift(), MAX_SHIFT);
+        assert_eq!(config.right_shift(40).data_shift(), MAX_SHIFT);
+    }
+
+    #[test]
+    fn max_oversampling_keeps_output_in_32_bits() {
+        for order in [
+            FilterOrder::FastSinc,
+            FilterOrder::Sinc1,
+            FilterOrder::Sinc2,
+            FilterOrder::Sinc3,
+            FilterOrder::Sinc4,
+            FilterOrder::Sinc5,
+        ] {
+            let config = Config::pdm_microphone(1, 3)
+                .order(order)
+                .oversampling(order.max_oversampling());
+            assert!(config.output_bits() <= 32, "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn validate_rejects_oversampling_over_the_order_limit() {
+        let config = Config::pdm_microphone(1, 3);
+        for (order, max) in [(FilterOrder::Sinc4, 215), (FilterOrder::Sinc5, 73)] {
+            assert_eq!(config.order(order).oversampling(max).validate(), Ok(()));
+            assert_eq!(
+                config.order(order).oversampling(max + 1).validate(),
+                Err(ConfigError::Oversampling)
+            );
+        }
+        assert_eq!(
+            config.oversampling(0).validate(),
+            Err(ConfigError::Oversampling)
+        );
+    }
+
+    #[test]
+    fn validate_rejects_shift_over_31() {
+        let config = Config::pdm_microphone(1, 3);
+        assert_eq!(config.right_shift(31).validate(), Ok(()));
+        assert_eq!(
+            config.right_shift(32).validate(),
+            Err(ConfigError::RightShift)
+        );
+    }
+
+    #[test]
+    fn sample_rate_is_solved_within_half_a_percent() {
+        let clock = Hertz::from_raw(96_000_000);
+        let config = Config::pdm_microphone_at(1, clock, Hertz::from_raw(16_000)).unwrap();
+        assert_eq!(config.microphone_clock(clock).raw(), 3_200_000);
+        assert_eq!(config.sample_rate(clock).raw(), 16_000);
+        assert_eq!(
+            Config::pdm_microphone_at(1, clock, Hertz::from_raw(0)),
+            Err(ConfigError::SampleRate)
+        );
+    }
+
+    #[test]
+    fn sample_sign_extends_the_upper_24_bits() {
+        assert_eq!(Dfsdm::sample(0x7fff_ff00), 0x7f_ffff);
+        assert_eq!(Dfsdm::sample(0xffff_ff00), -1);
+        assert_eq!(Dfsdm::sample(0x8000_0000), -0x80_0000);
+    }
+}
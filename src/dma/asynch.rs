@@ -0,0 +1,167 @@
+//! Async one-shot DMA transfers, woken by the stream interrupts
+//!
+//! The `DMAx_STREAMy` interrupts of the streams used by async drivers have to be bound
+//! to [`on_interrupt`] and unmasked in the NVIC:
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn DMA2_STREAM0() {
+//!     dma::asynch::on_interrupt::<Stream0<DMA2>>();
+//! }
+//! ```
+
+use core::future::poll_fn;
+use core::task::{Context, Poll};
+
+use atomic_waker::AtomicWaker;
+
+use super::traits::{Channel, DMASet, Direction, PeriAddress, Stream};
+use super::{ChannelX, StreamX};
+use crate::pac::{DMA1, DMA2};
+
+/// DMA stream with an interrupt waker
+pub trait AsyncStream: Stream {
+    #[doc(hidden)]
+    fn waker() -> &'static AtomicWaker;
+    #[doc(hidden)]
+    fn mask_interrupts();
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NEW_WAKER: AtomicWaker = AtomicWaker::new();
+static DMA1_WAKERS: [AtomicWaker; 8] = [NEW_WAKER; 8];
+static DMA2_WAKERS: [AtomicWaker; 8] = [NEW_WAKER; 8];
+
+macro_rules! async_stream {
+    ($($DMA:ty: $wakers:ident,)+) => {
+        $(
+            impl<const S: u8> AsyncStream for StreamX<$DMA, S>
+            where
+                Self: Stream,
+            {
+                fn waker() -> &'static AtomicWaker {
+                    &$wakers[S as usize]
+                }
+
+                fn mask_interrupts() {
+                    unsafe { Self::st() }.cr.modify(|_, w| {
+                        w.tcie()
+                            .clear_bit()
+                            .htie()
+                            .clear_bit()
+                            .teie()
+                            .clear_bit()
+                            .dmeie()
+                            .clear_bit()
+                    });
+                }
+            }
+        )+
+    };
+}
+
+async_stream! {
+    DMA1: DMA1_WAKERS,
+    DMA2: DMA2_WAKERS,
+}
+
+/// Handler for the `DMAx_STREAMy` interrupt of `STREAM`
+pub fn on_interrupt<STREAM: AsyncStream>() {
+    // Mask the interrupts, the flags are handled by the waiting transfer
+    STREAM::mask_interrupts();
+    STREAM::waker().wake();
+}
+
+/// The stream stopped on a bus error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TransferError;
+
+/// One-shot byte transfer between a peripheral and memory, aborted when dropped
+pub(crate) struct OneShot<'a, STREAM: AsyncStream> {
+    stream: &'a mut STREAM,
+}
+
+impl<'a, STREAM: AsyncStream> OneShot<'a, STREAM> {
+    /// Starts transferring `len` bytes between `peripheral` and `memory`
+    ///
+    /// # Safety
+    ///
+    /// `memory` must stay valid for `len` bytes as long as the stream runs, even if the
+    /// returned value is leaked.
+    pub(crate) unsafe fn start<PERIPHERAL, DIR, const CHANNEL: u8>(
+        stream: &'a mut STREAM,
+        peripheral: &PERIPHERAL,
+        memory: *const u8,
+        len: u16,
+        memory_increment: bool,
+    ) -> Self
+    where
+        PERIPHERAL: DMASet<STREAM, CHANNEL, DIR> + PeriAddress<MemSize = u8>,
+        DIR: Direction,
+        ChannelX<CHANNEL>: Channel,
+    {
+        stream.disable();
+        stream.clear_interrupts();
+        stream.set_channel::<CHANNEL>();
+        stream.set_direction(DIR::new());
+        stream.set_peripheral_address(peripheral.address());
+        stream.set_memory_address(memory as u32);
+        stream.set_number_of_transfers(len);
+        stream.set_memory_size(0);
+        stream.set_peripheral_size(0);
+        stream.set_memory_increment(memory_increment);
+        stream.set_peripheral_increment(false);
+        stream.set_double_buffer(false);
+        stream.set_fifo_enable(false);
+        stream.set_interrupts_enable(false, false, false, false);
+        stream.enable();
+
+        Self { stream }
+    }
+
+    /// Waits until all bytes were transferred
+    pub(crate) async fn wait(&mut self) -> Result<(), TransferError> {
+        poll_fn(|cx| self.poll_complete(cx)).await
+    }
+
+    /// Checks whether all bytes were transferred, otherwise wakes `cx` on completion or error
+    pub(crate) fn poll_complete(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), TransferError>> {
+        STREAM::waker().register(cx.waker());
+
+        if STREAM::get_transfer_error_flag() {
+            self.stream.clear_interrupts();
+            Poll::Ready(Err(TransferError))
+        } else if !STREAM::is_enabled() {
+            // The stream disables itself once NDTR reaches zero
+            self.stream.clear_interrupts();
+            Poll::Ready(Ok(()))
+        } else {
+            self.stream.set_interrupts_enable(true, false, true, false);
+            Poll::Pending
+        }
+    }
+
+    /// Number of bytes not transferred yet
+    pub(crate) fn remaining(&self) -> u16 {
+        STREAM::get_number_of_transfers()
+    }
+
+    /// Stops the stream, returns the number of bytes not transferred
+    pub(crate) fn stop(&mut self) -> u16 {
+        self.stream.disable();
+        STREAM::mask_interrupts();
+        self.stream.clear_interrupts();
+        self.remaining()
+    }
+}
+
+impl<STREAM: AsyncStream> Drop for OneShot<'_, STREAM> {
+    fn drop(&mut self) {
+        // Abort an unfinished transfer, a finished stream is already disabled
+        self.stop();
+    }
+}
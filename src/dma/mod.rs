@@ -6,12 +6,16 @@
 //! This module implements Memory To Memory, Peripheral To Memory and Memory to Peripheral
 //! transfers, double buffering is supported only for Peripheral To Memory and Memory to Peripheral
 //! transfers.
+//!
+//! A single buffer can also be used in circular mode, see
+//! [`DmaConfig::circular`](config::DmaConfig::circular): [`Transfer::next_half`] returns
+//! the half the CPU can work on while the DMA fills or sends the other one.
 
 use core::{
     fmt::{self, Debug, Formatter},
     marker::PhantomData,
     mem,
-    ops::Not,
+    ops::{Not, Range},
     ptr,
     sync::atomic::{compiler_fence, Ordering},
 };
@@ -20,6 +24,9 @@ use embedded_dma::{ReadBuffer, WriteBuffer};
 use crate::pac::RCC;
 use crate::{pac, rcc};
 
+#[cfg(feature = "async")]
+#[clippy::msrv = "1.75"]
+pub mod asynch;
 pub mod traits;
 use traits::{
     sealed::{Bits, Sealed},
@@ -28,6 +35,7 @@ use traits::{
 
 /// Errors.
 #[derive(PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum DMAError<T> {
     /// DMA not ready to change buffers.
     NotReady(T),
@@ -52,6 +60,7 @@ impl<T> Debug for DMAError<T> {
 
 /// Possible DMA's directions.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum DmaDirection {
     /// Memory to Memory transfer.
     MemoryToMemory,
@@ -148,6 +157,7 @@ unsafe impl PeriAddress for MemoryToMemory<u32> {
 
 /// How full the DMA stream's fifo is.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum FifoLevel {
     /// 0 < fifo_level < 1/4.
     GtZeroLtQuarter,
@@ -181,6 +191,7 @@ impl From<u8> for FifoLevel {
 
 /// Which DMA buffer is in use.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum CurrentBuffer {
     /// The first buffer (m0ar) is in use.
     FirstBuffer,
@@ -200,6 +211,48 @@ impl Not for CurrentBuffer {
     }
 }
 
+/// Half of a circular buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Half {
+    /// Items `0..len / 2`, complete at the half transfer event.
+    First,
+    /// Items `len / 2..len`, complete at the transfer complete event.
+    Second,
+}
+
+impl Half {
+    /// Indexes of the half in a buffer of `len` items.
+    pub fn range(self, len: usize) -> Range<usize> {
+        match self {
+            Half::First => 0..len / 2,
+            Half::Second => len / 2..len,
+        }
+    }
+}
+
+/// Half completed according to the `HTIF` and `TCIF` flags of a circular transfer
+fn completed_half(htif: bool, tcif: bool) -> Result<Half, DMAError<()>> {
+    match (htif, tcif) {
+        (false, false) => Err(DMAError::NotReady(())),
+        (true, false) => Ok(Half::First),
+        (false, true) => Ok(Half::Second),
+        // One of the halves was not handled in time
+        (true, true) => Err(DMAError::Overrun(())),
+    }
+}
+
+impl Not for Half {
+    type Output = Half;
+
+    fn not(self) -> Self::Output {
+        match self {
+            Half::First => Half::Second,
+            Half::Second => Half::First,
+        }
+    }
+}
+
 /// Stream on the DMA controller.
 pub struct StreamX<DMA, const S: u8> {
     _dma: PhantomData<DMA>,
@@ -211,6 +264,20 @@ impl<DMA, const S: u8> StreamX<DMA, S> {
     }
 }
 
+impl<DMA: Instance + rcc::LPEnable, const S: u8> StreamX<DMA, S> {
+    /// Keeps the clock of the DMA controller running in Sleep mode, for all its streams,
+    /// see [`Rcc::gate_sleep_clocks`](crate::rcc::Rcc::gate_sleep_clocks)
+    pub fn enable_in_sleep(&mut self, enable: bool) {
+        // NOTE(unsafe) only the bit of this controller is written, with bit-banding
+        let rcc = unsafe { &*RCC::ptr() };
+        if enable {
+            DMA::low_power_enable(rcc);
+        } else {
+            DMA::low_power_disable(rcc);
+        }
+    }
+}
+
 impl<DMA: Instance, const S: u8> StreamX<DMA, S> {
     #[cfg(not(any(
         feature = "stm32f411",
@@ -485,6 +552,13 @@ where
             .modify(|_, w| w.dbm().bit(double_buffer));
     }
 
+    #[inline(always)]
+    fn set_circular(&mut self, circular: bool) {
+        unsafe { Self::st() }
+            .cr
+            .modify(|_, w| w.circ().bit(circular));
+    }
+
     #[inline(always)]
     fn set_fifo_threshold(&mut self, fifo_threshold: config::FifoThreshold) {
         unsafe { Self::st() }
@@ -677,6 +751,7 @@ pub mod config {
     /// priority level, the stream with the lower number takes priority over the stream with the
     /// higher number. For example, Stream 2 takes priority over Stream 4.
     #[derive(Debug, Clone, Copy)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub enum Priority {
         /// Low priority.
         Low,
@@ -701,6 +776,7 @@ pub mod config {
 
     /// The level to fill the fifo to before performing the transaction.
     #[derive(Debug, Clone, Copy)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub enum FifoThreshold {
         /// 1/4 full.
         QuarterFull,
@@ -723,8 +799,21 @@ pub mod config {
         }
     }
 
-    /// How burst transfers are done, requires fifo enabled. Check datasheet for valid combinations.
-    #[derive(Debug, Clone, Copy)]
+    impl FifoThreshold {
+        /// Fill level in bytes of the 16 byte fifo
+        pub(crate) fn bytes(self) -> u32 {
+            (u32::from(self.bits()) + 1) * 4
+        }
+    }
+
+    /// How burst transfers are done, requires fifo enabled.
+    ///
+    /// A memory burst of `beats × word size` bytes must evenly divide the fifo threshold, e.g.
+    /// `Burst4` of `u16` works with `HalfFull` and `Full`. A peripheral burst must fit in the
+    /// 16 byte fifo. Bursts must not cross a 1 KB boundary, so incremented addresses have to
+    /// be aligned to the burst size.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub enum BurstMode {
         /// Single transfer, no burst.
         NoBurst,
@@ -747,8 +836,21 @@ pub mod config {
         }
     }
 
+    impl BurstMode {
+        /// Number of beats of a burst
+        pub(crate) fn beats(self) -> u32 {
+            match self {
+                BurstMode::NoBurst => 1,
+                BurstMode::Burst4 => 4,
+                BurstMode::Burst8 => 8,
+                BurstMode::Burst16 => 16,
+            }
+        }
+    }
+
     /// Contains the complete set of configuration for a DMA stream.
     #[derive(Debug, Clone, Copy)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub struct DmaConfig {
         pub(crate) priority: Priority,
         pub(crate) memory_increment: bool,
@@ -759,6 +861,7 @@ pub mod config {
         pub(crate) direct_mode_error_interrupt: bool,
         pub(crate) fifo_error_interrupt: bool,
         pub(crate) double_buffer: bool,
+        pub(crate) circular: bool,
         pub(crate) fifo_threshold: FifoThreshold,
         pub(crate) fifo_enable: bool,
         pub(crate) memory_burst: BurstMode,
@@ -777,6 +880,7 @@ pub mod config {
                 direct_mode_error_interrupt: false,
                 fifo_error_interrupt: false,
                 double_buffer: false,
+                circular: false,
                 fifo_threshold: FifoThreshold::QuarterFull,
                 fifo_enable: false,
                 memory_burst: BurstMode::NoBurst,
@@ -841,6 +945,13 @@ pub mod config {
             self.double_buffer = double_buffer;
             self
         }
+        /// Set the circular mode, the buffer is filled again from its start after each
+        /// transfer, see [`Transfer::next_half`](super::Transfer::next_half).
+        #[inline(always)]
+        pub fn circular(mut self, circular: bool) -> Self {
+            self.circular = circular;
+            self
+        }
         /// Set the fifo_threshold.
         #[inline(always)]
         pub fn fifo_threshold(mut self, fifo_threshold: FifoThreshold) -> Self {
@@ -865,6 +976,24 @@ pub mod config {
             self.peripheral_burst = peripheral_burst;
             self
         }
+
+        /// Checks the burst settings for `word` bytes transfers against the fifo, see
+        /// [`BurstMode`]
+        pub(crate) fn burst_error(&self, word: u32) -> Option<&'static str> {
+            if self.memory_burst == BurstMode::NoBurst
+                && self.peripheral_burst == BurstMode::NoBurst
+            {
+                None
+            } else if !self.fifo_enable {
+                Some("Burst with fifo disabled.")
+            } else if self.fifo_threshold.bytes() % (self.memory_burst.beats() * word) != 0 {
+                Some("Memory burst incompatible with fifo threshold.")
+            } else if self.peripheral_burst.beats() * word > 16 {
+                Some("Peripheral burst larger than fifo.")
+            } else {
+                None
+            }
+        }
     }
 }
 
@@ -898,6 +1027,7 @@ where
     /// # Panics
     ///
     /// * When double buffering is enabled but the `double_buf` argument is `None`.
+    /// * When the burst settings of `DmaConfig` are invalid, see [`config::BurstMode`].
     pub fn init_memory_to_peripheral(
         mut stream: STREAM,
         peripheral: PERIPHERAL,
@@ -928,6 +1058,30 @@ where
         }
     }
 
+    /// Runs `f` on a half of the buffer of a circular transfer, the one returned by
+    /// [`next_half`](Self::next_half), to refill it while the DMA sends the other half.
+    ///
+    /// # Safety
+    ///
+    /// The DMA must not read `half` while `f` runs, `f` must return before the DMA is done
+    /// with the other half.
+    pub unsafe fn with_half<F, T>(&mut self, half: Half, f: F) -> T
+    where
+        BUF: WriteBuffer<Word = <BUF as ReadBuffer>::Word>,
+        F: FnOnce(&mut [<BUF as ReadBuffer>::Word]) -> T,
+    {
+        let (ptr, len) = self.buf.as_mut().unwrap().write_buffer();
+        let len = len.min(self.transfer_length.into());
+        let range = half.range(len);
+        let result = f(core::slice::from_raw_parts_mut(
+            ptr.add(range.start),
+            range.len(),
+        ));
+        // "No re-ordering of reads and writes across this point is allowed"
+        compiler_fence(Ordering::SeqCst);
+        result
+    }
+
     /// Changes the buffer and restarts or continues a double buffer transfer. This must be called
     /// immediately after a transfer complete event if using double buffering, otherwise you might
     /// lose data. Returns the old buffer together with its `CurrentBuffer`. If an error occurs,
@@ -1043,6 +1197,7 @@ where
     /// # Panics
     ///
     /// * When double buffering is enabled but the `double_buf` argument is `None`.
+    /// * When the burst settings of `DmaConfig` are invalid, see [`config::BurstMode`].
     pub fn init_peripheral_to_memory(
         mut stream: STREAM,
         peripheral: PERIPHERAL,
@@ -1073,6 +1228,28 @@ where
         }
     }
 
+    /// Runs `f` on a half of the buffer of a circular transfer, the one returned by
+    /// [`next_half`](Self::next_half).
+    ///
+    /// # Safety
+    ///
+    /// The DMA must not write to `half` while `f` runs, `f` must return before the DMA is
+    /// done with the other half.
+    pub unsafe fn with_half<F, T>(&mut self, half: Half, f: F) -> T
+    where
+        F: FnOnce(&[<BUF as WriteBuffer>::Word]) -> T,
+    {
+        let (ptr, len) = self.buf.as_mut().unwrap().write_buffer();
+        let len = len.min(self.transfer_length.into());
+        // "No re-ordering of reads and writes across this point is allowed"
+        compiler_fence(Ordering::SeqCst);
+        let range = half.range(len);
+        f(core::slice::from_raw_parts(
+            ptr.add(range.start),
+            range.len(),
+        ))
+    }
+
     /// Changes the buffer and restarts or continues a double buffer transfer. This must be called
     /// immediately after a transfer complete event if using double buffering, otherwise you might
     /// lose data. Returns the old buffer together with its `CurrentBuffer`. If an error occurs,
@@ -1180,6 +1357,7 @@ where
     ///
     /// * When the FIFO is disabled or double buffering is enabled in `DmaConfig` while initializing
     /// a memory to memory transfer.
+    /// * When the burst settings of `DmaConfig` are invalid, see [`config::BurstMode`].
     pub fn init_memory_to_memory(
         mut stream: STREAM,
         peripheral: PERIPHERAL,
@@ -1320,12 +1498,54 @@ where
         self.stream.clear_transfer_complete_interrupt();
     }
 
+    /// Returns `true` if the whole buffer was transferred (tcif).
+    #[inline(always)]
+    pub fn is_transfer_complete(&self) -> bool {
+        STREAM::get_transfer_complete_flag()
+    }
+
     /// Clear half transfer interrupt (htif) for the DMA stream.
     #[inline(always)]
     pub fn clear_half_transfer_interrupt(&mut self) {
         self.stream.clear_half_transfer_interrupt();
     }
 
+    /// Enables the half transfer interrupt (htie) of the DMA stream.
+    #[inline(always)]
+    pub fn enable_half_transfer_interrupt(&mut self) {
+        self.stream.set_half_transfer_interrupt_enable(true);
+    }
+
+    /// Disables the half transfer interrupt (htie) of the DMA stream.
+    #[inline(always)]
+    pub fn disable_half_transfer_interrupt(&mut self) {
+        self.stream.set_half_transfer_interrupt_enable(false);
+    }
+
+    /// Returns `true` if the first half of the buffer was transferred (htif).
+    #[inline(always)]
+    pub fn is_half_transfer(&self) -> bool {
+        STREAM::get_half_transfer_flag()
+    }
+
+    /// Returns the half of the buffer that was just transferred and clears its flag, for a
+    /// transfer in circular mode.
+    ///
+    /// The CPU can use this half while the DMA works on the other one. A `NotReady` error is
+    /// returned while no half is complete, an `Overrun` error when both halves completed
+    /// since the last call, which means that the data of one of them was lost or overwritten.
+    pub fn next_half(&mut self) -> Result<Half, DMAError<()>> {
+        let half = STREAM::get_half_transfer_flag();
+        let complete = STREAM::get_transfer_complete_flag();
+        if half {
+            self.stream.clear_half_transfer_interrupt();
+        }
+        if complete {
+            self.stream.clear_transfer_complete_interrupt();
+        }
+        completed_half(half, complete)
+    }
+
     /// Clear transfer error interrupt (teif) for the DMA stream.
     #[inline(always)]
     pub fn clear_transfer_error_interrupt(&mut self) {
@@ -1374,6 +1594,7 @@ where
         stream.set_direct_mode_error_interrupt_enable(config.direct_mode_error_interrupt);
         stream.set_fifo_error_interrupt_enable(config.fifo_error_interrupt);
         stream.set_double_buffer(config.double_buffer);
+        stream.set_circular(config.circular);
         stream.set_fifo_threshold(config.fifo_threshold);
         stream.set_fifo_enable(config.fifo_enable);
         stream.set_memory_burst(config.memory_burst);
@@ -1407,17 +1628,39 @@ where
                 panic!("Fifo disabled.");
             } else if config.double_buffer {
                 panic!("Double buffering enabled.");
+            } else if config.circular {
+                panic!("Circular mode enabled.");
             }
         } else {
             // Set the peripheral address
             stream.set_peripheral_address(peripheral.address());
         }
 
+        let word = mem::size_of::<<PERIPHERAL as PeriAddress>::MemSize>() as u32;
+        let mburst = config.memory_burst.beats() * word;
+        let pburst = config.peripheral_burst.beats() * word;
+        if let Some(error) = config.burst_error(word) {
+            panic!("{}", error);
+        }
+        // A burst must not cross a 1 KB boundary, which is ensured by aligning the
+        // incremented address to the burst size
+        let check_alignment = |address: u32, burst: u32, increment: bool| {
+            if increment && address % burst != 0 {
+                panic!("Burst address misaligned.");
+            }
+        };
+        check_alignment(buf_ptr, mburst, config.memory_increment);
+        if !is_mem2mem {
+            check_alignment(peripheral.address(), pburst, config.peripheral_increment);
+        }
+
         let db_len = if let Some((db_ptr, db_len)) = db {
             if is_mem2mem {
                 // Double buffer is the source in mem2mem mode
+                check_alignment(db_ptr, pburst, config.peripheral_increment);
                 stream.set_peripheral_address(db_ptr as u32);
             } else {
+                check_alignment(db_ptr, mburst, config.memory_increment);
                 stream.set_memory_double_buffer_address(db_ptr as u32);
             }
             Some(db_len)
@@ -1600,3 +1843,105 @@ where
         compiler_fence(Ordering::SeqCst);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::config::{BurstMode, DmaConfig, FifoThreshold};
+    use super::{completed_half, Bits, DMAError, Half};
+
+    #[test]
+    fn half_ranges() {
+        assert_eq!(Half::First.range(8), 0..4);
+        assert_eq!(Half::Second.range(8), 4..8);
+        // HTIF is set after len / 2 items
+        assert_eq!(Half::First.range(5), 0..2);
+        assert_eq!(Half::Second.range(5), 2..5);
+        assert_eq!(Half::Second.range(0), 0..0);
+        assert_eq!(!Half::First, Half::Second);
+        assert_eq!(!Half::Second, Half::First);
+    }
+
+    #[test]
+    fn half_from_htif_and_tcif() {
+        assert_eq!(completed_half(false, false), Err(DMAError::NotReady(())));
+        assert_eq!(completed_half(true, false), Ok(Half::First));
+        assert_eq!(completed_half(false, true), Ok(Half::Second));
+        assert_eq!(completed_half(true, true), Err(DMAError::Overrun(())));
+    }
+
+    #[test]
+    fn burst_fcr_cr_encoding() {
+        // FCR.FTH
+        let fth = [
+            FifoThreshold::QuarterFull,
+            FifoThreshold::HalfFull,
+            FifoThreshold::ThreeQuarterFull,
+            FifoThreshold::Full,
+        ];
+        for (i, t) in fth.into_iter().enumerate() {
+            assert_eq!(t.bits(), i as u8);
+            assert_eq!(t.bytes(), 4 * (i as u32 + 1));
+        }
+        // CR.MBURST / CR.PBURST
+        let burst = [
+            BurstMode::NoBurst,
+            BurstMode::Burst4,
+            BurstMode::Burst8,
+            BurstMode::Burst16,
+        ];
+        for (i, b) in burst.into_iter().enumerate() {
+            assert_eq!(b.bits(), i as u8);
+        }
+        assert_eq!(BurstMode::Burst16.beats(), 16);
+    }
+
+    #[test]
+    fn burst_needs_fifo() {
+        let config = DmaConfig::default();
+        assert_eq!(config.burst_error(4), None);
+        let config = config.memory_burst(BurstMode::Burst4);
+        assert_eq!(config.burst_error(1), Some("Burst with fifo disabled."));
+        assert_eq!(config.fifo_enable(true).burst_error(1), None);
+    }
+
+    #[test]
+    fn memory_burst_divides_fifo_threshold() {
+        let config = DmaConfig::default()
+            .fifo_enable(true)
+            .memory_burst(BurstMode::Burst4);
+        // 4 × u16 = 8 bytes
+        for (threshold, ok) in [
+            (FifoThreshold::QuarterFull, false),
+            (FifoThreshold::HalfFull, true),
+            (FifoThreshold::ThreeQuarterFull, false),
+            (FifoThreshold::Full, true),
+        ] {
+            let error = config.fifo_threshold(threshold).burst_error(2);
+            assert_eq!(error.is_none(), ok, "{:?}", threshold);
+        }
+        // 4 × u32 = 16 bytes only fits a full fifo
+        let config = config.fifo_threshold(FifoThreshold::HalfFull);
+        assert!(config.burst_error(4).is_some());
+        assert!(config
+            .fifo_threshold(FifoThreshold::Full)
+            .burst_error(4)
+            .is_none());
+    }
+
+    #[test]
+    fn peripheral_burst_fits_fifo() {
+        let config = DmaConfig::default()
+            .fifo_enable(true)
+            .fifo_threshold(FifoThreshold::Full)
+            .peripheral_burst(BurstMode::Burst8);
+        assert_eq!(config.burst_error(2), None);
+        assert_eq!(
+            config.burst_error(4),
+            Some("Peripheral burst larger than fifo.")
+        );
+        assert_eq!(
+            config.peripheral_burst(BurstMode::Burst16).burst_error(1),
+            None
+        );
+    }
+}
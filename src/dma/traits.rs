@@ -166,6 +166,9 @@ pub trait Stream: StreamISR + Sealed {
     /// Enable/disable the double buffer (dbm) of the DMA stream.
     fn set_double_buffer(&mut self, double_buffer: bool);
 
+    /// Enable/disable the circular mode (circ) of the DMA stream.
+    fn set_circular(&mut self, circular: bool);
+
     /// Set the fifo threshold (fcr.fth) of the DMA stream.
     fn set_fifo_threshold(&mut self, fifo_threshold: config::FifoThreshold);
 
@@ -444,7 +447,7 @@ dma_map!(
     (Stream6<DMA1>, 4, serial::Tx<pac::USART2>, MemoryToPeripheral), //USART2_TX
     (Stream7<DMA1>, 7, pac::I2C2, MemoryToPeripheral),       //I2C2_TX
     (Stream0<DMA2>, 0, pac::ADC1, PeripheralToMemory),       //ADC1
-    (Stream0<DMA2>, 0, Adc<pac::ADC1>, PeripheralToMemory),
+    (Stream0<DMA2>, 0, Adc<pac::ADC1>, PeripheralToMemory), //ADC1
     (Stream0<DMA2>, 3, pac::SPI1, PeripheralToMemory), //SPI1_RX
     (Stream0<DMA2>, 3, spi::Rx<pac::SPI1>, PeripheralToMemory), //SPI1_RX
     (Stream1<DMA2>, 5, pac::USART6, PeripheralToMemory), //USART6_RX
@@ -456,6 +459,7 @@ dma_map!(
     (Stream2<DMA2>, 5, pac::USART6, PeripheralToMemory), //USART6_RX
     (Stream2<DMA2>, 5, serial::Rx<pac::USART6>, PeripheralToMemory), //USART6_RX
     (Stream4<DMA2>, 0, pac::ADC1, PeripheralToMemory), //ADC1
+    (Stream4<DMA2>, 0, Adc<pac::ADC1>, PeripheralToMemory), //ADC1
     (Stream5<DMA2>, 4, pac::USART1, PeripheralToMemory), //USART1_RX
     (Stream5<DMA2>, 4, serial::Rx<pac::USART1>, PeripheralToMemory), //USART1_RX
     (Stream6<DMA2>, 5, pac::USART6, MemoryToPeripheral), //USART6_TX
@@ -855,8 +859,8 @@ dma_map!(
     (Stream2<DMA2>, 1, pac::ADC2, PeripheralToMemory),  //ADC2
     (Stream3<DMA2>, 1, pac::ADC2, PeripheralToMemory),  //ADC2
     (Stream7<DMA2>, 1, pac::DCMI, PeripheralToMemory),  //DCMI
-    (Stream2<DMA2>, 1, Adc<pac::ADC3>, PeripheralToMemory), //ADC2
-    (Stream3<DMA2>, 1, Adc<pac::ADC3>, PeripheralToMemory), //ADC2
+    (Stream2<DMA2>, 1, Adc<pac::ADC2>, PeripheralToMemory), //ADC2
+    (Stream3<DMA2>, 1, Adc<pac::ADC2>, PeripheralToMemory), //ADC2
     (Stream0<DMA2>, 2, Adc<pac::ADC3>, PeripheralToMemory), //ADC3
     (Stream1<DMA2>, 2, Adc<pac::ADC3>, PeripheralToMemory), //ADC3
 );
@@ -977,23 +981,11 @@ dma_map!(
     (Stream4<DMA2>, 4, spi::Rx<pac::SPI4>, PeripheralToMemory),
 ); //SPI4_RX);
 
-/* TODO: DFSDM support
-#[cfg(feature = "stm32f412")]
-dma_map!(
-    (Stream0<pac::DMA2>, 7, pac::DFSDM, PeripheralToMemory), //DFSDM1_FLT0
-    (Stream1<pac::DMA2>, 3, pac::DFSDM, PeripheralToMemory), //DFSDM1_FLT1
-    (Stream4<pac::DMA2>, 3, pac::DFSDM, PeripheralToMemory), //DFSDM1_FLT1
-    (Stream6<pac::DMA2>, 3, pac::DFSDM, PeripheralToMemory), //DFSDM1_FLT0:DMA_CHANNEL_3
-);
-#[cfg(feature = "stm32f412")]
-address!((pac::DFSDM, dr),);
+// DFSDM1 filters are mapped in the `dfsdm` module
 
+/* TODO: DFSDM2 support
 #[cfg(any(feature = "stm32f413", feature = "stm32f423"))]
 dma_map!(
-    (Stream0<pac::DMA2>, 7, pac::DFSDM1, PeripheralToMemory), //DFSDM1_FLT0
-    (Stream1<pac::DMA2>, 3, pac::DFSDM1, PeripheralToMemory), //DFSDM1_FLT1
-    (Stream4<pac::DMA2>, 3, pac::DFSDM1, PeripheralToMemory), //DFSDM1_FLT1
-    (Stream6<pac::DMA2>, 3, pac::DFSDM1, PeripheralToMemory), //DFSDM1_FLT0:DMA_CHANNEL_3
     (Stream0<pac::DMA2>, 8, pac::DFSDM2, PeripheralToMemory), //DFSDM2_FLT0
     (Stream1<pac::DMA2>, 8, pac::DFSDM2, PeripheralToMemory), //DFSDM2_FLT1
     (Stream2<pac::DMA2>, 8, pac::DFSDM2, PeripheralToMemory), //DFSDM2_FLT2
@@ -1004,7 +996,7 @@ dma_map!(
     (Stream7<pac::DMA2>, 8, pac::DFSDM2, PeripheralToMemory), //DFSDM2_FLT3
 );
 #[cfg(any(feature = "stm32f413", feature = "stm32f423"))]
-address!((pac::DFSDM1, dr), (pac::DFSDM2, dr),);
+address!((pac::DFSDM2, dr),);
 */
 
 #[cfg(any(
@@ -1080,44 +1072,7 @@ address!(
     (pac::UART10, dr, u8),
 );
 
-/* Not sure how SAI works
-#[cfg(any(
-    feature = "stm32f413",
-    feature = "stm32f423",
-    feature = "stm32f427",
-    feature = "stm32f439",
-    feature = "stm32f437",
-    feature = "stm32f429",
-    feature = "stm32f446",
-    feature = "stm32f469",
-    feature = "stm32f479",
-))]
-dma_map!(
-    (pac::DMA2, Stream1, 0, pac::SAI, MemoryToPeripheral), //SAI1_A
-    (pac::DMA2, Stream1, 0, pac::SAI, PeripheralToMemory), //SAI1_A
-    (pac::DMA2, Stream3, 0, pac::SAI, MemoryToPeripheral), //SAI1_A
-    (pac::DMA2, Stream3, 0, pac::SAI, PeripheralToMemory), //SAI1_A
-    (pac::DMA2, Stream4, 1, pac::SAI, MemoryToPeripheral), //SAI1_B
-    (pac::DMA2, Stream4, 1, pac::SAI, PeripheralToMemory), //SAI1_B
-    (pac::DMA2, Stream5, 0, pac::SAI, MemoryToPeripheral), //SAI1_B:DMA_CHANNEL_0
-    (pac::DMA2, Stream5, 0, pac::SAI, PeripheralToMemory), //SAI1_B:DMA_CHANNEL_0
-);
-
-#[cfg(any(
-    feature = "stm32f413",
-    feature = "stm32f423",
-    feature = "stm32f427",
-    feature = "stm32f439",
-    feature = "stm32f437",
-    feature = "stm32f429",
-    feature = "stm32f446",
-    feature = "stm32f469",
-    feature = "stm32f479",
-))]
-address!(
-    (pac::SAI, dr),
-);
-*/
+// SAI sub-blocks are mapped in the `sai` module
 
 #[cfg(any(
     feature = "stm32f427",
@@ -1149,23 +1104,65 @@ address!((pac::SPI6, dr, u8),);
     feature = "stm32f446",
 ))]
 dma_map!(
-    (pac::DMA1, Stream1, 0, pac::SPDIFRX, PeripheralToMemory), //SPDIF_RX_DT
     (pac::DMA1, Stream2, 2, pac::FMPI2C1, PeripheralToMemory), //FMPI2C1_RX
     (pac::DMA1, Stream5, 2, pac::FMPI2C1, MemoryToPeripheral), //FMPI2C1_TX
-    (pac::DMA1, Stream6, 0, pac::SPDIFRX, PeripheralToMemory), //SPDIF_RX_CS
-    (pac::DMA2, Stream4, 3, pac::SAI2, MemoryToPeripheral), //SAI2_A
-    (pac::DMA2, Stream4, 3, pac::SAI2, PeripheralToMemory), //SAI2_A
-    (pac::DMA2, Stream6, 3, pac::SAI2, MemoryToPeripheral), //SAI2_B
-    (pac::DMA2, Stream6, 3, pac::SAI2, PeripheralToMemory), //SAI2_B
-    (pac::DMA2, Stream7, 0, pac::SAI2, MemoryToPeripheral), //SAI2_B:DMA_CHANNEL_0
-    (pac::DMA2, Stream7, 0, pac::SAI2, PeripheralToMemory), //SAI2_B:DMA_CHANNEL_0
 );
 #[cfg(any(
     feature = "stm32f446",
 ))]
 address!(
-    (pac::SPDIFRX, ??),
     (pac::FMPI2C1, ??),
-    (pac::SAI2, ??),
 );
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapped<STREAM, const CHANNEL: u8, PERIPHERAL>()
+    where
+        PERIPHERAL: DMASet<STREAM, CHANNEL, PeripheralToMemory>,
+    {
+    }
+
+    /// Resolves to a single impl, and compiles, only if `PERIPHERAL` is not mapped
+    trait NotMapped<STREAM, const CHANNEL: u8, A> {
+        fn check() {}
+    }
+    impl<STREAM, const CHANNEL: u8, PERIPHERAL> NotMapped<STREAM, CHANNEL, ()> for PERIPHERAL {}
+    impl<STREAM, const CHANNEL: u8, PERIPHERAL> NotMapped<STREAM, CHANNEL, u8> for PERIPHERAL where
+        PERIPHERAL: DMASet<STREAM, CHANNEL, PeripheralToMemory>
+    {
+    }
+
+    #[test]
+    fn adc1_mapping() {
+        mapped::<Stream0<DMA2>, 0, Adc<pac::ADC1>>();
+        mapped::<Stream4<DMA2>, 0, Adc<pac::ADC1>>();
+        <Adc<pac::ADC1> as NotMapped<Stream2<DMA2>, 1, _>>::check();
+    }
+
+    #[cfg(any(
+        feature = "stm32f417",
+        feature = "stm32f415",
+        feature = "stm32f405",
+        feature = "stm32f407",
+        feature = "stm32f427",
+        feature = "stm32f439",
+        feature = "stm32f437",
+        feature = "stm32f429",
+        feature = "stm32f446",
+        feature = "stm32f469",
+        feature = "stm32f479",
+    ))]
+    #[test]
+    fn adc2_mapping() {
+        mapped::<Stream2<DMA2>, 1, Adc<pac::ADC2>>();
+        mapped::<Stream3<DMA2>, 1, Adc<pac::ADC2>>();
+        mapped::<Stream0<DMA2>, 2, Adc<pac::ADC3>>();
+        mapped::<Stream1<DMA2>, 2, Adc<pac::ADC3>>();
+        // Channel 1 of streams 2 and 3 is ADC2, not ADC3
+        <Adc<pac::ADC3> as NotMapped<Stream2<DMA2>, 1, _>>::check();
+        <Adc<pac::ADC3> as NotMapped<Stream3<DMA2>, 1, _>>::check();
+    }
+}
@@ -0,0 +1,504 @@
+//! Chrom-ART accelerator (DMA2D)
+//!
+//! The DMA2D fills rectangles with a colour, copies them, converts their pixel format and
+//! blends two of them, without CPU intervention. Areas are described by [`Surface`]s, built
+//! from a borrowed buffer so the engine can't write outside of it.
+//!
+//! Every operation has a blocking form, e.g. [`Dma2d::fill_rect`], and an `unsafe`
+//! non-blocking form returning a [`Transfer`], e.g. [`Dma2d::start_fill_rect`]: only dropping
+//! the transfer stops the engine, which must not be skipped by leaking it. With
+//! [`Dma2d::listen`], the `DMA2D` interrupt fires at the end of the transfer. The interrupt
+//! handler must clear the flags with [`Dma2d::clear_interrupts`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut dma2d = Dma2d::new(dp.DMA2D);
+//! let mut screen = Surface::new(&mut framebuffer[..], PixelFormat::Rgb565, 240, 320, 0)?;
+//! dma2d.fill_rect(&mut screen, 0xf800)?;
+//!
+//! let sprite = Surface::new(&mut sprite_data[..], PixelFormat::Argb8888, 32, 32, 0)?;
+//! let mut area = screen.sub_surface(100, 100, 32, 32)?;
+//! dma2d.copy(&sprite, &mut area)?;
+//! ```
+
+use core::marker::PhantomData;
+use core::mem;
+
+use crate::pac::{DMA2D, RCC};
+use crate::rcc::{Enable, Reset};
+
+const CR_START: u32 = 1 << 0;
+const CR_ABORT: u32 = 1 << 2;
+const CR_MODE_OFFSET: u8 = 16;
+
+const ISR_TEIF: u32 = 1 << 0;
+const ISR_CEIF: u32 = 1 << 5;
+const IFCR_ALL: u32 = 0x3f;
+
+/// Largest width and line offset, in pixels
+const MAX_WIDTH: u16 = 0x3fff;
+
+/// DMA2D errors
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The buffer is too small or not aligned for the surface
+    BufferTooSmall,
+    /// A width, height or line offset is zero or out of its register field
+    InvalidSize,
+    /// Source and destination surfaces have different dimensions
+    SizeMismatch,
+    /// An AHB bus error occurred during the transfer
+    TransferError,
+    /// The DMA2D rejected the configuration
+    ConfigurationError,
+}
+
+/// Pixel format of a surface
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum PixelFormat {
+    /// 32 bits per pixel with alpha
+    Argb8888 = 0,
+    /// 24 bits per pixel
+    Rgb888 = 1,
+    /// 16 bits per pixel
+    Rgb565 = 2,
+    /// 16 bits per pixel with 1-bit alpha
+    Argb1555 = 3,
+    /// 16 bits per pixel with 4-bit alpha
+    Argb4444 = 4,
+}
+
+impl PixelFormat {
+    /// Size of a pixel in memory
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Argb8888 => 4,
+            Self::Rgb888 => 3,
+            Self::Rgb565 | Self::Argb1555 | Self::Argb4444 => 2,
+        }
+    }
+
+    /// Alignment required by the DMA2D for the buffer address
+    const fn alignment(self) -> usize {
+        match self {
+            Self::Argb8888 => 4,
+            Self::Rgb888 => 1,
+            Self::Rgb565 | Self::Argb1555 | Self::Argb4444 => 2,
+        }
+    }
+}
+
+/// Alpha applied to an input surface
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Alpha {
+    /// Pixel alpha, or opaque for formats without alpha
+    Keep,
+    /// The alpha of every pixel is replaced with this value
+    Replace(u8),
+    /// The alpha of every pixel is multiplied with this value
+    Multiply(u8),
+}
+
+impl Alpha {
+    fn pfccr_bits(self) -> u32 {
+        match self {
+            Self::Keep => 0,
+            Self::Replace(alpha) => 0b01 << 16 | u32::from(alpha) << 24,
+            Self::Multiply(alpha) => 0b10 << 16 | u32::from(alpha) << 24,
+        }
+    }
+}
+
+/// Interrupt events
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// A transfer is complete
+    TransferComplete,
+    /// An AHB bus error occurred
+    TransferError,
+    /// The configuration of a transfer was rejected
+    ConfigurationError,
+}
+
+impl Event {
+    /// Interrupt enable bit in CR, the flag is 8 bits lower in ISR
+    fn cr_bit(self) -> u32 {
+        match self {
+            Self::TransferError => 1 << 8,
+            Self::TransferComplete => 1 << 9,
+            Self::ConfigurationError => 1 << 13,
+        }
+    }
+}
+
+/// Transfer mode, the value of CR.MODE
+#[derive(Copy, Clone)]
+enum Mode {
+    MemoryToMemory = 0b00,
+    MemoryToMemoryPfc = 0b01,
+    Blend = 0b10,
+    RegisterToMemory = 0b11,
+}
+
+/// A rectangle of pixels in memory
+///
+/// Lines are `width` pixels long and separated by `line_offset` pixels, so a surface can
+/// describe a window of a larger framebuffer.
+pub struct Surface<'a> {
+    address: u32,
+    pixel_format: PixelFormat,
+    width: u16,
+    height: u16,
+    line_offset: u16,
+    _buffer: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> Surface<'a> {
+    /// Describes `buffer` as `height` lines of `width` pixels separated by `line_offset` pixels
+    ///
+    /// Fails if the buffer doesn't hold the whole surface or isn't aligned for the pixel format.
+    pub fn new<T>(
+        buffer: &'a mut [T],
+        pixel_format: PixelFormat,
+        width: u16,
+        height: u16,
+        line_offset: u16,
+    ) -> Result<Self, Error> {
+        check_size(width, height, line_offset)?;
+        let address = buffer.as_mut_ptr() as usize;
+        let len = mem::size_of_val(buffer);
+        let stride = usize::from(width) + usize::from(line_offset);
+        let required = ((usize::from(height) - 1) * stride + usize::from(width))
+            * pixel_format.bytes_per_pixel();
+        if required > len || address % pixel_format.alignment() != 0 {
+            return Err(Error::BufferTooSmall);
+        }
+        Ok(Self {
+            address: address as u32,
+            pixel_format,
+            width,
+            height,
+            line_offset,
+            _buffer: PhantomData,
+        })
+    }
+
+    /// Describes memory at `address`, for framebuffers outside of any Rust object
+    ///
+    /// # Safety
+    ///
+    /// The whole surface must be valid memory, not used by anything else while the surface
+    /// exists.
+    pub unsafe fn from_raw(
+        address: u32,
+        pixel_format: PixelFormat,
+        width: u16,
+        height: u16,
+        line_offset: u16,
+    ) -> Result<Self, Error> {
+        check_size(width, height, line_offset)?;
+        Ok(Self {
+            address,
+            pixel_format,
+            width,
+            height,
+            line_offset,
+            _buffer: PhantomData,
+        })
+    }
+
+    /// Borrows the `width` × `height` window at (`x`, `y`) as a surface
+    pub fn sub_surface(
+        &mut self,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+    ) -> Result<Surface<'_>, Error> {
+        if u32::from(x) + u32::from(width) > u32::from(self.width)
+            || u32::from(y) + u32::from(height) > u32::from(self.height)
+        {
+            return Err(Error::InvalidSize);
+        }
+        let stride = u32::from(self.width) + u32::from(self.line_offset);
+        let offset =
+            (u32::from(y) * stride + u32::from(x)) * self.pixel_format.bytes_per_pixel() as u32;
+        let line_offset = self.line_offset + (self.width - width);
+        check_size(width, height, line_offset)?;
+        Ok(Surface {
+            address: self.address + offset,
+            pixel_format: self.pixel_format,
+            width,
+            height,
+            line_offset,
+            _buffer: PhantomData,
+        })
+    }
+
+    /// Address of the first pixel
+    pub fn address(&self) -> u32 {
+        self.address
+    }
+
+    /// Pixel format
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    /// Width in pixels
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Height in lines
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Pixels skipped between two lines
+    pub fn line_offset(&self) -> u16 {
+        self.line_offset
+    }
+
+    fn same_size(&self, other: &Surface) -> Result<(), Error> {
+        if self.width == other.width && self.height == other.height {
+            Ok(())
+        } else {
+            Err(Error::SizeMismatch)
+        }
+    }
+}
+
+fn check_size(width: u16, height: u16, line_offset: u16) -> Result<(), Error> {
+    if width == 0 || height == 0 || width > MAX_WIDTH || line_offset > MAX_WIDTH {
+        Err(Error::InvalidSize)
+    } else {
+        Ok(())
+    }
+}
+
+/// Chrom-ART accelerator
+pub struct Dma2d {
+    dma2d: DMA2D,
+}
+
+impl Dma2d {
+    /// Enables and resets the DMA2D
+    pub fn new(dma2d: DMA2D) -> Self {
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            DMA2D::enable(rcc);
+            DMA2D::reset(rcc);
+        }
+        Self { dma2d }
+    }
+
+    /// Fills `dst` with `color`, given in the pixel format of `dst`
+    pub fn fill_rect(&mut self, dst: &mut Surface, color: u32) -> Result<(), Error> {
+        // NOTE(unsafe) the transfer is waited for right away
+        unsafe { self.start_fill_rect(dst, color)?.wait() }
+    }
+
+    /// Copies `src` to `dst`, converting the pixel format if they differ
+    pub fn copy(&mut self, src: &Surface, dst: &mut Surface) -> Result<(), Error> {
+        // NOTE(unsafe) the transfer is waited for right away
+        unsafe { self.start_copy(src, dst, Alpha::Keep)?.wait() }
+    }
+
+    /// Blends the foreground `fg` over the background `bg` into `dst`
+    ///
+    /// `bg` and `dst` may be the same memory, e.g. to draw over a framebuffer, by giving a
+    /// surface built with [`Surface::from_raw`] as background.
+    pub fn blend(
+        &mut self,
+        fg: &Surface,
+        fg_alpha: Alpha,
+        bg: &Surface,
+        dst: &mut Surface,
+    ) -> Result<(), Error> {
+        // NOTE(unsafe) the transfer is waited for right away
+        unsafe { self.start_blend(fg, fg_alpha, bg, Alpha::Keep, dst)?.wait() }
+    }
+
+    /// Starts filling `dst` with `color`, given in the pixel format of `dst`
+    ///
+    /// # Safety
+    ///
+    /// The returned [`Transfer`] must be dropped or waited for, not leaked with
+    /// [`mem::forget`] or similar, as the engine would keep accessing the surfaces after their
+    /// borrow ends.
+    pub unsafe fn start_fill_rect<'t>(
+        &'t mut self,
+        dst: &'t mut Surface,
+        color: u32,
+    ) -> Result<Transfer<'t>, Error> {
+        self.dma2d.ocolr.write(|w| w.bits(color));
+        self.set_output(dst);
+        Ok(self.start(Mode::RegisterToMemory))
+    }
+
+    /// Starts copying `src` to `dst`, converting the pixel format if they differ
+    ///
+    /// `alpha` is applied to the source when converting.
+    ///
+    /// # Safety
+    ///
+    /// The returned [`Transfer`] must be dropped or waited for, not leaked with
+    /// [`mem::forget`] or similar, as the engine would keep accessing the surfaces after their
+    /// borrow ends.
+    pub unsafe fn start_copy<'t>(
+        &'t mut self,
+        src: &'t Surface,
+        dst: &'t mut Surface,
+        alpha: Alpha,
+    ) -> Result<Transfer<'t>, Error> {
+        src.same_size(dst)?;
+        self.set_foreground(src, alpha);
+        self.set_output(dst);
+        let mode = if src.pixel_format == dst.pixel_format && alpha == Alpha::Keep {
+            Mode::MemoryToMemory
+        } else {
+            Mode::MemoryToMemoryPfc
+        };
+        Ok(self.start(mode))
+    }
+
+    /// Starts blending the foreground `fg` over the background `bg` into `dst`
+    ///
+    /// # Safety
+    ///
+    /// The returned [`Transfer`] must be dropped or waited for, not leaked with
+    /// [`mem::forget`] or similar, as the engine would keep accessing the surfaces after their
+    /// borrow ends.
+    pub unsafe fn start_blend<'t>(
+        &'t mut self,
+        fg: &'t Surface,
+        fg_alpha: Alpha,
+        bg: &'t Surface,
+        bg_alpha: Alpha,
+        dst: &'t mut Surface,
+    ) -> Result<Transfer<'t>, Error> {
+        fg.same_size(dst)?;
+        bg.same_size(dst)?;
+        self.set_foreground(fg, fg_alpha);
+        self.dma2d.bgmar.write(|w| w.bits(bg.address));
+        self.dma2d.bgor.write(|w| w.bits(bg.line_offset.into()));
+        self.dma2d
+            .bgpfccr
+            .write(|w| w.bits(bg.pixel_format as u32 | bg_alpha.pfccr_bits()));
+        self.set_output(dst);
+        Ok(self.start(Mode::Blend))
+    }
+
+    /// Enables the interrupt for `event`
+    pub fn listen(&mut self, event: Event) {
+        self.dma2d
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() | event.cr_bit()) });
+    }
+
+    /// Disables the interrupt for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.dma2d
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !event.cr_bit()) });
+    }
+
+    /// Clears all interrupt flags, to be called from the `DMA2D` interrupt handler
+    ///
+    /// Completion is still reported by [`Transfer::is_complete`] afterwards, but errors are
+    /// only reported by the flags.
+    pub fn clear_interrupts() {
+        // NOTE(unsafe) atomic write to a write-only register
+        unsafe { (*DMA2D::ptr()).ifcr.write(|w| w.bits(IFCR_ALL)) }
+    }
+
+    /// Disables the DMA2D clock and returns the peripheral
+    pub fn release(self) -> DMA2D {
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            DMA2D::disable(&(*RCC::ptr()));
+        }
+        self.dma2d
+    }
+
+    fn set_foreground(&mut self, src: &Surface, alpha: Alpha) {
+        unsafe {
+            self.dma2d.fgmar.write(|w| w.bits(src.address));
+            self.dma2d.fgor.write(|w| w.bits(src.line_offset.into()));
+            self.dma2d
+                .fgpfccr
+                .write(|w| w.bits(src.pixel_format as u32 | alpha.pfccr_bits()));
+        }
+    }
+
+    fn set_output(&mut self, dst: &Surface) {
+        unsafe {
+            self.dma2d.omar.write(|w| w.bits(dst.address));
+            self.dma2d.oor.write(|w| w.bits(dst.line_offset.into()));
+            self.dma2d.opfccr.write(|w| w.bits(dst.pixel_format as u32));
+            self.dma2d
+                .nlr
+                .write(|w| w.bits(u32::from(dst.width) << 16 | u32::from(dst.height)));
+        }
+    }
+
+    fn start(&mut self, mode: Mode) -> Transfer<'_> {
+        Self::clear_interrupts();
+        self.dma2d.cr.modify(|r, w| unsafe {
+            w.bits(
+                r.bits() & !(0b11 << CR_MODE_OFFSET) | (mode as u32) << CR_MODE_OFFSET | CR_START,
+            )
+        });
+        Transfer { dma2d: &self.dma2d }
+    }
+}
+
+/// A running DMA2D operation, borrowing the surfaces it uses
+///
+/// Dropping it before completion aborts the operation. It must not be leaked, see
+/// [`Dma2d::start_fill_rect`].
+pub struct Transfer<'t> {
+    dma2d: &'t DMA2D,
+}
+
+impl Transfer<'_> {
+    /// Returns `true` once the operation is complete, successfully or not
+    pub fn is_complete(&self) -> bool {
+        self.dma2d.cr.read().bits() & CR_START == 0
+    }
+
+    /// Waits for the end of the operation
+    pub fn wait(self) -> Result<(), Error> {
+        while !self.is_complete() {}
+        let isr = self.dma2d.isr.read().bits();
+        Dma2d::clear_interrupts();
+        if isr & ISR_CEIF != 0 {
+            Err(Error::ConfigurationError)
+        } else if isr & ISR_TEIF != 0 {
+            Err(Error::TransferError)
+        } else {
+            // TCIF isn't checked, the interrupt handler may have cleared it already
+            Ok(())
+        }
+    }
+}
+
+impl Drop for Transfer<'_> {
+    fn drop(&mut self) {
+        if !self.is_complete() {
+            self.dma2d
+                .cr
+                .modify(|r, w| unsafe { w.bits(r.bits() | CR_ABORT) });
+            while !self.is_complete() {}
+        }
+    }
+}
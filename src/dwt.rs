@@ -1,8 +1,9 @@
 //! Debug and trace and stuff
 
 use crate::rcc::Clocks;
+use crate::timer::SysTickTimer;
 use cortex_m::peripheral::{DCB, DWT};
-use fugit::HertzU32 as Hertz;
+use fugit::{HertzU32 as Hertz, NanosDurationU64};
 
 pub trait DwtExt {
     fn constrain(self, dcb: DCB, clocks: &Clocks) -> Dwt;
@@ -54,6 +55,21 @@ impl Dwt {
         f();
         sw.lap().lap_time(1).unwrap()
     }
+    /// Measure cycles it takes to execute closure `f`, detecting cycle counter overflows.
+    ///
+    /// Returns `None` when `f` ran for longer than the 32-bit counter range, about 25 s at
+    /// 168 MHz. The overflow is detected with the [`SysTickTimer`] tick, which must be
+    /// running for this to work.
+    pub fn measure_checked<F: FnOnce()>(&self, f: F) -> Option<ClockDuration> {
+        let start_ms = SysTickTimer::millis();
+        let duration = self.measure(f);
+        let elapsed_ms = SysTickTimer::millis().wrapping_sub(start_ms);
+        (!cycle_count_wrapped(elapsed_ms, duration.as_millis())).then(|| duration)
+    }
+    /// Create a profiler named `name`
+    pub fn profiler(&self, name: &'static str) -> DwtProfiler {
+        DwtProfiler::new(name, self.clock)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -114,26 +130,38 @@ impl<T: Into<u64>> embedded_hal::blocking::delay::DelayMs<T> for Delay {
     }
 }
 
-impl embedded_hal_one::delay::blocking::DelayUs for Delay {
-    type Error = core::convert::Infallible;
+impl embedded_hal_one::delay::DelayNs for Delay {
+    fn delay_ns(&mut self, ns: u32) {
+        // Convert ns to ticks, rounding up
+        let start = DWT::cycle_count();
+        let ticks = (ns as u64 * self.clock.raw() as u64 + 999_999_999) / 1_000_000_000;
+        Delay::delay_ticks(start, ticks);
+    }
 
-    fn delay_us(&mut self, us: u32) -> Result<(), Self::Error> {
+    fn delay_us(&mut self, us: u32) {
         // Convert us to ticks
         let start = DWT::cycle_count();
         let ticks = (us as u64 * self.clock.raw() as u64) / 1_000_000;
         Delay::delay_ticks(start, ticks);
-        Ok(())
     }
 
-    fn delay_ms(&mut self, ms: u32) -> Result<(), Self::Error> {
+    fn delay_ms(&mut self, ms: u32) {
         // Convert ms to ticks
         let start = DWT::cycle_count();
         let ticks = (ms as u64 * self.clock.raw() as u64) / 1_000;
         Delay::delay_ticks(start, ticks);
-        Ok(())
     }
 }
 
+/// Returns true if a cycle count measured as `measured_ms` wrapped around, while the tick
+/// counter measured `elapsed_ms`
+///
+/// A wrapped cycle count is short by whole counter periods, while the tick count is off by
+/// at most one.
+fn cycle_count_wrapped(elapsed_ms: u32, measured_ms: u64) -> bool {
+    u64::from(elapsed_ms.saturating_sub(1)) > measured_ms
+}
+
 /// Very simple stopwatch which reads from DWT Cycle Counter to record timing.
 ///
 /// Since DWT Cycle Counter is a 32-bit counter that wraps around to 0 on overflow,
@@ -225,6 +253,126 @@ impl ClockDuration {
     pub fn as_secs_f64(self) -> f64 {
         self.ticks as f64 / self.clock.raw() as f64
     }
+    /// Returns the duration, rounded down to nanoseconds
+    pub fn to_duration(self) -> NanosDurationU64 {
+        NanosDurationU64::from_ticks(self.as_nanos())
+    }
+}
+
+impl From<ClockDuration> for NanosDurationU64 {
+    fn from(duration: ClockDuration) -> Self {
+        duration.to_duration()
+    }
+}
+
+/// Cycle statistics of a named code section
+///
+/// ```ignore
+/// let mut profiler = dwt.profiler("filter");
+/// loop {
+///     profiler.measure(|| filter.update(sample));
+///     if profiler.count() == 1000 {
+///         defmt::info!("{}", profiler.report());
+///         profiler.reset();
+///     }
+/// }
+/// ```
+pub struct DwtProfiler {
+    name: &'static str,
+    clock: Hertz,
+    count: u32,
+    min: u32,
+    max: u32,
+    total: u64,
+}
+impl DwtProfiler {
+    fn new(name: &'static str, clock: Hertz) -> Self {
+        Self {
+            name,
+            clock,
+            count: 0,
+            min: u32::MAX,
+            max: 0,
+            total: 0,
+        }
+    }
+    /// Measure closure `f` and record its duration
+    pub fn measure<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let start = DWT::cycle_count();
+        let result = f();
+        self.record(DWT::cycle_count().wrapping_sub(start));
+        result
+    }
+    /// Record a duration of `cycles`
+    pub fn record(&mut self, cycles: u32) {
+        self.count = self.count.saturating_add(1);
+        self.min = self.min.min(cycles);
+        self.max = self.max.max(cycles);
+        self.total = self.total.saturating_add(cycles as u64);
+    }
+    /// Number of recorded durations
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+    /// Forget the recorded durations
+    pub fn reset(&mut self) {
+        *self = Self::new(self.name, self.clock);
+    }
+    /// Statistics of the recorded durations, `None` if there are none
+    pub fn report(&self) -> Option<ProfileReport> {
+        (self.count > 0).then(|| ProfileReport {
+            name: self.name,
+            count: self.count,
+            min: self.duration(self.min),
+            max: self.duration(self.max),
+            mean: self.duration((self.total / self.count as u64) as u32),
+        })
+    }
+    fn duration(&self, ticks: u32) -> ClockDuration {
+        ClockDuration {
+            ticks,
+            clock: self.clock,
+        }
+    }
+}
+
+/// Statistics returned by [`DwtProfiler::report`]
+#[derive(Clone, Copy)]
+pub struct ProfileReport {
+    pub name: &'static str,
+    pub count: u32,
+    pub min: ClockDuration,
+    pub max: ClockDuration,
+    pub mean: ClockDuration,
+}
+
+impl core::fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "{}: {} runs, min {} ns, mean {} ns, max {} ns",
+            self.name,
+            self.count,
+            self.min.as_nanos(),
+            self.mean.as_nanos(),
+            self.max.as_nanos()
+        )
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ProfileReport {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "{}: {} runs, min {} ns, mean {} ns, max {} ns",
+            self.name,
+            self.count,
+            self.min.as_nanos(),
+            self.mean.as_nanos(),
+            self.max.as_nanos()
+        );
+    }
 }
 
 /// A monotonic non-decreasing timer
@@ -276,3 +424,109 @@ impl Instant {
         DWT::cycle_count().wrapping_sub(self.now)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+    use fugit::RateExtU32;
+
+    fn duration(ticks: u32, clock: Hertz) -> ClockDuration {
+        ClockDuration { ticks, clock }
+    }
+
+    #[test]
+    fn duration_units() {
+        let d = duration(168_000_000, 168.MHz());
+        assert_eq!(d.as_millis(), 1_000);
+        assert_eq!(d.as_micros(), 1_000_000);
+        assert_eq!(d.as_nanos(), 1_000_000_000);
+        assert_eq!(d.as_secs_f32(), 1.0);
+        assert_eq!(d.to_duration(), NanosDurationU64::secs(1));
+
+        // Rounded down
+        let d = duration(1, 168.MHz());
+        assert_eq!(d.as_nanos(), 5);
+        assert_eq!(d.as_micros(), 0);
+    }
+
+    #[test]
+    fn longest_duration_does_not_overflow() {
+        let d = duration(u32::MAX, 168.MHz());
+        assert_eq!(d.as_millis(), 25_565);
+        assert_eq!(d.as_micros(), 25_565_281);
+        assert_eq!(d.as_nanos(), 25_565_281_517);
+    }
+
+    #[test]
+    fn wrapped_cycle_count() {
+        assert!(!cycle_count_wrapped(0, 0));
+        // The tick may come right after the start
+        assert!(!cycle_count_wrapped(1, 0));
+        assert!(!cycle_count_wrapped(25_566, 25_565));
+        assert!(cycle_count_wrapped(2, 0));
+        // 30 s at 168 MHz wraps once
+        assert!(cycle_count_wrapped(30_000, 30_000 - 25_565));
+    }
+
+    #[test]
+    fn profiler_statistics() {
+        let mut profiler = DwtProfiler::new("filter", 1_000.MHz());
+        assert!(profiler.report().is_none());
+        for cycles in [20, 10, 31] {
+            profiler.record(cycles);
+        }
+        let report = profiler.report().unwrap();
+        assert_eq!(report.count, 3);
+        assert_eq!(report.min.as_ticks(), 10);
+        assert_eq!(report.max.as_ticks(), 31);
+        // Rounded down
+        assert_eq!(report.mean.as_ticks(), 20);
+
+        profiler.reset();
+        assert_eq!(profiler.count(), 0);
+        assert!(profiler.report().is_none());
+    }
+
+    #[test]
+    fn profiler_mean_of_long_runs() {
+        let mut profiler = DwtProfiler::new("long", 1_000.MHz());
+        profiler.record(u32::MAX);
+        profiler.record(u32::MAX - 2);
+        assert_eq!(profiler.report().unwrap().mean.as_ticks(), u32::MAX - 1);
+    }
+
+    /// Formats into a fixed buffer
+    struct Buf {
+        bytes: [u8; 80],
+        len: usize,
+    }
+
+    impl Write for Buf {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            let end = self.len + s.len();
+            self.bytes
+                .get_mut(self.len..end)
+                .ok_or(core::fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn report_display() {
+        let mut profiler = DwtProfiler::new("filter", 1_000.MHz());
+        profiler.record(100);
+        profiler.record(300);
+        let mut buf = Buf {
+            bytes: [0; 80],
+            len: 0,
+        };
+        write!(buf, "{}", profiler.report().unwrap()).unwrap();
+        assert_eq!(
+            &buf.bytes[..buf.len],
+            b"filter: 2 runs, min 100 ns, mean 200 ns, max 300 ns"
+        );
+    }
+}
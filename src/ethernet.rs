@@ -0,0 +1,496 @@
+//! Ethernet MAC with an RMII PHY
+//!
+//! The MAC's DMA moves frames between the MAC and descriptor rings provided by the user in
+//! `static` memory, as the DMA keeps running when an [`Ethernet`] is leaked. Every ring entry holds a buffer for a whole frame, so frames
+//! are never split across descriptors.
+//!
+//! The PHY is managed over the station management (MDIO) bus with [`Mdio`]; [`Phy`] handles
+//! reset and auto-negotiation for PHYs using the standard IEEE 802.3 registers, such as the
+//! LAN8720 and LAN8742A. The negotiated speed and duplex mode must be applied to the MAC,
+//! [`Ethernet::poll_link`] does it when the link changes.
+//!
+//! With the `smoltcp` feature, [`Ethernet`] implements `smoltcp::phy::Device`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! static mut RX_RING: [RxRingEntry; 4] = [RxRingEntry::INIT; 4];
+//! static mut TX_RING: [TxRingEntry; 2] = [TxRingEntry::INIT; 2];
+//!
+//! let pins = RmiiPins {
+//!     ref_clk: gpioa.pa1.into_alternate(),
+//!     crs_dv: gpioa.pa7.into_alternate(),
+//!     rxd0: gpioc.pc4.into_alternate(),
+//!     rxd1: gpioc.pc5.into_alternate(),
+//!     tx_en: gpiog.pg11.into_alternate(),
+//!     txd0: gpiog.pg13.into_alternate(),
+//!     txd1: gpiob.pb13.into_alternate(),
+//!     mdio: gpioa.pa2.into_alternate(),
+//!     mdc: gpioc.pc1.into_alternate(),
+//! };
+//! let mut eth = Ethernet::new(
+//!     (dp.ETHERNET_MAC, dp.ETHERNET_DMA),
+//!     pins,
+//!     unsafe { &mut RX_RING },
+//!     unsafe { &mut TX_RING },
+//!     Config::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]),
+//!     &mut syscfg,
+//!     &clocks,
+//! )
+//! .unwrap();
+//!
+//! let phy = Phy::new(0);
+//! phy.reset(&mut eth.mdio());
+//! phy.start_autonegotiation(&mut eth.mdio());
+//! loop {
+//!     if let Some(change) = eth.poll_link(&phy) {
+//!         // ...
+//!     }
+//! }
+//! ```
+
+use crate::pac::{ETHERNET_DMA, ETHERNET_MAC, RCC};
+use crate::rcc::{Clocks, Enable, Reset};
+use crate::syscfg::SysCfg;
+
+mod desc;
+mod mdio;
+mod pins;
+#[cfg(feature = "smoltcp")]
+mod smoltcp_phy;
+
+pub use desc::{RxRingEntry, TxRingEntry, BUFFER_SIZE};
+pub use mdio::{Link, Mdio, Phy, Speed};
+pub use pins::{
+    PinCrsDv, PinMdc, PinMdio, PinRefClk, PinRxd0, PinRxd1, PinTxEn, PinTxd0, PinTxd1, Pins,
+    RmiiPins,
+};
+#[cfg(feature = "smoltcp")]
+pub use smoltcp_phy::{RxToken, TxToken};
+
+use desc::{RxRing, TxRing};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+const SYSCFG_PMC_MII_RMII_SEL: u32 = 1 << 23;
+
+const RCC_AHB1ENR_ETHMACTXEN: u32 = 1 << 26;
+const RCC_AHB1ENR_ETHMACRXEN: u32 = 1 << 27;
+
+const MACCR_RE: u32 = 1 << 2;
+const MACCR_TE: u32 = 1 << 3;
+const MACCR_IPCO: u32 = 1 << 10;
+const MACCR_DM: u32 = 1 << 11;
+const MACCR_FES: u32 = 1 << 14;
+
+const MACFFR_PM: u32 = 1 << 0;
+const MACFFR_PAM: u32 = 1 << 4;
+
+const DMABMR_SR: u32 = 1 << 0;
+const DMABMR_PBL_32: u32 = 32 << 8;
+const DMABMR_FB: u32 = 1 << 16;
+const DMABMR_RDP_32: u32 = 32 << 17;
+const DMABMR_USP: u32 = 1 << 23;
+const DMABMR_AAB: u32 = 1 << 25;
+
+const DMAOMR_SR: u32 = 1 << 1;
+const DMAOMR_ST: u32 = 1 << 13;
+const DMAOMR_FTF: u32 = 1 << 20;
+const DMAOMR_TSF: u32 = 1 << 21;
+const DMAOMR_RSF: u32 = 1 << 25;
+
+const DMASR_TS: u32 = 1 << 0;
+const DMASR_TBUS: u32 = 1 << 2;
+const DMASR_RS: u32 = 1 << 6;
+const DMASR_RBUS: u32 = 1 << 7;
+const DMASR_AIS: u32 = 1 << 15;
+const DMASR_RPS_MASK: u32 = 0b111 << 17;
+const DMASR_TPS_MASK: u32 = 0b111 << 20;
+/// All write-1-to-clear status bits
+const DMASR_CLEAR_ALL: u32 = 0x0001_e7ff;
+
+const DMAIER_TIE: u32 = 1 << 0;
+const DMAIER_RIE: u32 = 1 << 6;
+const DMAIER_NISE: u32 = 1 << 16;
+
+/// Ethernet error
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// A ring is empty or HCLK is below the 25 MHz needed by the MAC
+    InvalidConfig,
+    /// A frame was received with a CRC, length or checksum error and was dropped
+    Frame,
+    /// A received frame didn't fit in a ring buffer and was dropped
+    Truncated,
+    /// The frame to send doesn't fit in a ring buffer
+    FrameTooLong,
+}
+
+/// Ethernet configuration
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    mac_address: [u8; 6],
+    checksum_offload: bool,
+    promiscuous: bool,
+}
+
+impl Config {
+    /// Configuration for the station address `mac_address`, with checksum offload
+    pub const fn new(mac_address: [u8; 6]) -> Self {
+        Self {
+            mac_address,
+            checksum_offload: true,
+            promiscuous: false,
+        }
+    }
+
+    /// Checks IPv4 header and TCP/UDP/ICMP checksums of received frames, dropping frames with
+    /// errors, and inserts them in sent frames
+    pub fn checksum_offload(mut self, checksum_offload: bool) -> Self {
+        self.checksum_offload = checksum_offload;
+        self
+    }
+
+    /// Receives all frames, whatever their destination address
+    pub fn promiscuous(mut self, promiscuous: bool) -> Self {
+        self.promiscuous = promiscuous;
+        self
+    }
+}
+
+/// Interrupt events
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// A frame was received
+    Rx,
+    /// A frame was sent
+    Tx,
+}
+
+/// Events that caused an interrupt, returned by [`Ethernet::interrupt_handler`]
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InterruptReason {
+    /// A frame was received
+    pub rx: bool,
+    /// A frame was sent
+    pub tx: bool,
+    /// The DMA reported an abnormal condition, such as a bus error
+    pub error: bool,
+}
+
+/// Link state change, returned by [`Ethernet::poll_link`]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkChange {
+    /// The link went up, the MAC now uses its speed and duplex mode
+    Up(Link),
+    /// The link went down
+    Down,
+}
+
+/// Ethernet MAC and DMA
+pub struct Ethernet<PINS> {
+    mac: ETHERNET_MAC,
+    dma: ETHERNET_DMA,
+    pins: PINS,
+    rx_ring: RxRing<'static>,
+    tx_ring: TxRing<'static>,
+    clock_range: u32,
+    checksum_offload: bool,
+    link: Option<Link>,
+}
+
+impl<PINS: Pins> Ethernet<PINS> {
+    /// Selects RMII, initializes the MAC and DMA and starts reception and transmission
+    ///
+    /// The PHY must provide the 50 MHz reference clock, the DMA reset doesn't complete
+    /// without it.
+    ///
+    /// The MAC starts in 100 Mbit/s full-duplex mode, use [`poll_link`](Self::poll_link) or
+    /// [`set_link`](Self::set_link) to match the negotiated link.
+    pub fn new(
+        (mac, dma): (ETHERNET_MAC, ETHERNET_DMA),
+        pins: PINS,
+        rx_ring: &'static mut [RxRingEntry],
+        tx_ring: &'static mut [TxRingEntry],
+        config: Config,
+        syscfg: &mut SysCfg,
+        clocks: &Clocks,
+    ) -> Result<Self, Error> {
+        let clock_range = mdc_clock_range(clocks.hclk().raw()).ok_or(Error::InvalidConfig)?;
+        if rx_ring.is_empty() || tx_ring.is_empty() {
+            return Err(Error::InvalidConfig);
+        }
+
+        // RMII must be selected before the MAC clocks are enabled
+        syscfg
+            .pmc
+            .modify(|r, w| unsafe { w.bits(r.bits() | SYSCFG_PMC_MII_RMII_SEL) });
+
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            ETHERNET_MAC::enable(rcc);
+            rcc.ahb1enr
+                .modify(|r, w| w.bits(r.bits() | RCC_AHB1ENR_ETHMACTXEN | RCC_AHB1ENR_ETHMACRXEN));
+            ETHERNET_MAC::reset(rcc);
+        }
+
+        // Reset the DMA and MAC registers
+        dma.dmabmr
+            .modify(|r, w| unsafe { w.bits(r.bits() | DMABMR_SR) });
+        while dma.dmabmr.read().bits() & DMABMR_SR != 0 {}
+
+        let mut maccr = MACCR_FES | MACCR_DM;
+        if config.checksum_offload {
+            maccr |= MACCR_IPCO;
+        }
+        let mut macffr = MACFFR_PAM;
+        if config.promiscuous {
+            macffr |= MACFFR_PM;
+        }
+        let [a0, a1, a2, a3, a4, a5] = config.mac_address;
+        unsafe {
+            mac.maccr.write(|w| w.bits(maccr));
+            mac.macffr.write(|w| w.bits(macffr));
+            mac.maca0hr
+                .write(|w| w.bits(u32::from(u16::from_le_bytes([a4, a5]))));
+            mac.maca0lr
+                .write(|w| w.bits(u32::from_le_bytes([a0, a1, a2, a3])));
+
+            dma.dmabmr.write(|w| {
+                w.bits(DMABMR_AAB | DMABMR_USP | DMABMR_RDP_32 | DMABMR_PBL_32 | DMABMR_FB)
+            });
+        }
+
+        let (rx_ring, rx_address) = RxRing::new(rx_ring);
+        let (tx_ring, tx_address) = TxRing::new(tx_ring, config.checksum_offload);
+        unsafe {
+            dma.dmardlar.write(|w| w.bits(rx_address));
+            dma.dmatdlar.write(|w| w.bits(tx_address));
+
+            // Store and forward is needed for checksum offload
+            dma.dmaomr.write(|w| w.bits(DMAOMR_RSF | DMAOMR_TSF));
+            dma.dmaomr.modify(|r, w| w.bits(r.bits() | DMAOMR_FTF));
+            while dma.dmaomr.read().bits() & DMAOMR_FTF != 0 {}
+            dma.dmaomr
+                .modify(|r, w| w.bits(r.bits() | DMAOMR_ST | DMAOMR_SR));
+
+            mac.maccr
+                .modify(|r, w| w.bits(r.bits() | MACCR_TE | MACCR_RE));
+        }
+
+        Ok(Self {
+            mac,
+            dma,
+            pins,
+            rx_ring,
+            tx_ring,
+            clock_range,
+            checksum_offload: config.checksum_offload,
+            link: None,
+        })
+    }
+
+    /// Access to the PHY registers
+    pub fn mdio(&mut self) -> Mdio<'_> {
+        Mdio {
+            mac: &self.mac,
+            clock_range: self.clock_range,
+        }
+    }
+
+    /// Sets the MAC speed and duplex mode to match the link negotiated by the PHY
+    pub fn set_link(&mut self, link: Link) {
+        let mut bits = 0;
+        if link.speed == Speed::Mbps100 {
+            bits |= MACCR_FES;
+        }
+        if link.full_duplex {
+            bits |= MACCR_DM;
+        }
+        self.mac
+            .maccr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(MACCR_FES | MACCR_DM) | bits) });
+    }
+
+    /// Reads the link state from `phy`, updating the MAC when the link comes up
+    ///
+    /// Returns `None` if the link didn't change since the last call.
+    pub fn poll_link(&mut self, phy: &Phy) -> Option<LinkChange> {
+        let link = phy.link(&mut self.mdio());
+        if link == self.link {
+            return None;
+        }
+        self.link = link;
+        Some(match link {
+            Some(link) => {
+                self.set_link(link);
+                LinkChange::Up(link)
+            }
+            None => LinkChange::Down,
+        })
+    }
+
+    /// Last link state seen by [`poll_link`](Self::poll_link)
+    pub fn link(&self) -> Option<Link> {
+        self.link
+    }
+
+    /// Whether checksums are checked and inserted by the MAC
+    pub fn checksum_offload(&self) -> bool {
+        self.checksum_offload
+    }
+
+    /// Returns `true` if a received frame is waiting in the receive ring
+    pub fn rx_available(&self) -> bool {
+        self.rx_ring.is_available()
+    }
+
+    /// Returns `true` if a transmit ring entry is free
+    pub fn tx_available(&self) -> bool {
+        self.tx_ring.is_available()
+    }
+
+    /// Passes the next received frame, without its CRC, to `f`
+    pub fn recv<R>(&mut self, f: impl FnOnce(&mut [u8]) -> R) -> nb::Result<R, Error> {
+        let result = self.rx_ring.receive(f);
+        resume_rx(&self.dma);
+        result
+    }
+
+    /// Lets `f` fill a frame of `len` bytes, without CRC, and sends it
+    pub fn send<R>(&mut self, len: usize, f: impl FnOnce(&mut [u8]) -> R) -> nb::Result<R, Error> {
+        let result = self.tx_ring.send(len, f)?;
+        resume_tx(&self.dma);
+        Ok(result)
+    }
+
+    /// Enables the interrupt for `event`
+    pub fn listen(&mut self, event: Event) {
+        let bit = match event {
+            Event::Rx => DMAIER_RIE,
+            Event::Tx => DMAIER_TIE,
+        };
+        self.dma
+            .dmaier
+            .modify(|r, w| unsafe { w.bits(r.bits() | bit | DMAIER_NISE) });
+    }
+
+    /// Disables the interrupt for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        let bit = match event {
+            Event::Rx => DMAIER_RIE,
+            Event::Tx => DMAIER_TIE,
+        };
+        self.dma.dmaier.modify(|r, w| unsafe {
+            let bits = r.bits() & !bit;
+            if bits & (DMAIER_RIE | DMAIER_TIE) == 0 {
+                w.bits(bits & !DMAIER_NISE)
+            } else {
+                w.bits(bits)
+            }
+        });
+    }
+
+    /// Clears the DMA status flags, to be called from the `ETH` interrupt handler
+    ///
+    /// Frames are still handled with [`recv`](Self::recv) and [`send`](Self::send).
+    pub fn interrupt_handler() -> InterruptReason {
+        // NOTE(unsafe) atomic read and write-1-to-clear of status flags
+        let dma = unsafe { &*ETHERNET_DMA::ptr() };
+        let status = dma.dmasr.read().bits();
+        unsafe { dma.dmasr.write(|w| w.bits(status & DMASR_CLEAR_ALL)) };
+        InterruptReason {
+            rx: status & DMASR_RS != 0,
+            tx: status & DMASR_TS != 0,
+            error: status & DMASR_AIS != 0,
+        }
+    }
+
+    /// Stops the MAC and DMA and releases the peripherals and pins
+    pub fn release(self) -> ((ETHERNET_MAC, ETHERNET_DMA), PINS) {
+        unsafe {
+            self.dma
+                .dmaomr
+                .modify(|r, w| w.bits(r.bits() & !(DMAOMR_ST | DMAOMR_SR)));
+            self.mac
+                .maccr
+                .modify(|r, w| w.bits(r.bits() & !(MACCR_TE | MACCR_RE)));
+            self.dma.dmaier.write(|w| w.bits(0));
+        }
+        // Transmission and reception stop after the current frame
+        while self.dma.dmasr.read().bits() & (DMASR_TPS_MASK | DMASR_RPS_MASK) != 0 {}
+        ((self.mac, self.dma), self.pins)
+    }
+}
+
+/// MACMIIAR clock range (CR) value keeping MDC below 2.5 MHz, `None` below the 25 MHz HCLK
+/// needed by the MAC
+fn mdc_clock_range(hclk: u32) -> Option<u32> {
+    match hclk {
+        25_000_000..=34_999_999 => Some(2),
+        35_000_000..=59_999_999 => Some(3),
+        60_000_000..=99_999_999 => Some(0),
+        100_000_000..=149_999_999 => Some(1),
+        150_000_000..=u32::MAX => Some(4),
+        _ => None,
+    }
+}
+
+/// Restarts reception if the DMA suspended it for lack of free descriptors
+fn resume_rx(dma: &ETHERNET_DMA) {
+    if dma.dmasr.read().bits() & DMASR_RBUS != 0 {
+        unsafe {
+            dma.dmasr.write(|w| w.bits(DMASR_RBUS));
+            dma.dmarpdr.write(|w| w.bits(0));
+        }
+    }
+}
+
+/// Makes the DMA look for the frames to send
+fn resume_tx(dma: &ETHERNET_DMA) {
+    unsafe {
+        dma.dmasr.write(|w| w.bits(DMASR_TBUS));
+        dma.dmatpdr.write(|w| w.bits(0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mdc_clock_range;
+
+    #[test]
+    fn mdc_stays_below_2_5_mhz() {
+        let dividers = [42, 62, 16, 26, 102];
+        for hclk in [
+            25_000_000,
+            34_999_999,
+            35_000_000,
+            59_999_999,
+            60_000_000,
+            99_999_999,
+            100_000_000,
+            149_999_999,
+            150_000_000,
+            180_000_000,
+        ] {
+            let cr = mdc_clock_range(hclk).unwrap();
+            assert!(hclk / dividers[cr as usize] <= 2_500_000, "{} Hz", hclk);
+        }
+    }
+
+    #[test]
+    fn mdc_rejects_slow_hclk() {
+        assert_eq!(mdc_clock_range(0), None);
+        assert_eq!(mdc_clock_range(24_999_999), None);
+        assert_eq!(mdc_clock_range(168_000_000), Some(4));
+    }
+}
@@ -0,0 +1,194 @@
+//! DMA descriptor rings
+//!
+//! Each ring entry holds a normal (16-byte) descriptor and a buffer for a whole frame.
+//! Descriptors are chained to the next entry, the last one to the first.
+
+use core::sync::atomic::{fence, Ordering};
+use core::{mem, ptr};
+
+use super::Error;
+
+/// Size of each frame buffer, enough for a VLAN-tagged frame with its CRC
+pub const BUFFER_SIZE: usize = 1524;
+
+const DES0_OWN: u32 = 1 << 31;
+
+const TDES0_IC: u32 = 1 << 30;
+const TDES0_LS: u32 = 1 << 29;
+const TDES0_FS: u32 = 1 << 28;
+const TDES0_CIC_FULL: u32 = 0b11 << 22;
+const TDES0_TCH: u32 = 1 << 20;
+
+const RDES0_ES: u32 = 1 << 15;
+const RDES0_FS: u32 = 1 << 9;
+const RDES0_LS: u32 = 1 << 8;
+const RDES1_RCH: u32 = 1 << 14;
+
+/// Frame check sequence, included in the received length
+const CRC_SIZE: usize = 4;
+
+#[repr(C, align(4))]
+struct Descriptor([u32; 4]);
+
+impl Descriptor {
+    const INIT: Self = Self([0; 4]);
+
+    fn read(&self, i: usize) -> u32 {
+        unsafe { ptr::read_volatile(&self.0[i]) }
+    }
+
+    fn write(&mut self, i: usize, value: u32) {
+        unsafe { ptr::write_volatile(&mut self.0[i], value) }
+    }
+
+    fn is_owned_by_dma(&self) -> bool {
+        self.read(0) & DES0_OWN != 0
+    }
+}
+
+/// Receive ring entry
+#[repr(C, align(4))]
+pub struct RxRingEntry {
+    descriptor: Descriptor,
+    buffer: [u8; BUFFER_SIZE],
+}
+
+impl RxRingEntry {
+    /// Initial value, for use in `static` arrays
+    pub const INIT: Self = Self {
+        descriptor: Descriptor::INIT,
+        buffer: [0; BUFFER_SIZE],
+    };
+}
+
+/// Transmit ring entry
+#[repr(C, align(4))]
+pub struct TxRingEntry {
+    descriptor: Descriptor,
+    buffer: [u8; BUFFER_SIZE],
+}
+
+impl TxRingEntry {
+    /// Initial value, for use in `static` arrays
+    pub const INIT: Self = Self {
+        descriptor: Descriptor::INIT,
+        buffer: [0; BUFFER_SIZE],
+    };
+}
+
+pub(super) struct RxRing<'a> {
+    entries: &'a mut [RxRingEntry],
+    next: usize,
+}
+
+impl<'a> RxRing<'a> {
+    /// Links the descriptors and gives all of them to the DMA, returns the ring address
+    pub(super) fn new(entries: &'a mut [RxRingEntry]) -> (Self, u32) {
+        // The descriptor is the first field of an entry
+        let address = entries.as_ptr() as u32;
+        let len = entries.len();
+        for (i, entry) in entries.iter_mut().enumerate() {
+            let next = address + (((i + 1) % len) * mem::size_of::<RxRingEntry>()) as u32;
+            let buffer = entry.buffer.as_ptr() as u32;
+            entry.descriptor.write(1, RDES1_RCH | BUFFER_SIZE as u32);
+            entry.descriptor.write(2, buffer);
+            entry.descriptor.write(3, next);
+            entry.descriptor.write(0, DES0_OWN);
+        }
+        fence(Ordering::Release);
+        (Self { entries, next: 0 }, address)
+    }
+
+    pub(super) fn is_available(&self) -> bool {
+        !self.entries[self.next].descriptor.is_owned_by_dma()
+    }
+
+    /// Passes the next received frame, without its CRC, to `f` and gives the entry back to
+    /// the DMA
+    pub(super) fn receive<R>(&mut self, f: impl FnOnce(&mut [u8]) -> R) -> nb::Result<R, Error> {
+        if !self.is_available() {
+            return Err(nb::Error::WouldBlock);
+        }
+        fence(Ordering::Acquire);
+
+        let entry = &mut self.entries[self.next];
+        let status = entry.descriptor.read(0);
+        let result = if status & RDES0_ES != 0 {
+            Err(nb::Error::Other(Error::Frame))
+        } else if status & (RDES0_FS | RDES0_LS) != RDES0_FS | RDES0_LS {
+            // Frames larger than a buffer are dropped
+            Err(nb::Error::Other(Error::Truncated))
+        } else {
+            let len = ((status >> 16) & 0x3fff) as usize;
+            let len = len.saturating_sub(CRC_SIZE).min(BUFFER_SIZE);
+            Ok(f(&mut entry.buffer[..len]))
+        };
+
+        fence(Ordering::Release);
+        entry.descriptor.write(0, DES0_OWN);
+        self.next = (self.next + 1) % self.entries.len();
+        result
+    }
+}
+
+pub(super) struct TxRing<'a> {
+    entries: &'a mut [TxRingEntry],
+    next: usize,
+    checksum_insertion: bool,
+}
+
+impl<'a> TxRing<'a> {
+    /// Links the descriptors, returns the ring address
+    pub(super) fn new(entries: &'a mut [TxRingEntry], checksum_insertion: bool) -> (Self, u32) {
+        // The descriptor is the first field of an entry
+        let address = entries.as_ptr() as u32;
+        let len = entries.len();
+        for (i, entry) in entries.iter_mut().enumerate() {
+            let next = address + (((i + 1) % len) * mem::size_of::<TxRingEntry>()) as u32;
+            let buffer = entry.buffer.as_ptr() as u32;
+            entry.descriptor.write(0, TDES0_TCH);
+            entry.descriptor.write(1, 0);
+            entry.descriptor.write(2, buffer);
+            entry.descriptor.write(3, next);
+        }
+        fence(Ordering::Release);
+        let ring = Self {
+            entries,
+            next: 0,
+            checksum_insertion,
+        };
+        (ring, address)
+    }
+
+    pub(super) fn is_available(&self) -> bool {
+        !self.entries[self.next].descriptor.is_owned_by_dma()
+    }
+
+    /// Lets `f` fill a frame of `len` bytes, without CRC, and gives it to the DMA
+    pub(super) fn send<R>(
+        &mut self,
+        len: usize,
+        f: impl FnOnce(&mut [u8]) -> R,
+    ) -> nb::Result<R, Error> {
+        if len > BUFFER_SIZE - CRC_SIZE {
+            return Err(nb::Error::Other(Error::FrameTooLong));
+        }
+        if !self.is_available() {
+            return Err(nb::Error::WouldBlock);
+        }
+        fence(Ordering::Acquire);
+
+        let entry = &mut self.entries[self.next];
+        let result = f(&mut entry.buffer[..len]);
+
+        let mut tdes0 = DES0_OWN | TDES0_IC | TDES0_LS | TDES0_FS | TDES0_TCH;
+        if self.checksum_insertion {
+            tdes0 |= TDES0_CIC_FULL;
+        }
+        entry.descriptor.write(1, len as u32);
+        fence(Ordering::Release);
+        entry.descriptor.write(0, tdes0);
+        self.next = (self.next + 1) % self.entries.len();
+        Ok(result)
+    }
+}
@@ -0,0 +1,184 @@
+//! Station management (SMI/MDIO) and PHY link handling
+
+use crate::pac::ETHERNET_MAC;
+
+const MACMIIAR_MB: u32 = 1 << 0;
+const MACMIIAR_MW: u32 = 1 << 1;
+
+// IEEE 802.3 clause 22 registers
+const BCR: u8 = 0;
+const BSR: u8 = 1;
+const ANAR: u8 = 4;
+const ANLPAR: u8 = 5;
+
+const BCR_RESET: u16 = 1 << 15;
+const BCR_AN_ENABLE: u16 = 1 << 12;
+const BCR_AN_RESTART: u16 = 1 << 9;
+const BSR_AN_COMPLETE: u16 = 1 << 5;
+const BSR_LINK: u16 = 1 << 2;
+/// 10BASE-T and 100BASE-TX, half and full duplex, IEEE 802.3 selector
+const ANAR_ALL: u16 = 0x01e1;
+const AN_100_FULL: u16 = 1 << 8;
+const AN_100_HALF: u16 = 1 << 7;
+const AN_10_FULL: u16 = 1 << 6;
+
+/// Access to PHY registers over the MDIO/MDC bus
+pub struct Mdio<'a> {
+    pub(super) mac: &'a ETHERNET_MAC,
+    pub(super) clock_range: u32,
+}
+
+impl Mdio<'_> {
+    /// Reads register `reg` of the PHY at address `phy`
+    pub fn read(&mut self, phy: u8, reg: u8) -> u16 {
+        self.wait();
+        unsafe { self.mac.macmiiar.write(|w| w.bits(self.address(phy, reg))) };
+        self.wait();
+        self.mac.macmiidr.read().bits() as u16
+    }
+
+    /// Writes `value` to register `reg` of the PHY at address `phy`
+    pub fn write(&mut self, phy: u8, reg: u8, value: u16) {
+        self.wait();
+        unsafe {
+            self.mac.macmiidr.write(|w| w.bits(value.into()));
+            self.mac
+                .macmiiar
+                .write(|w| w.bits(self.address(phy, reg) | MACMIIAR_MW));
+        }
+        self.wait();
+    }
+
+    fn address(&self, phy: u8, reg: u8) -> u32 {
+        miiar_bits(phy, reg, self.clock_range)
+    }
+
+    fn wait(&self) {
+        while self.mac.macmiiar.read().bits() & MACMIIAR_MB != 0 {}
+    }
+}
+
+/// MACMIIAR value starting an access to register `reg` of the PHY at address `phy`
+fn miiar_bits(phy: u8, reg: u8, clock_range: u32) -> u32 {
+    u32::from(phy & 0x1f) << 11 | u32::from(reg & 0x1f) << 6 | clock_range << 2 | MACMIIAR_MB
+}
+
+/// Best mode advertised by both ends, from the ANAR and ANLPAR registers
+fn negotiated_link(anar: u16, anlpar: u16) -> Link {
+    let common = anar & anlpar;
+    let (speed, full_duplex) = if common & AN_100_FULL != 0 {
+        (Speed::Mbps100, true)
+    } else if common & AN_100_HALF != 0 {
+        (Speed::Mbps100, false)
+    } else if common & AN_10_FULL != 0 {
+        (Speed::Mbps10, true)
+    } else {
+        (Speed::Mbps10, false)
+    };
+    Link { speed, full_duplex }
+}
+
+/// Link speed
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Speed {
+    /// 10 Mbit/s
+    Mbps10,
+    /// 100 Mbit/s
+    Mbps100,
+}
+
+/// Negotiated link parameters
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Link {
+    pub speed: Speed,
+    pub full_duplex: bool,
+}
+
+/// A PHY using the standard IEEE 802.3 registers, such as the LAN8720 or LAN8742A
+pub struct Phy {
+    address: u8,
+}
+
+impl Phy {
+    /// PHY at MDIO address `address`, set by its strap pins
+    pub const fn new(address: u8) -> Self {
+        Self { address }
+    }
+
+    /// MDIO address
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Resets the PHY and waits for the end of the reset
+    pub fn reset(&self, mdio: &mut Mdio) {
+        mdio.write(self.address, BCR, BCR_RESET);
+        while mdio.read(self.address, BCR) & BCR_RESET != 0 {}
+    }
+
+    /// Advertises all 10/100 modes and (re)starts auto-negotiation
+    pub fn start_autonegotiation(&self, mdio: &mut Mdio) {
+        mdio.write(self.address, ANAR, ANAR_ALL);
+        mdio.write(self.address, BCR, BCR_AN_ENABLE | BCR_AN_RESTART);
+    }
+
+    /// Returns the negotiated link, or `None` if the link is down or auto-negotiation isn't
+    /// complete
+    pub fn link(&self, mdio: &mut Mdio) -> Option<Link> {
+        // The link status bit latches low, read it twice to get the current state
+        mdio.read(self.address, BSR);
+        let bsr = mdio.read(self.address, BSR);
+        if bsr & BSR_LINK == 0 || bsr & BSR_AN_COMPLETE == 0 {
+            return None;
+        }
+        let anar = mdio.read(self.address, ANAR);
+        let anlpar = mdio.read(self.address, ANLPAR);
+        Some(negotiated_link(anar, anlpar))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn miiar_fields() {
+        // PHY 1, BSR, HCLK/102
+        assert_eq!(miiar_bits(1, BSR, 4), 1 << 11 | 1 << 6 | 4 << 2 | 1);
+        assert_eq!(miiar_bits(31, 31, 0), 0b11111 << 11 | 0b11111 << 6 | 1);
+        // Out of range addresses don't spill into other fields
+        assert_eq!(miiar_bits(32, 32, 0), MACMIIAR_MB);
+    }
+
+    #[test]
+    fn link_is_best_common_mode() {
+        let full_100 = Link {
+            speed: Speed::Mbps100,
+            full_duplex: true,
+        };
+        assert_eq!(negotiated_link(ANAR_ALL, ANAR_ALL), full_100);
+        assert_eq!(
+            negotiated_link(ANAR_ALL, 0x0001 | AN_100_HALF | AN_10_FULL),
+            Link {
+                speed: Speed::Mbps100,
+                full_duplex: false
+            }
+        );
+        assert_eq!(
+            negotiated_link(0x0001 | AN_10_FULL | AN_100_HALF, ANAR_ALL & !AN_100_HALF),
+            Link {
+                speed: Speed::Mbps10,
+                full_duplex: true
+            }
+        );
+        assert_eq!(
+            negotiated_link(ANAR_ALL, 0x0001),
+            Link {
+                speed: Speed::Mbps10,
+                full_duplex: false
+            }
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! RMII and station management pins
+
+use super::sealed::Sealed;
+
+/// A pin that can be used for `RMII_REF_CLK`
+pub trait PinRefClk: Sealed {}
+/// A pin that can be used for `RMII_CRS_DV`
+pub trait PinCrsDv: Sealed {}
+/// A pin that can be used for `RMII_RXD0`
+pub trait PinRxd0: Sealed {}
+/// A pin that can be used for `RMII_RXD1`
+pub trait PinRxd1: Sealed {}
+/// A pin that can be used for `RMII_TX_EN`
+pub trait PinTxEn: Sealed {}
+/// A pin that can be used for `RMII_TXD0`
+pub trait PinTxd0: Sealed {}
+/// A pin that can be used for `RMII_TXD1`
+pub trait PinTxd1: Sealed {}
+/// A pin that can be used for `MDIO`
+pub trait PinMdio: Sealed {}
+/// A pin that can be used for `MDC`
+pub trait PinMdc: Sealed {}
+
+/// Pins connecting the MAC to an RMII PHY
+pub struct RmiiPins<REFCLK, CRSDV, RXD0, RXD1, TXEN, TXD0, TXD1, MDIO, MDC> {
+    /// 50 MHz reference clock, usually provided by the PHY
+    pub ref_clk: REFCLK,
+    /// Carrier sense / receive data valid
+    pub crs_dv: CRSDV,
+    pub rxd0: RXD0,
+    pub rxd1: RXD1,
+    /// Transmit enable
+    pub tx_en: TXEN,
+    pub txd0: TXD0,
+    pub txd1: TXD1,
+    /// Station management data
+    pub mdio: MDIO,
+    /// Station management clock
+    pub mdc: MDC,
+}
+
+/// A set of pins that can be used with [`Ethernet`](super::Ethernet)
+pub trait Pins: Sealed {}
+
+impl<REFCLK, CRSDV, RXD0, RXD1, TXEN, TXD0, TXD1, MDIO, MDC> Sealed
+    for RmiiPins<REFCLK, CRSDV, RXD0, RXD1, TXEN, TXD0, TXD1, MDIO, MDC>
+where
+    REFCLK: PinRefClk,
+    CRSDV: PinCrsDv,
+    RXD0: PinRxd0,
+    RXD1: PinRxd1,
+    TXEN: PinTxEn,
+    TXD0: PinTxd0,
+    TXD1: PinTxd1,
+    MDIO: PinMdio,
+    MDC: PinMdc,
+{
+}
+
+impl<REFCLK, CRSDV, RXD0, RXD1, TXEN, TXD0, TXD1, MDIO, MDC> Pins
+    for RmiiPins<REFCLK, CRSDV, RXD0, RXD1, TXEN, TXD0, TXD1, MDIO, MDC>
+where
+    REFCLK: PinRefClk,
+    CRSDV: PinCrsDv,
+    RXD0: PinRxd0,
+    RXD1: PinRxd1,
+    TXEN: PinTxEn,
+    TXD0: PinTxd0,
+    TXD1: PinTxd1,
+    MDIO: PinMdio,
+    MDC: PinMdc,
+{
+}
+
+mod common_pins {
+    use super::{
+        PinCrsDv, PinMdc, PinMdio, PinRefClk, PinRxd0, PinRxd1, PinTxEn, PinTxd0, PinTxd1, Sealed,
+    };
+    use crate::gpio::{
+        Pin, AF11, PA1, PA2, PA7, PB11, PB12, PB13, PC1, PC4, PC5, PG11, PG13, PG14,
+    };
+
+    impl<const P: char, const N: u8, MODE> Sealed for Pin<P, N, MODE> {}
+
+    // All Ethernet pins use AF11
+
+    impl PinRefClk for PA1<AF11> {}
+    impl PinMdio for PA2<AF11> {}
+    impl PinCrsDv for PA7<AF11> {}
+    impl PinMdc for PC1<AF11> {}
+    impl PinRxd0 for PC4<AF11> {}
+    impl PinRxd1 for PC5<AF11> {}
+    impl PinTxEn for PB11<AF11> {}
+    impl PinTxEn for PG11<AF11> {}
+    impl PinTxd0 for PB12<AF11> {}
+    impl PinTxd0 for PG13<AF11> {}
+    impl PinTxd1 for PB13<AF11> {}
+    impl PinTxd1 for PG14<AF11> {}
+}
@@ -0,0 +1,91 @@
+//! `smoltcp::phy::Device` implementation
+
+use smoltcp::phy::{ChecksumCapabilities, Device, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+
+use super::desc::{RxRing, TxRing};
+use super::{resume_rx, resume_tx, Ethernet, Pins};
+use crate::pac::ETHERNET_DMA;
+
+/// Received frame, borrowed from the receive ring
+pub struct RxToken<'a> {
+    ring: &'a mut RxRing<'static>,
+    dma: &'a ETHERNET_DMA,
+}
+
+impl smoltcp::phy::RxToken for RxToken<'_> {
+    fn consume<R, F>(self, _timestamp: Instant, f: F) -> smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
+    {
+        let result = self.ring.receive(f);
+        resume_rx(self.dma);
+        // Bad frames are only detected here, smoltcp drops them as it would malformed ones
+        result.unwrap_or(Err(smoltcp::Error::Malformed))
+    }
+}
+
+/// Free entry of the transmit ring
+pub struct TxToken<'a> {
+    ring: &'a mut TxRing<'static>,
+    dma: &'a ETHERNET_DMA,
+}
+
+impl smoltcp::phy::TxToken for TxToken<'_> {
+    fn consume<R, F>(self, _timestamp: Instant, len: usize, f: F) -> smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
+    {
+        match self.ring.send(len, f) {
+            Ok(result) => {
+                resume_tx(self.dma);
+                result
+            }
+            Err(nb::Error::WouldBlock) => Err(smoltcp::Error::Exhausted),
+            Err(nb::Error::Other(_)) => Err(smoltcp::Error::Truncated),
+        }
+    }
+}
+
+impl<'a, PINS: Pins + 'a> Device<'a> for Ethernet<PINS> {
+    type RxToken = RxToken<'a>;
+    type TxToken = TxToken<'a>;
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        if !self.rx_ring.is_available() || !self.tx_ring.is_available() {
+            return None;
+        }
+        let rx = RxToken {
+            ring: &mut self.rx_ring,
+            dma: &self.dma,
+        };
+        let tx = TxToken {
+            ring: &mut self.tx_ring,
+            dma: &self.dma,
+        };
+        Some((rx, tx))
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        if !self.tx_ring.is_available() {
+            return None;
+        }
+        Some(TxToken {
+            ring: &mut self.tx_ring,
+            dma: &self.dma,
+        })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ethernet;
+        // Ethernet header and 1500 bytes of payload
+        caps.max_transmission_unit = 1514;
+        caps.max_burst_size = Some(1);
+        if self.checksum_offload {
+            // Checked and inserted by the MAC
+            caps.checksum = ChecksumCapabilities::ignored();
+        }
+        caps
+    }
+}
@@ -4,14 +4,24 @@ use crate::pac::FLASH;
 use crate::signature::FlashSize;
 use core::{ptr, slice};
 
+mod option_bytes;
+pub use option_bytes::{BrownoutLevel, OptionBytes, ReadProtection, UnlockedOptionBytes};
+
 /// Flash erase/program error
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     ProgrammingSequence,
     ProgrammingParallelism,
     ProgrammingAlignment,
     WriteProtection,
     Operation,
+    /// Read protection level 2 requested from `UnlockedOptionBytes::apply`
+    ReadProtectionLevel2,
+    /// Option bytes read back differ from the programmed ones
+    Verify,
+    /// Sector number not present in the current flash layout
+    InvalidSector,
 }
 
 impl Error {
@@ -48,10 +58,93 @@ pub trait FlashExt {
     /// Unlock flash for erasing/programming until this method's
     /// result is dropped
     fn unlocked(&mut self) -> UnlockedFlash;
-    // Returns true if flash is in dual bank organization
+    /// Returns true if flash is in dual bank organization
+    ///
+    /// 2 MB devices are always dual bank, 1 MB F42x/F43x/F469/F479 devices
+    /// are when `OptionBytes::dual_bank_1m` is set.
     fn dual_bank(&self) -> bool;
     /// Returns flash memory sector of a given offset. Returns none if offset is out of range.
     fn sector(&self, offset: usize) -> Option<FlashSector>;
+    /// Returns current option byte values
+    fn option_bytes(&self) -> OptionBytes;
+    /// Unlock option bytes for programming until this method's
+    /// result is dropped
+    fn unlocked_options(&mut self) -> UnlockedOptionBytes;
+
+    /// Enables or disables the prefetch buffer, for the `supply` voltage range
+    ///
+    /// The prefetch is kept disabled on revision A of STM32F405/F407/F415/F417, where it is
+    /// not supported, and with a supply below 2.1 V.
+    fn enable_prefetch(&mut self, enable: bool, supply: VoltageRange) {
+        set_prefetch(enable, supply);
+    }
+
+    /// Enables or disables the instruction cache, which is reset before being enabled
+    fn enable_icache(&mut self, enable: bool) {
+        set_icache(enable);
+    }
+
+    /// Enables or disables the data cache, which is reset before being enabled
+    ///
+    /// Erasing and programming with [`UnlockedFlash`] resets the data cache, so it returns
+    /// the new contents.
+    fn enable_dcache(&mut self, enable: bool) {
+        set_dcache(enable);
+    }
+}
+
+/// Revision A of STM32F405/F407/F415/F417 does not support the prefetch
+///
+/// Its `DBGMCU_IDCODE` reads 0 unless a debugger is connected, which later revisions fix.
+fn prefetch_supported(idcode: u32) -> bool {
+    let f40x = cfg!(any(
+        feature = "stm32f405",
+        feature = "stm32f407",
+        feature = "stm32f415",
+        feature = "stm32f417"
+    ));
+    let rev_a = idcode == 0 || (idcode & 0xfff == 0x413 && idcode >> 16 == 0x1000);
+    !(f40x && rev_a)
+}
+
+pub(crate) fn set_prefetch(enable: bool, supply: VoltageRange) {
+    // NOTE(unsafe) atomic read of DBGMCU_IDCODE with no side effects
+    let idcode = unsafe { ptr::read_volatile(0xE004_2000 as *const u32) };
+    let enable = enable && supply.prefetch_allowed() && prefetch_supported(idcode);
+    // NOTE(unsafe) ACR is modified with a single RMW
+    let flash = unsafe { &*FLASH::ptr() };
+    flash.acr.modify(|_, w| w.prften().bit(enable));
+}
+
+pub(crate) fn set_icache(enable: bool) {
+    // NOTE(unsafe) ACR is modified with single RMWs
+    let flash = unsafe { &*FLASH::ptr() };
+    // The cache can only be reset while disabled
+    flash.acr.modify(|_, w| w.icen().clear_bit());
+    if enable {
+        flash.acr.modify(|_, w| w.icrst().set_bit());
+        flash.acr.modify(|_, w| w.icrst().clear_bit());
+        flash.acr.modify(|_, w| w.icen().set_bit());
+    }
+}
+
+pub(crate) fn set_dcache(enable: bool) {
+    // NOTE(unsafe) ACR is modified with single RMWs
+    let flash = unsafe { &*FLASH::ptr() };
+    // The cache can only be reset while disabled
+    flash.acr.modify(|_, w| w.dcen().clear_bit());
+    if enable {
+        flash.acr.modify(|_, w| w.dcrst().set_bit());
+        flash.acr.modify(|_, w| w.dcrst().clear_bit());
+        flash.acr.modify(|_, w| w.dcen().set_bit());
+    }
+}
+
+/// Drops the data cached from flash, which may be stale after erasing or programming
+fn invalidate_dcache(flash: &FLASH) {
+    if flash.acr.read().dcen().bit_is_set() {
+        set_dcache(true);
+    }
 }
 
 impl FlashExt for FLASH {
@@ -65,7 +158,10 @@ impl FlashExt for FLASH {
 
     fn unlocked(&mut self) -> UnlockedFlash {
         unlock(self);
-        UnlockedFlash { flash: self }
+        UnlockedFlash {
+            flash: self,
+            voltage_range: VoltageRange::default(),
+        }
     }
 
     fn dual_bank(&self) -> bool {
@@ -97,9 +193,55 @@ impl FlashExt for FLASH {
     fn sector(&self, offset: usize) -> Option<FlashSector> {
         flash_sectors(self.len(), self.dual_bank()).find(|s| s.contains(offset))
     }
+
+    fn option_bytes(&self) -> OptionBytes {
+        OptionBytes::read(self)
+    }
+
+    fn unlocked_options(&mut self) -> UnlockedOptionBytes {
+        UnlockedOptionBytes::new(self)
+    }
+}
+
+/// Supply voltage range, setting the erase/program parallelism
+///
+/// Wider accesses erase and program faster but need a higher supply voltage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum VoltageRange {
+    /// 1.8 V to 2.1 V, programs bytes
+    V1_8To2_1,
+    /// 2.1 V to 2.7 V, programs half-words
+    V2_1To2_7,
+    /// 2.7 V to 3.6 V, programs words
+    V2_7To3_6,
+}
+
+impl Default for VoltageRange {
+    fn default() -> Self {
+        Self::V1_8To2_1
+    }
 }
 
-const PSIZE_X8: u8 = 0b00;
+impl VoltageRange {
+    /// `PSIZE` field value
+    fn psize(self) -> u8 {
+        self as u8
+    }
+
+    /// Bytes programmed at once
+    fn width(self) -> usize {
+        1 << self as usize
+    }
+
+    /// The prefetch must be disabled below 2.1 V
+    fn prefetch_allowed(self) -> bool {
+        self != Self::V1_8To2_1
+    }
+}
+
+/// `EOP`, `OPERR`, `WRPERR`, `PGAERR`, `PGPERR` and `PGSERR`, cleared by writing 1
+const SR_CLEAR: u32 = 0xf3;
 
 /// Read-only flash
 ///
@@ -148,6 +290,14 @@ impl FlashExt for LockedFlash {
     fn sector(&self, offset: usize) -> Option<FlashSector> {
         self.flash.sector(offset)
     }
+
+    fn option_bytes(&self) -> OptionBytes {
+        self.flash.option_bytes()
+    }
+
+    fn unlocked_options(&mut self) -> UnlockedOptionBytes {
+        self.flash.unlocked_options()
+    }
 }
 
 /// Result of `FlashExt::unlocked()`
@@ -177,6 +327,7 @@ impl FlashExt for LockedFlash {
 /// ```
 pub struct UnlockedFlash<'a> {
     flash: &'a mut FLASH,
+    voltage_range: VoltageRange,
 }
 
 /// Automatically lock flash erase/program when leaving scope
@@ -187,19 +338,46 @@ impl Drop for UnlockedFlash<'_> {
 }
 
 impl UnlockedFlash<'_> {
+    /// Sets the supply voltage range, bytes are programmed one at a time until this is called
+    pub fn set_voltage_range(&mut self, voltage_range: VoltageRange) {
+        self.voltage_range = voltage_range;
+    }
+
     /// Erase a flash sector
     ///
     /// Refer to the reference manual to see which sector corresponds
     /// to which memory address.
     pub fn erase(&mut self, sector: u8) -> Result<(), Error> {
-        let snb = if sector < 12 { sector } else { sector + 4 };
+        self.start_erase(sector)?;
+        self.wait_ready();
+        self.flash.cr.modify(|_, w| w.ser().clear_bit());
+        invalidate_dcache(self.flash);
+        self.ok()
+    }
+
+    /// Start erasing a flash sector without waiting for it to complete
+    ///
+    /// With dual bank flash, code keeps running from one bank while a
+    /// sector of the other bank is erased. Accessing the bank being erased
+    /// stalls the CPU until the erase is done.
+    /// Use [`is_busy`](Self::is_busy) or [`check_erase`](Self::check_erase)
+    /// to find out when it's finished.
+    ///
+    /// Returns [`Error::InvalidSector`] if `sector` is not in the flash, e.g.
+    /// sectors 8 to 11 of 1 MB dual bank flash.
+    pub fn start_erase(&mut self, sector: u8) -> Result<(), Error> {
+        let snb = sector_snb(sector, self.flash.len(), self.flash.dual_bank())
+            .ok_or(Error::InvalidSector)?;
+        let psize = self.voltage_range.psize();
 
+        self.wait_ready();
+        self.clear_errors();
         #[rustfmt::skip]
         self.flash.cr.modify(|_, w| unsafe {
             w
                 // start
                 .strt().set_bit()
-                .psize().bits(PSIZE_X8)
+                .psize().bits(psize)
                 // sector number
                 .snb().bits(snb)
                 // sectore erase
@@ -207,50 +385,74 @@ impl UnlockedFlash<'_> {
                 // no programming
                 .pg().clear_bit()
         });
-        self.wait_ready();
-        self.ok()
+        Ok(())
+    }
+
+    /// Returns true while an erase or program operation is ongoing
+    pub fn is_busy(&self) -> bool {
+        self.flash.sr.read().bsy().bit()
+    }
+
+    /// Returns the result of an erase started with [`start_erase`](Self::start_erase)
+    pub fn check_erase(&mut self) -> nb::Result<(), Error> {
+        if self.is_busy() {
+            Err(nb::Error::WouldBlock)
+        } else {
+            self.flash.cr.modify(|_, w| w.ser().clear_bit());
+            invalidate_dcache(self.flash);
+            self.ok().map_err(nb::Error::Other)
+        }
     }
 
-    /// Program bytes with offset into flash memory,
-    /// aligned to 128-bit rows
-    pub fn program<'a, I>(&mut self, mut offset: usize, mut bytes: I) -> Result<(), Error>
+    /// Program bytes with offset into flash memory
+    ///
+    /// Bytes are programmed by bytes, half-words or words depending on the voltage range.
+    /// Unaligned starts and ends are padded with `0xFF`, which leaves flash contents unchanged.
+    pub fn program<'a, I>(&mut self, offset: usize, bytes: I) -> Result<(), Error>
     where
         I: Iterator<Item = &'a u8>,
     {
         let ptr = self.flash.address() as *mut u8;
-        let mut bytes_written = 1;
-        while bytes_written > 0 {
-            bytes_written = 0;
-            let amount = 16 - (offset % 16);
-
-            #[rustfmt::skip]
-            #[allow(unused_unsafe)]
-            self.flash.cr.modify(|_, w| unsafe {
-                w
-                    .psize().bits(PSIZE_X8)
-                    // no sector erase
-                    .ser().clear_bit()
-                    // programming
-                    .pg().set_bit()
-            });
-            for _ in 0..amount {
-                match bytes.next() {
-                    Some(byte) => {
-                        unsafe {
-                            ptr::write_volatile(ptr.add(offset), *byte);
-                        }
-                        offset += 1;
-                        bytes_written += 1;
-                    }
-                    None => break,
+        let width = self.voltage_range.width();
+        let psize = self.voltage_range.psize();
+
+        self.wait_ready();
+        self.clear_errors();
+        #[rustfmt::skip]
+        #[allow(unused_unsafe)]
+        self.flash.cr.modify(|_, w| unsafe {
+            w
+                .psize().bits(psize)
+                // no sector erase
+                .ser().clear_bit()
+                // programming
+                .pg().set_bit()
+        });
+
+        let result = program_units(offset, bytes, width, |start, data| {
+            unsafe {
+                let address = ptr.add(start);
+                match width {
+                    1 => ptr::write_volatile(address, data[0]),
+                    2 => ptr::write_volatile(
+                        address as *mut u16,
+                        u16::from_le_bytes([data[0], data[1]]),
+                    ),
+                    _ => ptr::write_volatile(address as *mut u32, u32::from_le_bytes(data)),
                 }
             }
             self.wait_ready();
-            self.ok()?;
-        }
+            self.ok()
+        });
         self.flash.cr.modify(|_, w| w.pg().clear_bit());
+        invalidate_dcache(self.flash);
 
-        Ok(())
+        result
+    }
+
+    /// Clears the error flags left by a previous operation, they would fail the next one
+    fn clear_errors(&mut self) {
+        self.flash.sr.write(|w| unsafe { w.bits(SR_CLEAR) });
     }
 
     fn ok(&self) -> Result<(), Error> {
@@ -258,10 +460,38 @@ impl UnlockedFlash<'_> {
     }
 
     fn wait_ready(&self) {
-        while self.flash.sr.read().bsy().bit() {}
+        while self.is_busy() {}
     }
 }
 
+/// Splits `bytes` programmed at `offset` into aligned units of `width` bytes
+///
+/// `program` is called with the offset of each unit and its bytes, unaligned starts and ends
+/// padded with `0xFF`. Stops at the first error.
+fn program_units<'a>(
+    mut offset: usize,
+    bytes: impl Iterator<Item = &'a u8>,
+    width: usize,
+    mut program: impl FnMut(usize, [u8; 4]) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut bytes = bytes.peekable();
+    while bytes.peek().is_some() {
+        let start = offset - offset % width;
+        let mut data = [0xff; 4];
+        for byte in &mut data[offset - start..width] {
+            match bytes.next() {
+                Some(b) => {
+                    *byte = *b;
+                    offset += 1;
+                }
+                None => break,
+            }
+        }
+        program(start, data)?;
+    }
+    Ok(())
+}
+
 const UNLOCK_KEY1: u32 = 0x45670123;
 const UNLOCK_KEY2: u32 = 0xCDEF89AB;
 
@@ -276,6 +506,16 @@ fn lock(flash: &FLASH) {
     flash.cr.modify(|_, w| w.lock().set_bit());
 }
 
+/// Flash memory bank
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Bank {
+    /// Sectors 0 to 11, the only bank of single bank flash
+    Bank1,
+    /// Sectors 12 to 23
+    Bank2,
+}
+
 /// Flash memory sector
 pub struct FlashSector {
     /// Sector number
@@ -291,6 +531,15 @@ impl FlashSector {
     pub fn contains(&self, offset: usize) -> bool {
         self.offset <= offset && offset < self.offset + self.size
     }
+
+    /// Returns the bank this sector belongs to
+    pub fn bank(&self) -> Bank {
+        if self.number < 12 {
+            Bank::Bank1
+        } else {
+            Bank::Bank2
+        }
+    }
 }
 
 /// Iterator of flash memory sectors in a single bank.
@@ -357,6 +606,16 @@ pub fn flash_sectors(flash_size: usize, dual_bank: bool) -> impl Iterator<Item =
     }
 }
 
+/// Returns the `SNB` value of `sector`, if the flash has it
+///
+/// 1 MB dual bank flash has sectors 0 to 7 and 12 to 19.
+fn sector_snb(sector: u8, flash_size: usize, dual_bank: bool) -> Option<u8> {
+    flash_sectors(flash_size, dual_bank)
+        .any(|s| s.number == sector)
+        // Sectors 12 and above belong to the second bank, numbered from 0b10000
+        .then(|| if sector < 12 { sector } else { sector + 4 })
+}
+
 impl ReadNorFlash for LockedFlash {
     type Error = Error;
 
@@ -419,3 +678,202 @@ impl<'a> NorFlash for UnlockedFlash<'a> {
 
 // STM32F4 supports multiple writes
 impl<'a> MultiwriteNorFlash for UnlockedFlash<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn units(offset: usize, bytes: &[u8], width: usize) -> ([(usize, [u8; 4]); 4], usize) {
+        let mut units = [(0, [0; 4]); 4];
+        let mut len = 0;
+        program_units(offset, bytes.iter(), width, |start, data| {
+            units[len] = (start, data);
+            len += 1;
+            Ok(())
+        })
+        .unwrap();
+        (units, len)
+    }
+
+    #[test]
+    fn default_voltage_range_programs_bytes() {
+        assert_eq!(VoltageRange::default(), VoltageRange::V1_8To2_1);
+        assert_eq!(VoltageRange::default().width(), 1);
+        assert_eq!(VoltageRange::V2_1To2_7.width(), 2);
+        assert_eq!(VoltageRange::V2_7To3_6.width(), 4);
+    }
+
+    #[test]
+    fn prefetch_off_below_2v1() {
+        assert!(!VoltageRange::V1_8To2_1.prefetch_allowed());
+        assert!(VoltageRange::V2_1To2_7.prefetch_allowed());
+        assert!(VoltageRange::V2_7To3_6.prefetch_allowed());
+    }
+
+    #[test]
+    fn prefetch_rev_a() {
+        let f40x = cfg!(any(
+            feature = "stm32f405",
+            feature = "stm32f407",
+            feature = "stm32f415",
+            feature = "stm32f417"
+        ));
+        // Revision A, with and without a debugger
+        assert_eq!(prefetch_supported(0x1000_6413), !f40x);
+        assert_eq!(prefetch_supported(0), !f40x);
+        // Revisions Z, 1 and 2
+        assert!(prefetch_supported(0x1001_6413));
+        assert!(prefetch_supported(0x1003_6413));
+        assert!(prefetch_supported(0x1007_6413));
+    }
+
+    #[test]
+    fn aligned_words() {
+        let (units, len) = units(0x100, &[1, 2, 3, 4, 5, 6, 7, 8], 4);
+        assert_eq!(units[..len], [(0x100, [1, 2, 3, 4]), (0x104, [5, 6, 7, 8])]);
+    }
+
+    #[test]
+    fn unaligned_words_are_padded() {
+        let (units, len) = units(0x102, &[1, 2, 3, 4, 5], 4);
+        assert_eq!(
+            units[..len],
+            [(0x100, [0xff, 0xff, 1, 2]), (0x104, [3, 4, 5, 0xff]),]
+        );
+    }
+
+    #[test]
+    fn unaligned_half_words_are_padded() {
+        let (units, len) = units(0x101, &[1, 2, 3], 2);
+        assert_eq!(
+            units[..len],
+            [(0x100, [0xff, 1, 0xff, 0xff]), (0x102, [2, 3, 0xff, 0xff])]
+        );
+    }
+
+    #[test]
+    fn bytes_are_not_padded() {
+        let (units, len) = units(0x101, &[1, 2], 1);
+        assert_eq!(
+            units[..len],
+            [
+                (0x101, [1, 0xff, 0xff, 0xff]),
+                (0x102, [2, 0xff, 0xff, 0xff])
+            ]
+        );
+    }
+
+    #[test]
+    fn write_across_sectors() {
+        // Sector 0 is 16 KiB, the write ends in sector 1
+        let (units, len) = units(0x3ffe, &[1, 2, 3, 4], 4);
+        assert_eq!(
+            units[..len],
+            [(0x3ffc, [0xff, 0xff, 1, 2]), (0x4000, [3, 4, 0xff, 0xff])]
+        );
+
+        let mut sectors = flash_sectors(512 * 1024, false);
+        let first = sectors.next().unwrap();
+        let second = sectors.next().unwrap();
+        assert!(first.contains(units[0].0) && first.number == 0);
+        assert!(second.contains(units[1].0) && second.number == 1);
+    }
+
+    #[test]
+    fn stops_at_first_error() {
+        let mut count = 0;
+        let result = program_units(0, [0; 12].iter(), 4, |_, _| {
+            count += 1;
+            if count == 2 {
+                Err(Error::ProgrammingAlignment)
+            } else {
+                Ok(())
+            }
+        });
+        assert!(matches!(result, Err(Error::ProgrammingAlignment)));
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn empty_write() {
+        assert_eq!(units(3, &[], 4).1, 0);
+    }
+
+    const K: usize = 1024;
+
+    fn last_sector(flash_size: usize, dual_bank: bool) -> (u8, usize, usize) {
+        let s = flash_sectors(flash_size, dual_bank).last().unwrap();
+        (s.number, s.offset, s.size)
+    }
+
+    fn sector_at(flash_size: usize, dual_bank: bool, offset: usize) -> Option<(u8, usize)> {
+        flash_sectors(flash_size, dual_bank)
+            .find(|s| s.contains(offset))
+            .map(|s| (s.number, s.offset))
+    }
+
+    #[test]
+    fn single_bank_layouts() {
+        let sizes: [usize; 8] = [16, 16, 16, 16, 64, 128, 128, 128];
+        assert!(flash_sectors(512 * K, false)
+            .map(|s| s.size / K)
+            .eq(sizes.iter().copied()));
+        assert_eq!(flash_sectors(256 * K, false).count(), 6);
+        assert_eq!(last_sector(512 * K, false), (7, 0x6_0000, 128 * K));
+        assert_eq!(last_sector(1024 * K, false), (11, 0xe_0000, 128 * K));
+
+        assert_eq!(sector_at(1024 * K, false, 0), Some((0, 0)));
+        assert_eq!(sector_at(1024 * K, false, 0x3fff), Some((0, 0)));
+        assert_eq!(sector_at(1024 * K, false, 0x4000), Some((1, 0x4000)));
+        assert_eq!(sector_at(1024 * K, false, 0x1_0000), Some((4, 0x1_0000)));
+        assert_eq!(sector_at(1024 * K, false, 0x2_0000), Some((5, 0x2_0000)));
+        assert_eq!(sector_at(1024 * K, false, 0x8_0000), Some((8, 0x8_0000)));
+        assert_eq!(sector_at(1024 * K, false, 0x10_0000), None);
+    }
+
+    #[test]
+    fn dual_bank_1m_layout() {
+        // Two banks of 512 KB: sectors 0 to 7, then 12 to 19
+        let numbers: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 12, 13, 14, 15, 16, 17, 18, 19];
+        assert!(flash_sectors(1024 * K, true)
+            .map(|s| s.number)
+            .eq(numbers.iter().copied()));
+        assert_eq!(sector_at(1024 * K, true, 0x7_ffff), Some((7, 0x6_0000)));
+        assert_eq!(sector_at(1024 * K, true, 0x8_0000), Some((12, 0x8_0000)));
+        assert_eq!(sector_at(1024 * K, true, 0x8_4000), Some((13, 0x8_4000)));
+        assert_eq!(sector_at(1024 * K, true, 0x9_0000), Some((16, 0x9_0000)));
+        assert_eq!(last_sector(1024 * K, true), (19, 0xe_0000, 128 * K));
+
+        let bank = |offset| {
+            flash_sectors(1024 * K, true)
+                .find(|s| s.contains(offset))
+                .unwrap()
+                .bank()
+        };
+        assert_eq!(bank(0x7_ffff), Bank::Bank1);
+        assert_eq!(bank(0x8_0000), Bank::Bank2);
+    }
+
+    #[test]
+    fn dual_bank_2m_layout() {
+        assert_eq!(flash_sectors(2048 * K, true).count(), 24);
+        assert_eq!(sector_at(2048 * K, true, 0xf_ffff), Some((11, 0xe_0000)));
+        assert_eq!(sector_at(2048 * K, true, 0x10_0000), Some((12, 0x10_0000)));
+        assert_eq!(last_sector(2048 * K, true), (23, 0x1e_0000, 128 * K));
+    }
+
+    #[test]
+    fn sector_numbers() {
+        assert_eq!(sector_snb(11, 1024 * K, false), Some(11));
+        assert_eq!(sector_snb(12, 1024 * K, false), None);
+        assert_eq!(sector_snb(8, 512 * K, false), None);
+        // DB1M: no sectors 8 to 11, the second bank is numbered from 0b10000
+        assert_eq!(sector_snb(7, 1024 * K, true), Some(7));
+        assert_eq!(sector_snb(8, 1024 * K, true), None);
+        assert_eq!(sector_snb(11, 1024 * K, true), None);
+        assert_eq!(sector_snb(12, 1024 * K, true), Some(0b10000));
+        assert_eq!(sector_snb(19, 1024 * K, true), Some(0b10111));
+        assert_eq!(sector_snb(20, 1024 * K, true), None);
+        assert_eq!(sector_snb(23, 2048 * K, true), Some(0b11011));
+    }
+}
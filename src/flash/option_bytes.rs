@@ -0,0 +1,377 @@
+//! Option bytes
+//!
+//! Read protection, sector write protection, brown-out reset level and
+//! user options are kept in the option bytes, reloaded at reset and
+//! mirrored in the `OPTCR` registers.
+//!
+//! # Examples
+//!
+//! ```
+//! use stm32f4xx_hal::pac::Peripherals;
+//! use stm32f4xx_hal::flash::{BrownoutLevel, FlashExt, LockedFlash, ReadProtection};
+//!
+//! let dp = Peripherals::take().unwrap();
+//! let mut flash = LockedFlash::new(dp.FLASH);
+//!
+//! let mut options = flash.option_bytes();
+//! options.read_protection = ReadProtection::Level1;
+//! options.brownout_level = BrownoutLevel::Level3;
+//! // Protect the 4 bootloader sectors
+//! options.write_protection |= 0b1111;
+//!
+//! // Programs the option bytes and checks them back
+//! flash.unlocked_options().apply(&options).unwrap();
+//! ```
+
+use super::Error;
+use crate::pac::FLASH;
+
+/// Read protection level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReadProtection {
+    /// No protection
+    Level0,
+    /// Flash can't be read by the debugger, going back to level 0 mass erases flash
+    Level1,
+    /// Debug is permanently disabled and option bytes can't be changed anymore
+    ///
+    /// Only applied by [`UnlockedOptionBytes::apply_irreversible`].
+    Level2,
+}
+
+impl ReadProtection {
+    fn from_bits(rdp: u8) -> Self {
+        match rdp {
+            0xAA => Self::Level0,
+            0xCC => Self::Level2,
+            _ => Self::Level1,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            Self::Level0 => 0xAA,
+            Self::Level1 => 0x55,
+            Self::Level2 => 0xCC,
+        }
+    }
+}
+
+/// Brown-out reset threshold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BrownoutLevel {
+    /// Reset at 2.70 V to 3.60 V supply
+    Level3 = 0b00,
+    /// Reset at 2.40 V to 2.70 V supply
+    Level2 = 0b01,
+    /// Reset at 2.10 V to 2.40 V supply
+    Level1 = 0b10,
+    /// Power-on/power-down reset threshold only, 1.8 V to 2.10 V supply
+    Off = 0b11,
+}
+
+impl BrownoutLevel {
+    fn from_bits(bor_lev: u8) -> Self {
+        match bor_lev & 0b11 {
+            0b00 => Self::Level3,
+            0b01 => Self::Level2,
+            0b10 => Self::Level1,
+            _ => Self::Off,
+        }
+    }
+}
+
+const OPTLOCK: u32 = 1 << 0;
+const OPTSTRT: u32 = 1 << 1;
+const BOR_LEV_SHIFT: u32 = 2;
+#[cfg(any(
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479",
+))]
+const BFB2: u32 = 1 << 4;
+const WDG_SW: u32 = 1 << 5;
+const NRST_STOP: u32 = 1 << 6;
+const NRST_STDBY: u32 = 1 << 7;
+const RDP_SHIFT: u32 = 8;
+const NWRP_SHIFT: u32 = 16;
+const NWRP_MASK: u32 = 0xfff;
+#[cfg(any(
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479",
+))]
+const DB1M: u32 = 1 << 30;
+/// Write protection bits of the sectors the part can have
+#[cfg(any(
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479",
+))]
+const WRP_MASK: u32 = 0xff_ffff;
+#[cfg(not(any(
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479",
+)))]
+const WRP_MASK: u32 = NWRP_MASK;
+
+/// Option byte values
+///
+/// Read with [`FlashExt::option_bytes`], modify fields and program with
+/// [`UnlockedOptionBytes::apply`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OptionBytes {
+    /// Read protection level
+    pub read_protection: ReadProtection,
+    /// Brown-out reset threshold
+    pub brownout_level: BrownoutLevel,
+    /// Write protected sectors, bit `n` set protects sector `n`
+    ///
+    /// Sectors 12 to 23 of the second bank are in bits 12 to 23. These bits are ignored on
+    /// parts without a second bank, and read back as 0.
+    pub write_protection: u32,
+    /// Independent watchdog is started by software, otherwise by hardware at reset
+    pub watchdog_software: bool,
+    /// Entering Stop mode generates a reset
+    pub reset_on_stop: bool,
+    /// Entering Standby mode generates a reset
+    pub reset_on_standby: bool,
+    /// 1 MB flash is organized in two banks (`DB1M`)
+    #[cfg(any(
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+        feature = "stm32f469",
+        feature = "stm32f479",
+    ))]
+    pub dual_bank_1m: bool,
+    /// Boot from the second bank when it holds a valid stack pointer (`BFB2`)
+    #[cfg(any(
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+        feature = "stm32f469",
+        feature = "stm32f479",
+    ))]
+    pub boot_from_bank2: bool,
+}
+
+impl OptionBytes {
+    pub(super) fn read(flash: &FLASH) -> Self {
+        let optcr = flash.optcr.read().bits();
+        #[allow(unused_mut)]
+        let mut n_wrp = (optcr >> NWRP_SHIFT) & NWRP_MASK;
+        #[cfg(any(
+            feature = "stm32f427",
+            feature = "stm32f429",
+            feature = "stm32f437",
+            feature = "stm32f439",
+            feature = "stm32f469",
+            feature = "stm32f479",
+        ))]
+        {
+            n_wrp |= ((flash.optcr1.read().bits() >> NWRP_SHIFT) & NWRP_MASK) << 12;
+        }
+        #[cfg(not(any(
+            feature = "stm32f427",
+            feature = "stm32f429",
+            feature = "stm32f437",
+            feature = "stm32f439",
+            feature = "stm32f469",
+            feature = "stm32f479",
+        )))]
+        {
+            n_wrp |= 0xfff << 12;
+        }
+
+        Self {
+            read_protection: ReadProtection::from_bits((optcr >> RDP_SHIFT) as u8),
+            brownout_level: BrownoutLevel::from_bits((optcr >> BOR_LEV_SHIFT) as u8),
+            write_protection: !n_wrp & 0xff_ffff,
+            watchdog_software: optcr & WDG_SW != 0,
+            reset_on_stop: optcr & NRST_STOP == 0,
+            reset_on_standby: optcr & NRST_STDBY == 0,
+            #[cfg(any(
+                feature = "stm32f427",
+                feature = "stm32f429",
+                feature = "stm32f437",
+                feature = "stm32f439",
+                feature = "stm32f469",
+                feature = "stm32f479",
+            ))]
+            dual_bank_1m: optcr & DB1M != 0,
+            #[cfg(any(
+                feature = "stm32f427",
+                feature = "stm32f429",
+                feature = "stm32f437",
+                feature = "stm32f439",
+                feature = "stm32f469",
+                feature = "stm32f479",
+            ))]
+            boot_from_bank2: optcr & BFB2 != 0,
+        }
+    }
+
+    /// `OPTCR` value, keeping bits not described here from `optcr`
+    fn optcr(&self, optcr: u32) -> u32 {
+        let mut bits = optcr
+            & !(0b11 << BOR_LEV_SHIFT
+                | WDG_SW
+                | NRST_STOP
+                | NRST_STDBY
+                | 0xff << RDP_SHIFT
+                | NWRP_MASK << NWRP_SHIFT);
+        bits |= (self.brownout_level as u32) << BOR_LEV_SHIFT;
+        bits |= (self.read_protection.bits() as u32) << RDP_SHIFT;
+        bits |= (!self.write_protection & NWRP_MASK) << NWRP_SHIFT;
+        if self.watchdog_software {
+            bits |= WDG_SW;
+        }
+        if !self.reset_on_stop {
+            bits |= NRST_STOP;
+        }
+        if !self.reset_on_standby {
+            bits |= NRST_STDBY;
+        }
+        #[cfg(any(
+            feature = "stm32f427",
+            feature = "stm32f429",
+            feature = "stm32f437",
+            feature = "stm32f439",
+            feature = "stm32f469",
+            feature = "stm32f479",
+        ))]
+        {
+            bits &= !(DB1M | BFB2);
+            if self.dual_bank_1m {
+                bits |= DB1M;
+            }
+            if self.boot_from_bank2 {
+                bits |= BFB2;
+            }
+        }
+        bits & !(OPTLOCK | OPTSTRT)
+    }
+}
+
+/// Result of `FlashExt::unlocked_options()`
+///
+/// Option bytes are locked again when dropped.
+pub struct UnlockedOptionBytes<'a> {
+    flash: &'a mut FLASH,
+}
+
+impl<'a> UnlockedOptionBytes<'a> {
+    pub(super) fn new(flash: &'a mut FLASH) -> Self {
+        unlock(flash);
+        Self { flash }
+    }
+
+    /// Program option bytes, then read them back and compare
+    ///
+    /// Read protection level 2 is refused with [`Error::ReadProtectionLevel2`],
+    /// use [`apply_irreversible`](Self::apply_irreversible) for it.
+    ///
+    /// New values are used right away, but read protection and brown-out level
+    /// changes are only fully effective after a reset. A power cycle is required
+    /// to reconnect a debugger after setting read protection level 1.
+    pub fn apply(&mut self, options: &OptionBytes) -> Result<(), Error> {
+        if options.read_protection == ReadProtection::Level2 {
+            return Err(Error::ReadProtectionLevel2);
+        }
+        self.program(options)
+    }
+
+    /// Program option bytes, allowing read protection level 2
+    ///
+    /// # Safety
+    ///
+    /// Level 2 can never be removed: debug access is disabled for good, and option
+    /// bytes, including write protection, can't be modified anymore, even by this driver.
+    pub unsafe fn apply_irreversible(&mut self, options: &OptionBytes) -> Result<(), Error> {
+        self.program(options)
+    }
+
+    fn program(&mut self, options: &OptionBytes) -> Result<(), Error> {
+        let optcr = options.optcr(self.flash.optcr.read().bits());
+
+        self.wait_ready();
+        self.flash.sr.write(|w| unsafe { w.bits(super::SR_CLEAR) });
+        #[cfg(any(
+            feature = "stm32f427",
+            feature = "stm32f429",
+            feature = "stm32f437",
+            feature = "stm32f439",
+            feature = "stm32f469",
+            feature = "stm32f479",
+        ))]
+        self.flash.optcr1.modify(|r, w| unsafe {
+            w.bits(
+                r.bits() & !(NWRP_MASK << NWRP_SHIFT)
+                    | (!options.write_protection >> 12 & NWRP_MASK) << NWRP_SHIFT,
+            )
+        });
+        self.flash.optcr.write(|w| unsafe { w.bits(optcr) });
+        self.flash
+            .optcr
+            .modify(|r, w| unsafe { w.bits(r.bits() | OPTSTRT) });
+        self.wait_ready();
+
+        if let Some(error) = Error::read(self.flash) {
+            return Err(error);
+        }
+        let expected = OptionBytes {
+            write_protection: options.write_protection & WRP_MASK,
+            ..*options
+        };
+        if OptionBytes::read(self.flash) != expected {
+            return Err(Error::Verify);
+        }
+        Ok(())
+    }
+
+    /// Read back programmed option bytes
+    pub fn read(&self) -> OptionBytes {
+        OptionBytes::read(self.flash)
+    }
+
+    fn wait_ready(&self) {
+        while self.flash.sr.read().bsy().bit() {}
+    }
+}
+
+/// Automatically lock option bytes when leaving scope
+impl Drop for UnlockedOptionBytes<'_> {
+    fn drop(&mut self) {
+        self.flash
+            .optcr
+            .modify(|r, w| unsafe { w.bits(r.bits() | OPTLOCK) });
+    }
+}
+
+const OPT_UNLOCK_KEY1: u32 = 0x08192A3B;
+const OPT_UNLOCK_KEY2: u32 = 0x4C5D6E7F;
+
+fn unlock(flash: &FLASH) {
+    flash.optkeyr.write(|w| w.bits(OPT_UNLOCK_KEY1));
+    flash.optkeyr.write(|w| w.bits(OPT_UNLOCK_KEY2));
+    assert!(flash.optcr.read().bits() & OPTLOCK == 0)
+}
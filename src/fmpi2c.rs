@@ -1,6 +1,6 @@
 use core::ops::Deref;
 
-use crate::i2c::{Error, NoAcknowledgeSource, Pins};
+use crate::i2c::{run_len, take_run, Error, NoAcknowledgeSource, Operation, Pins};
 use crate::pac::{fmpi2c1, FMPI2C1, RCC};
 use crate::rcc::{Enable, Reset};
 use fugit::{HertzU32 as Hertz, RateExtU32};
@@ -17,6 +17,7 @@ pub struct FMPI2c<I2C, PINS> {
 pub type FMPI2c1<PINS> = FMPI2c<FMPI2C1, PINS>;
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum FmpMode {
     Standard { frequency: Hertz },
     Fast { frequency: Hertz },
@@ -74,7 +75,7 @@ where
             FMPI2C1::enable(rcc);
             FMPI2C1::reset(rcc);
 
-            rcc.dckcfgr2.modify(|_, w| w.fmpi2c1sel().hsi());
+            cortex_m::interrupt::free(|_| rcc.dckcfgr2.modify(|_, w| w.fmpi2c1sel().hsi()));
         }
 
         pins.set_alt_mode();
@@ -311,4 +312,63 @@ where
 
         self.end_transaction()
     }
+
+    /// Executes a sequence of reads and writes as a single transaction
+    ///
+    /// Adjacent operations of the same direction are merged, a repeated START is sent
+    /// when the direction changes and a STOP after the last operation. Merged operations
+    /// are limited to 255 bytes.
+    pub fn transaction(&mut self, addr: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        let mut operations = operations;
+        while let Some((read, run)) = take_run(&mut operations) {
+            let last = operations.is_empty();
+
+            let nbytes = run_len(run);
+            if nbytes > 255 {
+                return Err(Error::Overrun);
+            }
+
+            // Set up current address and direction, end with STOP after the last operation
+            self.i2c.cr2.modify(|_, w| {
+                w.sadd()
+                    .bits(u16::from(addr) << 1)
+                    .nbytes()
+                    .bits(nbytes as u8)
+                    .rd_wrn()
+                    .bit(read)
+                    .autoend()
+                    .bit(last)
+            });
+
+            // Send a (repeated) START condition
+            self.i2c.cr2.modify(|_, w| w.start().set_bit());
+
+            for op in run.iter_mut() {
+                match op {
+                    Operation::Read(buffer) => {
+                        for c in buffer.iter_mut() {
+                            *c = self.recv_byte()?;
+                        }
+                    }
+                    Operation::Write(bytes) => {
+                        for c in bytes.iter() {
+                            self.send_byte(*c)?;
+                        }
+                    }
+                }
+            }
+
+            if !last {
+                // Wait until all bytes were transferred
+                while {
+                    let isr = self.i2c.isr.read();
+                    self.check_and_clear_error_flags(&isr)
+                        .map_err(Error::nack_data)?;
+                    isr.tc().bit_is_clear()
+                } {}
+            }
+        }
+
+        self.end_transaction()
+    }
 }
@@ -7,9 +7,9 @@ impl<I2C, PINS> ErrorType for super::FMPI2c<I2C, PINS> {
 mod blocking {
     use super::super::{fmpi2c1, FMPI2c};
     use core::ops::Deref;
-    use embedded_hal_one::i2c::blocking::Operation;
+    use embedded_hal_one::i2c::Operation;
 
-    impl<I2C, PINS> embedded_hal_one::i2c::blocking::I2c for FMPI2c<I2C, PINS>
+    impl<I2C, PINS> embedded_hal_one::i2c::I2c for FMPI2c<I2C, PINS>
     where
         I2C: Deref<Target = fmpi2c1::RegisterBlock>,
     {
@@ -21,13 +21,6 @@ mod blocking {
             self.write(addr, bytes)
         }
 
-        fn write_iter<B>(&mut self, _addr: u8, _bytes: B) -> Result<(), Self::Error>
-        where
-            B: IntoIterator<Item = u8>,
-        {
-            todo!()
-        }
-
         fn write_read(
             &mut self,
             addr: u8,
@@ -37,31 +30,12 @@ mod blocking {
             self.write_read(addr, bytes, buffer)
         }
 
-        fn write_iter_read<B>(
-            &mut self,
-            _addr: u8,
-            _bytes: B,
-            _buffer: &mut [u8],
-        ) -> Result<(), Self::Error>
-        where
-            B: IntoIterator<Item = u8>,
-        {
-            todo!()
-        }
-
-        fn transaction<'a>(
+        fn transaction(
             &mut self,
-            _addr: u8,
-            _operations: &mut [Operation<'a>],
+            addr: u8,
+            operations: &mut [Operation<'_>],
         ) -> Result<(), Self::Error> {
-            todo!()
-        }
-
-        fn transaction_iter<'a, O>(&mut self, _addr: u8, _operations: O) -> Result<(), Self::Error>
-        where
-            O: IntoIterator<Item = Operation<'a>>,
-        {
-            todo!()
+            self.transaction(addr, operations)
         }
     }
 }
@@ -0,0 +1,487 @@
+//!
+//! External SRAM, PSRAM and NOR flash using the Flexible Memory Controller (FMC) /
+//! Flexible Static Memory Controller (FSMC)
+//!
+//! The memory is mapped into the address space of bank 1: each of the four chip select
+//! signals `NE1` to `NE4` selects a 64 MiB sub-bank starting at `0x6000_0000`,
+//! `0x6400_0000`, `0x6800_0000` and `0x6c00_0000` respectively.
+//!
+//! # Timing
+//!
+//! Timings are given in nanoseconds and converted to HCLK cycles using the frequency from
+//! [`Clocks`], rounding up. Refer to the memory datasheet and to the "NOR Flash/PSRAM
+//! controller" section of the reference manual to find the values for your part.
+//! Mode 1 (the default, no extended mode) uses the same timing for reads and writes.
+//! If a write timing is given, extended mode is enabled and access modes A to D can be used.
+//!
+//! # Memory access
+//!
+//! Once configured, the memory is accessed with ordinary loads and stores at
+//! [`NorSramBank::base_address`]. SRAM and PSRAM can be used through a slice like internal RAM.
+//! NOR flash reads and writes issue bus commands and must only be done with volatile
+//! accesses (`core::ptr::read_volatile`/`write_volatile`) through [`NorSramBank::as_ptr`].
+//!
+//! # Example
+//!
+//! A 1 MiB, 16-bit SRAM on sub-bank 1:
+//!
+//! ```ignore
+//! let pins = NorSramPins {
+//!     data: (
+//!         gpiod.pd14.into_alternate(), gpiod.pd15.into_alternate(),
+//!         gpiod.pd0.into_alternate(), gpiod.pd1.into_alternate(),
+//!         gpioe.pe7.into_alternate(), gpioe.pe8.into_alternate(),
+//!         gpioe.pe9.into_alternate(), gpioe.pe10.into_alternate(),
+//!         gpioe.pe11.into_alternate(), gpioe.pe12.into_alternate(),
+//!         gpioe.pe13.into_alternate(), gpioe.pe14.into_alternate(),
+//!         gpioe.pe15.into_alternate(), gpiod.pd8.into_alternate(),
+//!         gpiod.pd9.into_alternate(), gpiod.pd10.into_alternate(),
+//!     ),
+//!     // A0..A18
+//!     address: (
+//!         gpiof.pf0.into_alternate(), /* ... */ gpiod.pd13.into_alternate(),
+//!     ),
+//!     read_enable: gpiod.pd4.into_alternate(),
+//!     write_enable: gpiod.pd5.into_alternate(),
+//!     chip_select: gpiod.pd7.into_alternate(),
+//!     byte_lanes: (gpioe.pe0.into_alternate(), gpioe.pe1.into_alternate()),
+//!     address_valid: NoPin,
+//! };
+//! let config = Config::new(MemoryType::Sram, 1 << 20).timing(
+//!     Timing::default()
+//!         .address_setup(10.nanos())
+//!         .data_setup(20.nanos()),
+//! );
+//! let mut sram = NorSramBank::new(dp.FSMC, pins, config, &clocks).unwrap();
+//! let framebuffer: &'static mut [u16] = sram.leak();
+//! ```
+//!
+//! LCD controllers with an Intel 8080 interface are driven by the `fsmc_lcd` module, which
+//! can take its timings in nanoseconds from [`Timing`] with `fsmc_lcd::Timing::from_nanos`.
+//!
+//! # SDRAM
+//!
+//! On models with an FMC, [`Sdram`] configures and initializes an SDRAM on one of the two
+//! SDRAM banks.
+
+mod pins;
+#[cfg(feature = "fmc")]
+mod sdram;
+
+pub use self::pins::{
+    AddressPins, ByteLanePins, DataPins, NorSramPins, PinAddress, PinAddressValid, PinByteLane0,
+    PinByteLane1, PinChipSelect, PinData, PinReadEnable, PinWriteEnable, Pins,
+};
+#[cfg(feature = "fmc")]
+pub use self::sdram::{
+    BankAddressPins, PinBankAddress, PinSdCas, PinSdChipEnable, PinSdClock, PinSdClockEnable,
+    PinSdRas, PinSdWriteEnable, SdClockDivider, Sdram, SdramConfig, SdramPinSet, SdramPins,
+    SdramTiming, SDRAM_BANK1_BASE, SDRAM_BANK2_BASE,
+};
+
+use fugit::NanosDurationU32 as Nanoseconds;
+
+use crate::pac::RCC;
+use crate::rcc::{Clocks, Enable, Reset};
+
+// Use the FMC or FSMC, whichever is available, and treat it like an FSMC
+#[cfg(feature = "fmc")]
+use crate::pac::FMC as FSMC;
+#[cfg(feature = "fsmc")]
+use crate::pac::FSMC;
+
+mod sealed {
+    pub trait Sealed {}
+
+    // Pin groups are tuples, the public traits bound their elements
+    macro_rules! tuples {
+        ($T:ident $(, $rT:ident)*) => {
+            impl<$T $(, $rT)*> Sealed for ($T, $($rT,)*) {}
+            tuples!($($rT),*);
+        };
+        () => {};
+    }
+
+    tuples!(
+        T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16, T17, T18, T19,
+        T20, T21, T22, T23, T24, T25
+    );
+}
+
+/// Start of bank 1
+pub const BANK1_BASE: usize = 0x6000_0000;
+/// Size of one sub-bank of bank 1
+pub const SUB_BANK_SIZE: usize = 0x0400_0000;
+
+/// Configuration error
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// A timing doesn't fit its register field at the current HCLK frequency
+    TimingOutOfRange,
+    /// The memory is larger than a sub-bank or than the address lines allow
+    SizeTooLarge,
+    /// The SDRAM clock enable and chip enable pins select different banks
+    BankMismatch,
+    /// A configuration field is out of its allowed range
+    InvalidConfig,
+}
+
+/// External memory type
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MemoryType {
+    Sram = 0b00,
+    Psram = 0b01,
+    Nor = 0b10,
+}
+
+/// Data bus width
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataWidth {
+    Bits8 = 0b00,
+    Bits16 = 0b01,
+}
+
+/// Memory access modes
+///
+/// These define the general shape of a transaction in extended mode and the meanings of some
+/// of the time fields. Refer to the microcontroller reference manual for more details.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AccessMode {
+    ModeA = 0b00,
+    ModeB = 0b01,
+    ModeC = 0b10,
+    ModeD = 0b11,
+}
+
+/// Timing configuration for reading or writing, in nanoseconds
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timing {
+    pub access_mode: AccessMode,
+    /// Address setup phase duration (ADDSET)
+    pub address_setup: Nanoseconds,
+    /// Address hold phase duration (ADDHLD), used in mode D and with multiplexed address/data
+    pub address_hold: Nanoseconds,
+    /// Data phase duration (DATAST)
+    pub data_setup: Nanoseconds,
+    /// Bus turnaround phase duration (BUSTURN)
+    pub bus_turnaround: Nanoseconds,
+}
+
+impl Default for Timing {
+    /// Returns a conservative timing with access mode A, suitable for most 55-70 ns parts
+    /// up to 180 MHz HCLK
+    fn default() -> Self {
+        Self {
+            access_mode: AccessMode::ModeA,
+            address_setup: Nanoseconds::from_ticks(20),
+            address_hold: Nanoseconds::from_ticks(10),
+            data_setup: Nanoseconds::from_ticks(70),
+            bus_turnaround: Nanoseconds::from_ticks(10),
+        }
+    }
+}
+
+impl Timing {
+    /// Maximum value of the address setup time, in HCLK cycles
+    pub const ADDRESS_SETUP_MAX: u32 = 15;
+    /// Minimum value of the address hold time, in HCLK cycles
+    pub const ADDRESS_HOLD_MIN: u32 = 1;
+    /// Maximum value of the address hold time, in HCLK cycles
+    pub const ADDRESS_HOLD_MAX: u32 = 15;
+    /// Minimum value of the data phase time, in HCLK cycles
+    pub const DATA_SETUP_MIN: u32 = 1;
+    /// Maximum value of the data phase time, in HCLK cycles
+    pub const DATA_SETUP_MAX: u32 = 255;
+    /// Maximum value of the bus turnaround time, in HCLK cycles
+    pub const BUS_TURNAROUND_MAX: u32 = 15;
+
+    /// Sets the access mode
+    pub fn access_mode(self, access_mode: AccessMode) -> Self {
+        Self {
+            access_mode,
+            ..self
+        }
+    }
+    /// Sets the address setup phase duration
+    pub fn address_setup(self, address_setup: Nanoseconds) -> Self {
+        Self {
+            address_setup,
+            ..self
+        }
+    }
+    /// Sets the address hold phase duration
+    pub fn address_hold(self, address_hold: Nanoseconds) -> Self {
+        Self {
+            address_hold,
+            ..self
+        }
+    }
+    /// Sets the data phase duration
+    pub fn data_setup(self, data_setup: Nanoseconds) -> Self {
+        Self { data_setup, ..self }
+    }
+    /// Sets the bus turnaround phase duration
+    pub fn bus_turnaround(self, bus_turnaround: Nanoseconds) -> Self {
+        Self {
+            bus_turnaround,
+            ..self
+        }
+    }
+
+    /// Converts the timing to HCLK cycles: `(ADDSET, ADDHLD, DATAST, BUSTURN)`
+    pub(crate) fn cycles(&self, clocks: &Clocks) -> Result<(u32, u32, u32, u32), Error> {
+        let hclk = clocks.hclk().raw() as u64;
+        let cycles = |t: Nanoseconds, min: u32, max: u32| {
+            let c = ((t.ticks() as u64 * hclk + 999_999_999) / 1_000_000_000) as u32;
+            let c = c.max(min);
+            if c > max {
+                Err(Error::TimingOutOfRange)
+            } else {
+                Ok(c)
+            }
+        };
+        let addset = cycles(self.address_setup, 0, Self::ADDRESS_SETUP_MAX)?;
+        let addhld = cycles(
+            self.address_hold,
+            Self::ADDRESS_HOLD_MIN,
+            Self::ADDRESS_HOLD_MAX,
+        )?;
+        let datast = cycles(self.data_setup, Self::DATA_SETUP_MIN, Self::DATA_SETUP_MAX)?;
+        let busturn = cycles(self.bus_turnaround, 0, Self::BUS_TURNAROUND_MAX)?;
+        Ok((addset, addhld, datast, busturn))
+    }
+
+    /// Computes the `BTR`/`BWTR` register value for the given HCLK frequency
+    fn register_bits(&self, clocks: &Clocks) -> Result<u32, Error> {
+        let (addset, addhld, datast, busturn) = self.cycles(clocks)?;
+        Ok(addset
+            | (addhld << 4)
+            | (datast << 8)
+            | (busturn << 16)
+            | ((self.access_mode as u32) << 28))
+    }
+}
+
+/// NOR/PSRAM/SRAM bank configuration
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    memory_type: MemoryType,
+    size: usize,
+    multiplexed: bool,
+    write_enable: bool,
+    timing: Timing,
+    write_timing: Option<Timing>,
+}
+
+impl Config {
+    /// Creates a configuration for a memory of `size` bytes, with the default timing
+    pub fn new(memory_type: MemoryType, size: usize) -> Self {
+        Self {
+            memory_type,
+            size,
+            multiplexed: false,
+            write_enable: true,
+            timing: Timing::default(),
+            write_timing: None,
+        }
+    }
+
+    /// Multiplexes the lower address bits on the data bus, `NADV` latches the address
+    pub fn multiplexed(mut self, multiplexed: bool) -> Self {
+        self.multiplexed = multiplexed;
+        self
+    }
+
+    /// Allows or forbids write operations, forbidden writes cause an AHB error
+    pub fn write_enable(mut self, write_enable: bool) -> Self {
+        self.write_enable = write_enable;
+        self
+    }
+
+    /// Sets the timing used for reads, and for writes unless a separate write timing is set
+    pub fn timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Sets a separate write timing, enabling extended mode
+    pub fn write_timing(mut self, timing: Timing) -> Self {
+        self.write_timing = Some(timing);
+        self
+    }
+}
+
+/// Computes the `BCR` register value, with the bank enabled
+fn bcr_bits(config: &Config, width: DataWidth) -> u32 {
+    // Bit 7 is reserved and must be kept at its reset value
+    let mut bcr = (1 << 7) | ((config.memory_type as u32) << 2) | ((width as u32) << 4) | (1 << 0);
+    if config.multiplexed {
+        bcr |= 1 << 1;
+    }
+    if config.memory_type == MemoryType::Nor {
+        // FACCEN
+        bcr |= 1 << 6;
+    }
+    if config.write_enable {
+        // WREN
+        bcr |= 1 << 12;
+    }
+    if config.write_timing.is_some() {
+        // EXTMOD
+        bcr |= 1 << 14;
+    }
+    bcr
+}
+
+/// Enables the FSMC/FMC and configures one sub-bank of bank 1
+fn configure_bank(fsmc: &FSMC, bank: u8, bcr: u32, btr: u32, bwtr: Option<u32>) {
+    unsafe {
+        //NOTE(unsafe) this reference will only be used for atomic writes with no side effects
+        let rcc = &(*RCC::ptr());
+        // Enable the FSMC/FMC peripheral
+        FSMC::enable(rcc);
+    }
+
+    // BWTR is only used in extended mode, otherwise it's kept at its reset value
+    let bwtr = bwtr.unwrap_or(0x0FFF_FFFF);
+    unsafe {
+        match bank {
+            1 => {
+                fsmc.btr1.write(|w| w.bits(btr));
+                fsmc.bwtr1.write(|w| w.bits(bwtr));
+                fsmc.bcr1.write(|w| w.bits(bcr));
+            }
+            2 => {
+                fsmc.btr2.write(|w| w.bits(btr));
+                fsmc.bwtr2.write(|w| w.bits(bwtr));
+                fsmc.bcr2.write(|w| w.bits(bcr));
+            }
+            3 => {
+                fsmc.btr3.write(|w| w.bits(btr));
+                fsmc.bwtr3.write(|w| w.bits(bwtr));
+                fsmc.bcr3.write(|w| w.bits(bcr));
+            }
+            _ => {
+                fsmc.btr4.write(|w| w.bits(btr));
+                fsmc.bwtr4.write(|w| w.bits(bwtr));
+                fsmc.bcr4.write(|w| w.bits(bcr));
+            }
+        }
+    }
+}
+
+/// Resets and disables the FSMC/FMC
+fn disable() {
+    unsafe {
+        //NOTE(unsafe) this reference will only be used for atomic writes with no side effects
+        let rcc = &(*RCC::ptr());
+        FSMC::reset(rcc);
+        FSMC::disable(rcc);
+    }
+}
+
+/// Address of the first byte of a sub-bank of bank 1
+const fn bank_address(bank: u8) -> usize {
+    BANK1_BASE + (bank as usize - 1) * SUB_BANK_SIZE
+}
+
+/// One sub-bank of bank 1 configured for an external SRAM, PSRAM or NOR flash
+pub struct NorSramBank<PINS> {
+    fsmc: FSMC,
+    pins: PINS,
+    memory_type: MemoryType,
+    size: usize,
+}
+
+impl<PINS> NorSramBank<PINS>
+where
+    PINS: Pins,
+{
+    /// Enables the FSMC/FMC and configures the sub-bank selected by the chip select pin
+    ///
+    /// The data width is taken from the data pins. The size must fit in the sub-bank and,
+    /// unless address/data are multiplexed, be addressable by the given address pins.
+    pub fn new(fsmc: FSMC, pins: PINS, config: Config, clocks: &Clocks) -> Result<Self, Error> {
+        let width_shift = match PINS::WIDTH {
+            DataWidth::Bits8 => 0,
+            DataWidth::Bits16 => 1,
+        };
+        if config.size > SUB_BANK_SIZE
+            || (!config.multiplexed && config.size > 1 << (PINS::ADDRESS_LINES + width_shift))
+        {
+            return Err(Error::SizeTooLarge);
+        }
+
+        let btr = config.timing.register_bits(clocks)?;
+        let bwtr = match &config.write_timing {
+            Some(timing) => Some(timing.register_bits(clocks)?),
+            None => None,
+        };
+
+        configure_bank(&fsmc, PINS::BANK, bcr_bits(&config, PINS::WIDTH), btr, bwtr);
+
+        Ok(Self {
+            fsmc,
+            pins,
+            memory_type: config.memory_type,
+            size: config.size,
+        })
+    }
+
+    /// Address of the first byte of the memory
+    pub fn base_address(&self) -> usize {
+        bank_address(PINS::BANK)
+    }
+
+    /// Size of the memory in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Raw pointer to the start of the memory
+    ///
+    /// NOR flash must only be accessed with volatile reads and writes, as they issue
+    /// commands to the device. SRAM and PSRAM can be accessed like any other memory.
+    pub fn as_ptr(&self) -> *mut u16 {
+        self.base_address() as *mut u16
+    }
+
+    /// Returns the memory as a slice of half-words
+    ///
+    /// # Panics
+    ///
+    /// Panics if the memory is a NOR flash.
+    pub fn as_mut_slice(&mut self) -> &mut [u16] {
+        assert!(self.memory_type != MemoryType::Nor);
+        // NOTE(unsafe) the bank is configured and owned, so the memory is valid and not aliased
+        unsafe { core::slice::from_raw_parts_mut(self.as_ptr(), self.size / 2) }
+    }
+
+    /// Leaks the bank, keeping it configured forever, and returns the memory as a slice
+    ///
+    /// This is typically used for framebuffers and heaps.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the memory is a NOR flash.
+    pub fn leak(self) -> &'static mut [u16] {
+        assert!(self.memory_type != MemoryType::Nor);
+        let (ptr, len) = (self.as_ptr(), self.size / 2);
+        core::mem::forget(self);
+        // NOTE(unsafe) the FSMC and pins are never released, so the memory stays valid
+        unsafe { core::slice::from_raw_parts_mut(ptr, len) }
+    }
+
+    /// Resets and disables the FSMC/FMC, and returns it with the pins for other uses
+    pub fn release(self) -> (FSMC, PINS) {
+        disable();
+        (self.fsmc, self.pins)
+    }
+}
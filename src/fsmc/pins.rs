@@ -0,0 +1,300 @@
+//! Pin definitions for external SRAM/NOR flash on the FSMC/FMC
+//!
+//! Signal numbering follows the reference manual: `A0` is the first external address line,
+//! which addresses bytes on an 8-bit bus and half-words on a 16-bit bus.
+
+use super::{sealed, DataWidth};
+use crate::gpio::NoPin;
+
+/// A pin that can be used for address line `A`
+pub trait PinAddress<const A: u8>: sealed::Sealed {}
+/// A pin that can be used for data line `D`
+pub trait PinData<const D: u8>: sealed::Sealed {}
+/// A pin that can be used for the output enable (read enable, NOE) signal
+pub trait PinReadEnable: sealed::Sealed {}
+/// A pin that can be used for the write enable (NWE) signal
+pub trait PinWriteEnable: sealed::Sealed {}
+/// A pin that can be used for the lower byte lane (NBL0) signal
+pub trait PinByteLane0: sealed::Sealed {}
+/// A pin that can be used for the upper byte lane (NBL1) signal
+pub trait PinByteLane1: sealed::Sealed {}
+/// A pin that can be used for the address valid (NADV) signal
+///
+/// Implemented for [`NoPin`] as the signal is only needed with multiplexed address/data.
+pub trait PinAddressValid: sealed::Sealed {}
+
+/// A chip select pin, which determines the sub-bank of bank 1 the memory is mapped to
+pub trait PinChipSelect: sealed::Sealed {
+    /// Sub-bank number, from 1 to 4
+    const BANK: u8;
+}
+
+/// A set of 8 or 16 data pins, `D0` first
+pub trait DataPins: sealed::Sealed {
+    const WIDTH: DataWidth;
+}
+
+impl<D0, D1, D2, D3, D4, D5, D6, D7> DataPins for (D0, D1, D2, D3, D4, D5, D6, D7)
+where
+    D0: PinData<0>,
+    D1: PinData<1>,
+    D2: PinData<2>,
+    D3: PinData<3>,
+    D4: PinData<4>,
+    D5: PinData<5>,
+    D6: PinData<6>,
+    D7: PinData<7>,
+{
+    const WIDTH: DataWidth = DataWidth::Bits8;
+}
+
+impl<D0, D1, D2, D3, D4, D5, D6, D7, D8, D9, D10, D11, D12, D13, D14, D15> DataPins
+    for (
+        D0,
+        D1,
+        D2,
+        D3,
+        D4,
+        D5,
+        D6,
+        D7,
+        D8,
+        D9,
+        D10,
+        D11,
+        D12,
+        D13,
+        D14,
+        D15,
+    )
+where
+    D0: PinData<0>,
+    D1: PinData<1>,
+    D2: PinData<2>,
+    D3: PinData<3>,
+    D4: PinData<4>,
+    D5: PinData<5>,
+    D6: PinData<6>,
+    D7: PinData<7>,
+    D8: PinData<8>,
+    D9: PinData<9>,
+    D10: PinData<10>,
+    D11: PinData<11>,
+    D12: PinData<12>,
+    D13: PinData<13>,
+    D14: PinData<14>,
+    D15: PinData<15>,
+{
+    const WIDTH: DataWidth = DataWidth::Bits16;
+}
+
+/// Address pins `A0` to `An`, given as a tuple in order
+///
+/// `()` is accepted for memories that are only addressed through a multiplexed data bus.
+pub trait AddressPins: sealed::Sealed {
+    /// Number of address lines
+    const LINES: u8;
+}
+
+impl sealed::Sealed for () {}
+impl AddressPins for () {
+    const LINES: u8 = 0;
+}
+
+macro_rules! address_pins {
+    ($($A:ident: $i:literal),+) => {
+        impl<$($A: PinAddress<$i>),+> AddressPins for ($($A,)+) {
+            const LINES: u8 = [$($i),+].len() as u8;
+        }
+    };
+}
+
+// Implements `AddressPins` for every prefix `(A0,)`, `(A0, A1)`, ... of the address bus
+macro_rules! address_pins_prefixes {
+    ([$($done:tt)*] $A:ident: $i:literal $(, $rA:ident: $ri:literal)*) => {
+        address_pins!($($done)* $A: $i);
+        address_pins_prefixes!([$($done)* $A: $i,] $($rA: $ri),*);
+    };
+    ([$($done:tt)*]) => {};
+}
+
+address_pins_prefixes!([]
+    A0: 0, A1: 1, A2: 2, A3: 3, A4: 4, A5: 5, A6: 6, A7: 7, A8: 8, A9: 9,
+    A10: 10, A11: 11, A12: 12, A13: 13, A14: 14, A15: 15, A16: 16, A17: 17, A18: 18, A19: 19,
+    A20: 20, A21: 21, A22: 22, A23: 23, A24: 24, A25: 25
+);
+
+/// Byte lane pins, [`NoPin`] or a `(NBL0, NBL1)` tuple
+///
+/// Byte lanes are required for byte writes to 16-bit memories.
+pub trait ByteLanePins: sealed::Sealed {}
+
+impl sealed::Sealed for NoPin {}
+impl ByteLanePins for NoPin {}
+impl<NBL0: PinByteLane0, NBL1: PinByteLane1> ByteLanePins for (NBL0, NBL1) {}
+
+impl PinAddressValid for NoPin {}
+
+/// Pins used to interface with an external SRAM, PSRAM or NOR flash
+pub struct NorSramPins<D, A, NOE, NWE, NE, NBL = NoPin, NADV = NoPin> {
+    /// 8-bit or 16-bit data bus
+    pub data: D,
+    /// Address bus
+    pub address: A,
+    /// Output enable (read enable)
+    pub read_enable: NOE,
+    /// Write enable
+    pub write_enable: NWE,
+    /// Chip select, selects the sub-bank
+    pub chip_select: NE,
+    /// Byte lanes or [`NoPin`]
+    pub byte_lanes: NBL,
+    /// Address valid or [`NoPin`]
+    pub address_valid: NADV,
+}
+
+/// A set of pins that can be used with [`NorSramBank`](super::NorSramBank)
+pub trait Pins: sealed::Sealed {
+    /// Sub-bank number, from 1 to 4
+    const BANK: u8;
+    /// Data bus width
+    const WIDTH: DataWidth;
+    /// Number of address lines
+    const ADDRESS_LINES: u8;
+}
+
+impl<D, A, NOE, NWE, NE, NBL, NADV> sealed::Sealed for NorSramPins<D, A, NOE, NWE, NE, NBL, NADV>
+where
+    D: DataPins,
+    A: AddressPins,
+    NOE: PinReadEnable,
+    NWE: PinWriteEnable,
+    NE: PinChipSelect,
+    NBL: ByteLanePins,
+    NADV: PinAddressValid,
+{
+}
+
+impl<D, A, NOE, NWE, NE, NBL, NADV> Pins for NorSramPins<D, A, NOE, NWE, NE, NBL, NADV>
+where
+    D: DataPins,
+    A: AddressPins,
+    NOE: PinReadEnable,
+    NWE: PinWriteEnable,
+    NE: PinChipSelect,
+    NBL: ByteLanePins,
+    NADV: PinAddressValid,
+{
+    const BANK: u8 = NE::BANK;
+    const WIDTH: DataWidth = D::WIDTH;
+    const ADDRESS_LINES: u8 = A::LINES;
+}
+
+/// Pins available on all STM32F4 models that have an FSMC/FMC
+mod common_pins {
+    use super::sealed::Sealed;
+    use super::{
+        PinAddress, PinAddressValid, PinByteLane0, PinByteLane1, PinChipSelect, PinData,
+        PinReadEnable, PinWriteEnable,
+    };
+    use crate::gpio::{
+        Pin, AF12, PB7, PD0, PD1, PD10, PD11, PD12, PD13, PD14, PD15, PD4, PD5, PD7, PD8, PD9, PE0,
+        PE1, PE10, PE11, PE12, PE13, PE14, PE15, PE2, PE3, PE4, PE5, PE6, PE7, PE8, PE9, PF0, PF1,
+        PF12, PF13, PF14, PF15, PF2, PF3, PF4, PF5, PG0, PG1, PG10, PG12, PG13, PG14, PG2, PG3,
+        PG4, PG5, PG9,
+    };
+
+    impl<const P: char, const N: u8, MODE> Sealed for Pin<P, N, MODE> {}
+
+    // All FSMC/FMC pins use AF12
+
+    impl PinAddress<0> for PF0<AF12> {}
+    impl PinAddress<1> for PF1<AF12> {}
+    impl PinAddress<2> for PF2<AF12> {}
+    impl PinAddress<3> for PF3<AF12> {}
+    impl PinAddress<4> for PF4<AF12> {}
+    impl PinAddress<5> for PF5<AF12> {}
+    impl PinAddress<6> for PF12<AF12> {}
+    impl PinAddress<7> for PF13<AF12> {}
+    impl PinAddress<8> for PF14<AF12> {}
+    impl PinAddress<9> for PF15<AF12> {}
+    impl PinAddress<10> for PG0<AF12> {}
+    impl PinAddress<11> for PG1<AF12> {}
+    impl PinAddress<12> for PG2<AF12> {}
+    impl PinAddress<13> for PG3<AF12> {}
+    impl PinAddress<14> for PG4<AF12> {}
+    impl PinAddress<15> for PG5<AF12> {}
+    impl PinAddress<16> for PD11<AF12> {}
+    impl PinAddress<17> for PD12<AF12> {}
+    impl PinAddress<18> for PD13<AF12> {}
+    impl PinAddress<19> for PE3<AF12> {}
+    impl PinAddress<20> for PE4<AF12> {}
+    impl PinAddress<21> for PE5<AF12> {}
+    impl PinAddress<22> for PE6<AF12> {}
+    impl PinAddress<23> for PE2<AF12> {}
+    impl PinAddress<24> for PG13<AF12> {}
+    impl PinAddress<25> for PG14<AF12> {}
+
+    impl PinData<0> for PD14<AF12> {}
+    impl PinData<1> for PD15<AF12> {}
+    impl PinData<2> for PD0<AF12> {}
+    impl PinData<3> for PD1<AF12> {}
+    impl PinData<4> for PE7<AF12> {}
+    impl PinData<5> for PE8<AF12> {}
+    impl PinData<6> for PE9<AF12> {}
+    impl PinData<7> for PE10<AF12> {}
+    impl PinData<8> for PE11<AF12> {}
+    impl PinData<9> for PE12<AF12> {}
+    impl PinData<10> for PE13<AF12> {}
+    impl PinData<11> for PE14<AF12> {}
+    impl PinData<12> for PE15<AF12> {}
+    impl PinData<13> for PD8<AF12> {}
+    impl PinData<14> for PD9<AF12> {}
+    impl PinData<15> for PD10<AF12> {}
+
+    impl PinReadEnable for PD4<AF12> {}
+    impl PinWriteEnable for PD5<AF12> {}
+    impl PinByteLane0 for PE0<AF12> {}
+    impl PinByteLane1 for PE1<AF12> {}
+    impl PinAddressValid for PB7<AF12> {}
+
+    impl PinChipSelect for PD7<AF12> {
+        const BANK: u8 = 1;
+    }
+    impl PinChipSelect for PG9<AF12> {
+        const BANK: u8 = 2;
+    }
+    impl PinChipSelect for PG10<AF12> {
+        const BANK: u8 = 3;
+    }
+    impl PinChipSelect for PG12<AF12> {
+        const BANK: u8 = 4;
+    }
+}
+
+/// Additional pins available on some models
+#[cfg(any(feature = "stm32f412", feature = "stm32f413", feature = "stm32f423"))]
+mod extra_pins {
+    use super::{PinChipSelect, PinData, PinReadEnable, PinWriteEnable};
+    use crate::gpio::{
+        AF10, AF12, PA2, PA3, PA4, PA5, PB12, PB14, PC11, PC12, PC2, PC4, PC5, PC6, PD2,
+    };
+
+    // Most FSMC/FMC pins use AF12, but a few use AF10
+
+    impl PinData<4> for PA2<AF12> {}
+    impl PinData<5> for PA3<AF12> {}
+    impl PinData<6> for PA4<AF12> {}
+    impl PinData<7> for PA5<AF12> {}
+    impl PinData<13> for PB12<AF12> {}
+    impl PinData<0> for PB14<AF10> {}
+    impl PinWriteEnable for PC2<AF12> {}
+    impl PinChipSelect for PC4<AF12> {
+        const BANK: u8 = 4;
+    }
+    impl PinReadEnable for PC5<AF12> {}
+    impl PinData<1> for PC6<AF10> {}
+    impl PinData<2> for PC11<AF10> {}
+    impl PinData<3> for PC12<AF10> {}
+    impl PinWriteEnable for PD2<AF10> {}
+}
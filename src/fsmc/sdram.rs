@@ -0,0 +1,454 @@
+//! SDRAM controller of the FMC
+//!
+//! The FMC has two SDRAM banks, selected by `SDNE0`/`SDCKE0` and `SDNE1`/`SDCKE1`, mapped at
+//! `0xc000_0000` and `0xd000_0000`. [`Sdram::new`] programs the controller, runs the JEDEC
+//! initialization sequence (clock enable, precharge all, 8 auto-refresh cycles, load mode
+//! register) and sets the refresh rate. The memory can then be used like internal RAM.
+//!
+//! Timings are given in nanoseconds, as in memory datasheets, and converted to SDCLK
+//! cycles. SDCLK is HCLK divided by 2 or 3, and must not exceed 90 MHz on F4.
+//!
+//! ```ignore
+//! // IS42S16400J on the STM32F429I-DISCO, HCLK 180 MHz
+//! let pins = SdramPins {
+//!     data: (/* PD14, PD15, PD0, PD1, PE7..PE15, PD8, PD9, PD10 */),
+//!     address: (/* PF0..PF5, PF12..PF15, PG0, PG1 */),
+//!     bank_address: (gpiog.pg4.into_alternate(), gpiog.pg5.into_alternate()),
+//!     byte_lanes: (gpioe.pe0.into_alternate(), gpioe.pe1.into_alternate()),
+//!     clock: gpiog.pg8.into_alternate(),
+//!     clock_enable: gpiob.pb5.into_alternate(),
+//!     chip_enable: gpiob.pb6.into_alternate(),
+//!     cas: gpiog.pg15.into_alternate(),
+//!     ras: gpiof.pf11.into_alternate(),
+//!     write_enable: gpioc.pc0.into_alternate(),
+//! };
+//! let mut sdram = Sdram::new(dp.FMC, pins, SdramConfig::is42s16400j(), &clocks, &mut delay)?;
+//! // NOTE(unsafe) called once, the bank is never released
+//! let ram: &'static mut [u8] = unsafe { sdram.as_static_mut_slice() };
+//! ```
+
+use embedded_hal::blocking::delay::DelayUs;
+use fugit::NanosDurationU32 as Nanoseconds;
+
+use super::{disable, sealed, AddressPins, DataPins, Error, PinByteLane0, PinByteLane1, FSMC};
+use crate::rcc::{Clocks, Enable};
+
+/// Start of SDRAM bank 1
+pub const SDRAM_BANK1_BASE: usize = 0xc000_0000;
+/// Start of SDRAM bank 2
+pub const SDRAM_BANK2_BASE: usize = 0xd000_0000;
+
+/// A pin that can be used for the SDRAM clock (SDCLK) signal
+pub trait PinSdClock: sealed::Sealed {}
+/// A pin that can be used for the column address strobe (SDNCAS) signal
+pub trait PinSdCas: sealed::Sealed {}
+/// A pin that can be used for the row address strobe (SDNRAS) signal
+pub trait PinSdRas: sealed::Sealed {}
+/// A pin that can be used for the SDRAM write enable (SDNWE) signal
+pub trait PinSdWriteEnable: sealed::Sealed {}
+/// A pin that can be used for the SDRAM clock enable (SDCKEx) signal
+pub trait PinSdClockEnable: sealed::Sealed {
+    /// SDRAM bank, 1 or 2
+    const BANK: u8;
+}
+/// A pin that can be used for the SDRAM chip enable (SDNEx) signal
+pub trait PinSdChipEnable: sealed::Sealed {
+    /// SDRAM bank, 1 or 2
+    const BANK: u8;
+}
+/// A pin that can be used for internal bank address line `BA`
+pub trait PinBankAddress<const BA: u8>: sealed::Sealed {}
+
+/// Internal bank address pins, `(BA0,)` for 2 internal banks or `(BA0, BA1)` for 4
+pub trait BankAddressPins: sealed::Sealed {
+    /// Number of internal banks
+    const BANKS: u8;
+}
+
+impl<BA0: PinBankAddress<0>> BankAddressPins for (BA0,) {
+    const BANKS: u8 = 2;
+}
+impl<BA0: PinBankAddress<0>, BA1: PinBankAddress<1>> BankAddressPins for (BA0, BA1) {
+    const BANKS: u8 = 4;
+}
+
+/// Pins used to interface with an SDRAM
+pub struct SdramPins<D, A, BA, NBL0, NBL1, CLK, CKE, NE, NCAS, NRAS, NWE> {
+    /// 8-bit or 16-bit data bus
+    pub data: D,
+    /// Address pins `A0` to `An`, at least as many as row address bits
+    pub address: A,
+    /// Internal bank address pins
+    pub bank_address: BA,
+    /// Byte lanes
+    pub byte_lanes: (NBL0, NBL1),
+    pub clock: CLK,
+    /// Clock enable, selects the bank together with the chip enable
+    pub clock_enable: CKE,
+    /// Chip enable
+    pub chip_enable: NE,
+    pub cas: NCAS,
+    pub ras: NRAS,
+    pub write_enable: NWE,
+}
+
+/// A set of pins that can be used with [`Sdram`]
+pub trait SdramPinSet: sealed::Sealed {
+    /// SDRAM bank selected by the clock enable pin
+    const BANK: u8;
+    /// SDRAM bank selected by the chip enable pin
+    const CHIP_ENABLE_BANK: u8;
+    /// Number of address lines
+    const ADDRESS_LINES: u8;
+    /// Number of internal banks
+    const INTERNAL_BANKS: u8;
+    /// Data bus width in bytes
+    const WIDTH_BYTES: usize;
+}
+
+impl<D, A, BA, NBL0, NBL1, CLK, CKE, NE, NCAS, NRAS, NWE> sealed::Sealed
+    for SdramPins<D, A, BA, NBL0, NBL1, CLK, CKE, NE, NCAS, NRAS, NWE>
+where
+    D: DataPins,
+    A: AddressPins,
+    BA: BankAddressPins,
+    NBL0: PinByteLane0,
+    NBL1: PinByteLane1,
+    CLK: PinSdClock,
+    CKE: PinSdClockEnable,
+    NE: PinSdChipEnable,
+    NCAS: PinSdCas,
+    NRAS: PinSdRas,
+    NWE: PinSdWriteEnable,
+{
+}
+
+impl<D, A, BA, NBL0, NBL1, CLK, CKE, NE, NCAS, NRAS, NWE> SdramPinSet
+    for SdramPins<D, A, BA, NBL0, NBL1, CLK, CKE, NE, NCAS, NRAS, NWE>
+where
+    D: DataPins,
+    A: AddressPins,
+    BA: BankAddressPins,
+    NBL0: PinByteLane0,
+    NBL1: PinByteLane1,
+    CLK: PinSdClock,
+    CKE: PinSdClockEnable,
+    NE: PinSdChipEnable,
+    NCAS: PinSdCas,
+    NRAS: PinSdRas,
+    NWE: PinSdWriteEnable,
+{
+    const BANK: u8 = CKE::BANK;
+    const CHIP_ENABLE_BANK: u8 = NE::BANK;
+    const ADDRESS_LINES: u8 = A::LINES;
+    const INTERNAL_BANKS: u8 = BA::BANKS;
+    const WIDTH_BYTES: usize = D::WIDTH as usize + 1;
+}
+
+/// SDCLK divider
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SdClockDivider {
+    /// SDCLK = HCLK / 2
+    Two = 2,
+    /// SDCLK = HCLK / 3
+    Three = 3,
+}
+
+/// SDRAM timings, from the memory datasheet
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SdramTiming {
+    /// Load mode register to active delay (tMRD)
+    pub load_to_active: Nanoseconds,
+    /// Exit self-refresh delay (tXSR)
+    pub exit_self_refresh: Nanoseconds,
+    /// Minimum self-refresh period, or row active time (tRAS)
+    pub self_refresh: Nanoseconds,
+    /// Row cycle delay (tRC)
+    pub row_cycle: Nanoseconds,
+    /// Write recovery time (tWR, tDPL), increased if needed to satisfy the tRAS and tRC
+    /// constraints of the controller
+    pub write_recovery: Nanoseconds,
+    /// Row precharge delay (tRP)
+    pub row_precharge: Nanoseconds,
+    /// Row to column delay (tRCD)
+    pub row_to_column: Nanoseconds,
+}
+
+/// SDRAM bank configuration
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SdramConfig {
+    /// Number of column address bits, 8 to 11
+    pub column_bits: u8,
+    /// Number of row address bits, 11 to 13
+    pub row_bits: u8,
+    /// CAS latency in SDCLK cycles, 1 to 3
+    pub cas_latency: u8,
+    pub clock_divider: SdClockDivider,
+    /// Anticipates the next read commands during CAS latency
+    pub read_burst: bool,
+    /// HCLK cycles of delay for reading data after the CAS latency, 0 to 2
+    pub read_pipe_delay: u8,
+    pub timing: SdramTiming,
+    /// Time in which all rows must be refreshed (tREF)
+    pub refresh_period: Nanoseconds,
+}
+
+impl SdramConfig {
+    /// Configuration for the ISSI IS42S16400J-7 (1M x 16 bits x 4 banks) of the
+    /// STM32F429I-DISCO, CAS latency 3, SDCLK = HCLK / 2
+    pub const fn is42s16400j() -> Self {
+        Self {
+            column_bits: 8,
+            row_bits: 12,
+            cas_latency: 3,
+            clock_divider: SdClockDivider::Two,
+            read_burst: true,
+            read_pipe_delay: 0,
+            timing: SdramTiming {
+                load_to_active: Nanoseconds::from_ticks(14),
+                exit_self_refresh: Nanoseconds::from_ticks(70),
+                self_refresh: Nanoseconds::from_ticks(42),
+                row_cycle: Nanoseconds::from_ticks(63),
+                write_recovery: Nanoseconds::from_ticks(14),
+                row_precharge: Nanoseconds::from_ticks(15),
+                row_to_column: Nanoseconds::from_ticks(15),
+            },
+            refresh_period: Nanoseconds::from_ticks(64_000_000),
+        }
+    }
+}
+
+// SDCMR command modes
+const MODE_CLOCK_ENABLE: u32 = 0b001;
+const MODE_PALL: u32 = 0b010;
+const MODE_AUTO_REFRESH: u32 = 0b011;
+const MODE_LOAD_MODE_REGISTER: u32 = 0b100;
+
+/// An SDRAM bank of the FMC, initialized and refreshed
+pub struct Sdram<PINS> {
+    fmc: FSMC,
+    pins: PINS,
+    size: usize,
+}
+
+impl<PINS> Sdram<PINS>
+where
+    PINS: SdramPinSet,
+{
+    /// Configures the SDRAM controller and initializes the memory
+    ///
+    /// `delay` is used for the 100 µs power-up delay after the clock is enabled.
+    pub fn new(
+        fmc: FSMC,
+        pins: PINS,
+        config: SdramConfig,
+        clocks: &Clocks,
+        delay: &mut impl DelayUs<u32>,
+    ) -> Result<Self, Error> {
+        if PINS::BANK != PINS::CHIP_ENABLE_BANK {
+            return Err(Error::BankMismatch);
+        }
+        if !(8..=11).contains(&config.column_bits)
+            || !(11..=13).contains(&config.row_bits)
+            || !(1..=3).contains(&config.cas_latency)
+            || config.read_pipe_delay > 2
+        {
+            return Err(Error::InvalidConfig);
+        }
+        if config.row_bits > PINS::ADDRESS_LINES || config.column_bits > PINS::ADDRESS_LINES {
+            return Err(Error::SizeTooLarge);
+        }
+
+        let sdclk = clocks.hclk().raw() / config.clock_divider as u32;
+        let cycles = |t: Nanoseconds| -> Result<u32, Error> {
+            let c = ((t.ticks() as u64 * sdclk as u64 + 999_999_999) / 1_000_000_000) as u32;
+            match c.max(1) {
+                c @ 1..=16 => Ok(c),
+                _ => Err(Error::TimingOutOfRange),
+            }
+        };
+        let t = &config.timing;
+        let tmrd = cycles(t.load_to_active)?;
+        let txsr = cycles(t.exit_self_refresh)?;
+        let tras = cycles(t.self_refresh)?;
+        let trc = cycles(t.row_cycle)?;
+        let trp = cycles(t.row_precharge)?;
+        let trcd = cycles(t.row_to_column)?;
+        // The controller requires TWR >= TRAS - TRCD and TWR >= TRC - TRCD - TRP
+        let twr = cycles(t.write_recovery)?
+            .max(tras.saturating_sub(trcd))
+            .max(trc.saturating_sub(trcd + trp));
+        if twr > 16 {
+            return Err(Error::TimingOutOfRange);
+        }
+
+        // Each row is refreshed once per period, minus a 20 cycles safety margin
+        let rows = 1u64 << config.row_bits;
+        let refresh =
+            (config.refresh_period.ticks() as u64 * sdclk as u64 / 1_000_000_000 / rows) as u32;
+        let refresh = refresh.checked_sub(20).ok_or(Error::TimingOutOfRange)?;
+        if !(41..0x2000).contains(&refresh) {
+            return Err(Error::TimingOutOfRange);
+        }
+
+        unsafe {
+            //NOTE(unsafe) this reference will only be used for atomic writes with no side effects
+            let rcc = &(*crate::pac::RCC::ptr());
+            FSMC::enable(rcc);
+        }
+
+        let sdcr = (config.column_bits as u32 - 8)
+            | ((config.row_bits as u32 - 11) << 2)
+            | ((PINS::WIDTH_BYTES as u32 - 1) << 4)
+            | (((PINS::INTERNAL_BANKS == 4) as u32) << 6)
+            | ((config.cas_latency as u32) << 7);
+        // SDCLK, RBURST and RPIPE are only taken from SDCR1
+        let common = ((config.clock_divider as u32) << 10)
+            | ((config.read_burst as u32) << 12)
+            | ((config.read_pipe_delay as u32) << 13);
+        let sdtr = (tmrd - 1)
+            | ((txsr - 1) << 4)
+            | ((tras - 1) << 8)
+            | ((twr - 1) << 16)
+            | ((trcd - 1) << 24);
+        // TRC and TRP are only taken from SDTR1
+        let sdtr_common = ((trc - 1) << 12) | ((trp - 1) << 20);
+
+        unsafe {
+            if PINS::BANK == 1 {
+                fmc.sdcr1().write(|w| w.bits(sdcr | common));
+                fmc.sdtr1().write(|w| w.bits(sdtr | sdtr_common));
+            } else {
+                fmc.sdcr1().write(|w| w.bits(common));
+                fmc.sdcr2().write(|w| w.bits(sdcr));
+                fmc.sdtr1().write(|w| w.bits(sdtr_common));
+                fmc.sdtr2().write(|w| w.bits(sdtr));
+            }
+        }
+
+        let sdram = Self {
+            fmc,
+            pins,
+            size: (1 << (config.row_bits + config.column_bits))
+                * PINS::INTERNAL_BANKS as usize
+                * PINS::WIDTH_BYTES,
+        };
+
+        sdram.command(MODE_CLOCK_ENABLE, 0, 0);
+        delay.delay_us(100);
+        sdram.command(MODE_PALL, 0, 0);
+        sdram.command(MODE_AUTO_REFRESH, 8, 0);
+        // Burst length 1, sequential, standard operation, single location write
+        let mode_register = (1 << 9) | ((config.cas_latency as u32) << 4);
+        sdram.command(MODE_LOAD_MODE_REGISTER, 1, mode_register);
+
+        unsafe {
+            sdram
+                .fmc
+                .sdrtr
+                .modify(|r, w| w.bits((r.bits() & !(0x1fff << 1)) | (refresh << 1)));
+        }
+
+        Ok(sdram)
+    }
+
+    /// Sends a command to the bank and waits until the controller is ready
+    fn command(&self, mode: u32, auto_refresh: u32, mode_register: u32) {
+        let target = if PINS::BANK == 1 { 1 << 4 } else { 1 << 3 };
+        unsafe {
+            self.fmc.sdcmr.write(|w| {
+                w.bits(mode | target | ((auto_refresh.max(1) - 1) << 5) | (mode_register << 9))
+            });
+        }
+        // Wait while BUSY
+        while self.fmc.sdsr.read().bits() & (1 << 5) != 0 {}
+    }
+
+    /// Address of the first byte of the memory
+    pub fn base_address(&self) -> usize {
+        if PINS::BANK == 1 {
+            SDRAM_BANK1_BASE
+        } else {
+            SDRAM_BANK2_BASE
+        }
+    }
+
+    /// Size of the memory in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Raw pointer to the start of the memory
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.base_address() as *mut u8
+    }
+
+    /// Returns the whole memory as a static slice
+    ///
+    /// # Safety
+    ///
+    /// This must be called at most once, and the bank must not be released afterwards,
+    /// as the slice would alias or outlive the configured memory.
+    /// The content of the memory is whatever was left in it, it's not zeroed.
+    pub unsafe fn as_static_mut_slice(&mut self) -> &'static mut [u8] {
+        core::slice::from_raw_parts_mut(self.as_ptr(), self.size)
+    }
+
+    /// Resets and disables the FMC, and returns it with the pins for other uses
+    pub fn release(self) -> (FSMC, PINS) {
+        disable();
+        (self.fmc, self.pins)
+    }
+}
+
+/// SDRAM pins available on all STM32F4 models that have an FMC
+mod sdram_pins {
+    use super::{
+        PinBankAddress, PinSdCas, PinSdChipEnable, PinSdClock, PinSdClockEnable, PinSdRas,
+        PinSdWriteEnable,
+    };
+    use crate::gpio::{AF12, PB5, PB6, PC0, PC2, PC3, PF11, PG15, PG4, PG5, PG8};
+
+    impl PinSdClock for PG8<AF12> {}
+    impl PinSdCas for PG15<AF12> {}
+    impl PinSdRas for PF11<AF12> {}
+    impl PinSdWriteEnable for PC0<AF12> {}
+    impl PinBankAddress<0> for PG4<AF12> {}
+    impl PinBankAddress<1> for PG5<AF12> {}
+    impl PinSdClockEnable for PC3<AF12> {
+        const BANK: u8 = 1;
+    }
+    impl PinSdChipEnable for PC2<AF12> {
+        const BANK: u8 = 1;
+    }
+    impl PinSdClockEnable for PB5<AF12> {
+        const BANK: u8 = 2;
+    }
+    impl PinSdChipEnable for PB6<AF12> {
+        const BANK: u8 = 2;
+    }
+}
+
+/// SDRAM pins on port H
+#[cfg(not(feature = "stm32f446"))]
+mod sdram_pins_h {
+    use super::{PinSdChipEnable, PinSdClockEnable, PinSdWriteEnable};
+    use crate::gpio::{AF12, PH2, PH3, PH5, PH6, PH7};
+
+    impl PinSdWriteEnable for PH5<AF12> {}
+    impl PinSdClockEnable for PH2<AF12> {
+        const BANK: u8 = 1;
+    }
+    impl PinSdChipEnable for PH3<AF12> {
+        const BANK: u8 = 1;
+    }
+    impl PinSdClockEnable for PH7<AF12> {
+        const BANK: u8 = 2;
+    }
+    impl PinSdChipEnable for PH6<AF12> {
+        const BANK: u8 = 2;
+    }
+}
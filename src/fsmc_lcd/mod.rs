@@ -37,6 +37,8 @@
 //! microcontroller and LCD controller, this driver does not try to calculate the correct
 //! timing settings. Instead, it exposes the access modes and timing options that the STM32F4
 //! hardware supports.
+//! [`Timing::from_nanos`] converts the times given in nanoseconds by the LCD controller
+//! datasheet to HCLK cycles.
 //!
 //! The default access mode is mode C. For an example timing diagram, refer to reference manual
 //! [RM0090](https://www.st.com/resource/en/reference_manual/dm00031020.pdf),
@@ -57,6 +59,9 @@
 //!    This function will return an `FsmcLcd` and one or more `Lcd` objects.
 //!
 //! 4. Use the returned `Lcd` object(s) to configure the controller(s) and display graphics
+//!
+//! Large pixel streams and area fills can be written with a DMA2 memory-to-memory transfer,
+//! see [`Lcd::write_data_dma`] and [`Lcd::fill_data_dma`].
 
 mod display_interface_impl;
 mod pins;
@@ -64,6 +69,7 @@ mod sealed;
 mod timing;
 
 use core::marker::PhantomData;
+use core::sync::atomic::{self, Ordering};
 
 pub use self::pins::{
     AddressPins, ChipSelect1, ChipSelect2, ChipSelect3, ChipSelect4, ChipSelectPins, DataPins,
@@ -73,6 +79,8 @@ pub use self::pins::{
 };
 pub use self::timing::{AccessMode, Timing};
 
+use crate::dma::traits::{DMASet, Direction, Stream};
+use crate::dma::MemoryToMemory;
 use crate::pac::RCC;
 use crate::rcc::{Enable, Reset};
 
@@ -391,6 +399,11 @@ fn configure_bwtr(bwtr: &fsmc::BWTR, write_timing: &Timing) {
     })
 }
 
+/// A DMA transfer to the LCD stopped on a transfer error, e.g. a bus error reading the source
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TransferError;
+
 /// An interface to an LCD controller using one sub-bank
 ///
 /// This struct provides low-level read and write commands that can be used to implement
@@ -420,6 +433,104 @@ where
         }
     }
 
+    /// Writes several values with the data/command (address) signals set high
+    pub fn write_data_slice(&mut self, values: &[u16]) {
+        for &value in values {
+            unsafe {
+                core::ptr::write_volatile(S::DATA_ADDRESS as *mut u16, value);
+            }
+        }
+    }
+
+    /// Writes `values` as data with a DMA2 memory-to-memory transfer and waits for the end
+    ///
+    /// The destination is the fixed data address, so the stream's memory increment is
+    /// disabled. The stream is left disabled afterwards and can be reused.
+    pub fn write_data_dma<STREAM>(
+        &mut self,
+        stream: &mut STREAM,
+        values: &[u16],
+    ) -> Result<(), TransferError>
+    where
+        STREAM: Stream,
+        MemoryToMemory<u16>: DMASet<STREAM, 0, MemoryToMemory<u16>>,
+    {
+        for chunk in values.chunks(u16::MAX as usize) {
+            self.dma_transfer(stream, chunk.as_ptr() as u32, true, chunk.len() as u16)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `value` as data `count` times with a DMA2 memory-to-memory transfer, e.g. to
+    /// fill an area with a solid colour, and waits for the end
+    ///
+    /// Both the source and destination addresses are fixed.
+    pub fn fill_data_dma<STREAM>(
+        &mut self,
+        stream: &mut STREAM,
+        value: u16,
+        mut count: usize,
+    ) -> Result<(), TransferError>
+    where
+        STREAM: Stream,
+        MemoryToMemory<u16>: DMASet<STREAM, 0, MemoryToMemory<u16>>,
+    {
+        while count > 0 {
+            let n = count.min(u16::MAX as usize);
+            self.dma_transfer(stream, &value as *const u16 as u32, false, n as u16)?;
+            count -= n;
+        }
+        Ok(())
+    }
+
+    fn dma_transfer<STREAM: Stream>(
+        &mut self,
+        stream: &mut STREAM,
+        source: u32,
+        source_increment: bool,
+        len: u16,
+    ) -> Result<(), TransferError> {
+        stream.disable();
+        while STREAM::is_enabled() {}
+        stream.clear_interrupts();
+
+        stream.set_channel::<0>();
+        stream.set_direction(MemoryToMemory::<u16>::new());
+        // NOTE(unsafe) 1 selects 16-bit transfers
+        unsafe {
+            stream.set_memory_size(1);
+            stream.set_peripheral_size(1);
+        }
+        // In memory-to-memory mode the peripheral port is the source
+        stream.set_peripheral_address(source);
+        stream.set_peripheral_increment(source_increment);
+        stream.set_memory_address(S::DATA_ADDRESS as u32);
+        stream.set_memory_increment(false);
+        stream.set_double_buffer(false);
+        // The FIFO is mandatory in memory-to-memory mode
+        stream.set_fifo_enable(true);
+        stream.set_number_of_transfers(len);
+
+        // Source data must be written before the DMA starts reading it
+        atomic::compiler_fence(Ordering::Release);
+        // NOTE(unsafe) the source outlives the transfer, which is waited for below
+        unsafe { stream.enable() };
+
+        while !STREAM::get_transfer_complete_flag() && !STREAM::get_transfer_error_flag() {}
+        // The stream disables itself on a transfer error, the flag is cleared below
+        let error = STREAM::get_transfer_error_flag();
+        stream.disable();
+        while STREAM::is_enabled() {}
+        stream.clear_interrupts();
+        atomic::compiler_fence(Ordering::Acquire);
+
+        if error {
+            Err(TransferError)
+        } else {
+            Ok(())
+        }
+    }
+
     /// Reads a value with the data/command (address) signals set high
     pub fn read_data(&self) -> u16 {
         unsafe { core::ptr::read_volatile(S::DATA_ADDRESS as *const u16) }
@@ -1,12 +1,14 @@
 //! FMC/FSMC timing
 
 use super::fsmc;
+use crate::rcc::Clocks;
 
 /// Memory access modes
 ///
 /// These define the general shape of a transaction and the meanings of some of the time fields.
 /// Refer to the microcontroller reference manual for more details.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum AccessMode {
     ModeA,
     ModeB,
@@ -46,6 +48,7 @@ impl AccessMode {
 ///
 /// All time fields are in units of HCLK cycles.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Timing {
     pub(crate) access_mode: AccessMode,
     pub(crate) bus_turnaround: u8,
@@ -79,6 +82,48 @@ impl Timing {
     /// Maximum allowed value of the address setup time
     pub const ADDRESS_SETUP_MAX: u8 = 15;
 
+    /// Converts a timing in nanoseconds, e.g. from the LCD controller datasheet, to HCLK
+    /// cycles
+    ///
+    /// For an ILI9341 write cycle: address setup `tast` (0 ns), data setup `tdst` (10 ns) and
+    /// write low pulse `twrl` (15 ns), the data phase has to cover both `twrl` and `tdst`.
+    /// Reads are much slower (`trdl` 45 ns for registers, 355 ns for the frame memory).
+    ///
+    /// ```ignore
+    /// let write = fsmc::Timing::default()
+    ///     .address_setup(0.nanos())
+    ///     .data_setup(15.nanos())
+    ///     .bus_turnaround(0.nanos());
+    /// let read = fsmc::Timing::default()
+    ///     .address_setup(0.nanos())
+    ///     .data_setup(355.nanos());
+    /// let (_fsmc, lcd) = FsmcLcd::new(
+    ///     dp.FSMC,
+    ///     lcd_pins,
+    ///     &Timing::from_nanos(&read, &clocks)?,
+    ///     &Timing::from_nanos(&write, &clocks)?,
+    /// );
+    /// ```
+    pub fn from_nanos(
+        timing: &crate::fsmc::Timing,
+        clocks: &Clocks,
+    ) -> Result<Self, crate::fsmc::Error> {
+        use crate::fsmc::AccessMode as Mode;
+        let (address_setup, address_hold, data, bus_turnaround) = timing.cycles(clocks)?;
+        Ok(Timing {
+            access_mode: match timing.access_mode {
+                Mode::ModeA => AccessMode::ModeA,
+                Mode::ModeB => AccessMode::ModeB,
+                Mode::ModeC => AccessMode::ModeC,
+                Mode::ModeD => AccessMode::ModeD,
+            },
+            bus_turnaround: bus_turnaround as u8,
+            data: data as u8,
+            address_hold: address_hold as u8,
+            address_setup: address_setup as u8,
+        })
+    }
+
     /// Sets the access mode
     pub fn access_mode(self, access_mode: AccessMode) -> Self {
         Timing {
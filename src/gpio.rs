@@ -22,7 +22,8 @@
 //!
 //! Each GPIO pin can be set to various modes:
 //!
-//! - **Alternate**: Pin mode required when the pin is driven by other peripherals
+//! - **Alternate**: Pin mode required when the pin is driven by other peripherals. The line can
+//!   still be read with `is_high`/`is_low`, e.g. to sniff a bus
 //! - **Analog**: Analog input to be used with ADC.
 //! - **Dynamic**: Pin mode is selected at runtime. See changing configurations for more details
 //! - Input
@@ -56,18 +57,23 @@
 
 use core::marker::PhantomData;
 
-mod alt;
+pub mod alt;
 pub(crate) use alt::{Const, PinA, SetAlternate};
 mod convert;
 pub use convert::PinMode;
 mod partially_erased;
 pub use partially_erased::{PEPin, PartiallyErasedPin};
 mod erased;
+pub(crate) use erased::write_bsrr;
 pub use erased::{EPin, ErasedPin};
 mod exti;
 pub use exti::ExtiPin;
 mod dynamic;
 pub use dynamic::{Dynamic, DynamicPin};
+mod group;
+pub use group::{OutputGroup, PinGroup, PinTuple, PortMismatch};
+mod debounce;
+pub use debounce::DebouncedInput;
 mod hal_02;
 mod hal_1;
 
@@ -153,6 +159,7 @@ impl<MODE> marker::Interruptable for Output<MODE> {}
 impl marker::Interruptable for Input {}
 impl marker::Readable for Input {}
 impl marker::Readable for Output<OpenDrain> {}
+impl<const A: u8, Otype> marker::Readable for Alternate<A, Otype> {}
 impl marker::Active for Input {}
 impl<Otype> marker::OutputSpeed for Output<Otype> {}
 impl<const A: u8, Otype> marker::OutputSpeed for Alternate<A, Otype> {}
@@ -228,6 +235,20 @@ impl<const P: char, const N: u8, MODE> Pin<P, N, MODE> {
     const fn new() -> Self {
         Self { _mode: PhantomData }
     }
+
+    /// Creates a pin without splitting the GPIO peripheral.
+    ///
+    /// Useful to get a pin handle in code that can't receive ownership,
+    /// like ISRs or callbacks from C code.
+    ///
+    /// # Safety
+    ///
+    /// The pin is not configured, `MODE` must match its current hardware state.
+    /// The caller must ensure that the pin is not used elsewhere concurrently,
+    /// as the returned handle aliases any other handle to the same pin.
+    pub const unsafe fn steal() -> Self {
+        Self::new()
+    }
 }
 
 impl<const P: char, const N: u8, MODE> fmt::Debug for Pin<P, N, MODE> {
@@ -269,11 +290,12 @@ where
     pub fn set_speed(&mut self, speed: Speed) {
         let offset = 2 * { N };
 
-        unsafe {
+        // NOTE(unsafe) atomic read-modify-write of the field of this pin
+        cortex_m::interrupt::free(|_| unsafe {
             (*Gpio::<P>::ptr())
                 .ospeedr
-                .modify(|r, w| w.bits((r.bits() & !(0b11 << offset)) | ((speed as u32) << offset)));
-        }
+                .modify(|r, w| w.bits(replace_field(r.bits(), offset, 2, speed as u32)));
+        });
     }
 
     /// Set pin speed
@@ -291,11 +313,12 @@ where
     pub fn set_internal_resistor(&mut self, resistor: Pull) {
         let offset = 2 * { N };
         let value = resistor as u32;
-        unsafe {
+        // NOTE(unsafe) atomic read-modify-write of the field of this pin
+        cortex_m::interrupt::free(|_| unsafe {
             (*Gpio::<P>::ptr())
                 .pupdr
-                .modify(|r, w| w.bits((r.bits() & !(0b11 << offset)) | (value << offset)));
-        }
+                .modify(|r, w| w.bits(replace_field(r.bits(), offset, 2, value)));
+        });
     }
 
     /// Set the internal pull-up and pull-down resistor
@@ -359,6 +382,36 @@ impl<const P: char, const N: u8, MODE> From<Pin<P, N, MODE>> for ErasedPin<MODE>
     }
 }
 
+/// BSRR value toggling the pins in `mask` from their `odr` state: set bits for pins that are
+/// low, reset bits for pins that are high
+#[inline(always)]
+const fn toggle_bits(odr: u32, mask: u32) -> u32 {
+    ((odr & mask) << 16) | (!odr & mask)
+}
+
+/// Is pin `n` low in `reg`, `ODR` for the driven level and `IDR` for the line level
+#[inline(always)]
+const fn is_pin_low(reg: u32, n: u8) -> bool {
+    reg & (1 << n) == 0
+}
+
+/// BSRR value driving pin `n` to `state`
+#[inline(always)]
+const fn state_bits(n: u8, state: PinState) -> u32 {
+    match state {
+        PinState::High => 1 << n,
+        PinState::Low => 1 << (16 + n),
+    }
+}
+
+/// `reg` with the `width` bits field at `offset` replaced by `value`, for the per pin fields
+/// of `MODER`, `OSPEEDR`, `PUPDR` and `AFRL`/`AFRH`
+#[inline(always)]
+const fn replace_field(reg: u32, offset: u8, width: u8, value: u32) -> u32 {
+    let mask = ((1 << width) - 1) << offset;
+    (reg & !mask) | ((value << offset) & mask)
+}
+
 impl<const P: char, const N: u8, MODE> Pin<P, N, MODE> {
     /// Set the output of the pin regardless of its mode.
     /// Primarily used to set the output value of the pin
@@ -366,30 +419,40 @@ impl<const P: char, const N: u8, MODE> Pin<P, N, MODE> {
     /// a short spike of an incorrect value
     #[inline(always)]
     fn _set_state(&mut self, state: PinState) {
-        match state {
-            PinState::High => self._set_high(),
-            PinState::Low => self._set_low(),
+        // NOTE(unsafe) atomic write to a stateless register
+        unsafe {
+            (*Gpio::<P>::ptr())
+                .bsrr
+                .write(|w| w.bits(state_bits(N, state)))
         }
     }
     #[inline(always)]
     fn _set_high(&mut self) {
         // NOTE(unsafe) atomic write to a stateless register
-        unsafe { (*Gpio::<P>::ptr()).bsrr.write(|w| w.bits(1 << N)) }
+        unsafe {
+            (*Gpio::<P>::ptr())
+                .bsrr
+                .write(|w| w.bits(state_bits(N, PinState::High)))
+        }
     }
     #[inline(always)]
     fn _set_low(&mut self) {
         // NOTE(unsafe) atomic write to a stateless register
-        unsafe { (*Gpio::<P>::ptr()).bsrr.write(|w| w.bits(1 << (16 + N))) }
+        unsafe {
+            (*Gpio::<P>::ptr())
+                .bsrr
+                .write(|w| w.bits(state_bits(N, PinState::Low)))
+        }
     }
     #[inline(always)]
     fn _is_set_low(&self) -> bool {
         // NOTE(unsafe) atomic read with no side effects
-        unsafe { (*Gpio::<P>::ptr()).odr.read().bits() & (1 << N) == 0 }
+        is_pin_low(unsafe { (*Gpio::<P>::ptr()).odr.read().bits() }, N)
     }
     #[inline(always)]
     fn _is_low(&self) -> bool {
         // NOTE(unsafe) atomic read with no side effects
-        unsafe { (*Gpio::<P>::ptr()).idr.read().bits() & (1 << N) == 0 }
+        is_pin_low(unsafe { (*Gpio::<P>::ptr()).idr.read().bits() }, N)
     }
 }
 
@@ -438,14 +501,31 @@ impl<const P: char, const N: u8, MODE> Pin<P, N, Output<MODE>> {
     }
 
     /// Toggle pin output
+    ///
+    /// ODR is read once and the result is written with a single BSRR store, which only
+    /// touches this pin. A write to the pin from an interrupt between the read and the store
+    /// is overridden, see [`toggle_atomic`](Self::toggle_atomic).
     #[inline(always)]
     pub fn toggle(&mut self) {
-        if self.is_set_low() {
-            self.set_high()
-        } else {
-            self.set_low()
+        // NOTE(unsafe) atomic read with no side effects
+        let odr = unsafe { (*Gpio::<P>::ptr()).odr.read().bits() };
+        let mask = 1 << N;
+        // NOTE(unsafe) atomic write to a stateless register
+        unsafe {
+            (*Gpio::<P>::ptr())
+                .bsrr
+                .write(|w| w.bits(toggle_bits(odr, mask)))
         }
     }
+
+    /// Toggle pin output with interrupts disabled between the ODR read and the BSRR store
+    ///
+    /// The GPIO has no toggle register, so this is the only way to not lose a write to the pin
+    /// from an interrupt handler.
+    #[inline(always)]
+    pub fn toggle_atomic(&mut self) {
+        cortex_m::interrupt::free(|_| self.toggle())
+    }
 }
 
 impl<const P: char, const N: u8, MODE> Pin<P, N, MODE>
@@ -465,6 +545,24 @@ where
     }
 }
 
+impl<const P: char, const N: u8> Pin<P, N, Output<OpenDrain>> {
+    /// Is the line high?
+    ///
+    /// Reads the actual level from IDR, which is low when another device pulls the line
+    /// down while the pin is released, unlike [`is_set_high`](Self::is_set_high) which
+    /// reads the driven level from ODR. Same as [`is_high`](Self::is_high).
+    #[inline(always)]
+    pub fn is_line_high(&self) -> bool {
+        !self._is_low()
+    }
+
+    /// Is the line low? See [`is_line_high`](Self::is_line_high)
+    #[inline(always)]
+    pub fn is_line_low(&self) -> bool {
+        self._is_low()
+    }
+}
+
 macro_rules! gpio {
     ($GPIOX:ident, $gpiox:ident, $PEPin:ident, $port_id:expr, $PXn:ident, [
         $($PXi:ident: ($pxi:ident, $i:expr, [$($A:literal),*] $(, $MODE:ty)?),)+
@@ -1868,3 +1966,119 @@ impl<const P: char> Gpio<P> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_pin_low, marker, replace_field, state_bits, toggle_bits, Alternate, ErasedPin, Input,
+        OpenDrain, Output, PartiallyErasedPin, Pin, PinExt, PinState, PushPull,
+    };
+
+    #[test]
+    fn toggle_bits_only_touch_masked_pin() {
+        // PA5 low: only its set bit
+        assert_eq!(toggle_bits(0x0000_0000, 1 << 5), 1 << 5);
+        // PA5 high: only its reset bit
+        assert_eq!(toggle_bits(0x0000_0020, 1 << 5), 1 << (5 + 16));
+        // Other pins high or low don't show up in the BSRR value
+        assert_eq!(toggle_bits(0x0000_ffdf, 1 << 5), 1 << 5);
+        assert_eq!(toggle_bits(0x0000_8020, 1 << 5), 1 << (5 + 16));
+        assert_eq!(toggle_bits(0x0000_8000, 1 << 15), 1 << 31);
+    }
+
+    #[test]
+    fn toggle_bits_never_set_and_reset_the_same_pin() {
+        for n in 0..16 {
+            let mask = 1 << n;
+            for odr in [0, 0xffff, 0xaaaa, 0x5555] {
+                let bsrr = toggle_bits(odr, mask);
+                assert_eq!(bsrr.count_ones(), 1);
+                assert_eq!((bsrr | bsrr >> 16) & 0xffff, mask);
+            }
+        }
+    }
+
+    fn is_readable<MODE: marker::Readable>() {}
+
+    fn bsrr_bits(pin: &impl PinExt) -> (u32, u32) {
+        (
+            state_bits(pin.pin_id(), PinState::High),
+            state_bits(pin.pin_id(), PinState::Low),
+        )
+    }
+
+    #[test]
+    fn stolen_pins_write_owned_bits() {
+        type Pa0 = Pin<'A', 0, Output<PushPull>>;
+        type Pa15 = Pin<'A', 15, Output<PushPull>>;
+        let pa0 = (1, 1 << 16);
+        let pa15 = (1 << 15, 1 << 31);
+
+        assert_eq!(bsrr_bits(&Pa0::new()), pa0);
+        assert_eq!(bsrr_bits(&unsafe { Pa0::steal() }), pa0);
+        assert_eq!(bsrr_bits(&Pa0::new().erase()), pa0);
+        assert_eq!(bsrr_bits(&unsafe { ErasedPin::<Output>::steal(0, 0) }), pa0);
+        assert_eq!(bsrr_bits(&Pa0::new().erase_number()), pa0);
+        assert_eq!(
+            bsrr_bits(&unsafe { PartiallyErasedPin::<'A', Output>::steal(0) }),
+            pa0
+        );
+
+        assert_eq!(bsrr_bits(&Pa15::new()), pa15);
+        assert_eq!(bsrr_bits(&unsafe { Pa15::steal() }), pa15);
+        assert_eq!(bsrr_bits(&Pa15::new().erase()), pa15);
+        assert_eq!(
+            bsrr_bits(&unsafe { ErasedPin::<Output>::steal(0, 15) }),
+            pa15
+        );
+        assert_eq!(bsrr_bits(&Pa15::new().erase_number()), pa15);
+        assert_eq!(
+            bsrr_bits(&unsafe { PartiallyErasedPin::<'A', Output>::steal(15) }),
+            pa15
+        );
+    }
+
+    #[test]
+    fn alternate_is_readable() {
+        is_readable::<Alternate<4, OpenDrain>>();
+        is_readable::<Alternate<7, PushPull>>();
+        is_readable::<Input>();
+        is_readable::<Output<OpenDrain>>();
+    }
+
+    #[test]
+    fn released_line_pulled_low() {
+        // Open drain PB7 released (ODR high) while another device holds the line low
+        let odr = 1 << 7;
+        let idr = 0x0000_ff7f;
+        // is_set_high
+        assert!(!is_pin_low(odr, 7));
+        // is_line_high
+        assert!(is_pin_low(idr, 7));
+        assert!(!is_pin_low(idr, 6));
+        assert!(!is_pin_low(idr, 8));
+    }
+
+    #[test]
+    fn replace_field_keeps_other_pins() {
+        // GPIOA MODER reset value: PA13/PA14/PA15 in alternate mode for the debugger
+        let moder = 0xa800_0000;
+        // PA0 as output while PA1 stays an input
+        let moder = replace_field(moder, 0, 2, 0b01);
+        assert_eq!(moder, 0xa800_0001);
+        assert_eq!((moder >> 2) & 0b11, 0b00);
+        // PA1 as analog leaves PA0 an output
+        let moder = replace_field(moder, 2, 2, 0b11);
+        assert_eq!(moder, 0xa800_000d);
+        // back to input
+        assert_eq!(replace_field(moder, 2, 2, 0b00), 0xa800_0001);
+    }
+
+    #[test]
+    fn replace_field_afr_nibble() {
+        assert_eq!(replace_field(0xffff_ffff, 4 * 3, 4, 7), 0xffff_7fff);
+        assert_eq!(replace_field(0, 4 * 7, 4, 15), 0xf000_0000);
+        // out of range values don't spill into the neighbour
+        assert_eq!(replace_field(0, 0, 2, 0b111), 0b11);
+    }
+}
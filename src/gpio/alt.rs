@@ -1,6 +1,25 @@
+//! Alternate function mapping of the pins
+//!
+//! Each valid pin of a peripheral function implements [`PinA`], generated by the `pin!`
+//! tables of this module for the selected chip. `PinA<FUNCTION, PER>::A` is the alternate
+//! function number, so a pin can be checked at compile time, for example with a
+//! `PIN: PinA<serial::TxPin, pac::USART1>` bound.
+//!
+//! The per peripheral marker traits, like [`usart1::Tx`], are shorter bounds giving errors
+//! that name the function:
+//!
+//! ```ignore
+//! use stm32f4xx_hal::gpio::alt::usart1;
+//!
+//! fn usart1_tx<PIN: usart1::Tx>(pin: PIN) {}
+//! usart1_tx(gpioa.pa9); // ok, AF7
+//! usart1_tx(gpioa.pa3); // error: the trait bound `Pin<'A', 3>: usart1::Tx` is not satisfied
+//! ```
+
 use super::{marker, Alternate, NoPin, OpenDrain, Pin, PinMode, PushPull};
 use crate::{gpio, i2c, i2s, pac, serial, spi};
 
+/// Alternate function number `A`
 pub struct Const<const A: u8>;
 
 pub trait SetAlternate<const A: u8, Otype> {
@@ -49,7 +68,9 @@ impl<const P: char, const N: u8, const A: u8> SetAlternate<A, OpenDrain>
     fn restore_mode(&mut self) {}
 }
 
+/// Pin usable as the `PIN` function of the `PER` peripheral
 pub trait PinA<PIN, PER> {
+    /// Alternate function number, as [`Const`]
     type A;
 }
 
@@ -73,6 +94,147 @@ macro_rules! pin {
     };
 }
 
+/// Marker traits naming the pins of each peripheral function
+///
+/// `alt::usart1::Tx` is implemented for the pins usable as `USART1_TX`, so a bound on it
+/// fails with "the trait bound `Pin<'A', 3>: usart1::Tx` is not satisfied" when a wrong pin
+/// is given. The traits are implemented from the [`PinA`] tables, they can't get out of sync.
+macro_rules! markers {
+    ($($(#[$attr:meta])* $module:ident: $PER:ident {
+        $($Marker:ident: $Function:ty = $signal:literal),+
+    })+) => {
+        $(
+            $(#[$attr])*
+            #[doc = concat!("Pins of ", stringify!($PER))]
+            pub mod $module {
+                use super::{pac, PinA};
+                $(
+                    #[doc = concat!("Pin usable as `", $signal, "`")]
+                    pub trait $Marker: PinA<$Function, pac::$PER> {}
+                    impl<PIN: PinA<$Function, pac::$PER>> $Marker for PIN {}
+                )+
+            }
+        )+
+    };
+}
+
+markers! {
+    usart1: USART1 {
+        Tx: crate::serial::TxPin = "USART1_TX",
+        Rx: crate::serial::RxPin = "USART1_RX"
+    }
+    usart2: USART2 {
+        Tx: crate::serial::TxPin = "USART2_TX",
+        Rx: crate::serial::RxPin = "USART2_RX"
+    }
+    #[cfg(feature = "usart3")]
+    usart3: USART3 {
+        Tx: crate::serial::TxPin = "USART3_TX",
+        Rx: crate::serial::RxPin = "USART3_RX"
+    }
+    #[cfg(feature = "uart4")]
+    uart4: UART4 {
+        Tx: crate::serial::TxPin = "UART4_TX",
+        Rx: crate::serial::RxPin = "UART4_RX"
+    }
+    #[cfg(feature = "uart5")]
+    uart5: UART5 {
+        Tx: crate::serial::TxPin = "UART5_TX",
+        Rx: crate::serial::RxPin = "UART5_RX"
+    }
+    usart6: USART6 {
+        Tx: crate::serial::TxPin = "USART6_TX",
+        Rx: crate::serial::RxPin = "USART6_RX"
+    }
+    #[cfg(feature = "uart7")]
+    uart7: UART7 {
+        Tx: crate::serial::TxPin = "UART7_TX",
+        Rx: crate::serial::RxPin = "UART7_RX"
+    }
+    #[cfg(feature = "uart8")]
+    uart8: UART8 {
+        Tx: crate::serial::TxPin = "UART8_TX",
+        Rx: crate::serial::RxPin = "UART8_RX"
+    }
+    #[cfg(feature = "uart9")]
+    uart9: UART9 {
+        Tx: crate::serial::TxPin = "UART9_TX",
+        Rx: crate::serial::RxPin = "UART9_RX"
+    }
+    #[cfg(feature = "uart10")]
+    uart10: UART10 {
+        Tx: crate::serial::TxPin = "UART10_TX",
+        Rx: crate::serial::RxPin = "UART10_RX"
+    }
+    spi1: SPI1 {
+        Sck: crate::spi::Sck = "SPI1_SCK",
+        Miso: crate::spi::Miso = "SPI1_MISO",
+        Mosi: crate::spi::Mosi = "SPI1_MOSI"
+    }
+    spi2: SPI2 {
+        Sck: crate::spi::Sck = "SPI2_SCK",
+        Miso: crate::spi::Miso = "SPI2_MISO",
+        Mosi: crate::spi::Mosi = "SPI2_MOSI"
+    }
+    #[cfg(feature = "spi3")]
+    spi3: SPI3 {
+        Sck: crate::spi::Sck = "SPI3_SCK",
+        Miso: crate::spi::Miso = "SPI3_MISO",
+        Mosi: crate::spi::Mosi = "SPI3_MOSI"
+    }
+    #[cfg(feature = "spi4")]
+    spi4: SPI4 {
+        Sck: crate::spi::Sck = "SPI4_SCK",
+        Miso: crate::spi::Miso = "SPI4_MISO",
+        Mosi: crate::spi::Mosi = "SPI4_MOSI"
+    }
+    #[cfg(feature = "spi5")]
+    spi5: SPI5 {
+        Sck: crate::spi::Sck = "SPI5_SCK",
+        Miso: crate::spi::Miso = "SPI5_MISO",
+        Mosi: crate::spi::Mosi = "SPI5_MOSI"
+    }
+    #[cfg(feature = "spi6")]
+    spi6: SPI6 {
+        Sck: crate::spi::Sck = "SPI6_SCK",
+        Miso: crate::spi::Miso = "SPI6_MISO",
+        Mosi: crate::spi::Mosi = "SPI6_MOSI"
+    }
+    i2c1: I2C1 {
+        Scl: crate::i2c::Scl = "I2C1_SCL",
+        Sda: crate::i2c::Sda = "I2C1_SDA"
+    }
+    i2c2: I2C2 {
+        Scl: crate::i2c::Scl = "I2C2_SCL",
+        Sda: crate::i2c::Sda = "I2C2_SDA"
+    }
+    #[cfg(feature = "i2c3")]
+    i2c3: I2C3 {
+        Scl: crate::i2c::Scl = "I2C3_SCL",
+        Sda: crate::i2c::Sda = "I2C3_SDA"
+    }
+    #[cfg(feature = "fmpi2c1")]
+    fmpi2c1: FMPI2C1 {
+        Scl: crate::i2c::Scl = "FMPI2C1_SCL",
+        Sda: crate::i2c::Sda = "FMPI2C1_SDA"
+    }
+    #[cfg(all(feature = "can", feature = "can1"))]
+    can1: CAN1 {
+        Tx: crate::can::Tx = "CAN1_TX",
+        Rx: crate::can::Rx = "CAN1_RX"
+    }
+    #[cfg(all(feature = "can", feature = "can2"))]
+    can2: CAN2 {
+        Tx: crate::can::Tx = "CAN2_TX",
+        Rx: crate::can::Rx = "CAN2_RX"
+    }
+    #[cfg(all(feature = "can", feature = "can3"))]
+    can3: CAN3 {
+        Tx: crate::can::Tx = "CAN3_TX",
+        Rx: crate::can::Rx = "CAN3_RX"
+    }
+}
+
 // CAN pins
 
 #[cfg(all(feature = "can", any(feature = "can1", feature = "can2")))]
@@ -145,6 +307,28 @@ mod can {
     }
 }
 
+// DFSDM pins
+
+#[cfg(feature = "dfsdm")]
+mod dfsdm {
+    use super::*;
+    use crate::dfsdm::{Ckout, Datin};
+
+    #[cfg(feature = "stm32f412")]
+    pin! {
+        <Ckout, DFSDM> for [PC2<6>],
+        <Datin<0>, DFSDM> for [PD3<6>],
+        <Datin<1>, DFSDM> for [PB1<6>, PD6<6>]
+    }
+
+    #[cfg(any(feature = "stm32f413", feature = "stm32f423"))]
+    pin! {
+        <Ckout, DFSDM1> for [PC2<6>],
+        <Datin<0>, DFSDM1> for [PD3<6>],
+        <Datin<1>, DFSDM1> for [PB1<6>, PD6<6>]
+    }
+}
+
 // I2C pins
 
 pin! {
@@ -612,6 +796,24 @@ pin! { <i2s::Mck,  SPI1> for [PC4<5>] }
 #[cfg(feature = "stm32f410")]
 pin! { <i2s::Mck,  SPI1> for [PC7<6>, PB10<6>] }
 
+// LPTIM pins
+
+#[cfg(feature = "lptim1")]
+mod lptim {
+    use super::*;
+    use crate::lptim;
+
+    #[cfg(feature = "stm32f410")]
+    pin! {
+        <lptim::Out, LPTIM1> for [PB2<1>]
+    }
+
+    #[cfg(any(feature = "stm32f413", feature = "stm32f423"))]
+    pin! {
+        <lptim::Out, LPTIM> for [PB2<1>]
+    }
+}
+
 // Serial pins
 
 pin! {
@@ -1,4 +1,5 @@
 use super::*;
+use crate::bb;
 
 impl<const P: char, const N: u8, const A: u8> Pin<P, N, Alternate<A, PushPull>> {
     /// Turns pin alternate configuration pin into open drain
@@ -154,7 +155,8 @@ impl<const P: char, const N: u8, MODE: PinMode> Pin<P, N, MODE> {
 
     /// Configures the pin to operate as an open drain output pin
     /// Initial state will be low.
-    pub fn into_open_drain_output(self) -> Pin<P, N, Output<OpenDrain>> {
+    pub fn into_open_drain_output(mut self) -> Pin<P, N, Output<OpenDrain>> {
+        self._set_low();
         self.into_mode()
     }
 
@@ -200,6 +202,10 @@ impl<const P: char, const N: u8, MODE: PinMode> Pin<P, N, MODE> {
 
     /// Puts `self` into mode `M`.
     ///
+    /// Only the bits of this pin are changed: `OTYPER` through bit-banding, `AFR` and `MODER`
+    /// with read-modify-writes in a critical section, so interrupts configuring other pins
+    /// of the same port can't lose an update.
+    ///
     /// This violates the type state constraints from `MODE`, so callers must
     /// ensure they use this properly.
     #[inline(always)]
@@ -208,33 +214,33 @@ impl<const P: char, const N: u8, MODE: PinMode> Pin<P, N, MODE> {
         unsafe {
             if MODE::OTYPER != M::OTYPER {
                 if let Some(otyper) = M::OTYPER {
-                    (*Gpio::<P>::ptr())
-                        .otyper
-                        .modify(|r, w| w.bits(r.bits() & !(0b1 << N) | (otyper << N)));
+                    bb::write(&(*Gpio::<P>::ptr()).otyper, N, otyper != 0);
                 }
             }
 
-            if MODE::AFR != M::AFR {
-                if let Some(afr) = M::AFR {
-                    if N < 8 {
-                        let offset2 = 4 * { N };
-                        (*Gpio::<P>::ptr()).afrl.modify(|r, w| {
-                            w.bits((r.bits() & !(0b1111 << offset2)) | (afr << offset2))
-                        });
-                    } else {
-                        let offset2 = 4 * { N - 8 };
-                        (*Gpio::<P>::ptr()).afrh.modify(|r, w| {
-                            w.bits((r.bits() & !(0b1111 << offset2)) | (afr << offset2))
-                        });
+            cortex_m::interrupt::free(|_| {
+                if MODE::AFR != M::AFR {
+                    if let Some(afr) = M::AFR {
+                        if N < 8 {
+                            let offset2 = 4 * { N };
+                            (*Gpio::<P>::ptr())
+                                .afrl
+                                .modify(|r, w| w.bits(replace_field(r.bits(), offset2, 4, afr)));
+                        } else {
+                            let offset2 = 4 * { N - 8 };
+                            (*Gpio::<P>::ptr())
+                                .afrh
+                                .modify(|r, w| w.bits(replace_field(r.bits(), offset2, 4, afr)));
+                        }
                     }
                 }
-            }
 
-            if MODE::MODER != M::MODER {
-                (*Gpio::<P>::ptr())
-                    .moder
-                    .modify(|r, w| w.bits((r.bits() & !(0b11 << offset)) | (M::MODER << offset)));
-            }
+                if MODE::MODER != M::MODER {
+                    (*Gpio::<P>::ptr())
+                        .moder
+                        .modify(|r, w| w.bits(replace_field(r.bits(), offset, 2, M::MODER)));
+                }
+            });
         }
     }
 
@@ -408,3 +414,49 @@ impl<const A: u8> PinMode for Alternate<A, PushPull> {
     const OTYPER: Option<u32> = Some(0b0);
     const AFR: Option<u32> = Some(A as _);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output data and mode of a port, as seen on the pads
+    struct Port {
+        odr: u32,
+        moder: u32,
+    }
+
+    impl Port {
+        fn write_bsrr(&mut self, bsrr: u32) {
+            self.odr = (self.odr & !(bsrr >> 16)) | (bsrr & 0xffff);
+        }
+
+        fn set_mode<M: PinMode>(&mut self, n: u8) {
+            self.moder = replace_field(self.moder, 2 * n, 2, M::MODER);
+        }
+
+        /// Level the pad drives, `None` while it is not an output
+        fn driven(&self, n: u8) -> Option<bool> {
+            let output = (self.moder >> (2 * n)) & 0b11 == Output::<PushPull>::MODER;
+            output.then(|| self.odr & (1 << n) != 0)
+        }
+    }
+
+    #[test]
+    fn odr_written_before_moder() {
+        for state in [PinState::Low, PinState::High] {
+            // Input left high by a previous output
+            let mut port = Port {
+                odr: 0xffff,
+                moder: 0,
+            };
+            // Same steps as `into_push_pull_output_in_state`
+            port.write_bsrr(state_bits(5, state));
+            assert_eq!(port.driven(5), None);
+            port.set_mode::<Output<PushPull>>(5);
+            assert_eq!(port.driven(5), Some(state == PinState::High));
+            // Other pins unchanged
+            assert_eq!(port.odr | (1 << 5), 0xffff);
+            assert_eq!(port.moder, 0b01 << 10);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Debouncing of mechanical inputs such as buttons
+//!
+//! [`DebouncedInput`] is fed in one of two ways:
+//!
+//! - Polling: [`poll`](DebouncedInput::poll) is called at a fixed rate, e.g. from a timer
+//!   interrupt. An integrator counts up while the input is active and down while it is
+//!   not, saturating at `DEPTH`. The input is pressed once the integrator reaches the up
+//!   threshold and released once it falls to the down threshold. Choose `DEPTH` for the
+//!   bounce time of the input: at 1 kHz, `DEPTH = 20` filters 20 ms, at 100 Hz `DEPTH = 2`
+//!   filters the same.
+//! - Edge lockout: [`on_edge`](DebouncedInput::on_edge) is called from the EXTI interrupt
+//!   of the pin. The first edge is reported immediately, and when it returns `true` a
+//!   one-shot timer has to be started, whose interrupt calls
+//!   [`end_lockout`](DebouncedInput::end_lockout). Edges during the lockout are ignored.
+//!
+//! ```ignore
+//! let mut button = DebouncedInput::<_, 20>::new(gpioa.pa0.into_pull_up_input(), PinState::Low);
+//!
+//! // In the 1 kHz timer interrupt
+//! button.poll().unwrap();
+//! if button.rising_event() {
+//!     // pressed
+//! }
+//! ```
+
+use embedded_hal_one::digital::InputPin;
+
+use super::PinState;
+
+/// Debounced input, with an integrator of `DEPTH` samples when polled
+pub struct DebouncedInput<PIN, const DEPTH: u8 = 8> {
+    pin: PIN,
+    active: PinState,
+    integrator: u8,
+    up: u8,
+    down: u8,
+    pressed: bool,
+    rising: bool,
+    falling: bool,
+    locked: bool,
+}
+
+impl<PIN: InputPin, const DEPTH: u8> DebouncedInput<PIN, DEPTH> {
+    /// Debounces `pin`, which is at the `active` level while pressed
+    ///
+    /// The input starts released, with the thresholds at `DEPTH` and `0`.
+    pub fn new(pin: PIN, active: PinState) -> Self {
+        assert!(DEPTH > 0);
+        Self {
+            pin,
+            active,
+            integrator: 0,
+            up: DEPTH,
+            down: 0,
+            pressed: false,
+            rising: false,
+            falling: false,
+            locked: false,
+        }
+    }
+
+    /// Sets the integrator thresholds: the input is pressed once the integrator reaches
+    /// `up` and released once it falls to `down`
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `down < up <= DEPTH`.
+    pub fn with_thresholds(mut self, up: u8, down: u8) -> Self {
+        assert!(down < up && up <= DEPTH);
+        self.up = up;
+        self.down = down;
+        self
+    }
+
+    /// Samples the pin, to be called at a fixed rate
+    pub fn poll(&mut self) -> Result<(), PIN::Error> {
+        if self.is_active()? {
+            self.integrator = self.integrator.saturating_add(1).min(DEPTH);
+        } else {
+            self.integrator = self.integrator.saturating_sub(1);
+        }
+
+        if !self.pressed && self.integrator >= self.up {
+            self.set_pressed(true);
+        } else if self.pressed && self.integrator <= self.down {
+            self.set_pressed(false);
+        }
+        Ok(())
+    }
+
+    /// Handles an edge interrupt of the pin, returns `true` if the lockout timer has to
+    /// be started
+    ///
+    /// Does nothing during a lockout or if the level did not change.
+    pub fn on_edge(&mut self) -> Result<bool, PIN::Error> {
+        if self.locked {
+            return Ok(false);
+        }
+        let active = self.is_active()?;
+        if active == self.pressed {
+            return Ok(false);
+        }
+        self.set_pressed(active);
+        self.integrator = if active { DEPTH } else { 0 };
+        self.locked = true;
+        Ok(true)
+    }
+
+    /// Ends the lockout started by [`on_edge`](Self::on_edge), returns `true` if the
+    /// lockout timer has to be started again
+    ///
+    /// The pin is sampled again, so a level change during the lockout is reported now.
+    pub fn end_lockout(&mut self) -> Result<bool, PIN::Error> {
+        self.locked = false;
+        self.on_edge()
+    }
+
+    /// Returns `true` while the debounced input is pressed
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Returns `true` if the input was pressed since the last call
+    pub fn rising_event(&mut self) -> bool {
+        core::mem::take(&mut self.rising)
+    }
+
+    /// Returns `true` if the input was released since the last call
+    pub fn falling_event(&mut self) -> bool {
+        core::mem::take(&mut self.falling)
+    }
+
+    /// Returns a reference to the pin, e.g. to clear its EXTI pending bit
+    pub fn pin(&mut self) -> &mut PIN {
+        &mut self.pin
+    }
+
+    /// Releases the pin
+    pub fn release(self) -> PIN {
+        self.pin
+    }
+
+    fn is_active(&mut self) -> Result<bool, PIN::Error> {
+        Ok(self.pin.is_high()? == (self.active == PinState::High))
+    }
+
+    fn set_pressed(&mut self, pressed: bool) {
+        if pressed {
+            self.rising = true;
+        } else {
+            self.falling = true;
+        }
+        self.pressed = pressed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use embedded_hal_one::digital::ErrorType;
+
+    /// Pin whose level is set by the test
+    struct MockPin(bool);
+
+    impl ErrorType for MockPin {
+        type Error = Infallible;
+    }
+
+    impl InputPin for MockPin {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(self.0)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            Ok(!self.0)
+        }
+    }
+
+    #[test]
+    fn poll_integrates_up_and_down() {
+        let mut input = DebouncedInput::<_, 3>::new(MockPin(false), PinState::Low);
+        for _ in 0..2 {
+            input.poll().unwrap();
+            assert!(!input.is_pressed());
+        }
+        input.poll().unwrap();
+        assert!(input.is_pressed());
+        assert!(input.rising_event());
+        assert!(!input.rising_event());
+
+        // A bounce does not release the input
+        input.pin().0 = true;
+        input.poll().unwrap();
+        input.pin().0 = false;
+        input.poll().unwrap();
+        assert!(input.is_pressed());
+
+        input.pin().0 = true;
+        for _ in 0..3 {
+            input.poll().unwrap();
+        }
+        assert!(!input.is_pressed());
+        assert!(input.falling_event());
+    }
+
+    #[test]
+    fn poll_saturates_at_max_depth() {
+        let mut input = DebouncedInput::<_, 255>::new(MockPin(true), PinState::High);
+        for _ in 0..300 {
+            input.poll().unwrap();
+        }
+        assert!(input.is_pressed());
+        assert_eq!(input.integrator, 255);
+    }
+
+    #[test]
+    fn thresholds() {
+        let mut input =
+            DebouncedInput::<_, 4>::new(MockPin(true), PinState::High).with_thresholds(2, 1);
+        input.poll().unwrap();
+        assert!(!input.is_pressed());
+        input.poll().unwrap();
+        assert!(input.is_pressed());
+        input.poll().unwrap();
+        input.poll().unwrap();
+
+        // From 4 down to 1
+        input.pin().0 = false;
+        input.poll().unwrap();
+        input.poll().unwrap();
+        assert!(input.is_pressed());
+        input.poll().unwrap();
+        assert!(!input.is_pressed());
+    }
+
+    #[test]
+    fn exti_edge_lockout() {
+        let mut input = DebouncedInput::<_, 8>::new(MockPin(false), PinState::Low);
+
+        // First edge reported at once, the lockout timer has to be started
+        assert!(input.on_edge().unwrap());
+        assert!(input.is_pressed());
+        assert!(input.rising_event());
+
+        // Bounces during the lockout are ignored
+        input.pin().0 = true;
+        assert!(!input.on_edge().unwrap());
+        input.pin().0 = false;
+        assert!(!input.on_edge().unwrap());
+        assert!(!input.falling_event());
+
+        // Still pressed at the end of the lockout, no new lockout
+        assert!(!input.end_lockout().unwrap());
+        assert!(input.is_pressed());
+
+        // Released
+        input.pin().0 = true;
+        assert!(input.on_edge().unwrap());
+        assert!(input.falling_event());
+        assert!(!input.is_pressed());
+    }
+
+    #[test]
+    fn exti_release_during_lockout() {
+        let mut input = DebouncedInput::<_, 8>::new(MockPin(false), PinState::Low);
+        assert!(input.on_edge().unwrap());
+        assert!(input.rising_event());
+
+        // Released before the end of the lockout, reported when it ends
+        input.pin().0 = true;
+        assert!(!input.on_edge().unwrap());
+        assert!(input.end_lockout().unwrap());
+        assert!(!input.is_pressed());
+        assert!(input.falling_event());
+    }
+}
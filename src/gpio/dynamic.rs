@@ -10,6 +10,8 @@ pub struct DynamicPin<const P: char, const N: u8> {
 }
 
 /// Tracks the current pin state for dynamic pins
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Dynamic {
     /// Floating input mode
     InputFloating,
@@ -25,6 +27,7 @@ pub enum Dynamic {
 
 /// Error for [DynamicPin]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PinModeError {
     /// For operations unsupported in current mode
     IncorrectMode,
@@ -131,6 +134,16 @@ impl<const P: char, const N: u8> DynamicPin<P, N> {
         }
     }
 
+    /// Drives the pin high or low depending on the provided value
+    pub fn set_state(&mut self, state: PinState) -> Result<(), PinModeError> {
+        if self.mode.is_output() {
+            Pin::<P, N, Unknown>::new()._set_state(state);
+            Ok(())
+        } else {
+            Err(PinModeError::IncorrectMode)
+        }
+    }
+
     /// Is the input pin high?
     pub fn is_high(&self) -> Result<bool, PinModeError> {
         self.is_low().map(|b| !b)
@@ -56,25 +56,74 @@ impl<MODE> ErasedPin<MODE> {
         }
     }
 
+    /// Creates a pin from its raw port (`0` for GPIOA, `1` for GPIOB, ...) and number.
+    ///
+    /// # Safety
+    ///
+    /// The port must exist on the device and `pin` must be lower than 16.
+    /// The pin is not configured, `MODE` must match its current hardware state.
+    /// The caller must ensure that the pin is not used elsewhere concurrently,
+    /// as the returned handle aliases any other handle to the same pin.
+    pub unsafe fn steal(port: u8, pin: u8) -> Self {
+        debug_assert!(pin < 16);
+        Self::new(port, pin)
+    }
+
+    /// Index of the pin over all ports, `port * 16 + pin`
+    ///
+    /// Unlike [`PinExt::pin_id`], which is the pin number within its port, it is unique
+    /// to the pin and can be used to address pins stored in tables.
+    #[inline(always)]
+    pub fn id(&self) -> u8 {
+        self.pin_port
+    }
+
+    /// Creates a pin from an index returned by [`id`](Self::id)
+    ///
+    /// # Safety
+    ///
+    /// Same as [`steal`](Self::steal).
+    #[inline(always)]
+    pub unsafe fn from_id(id: u8) -> Self {
+        Self::steal(id >> 4, id & 0x0f)
+    }
+
     #[inline]
     fn block(&self) -> &crate::pac::gpioa::RegisterBlock {
-        // This function uses pointer arithmetic instead of branching to be more efficient
+        port_block(self.port_id())
+    }
+}
 
-        // The logic relies on the following assumptions:
-        // - GPIOA register is available on all chips
-        // - all gpio register blocks have the same layout
-        // - consecutive gpio register blocks have the same offset between them, namely 0x0400
-        // - ErasedPin::new was called with a valid port
+/// Register block of `port`, `0` for GPIOA
+#[inline]
+pub(super) fn port_block(port: u8) -> &'static crate::pac::gpioa::RegisterBlock {
+    // This function uses pointer arithmetic instead of branching to be more efficient
 
-        // FIXME could be calculated after const_raw_ptr_to_usize_cast stabilization #51910
-        const GPIO_REGISTER_OFFSET: usize = 0x0400;
+    // The logic relies on the following assumptions:
+    // - GPIOA register is available on all chips
+    // - all gpio register blocks have the same layout
+    // - consecutive gpio register blocks have the same offset between them, namely 0x0400
+    // - ErasedPin::new was called with a valid port
 
-        let offset = GPIO_REGISTER_OFFSET * self.port_id() as usize;
-        let block_ptr =
-            (crate::pac::GPIOA::ptr() as usize + offset) as *const crate::pac::gpioa::RegisterBlock;
+    // FIXME could be calculated after const_raw_ptr_to_usize_cast stabilization #51910
+    const GPIO_REGISTER_OFFSET: usize = 0x0400;
 
-        unsafe { &*block_ptr }
-    }
+    let offset = GPIO_REGISTER_OFFSET * port as usize;
+    let block_ptr =
+        (crate::pac::GPIOA::ptr() as usize + offset) as *const crate::pac::gpioa::RegisterBlock;
+
+    unsafe { &*block_ptr }
+}
+
+/// Sets and resets several pins of `port` at once with a single `BSRR` write
+///
+/// # Safety
+///
+/// The port must exist on the device and the written pins must be outputs owned by the
+/// caller.
+#[inline(always)]
+pub(crate) unsafe fn write_bsrr(port: u8, bits: u32) {
+    port_block(port).bsrr.write(|w| w.bits(bits));
 }
 
 impl<MODE> ErasedPin<Output<MODE>> {
@@ -82,7 +131,11 @@ impl<MODE> ErasedPin<Output<MODE>> {
     #[inline(always)]
     pub fn set_high(&mut self) {
         // NOTE(unsafe) atomic write to a stateless register
-        unsafe { self.block().bsrr.write(|w| w.bits(1 << self.pin_id())) };
+        unsafe {
+            self.block()
+                .bsrr
+                .write(|w| w.bits(state_bits(self.pin_id(), PinState::High)))
+        };
     }
 
     /// Drives the pin low
@@ -92,7 +145,7 @@ impl<MODE> ErasedPin<Output<MODE>> {
         unsafe {
             self.block()
                 .bsrr
-                .write(|w| w.bits(1 << (self.pin_id() + 16)))
+                .write(|w| w.bits(state_bits(self.pin_id(), PinState::Low)))
         };
     }
 
@@ -124,17 +177,37 @@ impl<MODE> ErasedPin<Output<MODE>> {
     /// Is the pin in drive low mode?
     #[inline(always)]
     pub fn is_set_low(&self) -> bool {
-        self.block().odr.read().bits() & (1 << self.pin_id()) == 0
+        super::is_pin_low(self.block().odr.read().bits(), self.pin_id())
     }
 
-    /// Toggle pin output
+    /// Toggle pin output, see [`Pin::toggle`](super::Pin::toggle)
     #[inline(always)]
     pub fn toggle(&mut self) {
-        if self.is_set_low() {
-            self.set_high()
-        } else {
-            self.set_low()
-        }
+        let odr = self.block().odr.read().bits();
+        let mask = 1 << self.pin_id();
+        // NOTE(unsafe) atomic write to a stateless register
+        unsafe {
+            self.block()
+                .bsrr
+                .write(|w| w.bits(super::toggle_bits(odr, mask)))
+        };
+    }
+}
+
+impl ErasedPin<Input> {
+    /// Returns the internal pull-up or pull-down resistor configured in `PUPDR`
+    pub fn pull(&self) -> Pull {
+        pupdr_pull(self.block().pupdr.read().bits(), self.pin_id())
+    }
+}
+
+/// Pull of pin `n` in `pupdr`
+fn pupdr_pull(pupdr: u32, n: u8) -> Pull {
+    match (pupdr >> (2 * n)) & 0b11 {
+        1 => Pull::Up,
+        2 => Pull::Down,
+        // 0b11 is reserved
+        _ => Pull::None,
     }
 }
 
@@ -151,6 +224,52 @@ where
     /// Is the input pin low?
     #[inline(always)]
     pub fn is_low(&self) -> bool {
-        self.block().idr.read().bits() & (1 << self.pin_id()) == 0
+        super::is_pin_low(self.block().idr.read().bits(), self.pin_id())
+    }
+}
+
+impl ErasedPin<Output<OpenDrain>> {
+    /// Is the line high? See [`Pin::is_line_high`]
+    #[inline(always)]
+    pub fn is_line_high(&self) -> bool {
+        !self.is_low()
+    }
+
+    /// Is the line low? See [`Pin::is_line_high`]
+    #[inline(always)]
+    pub fn is_line_low(&self) -> bool {
+        self.is_low()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pupdr_decode() {
+        // GPIOA reset value: PA13 and PA15 pulled up, PA14 pulled down for the debugger
+        let pupdr = 0x6400_0000;
+        assert_eq!(pupdr_pull(pupdr, 13), Pull::Up);
+        assert_eq!(pupdr_pull(pupdr, 14), Pull::Down);
+        assert_eq!(pupdr_pull(pupdr, 15), Pull::Up);
+        assert_eq!(pupdr_pull(pupdr, 0), Pull::None);
+        // Reserved
+        assert_eq!(pupdr_pull(0b11 << 6, 3), Pull::None);
+        assert_eq!(pupdr_pull(0b11 << 6, 2), Pull::None);
+    }
+
+    #[test]
+    fn id_round_trip() {
+        for port in 0..9 {
+            for pin in 0..16 {
+                let erased = ErasedPin::<Input>::new(port, pin);
+                let id = erased.id();
+                assert_eq!(id, port * 16 + pin);
+                let restored = unsafe { ErasedPin::<Input>::from_id(id) };
+                assert_eq!(restored.port_id(), port);
+                assert_eq!(restored.pin_id(), pin);
+            }
+        }
     }
 }
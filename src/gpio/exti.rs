@@ -1,4 +1,4 @@
-use super::{marker, Edge, Pin, PinExt};
+use super::{marker, Edge, ErasedPin, PartiallyErasedPin, Pin, PinExt};
 use crate::{
     pac::{Interrupt, EXTI},
     syscfg::SysCfg,
@@ -10,20 +10,57 @@ impl<const P: char, const N: u8, MODE> Pin<P, N, MODE> {
     /// Used to unmask / enable the interrupt with [`cortex_m::peripheral::NVIC::unmask()`].
     /// This is also useful for all other [`cortex_m::peripheral::NVIC`] functions.
     pub const fn interrupt(&self) -> Interrupt {
-        match N {
-            0 => Interrupt::EXTI0,
-            1 => Interrupt::EXTI1,
-            2 => Interrupt::EXTI2,
-            3 => Interrupt::EXTI3,
-            4 => Interrupt::EXTI4,
-            5..=9 => Interrupt::EXTI9_5,
-            10..=15 => Interrupt::EXTI15_10,
-            _ => panic!("Unsupported pin number"),
-        }
+        interrupt(N)
+    }
+}
+
+impl<const P: char, MODE> PartiallyErasedPin<P, MODE> {
+    /// NVIC interrupt number of interrupt from this pin
+    ///
+    /// Used to unmask / enable the interrupt with [`cortex_m::peripheral::NVIC::unmask()`].
+    pub fn interrupt(&self) -> Interrupt {
+        interrupt(self.pin_id())
+    }
+}
+
+impl<MODE> ErasedPin<MODE> {
+    /// NVIC interrupt number of interrupt from this pin
+    ///
+    /// Used to unmask / enable the interrupt with [`cortex_m::peripheral::NVIC::unmask()`].
+    pub fn interrupt(&self) -> Interrupt {
+        interrupt(self.pin_id())
+    }
+}
+
+/// Interrupt of the EXTI line `n`, shared by lines 5 to 9 and 10 to 15
+const fn interrupt(n: u8) -> Interrupt {
+    match n {
+        0 => Interrupt::EXTI0,
+        1 => Interrupt::EXTI1,
+        2 => Interrupt::EXTI2,
+        3 => Interrupt::EXTI3,
+        4 => Interrupt::EXTI4,
+        5..=9 => Interrupt::EXTI9_5,
+        10..=15 => Interrupt::EXTI15_10,
+        _ => panic!("Unsupported pin number"),
     }
 }
 
+/// Index of the `SYSCFG_EXTICRx` register selecting the port of EXTI line `n`, and offset
+/// of its 4-bit field
+const fn exticr_field(n: u8) -> (usize, u8) {
+    ((n / 4) as usize, 4 * (n % 4))
+}
+
+/// `exticr` with the field at `offset` set to `port`
+const fn exticr_select(exticr: u32, offset: u8, port: u8) -> u32 {
+    (exticr & !(0xf << offset)) | ((port as u32) << offset)
+}
+
 /// External Interrupt Pin
+///
+/// Also implemented by [`PartiallyErasedPin`] and [`ErasedPin`], which select the EXTI line
+/// and the `SYSCFG_EXTICRx` port from their runtime pin and port numbers.
 pub trait ExtiPin {
     /// Make corresponding EXTI line sensitive to this pin
     fn make_interrupt_source(&mut self, syscfg: &mut SysCfg);
@@ -51,38 +88,39 @@ where
 {
     #[inline(always)]
     fn make_interrupt_source(&mut self, syscfg: &mut SysCfg) {
-        let i = self.pin_id();
-        let port = self.port_id() as u32;
-        let offset = 4 * (i % 4);
-        match i {
-            0..=3 => {
-                syscfg.exticr1.modify(|r, w| unsafe {
-                    w.bits((r.bits() & !(0xf << offset)) | (port << offset))
-                });
+        let (index, offset) = exticr_field(self.pin_id());
+        let port = self.port_id();
+        // EXTICR are shared with the other pins of the same group of 4
+        cortex_m::interrupt::free(|_| match index {
+            0 => {
+                syscfg
+                    .exticr1
+                    .modify(|r, w| unsafe { w.bits(exticr_select(r.bits(), offset, port)) });
             }
-            4..=7 => {
-                syscfg.exticr2.modify(|r, w| unsafe {
-                    w.bits((r.bits() & !(0xf << offset)) | (port << offset))
-                });
+            1 => {
+                syscfg
+                    .exticr2
+                    .modify(|r, w| unsafe { w.bits(exticr_select(r.bits(), offset, port)) });
             }
-            8..=11 => {
-                syscfg.exticr3.modify(|r, w| unsafe {
-                    w.bits((r.bits() & !(0xf << offset)) | (port << offset))
-                });
+            2 => {
+                syscfg
+                    .exticr3
+                    .modify(|r, w| unsafe { w.bits(exticr_select(r.bits(), offset, port)) });
             }
-            12..=15 => {
-                syscfg.exticr4.modify(|r, w| unsafe {
-                    w.bits((r.bits() & !(0xf << offset)) | (port << offset))
-                });
+            3 => {
+                syscfg
+                    .exticr4
+                    .modify(|r, w| unsafe { w.bits(exticr_select(r.bits(), offset, port)) });
             }
             _ => unreachable!(),
-        }
+        });
     }
 
     #[inline(always)]
     fn trigger_on_edge(&mut self, exti: &mut EXTI, edge: Edge) {
         let i = self.pin_id();
-        match edge {
+        // EXTI registers are shared by all lines
+        cortex_m::interrupt::free(|_| match edge {
             Edge::Rising => {
                 exti.rtsr
                     .modify(|r, w| unsafe { w.bits(r.bits() | (1 << i)) });
@@ -101,19 +139,23 @@ where
                 exti.ftsr
                     .modify(|r, w| unsafe { w.bits(r.bits() | (1 << i)) });
             }
-        }
+        });
     }
 
     #[inline(always)]
     fn enable_interrupt(&mut self, exti: &mut EXTI) {
-        exti.imr
-            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << self.pin_id())) });
+        cortex_m::interrupt::free(|_| {
+            exti.imr
+                .modify(|r, w| unsafe { w.bits(r.bits() | (1 << self.pin_id())) })
+        });
     }
 
     #[inline(always)]
     fn disable_interrupt(&mut self, exti: &mut EXTI) {
-        exti.imr
-            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << self.pin_id())) });
+        cortex_m::interrupt::free(|_| {
+            exti.imr
+                .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << self.pin_id())) })
+        });
     }
 
     #[inline(always)]
@@ -126,3 +168,40 @@ where
         unsafe { ((*EXTI::ptr()).pr.read().bits() & (1 << self.pin_id())) != 0 }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::Input;
+    use super::*;
+
+    #[test]
+    fn runtime_pin_lines() {
+        // Pin, EXTICR index and offset, interrupt
+        let lines = [
+            (0, 0, 0, Interrupt::EXTI0),
+            (3, 0, 12, Interrupt::EXTI3),
+            (4, 1, 0, Interrupt::EXTI4),
+            (15, 3, 12, Interrupt::EXTI15_10),
+        ];
+        // Ports A, C and H
+        for port in [0, 2, 7] {
+            for (pin, index, offset, irq) in lines {
+                let erased = ErasedPin::<Input>::new(port, pin);
+                assert_eq!(erased.interrupt(), irq);
+                assert_eq!(exticr_field(erased.pin_id()), (index, offset));
+                let exticr = exticr_select(0xffff_ffff, offset, erased.port_id());
+                assert_eq!(exticr >> offset & 0xf, port as u32);
+                assert_eq!(exticr | (0xf << offset), 0xffff_ffff);
+            }
+        }
+        for (pin, index, offset, irq) in lines {
+            let partially_erased = PartiallyErasedPin::<'C', Input>::new(pin);
+            assert_eq!(partially_erased.interrupt(), irq);
+            assert_eq!(exticr_field(partially_erased.pin_id()), (index, offset));
+            assert_eq!(
+                exticr_select(0, offset, partially_erased.port_id()),
+                2 << offset
+            );
+        }
+    }
+}
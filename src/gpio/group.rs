@@ -0,0 +1,372 @@
+use super::erased::port_block;
+use super::*;
+
+/// Pins of the same port making up a [`PinGroup`], bit `i` of values is the `i`-th pin
+pub trait PinTuple<const P: char> {
+    /// Mode shared by all pins
+    type Mode;
+    /// Pin numbers, in bit order
+    const IDS: &'static [u8];
+}
+
+macro_rules! pin_tuple {
+    ($($N:ident),+) => {
+        impl<const P: char, MODE, $(const $N: u8),+> PinTuple<P> for ($(Pin<P, $N, MODE>,)+) {
+            type Mode = MODE;
+            const IDS: &'static [u8] = &[$($N),+];
+        }
+    };
+}
+
+pin_tuple!(N0, N1);
+pin_tuple!(N0, N1, N2);
+pin_tuple!(N0, N1, N2, N3);
+pin_tuple!(N0, N1, N2, N3, N4);
+pin_tuple!(N0, N1, N2, N3, N4, N5);
+pin_tuple!(N0, N1, N2, N3, N4, N5, N6);
+pin_tuple!(N0, N1, N2, N3, N4, N5, N6, N7);
+
+/// 2 to 8 pins of the same port read and written together, like a parallel bus
+///
+/// Each write is a single BSRR store and each read a single IDR load. When pin numbers
+/// follow each other (like `(PB8, PB9, ..., PB15)`) the value is just shifted in place,
+/// otherwise bits are moved one by one.
+///
+/// ```ignore
+/// let gpiob = dp.GPIOB.split();
+/// let mut bus = PinGroup::new((
+///     gpiob.pb8.into_push_pull_output(),
+///     gpiob.pb9.into_push_pull_output(),
+///     gpiob.pb12.into_push_pull_output(),
+/// ));
+/// // PB8 and PB12 high, PB9 low
+/// bus.write(0b101);
+/// ```
+pub struct PinGroup<const P: char, PINS> {
+    pins: PINS,
+}
+
+impl<const P: char, PINS: PinTuple<P>> PinGroup<P, PINS> {
+    const FIRST: u8 = PINS::IDS[0];
+    const CONTIGUOUS: bool = contiguous(PINS::IDS);
+    /// Bits of the group in port registers
+    const MASK: u32 = mask(PINS::IDS);
+
+    /// Groups pins, the first one is bit 0
+    pub fn new(pins: PINS) -> Self {
+        Self { pins }
+    }
+
+    /// Returns the pins
+    pub fn release(self) -> PINS {
+        self.pins
+    }
+
+    /// Moves bits of `value` to pin positions
+    #[inline(always)]
+    fn spread(value: u8) -> u32 {
+        if Self::CONTIGUOUS {
+            ((value as u32) << Self::FIRST) & Self::MASK
+        } else {
+            let mut bits = 0;
+            for (i, n) in PINS::IDS.iter().enumerate() {
+                bits |= ((value as u32 >> i) & 1) << n;
+            }
+            bits
+        }
+    }
+
+    /// Moves pin bits of a port register to a value
+    #[inline(always)]
+    fn gather(bits: u32) -> u8 {
+        if Self::CONTIGUOUS {
+            ((bits & Self::MASK) >> Self::FIRST) as u8
+        } else {
+            let mut value = 0;
+            for (i, n) in PINS::IDS.iter().enumerate() {
+                value |= (((bits >> n) & 1) as u8) << i;
+            }
+            value
+        }
+    }
+}
+
+impl<const P: char, MODE, PINS> PinGroup<P, PINS>
+where
+    PINS: PinTuple<P, Mode = Output<MODE>>,
+{
+    /// Drives all pins at once, bits above the number of pins are ignored
+    #[inline(always)]
+    pub fn write(&mut self, value: u8) {
+        let set = Self::spread(value);
+        let reset = Self::MASK & !set;
+        // NOTE(unsafe) atomic write to a stateless register
+        unsafe {
+            (*Gpio::<P>::ptr())
+                .bsrr
+                .write(|w| w.bits(reset << 16 | set))
+        }
+    }
+
+    /// Value the pins are driven to
+    #[inline(always)]
+    pub fn get_state(&self) -> u8 {
+        // NOTE(unsafe) atomic read with no side effects
+        Self::gather(unsafe { (*Gpio::<P>::ptr()).odr.read().bits() })
+    }
+}
+
+impl<const P: char, PINS> PinGroup<P, PINS>
+where
+    PINS: PinTuple<P>,
+    PINS::Mode: marker::Readable,
+{
+    /// Reads all pins at once
+    #[inline(always)]
+    pub fn read(&self) -> u8 {
+        // NOTE(unsafe) atomic read with no side effects
+        Self::gather(unsafe { (*Gpio::<P>::ptr()).idr.read().bits() })
+    }
+}
+
+const fn contiguous(ids: &[u8]) -> bool {
+    let mut i = 1;
+    while i < ids.len() {
+        if ids[i] != ids[0] + i as u8 {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn mask(ids: &[u8]) -> u32 {
+    let mut mask = 0;
+    let mut i = 0;
+    while i < ids.len() {
+        mask |= 1 << ids[i];
+        i += 1;
+    }
+    mask
+}
+
+/// Pins of an [`OutputGroup`] are not all on the same port, the pins are returned
+pub struct PortMismatch<MODE, const N: usize> {
+    /// The pins, in the order they were passed
+    pub pins: [ErasedPin<Output<MODE>>; N],
+    /// Bit `i` is set if the `i`-th pin is not on the port of the first pin
+    pub mismatched: u16,
+}
+
+impl<MODE, const N: usize> fmt::Debug for PortMismatch<MODE, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PortMismatch")
+            .field("pins", &self.pins)
+            .field("mismatched", &self.mismatched)
+            .finish()
+    }
+}
+
+/// 1 to 16 erased output pins of the same port driven together
+///
+/// Same as [`PinGroup`], for pins assembled at runtime: the port is checked when the
+/// group is created. Bit `i` of masks and values is the `i`-th pin, each change is a
+/// single BSRR store, which is safe to do from interrupts without a critical section.
+///
+/// ```ignore
+/// let gpioa = dp.GPIOA.split();
+/// let lines = [
+///     gpioa.pa0.into_push_pull_output().erase(),
+///     gpioa.pa1.into_push_pull_output().erase(),
+///     gpioa.pa4.into_push_pull_output().erase(),
+/// ];
+/// let mut group = OutputGroup::new(lines).unwrap();
+/// // PA0 and PA4 high, PA1 low
+/// group.write(0b101);
+/// ```
+pub struct OutputGroup<MODE, const N: usize> {
+    pins: [ErasedPin<Output<MODE>>; N],
+    port: u8,
+    /// Bits of the group in port registers
+    mask: u16,
+}
+
+impl<MODE, const N: usize> OutputGroup<MODE, N> {
+    /// Groups pins, the first one is bit 0
+    ///
+    /// # Panics
+    ///
+    /// When `N` is 0 or more than 16.
+    pub fn new(pins: [ErasedPin<Output<MODE>>; N]) -> Result<Self, PortMismatch<MODE, N>> {
+        assert!(N > 0 && N <= 16);
+        let port = pins[0].port_id();
+        let mut mismatched = 0;
+        let mut mask = 0;
+        for (i, pin) in pins.iter().enumerate() {
+            if pin.port_id() != port {
+                mismatched |= 1 << i;
+            }
+            mask |= 1 << pin.pin_id();
+        }
+        if mismatched != 0 {
+            return Err(PortMismatch { pins, mismatched });
+        }
+        Ok(Self { pins, port, mask })
+    }
+
+    /// Returns the pins
+    pub fn release(self) -> [ErasedPin<Output<MODE>>; N] {
+        self.pins
+    }
+
+    /// Moves bits of `value` to pin positions
+    #[inline(always)]
+    fn spread(&self, value: u16) -> u32 {
+        let mut bits = 0;
+        for (i, pin) in self.pins.iter().enumerate() {
+            bits |= ((value as u32 >> i) & 1) << pin.pin_id();
+        }
+        bits
+    }
+
+    #[inline(always)]
+    fn write_bsrr(&mut self, bits: u32) {
+        // NOTE(unsafe) atomic write to a stateless register, the pins are owned outputs
+        unsafe { write_bsrr(self.port, bits) }
+    }
+
+    /// Drives the pins of `mask` high, the others are unchanged
+    #[inline(always)]
+    pub fn set_bits(&mut self, mask: u16) {
+        let set = self.spread(mask);
+        self.write_bsrr(set);
+    }
+
+    /// Drives the pins of `mask` low, the others are unchanged
+    #[inline(always)]
+    pub fn clear_bits(&mut self, mask: u16) {
+        let reset = self.spread(mask);
+        self.write_bsrr(reset << 16);
+    }
+
+    /// Drives all pins at once, bits above the number of pins are ignored
+    #[inline(always)]
+    pub fn write(&mut self, value: u16) {
+        let set = self.spread(value);
+        let reset = u32::from(self.mask) & !set;
+        self.write_bsrr(reset << 16 | set);
+    }
+
+    /// Drives all pins high
+    #[inline(always)]
+    pub fn all_high(&mut self) {
+        self.write_bsrr(u32::from(self.mask));
+    }
+
+    /// Drives all pins low
+    #[inline(always)]
+    pub fn all_low(&mut self) {
+        self.write_bsrr(u32::from(self.mask) << 16);
+    }
+
+    /// Values the pins are driven to
+    #[inline(always)]
+    pub fn read_outputs(&self) -> u16 {
+        let odr = port_block(self.port).odr.read().bits();
+        let mut value = 0;
+        for (i, pin) in self.pins.iter().enumerate() {
+            value |= (((odr >> pin.pin_id()) & 1) as u16) << i;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Bus = (
+        Pin<'B', 8, Output>,
+        Pin<'B', 9, Output>,
+        Pin<'B', 10, Output>,
+        Pin<'B', 11, Output>,
+    );
+    type Scattered = (
+        Pin<'B', 8, Output>,
+        Pin<'B', 9, Output>,
+        Pin<'B', 12, Output>,
+    );
+
+    #[test]
+    fn contiguous_pins() {
+        for (ids, expected) in [
+            (&[8, 9, 10, 11][..], true),
+            (&[0, 1], true),
+            (&[8, 9, 12], false),
+            (&[9, 8], false),
+        ] {
+            assert_eq!(contiguous(ids), expected);
+        }
+    }
+
+    #[test]
+    fn pin_group_contiguous() {
+        type G = PinGroup<'B', Bus>;
+        assert_eq!(G::MASK, 0x0f00);
+        assert_eq!(G::spread(0b1010), 0x0a00);
+        // Bits above the number of pins are ignored
+        assert_eq!(G::spread(0xf5), 0x0500);
+        assert_eq!(G::gather(0xfaff), 0b1010);
+        for value in 0..16 {
+            assert_eq!(G::gather(G::spread(value)), value);
+        }
+    }
+
+    #[test]
+    fn pin_group_scattered() {
+        type G = PinGroup<'B', Scattered>;
+        assert_eq!(G::MASK, 0x1300);
+        // PB8 and PB12 high, PB9 low
+        assert_eq!(G::spread(0b101), 0x1100);
+        assert_eq!(G::spread(0xf8), 0);
+        assert_eq!(G::gather(0xeeff), 0b010);
+        for value in 0..8 {
+            assert_eq!(G::gather(G::spread(value)), value);
+        }
+    }
+
+    fn pins<const N: usize>(ids: [(u8, u8); N]) -> [ErasedPin<Output<PushPull>>; N] {
+        ids.map(|(port, pin)| ErasedPin::new(port, pin))
+    }
+
+    #[test]
+    fn output_group_same_port() {
+        let group = OutputGroup::new(pins([(0, 0), (0, 1), (0, 4)])).unwrap();
+        assert_eq!(group.port, 0);
+        assert_eq!(group.mask, 0b1_0011);
+        assert_eq!(group.spread(0b101), 0b1_0001);
+        // Bits above the number of pins are ignored
+        assert_eq!(group.spread(0xfff8), 0);
+
+        let group = OutputGroup::new(pins([(7, 15)])).unwrap();
+        assert_eq!(group.port, 7);
+        assert_eq!(group.spread(1), 1 << 15);
+    }
+
+    #[test]
+    fn output_group_port_mismatch() {
+        let err = OutputGroup::new(pins([(1, 0), (0, 1), (1, 2), (2, 3)]))
+            .err()
+            .unwrap();
+        assert_eq!(err.mismatched, 0b1010);
+        // Pins are returned in order
+        let ids = err.pins.map(|pin| (pin.port_id(), pin.pin_id()));
+        assert_eq!(ids, [(1, 0), (0, 1), (1, 2), (2, 3)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn output_group_too_many_pins() {
+        let _ = OutputGroup::new(pins([(0, 0); 17]));
+    }
+}
@@ -18,6 +18,19 @@ impl<const P: char, MODE> PartiallyErasedPin<P, MODE> {
             _mode: PhantomData,
         }
     }
+
+    /// Creates a pin from its raw number.
+    ///
+    /// # Safety
+    ///
+    /// `i` must be lower than 16.
+    /// The pin is not configured, `MODE` must match its current hardware state.
+    /// The caller must ensure that the pin is not used elsewhere concurrently,
+    /// as the returned handle aliases any other handle to the same pin.
+    pub unsafe fn steal(i: u8) -> Self {
+        debug_assert!(i < 16);
+        Self::new(i)
+    }
 }
 
 impl<const P: char, MODE> fmt::Debug for PartiallyErasedPin<P, MODE> {
@@ -62,7 +75,11 @@ impl<const P: char, MODE> PartiallyErasedPin<P, Output<MODE>> {
     #[inline(always)]
     pub fn set_high(&mut self) {
         // NOTE(unsafe) atomic write to a stateless register
-        unsafe { (*Gpio::<P>::ptr()).bsrr.write(|w| w.bits(1 << self.i)) }
+        unsafe {
+            (*Gpio::<P>::ptr())
+                .bsrr
+                .write(|w| w.bits(state_bits(self.i, PinState::High)))
+        }
     }
 
     /// Drives the pin low
@@ -72,7 +89,7 @@ impl<const P: char, MODE> PartiallyErasedPin<P, Output<MODE>> {
         unsafe {
             (*Gpio::<P>::ptr())
                 .bsrr
-                .write(|w| w.bits(1 << (self.i + 16)))
+                .write(|w| w.bits(state_bits(self.i, PinState::Low)))
         }
     }
 
@@ -105,16 +122,20 @@ impl<const P: char, MODE> PartiallyErasedPin<P, Output<MODE>> {
     #[inline(always)]
     pub fn is_set_low(&self) -> bool {
         // NOTE(unsafe) atomic read with no side effects
-        unsafe { (*Gpio::<P>::ptr()).odr.read().bits() & (1 << self.i) == 0 }
+        super::is_pin_low(unsafe { (*Gpio::<P>::ptr()).odr.read().bits() }, self.i)
     }
 
-    /// Toggle pin output
+    /// Toggle pin output, see [`Pin::toggle`](super::Pin::toggle)
     #[inline(always)]
     pub fn toggle(&mut self) {
-        if self.is_set_low() {
-            self.set_high()
-        } else {
-            self.set_low()
+        // NOTE(unsafe) atomic read with no side effects
+        let odr = unsafe { (*Gpio::<P>::ptr()).odr.read().bits() };
+        let mask = 1 << self.i;
+        // NOTE(unsafe) atomic write to a stateless register
+        unsafe {
+            (*Gpio::<P>::ptr())
+                .bsrr
+                .write(|w| w.bits(super::toggle_bits(odr, mask)))
         }
     }
 }
@@ -133,7 +154,21 @@ where
     #[inline(always)]
     pub fn is_low(&self) -> bool {
         // NOTE(unsafe) atomic read with no side effects
-        unsafe { (*Gpio::<P>::ptr()).idr.read().bits() & (1 << self.i) == 0 }
+        super::is_pin_low(unsafe { (*Gpio::<P>::ptr()).idr.read().bits() }, self.i)
+    }
+}
+
+impl<const P: char> PartiallyErasedPin<P, Output<OpenDrain>> {
+    /// Is the line high? See [`Pin::is_line_high`]
+    #[inline(always)]
+    pub fn is_line_high(&self) -> bool {
+        !self.is_low()
+    }
+
+    /// Is the line low? See [`Pin::is_line_high`]
+    #[inline(always)]
+    pub fn is_line_low(&self) -> bool {
+        self.is_low()
     }
 }
 
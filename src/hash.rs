@@ -0,0 +1,460 @@
+//! Hash processor (HASH)
+//!
+//! SHA-1 and MD5 digests and HMACs on STM32F415, F417, F437, F439 and F479, as well as
+//! SHA-224 and SHA-256 on STM32F437, F439 and F479.
+//!
+//! Messages of any length are fed in pieces with [`Hasher::update`], bytes are packed in
+//! words in memory order and the number of valid bits of the last word is set when the digest
+//! is computed. A computation can be suspended to compute another one in between, see
+//! [`Hasher::suspend`] and [`Hash::resume`].
+//!
+//! # Example
+//!
+//! ```
+//! use stm32f4xx_hal::{hash::{Hash, Md5, Sha1}, pac};
+//!
+//! let dp = pac::Peripherals::take().unwrap();
+//! let mut hash = Hash::new(dp.HASH);
+//!
+//! let mut sha1 = hash.digest::<Sha1>();
+//! sha1.update(b"a");
+//! sha1.update(b"bc");
+//! assert_eq!(sha1.finalize()[..4], [0xa9, 0x99, 0x3e, 0x36]);
+//!
+//! let mut hmac = hash.hmac::<Sha1>(b"key");
+//! hmac.update(b"The quick brown fox jumps over the lazy dog");
+//! assert_eq!(hmac.finalize()[..4], [0xde, 0x7c, 0x9b, 0x85]);
+//!
+//! // Two interleaved computations
+//! let mut first = hash.digest::<Sha1>();
+//! first.update(b"a");
+//! let first = first.suspend();
+//! let mut second = hash.digest::<Md5>();
+//! second.update(b"message");
+//! let second = second.suspend();
+//! let mut first = hash.resume(first);
+//! first.update(b"bc");
+//! assert_eq!(first.finalize()[..4], [0xa9, 0x99, 0x3e, 0x36]);
+//! let _ = hash.resume(second).finalize();
+//! ```
+//!
+//! With the `digest` feature, [`HashDigest`] implements the RustCrypto `Digest` trait.
+//!
+//! # DMA
+//!
+//! DMA2 stream 7, channel 2 writes the end of a message to [`DataIn`] with 32-bit accesses.
+//! Call [`Hasher::enable_dma`] with the length in bytes of the transfer before starting it,
+//! the digest is computed by itself at the end of the transfer and returned by
+//! [`Hasher::finalize`].
+
+use core::marker::PhantomData;
+use core::ptr;
+
+use crate::dma::traits::{DMASet, PeriAddress};
+use crate::dma::{MemoryToPeripheral, Stream7};
+use crate::pac::{DMA2, HASH, RCC};
+use crate::rcc::{Enable, Reset};
+
+#[cfg(feature = "digest")]
+mod rustcrypto;
+#[cfg(feature = "digest")]
+pub use rustcrypto::{DigestSize, HashDigest};
+
+// Registers are accessed by offset, SVDs don't describe the HASH processor consistently
+const CR: usize = 0x00;
+const DIN: usize = 0x04;
+const STR: usize = 0x08;
+const HR: usize = 0x0c;
+const IMR: usize = 0x20;
+const SR: usize = 0x24;
+const CSR: usize = 0xf8;
+/// Extended digest registers, for SHA-224 and SHA-256
+#[cfg(any(feature = "stm32f437", feature = "stm32f439", feature = "stm32f479"))]
+const HR_EXT: usize = 0x310;
+
+const CR_INIT: u32 = 1 << 2;
+const CR_DMAE: u32 = 1 << 3;
+/// Byte swapping, words are written in memory order
+const CR_DATATYPE_BYTES: u32 = 0b10 << 4;
+const CR_MODE_HMAC: u32 = 1 << 6;
+const CR_LKEY: u32 = 1 << 16;
+
+const STR_DCAL: u32 = 1 << 8;
+
+const SR_DCIS: u32 = 1 << 1;
+const SR_BUSY: u32 = 1 << 3;
+
+/// Context registers to save in hash mode
+const CSR_HASH: usize = 38;
+/// Context registers to save in HMAC mode
+const CSR_HMAC: usize = 54;
+
+/// HMAC keys longer than a block are hashed first
+const HMAC_LONG_KEY: usize = 64;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Hash algorithm
+pub trait Algorithm: sealed::Sealed {
+    /// `ALGO` bits of `HASH_CR`
+    const CR_ALGO: u32;
+    /// Digest
+    type Output: Default + AsMut<[u8]>;
+}
+
+/// SHA-1, 160-bit digest
+pub struct Sha1;
+/// MD5, 128-bit digest
+pub struct Md5;
+/// SHA-224, 224-bit digest
+#[cfg(any(feature = "stm32f437", feature = "stm32f439", feature = "stm32f479"))]
+pub struct Sha224;
+/// SHA-256, 256-bit digest
+#[cfg(any(feature = "stm32f437", feature = "stm32f439", feature = "stm32f479"))]
+pub struct Sha256;
+
+impl sealed::Sealed for Sha1 {}
+impl Algorithm for Sha1 {
+    const CR_ALGO: u32 = 0;
+    type Output = [u8; 20];
+}
+
+impl sealed::Sealed for Md5 {}
+impl Algorithm for Md5 {
+    const CR_ALGO: u32 = 1 << 7;
+    type Output = [u8; 16];
+}
+
+#[cfg(any(feature = "stm32f437", feature = "stm32f439", feature = "stm32f479"))]
+impl sealed::Sealed for Sha224 {}
+#[cfg(any(feature = "stm32f437", feature = "stm32f439", feature = "stm32f479"))]
+impl Algorithm for Sha224 {
+    const CR_ALGO: u32 = 1 << 18;
+    type Output = [u8; 28];
+}
+
+#[cfg(any(feature = "stm32f437", feature = "stm32f439", feature = "stm32f479"))]
+impl sealed::Sealed for Sha256 {}
+#[cfg(any(feature = "stm32f437", feature = "stm32f439", feature = "stm32f479"))]
+impl Algorithm for Sha256 {
+    const CR_ALGO: u32 = 1 << 18 | 1 << 7;
+    type Output = [u8; 32];
+}
+
+/// Hash processor
+pub struct Hash {
+    hash: HASH,
+}
+
+impl Hash {
+    /// Enables and resets the hash processor
+    pub fn new(hash: HASH) -> Self {
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            HASH::enable(rcc);
+            HASH::reset(rcc);
+        }
+
+        Self { hash }
+    }
+
+    /// Computes the digest of a whole message, like a firmware image
+    pub fn compute<A: Algorithm>(&mut self, data: &[u8]) -> A::Output {
+        let mut hasher = self.digest::<A>();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// Starts a digest computation
+    pub fn digest<A: Algorithm>(&mut self) -> Hasher<'_, 'static, A> {
+        self.init(A::CR_ALGO);
+        Hasher::new(self, None)
+    }
+
+    /// Starts an HMAC computation, the key is fed right away
+    pub fn hmac<'a, 'k, A: Algorithm>(&'a mut self, key: &'k [u8]) -> Hasher<'a, 'k, A> {
+        let mut cr = A::CR_ALGO | CR_MODE_HMAC;
+        if key.len() > HMAC_LONG_KEY {
+            cr |= CR_LKEY;
+        }
+        self.init(cr);
+        self.feed_key(key);
+        Hasher::new(self, Some(key))
+    }
+
+    /// Restores a computation saved by [`Hasher::suspend`]
+    ///
+    /// The context doesn't borrow the processor, so several computations can be interleaved.
+    pub fn resume<'k, A: Algorithm>(&mut self, context: Context<'k, A>) -> Hasher<'_, 'k, A> {
+        self.write(IMR, context.imr);
+        self.write(STR, context.str);
+        self.write(CR, context.cr);
+        self.write(CR, context.cr | CR_INIT);
+        let count = if context.key.is_some() {
+            CSR_HMAC
+        } else {
+            CSR_HASH
+        };
+        for (i, csr) in context.csr[..count].iter().enumerate() {
+            self.write(CSR + 4 * i, *csr);
+        }
+        Hasher {
+            hash: self,
+            key: context.key,
+            buffer: context.buffer,
+            buffered: context.buffered,
+            dma: false,
+            _algorithm: PhantomData,
+        }
+    }
+
+    /// Returns the DMA target of the input FIFO
+    pub fn dma_target(&mut self) -> DataIn {
+        DataIn { _private: () }
+    }
+
+    /// Disables the processor and its clock, and returns the HASH peripheral
+    pub fn release(self) -> HASH {
+        self.write(CR, 0);
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            HASH::disable(&(*RCC::ptr()));
+        }
+        self.hash
+    }
+
+    fn init(&mut self, cr: u32) {
+        self.wait_idle();
+        self.write(CR, cr | CR_DATATYPE_BYTES);
+        self.write(CR, cr | CR_DATATYPE_BYTES | CR_INIT);
+    }
+
+    /// First and last HMAC phases
+    fn feed_key(&mut self, key: &[u8]) {
+        let chunks = key.chunks_exact(4);
+        let remainder = chunks.remainder();
+        for word in chunks {
+            self.write(
+                DIN,
+                u32::from_le_bytes([word[0], word[1], word[2], word[3]]),
+            );
+        }
+        self.finish(remainder);
+        self.wait_idle();
+    }
+
+    /// Writes the last bytes and starts the digest calculation
+    fn finish(&mut self, last: &[u8]) {
+        self.clear_digest_flag();
+        self.write(STR, last_word_bits(last.len()));
+        if !last.is_empty() {
+            let mut word = [0; 4];
+            word[..last.len()].copy_from_slice(last);
+            self.write(DIN, u32::from_le_bytes(word));
+        }
+        self.write(STR, last_word_bits(last.len()) | STR_DCAL);
+    }
+
+    /// `DCIS` is cleared by writing 0, other flags are unaffected by 1
+    fn clear_digest_flag(&mut self) {
+        self.write(SR, !SR_DCIS);
+    }
+
+    fn wait_idle(&self) {
+        while self.read(SR) & SR_BUSY != 0 {}
+    }
+
+    fn register(&self, offset: usize) -> *mut u32 {
+        (HASH::ptr() as *mut u8).wrapping_add(offset) as *mut u32
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ptr::read_volatile(self.register(offset)) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { ptr::write_volatile(self.register(offset), value) }
+    }
+}
+
+/// Digest or HMAC computation in progress
+pub struct Hasher<'a, 'k, A: Algorithm> {
+    hash: &'a mut Hash,
+    /// HMAC key, fed again at the end
+    key: Option<&'k [u8]>,
+    buffer: [u8; 4],
+    buffered: usize,
+    /// The end of the message is transferred by DMA
+    dma: bool,
+    _algorithm: PhantomData<A>,
+}
+
+impl<'a, 'k, A: Algorithm> Hasher<'a, 'k, A> {
+    fn new(hash: &'a mut Hash, key: Option<&'k [u8]>) -> Self {
+        Self {
+            hash,
+            key,
+            buffer: [0; 4],
+            buffered: 0,
+            dma: false,
+            _algorithm: PhantomData,
+        }
+    }
+
+    /// Feeds message bytes, of any length
+    ///
+    /// Writes stall the bus while a block is being processed.
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.buffered > 0 {
+            let n = (4 - self.buffered).min(data.len());
+            self.buffer[self.buffered..self.buffered + n].copy_from_slice(&data[..n]);
+            self.buffered += n;
+            data = &data[n..];
+            if self.buffered < 4 {
+                return;
+            }
+            self.hash.write(DIN, u32::from_le_bytes(self.buffer));
+            self.buffered = 0;
+        }
+
+        let chunks = data.chunks_exact(4);
+        let remainder = chunks.remainder();
+        for word in chunks {
+            self.hash.write(
+                DIN,
+                u32::from_le_bytes([word[0], word[1], word[2], word[3]]),
+            );
+        }
+        self.buffer[..remainder.len()].copy_from_slice(remainder);
+        self.buffered = remainder.len();
+    }
+
+    /// Lets DMA transfer the last `len` bytes of the message to [`DataIn`]
+    ///
+    /// Bytes fed so far must be a multiple of 4. The digest calculation starts at
+    /// the end of the transfer.
+    pub fn enable_dma(&mut self, len: usize) {
+        assert!(
+            self.buffered == 0,
+            "DMA transfers must start on a word boundary"
+        );
+        self.hash.clear_digest_flag();
+        self.hash.write(STR, last_word_bits(len));
+        let cr = self.hash.read(CR);
+        self.hash.write(CR, cr | CR_DMAE);
+        self.dma = true;
+    }
+
+    /// Computes and returns the digest
+    pub fn finalize(self) -> A::Output {
+        if self.dma {
+            // DCAL is set by hardware at the end of the transfer
+            while self.hash.read(SR) & SR_BUSY != 0 || self.hash.read(CR) & CR_DMAE != 0 {}
+        } else {
+            self.hash.finish(&self.buffer[..self.buffered]);
+        }
+        if let Some(key) = self.key {
+            self.hash.wait_idle();
+            self.hash.feed_key(key);
+        }
+        while self.hash.read(SR) & SR_DCIS == 0 {}
+
+        let mut output = A::Output::default();
+        for (i, bytes) in output.as_mut().chunks_exact_mut(4).enumerate() {
+            let offset = if i < 5 { HR + 4 * i } else { hr_ext(i) };
+            bytes.copy_from_slice(&self.hash.read(offset).to_be_bytes());
+        }
+        output
+    }
+
+    /// Saves the computation state to compute another digest in between
+    ///
+    /// The context only borrows the HMAC key, the processor is free for other computations.
+    pub fn suspend(self) -> Context<'k, A> {
+        assert!(!self.dma, "a DMA computation can't be suspended");
+        self.hash.wait_idle();
+        let mut context = Context {
+            imr: self.hash.read(IMR),
+            str: self.hash.read(STR),
+            cr: self.hash.read(CR),
+            csr: [0; CSR_HMAC],
+            key: self.key,
+            buffer: self.buffer,
+            buffered: self.buffered,
+            _algorithm: PhantomData,
+        };
+        let count = if self.key.is_some() {
+            CSR_HMAC
+        } else {
+            CSR_HASH
+        };
+        for (i, csr) in context.csr[..count].iter_mut().enumerate() {
+            *csr = self.hash.read(CSR + 4 * i);
+        }
+        context
+    }
+}
+
+/// `NBLW` value: valid bits in the last word of a message of `len` bytes, 0 when it is full
+const fn last_word_bits(len: usize) -> u32 {
+    (len % 4) as u32 * 8
+}
+
+#[cfg(any(feature = "stm32f437", feature = "stm32f439", feature = "stm32f479"))]
+fn hr_ext(i: usize) -> usize {
+    HR_EXT + 4 * i
+}
+
+#[cfg(not(any(feature = "stm32f437", feature = "stm32f439", feature = "stm32f479")))]
+fn hr_ext(_: usize) -> usize {
+    unreachable!()
+}
+
+/// Saved state of a suspended computation
+pub struct Context<'k, A: Algorithm> {
+    imr: u32,
+    str: u32,
+    cr: u32,
+    csr: [u32; CSR_HMAC],
+    key: Option<&'k [u8]>,
+    buffer: [u8; 4],
+    buffered: usize,
+    _algorithm: PhantomData<A>,
+}
+
+/// DMA target of the input FIFO, DMA2 stream 7 channel 2
+pub struct DataIn {
+    _private: (),
+}
+
+unsafe impl PeriAddress for DataIn {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        HASH::ptr() as u32 + DIN as u32
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl DMASet<Stream7<DMA2>, 2, MemoryToPeripheral> for DataIn {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nblw() {
+        // A full last word is 0, not 32
+        assert_eq!(last_word_bits(0), 0);
+        assert_eq!(last_word_bits(4), 0);
+        assert_eq!(last_word_bits(64), 0);
+        assert_eq!(last_word_bits(1), 8);
+        assert_eq!(last_word_bits(3), 24);
+        assert_eq!(last_word_bits(1025), 8);
+        // DCAL is never set by the length
+        assert_eq!(last_word_bits(usize::MAX) & STR_DCAL, 0);
+    }
+}
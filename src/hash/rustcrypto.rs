@@ -0,0 +1,140 @@
+//! RustCrypto `digest` traits
+//!
+//! `Digest::new` takes no argument, so the processor is first handed over with
+//! [`Hash::share`]. A [`HashDigest`] takes it for the duration of a computation and gives it
+//! back when dropped.
+//!
+//! ```ignore
+//! use digest::Digest;
+//!
+//! Hash::new(dp.HASH).share();
+//! let digest = HashDigest::<Sha1>::digest(b"abc");
+//! ```
+
+use core::cell::RefCell;
+use core::marker::PhantomData;
+
+use cortex_m::interrupt::{self, Mutex};
+use digest::generic_array::ArrayLength;
+use digest::typenum::{U16, U20};
+use digest::{FixedOutput, HashMarker, Output, OutputSizeUser, Reset, Update};
+
+use super::{Algorithm, Hash, Hasher, Md5, Sha1};
+#[cfg(any(feature = "stm32f437", feature = "stm32f439", feature = "stm32f479"))]
+use super::{Sha224, Sha256};
+
+static SHARED: Mutex<RefCell<Option<Hash>>> = Mutex::new(RefCell::new(None));
+
+impl Hash {
+    /// Hands the processor over to [`HashDigest`]s
+    pub fn share(self) {
+        interrupt::free(|cs| *SHARED.borrow(cs).borrow_mut() = Some(self));
+    }
+
+    /// Takes back the processor given to [`share`](Self::share), `None` while a
+    /// [`HashDigest`] uses it
+    pub fn unshare() -> Option<Self> {
+        interrupt::free(|cs| SHARED.borrow(cs).borrow_mut().take())
+    }
+}
+
+/// Digest size of an [`Algorithm`], as a type
+pub trait DigestSize: Algorithm {
+    type OutputSize: ArrayLength<u8> + 'static;
+}
+
+impl DigestSize for Sha1 {
+    type OutputSize = U20;
+}
+
+impl DigestSize for Md5 {
+    type OutputSize = U16;
+}
+
+#[cfg(any(feature = "stm32f437", feature = "stm32f439", feature = "stm32f479"))]
+impl DigestSize for Sha224 {
+    type OutputSize = digest::typenum::U28;
+}
+
+#[cfg(any(feature = "stm32f437", feature = "stm32f439", feature = "stm32f479"))]
+impl DigestSize for Sha256 {
+    type OutputSize = digest::typenum::U32;
+}
+
+/// Digest computation on the shared processor, implementing `digest::Digest`
+pub struct HashDigest<A: Algorithm> {
+    /// Always `Some`, taken back when dropped
+    hash: Option<Hash>,
+    buffer: [u8; 4],
+    buffered: usize,
+    _algorithm: PhantomData<A>,
+}
+
+impl<A: Algorithm> HashDigest<A> {
+    fn hasher(&mut self) -> Hasher<'_, 'static, A> {
+        Hasher {
+            hash: self.hash.as_mut().unwrap(),
+            key: None,
+            buffer: self.buffer,
+            buffered: self.buffered,
+            dma: false,
+            _algorithm: PhantomData,
+        }
+    }
+}
+
+impl<A: Algorithm> Default for HashDigest<A> {
+    /// Takes the shared processor and starts a computation
+    ///
+    /// # Panics
+    ///
+    /// If the processor wasn't given to [`Hash::share`] or another [`HashDigest`] uses it.
+    fn default() -> Self {
+        let mut hash = Hash::unshare().expect("hash processor not shared or in use");
+        hash.init(A::CR_ALGO);
+        Self {
+            hash: Some(hash),
+            buffer: [0; 4],
+            buffered: 0,
+            _algorithm: PhantomData,
+        }
+    }
+}
+
+impl<A: Algorithm> Drop for HashDigest<A> {
+    fn drop(&mut self) {
+        if let Some(hash) = self.hash.take() {
+            hash.share();
+        }
+    }
+}
+
+impl<A: DigestSize> OutputSizeUser for HashDigest<A> {
+    type OutputSize = A::OutputSize;
+}
+
+impl<A: Algorithm> Update for HashDigest<A> {
+    fn update(&mut self, data: &[u8]) {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        let (buffer, buffered) = (hasher.buffer, hasher.buffered);
+        self.buffer = buffer;
+        self.buffered = buffered;
+    }
+}
+
+impl<A: DigestSize> FixedOutput for HashDigest<A> {
+    fn finalize_into(mut self, out: &mut Output<Self>) {
+        let mut digest = self.hasher().finalize();
+        out.copy_from_slice(digest.as_mut());
+    }
+}
+
+impl<A: Algorithm> Reset for HashDigest<A> {
+    fn reset(&mut self) {
+        self.buffered = 0;
+        self.hash.as_mut().unwrap().init(A::CR_ALGO);
+    }
+}
+
+impl<A: Algorithm> HashMarker for HashDigest<A> {}
@@ -9,16 +9,21 @@ use crate::pac::RCC;
 use crate::rcc::Clocks;
 use fugit::{HertzU32 as Hertz, RateExtU32};
 
+#[cfg(feature = "async")]
+#[clippy::msrv = "1.75"]
+pub mod asynch;
 mod hal_02;
 mod hal_1;
 
 #[derive(Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum DutyCycle {
     Ratio2to1,
     Ratio16to9,
 }
 
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Mode {
     Standard {
         frequency: Hertz,
@@ -94,9 +99,10 @@ where
     }
 }
 
-pub use embedded_hal_one::i2c::NoAcknowledgeSource;
+pub use embedded_hal_one::i2c::{NoAcknowledgeSource, Operation};
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[non_exhaustive]
 pub enum Error {
     Overrun,
@@ -106,6 +112,8 @@ pub enum Error {
     Bus,
     Crc,
     ArbitrationLoss,
+    /// 10-bit address above `0x3FF`
+    InvalidAddress,
 }
 
 impl Error {
@@ -186,6 +194,7 @@ where
         i2c
     }
 
+    /// Releases the I2C peripheral and pins, set back to their mode before construction
     pub fn release(mut self) -> (I2C, (SCL, SDA)) {
         self.pins.restore_mode();
 
@@ -345,6 +354,56 @@ impl<I2C: Instance, PINS> I2c<I2C, PINS> {
         Ok(())
     }
 
+    /// Sends a START condition followed by the 10-bit address `addr` in write direction
+    fn start_10bit(&self, addr: u16) -> Result<(), Error> {
+        let header = header_10bit(addr, false).ok_or(Error::InvalidAddress)?;
+
+        // Send a START condition
+        self.i2c.cr1.modify(|_, w| w.start().set_bit());
+
+        // Wait until START condition was generated
+        while self.check_and_clear_error_flags()?.sb().bit_is_clear() {}
+
+        // Also wait until signalled we're master and everything is waiting for us
+        loop {
+            self.check_and_clear_error_flags()?;
+
+            let sr2 = self.i2c.sr2.read();
+            if !(sr2.msl().bit_is_clear() && sr2.busy().bit_is_clear()) {
+                break;
+            }
+        }
+
+        // Send the header with the 2 most significant address bits
+        self.i2c.dr.write(|w| unsafe { w.bits(u32::from(header)) });
+
+        // Wait until header was sent
+        while self
+            .check_and_clear_error_flags()
+            .map_err(Error::nack_addr)?
+            .add10()
+            .bit_is_clear()
+        {}
+
+        // Send the 8 least significant address bits, this also clears ADD10
+        self.i2c
+            .dr
+            .write(|w| unsafe { w.bits(u32::from(addr as u8)) });
+
+        // Wait until address was sent
+        while self
+            .check_and_clear_error_flags()
+            .map_err(Error::nack_addr)?
+            .addr()
+            .bit_is_clear()
+        {}
+
+        // Clear condition by reading SR2
+        self.i2c.sr2.read();
+
+        Ok(())
+    }
+
     fn send_byte(&self, byte: u8) -> Result<(), Error> {
         // Wait until we're ready for sending
         // Check for any I2C errors. If a NACK occurs, the ADDR bit will never be set.
@@ -385,39 +444,8 @@ impl<I2C: Instance, PINS> I2c<I2C, PINS> {
         Ok(value)
     }
 
-    pub fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
+    fn recv_bytes(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
         if let Some((last, buffer)) = buffer.split_last_mut() {
-            // Send a START condition and set ACK bit
-            self.i2c
-                .cr1
-                .modify(|_, w| w.start().set_bit().ack().set_bit());
-
-            // Wait until START condition was generated
-            while self.i2c.sr1.read().sb().bit_is_clear() {}
-
-            // Also wait until signalled we're master and everything is waiting for us
-            while {
-                let sr2 = self.i2c.sr2.read();
-                sr2.msl().bit_is_clear() && sr2.busy().bit_is_clear()
-            } {}
-
-            // Set up current address, we're trying to talk to
-            self.i2c
-                .dr
-                .write(|w| unsafe { w.bits((u32::from(addr) << 1) + 1) });
-
-            // Wait until address was sent
-            loop {
-                self.check_and_clear_error_flags()
-                    .map_err(Error::nack_addr)?;
-                if self.i2c.sr1.read().addr().bit_is_set() {
-                    break;
-                }
-            }
-
-            // Clear condition by reading SR2
-            self.i2c.sr2.read();
-
             // Receive bytes into buffer
             for c in buffer {
                 *c = self.recv_byte()?;
@@ -441,6 +469,92 @@ impl<I2C: Instance, PINS> I2c<I2C, PINS> {
         }
     }
 
+    /// Disables clock stretching (`NOSTRETCH`) while operating in slave mode
+    ///
+    /// Some SMBus hosts require it to meet their timing. Without stretching the peripheral
+    /// can no longer hold SCL low while data is not ready, so overrun and underrun become
+    /// possible and are reported as [`Error::Overrun`].
+    pub fn set_no_stretch(&mut self, no_stretch: bool) {
+        // The peripheral has to be disabled while changing the configuration
+        self.i2c.cr1.modify(|_, w| w.pe().clear_bit());
+        self.i2c
+            .cr1
+            .modify(|_, w| w.nostretch().bit(no_stretch).pe().set_bit());
+    }
+
+    /// Resets the peripheral through its RCC reset line, keeping the current configuration
+    ///
+    /// Recovers from states the peripheral cannot leave on its own, e.g. a stuck `BUSY`
+    /// flag. A transfer in progress is aborted.
+    pub fn reset(&mut self) {
+        let cr1 = self.i2c.cr1.read().bits() & CR1_CONFIG;
+        let cr2 = self.i2c.cr2.read().bits();
+        let oar1 = self.i2c.oar1.read().bits();
+        let oar2 = self.i2c.oar2.read().bits();
+        let ccr = self.i2c.ccr.read().bits();
+        let trise = self.i2c.trise.read().bits();
+
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            I2C::reset(rcc);
+        }
+
+        // The timing registers can only be written while the peripheral is disabled
+        self.i2c.cr2.write(|w| unsafe { w.bits(cr2) });
+        self.i2c.oar1.write(|w| unsafe { w.bits(oar1) });
+        self.i2c.oar2.write(|w| unsafe { w.bits(oar2) });
+        self.i2c.ccr.write(|w| unsafe { w.bits(ccr) });
+        self.i2c.trise.write(|w| unsafe { w.bits(trise) });
+        self.i2c.cr1.write(|w| unsafe { w.bits(cr1) });
+        self.i2c.cr1.modify(|_, w| w.pe().set_bit());
+    }
+
+    pub fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        if buffer.is_empty() {
+            return Err(Error::Overrun);
+        }
+
+        self.start_read(addr)?;
+        self.recv_bytes(buffer)
+    }
+
+    /// Sends a (repeated) START condition followed by `addr` in read direction
+    fn start_read(&mut self, addr: u8) -> Result<(), Error> {
+        // Send a START condition and set ACK bit
+        self.i2c
+            .cr1
+            .modify(|_, w| w.start().set_bit().ack().set_bit());
+
+        // Wait until START condition was generated
+        while self.i2c.sr1.read().sb().bit_is_clear() {}
+
+        // Also wait until signalled we're master and everything is waiting for us
+        while {
+            let sr2 = self.i2c.sr2.read();
+            sr2.msl().bit_is_clear() && sr2.busy().bit_is_clear()
+        } {}
+
+        // Set up current address, we're trying to talk to
+        self.i2c
+            .dr
+            .write(|w| unsafe { w.bits((u32::from(addr) << 1) + 1) });
+
+        // Wait until address was sent
+        loop {
+            self.check_and_clear_error_flags()
+                .map_err(Error::nack_addr)?;
+            if self.i2c.sr1.read().addr().bit_is_set() {
+                break;
+            }
+        }
+
+        // Clear condition by reading SR2
+        self.i2c.sr2.read();
+
+        Ok(())
+    }
+
     pub fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
         self.write_bytes(addr, bytes.iter().cloned())?;
 
@@ -471,8 +585,15 @@ impl<I2C: Instance, PINS> I2c<I2C, PINS> {
     }
 
     pub fn write_read(&mut self, addr: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
-        self.write_bytes(addr, bytes.iter().cloned())?;
-        self.read(addr, buffer)
+        self.transaction(
+            addr,
+            &mut [Operation::Write(bytes), Operation::Read(buffer)],
+        )
+    }
+
+    /// Checks whether a device acknowledges `addr`, with a zero-length write
+    pub fn probe(&mut self, addr: u8) -> bool {
+        self.transaction(addr, &mut [Operation::Write(&[])]).is_ok()
     }
 
     pub fn write_iter_read<B>(&mut self, addr: u8, bytes: B, buffer: &mut [u8]) -> Result<(), Error>
@@ -482,4 +603,352 @@ impl<I2C: Instance, PINS> I2c<I2C, PINS> {
         self.write_bytes(addr, bytes.into_iter())?;
         self.read(addr, buffer)
     }
+
+    /// Executes a sequence of reads and writes as a single transaction
+    ///
+    /// Adjacent operations of the same direction are merged, a repeated START is sent
+    /// when the direction changes and a STOP after the last operation. Zero-length
+    /// writes only address the device, zero-length reads are rejected with
+    /// [`Error::Overrun`] before anything is sent. On any error but an arbitration loss a
+    /// STOP is still generated to release the bus, or the peripheral is [`reset`](Self::reset)
+    /// if the STOP cannot be sent.
+    pub fn transaction(&mut self, addr: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        if operations
+            .iter()
+            .any(|op| matches!(op, Operation::Read(buffer) if buffer.is_empty()))
+        {
+            return Err(Error::Overrun);
+        }
+
+        let result = self.transaction_runs(addr, operations);
+        match result {
+            Ok(()) => {}
+            // The peripheral fell back to slave mode, the bus belongs to the other master
+            Err(Error::ArbitrationLoss) => {}
+            Err(_) => self.release_bus(),
+        }
+        result
+    }
+
+    /// Sends a STOP condition after a failed transfer, or resets the peripheral if the STOP
+    /// is not sent, e.g. because SCL is held low
+    fn release_bus(&mut self) {
+        // Far longer than a STOP condition at 10 kHz with the fastest core clock
+        const STOP_SPINS: u32 = 100_000;
+
+        // Send a STOP condition
+        self.i2c
+            .cr1
+            .modify(|_, w| w.ack().clear_bit().stop().set_bit());
+
+        // Wait for STOP condition to transmit.
+        let mut spins = 0;
+        while self.i2c.cr1.read().stop().bit_is_set() {
+            spins += 1;
+            if spins == STOP_SPINS {
+                self.reset();
+                return;
+            }
+        }
+    }
+
+    fn transaction_runs(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        let mut operations = operations;
+        while let Some((read, run)) = take_run(&mut operations) {
+            let last = operations.is_empty();
+
+            if read {
+                self.read_operations(addr, run, last)?;
+            } else {
+                self.write_bytes(
+                    addr,
+                    run.iter().flat_map(|op| match op {
+                        Operation::Write(bytes) => bytes.iter().copied(),
+                        Operation::Read(_) => [].iter().copied(),
+                    }),
+                )?;
+                if last {
+                    // Send a STOP condition
+                    self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+
+                    // Wait for STOP condition to transmit.
+                    while self.i2c.cr1.read().stop().bit_is_set() {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads into the buffers of adjacent read operations, NACKs the last byte and sends
+    /// a STOP if `stop` is set, otherwise the next START is a repeated START
+    fn read_operations(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+        stop: bool,
+    ) -> Result<(), Error> {
+        let mut remaining = run_len(operations);
+        if remaining == 0 {
+            return Err(Error::Overrun);
+        }
+
+        self.start_read(addr)?;
+        for op in operations {
+            if let Operation::Read(buffer) = op {
+                for byte in buffer.iter_mut() {
+                    remaining -= 1;
+                    if remaining == 0 {
+                        // Prepare to send NACK, then STOP if requested, after next byte
+                        self.i2c.cr1.modify(|_, w| {
+                            let w = w.ack().clear_bit();
+                            if stop {
+                                w.stop().set_bit()
+                            } else {
+                                w
+                            }
+                        });
+                    }
+                    *byte = self.recv_byte()?;
+                }
+            }
+        }
+
+        if stop {
+            // Wait for the STOP to be sent.
+            while self.i2c.cr1.read().stop().bit_is_set() {}
+        }
+
+        Ok(())
+    }
+
+    /// Reads from a device with 10-bit address `addr`.
+    ///
+    /// The address is sent in write direction, then a repeated START with the
+    /// read header switches the bus to reading. Addresses above `0x3FF` are rejected with
+    /// [`Error::InvalidAddress`] before anything is sent, by all the 10-bit transfers.
+    pub fn read_10bit(&mut self, addr: u16, buffer: &mut [u8]) -> Result<(), Error> {
+        if buffer.is_empty() {
+            return Err(Error::Overrun);
+        }
+
+        self.start_10bit(addr)?;
+        self.restart_read_10bit(addr)?;
+        self.recv_bytes(buffer)
+    }
+
+    /// Writes `bytes` to a device with 10-bit address `addr`
+    pub fn write_10bit(&mut self, addr: u16, bytes: &[u8]) -> Result<(), Error> {
+        self.start_10bit(addr)?;
+
+        for c in bytes {
+            self.send_byte(*c)?;
+        }
+
+        // Send a STOP condition
+        self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+
+        // Wait for STOP condition to transmit.
+        while self.i2c.cr1.read().stop().bit_is_set() {}
+
+        // Fallthrough is success
+        Ok(())
+    }
+
+    /// Writes `bytes` to a device with 10-bit address `addr`, then reads into
+    /// `buffer` after a repeated START
+    pub fn write_read_10bit(
+        &mut self,
+        addr: u16,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        if buffer.is_empty() {
+            return Err(Error::Overrun);
+        }
+
+        self.start_10bit(addr)?;
+
+        for c in bytes {
+            self.send_byte(*c)?;
+        }
+
+        self.restart_read_10bit(addr)?;
+        self.recv_bytes(buffer)
+    }
+
+    /// Sends a repeated START followed by the read header of 10-bit address `addr`.
+    ///
+    /// The device remembers the full address from the preceding write, so only
+    /// the header is sent.
+    fn restart_read_10bit(&mut self, addr: u16) -> Result<(), Error> {
+        let header = header_10bit(addr, true).ok_or(Error::InvalidAddress)?;
+
+        // Send a repeated START condition and set ACK bit
+        self.i2c
+            .cr1
+            .modify(|_, w| w.start().set_bit().ack().set_bit());
+
+        // Wait until START condition was generated
+        while self.check_and_clear_error_flags()?.sb().bit_is_clear() {}
+
+        self.i2c.dr.write(|w| unsafe { w.bits(u32::from(header)) });
+
+        // Wait until header was sent
+        while self
+            .check_and_clear_error_flags()
+            .map_err(Error::nack_addr)?
+            .addr()
+            .bit_is_clear()
+        {}
+
+        // Clear condition by reading SR2
+        self.i2c.sr2.read();
+
+        Ok(())
+    }
+}
+
+/// First byte of a 10-bit address: `0b11110`, address bits 9 and 8, direction bit, `None`
+/// for an address above `0x3FF`
+#[inline(always)]
+const fn header_10bit(addr: u16, read: bool) -> Option<u8> {
+    if addr > 0x3ff {
+        None
+    } else {
+        Some(0b1111_0000 | ((addr >> 7) as u8 & 0b110) | read as u8)
+    }
+}
+
+/// Takes the leading operations of the same direction off `operations`, returns them with
+/// `true` for reads
+///
+/// Each run is sent after a (repeated) START, in a single direction.
+pub(crate) fn take_run<'a, 'b>(
+    operations: &mut &'a mut [Operation<'b>],
+) -> Option<(bool, &'a mut [Operation<'b>])> {
+    let read = matches!(operations.first()?, Operation::Read(_));
+    let len = operations
+        .iter()
+        .position(|op| matches!(op, Operation::Read(_)) != read)
+        .unwrap_or(operations.len());
+    let (run, rest) = core::mem::take(operations).split_at_mut(len);
+    *operations = rest;
+    Some((read, run))
+}
+
+/// Number of bytes read or written by `operations`
+pub(crate) fn run_len(operations: &[Operation<'_>]) -> usize {
+    operations
+        .iter()
+        .map(|op| match op {
+            Operation::Read(buffer) => buffer.len(),
+            Operation::Write(bytes) => bytes.len(),
+        })
+        .sum()
+}
+
+/// Configuration bits of CR1 kept by [`I2c::reset`]: `SMBUS`, `SMBTYPE`, `ENARP`, `ENPEC`,
+/// `ENGC` and `NOSTRETCH`, not `PE` or the `START`/`STOP`/`ACK` controls
+const CR1_CONFIG: u32 = 0b1111_1010;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ten_bit_headers() {
+        // 0b11110, address bits 9:8, R/W
+        for (addr, write) in [
+            (0x000, 0b1111_0000),
+            (0x0ff, 0b1111_0000),
+            (0x155, 0b1111_0010),
+            (0x2a5, 0b1111_0100),
+            (0x3ff, 0b1111_0110),
+        ] {
+            assert_eq!(header_10bit(addr, false), Some(write));
+            // Sent again after the repeated START
+            assert_eq!(header_10bit(addr, true), Some(write | 1));
+        }
+    }
+
+    #[test]
+    fn ten_bit_address_range() {
+        for addr in [0x400, 0x7ff, 0xffff] {
+            assert_eq!(header_10bit(addr, false), None);
+            assert_eq!(header_10bit(addr, true), None);
+        }
+    }
+
+    #[test]
+    fn reset_keeps_nostretch() {
+        const NOSTRETCH: u32 = 1 << 7;
+        assert_eq!(CR1_CONFIG & NOSTRETCH, NOSTRETCH);
+    }
+
+    #[test]
+    fn reset_drops_controls() {
+        const PE: u32 = 1 << 0;
+        const START: u32 = 1 << 8;
+        const STOP: u32 = 1 << 9;
+        const ACK: u32 = 1 << 10;
+        const POS: u32 = 1 << 11;
+        assert_eq!(CR1_CONFIG & (PE | START | STOP | ACK | POS), 0);
+    }
+
+    #[test]
+    fn transaction_runs() {
+        let (mut a, mut b, mut c) = ([0; 2], [0; 3], [0; 1]);
+        let mut operations = [
+            Operation::Write(&[0x10]),
+            Operation::Write(&[0x11, 0x12]),
+            Operation::Read(&mut a),
+            Operation::Read(&mut b),
+            Operation::Write(&[0x20]),
+            Operation::Read(&mut c),
+        ];
+        let mut rest = &mut operations[..];
+
+        let (read, run) = take_run(&mut rest).unwrap();
+        assert!(!read);
+        assert_eq!((run.len(), run_len(run)), (2, 3));
+
+        let (read, run) = take_run(&mut rest).unwrap();
+        assert!(read);
+        assert_eq!((run.len(), run_len(run)), (2, 5));
+        // Written into the buffers of the caller
+        if let Operation::Read(buffer) = &mut run[1] {
+            buffer[2] = 0xaa;
+        }
+
+        let (read, run) = take_run(&mut rest).unwrap();
+        assert!(!read);
+        assert_eq!(run_len(run), 1);
+
+        let (read, run) = take_run(&mut rest).unwrap();
+        assert!(read);
+        assert_eq!(run_len(run), 1);
+        // Last run, ends with a STOP
+        assert!(rest.is_empty());
+        assert!(take_run(&mut rest).is_none());
+
+        assert_eq!(b, [0, 0, 0xaa]);
+    }
+
+    #[test]
+    fn single_run() {
+        let mut buffer = [0; 4];
+        let mut operations = [Operation::Read(&mut buffer)];
+        let mut rest = &mut operations[..];
+        let (read, run) = take_run(&mut rest).unwrap();
+        assert!(read && rest.is_empty());
+        assert_eq!(run_len(run), 4);
+
+        let mut rest: &mut [Operation] = &mut [];
+        assert!(take_run(&mut rest).is_none());
+    }
 }
@@ -0,0 +1,435 @@
+//! Async I2C master driven by the event and error interrupts
+//!
+//! The transfer futures advance the bus state machine and sleep until the next event
+//! (`SB`, `ADDR`, `BTF`, `RXNE`, `TXE`) or error. The interrupt handlers only mask the
+//! I2C interrupts and wake the waiting future, so they have to be bound by the user:
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn I2C1_EV() {
+//!     i2c::asynch::on_event::<pac::I2C1>();
+//! }
+//!
+//! #[interrupt]
+//! fn I2C1_ER() {
+//!     i2c::asynch::on_error::<pac::I2C1>();
+//! }
+//! ```
+//!
+//! Both interrupts also have to be unmasked in the NVIC. Dropping a transfer future
+//! before it completes generates a STOP condition, leaving the bus idle.
+//!
+//! The `async` feature needs Rust 1.75 (`async fn` in traits), above the MSRV of the
+//! rest of the crate.
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use atomic_waker::AtomicWaker;
+
+use super::{Error, I2c, Operation};
+use crate::pac::{self, i2c1};
+
+/// I2C peripheral with an interrupt waker
+pub trait Instance: super::Instance {
+    #[doc(hidden)]
+    fn waker() -> &'static AtomicWaker;
+    #[doc(hidden)]
+    fn registers() -> &'static i2c1::RegisterBlock;
+}
+
+macro_rules! async_instance {
+    ($($(#[$attr:meta])* $I2C:ident,)+) => {
+        $(
+            $(#[$attr])*
+            impl Instance for pac::$I2C {
+                fn waker() -> &'static AtomicWaker {
+                    static WAKER: AtomicWaker = AtomicWaker::new();
+                    &WAKER
+                }
+
+                fn registers() -> &'static i2c1::RegisterBlock {
+                    unsafe { &*pac::$I2C::ptr() }
+                }
+            }
+        )+
+    };
+}
+
+async_instance! {
+    I2C1,
+    I2C2,
+    #[cfg(feature = "i2c3")]
+    I2C3,
+}
+
+/// Handler for the `I2Cx_EV` event interrupt
+pub fn on_event<I2C: Instance>() {
+    on_interrupt::<I2C>();
+}
+
+/// Handler for the `I2Cx_ER` error interrupt
+pub fn on_error<I2C: Instance>() {
+    on_interrupt::<I2C>();
+}
+
+fn on_interrupt<I2C: Instance>() {
+    // Mask the interrupts, the flags are handled by the waiting future
+    disable_interrupts(I2C::registers());
+    I2C::waker().wake();
+}
+
+fn disable_interrupts(i2c: &i2c1::RegisterBlock) {
+    i2c.cr2.modify(|_, w| {
+        w.itevten()
+            .clear_bit()
+            .itbufen()
+            .clear_bit()
+            .iterren()
+            .clear_bit()
+    });
+}
+
+/// Generates a STOP condition if the transfer is dropped or failed before completion
+struct StopOnDrop<I2C: Instance> {
+    armed: bool,
+    _i2c: PhantomData<I2C>,
+}
+
+impl<I2C: Instance> Drop for StopOnDrop<I2C> {
+    fn drop(&mut self) {
+        let i2c = I2C::registers();
+        disable_interrupts(i2c);
+
+        if self.armed && (i2c.sr2.read().msl().bit_is_set() || i2c.cr1.read().start().bit_is_set())
+        {
+            // Send a STOP condition, after a pending START if there is one
+            i2c.cr1.modify(|_, w| w.ack().clear_bit().stop().set_bit());
+
+            // Wait for STOP condition to transmit.
+            while i2c.cr1.read().stop().bit_is_set() {}
+        }
+        i2c.cr1.modify(|_, w| w.pos().clear_bit());
+    }
+}
+
+/// I2C master implementing the `embedded-hal-async` traits
+pub struct AsyncI2c<I2C: Instance, PINS> {
+    i2c: I2c<I2C, PINS>,
+}
+
+impl<I2C: Instance, PINS> I2c<I2C, PINS> {
+    /// Converts into an interrupt driven async I2C master
+    pub fn into_async(self) -> AsyncI2c<I2C, PINS> {
+        disable_interrupts(&self.i2c);
+        AsyncI2c { i2c: self }
+    }
+}
+
+impl<I2C: Instance, PINS> AsyncI2c<I2C, PINS> {
+    /// Converts back into the blocking I2C master
+    pub fn into_blocking(self) -> I2c<I2C, PINS> {
+        disable_interrupts(&self.i2c.i2c);
+        self.i2c
+    }
+
+    pub async fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        self.transaction(addr, &mut [Operation::Read(buffer)]).await
+    }
+
+    pub async fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+        self.transaction(addr, &mut [Operation::Write(bytes)]).await
+    }
+
+    pub async fn write_read(
+        &mut self,
+        addr: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        self.transaction(
+            addr,
+            &mut [Operation::Write(bytes), Operation::Read(buffer)],
+        )
+        .await
+    }
+
+    /// Checks whether a device acknowledges `addr`, with a zero-length write
+    pub async fn probe(&mut self, addr: u8) -> bool {
+        self.transaction(addr, &mut [Operation::Write(&[])])
+            .await
+            .is_ok()
+    }
+
+    /// Executes a sequence of reads and writes as a single transaction
+    ///
+    /// Same bus behaviour as the blocking [`I2c::transaction`]. The bus is released with a
+    /// STOP condition on errors and when the future is dropped before completion.
+    pub async fn transaction(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        if operations
+            .iter()
+            .any(|op| matches!(op, Operation::Read(buffer) if buffer.is_empty()))
+        {
+            return Err(Error::Overrun);
+        }
+
+        let mut guard = StopOnDrop::<I2C> {
+            armed: true,
+            _i2c: PhantomData,
+        };
+        let result = self.transaction_runs(addr, operations).await;
+        guard.armed = result.is_err();
+        result
+    }
+
+    async fn transaction_runs(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        let mut operations = operations;
+        while let Some(first) = operations.first() {
+            let read = matches!(first, Operation::Read(_));
+            let len = operations
+                .iter()
+                .position(|op| matches!(op, Operation::Read(_)) != read)
+                .unwrap_or(operations.len());
+            let (run, rest) = core::mem::take(&mut operations).split_at_mut(len);
+            let last = rest.is_empty();
+
+            if read {
+                self.read_operations(addr, run, last).await?;
+            } else {
+                self.write_operations(addr, run, last).await?;
+            }
+
+            operations = rest;
+        }
+
+        Ok(())
+    }
+
+    /// Sends a (repeated) START condition followed by `addr`, `ADDR` is left set
+    async fn start(&mut self, addr: u8, read: bool) -> Result<(), Error> {
+        // Send a START condition, set ACK bit and clear POS
+        self.i2c
+            .i2c
+            .cr1
+            .modify(|_, w| w.start().set_bit().ack().set_bit().pos().clear_bit());
+
+        // Wait until START condition was generated
+        self.wait(false, |sr1| sr1.sb().bit_is_set()).await?;
+
+        // Set up current address, we're trying to talk to
+        self.i2c
+            .i2c
+            .dr
+            .write(|w| unsafe { w.bits(u32::from(addr) << 1 | u32::from(read)) });
+
+        // Wait until address was sent
+        self.wait(false, |sr1| sr1.addr().bit_is_set())
+            .await
+            .map_err(Error::nack_addr)
+    }
+
+    async fn write_operations(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+        stop: bool,
+    ) -> Result<(), Error> {
+        self.start(addr, false).await?;
+
+        // Clear condition by reading SR2
+        self.i2c.i2c.sr2.read();
+
+        let mut sent = false;
+        for op in operations.iter() {
+            if let Operation::Write(bytes) = op {
+                for byte in bytes.iter() {
+                    // Wait until the data register is empty
+                    self.wait(true, |sr1| sr1.tx_e().bit_is_set())
+                        .await
+                        .map_err(Error::nack_data)?;
+
+                    self.i2c
+                        .i2c
+                        .dr
+                        .write(|w| unsafe { w.bits(u32::from(*byte)) });
+                    sent = true;
+                }
+            }
+        }
+
+        if sent {
+            // Wait until the last byte is transferred
+            self.wait(false, |sr1| sr1.btf().bit_is_set())
+                .await
+                .map_err(Error::nack_data)?;
+        }
+
+        if stop {
+            // Send a STOP condition
+            self.i2c.i2c.cr1.modify(|_, w| w.stop().set_bit());
+
+            // Wait for STOP condition to transmit.
+            while self.i2c.i2c.cr1.read().stop().bit_is_set() {}
+        }
+
+        Ok(())
+    }
+
+    /// Reads into the buffers of adjacent read operations
+    ///
+    /// Follows the reference manual (RM0090 27.3.3) sequences for receiving 1, 2 and
+    /// more than 2 bytes, so the last byte is NACKed without relying on the interrupt
+    /// latency. A STOP is sent after the last byte if `stop` is set, otherwise the next
+    /// START is a repeated START.
+    async fn read_operations(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+        stop: bool,
+    ) -> Result<(), Error> {
+        let len: usize = operations
+            .iter()
+            .map(|op| match op {
+                Operation::Read(buffer) => buffer.len(),
+                Operation::Write(_) => 0,
+            })
+            .sum();
+
+        self.start(addr, true).await?;
+
+        match len {
+            1 => {
+                // A single byte has to be NACKed before ADDR is cleared
+                self.i2c.i2c.cr1.modify(|_, w| w.ack().clear_bit());
+                self.i2c.i2c.sr2.read();
+                if stop {
+                    self.i2c.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                }
+            }
+            2 => {
+                // With POS set, ACK applies to the byte in the shift register, which is
+                // the second one
+                self.i2c
+                    .i2c
+                    .cr1
+                    .modify(|_, w| w.pos().set_bit().ack().clear_bit());
+                self.i2c.i2c.sr2.read();
+            }
+            _ => {
+                // Clear condition by reading SR2
+                self.i2c.i2c.sr2.read();
+            }
+        }
+
+        let bytes = operations.iter_mut().flat_map(|op| match op {
+            Operation::Read(buffer) => buffer.iter_mut(),
+            Operation::Write(_) => [].iter_mut(),
+        });
+        for (i, byte) in bytes.enumerate() {
+            match len - i {
+                3 => {
+                    // Byte N-2 is in DR and N-1 in the shift register, NACK byte N
+                    self.wait(false, |sr1| sr1.btf().bit_is_set())
+                        .await
+                        .map_err(Error::nack_data)?;
+                    self.i2c.i2c.cr1.modify(|_, w| w.ack().clear_bit());
+                }
+                2 => {
+                    // Byte N-1 is in DR and N in the shift register
+                    self.wait(false, |sr1| sr1.btf().bit_is_set())
+                        .await
+                        .map_err(Error::nack_data)?;
+                    if stop {
+                        self.i2c.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                    }
+                }
+                _ => {
+                    // Wait until a byte was received
+                    self.wait(true, |sr1| sr1.rx_ne().bit_is_set())
+                        .await
+                        .map_err(Error::nack_data)?;
+                }
+            }
+
+            *byte = self.i2c.i2c.dr.read().bits() as u8;
+        }
+
+        if len == 2 {
+            self.i2c.i2c.cr1.modify(|_, w| w.pos().clear_bit());
+        }
+
+        if stop {
+            // Wait for the STOP to be sent.
+            while self.i2c.i2c.cr1.read().stop().bit_is_set() {}
+        }
+
+        Ok(())
+    }
+
+    /// Waits until `done` holds for `SR1` or an error flag is raised
+    ///
+    /// Sleeps on the event and error interrupts, and also on the buffer interrupts
+    /// (`TXE`, `RXNE`) if `buffer` is set.
+    async fn wait(&self, buffer: bool, done: impl Fn(&i2c1::sr1::R) -> bool) -> Result<(), Error> {
+        poll_fn(|cx| {
+            I2C::waker().register(cx.waker());
+
+            match self.i2c.check_and_clear_error_flags() {
+                Err(e) => Poll::Ready(Err(e)),
+                Ok(sr1) if done(&sr1) => Poll::Ready(Ok(())),
+                Ok(_) => {
+                    self.i2c.i2c.cr2.modify(|_, w| {
+                        w.itevten()
+                            .set_bit()
+                            .itbufen()
+                            .bit(buffer)
+                            .iterren()
+                            .set_bit()
+                    });
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl<I2C: Instance, PINS> embedded_hal_async::i2c::ErrorType for AsyncI2c<I2C, PINS> {
+    type Error = Error;
+}
+
+impl<I2C: Instance, PINS> embedded_hal_async::i2c::I2c for AsyncI2c<I2C, PINS> {
+    async fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        Self::read(self, addr, buffer).await
+    }
+
+    async fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        Self::write(self, addr, bytes).await
+    }
+
+    async fn write_read(
+        &mut self,
+        addr: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        Self::write_read(self, addr, bytes, buffer).await
+    }
+
+    async fn transaction(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        Self::transaction(self, addr, operations).await
+    }
+}
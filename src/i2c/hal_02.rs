@@ -1,6 +1,8 @@
 mod blocking {
     use super::super::{Error, I2c, Instance};
-    use embedded_hal::blocking::i2c::{Read, Write, WriteIter, WriteIterRead, WriteRead};
+    use embedded_hal::blocking::i2c::{
+        Read, TenBitAddress, Write, WriteIter, WriteIterRead, WriteRead,
+    };
 
     impl<I2C, PINS> WriteRead for I2c<I2C, PINS>
     where
@@ -72,4 +74,42 @@ mod blocking {
             self.read(addr, buffer)
         }
     }
+
+    impl<I2C, PINS> WriteRead<TenBitAddress> for I2c<I2C, PINS>
+    where
+        I2C: Instance,
+    {
+        type Error = Error;
+
+        fn write_read(
+            &mut self,
+            addr: u16,
+            bytes: &[u8],
+            buffer: &mut [u8],
+        ) -> Result<(), Self::Error> {
+            self.write_read_10bit(addr, bytes, buffer)
+        }
+    }
+
+    impl<I2C, PINS> Write<TenBitAddress> for I2c<I2C, PINS>
+    where
+        I2C: Instance,
+    {
+        type Error = Error;
+
+        fn write(&mut self, addr: u16, bytes: &[u8]) -> Result<(), Self::Error> {
+            self.write_10bit(addr, bytes)
+        }
+    }
+
+    impl<I2C, PINS> Read<TenBitAddress> for I2c<I2C, PINS>
+    where
+        I2C: Instance,
+    {
+        type Error = Error;
+
+        fn read(&mut self, addr: u16, buffer: &mut [u8]) -> Result<(), Self::Error> {
+            self.read_10bit(addr, buffer)
+        }
+    }
 }
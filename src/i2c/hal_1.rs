@@ -7,7 +7,7 @@ impl Error for super::Error {
             Self::Bus => ErrorKind::Bus,
             Self::ArbitrationLoss => ErrorKind::ArbitrationLoss,
             Self::NoAcknowledge(nack) => ErrorKind::NoAcknowledge(nack),
-            Self::Crc | Self::Timeout => ErrorKind::Other,
+            Self::Crc | Self::Timeout | Self::InvalidAddress => ErrorKind::Other,
         }
     }
 }
@@ -18,9 +18,9 @@ impl<I2C: super::Instance, PINS> ErrorType for super::I2c<I2C, PINS> {
 
 mod blocking {
     use super::super::{I2c, Instance};
-    use embedded_hal_one::i2c::blocking::Operation;
+    use embedded_hal_one::i2c::Operation;
 
-    impl<I2C: Instance, PINS> embedded_hal_one::i2c::blocking::I2c for I2c<I2C, PINS> {
+    impl<I2C: Instance, PINS> embedded_hal_one::i2c::I2c for I2c<I2C, PINS> {
         fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
             self.read(addr, buffer)
         }
@@ -29,13 +29,6 @@ mod blocking {
             self.write(addr, bytes)
         }
 
-        fn write_iter<B>(&mut self, addr: u8, bytes: B) -> Result<(), Self::Error>
-        where
-            B: IntoIterator<Item = u8>,
-        {
-            self.write_iter(addr, bytes)
-        }
-
         fn write_read(
             &mut self,
             addr: u8,
@@ -45,31 +38,12 @@ mod blocking {
             self.write_read(addr, bytes, buffer)
         }
 
-        fn write_iter_read<B>(
+        fn transaction(
             &mut self,
             addr: u8,
-            bytes: B,
-            buffer: &mut [u8],
-        ) -> Result<(), Self::Error>
-        where
-            B: IntoIterator<Item = u8>,
-        {
-            self.write_iter_read(addr, bytes, buffer)
-        }
-
-        fn transaction<'a>(
-            &mut self,
-            _addr: u8,
-            _operations: &mut [Operation<'a>],
+            operations: &mut [Operation<'_>],
         ) -> Result<(), Self::Error> {
-            todo!()
-        }
-
-        fn transaction_iter<'a, O>(&mut self, _addr: u8, _operations: O) -> Result<(), Self::Error>
-        where
-            O: IntoIterator<Item = Operation<'a>>,
-        {
-            todo!()
+            self.transaction(addr, operations)
         }
     }
 }
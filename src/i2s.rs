@@ -188,6 +188,7 @@ where
         }
     }
 
+    /// Releases the SPI peripheral and pins, set back to their mode before construction
     pub fn release(mut self) -> (SPI, (WS, CK, MCLK, SD)) {
         self.pins.restore_mode();
 
@@ -350,3 +351,172 @@ mod dma {
     {
     }
 }
+
+/// Conversion of 24-bit and 32-bit samples to and from the half-words moved by DMA
+///
+/// The data register is 16 bits wide, so a 24-bit or 32-bit sample takes two DMA transfers,
+/// most significant half-word first. Reading the buffer as `u32` on this little-endian core
+/// would swap the halves, these functions put them in the right order.
+///
+/// ```
+/// use stm32f4xx_hal::i2s::sample::{pack, unpack, SampleFormat};
+///
+/// let mut dma_buffer = [0u16; 4];
+/// pack(SampleFormat::Data24Frame32, &[-2, 0x12_3456], &mut dma_buffer);
+/// assert_eq!(dma_buffer, [0xffff, 0xfe00, 0x1234, 0x5600]);
+///
+/// let mut samples = [0i32; 2];
+/// unpack(SampleFormat::Data24Frame32, &dma_buffer, &mut samples);
+/// assert_eq!(samples, [-2, 0x12_3456]);
+/// ```
+pub mod sample {
+    /// Data format of the samples exchanged with the codec
+    #[derive(Debug, Eq, PartialEq, Copy, Clone)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    pub enum SampleFormat {
+        /// 24-bit data in a 32-bit frame, samples are sign-extended 24-bit values
+        Data24Frame32,
+        /// 32-bit data
+        Data32,
+    }
+
+    /// Half-words sent for `sample`, in transfer order
+    pub fn to_half_words(format: SampleFormat, sample: i32) -> [u16; 2] {
+        match format {
+            // D23..D8, then D7..D0 left-aligned, the low byte is padding
+            SampleFormat::Data24Frame32 => [(sample >> 8) as u16, (sample << 8) as u16],
+            SampleFormat::Data32 => [(sample >> 16) as u16, sample as u16],
+        }
+    }
+
+    /// Sample received as `half_words`, in transfer order
+    pub fn from_half_words(format: SampleFormat, half_words: [u16; 2]) -> i32 {
+        let word = (u32::from(half_words[0]) << 16 | u32::from(half_words[1])) as i32;
+        match format {
+            // Arithmetic shift sign-extends the 24-bit value
+            SampleFormat::Data24Frame32 => word >> 8,
+            SampleFormat::Data32 => word,
+        }
+    }
+
+    /// Packs `samples` in `half_words` for a DMA transmission
+    ///
+    /// Returns the number of samples packed, limited by the length of `half_words`.
+    pub fn pack(format: SampleFormat, samples: &[i32], half_words: &mut [u16]) -> usize {
+        let mut count = 0;
+        for (sample, pair) in samples.iter().zip(half_words.chunks_exact_mut(2)) {
+            pair.copy_from_slice(&to_half_words(format, *sample));
+            count += 1;
+        }
+        count
+    }
+
+    /// Unpacks the `half_words` received by DMA to `samples`
+    ///
+    /// Returns the number of samples unpacked, limited by the length of `samples`.
+    pub fn unpack(format: SampleFormat, half_words: &[u16], samples: &mut [i32]) -> usize {
+        let mut count = 0;
+        for (pair, sample) in half_words.chunks_exact(2).zip(samples.iter_mut()) {
+            *sample = from_half_words(format, [pair[0], pair[1]]);
+            count += 1;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sample::*;
+
+    #[test]
+    fn half_word_order() {
+        // Most significant half-word first
+        assert_eq!(
+            to_half_words(SampleFormat::Data32, 0x1234_5678),
+            [0x1234, 0x5678]
+        );
+        assert_eq!(
+            to_half_words(SampleFormat::Data24Frame32, 0x12_3456),
+            [0x1234, 0x5600]
+        );
+        assert_eq!(
+            from_half_words(SampleFormat::Data32, [0x1234, 0x5678]),
+            0x1234_5678
+        );
+    }
+
+    #[test]
+    fn sign_extension_24() {
+        assert_eq!(
+            to_half_words(SampleFormat::Data24Frame32, -1),
+            [0xffff, 0xff00]
+        );
+        assert_eq!(
+            to_half_words(SampleFormat::Data24Frame32, -0x80_0000),
+            [0x8000, 0x0000]
+        );
+        assert_eq!(
+            from_half_words(SampleFormat::Data24Frame32, [0xffff, 0xff00]),
+            -1
+        );
+        assert_eq!(
+            from_half_words(SampleFormat::Data24Frame32, [0x8000, 0x0000]),
+            -0x80_0000
+        );
+        assert_eq!(
+            from_half_words(SampleFormat::Data24Frame32, [0x7fff, 0xff00]),
+            0x7f_ffff
+        );
+        // The padding byte is ignored
+        assert_eq!(
+            from_half_words(SampleFormat::Data24Frame32, [0xffff, 0xfeff]),
+            -2
+        );
+    }
+
+    #[test]
+    fn out_of_range_24_keeps_low_bits() {
+        // Only D23..D0 are sent, the result is sign-extended from bit 23
+        let mut half_words = [0; 4];
+        pack(
+            SampleFormat::Data24Frame32,
+            &[i32::MAX, i32::MIN],
+            &mut half_words,
+        );
+        assert_eq!(half_words, [0xffff, 0xff00, 0x0000, 0x0000]);
+        let mut samples = [0; 2];
+        unpack(SampleFormat::Data24Frame32, &half_words, &mut samples);
+        assert_eq!(samples, [-1, 0]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let cases = [
+            (
+                SampleFormat::Data24Frame32,
+                [0, 1, -1, 0x12_3456, -0x12_3456, 0x7f_ffff, -0x80_0000],
+            ),
+            (
+                SampleFormat::Data32,
+                [0, 1, -1, 0x1234_5678, -0x1234_5678, i32::MAX, i32::MIN],
+            ),
+        ];
+        for (format, samples) in cases {
+            let mut half_words = [0; 14];
+            assert_eq!(pack(format, &samples, &mut half_words), 7);
+            let mut unpacked = [0; 7];
+            assert_eq!(unpack(format, &half_words, &mut unpacked), 7);
+            assert_eq!(unpacked, samples);
+        }
+    }
+
+    #[test]
+    fn short_buffers() {
+        let mut half_words = [0; 3];
+        assert_eq!(pack(SampleFormat::Data32, &[1, 2], &mut half_words), 1);
+        assert_eq!(half_words, [0, 1, 0]);
+        let mut samples = [0; 1];
+        assert_eq!(unpack(SampleFormat::Data32, &[0, 1, 0, 2], &mut samples), 1);
+        assert_eq!(samples, [1]);
+    }
+}
@@ -114,6 +114,8 @@ pub mod bb;
 pub mod can;
 #[cfg(feature = "device-selected")]
 pub mod crc32;
+#[cfg(all(feature = "device-selected", feature = "cryp"))]
+pub mod cryp;
 #[cfg(all(feature = "device-selected", feature = "dac"))]
 pub mod dac;
 #[cfg(feature = "device-selected")]
@@ -121,10 +123,14 @@ pub mod dac;
 pub mod fmpi2c;
 #[cfg(feature = "device-selected")]
 pub mod gpio;
+#[cfg(all(feature = "device-selected", feature = "hash"))]
+pub mod hash;
 #[cfg(feature = "device-selected")]
 pub mod i2c;
 #[cfg(all(feature = "device-selected"))]
 pub mod i2s;
+#[cfg(feature = "lptim1")]
+pub mod lptim;
 #[cfg(all(feature = "device-selected", feature = "usb_fs", feature = "otg-fs"))]
 pub mod otg_fs;
 #[cfg(all(
@@ -137,26 +143,44 @@ pub mod otg_hs;
 #[cfg(all(feature = "device-selected", feature = "rng"))]
 pub mod rng;
 
+#[cfg(all(feature = "device-selected", feature = "dcmi"))]
+pub mod dcmi;
+#[cfg(all(feature = "device-selected", feature = "dfsdm"))]
+pub mod dfsdm;
 #[cfg(feature = "device-selected")]
 pub mod dma;
+#[cfg(all(feature = "device-selected", feature = "dma2d"))]
+pub mod dma2d;
 #[cfg(feature = "device-selected")]
 pub mod dwt;
+#[cfg(all(feature = "device-selected", feature = "eth"))]
+pub mod ethernet;
 #[cfg(feature = "device-selected")]
 pub mod flash;
+#[cfg(all(feature = "device-selected", any(feature = "fmc", feature = "fsmc")))]
+pub mod fsmc;
 #[cfg(all(
     feature = "device-selected",
     feature = "fsmc_lcd",
     any(feature = "fmc", feature = "fsmc")
 ))]
 pub mod fsmc_lcd;
+#[cfg(all(feature = "device-selected", feature = "ltdc"))]
+pub mod ltdc;
+#[cfg(feature = "device-selected")]
+pub mod mpu;
 #[cfg(feature = "device-selected")]
 pub mod prelude;
 #[cfg(feature = "device-selected")]
 pub mod qei;
+#[cfg(all(feature = "device-selected", feature = "quadspi"))]
+pub mod qspi;
 #[cfg(feature = "device-selected")]
 pub mod rcc;
 #[cfg(feature = "device-selected")]
 pub mod rtc;
+#[cfg(all(feature = "device-selected", feature = "sai1"))]
+pub mod sai;
 #[cfg(all(feature = "device-selected", feature = "sdio-host", feature = "sdio"))]
 pub mod sdio;
 #[cfg(feature = "device-selected")]
@@ -165,6 +189,8 @@ pub mod serial;
 pub mod signature;
 #[cfg(feature = "device-selected")]
 pub mod spi;
+#[cfg(all(feature = "device-selected", feature = "spdifrx"))]
+pub mod spdifrx;
 #[cfg(feature = "device-selected")]
 pub mod syscfg;
 #[cfg(feature = "device-selected")]
@@ -0,0 +1,378 @@
+//! Low-power timer (LPTIM1)
+//!
+//! The LPTIM keeps counting in Stop mode when clocked from LSI or LSE, for periodic
+//! wakeups with [`LpTimer::listen`] or a PWM output with a low current budget.
+//!
+//! LSE has to be running already, e.g. started by [`Rtc::new`](crate::rtc::Rtc::new). LSI
+//! is started by [`LpTimer::new`].
+//!
+//! ```ignore
+//! let mut timer = LpTimer::new(dp.LPTIM1, ClockSource::Lse, &clocks);
+//! timer.start(500.millis()).unwrap();
+//! timer.listen();
+//! ```
+
+use crate::gpio::{Const, PinA, PushPull, SetAlternate};
+#[cfg(feature = "stm32f410")]
+use crate::pac::LPTIM1;
+// The stm32f413 PAC drops the instance number
+#[cfg(any(feature = "stm32f413", feature = "stm32f423"))]
+use crate::pac::LPTIM as LPTIM1;
+use crate::pac::RCC;
+use crate::rcc::{Clocks, Enable, Reset};
+use crate::timer::Error;
+use fugit::{HertzU32 as Hertz, MicrosDurationU32, RateExtU32};
+
+mod hal_02;
+
+/// `LPTIM1_OUT` pin function
+pub struct Out;
+impl crate::Sealed for Out {}
+
+/// Kernel clock of the LPTIM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClockSource {
+    /// APB1 clock, stopped in Stop mode
+    Apb1,
+    /// 16 MHz HSI, stopped in Stop mode
+    Hsi,
+    /// 32 kHz LSI
+    Lsi,
+    /// 32.768 kHz LSE
+    Lse,
+}
+
+impl ClockSource {
+    fn sel(self) -> u32 {
+        match self {
+            Self::Apb1 => 0b00,
+            Self::Hsi => 0b01,
+            Self::Lsi => 0b10,
+            Self::Lse => 0b11,
+        }
+    }
+
+    fn frequency(self, clocks: &Clocks) -> Hertz {
+        match self {
+            Self::Apb1 => clocks.pclk1(),
+            Self::Hsi => 16.MHz(),
+            Self::Lsi => 32.kHz(),
+            Self::Lse => 32_768.Hz(),
+        }
+    }
+}
+
+// ISR and ICR bits
+const CMPM: u32 = 1 << 0;
+const ARRM: u32 = 1 << 1;
+const CMPOK: u32 = 1 << 3;
+const ARROK: u32 = 1 << 4;
+
+// CFGR bits
+const PRESC_OFFSET: u32 = 9;
+const PRESC_MASK: u32 = 0b111 << PRESC_OFFSET;
+const WAVPOL: u32 = 1 << 21;
+
+// CR bits
+const ENABLE: u32 = 1 << 0;
+const CNTSTRT: u32 = 1 << 2;
+
+/// LPTIM clock cycles to wait for a write of ARR or CMP to be taken over, a few are needed
+const SYNC_CYCLES: u32 = 16;
+
+/// Returns the `PRESC` value and the auto-reload value for a period of `ticks` kernel
+/// clock cycles, the prescaler divides by `2^PRESC`
+pub(crate) const fn compute_arr_presc(ticks: u32) -> Option<(u8, u16)> {
+    let mut presc = 0;
+    while presc < 8 {
+        let period = ticks >> presc;
+        if period <= 1 << 16 {
+            return if period < 2 {
+                None
+            } else {
+                Some((presc, (period - 1) as u16))
+            };
+        }
+        presc += 1;
+    }
+    None
+}
+
+/// Polls of ISR covering at least `SYNC_CYCLES` cycles of the kernel clock `clk`, a poll
+/// taking at least one `hclk` cycle
+const fn sync_polls(hclk: Hertz, clk: Hertz) -> u32 {
+    let ratio = hclk.raw() / clk.raw();
+    let ratio = if ratio == 0 { 1 } else { ratio };
+    ratio.saturating_mul(SYNC_CYCLES)
+}
+
+/// Low-power timer
+pub struct LpTimer {
+    lptim: LPTIM1,
+    clk: Hertz,
+    /// Polls of ISR covering at least `SYNC_CYCLES` LPTIM clock cycles
+    sync_polls: u32,
+}
+
+impl LpTimer {
+    /// Enables and resets the LPTIM, clocked from `clock_source`
+    pub fn new(lptim: LPTIM1, clock_source: ClockSource, clocks: &Clocks) -> Self {
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+
+            if clock_source == ClockSource::Lsi {
+                cortex_m::interrupt::free(|_| rcc.csr.modify(|_, w| w.lsion().on()));
+                while rcc.csr.read().lsirdy().bit_is_clear() {}
+            }
+
+            // LPTIM1SEL
+            cortex_m::interrupt::free(|_| {
+                rcc.dckcfgr2
+                    .modify(|r, w| w.bits((r.bits() & !(0b11 << 30)) | (clock_source.sel() << 30)))
+            });
+
+            LPTIM1::enable(rcc);
+            LPTIM1::reset(rcc);
+        }
+
+        let clk = clock_source.frequency(clocks);
+        Self {
+            lptim,
+            clk,
+            sync_polls: sync_polls(clocks.hclk(), clk),
+        }
+    }
+
+    /// Kernel clock frequency
+    pub fn clk(&self) -> Hertz {
+        self.clk
+    }
+
+    /// Starts counting, the update flag is set every `timeout`
+    ///
+    /// Returns [`Error::Disabled`] if the kernel clock is not running.
+    pub fn start(&mut self, timeout: MicrosDurationU32) -> Result<(), Error> {
+        let ticks = u64::from(self.clk.raw()) * u64::from(timeout.ticks()) / 1_000_000;
+        let ticks = u32::try_from(ticks).map_err(|_| Error::WrongAutoReload)?;
+        let (presc, arr) = compute_arr_presc(ticks).ok_or(Error::WrongAutoReload)?;
+
+        self.configure(presc, 0);
+        self.set_arr(arr)?;
+        self.clear_flags(ARRM | CMPM);
+        self.lptim
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() | CNTSTRT) });
+        Ok(())
+    }
+
+    /// Returns `Ok` once per period, clearing the update flag
+    pub fn wait(&mut self) -> nb::Result<(), Error> {
+        if self.lptim.isr.read().bits() & ARRM != 0 {
+            self.clear_flags(ARRM);
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Stops counting
+    pub fn cancel(&mut self) -> Result<(), Error> {
+        if self.lptim.cr.read().bits() & ENABLE == 0 {
+            return Err(Error::Disabled);
+        }
+        self.lptim.cr.write(|w| unsafe { w.bits(0) });
+        Ok(())
+    }
+
+    /// Enables the interrupt on each period, it can wake the core up from Stop mode
+    pub fn listen(&mut self) {
+        // IER can only be written while the LPTIM is disabled
+        self.modify_disabled(|lptim| {
+            lptim.ier.modify(|r, w| unsafe { w.bits(r.bits() | ARRM) });
+        });
+    }
+
+    /// Disables the interrupt on each period
+    pub fn unlisten(&mut self) {
+        self.modify_disabled(|lptim| {
+            lptim.ier.modify(|r, w| unsafe { w.bits(r.bits() & !ARRM) });
+        });
+    }
+
+    /// Clears the interrupt flag of the period
+    pub fn clear_interrupt(&mut self) {
+        self.clear_flags(ARRM);
+    }
+
+    /// Returns the current count
+    pub fn count(&self) -> u16 {
+        // The counter is asynchronous to the APB clock, it is stable once read twice
+        loop {
+            let a = self.lptim.cnt.read().bits();
+            let b = self.lptim.cnt.read().bits();
+            if a == b {
+                return a as u16;
+            }
+        }
+    }
+
+    /// Outputs a PWM signal of `freq` on `pin`, with a duty cycle of 0
+    ///
+    /// Returns [`Error::WrongAutoReload`] if `freq` is 0 or out of range, and
+    /// [`Error::Disabled`] if the kernel clock is not running.
+    pub fn pwm<PIN, const A: u8>(self, mut pin: PIN, freq: Hertz) -> Result<LpPwm<PIN>, Error>
+    where
+        PIN: PinA<Out, LPTIM1, A = Const<A>> + SetAlternate<A, PushPull>,
+    {
+        let ticks = self
+            .clk
+            .raw()
+            .checked_div(freq.raw())
+            .ok_or(Error::WrongAutoReload)?;
+        let (presc, arr) = compute_arr_presc(ticks).ok_or(Error::WrongAutoReload)?;
+
+        pin.set_alt_mode();
+        // WAVPOL: the output is high from the start of the period until the compare match
+        self.configure(presc, WAVPOL);
+        self.set_arr(arr)?;
+        self.set_cmp(0)?;
+        self.lptim
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() | CNTSTRT) });
+
+        Ok(LpPwm { timer: self, pin })
+    }
+
+    /// Disables the LPTIM and releases it
+    pub fn release(self) -> LPTIM1 {
+        self.lptim.cr.write(|w| unsafe { w.bits(0) });
+        self.lptim
+    }
+
+    /// Writes CFGR, which requires the LPTIM to be disabled, then enables it
+    fn configure(&self, presc: u8, cfgr: u32) {
+        self.lptim.cr.write(|w| unsafe { w.bits(0) });
+        self.lptim.cfgr.modify(|r, w| unsafe {
+            w.bits((r.bits() & !(PRESC_MASK | WAVPOL)) | (u32::from(presc) << PRESC_OFFSET) | cfgr)
+        });
+        // ARR and CMP can only be written while the LPTIM is enabled
+        self.lptim.cr.write(|w| unsafe { w.bits(ENABLE) });
+    }
+
+    fn modify_disabled(&mut self, f: impl FnOnce(&LPTIM1)) {
+        let cr = self.lptim.cr.read().bits();
+        self.lptim.cr.write(|w| unsafe { w.bits(0) });
+        f(&self.lptim);
+        if cr & ENABLE != 0 {
+            // Restarting needs ARR written again, reloading the same value
+            let arr = self.lptim.arr.read().bits() as u16;
+            let cmp = self.lptim.cmp.read().bits() as u16;
+            self.lptim.cr.write(|w| unsafe { w.bits(ENABLE) });
+            // The kernel clock was running when the LPTIM was started
+            let _ = self.set_arr(arr);
+            let _ = self.set_cmp(cmp);
+            self.lptim.cr.write(|w| unsafe { w.bits(cr) });
+        }
+    }
+
+    /// Writes ARR and waits until it was transferred to the LPTIM clock domain, a new
+    /// write before that is lost
+    fn set_arr(&self, arr: u16) -> Result<(), Error> {
+        self.lptim.arr.write(|w| unsafe { w.bits(arr.into()) });
+        self.wait_sync(ARROK)
+    }
+
+    /// Writes CMP and waits until it was transferred to the LPTIM clock domain
+    fn set_cmp(&self, cmp: u16) -> Result<(), Error> {
+        self.lptim.cmp.write(|w| unsafe { w.bits(cmp.into()) });
+        self.wait_sync(CMPOK)
+    }
+
+    /// Waits for the `ARROK` or `CMPOK` `flag`, which is never set without a kernel clock
+    fn wait_sync(&self, flag: u32) -> Result<(), Error> {
+        for _ in 0..self.sync_polls {
+            if self.lptim.isr.read().bits() & flag != 0 {
+                self.clear_flags(flag);
+                return Ok(());
+            }
+        }
+        Err(Error::Disabled)
+    }
+
+    fn clear_flags(&self, flags: u32) {
+        self.lptim.icr.write(|w| unsafe { w.bits(flags) });
+    }
+}
+
+/// PWM output of the low-power timer
+pub struct LpPwm<PIN> {
+    timer: LpTimer,
+    pin: PIN,
+}
+
+impl<PIN> LpPwm<PIN> {
+    /// Returns the duty cycle of 100%
+    pub fn get_max_duty(&self) -> u16 {
+        self.timer.lptim.arr.read().bits() as u16
+    }
+
+    /// Returns the current duty cycle
+    pub fn get_duty(&self) -> u16 {
+        self.timer.lptim.cmp.read().bits() as u16
+    }
+
+    /// Sets the duty cycle, `duty` is clamped to [`get_max_duty`](Self::get_max_duty)
+    ///
+    /// Blocks until the value was taken over by the LPTIM clock domain, up to a few LPTIM
+    /// clock cycles, or returns [`Error::Disabled`] if the kernel clock stopped.
+    pub fn set_duty(&mut self, duty: u16) -> Result<(), Error> {
+        self.timer.set_cmp(duty.min(self.get_max_duty()))
+    }
+
+    /// Stops the output and releases the timer and the pin, which stays in alternate mode
+    pub fn release(self) -> (LpTimer, PIN) {
+        self.timer.lptim.cr.write(|w| unsafe { w.bits(0) });
+        (self.timer, self.pin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arr_presc_without_prescaler() {
+        assert_eq!(compute_arr_presc(2), Some((0, 1)));
+        assert_eq!(compute_arr_presc(1000), Some((0, 999)));
+        assert_eq!(compute_arr_presc(1 << 16), Some((0, 0xffff)));
+    }
+
+    #[test]
+    fn arr_presc_with_prescaler() {
+        assert_eq!(compute_arr_presc((1 << 16) + 1), Some((1, 0x7fff)));
+        assert_eq!(compute_arr_presc(1 << 17), Some((1, 0xffff)));
+        assert_eq!(compute_arr_presc(1 << 23), Some((7, 0xffff)));
+        // 1 s at 32.768 kHz
+        assert_eq!(compute_arr_presc(32_768), Some((0, 32_767)));
+        // 10 s at 32.768 kHz
+        assert_eq!(compute_arr_presc(327_680), Some((3, 40_959)));
+    }
+
+    #[test]
+    fn arr_presc_out_of_range() {
+        assert_eq!(compute_arr_presc(0), None);
+        assert_eq!(compute_arr_presc(1), None);
+        assert_eq!(compute_arr_presc((1 << 23) + (1 << 7)), None);
+        assert_eq!(compute_arr_presc(u32::MAX), None);
+    }
+
+    #[test]
+    fn sync_polls_cover_the_kernel_clock() {
+        assert_eq!(sync_polls(100.MHz(), 32_768.Hz()), 3051 * SYNC_CYCLES);
+        assert_eq!(sync_polls(16.MHz(), 16.MHz()), SYNC_CYCLES);
+        // APB1 below the core clock still gets one poll per cycle
+        assert_eq!(sync_polls(8.MHz(), 16.MHz()), SYNC_CYCLES);
+    }
+}
@@ -0,0 +1,33 @@
+use embedded_hal::timer::{Cancel, CountDown, Periodic};
+use fugit::MicrosDurationU32;
+use void::Void;
+
+use super::{Error, LpTimer};
+
+impl Periodic for LpTimer {}
+
+impl CountDown for LpTimer {
+    type Time = MicrosDurationU32;
+
+    fn start<T>(&mut self, timeout: T)
+    where
+        T: Into<Self::Time>,
+    {
+        self.start(timeout.into()).unwrap()
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        match self.wait() {
+            Err(nb::Error::WouldBlock) => Err(nb::Error::WouldBlock),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Cancel for LpTimer {
+    type Error = Error;
+
+    fn cancel(&mut self) -> Result<(), Self::Error> {
+        self.cancel()
+    }
+}
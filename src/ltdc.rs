@@ -0,0 +1,668 @@
+//! LCD-TFT display controller (LTDC)
+//!
+//! The LTDC continuously reads up to two layers from framebuffers in memory, blends them over
+//! a background colour and drives a parallel RGB panel with the timing given in
+//! [`DisplayConfig`].
+//!
+//! # Pixel clock
+//!
+//! The pixel clock is the R output of PLLSAI divided by `PLLSAIDIVR`. If PLLSAI was already
+//! started by [`rcc`](crate::rcc), e.g. with [`CFGR::pllsai`](crate::rcc::CFGR::pllsai),
+//! [`Ltdc::new`] uses it as configured and [`Ltdc::pixel_clock`] tells the frequency obtained.
+//! Otherwise PLLSAI is programmed for the requested frequency from the PLL input clock.
+//!
+//! # Pins
+//!
+//! Signals are not checked by the type system: put the `R[7:0]`, `G[7:0]`, `B[7:0]`, `HSYNC`,
+//! `VSYNC`, `DE` and `CLK` pins used by the board in alternate function 14 (or 9 for the few
+//! pins listed so in the datasheet) with a high output speed.
+//!
+//! # Shadow registers
+//!
+//! Layer settings are written to shadow registers. They are applied by [`Ltdc::reload`] at
+//! the next vertical blanking period, which avoids tearing, or by
+//! [`Ltdc::reload_immediate`].
+//!
+//! # Example
+//!
+//! The 240×320 panel of the 32F429IDISCOVERY (its ILI9341 is set to RGB interface mode over
+//! SPI beforehand), with an RGB565 framebuffer in SDRAM:
+//!
+//! ```ignore
+//! let config = DisplayConfig {
+//!     active_width: 240,
+//!     active_height: 320,
+//!     h_sync: 10,
+//!     h_back_porch: 20,
+//!     h_front_porch: 10,
+//!     v_sync: 2,
+//!     v_back_porch: 2,
+//!     v_front_porch: 4,
+//!     h_sync_polarity: Polarity::ActiveLow,
+//!     v_sync_polarity: Polarity::ActiveLow,
+//!     data_enable_polarity: Polarity::ActiveLow,
+//!     pixel_clock_polarity: Polarity::ActiveLow,
+//!     pixel_clock: 6.MHz(),
+//! };
+//! let mut ltdc = Ltdc::new(dp.LTDC, config, &clocks).unwrap();
+//!
+//! let mut layer = Layer::new(&mut framebuffer[..240 * 320], 240, 320);
+//! ltdc.configure_layer(LayerId::L1, &layer.config(), layer.address()).unwrap();
+//! ltdc.reload();
+//!
+//! Rectangle::new(Point::new(20, 20), Size::new(100, 50))
+//!     .into_styled(PrimitiveStyle::with_fill(Rgb565::RED))
+//!     .draw(&mut layer)
+//!     .unwrap();
+//! ```
+
+use crate::pac::{LTDC, RCC};
+use crate::rcc::{Clocks, Enable, Reset, HSI};
+use fugit::HertzU32 as Hertz;
+
+/// Offset of the layer 1 registers, layer 2 follows after `LAYER_STRIDE` bytes
+const LAYER_OFFSET: usize = 0x84;
+const LAYER_STRIDE: usize = 0x80;
+
+const LXCR: usize = 0x00;
+const LXWHPCR: usize = 0x04;
+const LXWVPCR: usize = 0x08;
+const LXCKCR: usize = 0x0c;
+const LXPFCR: usize = 0x10;
+const LXCACR: usize = 0x14;
+const LXDCCR: usize = 0x18;
+const LXBFCR: usize = 0x1c;
+const LXCFBAR: usize = 0x28;
+const LXCFBLR: usize = 0x2c;
+const LXCFBLNR: usize = 0x30;
+const LXCLUTWR: usize = 0x40;
+
+const LXCR_LEN: u32 = 1 << 0;
+const LXCR_COLKEN: u32 = 1 << 1;
+const LXCR_CLUTEN: u32 = 1 << 4;
+
+const GCR_LTDCEN: u32 = 1 << 0;
+const SRCR_IMR: u32 = 1 << 0;
+const SRCR_VBR: u32 = 1 << 1;
+
+/// LTDC errors
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// No PLLSAI configuration gives the pixel clock, or the PLL input clock can't be found
+    PixelClock,
+    /// A timing or layer field is out of its allowed range
+    InvalidConfig,
+}
+
+/// Polarity of a synchronisation signal
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Polarity {
+    /// Active low, or the pixel clock as is
+    ActiveLow,
+    /// Active high, or the inverted pixel clock
+    ActiveHigh,
+}
+
+/// Panel timing, in pixel clock periods horizontally and in lines vertically
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct DisplayConfig {
+    /// Number of visible pixels per line
+    pub active_width: u16,
+    /// Number of visible lines
+    pub active_height: u16,
+    /// Horizontal synchronisation pulse width
+    pub h_sync: u16,
+    /// Horizontal back porch
+    pub h_back_porch: u16,
+    /// Horizontal front porch
+    pub h_front_porch: u16,
+    /// Vertical synchronisation pulse width
+    pub v_sync: u16,
+    /// Vertical back porch
+    pub v_back_porch: u16,
+    /// Vertical front porch
+    pub v_front_porch: u16,
+    /// Polarity of `HSYNC`
+    pub h_sync_polarity: Polarity,
+    /// Polarity of `VSYNC`
+    pub v_sync_polarity: Polarity,
+    /// Polarity of `DE`
+    pub data_enable_polarity: Polarity,
+    /// Polarity of `CLK`
+    pub pixel_clock_polarity: Polarity,
+    /// Pixel clock frequency
+    pub pixel_clock: Hertz,
+}
+
+/// Layer selection
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LayerId {
+    /// Layer 1, the bottom layer
+    L1,
+    /// Layer 2, blended over layer 1
+    L2,
+}
+
+/// Framebuffer pixel format
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum PixelFormat {
+    /// 32 bits per pixel with alpha
+    Argb8888 = 0,
+    /// 24 bits per pixel
+    Rgb888 = 1,
+    /// 16 bits per pixel
+    Rgb565 = 2,
+    /// 16 bits per pixel with 1-bit alpha
+    Argb1555 = 3,
+    /// 16 bits per pixel with 4-bit alpha
+    Argb4444 = 4,
+    /// 8-bit index into the colour look-up table
+    L8 = 5,
+    /// 4-bit alpha, 4-bit index into the colour look-up table
+    Al44 = 6,
+    /// 8-bit alpha, 8-bit index into the colour look-up table
+    Al88 = 7,
+}
+
+impl PixelFormat {
+    /// Size of a pixel in memory
+    pub const fn bytes_per_pixel(self) -> u16 {
+        match self {
+            Self::Argb8888 => 4,
+            Self::Rgb888 => 3,
+            Self::Rgb565 | Self::Argb1555 | Self::Argb4444 | Self::Al88 => 2,
+            Self::L8 | Self::Al44 => 1,
+        }
+    }
+}
+
+/// Layer window and blending configuration
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct LayerConfig {
+    /// Pixel format of the framebuffer
+    pub pixel_format: PixelFormat,
+    /// Horizontal position of the window in the active area
+    pub x: u16,
+    /// Vertical position of the window in the active area
+    pub y: u16,
+    /// Window width, equal to the framebuffer width
+    pub width: u16,
+    /// Window height, equal to the framebuffer height
+    pub height: u16,
+    /// Constant alpha, multiplied with the pixel alpha for blending
+    pub constant_alpha: u8,
+    /// Pixels of this RGB888 colour are made transparent
+    pub color_key: Option<u32>,
+    /// ARGB8888 colour used outside of the window
+    pub default_color: u32,
+}
+
+impl LayerConfig {
+    /// Opaque full-window configuration at the top-left corner of the active area
+    pub const fn new(pixel_format: PixelFormat, width: u16, height: u16) -> Self {
+        Self {
+            pixel_format,
+            x: 0,
+            y: 0,
+            width,
+            height,
+            constant_alpha: 0xff,
+            color_key: None,
+            default_color: 0,
+        }
+    }
+
+    /// Sets the window position in the active area
+    pub const fn position(mut self, x: u16, y: u16) -> Self {
+        self.x = x;
+        self.y = y;
+        self
+    }
+
+    /// Sets the constant alpha
+    pub const fn constant_alpha(mut self, alpha: u8) -> Self {
+        self.constant_alpha = alpha;
+        self
+    }
+
+    /// Makes pixels of the RGB888 colour `key` transparent
+    pub const fn color_key(mut self, key: u32) -> Self {
+        self.color_key = Some(key);
+        self
+    }
+
+    /// Sets the ARGB8888 colour used outside of the window
+    pub const fn default_color(mut self, color: u32) -> Self {
+        self.default_color = color;
+        self
+    }
+}
+
+/// LCD-TFT display controller
+pub struct Ltdc {
+    ltdc: LTDC,
+    config: DisplayConfig,
+    pixel_clock: Hertz,
+}
+
+impl Ltdc {
+    /// Enables the LTDC, sets up the pixel clock and the panel timing
+    ///
+    /// Both layers are disabled and the background is black.
+    pub fn new(ltdc: LTDC, config: DisplayConfig, clocks: &Clocks) -> Result<Self, Error> {
+        let c = &config;
+        if c.h_sync == 0 || c.v_sync == 0 {
+            return Err(Error::InvalidConfig);
+        }
+        let ahbp = u32::from(c.h_sync) + u32::from(c.h_back_porch) - 1;
+        let aavbp = u32::from(c.v_sync) + u32::from(c.v_back_porch) - 1;
+        let aaw = ahbp + u32::from(c.active_width);
+        let aah = aavbp + u32::from(c.active_height);
+        let totalw = aaw + u32::from(c.h_front_porch);
+        let totalh = aah + u32::from(c.v_front_porch);
+        if totalw > 0xfff || totalh > 0x7ff {
+            return Err(Error::InvalidConfig);
+        }
+
+        let pixel_clock = setup_pixel_clock(c.pixel_clock.raw(), clocks)?;
+
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            LTDC::enable(rcc);
+            LTDC::reset(rcc);
+        }
+
+        let polarity = |p: Polarity, bit: u32| match p {
+            Polarity::ActiveLow => 0,
+            Polarity::ActiveHigh => 1 << bit,
+        };
+        unsafe {
+            ltdc.sscr
+                .write(|w| w.bits((u32::from(c.h_sync) - 1) << 16 | (u32::from(c.v_sync) - 1)));
+            ltdc.bpcr.write(|w| w.bits(ahbp << 16 | aavbp));
+            ltdc.awcr.write(|w| w.bits(aaw << 16 | aah));
+            ltdc.twcr.write(|w| w.bits(totalw << 16 | totalh));
+            ltdc.bccr.write(|w| w.bits(0));
+            ltdc.gcr.write(|w| {
+                w.bits(
+                    polarity(c.h_sync_polarity, 31)
+                        | polarity(c.v_sync_polarity, 30)
+                        | polarity(c.data_enable_polarity, 29)
+                        | polarity(c.pixel_clock_polarity, 28)
+                        | GCR_LTDCEN,
+                )
+            });
+        }
+
+        Ok(Self {
+            ltdc,
+            config,
+            pixel_clock,
+        })
+    }
+
+    /// Pixel clock frequency actually configured
+    pub fn pixel_clock(&self) -> Hertz {
+        self.pixel_clock
+    }
+
+    /// Panel timing given to [`Ltdc::new`]
+    pub fn display_config(&self) -> &DisplayConfig {
+        &self.config
+    }
+
+    /// Sets the RGB888 colour shown where no layer is enabled
+    ///
+    /// The background colour isn't shadowed and changes immediately.
+    pub fn set_background_color(&mut self, rgb: u32) {
+        unsafe { self.ltdc.bccr.write(|w| w.bits(rgb & 0xff_ffff)) }
+    }
+
+    /// Configures and enables a layer reading from the framebuffer at `address`
+    ///
+    /// The framebuffer must hold `width * height` pixels of the configured format and stay
+    /// valid as long as the layer is enabled. Takes effect on the next reload.
+    pub fn configure_layer(
+        &mut self,
+        layer: LayerId,
+        config: &LayerConfig,
+        address: u32,
+    ) -> Result<(), Error> {
+        let x = u32::from(config.x);
+        let y = u32::from(config.y);
+        let width = u32::from(config.width);
+        let height = u32::from(config.height);
+        if width == 0
+            || height == 0
+            || x + width > u32::from(self.config.active_width)
+            || y + height > u32::from(self.config.active_height)
+        {
+            return Err(Error::InvalidConfig);
+        }
+
+        let bpcr = self.ltdc.bpcr.read().bits();
+        let ahbp = (bpcr >> 16) & 0xfff;
+        let avbp = bpcr & 0x7ff;
+        let pitch = width * u32::from(config.pixel_format.bytes_per_pixel());
+
+        let mut cr = self.layer_read(layer, LXCR) & !LXCR_COLKEN;
+        cr |= LXCR_LEN;
+        if let Some(key) = config.color_key {
+            self.layer_write(layer, LXCKCR, key & 0xff_ffff);
+            cr |= LXCR_COLKEN;
+        }
+        self.layer_write(layer, LXWHPCR, (ahbp + x + width) << 16 | (ahbp + x + 1));
+        self.layer_write(layer, LXWVPCR, (avbp + y + height) << 16 | (avbp + y + 1));
+        self.layer_write(layer, LXPFCR, config.pixel_format as u32);
+        self.layer_write(layer, LXCACR, u32::from(config.constant_alpha));
+        self.layer_write(layer, LXDCCR, config.default_color);
+        // Pixel alpha × constant alpha over 1 - (pixel alpha × constant alpha)
+        self.layer_write(layer, LXBFCR, 0b110 << 8 | 0b111);
+        self.layer_write(layer, LXCFBAR, address);
+        // The line length includes 3 extra bytes
+        self.layer_write(layer, LXCFBLR, pitch << 16 | (pitch + 3));
+        self.layer_write(layer, LXCFBLNR, height);
+        self.layer_write(layer, LXCR, cr);
+        Ok(())
+    }
+
+    /// Points an already configured layer to another framebuffer, e.g. for double buffering
+    ///
+    /// Takes effect on the next reload.
+    pub fn set_framebuffer(&mut self, layer: LayerId, address: u32) {
+        self.layer_write(layer, LXCFBAR, address);
+    }
+
+    /// Changes the constant alpha of a layer, takes effect on the next reload
+    pub fn set_constant_alpha(&mut self, layer: LayerId, alpha: u8) {
+        self.layer_write(layer, LXCACR, u32::from(alpha));
+    }
+
+    /// Enables colour keying with the RGB888 colour `key`, or disables it with `None`
+    ///
+    /// Takes effect on the next reload.
+    pub fn set_color_key(&mut self, layer: LayerId, key: Option<u32>) {
+        let cr = self.layer_read(layer, LXCR);
+        match key {
+            Some(key) => {
+                self.layer_write(layer, LXCKCR, key & 0xff_ffff);
+                self.layer_write(layer, LXCR, cr | LXCR_COLKEN);
+            }
+            None => self.layer_write(layer, LXCR, cr & !LXCR_COLKEN),
+        }
+    }
+
+    /// Loads RGB888 `colors` in the look-up table used by the L8, AL44 and AL88 formats and
+    /// enables it
+    ///
+    /// Only up to 256 entries are written. The table must be written while the layer is
+    /// disabled or during vertical blanking.
+    pub fn set_clut(&mut self, layer: LayerId, colors: &[u32]) {
+        for (i, &color) in colors.iter().take(256).enumerate() {
+            self.layer_write(layer, LXCLUTWR, (i as u32) << 24 | (color & 0xff_ffff));
+        }
+        let cr = self.layer_read(layer, LXCR);
+        self.layer_write(layer, LXCR, cr | LXCR_CLUTEN);
+    }
+
+    /// Enables a layer, takes effect on the next reload
+    pub fn enable_layer(&mut self, layer: LayerId) {
+        let cr = self.layer_read(layer, LXCR);
+        self.layer_write(layer, LXCR, cr | LXCR_LEN);
+    }
+
+    /// Disables a layer, takes effect on the next reload
+    pub fn disable_layer(&mut self, layer: LayerId) {
+        let cr = self.layer_read(layer, LXCR);
+        self.layer_write(layer, LXCR, cr & !LXCR_LEN);
+    }
+
+    /// Applies the layer settings at the beginning of the next vertical blanking period
+    ///
+    /// Use [`Ltdc::is_reload_pending`] to know when the new settings are in use, e.g. before
+    /// drawing to the previous framebuffer.
+    pub fn reload(&mut self) {
+        unsafe { self.ltdc.srcr.write(|w| w.bits(SRCR_VBR)) }
+    }
+
+    /// Applies the layer settings immediately, may cause tearing
+    pub fn reload_immediate(&mut self) {
+        unsafe { self.ltdc.srcr.write(|w| w.bits(SRCR_IMR)) }
+    }
+
+    /// Returns `true` while a reload requested by [`Ltdc::reload`] hasn't happened yet
+    pub fn is_reload_pending(&self) -> bool {
+        self.ltdc.srcr.read().bits() & (SRCR_VBR | SRCR_IMR) != 0
+    }
+
+    /// Stops the LTDC and disables its clock
+    pub fn release(self) -> LTDC {
+        unsafe {
+            self.ltdc.gcr.write(|w| w.bits(0));
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            LTDC::disable(&(*RCC::ptr()));
+        }
+        self.ltdc
+    }
+
+    fn layer_register(layer: LayerId, offset: usize) -> *mut u32 {
+        let index = match layer {
+            LayerId::L1 => 0,
+            LayerId::L2 => 1,
+        };
+        (LTDC::ptr() as usize + LAYER_OFFSET + index * LAYER_STRIDE + offset) as *mut u32
+    }
+
+    fn layer_read(&self, layer: LayerId, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile(Self::layer_register(layer, offset)) }
+    }
+
+    fn layer_write(&mut self, layer: LayerId, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile(Self::layer_register(layer, offset), value) }
+    }
+}
+
+/// Finds the PLLSAI VCO input frequency from the main PLL settings
+fn pll_input_clock(clocks: &Clocks) -> Option<u32> {
+    let rcc = unsafe { &*RCC::ptr() };
+    let pllcfgr = rcc.pllcfgr.read();
+    let m = u32::from(pllcfgr.pllm().bits());
+    let sysclk = clocks.sysclk().raw();
+    match rcc.cfgr.read().sws().bits() {
+        // The PLL drives SYSCLK, work back from its output
+        0b10 => {
+            let n = u32::from(pllcfgr.plln().bits());
+            let p = (u32::from(pllcfgr.pllp().bits()) + 1) * 2;
+            Some(sysclk * p / n)
+        }
+        _ if pllcfgr.pllsrc().bit_is_clear() => Some(HSI / m),
+        // HSE drives SYSCLK and the PLL
+        0b01 => Some(sysclk / m),
+        _ => None,
+    }
+}
+
+/// Pixel clock from the PLLSAI VCO input, `PLLSAIN`, `PLLSAIR` and `PLLSAIDIVR` bits
+fn pixel_clock(vco_in: u32, n: u16, r: u8, divr: u8) -> u32 {
+    vco_in * u32::from(n) / u32::from(r) / (2 << divr)
+}
+
+/// PLLSAI settings `(n, r, divr, clock)` closest to the `target` pixel clock
+fn best_pixel_clock(vco_in: u32, target: u32) -> Option<(u16, u8, u8, u32)> {
+    (50..=432u16)
+        .filter(|&n| (100_000_000..=432_000_000).contains(&(u64::from(vco_in) * u64::from(n))))
+        .flat_map(|n| (2..=7u8).map(move |r| (n, r)))
+        .flat_map(|(n, r)| (0..=3u8).map(move |divr| (n, r, divr)))
+        .map(|(n, r, divr)| (n, r, divr, pixel_clock(vco_in, n, r, divr)))
+        .min_by_key(|&(_, _, _, clock)| clock.abs_diff(target))
+}
+
+/// Programs the PLLSAI R output and `PLLSAIDIVR` unless PLLSAI is running already, returns
+/// the pixel clock obtained
+fn setup_pixel_clock(target: u32, clocks: &Clocks) -> Result<Hertz, Error> {
+    let rcc = unsafe { &*RCC::ptr() };
+    let vco_in = pll_input_clock(clocks).ok_or(Error::PixelClock)?;
+    if rcc.cr.read().pllsairdy().bit_is_set() {
+        // Configured by `rcc`, possibly for the SAI too
+        let pllsaicfgr = rcc.pllsaicfgr.read();
+        let divr = rcc.dckcfgr.read().pllsaidivr().bits();
+        let clock = pixel_clock(
+            vco_in,
+            pllsaicfgr.pllsain().bits(),
+            pllsaicfgr.pllsair().bits(),
+            divr,
+        );
+        return Ok(Hertz::from_raw(clock));
+    }
+
+    let (n, r, divr, clock) = best_pixel_clock(vco_in, target).ok_or(Error::PixelClock)?;
+
+    rcc.pllsaicfgr
+        .modify(|_, w| unsafe { w.pllsain().bits(n).pllsair().bits(r) });
+    cortex_m::interrupt::free(|_| rcc.dckcfgr.modify(|_, w| w.pllsaidivr().bits(divr)));
+    cortex_m::interrupt::free(|_| rcc.cr.modify(|_, w| w.pllsaion().set_bit()));
+    while rcc.cr.read().pllsairdy().bit_is_clear() {}
+
+    Ok(Hertz::from_raw(clock))
+}
+
+/// RGB565 framebuffer for a layer
+///
+/// Implements `DrawTarget` from `embedded-graphics` when the `embedded-graphics-core`
+/// feature is enabled.
+pub struct Layer<'a> {
+    buffer: &'a mut [u16],
+    width: u16,
+    height: u16,
+}
+
+impl<'a> Layer<'a> {
+    /// Wraps a framebuffer of `width` × `height` pixels
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is too small.
+    pub fn new(buffer: &'a mut [u16], width: u16, height: u16) -> Self {
+        assert!(buffer.len() >= usize::from(width) * usize::from(height));
+        Self {
+            buffer,
+            width,
+            height,
+        }
+    }
+
+    /// Address to give to [`Ltdc::configure_layer`]
+    pub fn address(&self) -> u32 {
+        self.buffer.as_ptr() as u32
+    }
+
+    /// Full-window RGB565 layer configuration matching this framebuffer
+    pub fn config(&self) -> LayerConfig {
+        LayerConfig::new(PixelFormat::Rgb565, self.width, self.height)
+    }
+
+    /// Framebuffer width in pixels
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Framebuffer height in pixels
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Raw RGB565 pixels, line by line
+    pub fn buffer_mut(&mut self) -> &mut [u16] {
+        self.buffer
+    }
+
+    /// Returns the framebuffer
+    pub fn release(self) -> &'a mut [u16] {
+        self.buffer
+    }
+}
+
+#[cfg(feature = "embedded-graphics-core")]
+mod graphics {
+    use super::Layer;
+    use core::convert::Infallible;
+    use embedded_graphics_core::{
+        draw_target::DrawTarget,
+        geometry::{OriginDimensions, Size},
+        pixelcolor::{
+            raw::{RawData, RawU16},
+            Rgb565,
+        },
+        Pixel,
+    };
+
+    impl OriginDimensions for Layer<'_> {
+        fn size(&self) -> Size {
+            Size::new(self.width.into(), self.height.into())
+        }
+    }
+
+    impl DrawTarget for Layer<'_> {
+        type Color = Rgb565;
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            let width = i32::from(self.width);
+            let height = i32::from(self.height);
+            for Pixel(point, color) in pixels {
+                if (0..width).contains(&point.x) && (0..height).contains(&point.y) {
+                    let index = (point.y * width + point.x) as usize;
+                    self.buffer[index] = RawU16::from(color).into_inner();
+                }
+            }
+            Ok(())
+        }
+
+        fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+            let raw = RawU16::from(color).into_inner();
+            let len = usize::from(self.width) * usize::from(self.height);
+            self.buffer[..len].fill(raw);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_clock_from_pllsai() {
+        // 1 MHz VCO input, N = 192, R = 4, DIVR = /8: 6 MHz
+        assert_eq!(pixel_clock(1_000_000, 192, 4, 0b10), 6_000_000);
+        assert_eq!(pixel_clock(2_000_000, 100, 2, 0b00), 50_000_000);
+    }
+
+    #[test]
+    fn best_pixel_clock_is_exact_when_possible() {
+        let (n, r, divr, clock) = best_pixel_clock(1_000_000, 6_000_000).unwrap();
+        assert_eq!(clock, 6_000_000);
+        assert_eq!(pixel_clock(1_000_000, n, r, divr), clock);
+        assert!((100_000_000..=432_000_000).contains(&(1_000_000 * u32::from(n))));
+    }
+
+    #[test]
+    fn best_pixel_clock_keeps_vco_in_range() {
+        // Too fast: the VCO stops at 432 MHz, divided by at least 2 × 2
+        let (n, r, divr, clock) = best_pixel_clock(2_000_000, 200_000_000).unwrap();
+        assert_eq!((n, r, divr), (216, 2, 0));
+        assert_eq!(clock, 108_000_000);
+        // N from 50 puts a 10 MHz input above 432 MHz
+        assert_eq!(best_pixel_clock(10_000_000, 6_000_000), None);
+    }
+}
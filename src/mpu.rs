@@ -0,0 +1,292 @@
+//! Memory Protection Unit regions
+//!
+//! Regions are built with [`Region`], whose size and alignment checks run at compile time when
+//! the region is built in a `const` item:
+//!
+//! ```ignore
+//! use stm32f4xx_hal::mpu::{MemoryType, MpuExt, Region};
+//!
+//! const FRAMEBUFFER: Region = Region::new(0xC000_0000, 0x0008_0000)
+//!     .memory(MemoryType::NonCacheable)
+//!     .shareable(true)
+//!     .executable(false);
+//!
+//! let mut mpu = cp.MPU.constrain();
+//! mpu.set_region(0, FRAMEBUFFER);
+//! mpu.set_region(1, stack_guard!(256));
+//! mpu.enable(true);
+//! cp.SCB.enable(SystemHandler::MemoryManagement);
+//! ```
+//!
+//! The MPU only checks accesses from the core: it cannot keep DMA out of a region. DMA has
+//! no access to the CCM RAM at all, buffers placed there are silently left untouched.
+
+use cortex_m::asm;
+use cortex_m::peripheral::MPU;
+
+/// Access permissions of a region, the `AP` field
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Access {
+    /// Any access faults
+    NoAccess = 0b000,
+    /// Read/write in privileged mode only
+    PrivilegedReadWrite = 0b001,
+    /// Read/write in privileged mode, read-only in unprivileged mode
+    UnprivilegedReadOnly = 0b010,
+    /// Read/write
+    ReadWrite = 0b011,
+    /// Read-only in privileged mode only
+    PrivilegedReadOnly = 0b101,
+    /// Read-only
+    ReadOnly = 0b110,
+}
+
+/// Memory type of a region, the `TEX`, `C` and `B` fields
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MemoryType {
+    /// Every access is done in order and waits for the previous one
+    StronglyOrdered,
+    /// Peripheral registers, buffered writes
+    Device,
+    /// Normal memory, write-back cacheable
+    Normal,
+    /// Normal memory, not cacheable
+    ///
+    /// Buffers shared with DMA or DMA2D/LTDC, such as framebuffers.
+    NonCacheable,
+}
+
+impl MemoryType {
+    /// `TEX`, `C` and `B` fields of `MPU_RASR`
+    const fn bits(self) -> u32 {
+        let (tex, c, b) = match self {
+            Self::StronglyOrdered => (0b000, 0, 0),
+            Self::Device => (0b000, 0, 1),
+            Self::Normal => (0b000, 1, 1),
+            Self::NonCacheable => (0b001, 0, 0),
+        };
+        (tex << 19) | (c << 17) | (b << 16)
+    }
+}
+
+/// A memory region, with attributes set by the builder methods
+///
+/// Defaults to read/write, executable, non-shareable [`MemoryType::Normal`] memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Region {
+    base: u32,
+    size: u32,
+    access: Access,
+    memory: MemoryType,
+    shareable: bool,
+    executable: bool,
+}
+
+impl Region {
+    /// Region of `size` bytes starting at `base`
+    ///
+    /// # Panics
+    ///
+    /// When `size` is not a power of two of at least 32 bytes, or `base` is not aligned to
+    /// `size`. Building the region in a `const` item turns the panic into a build error.
+    pub const fn new(base: u32, size: u32) -> Self {
+        assert!(size >= 32 && size.is_power_of_two());
+        assert!(base & (size - 1) == 0);
+        Self {
+            base,
+            size,
+            access: Access::ReadWrite,
+            memory: MemoryType::Normal,
+            shareable: false,
+            executable: true,
+        }
+    }
+
+    /// No-access region of `size` bytes at the lowest address the stack may grow to
+    ///
+    /// The region starts at the first `size` aligned address from `stack_limit`, up to
+    /// `size - 1` bytes of stack are lost to the alignment. See [`stack_guard!`].
+    ///
+    /// # Panics
+    ///
+    /// Same as [`new`](Self::new).
+    pub const fn stack_guard(stack_limit: u32, size: u32) -> Self {
+        assert!(size >= 32 && size.is_power_of_two());
+        let base = (stack_limit + size - 1) & !(size - 1);
+        Self::new(base, size)
+            .access(Access::NoAccess)
+            .executable(false)
+    }
+
+    /// The 64 KiB of CCM RAM, which can hold data but no code
+    #[cfg(any(
+        feature = "stm32f405",
+        feature = "stm32f407",
+        feature = "stm32f415",
+        feature = "stm32f417",
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+        feature = "stm32f469",
+        feature = "stm32f479"
+    ))]
+    pub const fn ccm_ram() -> Self {
+        Self::new(0x1000_0000, 0x1_0000).executable(false)
+    }
+
+    /// Sets the access permissions
+    pub const fn access(mut self, access: Access) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Sets the memory type
+    pub const fn memory(mut self, memory: MemoryType) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    /// Marks the memory as shared with other bus masters, such as DMA
+    pub const fn shareable(mut self, shareable: bool) -> Self {
+        self.shareable = shareable;
+        self
+    }
+
+    /// Allows instruction fetches from the region
+    pub const fn executable(mut self, executable: bool) -> Self {
+        self.executable = executable;
+        self
+    }
+
+    /// Start address
+    pub const fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// Size in bytes
+    pub const fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// `MPU_RASR` value, with the region enabled
+    const fn rasr(&self) -> u32 {
+        ((!self.executable as u32) << 28)
+            | ((self.access as u32) << 24)
+            | self.memory.bits()
+            | ((self.shareable as u32) << 18)
+            | ((self.size.trailing_zeros() - 1) << 1)
+            | 1
+    }
+}
+
+/// Guard [`Region`] of `size` bytes at the bottom of the stack
+///
+/// The `cortex-m-rt` stack grows down towards the end of the static data, a stack overflow
+/// then faults instead of overwriting `.bss` and `.data`. A heap starting at the same
+/// address overlaps with the guard.
+///
+/// `size` must be a constant, it is checked at compile time.
+#[macro_export]
+macro_rules! stack_guard {
+    ($size:expr) => {{
+        const SIZE: u32 = $size;
+        const _: () = assert!(SIZE >= 32 && SIZE.is_power_of_two());
+        extern "C" {
+            static mut __sheap: u32;
+        }
+        // NOTE(unsafe) only the address of the symbol is taken
+        let stack_limit = unsafe { core::ptr::addr_of!(__sheap) } as u32;
+        $crate::mpu::Region::stack_guard(stack_limit, SIZE)
+    }};
+}
+pub use crate::stack_guard;
+
+pub trait MpuExt {
+    fn constrain(self) -> Mpu;
+}
+
+impl MpuExt for MPU {
+    /// Disables the MPU and clears all regions
+    fn constrain(self) -> Mpu {
+        let mut mpu = Mpu { mpu: self };
+        mpu.disable();
+        for number in 0..mpu.regions() {
+            mpu.clear_region(number);
+        }
+        mpu
+    }
+}
+
+/// Memory Protection Unit
+pub struct Mpu {
+    mpu: MPU,
+}
+
+impl Mpu {
+    /// Number of regions, 8 on all STM32F4
+    pub fn regions(&self) -> u8 {
+        (self.mpu._type.read() >> 8) as u8
+    }
+
+    /// Configures region `number`, which takes precedence over lower-numbered regions
+    ///
+    /// # Panics
+    ///
+    /// When `number` is out of range.
+    pub fn set_region(&mut self, number: u8, region: Region) {
+        assert!(number < self.regions());
+        // NOTE(unsafe) the region attributes were checked by `Region::new`
+        unsafe {
+            self.mpu.rnr.write(number.into());
+            self.mpu.rbar.write(region.base);
+            self.mpu.rasr.write(region.rasr());
+        }
+        asm::dsb();
+        asm::isb();
+    }
+
+    /// Disables region `number`
+    ///
+    /// # Panics
+    ///
+    /// When `number` is out of range.
+    pub fn clear_region(&mut self, number: u8) {
+        assert!(number < self.regions());
+        unsafe {
+            self.mpu.rnr.write(number.into());
+            self.mpu.rasr.write(0);
+        }
+        asm::dsb();
+        asm::isb();
+    }
+
+    /// Enables the MPU
+    ///
+    /// With `default_map`, privileged code can access memory outside of the regions with
+    /// the default attributes, otherwise any such access faults.
+    ///
+    /// Faults escalate to HardFault unless the MemManage exception is enabled in `SCB`.
+    pub fn enable(&mut self, default_map: bool) {
+        // NOTE(unsafe) the regions were checked by `Region::new`
+        unsafe { self.mpu.ctrl.write(((default_map as u32) << 2) | 1) };
+        asm::dsb();
+        asm::isb();
+    }
+
+    /// Disables the MPU, all accesses get the default attributes
+    pub fn disable(&mut self) {
+        asm::dmb();
+        unsafe { self.mpu.ctrl.write(0) };
+        asm::dsb();
+        asm::isb();
+    }
+
+    /// Releases the MPU, leaving the regions as they are
+    pub fn release(self) -> MPU {
+        self.mpu
+    }
+}
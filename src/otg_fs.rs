@@ -2,16 +2,52 @@
 //!
 //! Requires the `usb_fs` feature.
 //! Only one of the `usb_fs`/`usb_hs` features can be selected at the same time.
+//!
+//! # VBUS sensing
+//!
+//! The USB specification requires self-powered devices to detect when the host
+//! removes VBUS. `synopsys-usb-otg` disables VBUS sensing when the bus is enabled,
+//! so [`VbusSense`] must be created after the `UsbDevice` has been built. The core
+//! then disconnects from the bus on session end, which `usb-device` sees as a
+//! suspend followed by a reset when the cable is plugged back in.
+//!
+//! # Re-enumeration and bus state
+//!
+//! A device reset by a debugger stays attached from the host point of view, which then
+//! fails to enumerate it again. [`USB::force_reenumeration`] simulates an unplug before
+//! the bus is built. [`USB::control`] returns a [`UsbControl`] handle, usable once `USB`
+//! has been moved into the `UsbBus`, for soft disconnects, the frame number, suspend state
+//! and remote wakeup.
+//!
+//! # Host mode
+//!
+//! See the [`host`] module for a minimal polling USB host driver.
 
 use crate::pac;
 
-use crate::gpio::{Alternate, PushPull, PA11, PA12};
+use crate::gpio::{Alternate, Input, PushPull, PA11, PA12, PA9};
+#[cfg(any(
+    feature = "stm32f401",
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f411",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+))]
+use crate::gpio::PA8;
 use crate::rcc::{Enable, Reset};
+use embedded_hal::blocking::delay::DelayMs;
 use fugit::HertzU32 as Hertz;
 
 pub use synopsys_usb_otg::UsbBus;
 use synopsys_usb_otg::UsbPeripheral;
 
+pub mod host;
+
 pub struct USB {
     pub usb_global: pac::OTG_FS_GLOBAL,
     pub usb_device: pac::OTG_FS_DEVICE,
@@ -67,4 +103,237 @@ unsafe impl UsbPeripheral for USB {
     }
 }
 
+impl USB {
+    /// Makes the host enumerate the device again, to be called before `UsbBus::new`
+    ///
+    /// `DP` is driven low for 10 ms, which the host sees as a disconnect, then given back to
+    /// the peripheral.
+    pub fn force_reenumeration(&mut self, delay: &mut impl DelayMs<u32>) {
+        self.pin_dp.with_push_pull_output(|dp| {
+            dp.set_low();
+            delay.delay_ms(10);
+        });
+    }
+
+    /// Returns a handle to control the device once `USB` is owned by the `UsbBus`
+    pub fn control(&self) -> UsbControl {
+        UsbControl { _private: () }
+    }
+}
+
 pub type UsbBusType = UsbBus<USB>;
+
+// OTG_FS_GOTGCTL
+const GOTGCTL_BSVLD: u32 = 1 << 19;
+// OTG_FS_GOTGINT
+const GOTGINT_SEDET: u32 = 1 << 2;
+
+/// Enables or disables VBUS sensing on cores with `VBUSBSEN`/`NOVBUSSENS` bits
+#[cfg(any(
+    feature = "stm32f401",
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f411",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+))]
+fn set_vbus_sensing(global: &pac::otg_fs_global::RegisterBlock, enable: bool) {
+    const GCCFG_VBUSBSEN: u32 = 1 << 19;
+    const GCCFG_NOVBUSSENS: u32 = 1 << 21;
+
+    cortex_m::interrupt::free(|_| {
+        global.gccfg.modify(|r, w| unsafe {
+            if enable {
+                w.bits((r.bits() & !GCCFG_NOVBUSSENS) | GCCFG_VBUSBSEN)
+            } else {
+                w.bits((r.bits() & !GCCFG_VBUSBSEN) | GCCFG_NOVBUSSENS)
+            }
+        })
+    });
+}
+
+/// Enables or disables VBUS sensing on cores with the `VBDEN` bit
+#[cfg(any(
+    feature = "stm32f412",
+    feature = "stm32f413",
+    feature = "stm32f423",
+    feature = "stm32f446",
+    feature = "stm32f469",
+    feature = "stm32f479",
+))]
+fn set_vbus_sensing(global: &pac::otg_fs_global::RegisterBlock, enable: bool) {
+    const GOTGCTL_BVALOEN: u32 = 1 << 6;
+    const GOTGCTL_BVALOVAL: u32 = 1 << 7;
+    const GCCFG_VBDEN: u32 = 1 << 21;
+
+    cortex_m::interrupt::free(|_| unsafe {
+        if enable {
+            // Drop the forced B-session valid override set by the bus driver
+            global
+                .gotgctl
+                .modify(|r, w| w.bits(r.bits() & !(GOTGCTL_BVALOEN | GOTGCTL_BVALOVAL)));
+            global.gccfg.modify(|r, w| w.bits(r.bits() | GCCFG_VBDEN));
+        } else {
+            global.gccfg.modify(|r, w| w.bits(r.bits() & !GCCFG_VBDEN));
+            global
+                .gotgctl
+                .modify(|r, w| w.bits(r.bits() | GOTGCTL_BVALOEN | GOTGCTL_BVALOVAL));
+        }
+    });
+}
+
+/// Hardware VBUS sensing on `PA9`
+///
+/// Required for self-powered devices, which have to detect host disconnect.
+pub struct VbusSense {
+    pin: PA9<Input>,
+}
+
+impl VbusSense {
+    /// Enables VBUS sensing on `PA9`.
+    ///
+    /// Must be called after the `UsbDevice` has been built, as enabling the bus
+    /// turns VBUS sensing off.
+    pub fn new(pin: PA9<Input>) -> Self {
+        set_vbus_sensing(unsafe { &*pac::OTG_FS_GLOBAL::ptr() }, true);
+        Self { pin }
+    }
+
+    /// Returns `true` while VBUS is above the B-session valid threshold
+    pub fn is_session_valid(&self) -> bool {
+        let global = unsafe { &*pac::OTG_FS_GLOBAL::ptr() };
+        global.gotgctl.read().bits() & GOTGCTL_BSVLD != 0
+    }
+
+    /// Returns `true` if a session end (host disconnect) was detected since the
+    /// last call, and clears the flag
+    pub fn take_session_end(&mut self) -> bool {
+        let global = unsafe { &*pac::OTG_FS_GLOBAL::ptr() };
+        let detected = global.gotgint.read().bits() & GOTGINT_SEDET != 0;
+        if detected {
+            global.gotgint.write(|w| unsafe { w.bits(GOTGINT_SEDET) });
+        }
+        detected
+    }
+
+    /// Disables VBUS sensing and releases the pin
+    pub fn release(self) -> PA9<Input> {
+        set_vbus_sensing(unsafe { &*pac::OTG_FS_GLOBAL::ptr() }, false);
+        self.pin
+    }
+}
+
+/// Outputs the start-of-frame pulse on `PA8`, e.g. for audio clock recovery.
+///
+/// Like [`VbusSense`], must be called after the `UsbDevice` has been built.
+/// Only available on cores with the `SOFOUTEN` bit.
+#[cfg(any(
+    feature = "stm32f401",
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f411",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+))]
+pub fn enable_sof_output(_pin: PA8<Alternate<10, PushPull>>) {
+    const GCCFG_SOFOUTEN: u32 = 1 << 20;
+
+    let global = unsafe { &*pac::OTG_FS_GLOBAL::ptr() };
+    cortex_m::interrupt::free(|_| {
+        global
+            .gccfg
+            .modify(|r, w| unsafe { w.bits(r.bits() | GCCFG_SOFOUTEN) })
+    });
+}
+
+// Offsets from the OTG_FS_DEVICE and OTG_FS_PWRCLK blocks, whose register names change
+// between SVDs
+const DCTL: usize = 0x04;
+const DSTS: usize = 0x08;
+const DCTL_RWUSIG: u32 = 1 << 0;
+const DCTL_SDIS: u32 = 1 << 1;
+const DSTS_SUSPSTS: u32 = 1 << 0;
+const DSTS_FNSOF_SHIFT: u32 = 8;
+const DSTS_FNSOF_MASK: u32 = 0x3fff;
+const PCGCCTL_STPPCLK: u32 = 1 << 0;
+const PCGCCTL_GATEHCLK: u32 = 1 << 1;
+
+/// Device control and status, alongside the `UsbBus` driver
+///
+/// Writes are read-modify-writes of registers the driver also writes to, they are done in
+/// critical sections.
+pub struct UsbControl {
+    _private: (),
+}
+
+impl UsbControl {
+    fn device(offset: usize) -> *mut u32 {
+        (pac::OTG_FS_DEVICE::ptr() as *mut u8).wrapping_add(offset) as *mut u32
+    }
+
+    fn modify_dctl(&mut self, f: impl FnOnce(u32) -> u32) {
+        cortex_m::interrupt::free(|_| unsafe {
+            let dctl = core::ptr::read_volatile(Self::device(DCTL));
+            core::ptr::write_volatile(Self::device(DCTL), f(dctl));
+        });
+    }
+
+    fn dsts(&self) -> u32 {
+        unsafe { core::ptr::read_volatile(Self::device(DSTS)) }
+    }
+
+    /// Disconnects from the bus, `DP` pull-up is removed
+    pub fn soft_disconnect(&mut self) {
+        self.modify_dctl(|dctl| dctl | DCTL_SDIS);
+    }
+
+    /// Connects to the bus again after [`soft_disconnect`](Self::soft_disconnect)
+    pub fn soft_connect(&mut self) {
+        self.modify_dctl(|dctl| dctl & !DCTL_SDIS);
+    }
+
+    /// Disconnects for 10 ms, making the host reset and enumerate the device again
+    pub fn force_reenumeration(&mut self, delay: &mut impl DelayMs<u32>) {
+        self.soft_disconnect();
+        delay.delay_ms(10);
+        self.soft_connect();
+    }
+
+    /// Number of the last received start of frame, 11 bits at full speed
+    pub fn frame_number(&self) -> u16 {
+        ((self.dsts() >> DSTS_FNSOF_SHIFT) & DSTS_FNSOF_MASK) as u16
+    }
+
+    /// Returns `true` while the bus is suspended
+    pub fn is_suspended(&self) -> bool {
+        self.dsts() & DSTS_SUSPSTS != 0
+    }
+
+    /// Signals remote wakeup to the host, driving resume for 10 ms (1 to 15 ms allowed)
+    ///
+    /// The host must have enabled the feature, see `UsbDevice::remote_wakeup_enabled`.
+    /// Returns `false`, without signalling, when the bus isn't suspended.
+    pub fn remote_wakeup(&mut self, delay: &mut impl DelayMs<u32>) -> bool {
+        if !self.is_suspended() {
+            return false;
+        }
+        // Restart the PHY clock in case the driver stopped it for the suspend
+        let pwrclk = pac::OTG_FS_PWRCLK::ptr() as *mut u32;
+        cortex_m::interrupt::free(|_| unsafe {
+            let pcgcctl = core::ptr::read_volatile(pwrclk);
+            core::ptr::write_volatile(pwrclk, pcgcctl & !(PCGCCTL_STPPCLK | PCGCCTL_GATEHCLK));
+        });
+        self.modify_dctl(|dctl| dctl | DCTL_RWUSIG);
+        delay.delay_ms(10);
+        self.modify_dctl(|dctl| dctl & !DCTL_RWUSIG);
+        true
+    }
+}
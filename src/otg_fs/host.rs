@@ -0,0 +1,838 @@
+//! USB host mode on the OTG FS core
+//!
+//! This is a minimal polling driver: it controls the root port (power, reset,
+//! speed detection, connect/disconnect), allocates host channels and performs
+//! blocking control, bulk and interrupt transfers with NAK retries.
+//! That's enough to enumerate a device and to run Mass Storage Bulk-Only
+//! Transport or poll a HID interrupt endpoint on top of it.
+//!
+//! The core is driven in slave (non-DMA) mode with the default FIFO layout:
+//! 128 words of RX FIFO, 96 words of non-periodic and 96 words of periodic TX FIFO.
+//!
+//! ```ignore
+//! let mut host = UsbHost::new(
+//!     (dp.OTG_FS_GLOBAL, dp.OTG_FS_HOST, dp.OTG_FS_PWRCLK),
+//!     (gpioa.pa11.into_alternate(), gpioa.pa12.into_alternate()),
+//!     vbus_switch,
+//!     &clocks,
+//! )?;
+//! host.power_on();
+//! while !host.is_connected() {}
+//! let speed = host.reset_port(&mut delay)?;
+//! let mut ep0 = host.alloc_channel(Endpoint::control(0, 8, speed))?;
+//! let mut desc = [0; 18];
+//! host.control_in(&mut ep0, [0x80, 6, 0, 1, 0, 0, 18, 0], &mut desc)?;
+//! ```
+
+use core::ptr;
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::gpio::{Alternate, PushPull, PA11, PA12};
+use crate::pac;
+use crate::rcc::{Clocks, Enable, Reset};
+
+#[cfg(any(
+    feature = "stm32f401",
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f411",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+))]
+/// Number of host channels
+pub const CHANNEL_COUNT: u8 = 8;
+#[cfg(any(
+    feature = "stm32f412",
+    feature = "stm32f413",
+    feature = "stm32f423",
+    feature = "stm32f446",
+    feature = "stm32f469",
+    feature = "stm32f479",
+))]
+/// Number of host channels
+pub const CHANNEL_COUNT: u8 = 12;
+
+/// Number of NAKs after which a transfer gives up with [`Error::Nak`]
+pub const DEFAULT_NAK_LIMIT: u32 = 10_000;
+
+// Global registers
+const GAHBCFG: usize = 0x008;
+const GUSBCFG: usize = 0x00C;
+const GRSTCTL: usize = 0x010;
+const GINTSTS: usize = 0x014;
+const GRXSTSP: usize = 0x020;
+const GRXFSIZ: usize = 0x024;
+const HNPTXFSIZ: usize = 0x028;
+const HNPTXSTS: usize = 0x02C;
+const GCCFG: usize = 0x038;
+const HPTXFSIZ: usize = 0x100;
+// Host registers
+const HCFG: usize = 0x400;
+const HFIR: usize = 0x404;
+const HFNUM: usize = 0x408;
+const HPTXSTS: usize = 0x410;
+const HPRT: usize = 0x440;
+const HCCHAR: usize = 0x500;
+const HCINT: usize = 0x508;
+const HCINTMSK: usize = 0x50C;
+const HCTSIZ: usize = 0x510;
+const PCGCCTL: usize = 0xE00;
+const FIFO: usize = 0x1000;
+
+const GUSBCFG_FHMOD: u32 = 1 << 29;
+const GUSBCFG_FDMOD: u32 = 1 << 30;
+const GRSTCTL_CSRST: u32 = 1 << 0;
+const GRSTCTL_RXFFLSH: u32 = 1 << 4;
+const GRSTCTL_TXFFLSH: u32 = 1 << 5;
+const GRSTCTL_TXFNUM_ALL: u32 = 0x10 << 6;
+const GRSTCTL_AHBIDL: u32 = 1 << 31;
+const GINTSTS_CMOD: u32 = 1 << 0;
+const GINTSTS_RXFLVL: u32 = 1 << 4;
+const GINTSTS_DISCINT: u32 = 1 << 29;
+const GCCFG_PWRDWN: u32 = 1 << 16;
+
+const HPRT_PCSTS: u32 = 1 << 0;
+const HPRT_PCDET: u32 = 1 << 1;
+const HPRT_PENA: u32 = 1 << 2;
+const HPRT_PENCHNG: u32 = 1 << 3;
+const HPRT_POCA: u32 = 1 << 4;
+const HPRT_POCCHNG: u32 = 1 << 5;
+const HPRT_PRST: u32 = 1 << 8;
+const HPRT_PPWR: u32 = 1 << 12;
+/// Bits cleared (or port disabled) by writing 1
+const HPRT_W1C: u32 = HPRT_PCDET | HPRT_PENA | HPRT_PENCHNG | HPRT_POCCHNG;
+
+const HCCHAR_EPDIR_IN: u32 = 1 << 15;
+const HCCHAR_LSDEV: u32 = 1 << 17;
+const HCCHAR_ODDFRM: u32 = 1 << 29;
+const HCCHAR_CHDIS: u32 = 1 << 30;
+const HCCHAR_CHENA: u32 = 1 << 31;
+
+const HCINT_XFRC: u32 = 1 << 0;
+const HCINT_CHH: u32 = 1 << 1;
+const HCINT_STALL: u32 = 1 << 3;
+const HCINT_NAK: u32 = 1 << 4;
+const HCINT_TXERR: u32 = 1 << 7;
+const HCINT_BBERR: u32 = 1 << 8;
+const HCINT_FRMOR: u32 = 1 << 9;
+const HCINT_DTERR: u32 = 1 << 10;
+
+const PKTSTS_IN_DATA: u32 = 2;
+
+/// Host error
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// No device connected or the port is not enabled
+    NotConnected,
+    /// All host channels are in use
+    NoChannel,
+    /// The device kept answering NAK
+    Nak,
+    /// The endpoint is halted
+    Stall,
+    /// CRC, timeout, bit stuff or false EOP error
+    Transaction,
+    /// The device sent more data than requested
+    Babble,
+    /// Data toggle mismatch
+    DataToggle,
+    /// Periodic transfer missed its frame
+    FrameOverrun,
+    /// Data doesn't fit the endpoint or the buffer
+    BufferOverflow,
+    /// The 48 MHz clock is not valid
+    InvalidClock,
+}
+
+/// Root port speed
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Speed {
+    Full,
+    Low,
+}
+
+/// Root port event, see [`UsbHost::poll_port`]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PortEvent {
+    /// A device was attached
+    Connected,
+    /// The device was removed
+    Disconnected,
+    /// Port was enabled after a reset
+    Enabled(Speed),
+    /// Port was disabled, e.g. due to a babble error
+    Disabled,
+    /// Overcurrent detected by the core
+    Overcurrent,
+}
+
+/// Endpoint transfer type
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum TransferType {
+    Control = 0,
+    Isochronous = 1,
+    Bulk = 2,
+    Interrupt = 3,
+}
+
+/// Transfer direction, from the host point of view
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    Out,
+    In,
+}
+
+/// Data PID
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Pid {
+    Data0 = 0,
+    Data1 = 2,
+    Setup = 3,
+}
+
+/// Remote endpoint description
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Endpoint {
+    /// Device address, `0` before `SET_ADDRESS`
+    pub address: u8,
+    /// Endpoint number, without the direction bit
+    pub number: u8,
+    pub direction: Direction,
+    pub transfer_type: TransferType,
+    pub max_packet_size: u16,
+    pub speed: Speed,
+}
+
+impl Endpoint {
+    /// Control endpoint `0` of device `address`
+    pub fn control(address: u8, max_packet_size: u16, speed: Speed) -> Self {
+        Self {
+            address,
+            number: 0,
+            direction: Direction::Out,
+            transfer_type: TransferType::Control,
+            max_packet_size,
+            speed,
+        }
+    }
+}
+
+/// Allocated host channel
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Channel {
+    index: u8,
+    endpoint: Endpoint,
+    toggle: Pid,
+}
+
+impl Channel {
+    /// Channel number
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Endpoint the channel talks to
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    /// Updates the device address, e.g. after `SET_ADDRESS`
+    pub fn set_address(&mut self, address: u8) {
+        self.endpoint.address = address;
+    }
+
+    /// Updates the maximum packet size, e.g. after reading the device descriptor
+    pub fn set_max_packet_size(&mut self, max_packet_size: u16) {
+        self.endpoint.max_packet_size = max_packet_size;
+    }
+
+    /// Resets the data toggle to DATA0, e.g. after `CLEAR_FEATURE(ENDPOINT_HALT)`
+    pub fn reset_toggle(&mut self) {
+        self.toggle = Pid::Data0;
+    }
+}
+
+/// OTG FS core in host mode
+pub struct UsbHost<PWR> {
+    usb_global: pac::OTG_FS_GLOBAL,
+    usb_host: pac::OTG_FS_HOST,
+    usb_pwrclk: pac::OTG_FS_PWRCLK,
+    pins: (PA11<Alternate<10, PushPull>>, PA12<Alternate<10, PushPull>>),
+    power: PWR,
+    channels: u16,
+    nak_limit: u32,
+}
+
+#[inline(always)]
+fn read_reg(offset: usize) -> u32 {
+    // NOTE(unsafe) the offset is within the OTG FS register block
+    unsafe { ptr::read_volatile((pac::OTG_FS_GLOBAL::ptr() as usize + offset) as *const u32) }
+}
+
+#[inline(always)]
+fn write_reg(offset: usize, value: u32) {
+    // NOTE(unsafe) the offset is within the OTG FS register block
+    unsafe { ptr::write_volatile((pac::OTG_FS_GLOBAL::ptr() as usize + offset) as *mut u32, value) }
+}
+
+#[inline(always)]
+fn modify_reg(offset: usize, f: impl FnOnce(u32) -> u32) {
+    write_reg(offset, f(read_reg(offset)))
+}
+
+#[inline(always)]
+/// Packets needed for a transfer of `len` bytes, a zero-length transfer is one packet
+fn packet_count(len: usize, max_packet_size: u16) -> u32 {
+    let mps = usize::from(max_packet_size.max(1));
+    ((len + mps - 1) / mps).max(1) as u32
+}
+
+/// Bytes of a transfer of `len` bytes acknowledged by the device when `left` packets remain
+fn acked_len(len: usize, max_packet_size: u16, left: u32) -> usize {
+    let acked = packet_count(len, max_packet_size).saturating_sub(left) as usize;
+    (acked * usize::from(max_packet_size.max(1))).min(len)
+}
+
+const fn ch_reg(reg: usize, ch: u8) -> usize {
+    reg + 0x20 * ch as usize
+}
+
+impl<PWR: OutputPin> UsbHost<PWR> {
+    /// Configures the OTG FS core as a host.
+    ///
+    /// `power` drives the external VBUS switch, it's kept low until [`UsbHost::power_on`].
+    /// Fails with [`Error::InvalidClock`] if the 48 MHz clock is not valid, see
+    /// `require_pll48clk`.
+    pub fn new(
+        periphs: (
+            pac::OTG_FS_GLOBAL,
+            pac::OTG_FS_HOST,
+            pac::OTG_FS_PWRCLK,
+        ),
+        pins: (PA11<Alternate<10, PushPull>>, PA12<Alternate<10, PushPull>>),
+        mut power: PWR,
+        clocks: &Clocks,
+    ) -> Result<Self, Error> {
+        if !clocks.is_pll48clk_valid() {
+            return Err(Error::InvalidClock);
+        }
+
+        power.set_low().ok();
+
+        let rcc = unsafe { &*pac::RCC::ptr() };
+        cortex_m::interrupt::free(|_| {
+            pac::OTG_FS_GLOBAL::enable(rcc);
+            pac::OTG_FS_GLOBAL::reset(rcc);
+        });
+
+        // Interrupts are not used, transfers are polled
+        write_reg(GAHBCFG, 0);
+
+        // Core soft reset
+        while read_reg(GRSTCTL) & GRSTCTL_AHBIDL == 0 {}
+        write_reg(GRSTCTL, GRSTCTL_CSRST);
+        while read_reg(GRSTCTL) & GRSTCTL_CSRST != 0 {}
+
+        // Force host mode and wait for the core to switch
+        modify_reg(GUSBCFG, |r| (r & !GUSBCFG_FDMOD) | GUSBCFG_FHMOD);
+        while read_reg(GINTSTS) & GINTSTS_CMOD == 0 {}
+
+        // Power up the transceiver, VBUS is provided by the external switch
+        write_reg(GCCFG, GCCFG_PWRDWN);
+        // Restart the PHY clock
+        write_reg(PCGCCTL, 0);
+
+        // 48 MHz PHY clock, full speed frame interval
+        write_reg(HCFG, 1);
+        write_reg(HFIR, 48_000);
+
+        // FIFO layout in words
+        write_reg(GRXFSIZ, 128);
+        write_reg(HNPTXFSIZ, (96 << 16) | 128);
+        write_reg(HPTXFSIZ, (96 << 16) | 224);
+
+        Self::flush_fifos();
+
+        // Clear pending interrupts
+        write_reg(GINTSTS, 0xFFFF_FFFF);
+
+        Ok(Self {
+            usb_global: periphs.0,
+            usb_host: periphs.1,
+            usb_pwrclk: periphs.2,
+            pins,
+            power,
+            channels: 0,
+            nak_limit: DEFAULT_NAK_LIMIT,
+        })
+    }
+
+    /// Turns the port power on, driving the external VBUS switch high
+    pub fn power_on(&mut self) {
+        self.power.set_high().ok();
+        modify_reg(HPRT, |r| (r & !HPRT_W1C) | HPRT_PPWR);
+    }
+
+    /// Turns the port power off
+    pub fn power_off(&mut self) {
+        modify_reg(HPRT, |r| r & !(HPRT_W1C | HPRT_PPWR));
+        self.power.set_low().ok();
+    }
+
+    /// Disables the core and releases the peripherals, pins and power switch
+    #[allow(clippy::type_complexity)]
+    pub fn release(
+        mut self,
+    ) -> (
+        (
+            pac::OTG_FS_GLOBAL,
+            pac::OTG_FS_HOST,
+            pac::OTG_FS_PWRCLK,
+        ),
+        (PA11<Alternate<10, PushPull>>, PA12<Alternate<10, PushPull>>),
+        PWR,
+    ) {
+        self.power_off();
+        (
+            (self.usb_global, self.usb_host, self.usb_pwrclk),
+            self.pins,
+            self.power,
+        )
+    }
+
+    /// Number of NAKs after which a transfer gives up
+    pub fn set_nak_limit(&mut self, limit: u32) {
+        self.nak_limit = limit;
+    }
+}
+
+impl<PWR> UsbHost<PWR> {
+    fn flush_fifos() {
+        write_reg(GRSTCTL, GRSTCTL_TXFFLSH | GRSTCTL_TXFNUM_ALL);
+        while read_reg(GRSTCTL) & GRSTCTL_TXFFLSH != 0 {}
+        write_reg(GRSTCTL, GRSTCTL_RXFFLSH);
+        while read_reg(GRSTCTL) & GRSTCTL_RXFFLSH != 0 {}
+    }
+
+    /// Returns `true` if a device is attached to the port
+    pub fn is_connected(&self) -> bool {
+        read_reg(HPRT) & HPRT_PCSTS != 0
+    }
+
+    /// Returns `true` if the port is enabled, i.e. a device is attached and was reset
+    pub fn is_enabled(&self) -> bool {
+        read_reg(HPRT) & HPRT_PENA != 0
+    }
+
+    /// Speed of the attached device, once the port is enabled
+    pub fn speed(&self) -> Option<Speed> {
+        let hprt = read_reg(HPRT);
+        if hprt & HPRT_PENA == 0 {
+            return None;
+        }
+        match (hprt >> 17) & 0b11 {
+            2 => Some(Speed::Low),
+            _ => Some(Speed::Full),
+        }
+    }
+
+    /// Current (micro)frame number
+    pub fn frame_number(&self) -> u16 {
+        read_reg(HFNUM) as u16
+    }
+
+    /// Checks for and acknowledges the next root port event
+    pub fn poll_port(&mut self) -> Option<PortEvent> {
+        if read_reg(GINTSTS) & GINTSTS_DISCINT != 0 {
+            write_reg(GINTSTS, GINTSTS_DISCINT);
+            return Some(PortEvent::Disconnected);
+        }
+
+        let hprt = read_reg(HPRT);
+        let keep = hprt & !HPRT_W1C;
+        if hprt & HPRT_PCDET != 0 {
+            write_reg(HPRT, keep | HPRT_PCDET);
+            return Some(PortEvent::Connected);
+        }
+        if hprt & HPRT_PENCHNG != 0 {
+            write_reg(HPRT, keep | HPRT_PENCHNG);
+            return Some(match self.speed() {
+                Some(speed) => {
+                    self.configure_speed(speed);
+                    PortEvent::Enabled(speed)
+                }
+                None => PortEvent::Disabled,
+            });
+        }
+        if hprt & HPRT_POCCHNG != 0 {
+            write_reg(HPRT, keep | HPRT_POCCHNG);
+            if hprt & HPRT_POCA != 0 {
+                return Some(PortEvent::Overcurrent);
+            }
+        }
+        None
+    }
+
+    /// Resets the attached device and returns its speed.
+    ///
+    /// Waits 100 ms for the connection to settle, then drives reset for 15 ms.
+    pub fn reset_port(&mut self, delay: &mut impl DelayMs<u32>) -> Result<Speed, Error> {
+        if !self.is_connected() {
+            return Err(Error::NotConnected);
+        }
+
+        delay.delay_ms(100);
+        modify_reg(HPRT, |r| (r & !HPRT_W1C) | HPRT_PRST);
+        delay.delay_ms(15);
+        modify_reg(HPRT, |r| r & !(HPRT_W1C | HPRT_PRST));
+        // Reset recovery
+        delay.delay_ms(20);
+
+        let speed = self.speed().ok_or(Error::NotConnected)?;
+        self.configure_speed(speed);
+        Ok(speed)
+    }
+
+    fn configure_speed(&mut self, speed: Speed) {
+        let (fslspcs, hfir) = match speed {
+            Speed::Full => (1, 48_000),
+            Speed::Low => (2, 6_000),
+        };
+        modify_reg(HCFG, |r| (r & !0b11) | fslspcs);
+        write_reg(HFIR, hfir);
+    }
+
+    /// Allocates a host channel talking to `endpoint`
+    pub fn alloc_channel(&mut self, endpoint: Endpoint) -> Result<Channel, Error> {
+        let index = (0..CHANNEL_COUNT)
+            .find(|i| self.channels & (1 << i) == 0)
+            .ok_or(Error::NoChannel)?;
+        self.channels |= 1 << index;
+        write_reg(ch_reg(HCINTMSK, index), 0);
+        write_reg(ch_reg(HCINT, index), 0xFFFF_FFFF);
+        Ok(Channel {
+            index,
+            endpoint,
+            toggle: Pid::Data0,
+        })
+    }
+
+    /// Returns a channel to the pool
+    pub fn free_channel(&mut self, channel: Channel) {
+        self.halt(channel.index);
+        self.channels &= !(1 << channel.index);
+    }
+
+    /// Performs a control transfer with an IN data stage.
+    ///
+    /// Returns the number of bytes received.
+    pub fn control_in(
+        &mut self,
+        channel: &mut Channel,
+        setup: [u8; 8],
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        self.setup_stage(channel, setup)?;
+        let mut len = 0;
+        if !buf.is_empty() {
+            channel.toggle = Pid::Data1;
+            len = self.transfer_in(channel, Direction::In, buf)?;
+        }
+        // Status stage
+        channel.toggle = Pid::Data1;
+        self.transfer_out(channel, Direction::Out, &[])?;
+        Ok(len)
+    }
+
+    /// Performs a control transfer with an optional OUT data stage
+    pub fn control_out(
+        &mut self,
+        channel: &mut Channel,
+        setup: [u8; 8],
+        data: &[u8],
+    ) -> Result<(), Error> {
+        self.setup_stage(channel, setup)?;
+        if !data.is_empty() {
+            channel.toggle = Pid::Data1;
+            self.transfer_out(channel, Direction::Out, data)?;
+        }
+        // Status stage
+        channel.toggle = Pid::Data1;
+        self.transfer_in(channel, Direction::In, &mut [])?;
+        Ok(())
+    }
+
+    fn setup_stage(&mut self, channel: &mut Channel, setup: [u8; 8]) -> Result<(), Error> {
+        channel.toggle = Pid::Setup;
+        self.transfer_out(channel, Direction::Out, &setup)
+    }
+
+    /// Reads from a bulk or interrupt IN endpoint, returns the number of bytes received
+    pub fn read(&mut self, channel: &mut Channel, buf: &mut [u8]) -> Result<usize, Error> {
+        self.transfer_in(channel, Direction::In, buf)
+    }
+
+    /// Writes to a bulk or interrupt OUT endpoint
+    pub fn write(&mut self, channel: &mut Channel, data: &[u8]) -> Result<(), Error> {
+        self.transfer_out(channel, Direction::Out, data)
+    }
+
+    fn start(&mut self, channel: &Channel, direction: Direction, len: usize) {
+        let ep = &channel.endpoint;
+        let packets = packet_count(len, ep.max_packet_size);
+        let ch = channel.index;
+
+        write_reg(ch_reg(HCINT, ch), 0xFFFF_FFFF);
+        write_reg(
+            ch_reg(HCTSIZ, ch),
+            (len as u32 & 0x7_FFFF) | (packets << 19) | ((channel.toggle as u32) << 29),
+        );
+
+        let mut hcchar = (ep.max_packet_size as u32 & 0x7FF)
+            | ((ep.number as u32 & 0xF) << 11)
+            | ((ep.transfer_type as u32) << 18)
+            | (1 << 20)
+            | ((ep.address as u32 & 0x7F) << 22)
+            | HCCHAR_CHENA;
+        if direction == Direction::In {
+            hcchar |= HCCHAR_EPDIR_IN;
+        }
+        if ep.speed == Speed::Low {
+            hcchar |= HCCHAR_LSDEV;
+        }
+        if ep.transfer_type == TransferType::Interrupt && self.frame_number() & 1 == 0 {
+            hcchar |= HCCHAR_ODDFRM;
+        }
+        write_reg(ch_reg(HCCHAR, ch), hcchar);
+    }
+
+    fn halt(&mut self, ch: u8) {
+        let hcchar = read_reg(ch_reg(HCCHAR, ch));
+        if hcchar & HCCHAR_CHENA != 0 {
+            write_reg(ch_reg(HCCHAR, ch), hcchar | HCCHAR_CHDIS | HCCHAR_CHENA);
+            while read_reg(ch_reg(HCINT, ch)) & HCINT_CHH == 0 {
+                // Drain the RX FIFO so the halt can complete
+                if read_reg(GINTSTS) & GINTSTS_RXFLVL != 0 {
+                    let sts = read_reg(GRXSTSP);
+                    for _ in 0..(((sts >> 4) & 0x7FF) + 3) / 4 {
+                        read_reg(FIFO);
+                    }
+                }
+            }
+        }
+        write_reg(ch_reg(HCINT, ch), 0xFFFF_FFFF);
+    }
+
+    /// Checks channel interrupts for errors, halting the channel if one occurred
+    fn check_errors(&mut self, ch: u8) -> Result<(), Error> {
+        let hcint = read_reg(ch_reg(HCINT, ch));
+        let err = if hcint & HCINT_STALL != 0 {
+            Error::Stall
+        } else if hcint & HCINT_TXERR != 0 {
+            Error::Transaction
+        } else if hcint & HCINT_BBERR != 0 {
+            Error::Babble
+        } else if hcint & HCINT_DTERR != 0 {
+            Error::DataToggle
+        } else if hcint & HCINT_FRMOR != 0 {
+            Error::FrameOverrun
+        } else if !self.is_connected() {
+            Error::NotConnected
+        } else {
+            return Ok(());
+        };
+        self.halt(ch);
+        Err(err)
+    }
+
+    /// Latches the next data toggle from `HCTSIZ`
+    fn save_toggle(&self, channel: &mut Channel) {
+        channel.toggle = match (read_reg(ch_reg(HCTSIZ, channel.index)) >> 29) & 0b11 {
+            0 => Pid::Data0,
+            _ => Pid::Data1,
+        };
+    }
+
+    fn transfer_out(
+        &mut self,
+        channel: &mut Channel,
+        direction: Direction,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        if !self.is_enabled() {
+            return Err(Error::NotConnected);
+        }
+        let ch = channel.index;
+        let mps = channel.endpoint.max_packet_size.max(1) as usize;
+        let periodic = channel.endpoint.transfer_type == TransferType::Interrupt;
+        let mut naks = 0;
+        // Start of the packets not acknowledged yet
+        let mut offset = 0;
+
+        'retry: loop {
+            let data = &data[offset..];
+            self.start(channel, direction, data.len());
+
+            for packet in data.chunks(mps) {
+                let words = (packet.len() + 3) / 4;
+                // Wait for FIFO space in words
+                loop {
+                    self.check_errors(ch)?;
+                    let space = if periodic {
+                        read_reg(HPTXSTS) & 0xFFFF
+                    } else {
+                        read_reg(HNPTXSTS) & 0xFFFF
+                    };
+                    if space as usize >= words {
+                        break;
+                    }
+                    if read_reg(ch_reg(HCINT, ch)) & HCINT_NAK != 0 {
+                        break;
+                    }
+                }
+                if read_reg(ch_reg(HCINT, ch)) & HCINT_NAK != 0 {
+                    break;
+                }
+                let fifo = FIFO * (ch as usize + 1);
+                for word in packet.chunks(4) {
+                    let mut bytes = [0; 4];
+                    bytes[..word.len()].copy_from_slice(word);
+                    write_reg(fifo, u32::from_le_bytes(bytes));
+                }
+            }
+
+            loop {
+                self.check_errors(ch)?;
+                let hcint = read_reg(ch_reg(HCINT, ch));
+                if hcint & HCINT_XFRC != 0 {
+                    self.save_toggle(channel);
+                    write_reg(ch_reg(HCINT, ch), 0xFFFF_FFFF);
+                    return Ok(());
+                }
+                if hcint & HCINT_NAK != 0 {
+                    // The device is busy, packets it acknowledged so far are not resent and
+                    // the next one goes with the toggle the core has reached
+                    self.halt(ch);
+                    // Packets queued after the NAKed one are written again
+                    Self::flush_fifos();
+                    let left = (read_reg(ch_reg(HCTSIZ, ch)) >> 19) & 0x3FF;
+                    offset += acked_len(data.len(), channel.endpoint.max_packet_size, left);
+                    self.save_toggle(channel);
+                    naks += 1;
+                    if naks >= self.nak_limit {
+                        return Err(Error::Nak);
+                    }
+                    continue 'retry;
+                }
+            }
+        }
+    }
+
+    fn transfer_in(
+        &mut self,
+        channel: &mut Channel,
+        direction: Direction,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        if !self.is_enabled() {
+            return Err(Error::NotConnected);
+        }
+        let ch = channel.index;
+        let mut naks = 0;
+
+        'retry: loop {
+            let mut received = 0;
+            self.start(channel, direction, buf.len());
+
+            loop {
+                self.check_errors(ch)?;
+
+                if read_reg(GINTSTS) & GINTSTS_RXFLVL != 0 {
+                    let sts = read_reg(GRXSTSP);
+                    let count = ((sts >> 4) & 0x7FF) as usize;
+                    if (sts >> 17) & 0xF == PKTSTS_IN_DATA && (sts & 0xF) as u8 == ch {
+                        if received + count > buf.len() {
+                            for _ in 0..(count + 3) / 4 {
+                                read_reg(FIFO);
+                            }
+                            self.halt(ch);
+                            return Err(Error::BufferOverflow);
+                        }
+                        for word in buf[received..received + count].chunks_mut(4) {
+                            let bytes = read_reg(FIFO).to_le_bytes();
+                            word.copy_from_slice(&bytes[..word.len()]);
+                        }
+                        received += count;
+
+                        // Re-enable the channel when more packets are expected
+                        if (read_reg(ch_reg(HCTSIZ, ch)) >> 19) & 0x3FF > 0 {
+                            modify_reg(ch_reg(HCCHAR, ch), |r| (r & !HCCHAR_CHDIS) | HCCHAR_CHENA);
+                        }
+                    }
+                }
+
+                let hcint = read_reg(ch_reg(HCINT, ch));
+                if hcint & HCINT_XFRC != 0 {
+                    self.save_toggle(channel);
+                    self.halt(ch);
+                    return Ok(received);
+                }
+                if hcint & HCINT_NAK != 0 {
+                    self.halt(ch);
+                    naks += 1;
+                    if naks >= self.nak_limit {
+                        return Err(Error::Nak);
+                    }
+                    if received > 0 {
+                        // Partially received data can't be resent by the device
+                        self.save_toggle(channel);
+                        return Ok(received);
+                    }
+                    continue 'retry;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{acked_len, packet_count};
+
+    #[test]
+    fn packets() {
+        assert_eq!(packet_count(0, 64), 1);
+        assert_eq!(packet_count(1, 64), 1);
+        assert_eq!(packet_count(64, 64), 1);
+        assert_eq!(packet_count(65, 64), 2);
+        assert_eq!(packet_count(8, 0), 8);
+    }
+
+    #[test]
+    fn nak_resumes_after_acked_packets() {
+        // 150 bytes in 3 packets of 64
+        assert_eq!(acked_len(150, 64, 3), 0);
+        assert_eq!(acked_len(150, 64, 2), 64);
+        assert_eq!(acked_len(150, 64, 1), 128);
+        // The short last packet is never skipped past the end
+        assert_eq!(acked_len(150, 64, 0), 150);
+        // Zero-length packet not acknowledged
+        assert_eq!(acked_len(0, 64, 1), 0);
+    }
+}
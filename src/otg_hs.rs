@@ -3,17 +3,20 @@
 //! Requires the `usb_hs` feature.
 //! Only one of the `usb_fs`/`usb_hs` features can be selected at the same time.
 //!
-//! Note that only full-speed mode is supported,
-//! external high-speed PHY is not supported.
+//! [`USB`] uses the internal full-speed PHY. High-speed (480 Mbit/s) operation
+//! requires an external ULPI PHY, see [`USB_HS_ULPI`].
 
 use crate::pac;
 
-use crate::gpio::{Alternate, PushPull, PB14, PB15};
-use crate::rcc::{Enable, Reset};
+use crate::gpio::{
+    Alternate, PushPull, PA3, PA5, PB0, PB1, PB10, PB11, PB12, PB13, PB14, PB15, PB5, PC0, PC2,
+    PC3,
+};
+use crate::rcc::{Clocks, Enable, Reset};
 use fugit::HertzU32 as Hertz;
 
 pub use synopsys_usb_otg::UsbBus;
-use synopsys_usb_otg::UsbPeripheral;
+use synopsys_usb_otg::{PhyType, UsbPeripheral};
 
 pub struct USB {
     pub usb_global: pac::OTG_HS_GLOBAL,
@@ -63,3 +66,163 @@ unsafe impl UsbPeripheral for USB {
 }
 
 pub type UsbBusType = UsbBus<USB>;
+
+/// ULPI error
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// HCLK is below the 30 MHz required by the core in high-speed mode
+    HclkTooLow,
+}
+
+/// Minimum HCLK frequency for high-speed operation
+pub const ULPI_MIN_HCLK: Hertz = Hertz::MHz(30);
+
+mod sealed {
+    pub trait Sealed {}
+}
+use sealed::Sealed;
+impl<const P: char, const N: u8, const A: u8> Sealed
+    for crate::gpio::Pin<P, N, Alternate<A, PushPull>>
+{
+}
+
+/// Pins usable as ULPI `DIR`
+pub trait UlpiDir: Sealed {}
+impl UlpiDir for PC2<Alternate<10, PushPull>> {}
+#[cfg(feature = "gpioi")]
+impl UlpiDir for crate::gpio::PI11<Alternate<10, PushPull>> {}
+
+/// Pins usable as ULPI `NXT`
+pub trait UlpiNxt: Sealed {}
+impl UlpiNxt for PC3<Alternate<10, PushPull>> {}
+#[cfg(any(
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479",
+))]
+impl UlpiNxt for crate::gpio::PH4<Alternate<10, PushPull>> {}
+
+/// External ULPI PHY pins, all in alternate function 10
+pub struct UlpiPins<DIR, NXT> {
+    pub ck: PA5<Alternate<10, PushPull>>,
+    pub d0: PA3<Alternate<10, PushPull>>,
+    pub d1: PB0<Alternate<10, PushPull>>,
+    pub d2: PB1<Alternate<10, PushPull>>,
+    pub d3: PB10<Alternate<10, PushPull>>,
+    pub d4: PB11<Alternate<10, PushPull>>,
+    pub d5: PB12<Alternate<10, PushPull>>,
+    pub d6: PB13<Alternate<10, PushPull>>,
+    pub d7: PB5<Alternate<10, PushPull>>,
+    pub stp: PC0<Alternate<10, PushPull>>,
+    pub dir: DIR,
+    pub nxt: NXT,
+}
+
+/// OTG HS peripheral connected to an external ULPI PHY (USB3300 and similar)
+pub struct USB_HS_ULPI<DIR, NXT> {
+    pub usb_global: pac::OTG_HS_GLOBAL,
+    pub usb_device: pac::OTG_HS_DEVICE,
+    pub usb_pwrclk: pac::OTG_HS_PWRCLK,
+    pub pins: UlpiPins<DIR, NXT>,
+    hclk: Hertz,
+}
+
+impl<DIR: UlpiDir, NXT: UlpiNxt> USB_HS_ULPI<DIR, NXT> {
+    /// Creates the ULPI peripheral.
+    ///
+    /// Fails if HCLK is too low for high-speed operation, instead of silently
+    /// enumerating at the wrong speed.
+    pub fn new(
+        periphs: (
+            pac::OTG_HS_GLOBAL,
+            pac::OTG_HS_DEVICE,
+            pac::OTG_HS_PWRCLK,
+        ),
+        pins: UlpiPins<DIR, NXT>,
+        clocks: &Clocks,
+    ) -> Result<Self, Error> {
+        let hclk = clocks.hclk();
+        if hclk < ULPI_MIN_HCLK {
+            return Err(Error::HclkTooLow);
+        }
+
+        Ok(Self {
+            usb_global: periphs.0,
+            usb_device: periphs.1,
+            usb_pwrclk: periphs.2,
+            pins,
+            hclk,
+        })
+    }
+
+    /// Releases the peripherals and pins
+    pub fn release(
+        self,
+    ) -> (
+        (
+            pac::OTG_HS_GLOBAL,
+            pac::OTG_HS_DEVICE,
+            pac::OTG_HS_PWRCLK,
+        ),
+        UlpiPins<DIR, NXT>,
+    ) {
+        (
+            (self.usb_global, self.usb_device, self.usb_pwrclk),
+            self.pins,
+        )
+    }
+}
+
+unsafe impl<DIR, NXT> Sync for USB_HS_ULPI<DIR, NXT> {}
+
+unsafe impl<DIR: UlpiDir + Send, NXT: UlpiNxt + Send> UsbPeripheral for USB_HS_ULPI<DIR, NXT> {
+    const REGISTERS: *const () = pac::OTG_HS_GLOBAL::ptr() as *const ();
+
+    const HIGH_SPEED: bool = true;
+    const FIFO_DEPTH_WORDS: usize = 1024;
+
+    #[cfg(any(
+        feature = "stm32f405",
+        feature = "stm32f407",
+        feature = "stm32f415",
+        feature = "stm32f417",
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+    ))]
+    const ENDPOINT_COUNT: usize = 6;
+    #[cfg(any(feature = "stm32f446", feature = "stm32f469", feature = "stm32f479"))]
+    const ENDPOINT_COUNT: usize = 9;
+
+    fn enable() {
+        let rcc = unsafe { &*pac::RCC::ptr() };
+
+        cortex_m::interrupt::free(|_| {
+            // Enable USB peripheral
+            pac::OTG_HS_GLOBAL::enable(rcc);
+            // Enable the ULPI interface clock
+            rcc.ahb1enr.modify(|_, w| w.otghsulpien().set_bit());
+            // Reset USB peripheral
+            pac::OTG_HS_GLOBAL::reset(rcc);
+        });
+    }
+
+    fn ahb_frequency_hz(&self) -> u32 {
+        self.hclk.raw()
+    }
+
+    fn phy_type(&self) -> PhyType {
+        PhyType::ExternalHighSpeed
+    }
+}
+
+pub type UsbBusUlpiType<DIR, NXT> = UsbBus<USB_HS_ULPI<DIR, NXT>>;
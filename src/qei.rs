@@ -1,5 +1,10 @@
 //! # Quadrature Encoder Interface
-use crate::{pac::RCC, rcc, timer::General};
+use crate::{
+    pac::RCC,
+    rcc,
+    timer::{Event, General},
+};
+use fugit::MicrosDurationU32;
 
 pub trait Pins<TIM> {}
 use crate::timer::CPin;
@@ -49,8 +54,9 @@ where
         Qei { tim, pins }
     }
 
-    /// Releases the TIM peripheral and QEI pins
-    pub fn release(self) -> (TIM, (PC1, PC2)) {
+    /// Stops the counter and releases the TIM peripheral and QEI pins
+    pub fn release(mut self) -> (TIM, (PC1, PC2)) {
+        self.tim.disable_counter();
         (self.tim, (self.pins.0, self.pins.1))
     }
 }
@@ -71,6 +77,112 @@ impl<TIM: Instance, PINS> embedded_hal::Qei for Qei<TIM, PINS> {
     }
 }
 
+/// Position of a [`Qei`] extended to 64 bits by counting the counter wraps
+///
+/// The update interrupt of the timer is enabled, its handler has to call
+/// [`on_interrupt`](Self::on_interrupt).
+pub struct EncoderPosition<TIM, PINS> {
+    qei: Qei<TIM, PINS>,
+    wraps: i64,
+    last_position: i64,
+}
+
+impl<TIM: Instance, PINS> Qei<TIM, PINS> {
+    /// Tracks the counter wraps, starting from the current count
+    pub fn into_position(mut self) -> EncoderPosition<TIM, PINS> {
+        self.tim.clear_interrupt_flag(Event::Update);
+        self.tim.listen_interrupt(Event::Update, true);
+        let mut position = EncoderPosition {
+            qei: self,
+            wraps: 0,
+            last_position: 0,
+        };
+        position.last_position = position.position();
+        position
+    }
+}
+
+impl<TIM: Instance, PINS> EncoderPosition<TIM, PINS> {
+    /// Handler for the timer update interrupt, counts a wrap of the counter
+    pub fn on_interrupt(&mut self) {
+        if self.qei.tim.get_interrupt_flag().contains(Event::Update) {
+            self.qei.tim.clear_interrupt_flag(Event::Update);
+            self.wraps += self.wrap_direction(self.qei.tim.read_count().into());
+        }
+    }
+
+    /// Absolute position, the counter value extended by the wraps counted since
+    /// [`into_position`](Qei::into_position), including a wrap the interrupt did not
+    /// handle yet
+    ///
+    /// The count at [`into_position`](Qei::into_position) is not subtracted, use
+    /// [`velocity`](Self::velocity) or the difference of two positions for relative
+    /// motion.
+    pub fn position(&self) -> i64 {
+        loop {
+            let pending = self.qei.tim.get_interrupt_flag().contains(Event::Update);
+            let count: u32 = self.qei.tim.read_count().into();
+            // The count belongs to the same side of a wrap when the flag did not change
+            if self.qei.tim.get_interrupt_flag().contains(Event::Update) != pending {
+                continue;
+            }
+            let mut wraps = self.wraps;
+            if pending {
+                wraps += self.wrap_direction(count);
+            }
+            return extend_count(wraps, TIM::read_auto_reload(), count);
+        }
+    }
+
+    /// Counts per second since the previous call, `dt` being the time elapsed since then
+    pub fn velocity(&mut self, dt: MicrosDurationU32) -> i64 {
+        let position = self.position();
+        let delta = position - self.last_position;
+        self.last_position = position;
+        match dt.ticks() {
+            0 => 0,
+            us => delta * 1_000_000 / i64::from(us),
+        }
+    }
+
+    /// Direction of the wrap that set the update flag, `count` being read after it
+    fn wrap_direction(&self, count: u32) -> i64 {
+        wrap_direction(
+            count,
+            TIM::read_auto_reload(),
+            self.qei.tim.read_direction(),
+        )
+    }
+
+    /// Disables the update interrupt and releases the encoder interface
+    pub fn release(mut self) -> Qei<TIM, PINS> {
+        self.qei.tim.listen_interrupt(Event::Update, false);
+        self.qei
+    }
+}
+
+/// Direction of the wrap that set the update flag, `count` being read after it and `up`
+/// the counting direction
+///
+/// The direction may have changed right after the wrap, so a count close to 0 or to
+/// the auto-reload value tells the side of the wrap better than the direction bit.
+fn wrap_direction(count: u32, arr: u32, up: bool) -> i64 {
+    if count < arr / 4 {
+        1
+    } else if count > arr - arr / 4 {
+        -1
+    } else if up {
+        1
+    } else {
+        -1
+    }
+}
+
+/// `count` extended to 64 bits by `wraps` wraps of a counter reloaded with `arr`
+fn extend_count(wraps: i64, arr: u32, count: u32) -> i64 {
+    wraps * (i64::from(arr) + 1) + i64::from(count)
+}
+
 pub trait Instance: crate::Sealed + rcc::Enable + rcc::Reset + General {
     fn setup_qei(&mut self);
 
@@ -128,3 +240,44 @@ hal! {
 hal! {
     crate::pac::TIM8,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARR: u32 = u16::MAX as u32;
+
+    #[test]
+    fn wrap_direction_from_count() {
+        // Just wrapped up, even if the direction changed right after
+        assert_eq!(wrap_direction(3, ARR, false), 1);
+        // Just wrapped down
+        assert_eq!(wrap_direction(ARR - 3, ARR, true), -1);
+        // Far from the wrap, the direction bit decides
+        assert_eq!(wrap_direction(ARR / 2, ARR, true), 1);
+        assert_eq!(wrap_direction(ARR / 2, ARR, false), -1);
+    }
+
+    #[test]
+    fn position_across_wraps() {
+        assert_eq!(extend_count(0, ARR, 1234), 1234);
+        // One wrap up, then back down below 0
+        assert_eq!(extend_count(1, ARR, 5), 65_541);
+        assert_eq!(extend_count(-1, ARR, ARR), -1);
+        assert_eq!(extend_count(-2, ARR, ARR - 9), -65_546);
+        // 32-bit timers
+        assert_eq!(extend_count(3, u32::MAX, 7), 3 * (1 << 32) + 7);
+    }
+
+    #[test]
+    fn pending_wrap_is_counted_once() {
+        // Counting up through 0xffff -> 0: the count read with the flag still pending
+        let mut wraps = 0;
+        let count = 2;
+        let pending = extend_count(wraps + wrap_direction(count, ARR, true), ARR, count);
+        // The interrupt then handles the same wrap
+        wraps += wrap_direction(count, ARR, true);
+        assert_eq!(extend_count(wraps, ARR, count), pending);
+        assert_eq!(pending, 65_538);
+    }
+}
@@ -0,0 +1,921 @@
+//! Quad-SPI (QUADSPI) interface for serial NOR flash
+//!
+//! Commands are described with [`QspiCommand`]: an instruction, an address, alternate bytes,
+//! dummy cycles and data, each phase being optional and using 1, 2 or 4 lines. They are run
+//! in one of three modes:
+//!
+//! - indirect mode, with [`Qspi::indirect_read`] and [`Qspi::indirect_write`], the data going
+//!   through the 32-byte FIFO,
+//! - automatic polling, with [`Qspi::auto_poll`], reading a status register until some of its
+//!   bits match, e.g. until the busy bit of a flash clears,
+//! - memory-mapped mode, with [`Qspi::memory_mapped`], the flash being readable at
+//!   `0x9000_0000` like internal memory.
+//!
+//! [`QspiFlash`] implements the `embedded-storage` NOR flash traits on top of these, for flash
+//! memories following the common Winbond W25Q command set.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let qspi = Qspi::new(
+//!     dp.QUADSPI,
+//!     (
+//!         gpiob.pb2.into_alternate(),
+//!         gpiog.pg6.into_alternate(),
+//!         gpiof.pf8.into_alternate(),
+//!         gpiof.pf9.into_alternate(),
+//!         gpiof.pf7.into_alternate(),
+//!         gpiof.pf6.into_alternate(),
+//!     ),
+//!     Config::new(50.MHz(), 16 * 1024 * 1024),
+//!     &clocks,
+//! )
+//! .unwrap();
+//!
+//! // Read the JEDEC ID
+//! let mut id = [0; 3];
+//! let read_id = QspiCommand::new()
+//!     .instruction(0x9f, LineMode::Single)
+//!     .data_mode(LineMode::Single);
+//! qspi.indirect_read(&read_id, &mut id).unwrap();
+//! ```
+
+use core::marker::PhantomData;
+use core::{ptr, slice};
+
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+use crate::pac::{QUADSPI, RCC};
+use crate::rcc::{Clocks, Enable, Reset};
+use fugit::HertzU32 as Hertz;
+
+pub use pins::{PinClk, PinIo0, PinIo1, PinIo2, PinIo3, PinNcs, Pins};
+
+mod sealed {
+    pub trait Sealed {}
+}
+use sealed::Sealed;
+
+/// Start of the memory-mapped flash region
+pub const MEMORY_MAPPED_BASE: usize = 0x9000_0000;
+/// Size of the memory-mapped flash region
+const MEMORY_MAPPED_SIZE: u32 = 256 * 1024 * 1024;
+const FIFO_SIZE: u8 = 32;
+
+const CR_EN: u32 = 1 << 0;
+const CR_ABORT: u32 = 1 << 1;
+const CR_SSHIFT: u32 = 1 << 4;
+const CR_DFM: u32 = 1 << 6;
+const CR_FSEL: u32 = 1 << 7;
+const CR_APMS: u32 = 1 << 22;
+const CR_PMM: u32 = 1 << 23;
+
+const SR_TEF: u32 = 1 << 0;
+const SR_TCF: u32 = 1 << 1;
+const SR_FTF: u32 = 1 << 2;
+const SR_SMF: u32 = 1 << 3;
+const SR_BUSY: u32 = 1 << 5;
+
+const FCR_ALL: u32 = 0b11011;
+
+const CCR_FMODE_INDIRECT_WRITE: u32 = 0b00 << 26;
+const CCR_FMODE_INDIRECT_READ: u32 = 0b01 << 26;
+const CCR_FMODE_AUTO_POLL: u32 = 0b10 << 26;
+const CCR_FMODE_MEMORY_MAPPED: u32 = 0b11 << 26;
+
+/// QUADSPI error
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The clock frequency can't be reached or the flash size isn't a power of two
+    InvalidConfig,
+    /// The command has more than 31 dummy cycles, or data without a data phase
+    InvalidCommand,
+    /// The access is beyond the flash size
+    OutOfBounds,
+    /// The address or length isn't aligned to an erase sector
+    NotAligned,
+    /// The peripheral reported a transfer error
+    Transfer,
+}
+
+/// Number of lines used by a command phase
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LineMode {
+    /// The phase is skipped
+    None = 0,
+    /// Single line, on IO0 (and IO1 for input)
+    Single = 1,
+    /// IO0 and IO1
+    Dual = 2,
+    /// IO0 to IO3
+    Quad = 3,
+}
+
+/// Size of the address or of the alternate bytes
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FieldSize {
+    Bits8 = 0,
+    Bits16 = 1,
+    Bits24 = 2,
+    Bits32 = 3,
+}
+
+/// A command sent to the flash
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct QspiCommand {
+    instruction: Option<(u8, LineMode)>,
+    address: Option<(u32, FieldSize, LineMode)>,
+    alt_bytes: Option<(u32, FieldSize, LineMode)>,
+    dummy_cycles: u8,
+    data_mode: LineMode,
+}
+
+impl Default for QspiCommand {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QspiCommand {
+    /// A command with no phase
+    pub const fn new() -> Self {
+        Self {
+            instruction: None,
+            address: None,
+            alt_bytes: None,
+            dummy_cycles: 0,
+            data_mode: LineMode::None,
+        }
+    }
+
+    /// Sends `instruction` on `mode` lines
+    pub fn instruction(mut self, instruction: u8, mode: LineMode) -> Self {
+        self.instruction = Some((instruction, mode));
+        self
+    }
+
+    /// Sends `address` on `mode` lines
+    pub fn address(mut self, address: u32, size: FieldSize, mode: LineMode) -> Self {
+        self.address = Some((address, size, mode));
+        self
+    }
+
+    /// Sends the alternate bytes `alt_bytes` on `mode` lines, e.g. a continuous read mode byte
+    pub fn alt_bytes(mut self, alt_bytes: u32, size: FieldSize, mode: LineMode) -> Self {
+        self.alt_bytes = Some((alt_bytes, size, mode));
+        self
+    }
+
+    /// Waits `cycles` clock cycles before the data phase, up to 31
+    pub fn dummy_cycles(mut self, cycles: u8) -> Self {
+        self.dummy_cycles = cycles;
+        self
+    }
+
+    /// Transfers data on `mode` lines
+    pub fn data_mode(mut self, mode: LineMode) -> Self {
+        self.data_mode = mode;
+        self
+    }
+
+    fn with_address(mut self, address: u32) -> Self {
+        if let Some((_, size, mode)) = self.address {
+            self.address = Some((address, size, mode));
+        }
+        self
+    }
+
+    /// CCR value with functional mode `fmode`
+    fn ccr(&self, fmode: u32) -> Result<u32, Error> {
+        if self.dummy_cycles > 31 {
+            return Err(Error::InvalidCommand);
+        }
+        let mut ccr = fmode | (self.data_mode as u32) << 24 | u32::from(self.dummy_cycles) << 18;
+        if let Some((instruction, mode)) = self.instruction {
+            ccr |= (mode as u32) << 8 | u32::from(instruction);
+        }
+        if let Some((_, size, mode)) = self.address {
+            ccr |= (size as u32) << 12 | (mode as u32) << 10;
+        }
+        if let Some((_, size, mode)) = self.alt_bytes {
+            ccr |= (size as u32) << 16 | (mode as u32) << 14;
+        }
+        Ok(ccr)
+    }
+
+    fn has_address(&self) -> bool {
+        matches!(self.address, Some((_, _, mode)) if mode != LineMode::None)
+    }
+}
+
+/// Clock polarity when the chip is deselected
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClockMode {
+    /// CLK stays low, SPI mode 0
+    Mode0,
+    /// CLK stays high, SPI mode 3
+    Mode3,
+}
+
+/// QUADSPI configuration
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    frequency: Hertz,
+    flash_size: u32,
+    clock_mode: ClockMode,
+    chip_select_high_time: u8,
+    sample_shift: bool,
+    fifo_threshold: u8,
+}
+
+impl Config {
+    /// Configuration for a flash of `flash_size` bytes, a power of two, clocked at up to
+    /// `frequency`
+    ///
+    /// With two flash memories, `flash_size` is their total size.
+    pub fn new(frequency: Hertz, flash_size: u32) -> Self {
+        Self {
+            frequency,
+            flash_size,
+            clock_mode: ClockMode::Mode0,
+            chip_select_high_time: 2,
+            sample_shift: true,
+            fifo_threshold: 4,
+        }
+    }
+
+    /// Clock polarity when the chip is deselected
+    pub fn clock_mode(mut self, clock_mode: ClockMode) -> Self {
+        self.clock_mode = clock_mode;
+        self
+    }
+
+    /// Minimum number of cycles, 1 to 8, that NCS stays high between commands
+    pub fn chip_select_high_time(mut self, cycles: u8) -> Self {
+        self.chip_select_high_time = cycles.clamp(1, 8);
+        self
+    }
+
+    /// Samples input data half a cycle later, needed at high frequencies
+    pub fn sample_shift(mut self, sample_shift: bool) -> Self {
+        self.sample_shift = sample_shift;
+        self
+    }
+
+    /// Number of bytes, 1 to 32, moved at once between the FIFO and the CPU
+    pub fn fifo_threshold(mut self, bytes: u8) -> Self {
+        self.fifo_threshold = bytes.clamp(1, FIFO_SIZE);
+        self
+    }
+}
+
+/// Flash memories connected to bank 1
+pub struct Bank1;
+/// Flash memories connected to bank 2
+pub struct Bank2;
+/// Two flash memories, one on each bank, accessed in parallel
+pub struct DualBank;
+
+/// Bank selection
+pub trait Bank: Sealed {
+    #[doc(hidden)]
+    const CR: u32;
+}
+
+impl Sealed for Bank1 {}
+impl Bank for Bank1 {
+    const CR: u32 = 0;
+}
+impl Sealed for Bank2 {}
+impl Bank for Bank2 {
+    const CR: u32 = CR_FSEL;
+}
+impl Sealed for DualBank {}
+impl Bank for DualBank {
+    const CR: u32 = CR_DFM;
+}
+
+/// A bank with a single flash memory, [`Bank1`] or [`Bank2`]
+pub trait SingleBank: Bank {}
+impl SingleBank for Bank1 {}
+impl SingleBank for Bank2 {}
+
+/// Automatic polling condition
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Polling {
+    mask: u32,
+    value: u32,
+    len: u8,
+    interval: u16,
+}
+
+impl Polling {
+    /// Waits until the bits of a 1-byte status selected by `mask` are equal to `value`
+    pub fn new(mask: u32, value: u32) -> Self {
+        Self {
+            mask,
+            value,
+            len: 1,
+            interval: 16,
+        }
+    }
+
+    /// Size of the status, 1 to 4 bytes
+    pub fn status_size(mut self, bytes: u8) -> Self {
+        self.len = bytes.clamp(1, 4);
+        self
+    }
+
+    /// Number of clock cycles between two reads of the status
+    pub fn interval(mut self, cycles: u16) -> Self {
+        self.interval = cycles;
+        self
+    }
+}
+
+/// QUADSPI interface
+pub struct Qspi<BANK, PINS> {
+    qspi: QUADSPI,
+    pins: PINS,
+    flash_size: u32,
+    fifo_threshold: u8,
+    _bank: PhantomData<BANK>,
+}
+
+impl<BANK: Bank, PINS: Pins<BANK>> Qspi<BANK, PINS> {
+    /// Enables QUADSPI and applies `config`
+    ///
+    /// The clock is HCLK divided by 1 to 256, the frequency is at most `config`'s.
+    pub fn new(qspi: QUADSPI, pins: PINS, config: Config, clocks: &Clocks) -> Result<Self, Error> {
+        let hclk = clocks.hclk().raw();
+        let frequency = config.frequency.raw();
+        if frequency == 0 {
+            return Err(Error::InvalidConfig);
+        }
+        let prescaler = (hclk + frequency - 1) / frequency;
+        if !(1..=256).contains(&prescaler) {
+            return Err(Error::InvalidConfig);
+        }
+
+        let size = config.flash_size;
+        if !size.is_power_of_two() || !(2..=MEMORY_MAPPED_SIZE).contains(&size) {
+            return Err(Error::InvalidConfig);
+        }
+        // Size is 2^(FSIZE + 1) bytes
+        let fsize = size.trailing_zeros() - 1;
+
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            QUADSPI::enable(rcc);
+            QUADSPI::reset(rcc);
+        }
+
+        let mut dcr = fsize << 16 | u32::from(config.chip_select_high_time - 1) << 8;
+        if config.clock_mode == ClockMode::Mode3 {
+            dcr |= 1;
+        }
+        let mut cr =
+            (prescaler - 1) << 24 | u32::from(config.fifo_threshold - 1) << 8 | BANK::CR | CR_EN;
+        if config.sample_shift {
+            cr |= CR_SSHIFT;
+        }
+        unsafe {
+            qspi.dcr.write(|w| w.bits(dcr));
+            qspi.cr.write(|w| w.bits(cr));
+        }
+
+        Ok(Self {
+            qspi,
+            pins,
+            flash_size: size,
+            fifo_threshold: config.fifo_threshold,
+            _bank: PhantomData,
+        })
+    }
+
+    /// Flash size in bytes
+    pub fn flash_size(&self) -> u32 {
+        self.flash_size
+    }
+
+    /// Runs `command` and sends `data` in its data phase, if any
+    ///
+    /// A command without data, such as Write Enable, is sent with an empty `data`.
+    pub fn indirect_write(&mut self, command: &QspiCommand, data: &[u8]) -> Result<(), Error> {
+        self.setup(command, data.len(), CCR_FMODE_INDIRECT_WRITE)?;
+
+        let dr = &self.qspi.dr as *const _ as *mut u8;
+        for chunk in data.chunks(self.fifo_threshold.into()) {
+            // At least `fifo_threshold` bytes free
+            self.wait_flag(SR_FTF)?;
+            for byte in chunk {
+                // NOTE(unsafe) byte access pushes one byte in the FIFO
+                unsafe { ptr::write_volatile(dr, *byte) };
+            }
+        }
+
+        self.complete()
+    }
+
+    /// Runs `command` and fills `buffer` with its data phase
+    pub fn indirect_read(&mut self, command: &QspiCommand, buffer: &mut [u8]) -> Result<(), Error> {
+        if buffer.is_empty() || command.data_mode == LineMode::None {
+            return Err(Error::InvalidCommand);
+        }
+        self.setup(command, buffer.len(), CCR_FMODE_INDIRECT_READ)?;
+
+        let dr = &self.qspi.dr as *const _ as *const u8;
+        let threshold = usize::from(self.fifo_threshold);
+        let mut received = 0;
+        while received < buffer.len() {
+            let sr = self.status()?;
+            // At least `fifo_threshold` bytes are available, or the transfer is complete and
+            // the FIFO holds the last bytes
+            let available = if sr & SR_FTF != 0 {
+                threshold
+            } else if sr & SR_TCF != 0 {
+                ((sr >> 8) & 0x3f) as usize
+            } else {
+                continue;
+            };
+            let end = (received + available).min(buffer.len());
+            for byte in &mut buffer[received..end] {
+                // NOTE(unsafe) byte access pops one byte from the FIFO
+                *byte = unsafe { ptr::read_volatile(dr) };
+            }
+            received = end;
+        }
+
+        self.complete()
+    }
+
+    /// Runs `command` until the status it reads matches `polling`
+    pub fn auto_poll(&mut self, command: &QspiCommand, polling: Polling) -> Result<(), Error> {
+        if command.data_mode == LineMode::None {
+            return Err(Error::InvalidCommand);
+        }
+        self.wait_idle();
+        unsafe {
+            self.qspi.psmkr.write(|w| w.bits(polling.mask));
+            self.qspi.psmar.write(|w| w.bits(polling.value));
+            self.qspi.pir.write(|w| w.bits(u32::from(polling.interval)));
+            // Stop polling on the first match, AND of the masked bits
+            self.qspi
+                .cr
+                .modify(|r, w| w.bits((r.bits() & !CR_PMM) | CR_APMS));
+        }
+        self.setup(command, polling.len.into(), CCR_FMODE_AUTO_POLL)?;
+
+        self.wait_flag(SR_SMF)?;
+        unsafe { self.qspi.fcr.write(|w| w.bits(FCR_ALL)) };
+        self.wait_idle();
+        Ok(())
+    }
+
+    /// Enters memory-mapped mode, `command` being used to read the flash
+    ///
+    /// The address of the command is ignored, the flash is read at the address accessed in
+    /// the mapped region. Indirect and polling commands are available again once the returned
+    /// [`MemoryMapped`] is dropped.
+    pub fn memory_mapped(&mut self, command: &QspiCommand) -> Result<MemoryMapped<'_>, Error> {
+        if command.data_mode == LineMode::None {
+            return Err(Error::InvalidCommand);
+        }
+        let ccr = command.ccr(CCR_FMODE_MEMORY_MAPPED)?;
+        self.wait_idle();
+        unsafe {
+            self.qspi.fcr.write(|w| w.bits(FCR_ALL));
+            if let Some((alt_bytes, _, _)) = command.alt_bytes {
+                self.qspi.abr.write(|w| w.bits(alt_bytes));
+            }
+            self.qspi.ccr.write(|w| w.bits(ccr));
+        }
+        Ok(MemoryMapped {
+            qspi: &self.qspi,
+            size: self.flash_size,
+        })
+    }
+
+    /// Aborts the ongoing command and flushes the FIFO
+    pub fn abort(&mut self) {
+        abort(&self.qspi);
+    }
+
+    /// Disables QUADSPI and releases the peripheral and pins
+    pub fn release(self) -> (QUADSPI, PINS) {
+        abort(&self.qspi);
+        unsafe { self.qspi.cr.write(|w| w.bits(0)) };
+        (self.qspi, self.pins)
+    }
+
+    /// Writes the command registers, starting the command
+    fn setup(&mut self, command: &QspiCommand, len: usize, fmode: u32) -> Result<(), Error> {
+        if len > 0 && command.data_mode == LineMode::None {
+            return Err(Error::InvalidCommand);
+        }
+        if let Some((address, _, _)) = command.address {
+            if command.has_address() && address >= self.flash_size {
+                return Err(Error::OutOfBounds);
+            }
+        }
+        let ccr = command.ccr(fmode)?;
+
+        // A previous command must be over before the registers are written
+        self.wait_idle();
+        unsafe {
+            self.qspi.fcr.write(|w| w.bits(FCR_ALL));
+            if len > 0 {
+                self.qspi.dlr.write(|w| w.bits(len as u32 - 1));
+            }
+            if let Some((alt_bytes, _, _)) = command.alt_bytes {
+                self.qspi.abr.write(|w| w.bits(alt_bytes));
+            }
+            // The command starts when CCR is written, or AR if there's an address
+            self.qspi.ccr.write(|w| w.bits(ccr));
+            if let Some((address, _, _)) = command.address.filter(|_| command.has_address()) {
+                self.qspi.ar.write(|w| w.bits(address));
+            }
+        }
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<u32, Error> {
+        let sr = self.qspi.sr.read().bits();
+        if sr & SR_TEF != 0 {
+            abort(&self.qspi);
+            return Err(Error::Transfer);
+        }
+        Ok(sr)
+    }
+
+    fn wait_flag(&mut self, flag: u32) -> Result<(), Error> {
+        while self.status()? & flag == 0 {}
+        Ok(())
+    }
+
+    /// Waits for the end of the transfer and clears its flag
+    fn complete(&mut self) -> Result<(), Error> {
+        self.wait_flag(SR_TCF)?;
+        unsafe { self.qspi.fcr.write(|w| w.bits(FCR_ALL)) };
+        self.wait_idle();
+        Ok(())
+    }
+
+    fn wait_idle(&self) {
+        while self.qspi.sr.read().bits() & SR_BUSY != 0 {}
+    }
+}
+
+/// Aborts the ongoing command, the abort bit clears once the peripheral is idle
+fn abort(qspi: &QUADSPI) {
+    unsafe { qspi.cr.modify(|r, w| w.bits(r.bits() | CR_ABORT)) };
+    while qspi.cr.read().bits() & CR_ABORT != 0 {}
+    while qspi.sr.read().bits() & SR_BUSY != 0 {}
+    unsafe { qspi.fcr.write(|w| w.bits(FCR_ALL)) };
+}
+
+/// The flash mapped at [`MEMORY_MAPPED_BASE`], memory-mapped mode ends when it's dropped
+pub struct MemoryMapped<'a> {
+    qspi: &'a QUADSPI,
+    size: u32,
+}
+
+impl MemoryMapped<'_> {
+    /// Address of the first byte
+    pub fn base(&self) -> *const u8 {
+        MEMORY_MAPPED_BASE as *const u8
+    }
+
+    /// Flash size in bytes
+    pub fn len(&self) -> usize {
+        self.size as usize
+    }
+
+    /// Always `false`, the flash size is at least 2 bytes
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The whole flash
+    pub fn as_slice(&self) -> &[u8] {
+        // NOTE(unsafe) the region is readable while memory-mapped mode lasts
+        unsafe { slice::from_raw_parts(self.base(), self.len()) }
+    }
+
+    /// Reads `buffer.len()` bytes at `offset`
+    pub fn read(&self, offset: u32, buffer: &mut [u8]) -> Result<(), Error> {
+        let start = offset as usize;
+        let end = start
+            .checked_add(buffer.len())
+            .filter(|end| *end <= self.len())
+            .ok_or(Error::OutOfBounds)?;
+        buffer.copy_from_slice(&self.as_slice()[start..end]);
+        Ok(())
+    }
+}
+
+impl Drop for MemoryMapped<'_> {
+    fn drop(&mut self) {
+        abort(self.qspi);
+    }
+}
+
+/// Read command used by [`QspiFlash`]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReadMode {
+    /// Fast Read (`0x0B`), single line
+    Single,
+    /// Fast Read Quad Output (`0x6B`), the Quad Enable bit of the flash must be set
+    QuadOutput,
+}
+
+const CMD_WRITE_ENABLE: u8 = 0x06;
+const CMD_READ_STATUS: u8 = 0x05;
+const CMD_PAGE_PROGRAM: u8 = 0x02;
+const CMD_SECTOR_ERASE: u8 = 0x20;
+const CMD_FAST_READ: u8 = 0x0b;
+const CMD_FAST_READ_QUAD: u8 = 0x6b;
+const STATUS_BUSY: u32 = 1 << 0;
+const PAGE_SIZE: u32 = 256;
+
+/// NOR flash with the common W25Q command set and 24-bit addresses, up to 16 MiB, on a
+/// single bank
+///
+/// In dual-flash mode the bytes are interleaved between the two memories, so addresses,
+/// page and sector sizes as seen by one memory are halved, which these commands don't
+/// account for.
+pub struct QspiFlash<BANK, PINS> {
+    qspi: Qspi<BANK, PINS>,
+    read_mode: ReadMode,
+}
+
+impl<BANK: SingleBank, PINS: Pins<BANK>> QspiFlash<BANK, PINS> {
+    /// Flash behind `qspi`, read with `read_mode`
+    pub fn new(qspi: Qspi<BANK, PINS>, read_mode: ReadMode) -> Result<Self, Error> {
+        if qspi.flash_size() > 1 << 24 {
+            return Err(Error::InvalidConfig);
+        }
+        Ok(Self { qspi, read_mode })
+    }
+
+    /// Access to the interface, e.g. for vendor-specific commands
+    pub fn qspi(&mut self) -> &mut Qspi<BANK, PINS> {
+        &mut self.qspi
+    }
+
+    /// Releases the interface
+    pub fn release(self) -> Qspi<BANK, PINS> {
+        self.qspi
+    }
+
+    /// Command reading data at any address
+    pub fn read_command(&self) -> QspiCommand {
+        let (instruction, data_mode) = match self.read_mode {
+            ReadMode::Single => (CMD_FAST_READ, LineMode::Single),
+            ReadMode::QuadOutput => (CMD_FAST_READ_QUAD, LineMode::Quad),
+        };
+        QspiCommand::new()
+            .instruction(instruction, LineMode::Single)
+            .address(0, FieldSize::Bits24, LineMode::Single)
+            .dummy_cycles(8)
+            .data_mode(data_mode)
+    }
+
+    /// Sends Write Enable, runs `command` with `data` and waits for the end of the operation
+    fn program(&mut self, command: &QspiCommand, data: &[u8]) -> Result<(), Error> {
+        let write_enable = QspiCommand::new().instruction(CMD_WRITE_ENABLE, LineMode::Single);
+        self.qspi.indirect_write(&write_enable, &[])?;
+        self.qspi.indirect_write(command, data)?;
+
+        let read_status = QspiCommand::new()
+            .instruction(CMD_READ_STATUS, LineMode::Single)
+            .data_mode(LineMode::Single);
+        self.qspi
+            .auto_poll(&read_status, Polling::new(STATUS_BUSY, 0))
+    }
+
+    fn check_bounds(&self, offset: u32, len: usize) -> Result<(), Error> {
+        match (offset as usize).checked_add(len) {
+            Some(end) if end <= self.capacity() => Ok(()),
+            _ => Err(Error::OutOfBounds),
+        }
+    }
+}
+
+impl<BANK: SingleBank, PINS: Pins<BANK>> ReadNorFlash for QspiFlash<BANK, PINS> {
+    type Error = Error;
+
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.check_bounds(offset, bytes.len())?;
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        let command = self.read_command().with_address(offset);
+        self.qspi.indirect_read(&command, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        self.qspi.flash_size() as usize
+    }
+}
+
+impl<BANK: SingleBank, PINS: Pins<BANK>> NorFlash for QspiFlash<BANK, PINS> {
+    const WRITE_SIZE: usize = 1;
+
+    const ERASE_SIZE: usize = 4096;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let sector = Self::ERASE_SIZE as u32;
+        if from % sector != 0 || to % sector != 0 || to < from {
+            return Err(Error::NotAligned);
+        }
+        self.check_bounds(from, (to - from) as usize)?;
+        for address in (from..to).step_by(Self::ERASE_SIZE) {
+            let command = QspiCommand::new()
+                .instruction(CMD_SECTOR_ERASE, LineMode::Single)
+                .address(address, FieldSize::Bits24, LineMode::Single);
+            self.program(&command, &[])?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.check_bounds(offset, bytes.len())?;
+        let mut address = offset;
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            // A page program wraps at the end of the page
+            let len = ((PAGE_SIZE - address % PAGE_SIZE) as usize).min(bytes.len());
+            let (page, rest) = bytes.split_at(len);
+            let command = QspiCommand::new()
+                .instruction(CMD_PAGE_PROGRAM, LineMode::Single)
+                .address(address, FieldSize::Bits24, LineMode::Single)
+                .data_mode(LineMode::Single);
+            self.program(&command, page)?;
+            address += len as u32;
+            bytes = rest;
+        }
+        Ok(())
+    }
+}
+
+mod pins {
+    use super::{Bank1, Bank2, DualBank, Sealed};
+    use crate::gpio::{self, Alternate};
+
+    /// A pin that can be used for `CLK`
+    pub trait PinClk: Sealed {}
+    /// A pin that can be used for `NCS` of bank `B`
+    pub trait PinNcs<const B: u8>: Sealed {}
+    /// A pin that can be used for `IO0` of bank `B`
+    pub trait PinIo0<const B: u8>: Sealed {}
+    /// A pin that can be used for `IO1` of bank `B`
+    pub trait PinIo1<const B: u8>: Sealed {}
+    /// A pin that can be used for `IO2` of bank `B`
+    pub trait PinIo2<const B: u8>: Sealed {}
+    /// A pin that can be used for `IO3` of bank `B`
+    pub trait PinIo3<const B: u8>: Sealed {}
+
+    /// A set of pins that can be used with [`Qspi`](super::Qspi) on `BANK`
+    ///
+    /// `(CLK, NCS, IO0, IO1, IO2, IO3)` for a single bank, and
+    /// `(CLK, NCS1, IO0..IO3 of bank 1, NCS2, IO0..IO3 of bank 2)` for dual-flash mode.
+    pub trait Pins<BANK>: Sealed {}
+
+    impl<CLK, NCS, IO0, IO1, IO2, IO3> Sealed for (CLK, NCS, IO0, IO1, IO2, IO3) {}
+
+    impl<CLK, NCS, IO0, IO1, IO2, IO3> Pins<Bank1> for (CLK, NCS, IO0, IO1, IO2, IO3)
+    where
+        CLK: PinClk,
+        NCS: PinNcs<1>,
+        IO0: PinIo0<1>,
+        IO1: PinIo1<1>,
+        IO2: PinIo2<1>,
+        IO3: PinIo3<1>,
+    {
+    }
+
+    impl<CLK, NCS, IO0, IO1, IO2, IO3> Pins<Bank2> for (CLK, NCS, IO0, IO1, IO2, IO3)
+    where
+        CLK: PinClk,
+        NCS: PinNcs<2>,
+        IO0: PinIo0<2>,
+        IO1: PinIo1<2>,
+        IO2: PinIo2<2>,
+        IO3: PinIo3<2>,
+    {
+    }
+
+    impl<CLK, NCS1, IO10, IO11, IO12, IO13, NCS2, IO20, IO21, IO22, IO23> Sealed
+        for (
+            CLK,
+            NCS1,
+            IO10,
+            IO11,
+            IO12,
+            IO13,
+            NCS2,
+            IO20,
+            IO21,
+            IO22,
+            IO23,
+        )
+    {
+    }
+
+    impl<CLK, NCS1, IO10, IO11, IO12, IO13, NCS2, IO20, IO21, IO22, IO23> Pins<DualBank>
+        for (
+            CLK,
+            NCS1,
+            IO10,
+            IO11,
+            IO12,
+            IO13,
+            NCS2,
+            IO20,
+            IO21,
+            IO22,
+            IO23,
+        )
+    where
+        CLK: PinClk,
+        NCS1: PinNcs<1>,
+        IO10: PinIo0<1>,
+        IO11: PinIo1<1>,
+        IO12: PinIo2<1>,
+        IO13: PinIo3<1>,
+        NCS2: PinNcs<2>,
+        IO20: PinIo0<2>,
+        IO21: PinIo1<2>,
+        IO22: PinIo2<2>,
+        IO23: PinIo3<2>,
+    {
+    }
+
+    impl<const P: char, const N: u8, const A: u8, OTYPE> Sealed
+        for gpio::Pin<P, N, Alternate<A, OTYPE>>
+    {
+    }
+
+    macro_rules! pin {
+        ($trait:ident<$bank:literal> for $($PIN:ident<$A:literal>),+) => {
+            $(impl<OTYPE> $trait<$bank> for gpio::$PIN<Alternate<$A, OTYPE>> {})+
+        };
+        ($trait:ident for $($PIN:ident<$A:literal>),+) => {
+            $(impl<OTYPE> $trait for gpio::$PIN<Alternate<$A, OTYPE>> {})+
+        };
+    }
+
+    // Available on all parts with QUADSPI
+    pin!(PinNcs<1> for PB6<10>);
+    pin!(PinIo0<1> for PC9<9>, PD11<9>, PF8<10>);
+    pin!(PinIo1<1> for PC10<9>, PD12<9>, PF9<10>);
+    pin!(PinIo2<1> for PE2<9>, PF7<9>);
+    pin!(PinIo3<1> for PA1<9>, PD13<9>, PF6<9>);
+    pin!(PinNcs<2> for PC11<9>);
+    pin!(PinIo0<2> for PE7<10>);
+    pin!(PinIo1<2> for PE8<10>);
+    pin!(PinIo2<2> for PE9<10>, PG9<9>);
+    pin!(PinIo3<2> for PE10<10>, PG14<9>);
+
+    #[cfg(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446"))]
+    mod f412_f446 {
+        use super::*;
+
+        pin!(PinClk for PB2<9>, PD3<9>);
+        pin!(PinNcs<1> for PG6<10>);
+    }
+
+    #[cfg(any(feature = "gpio-f412", feature = "gpio-f413"))]
+    mod f412 {
+        use super::*;
+
+        pin!(PinClk for PB1<9>);
+        pin!(PinIo2<1> for PC8<9>);
+        pin!(PinIo0<2> for PA6<10>);
+        pin!(PinIo1<2> for PA7<10>);
+        pin!(PinIo2<2> for PC4<10>);
+        pin!(PinIo3<2> for PC5<10>);
+    }
+
+    #[cfg(feature = "gpio-f469")]
+    mod f469 {
+        use super::*;
+
+        pin!(PinClk for PF10<9>);
+        pin!(PinIo0<2> for PH2<9>);
+        pin!(PinIo1<2> for PH3<9>);
+    }
+}
@@ -1,11 +1,45 @@
 use super::*;
 use crate::bb;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Enable count of each peripheral, by bus and enable bit
+static ENABLE_COUNT: [AtomicU8; 5 * 32] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU8 = AtomicU8::new(0);
+    [ZERO; 5 * 32]
+};
+
+/// Counts one more user of the peripheral, saturating
+#[inline(always)]
+fn count_enable(count: &AtomicU8) {
+    let _ = count.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_add(1));
+}
+
+/// Counts one less user of the peripheral, returns `true` if there are none left
+#[inline(always)]
+fn count_disable(count: &AtomicU8) -> bool {
+    match count.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)) {
+        Ok(n) => n <= 1,
+        // Never enabled through `Enable`
+        Err(_) => true,
+    }
+}
+
+/// Forgets the users from before a reset, only the one resetting is left if the clock is on
+///
+/// Drivers which never disable the clock enable it again each time they are created, after
+/// a reset of the peripheral.
+#[inline(always)]
+fn count_reset(count: &AtomicU8, enabled: bool) {
+    count.store(enabled as u8, Ordering::Relaxed);
+}
 
 macro_rules! bus_enable {
     ($PER:ident => $bit:literal) => {
         impl Enable for crate::pac::$PER {
             #[inline(always)]
             fn enable(rcc: &RccRB) {
+                count_enable(&ENABLE_COUNT[Self::Bus::INDEX * 32 + $bit]);
                 unsafe {
                     bb::set(Self::Bus::enr(rcc), $bit);
                 }
@@ -14,8 +48,10 @@ macro_rules! bus_enable {
             }
             #[inline(always)]
             fn disable(rcc: &RccRB) {
-                unsafe {
-                    bb::clear(Self::Bus::enr(rcc), $bit);
+                if count_disable(&ENABLE_COUNT[Self::Bus::INDEX * 32 + $bit]) {
+                    unsafe {
+                        bb::clear(Self::Bus::enr(rcc), $bit);
+                    }
                 }
             }
         }
@@ -43,15 +79,28 @@ macro_rules! bus_lpenable {
 }
 macro_rules! bus_reset {
     ($PER:ident => $bit:literal) => {
+        bus_reset!($PER => $bit, enable: $bit);
+    };
+    // Reset bit shared with other peripherals, the enable bit keys the enable count
+    ($PER:ident => $bit:literal, enable: $en:literal) => {
         impl Reset for crate::pac::$PER {
             #[inline(always)]
             fn reset(rcc: &RccRB) {
                 unsafe {
                     bb::set(Self::Bus::rstr(rcc), $bit);
                     bb::clear(Self::Bus::rstr(rcc), $bit);
+                    count_reset(
+                        &ENABLE_COUNT[Self::Bus::INDEX * 32 + $en],
+                        bb::read(Self::Bus::enr(rcc), $en),
+                    );
                 }
             }
         }
+        #[cfg(test)]
+        impl tests::ResetBit for crate::pac::$PER {
+            const RESET_BIT: u8 = $bit;
+            const ENABLE_BIT: u8 = $en;
+        }
     };
 }
 
@@ -109,6 +158,21 @@ bus! {
     RNG => (AHB2, 6),
 }
 
+#[cfg(feature = "dcmi")]
+bus! {
+    DCMI => (AHB2, 0),
+}
+
+#[cfg(feature = "cryp")]
+bus! {
+    CRYP => (AHB2, 4),
+}
+
+#[cfg(feature = "hash")]
+bus! {
+    HASH => (AHB2, 5),
+}
+
 #[cfg(feature = "otg-fs")]
 bus! {
     OTG_FS_GLOBAL => (AHB2, 7),
@@ -136,6 +200,19 @@ bus_enable!(FSMC => 0);
 #[cfg(feature = "fsmc")]
 bus_reset!(FSMC => 0);
 
+#[cfg(feature = "quadspi")]
+impl crate::Sealed for crate::pac::QUADSPI {}
+#[cfg(feature = "quadspi")]
+impl RccBus for crate::pac::QUADSPI {
+    type Bus = AHB3;
+}
+#[cfg(feature = "quadspi")]
+bus_enable!(QUADSPI => 1);
+#[cfg(all(feature = "quadspi", feature = "fmc"))]
+bus_lpenable!(QUADSPI => 1);
+#[cfg(feature = "quadspi")]
+bus_reset!(QUADSPI => 1);
+
 bus! {
     PWR => (APB1, 28),
 }
@@ -220,10 +297,34 @@ bus! {
     DAC => (APB1, 29),
 }
 
+#[cfg(feature = "stm32f410")]
+bus! {
+    LPTIM1 => (APB1, 9),
+}
+
+#[cfg(any(feature = "stm32f413", feature = "stm32f423"))]
+bus! {
+    LPTIM => (APB1, 9),
+}
+
 bus! {
     SYSCFG => (APB2, 14),
 }
 
+bus! {
+    WWDG => (APB1, 11),
+}
+
+#[cfg(feature = "stm32f412")]
+bus! {
+    DFSDM => (APB2, 16),
+}
+#[cfg(any(feature = "stm32f413", feature = "stm32f423"))]
+bus! {
+    DFSDM1 => (APB2, 16),
+    DFSDM2 => (APB2, 17),
+}
+
 bus! {
     ADC1 => (APB2, 8),
 }
@@ -239,7 +340,7 @@ bus_enable!(ADC2 => 9);
 #[cfg(feature = "adc2")]
 bus_lpenable!(ADC2 => 9);
 #[cfg(feature = "adc2")]
-bus_reset!(ADC2 => 8);
+bus_reset!(ADC2 => 8, enable: 9);
 
 #[cfg(feature = "adc3")]
 impl crate::Sealed for crate::pac::ADC3 {}
@@ -252,13 +353,51 @@ bus_enable!(ADC3 => 10);
 #[cfg(feature = "adc3")]
 bus_lpenable!(ADC3 => 10);
 #[cfg(feature = "adc3")]
-bus_reset!(ADC3 => 8);
+bus_reset!(ADC3 => 8, enable: 10);
 
 #[cfg(feature = "sdio")]
 bus! {
     SDIO => (APB2, 11),
 }
 
+#[cfg(feature = "dma2d")]
+bus! {
+    DMA2D => (AHB1, 23),
+}
+
+#[cfg(feature = "eth")]
+bus! {
+    ETHERNET_MAC => (AHB1, 25),
+}
+
+#[cfg(feature = "ltdc")]
+bus! {
+    LTDC => (APB2, 26),
+}
+
+#[cfg(all(
+    feature = "sai1",
+    not(any(feature = "stm32f427", feature = "stm32f437", feature = "stm32f446"))
+))]
+bus! {
+    SAI => (APB2, 22),
+}
+
+#[cfg(any(feature = "stm32f427", feature = "stm32f437", feature = "stm32f446"))]
+bus! {
+    SAI1 => (APB2, 22),
+}
+
+#[cfg(feature = "sai2")]
+bus! {
+    SAI2 => (APB2, 23),
+}
+
+#[cfg(feature = "spdifrx")]
+bus! {
+    SPDIFRX => (APB1, 16),
+}
+
 bus! {
     TIM1 => (APB2, 0),
     TIM5 => (APB1, 3),
@@ -335,3 +474,89 @@ bus! {
     TIM13 => (APB1, 7),
     TIM14 => (APB1, 8),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bits of a peripheral in the reset and enable registers of its bus
+    pub(super) trait ResetBit: RccBus {
+        const RESET_BIT: u8;
+        const ENABLE_BIT: u8;
+    }
+
+    fn bits<PER: ResetBit>() -> (u8, u8) {
+        (PER::RESET_BIT, PER::ENABLE_BIT)
+    }
+
+    fn on_apb1<PER: RccBus<Bus = APB1>>() {}
+    fn on_apb2<PER: RccBus<Bus = APB2>>() {}
+
+    #[test]
+    fn driver_reset_bits() {
+        use crate::pac::{I2C1, SPI1, SPI2, USART1, USART2};
+        // USART1RST, SPI1RST
+        on_apb2::<USART1>();
+        assert_eq!(bits::<USART1>(), (4, 4));
+        on_apb2::<SPI1>();
+        assert_eq!(bits::<SPI1>(), (12, 12));
+        // USART2RST, SPI2RST, I2C1RST
+        on_apb1::<USART2>();
+        assert_eq!(bits::<USART2>(), (17, 17));
+        on_apb1::<SPI2>();
+        assert_eq!(bits::<SPI2>(), (14, 14));
+        on_apb1::<I2C1>();
+        assert_eq!(bits::<I2C1>(), (21, 21));
+    }
+
+    #[cfg(feature = "adc2")]
+    #[test]
+    fn shared_adc_reset_bit() {
+        use crate::pac::{ADC1, ADC2};
+        // ADCRST resets all the ADCs
+        on_apb2::<ADC2>();
+        assert_eq!(bits::<ADC1>(), (8, 8));
+        assert_eq!(bits::<ADC2>(), (8, 9));
+    }
+
+    #[test]
+    fn last_disable_stops_the_clock() {
+        let count = AtomicU8::new(0);
+        count_enable(&count);
+        count_enable(&count);
+        // Still used by the first driver
+        assert!(!count_disable(&count));
+        assert!(count_disable(&count));
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn disable_without_enable() {
+        let count = AtomicU8::new(0);
+        assert!(count_disable(&count));
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn enable_count_saturates() {
+        let count = AtomicU8::new(254);
+        count_enable(&count);
+        count_enable(&count);
+        assert_eq!(count.load(Ordering::Relaxed), 255);
+    }
+
+    #[test]
+    fn reset_forgets_earlier_users() {
+        let count = AtomicU8::new(0);
+        // A driver created three times, never disabling the clock
+        for _ in 0..3 {
+            count_enable(&count);
+            count_reset(&count, true);
+        }
+        assert!(count_disable(&count));
+
+        count_enable(&count);
+        count_reset(&count, false);
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+    }
+}
@@ -40,9 +40,11 @@
 //! and on the STM32F413/423 SAI clocks are generated by the I2S PLL. On these MCUs, the actual
 //! frequencies may substantially deviate from the requested frequencies.
 
+use crate::flash::VoltageRange;
 use crate::pac::rcc::cfgr::{HPRE_A, SW_A};
 use crate::pac::{rcc, RCC};
 
+use cortex_m::interrupt::CriticalSection;
 use fugit::HertzU32 as Hertz;
 use fugit::RateExtU32;
 
@@ -61,6 +63,29 @@ use pll::MainPll;
 use pll::SaiPll;
 
 mod pll;
+#[cfg(any(
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479"
+))]
+pub use pll::PllSaiConfig;
+#[cfg(any(
+    feature = "stm32f401",
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479"
+))]
+pub use pll::{PllConfigError, PllI2sConfig};
 
 mod enable;
 use crate::pac::rcc::RegisterBlock as RccRB;
@@ -72,20 +97,80 @@ pub trait RccBus: crate::Sealed {
 }
 
 /// Enable/disable peripheral
+///
+/// Implemented for the PAC peripherals, drivers outside of this crate can use it with
+/// [`BusClock`] to set up a peripheral not covered here.
+///
+/// The enable, low-power enable and reset bits are written with bit-banding, a single bus
+/// write which cannot race with other drivers doing the same from another priority level.
+/// After `freeze`, the other fields of registers shared between drivers are only modified
+/// in a critical section:
+///
+/// - `RCC_CR`: `HSITRIM`, `PLLSAION`
+/// - `RCC_CSR`: `LSION`
+/// - `RCC_BDCR`: `RTCSEL`
+/// - `RCC_DCKCFGR`: `PLLSAIDIVR`
+/// - `RCC_DCKCFGR2`: `FMPI2C1SEL`, `LPTIM1SEL`
+/// - `ADC_CCR`
+/// - `SYSCFG_EXTICR1` to `SYSCFG_EXTICR4`
+/// - `EXTI_IMR`, `EXTI_RTSR`, `EXTI_FTSR`
+///
+/// Enables are counted per peripheral: `disable` only stops the clock once it was called as
+/// many times as `enable`, so a driver cannot stop a clock still used by another one.
+/// [`Reset::reset`] reinitializes the peripheral, the users from before it are forgotten and
+/// only the one resetting is counted, so drivers which never disable the clock do not keep
+/// it counted when they are created again.
 pub trait Enable: RccBus {
     fn enable(rcc: &RccRB);
     fn disable(rcc: &RccRB);
+
+    /// Same as [`enable`](Self::enable), after the `RCC` peripheral was constrained
+    #[inline(always)]
+    fn enable_cs(_cs: &CriticalSection) {
+        // NOTE(unsafe) only the bit of this peripheral is written, with bit-banding
+        Self::enable(unsafe { &*RCC::ptr() });
+    }
+
+    /// Same as [`disable`](Self::disable), after the `RCC` peripheral was constrained
+    #[inline(always)]
+    fn disable_cs(_cs: &CriticalSection) {
+        // NOTE(unsafe) only the bit of this peripheral is written, with bit-banding
+        Self::disable(unsafe { &*RCC::ptr() });
+    }
 }
 
 /// Low power enable/disable peripheral
 pub trait LPEnable: RccBus {
     fn low_power_enable(rcc: &RccRB);
     fn low_power_disable(rcc: &RccRB);
+
+    /// Same as [`low_power_enable`](Self::low_power_enable), after the `RCC` peripheral was
+    /// constrained
+    #[inline(always)]
+    fn low_power_enable_cs(_cs: &CriticalSection) {
+        // NOTE(unsafe) only the bit of this peripheral is written, with bit-banding
+        Self::low_power_enable(unsafe { &*RCC::ptr() });
+    }
+
+    /// Same as [`low_power_disable`](Self::low_power_disable), after the `RCC` peripheral
+    /// was constrained
+    #[inline(always)]
+    fn low_power_disable_cs(_cs: &CriticalSection) {
+        // NOTE(unsafe) only the bit of this peripheral is written, with bit-banding
+        Self::low_power_disable(unsafe { &*RCC::ptr() });
+    }
 }
 
 /// Reset peripheral
 pub trait Reset: RccBus {
     fn reset(rcc: &RccRB);
+
+    /// Same as [`reset`](Self::reset), after the `RCC` peripheral was constrained
+    #[inline(always)]
+    fn reset_cs(_cs: &CriticalSection) {
+        // NOTE(unsafe) only the bit of this peripheral is written, with bit-banding
+        Self::reset(unsafe { &*RCC::ptr() });
+    }
 }
 
 /// Extension trait that constrains the `RCC` peripheral
@@ -132,6 +217,9 @@ pub struct AHB1 {
 }
 
 impl AHB1 {
+    /// Index of the bus in the enable counters
+    const INDEX: usize = 0;
+
     #[inline(always)]
     fn enr(rcc: &RccRB) -> &rcc::AHB1ENR {
         &rcc.ahb1enr
@@ -154,6 +242,9 @@ pub struct AHB2 {
 
 #[cfg(not(feature = "stm32f410"))]
 impl AHB2 {
+    /// Index of the bus in the enable counters
+    const INDEX: usize = 1;
+
     #[inline(always)]
     fn enr(rcc: &RccRB) -> &rcc::AHB2ENR {
         &rcc.ahb2enr
@@ -169,13 +260,16 @@ impl AHB2 {
 }
 
 /// AMBA High-performance Bus 3 (AHB3) registers
-#[cfg(any(feature = "fsmc", feature = "fmc"))]
+#[cfg(any(feature = "fsmc", feature = "fmc", feature = "quadspi"))]
 pub struct AHB3 {
     _0: (),
 }
 
-#[cfg(any(feature = "fsmc", feature = "fmc"))]
+#[cfg(any(feature = "fsmc", feature = "fmc", feature = "quadspi"))]
 impl AHB3 {
+    /// Index of the bus in the enable counters
+    const INDEX: usize = 2;
+
     #[inline(always)]
     fn enr(rcc: &RccRB) -> &rcc::AHB3ENR {
         &rcc.ahb3enr
@@ -197,6 +291,9 @@ pub struct APB1 {
 }
 
 impl APB1 {
+    /// Index of the bus in the enable counters
+    const INDEX: usize = 3;
+
     #[inline(always)]
     fn enr(rcc: &RccRB) -> &rcc::APB1ENR {
         &rcc.apb1enr
@@ -217,6 +314,9 @@ pub struct APB2 {
 }
 
 impl APB2 {
+    /// Index of the bus in the enable counters
+    const INDEX: usize = 4;
+
     #[inline(always)]
     fn enr(rcc: &RccRB) -> &rcc::APB2ENR {
         &rcc.apb2enr
@@ -244,7 +344,7 @@ impl BusClock for AHB2 {
     }
 }
 
-#[cfg(any(feature = "fsmc", feature = "fmc"))]
+#[cfg(any(feature = "fsmc", feature = "fmc", feature = "quadspi"))]
 impl BusClock for AHB3 {
     fn clock(clocks: &Clocks) -> Hertz {
         clocks.hclk
@@ -288,6 +388,10 @@ impl RccExt for RCC {
                 pclk2: None,
                 sysclk: None,
                 pll48clk: false,
+                flash_prefetch: true,
+                flash_supply: VoltageRange::V2_7To3_6,
+                flash_icache: true,
+                flash_dcache: true,
                 i2s_ckin: None,
                 #[cfg(any(
                     feature = "stm32f401",
@@ -343,6 +447,29 @@ impl RccExt for RCC {
                     feature = "stm32f479",
                 ))]
                 sai2_clk: None,
+                #[cfg(any(
+                    feature = "stm32f401",
+                    feature = "stm32f405",
+                    feature = "stm32f407",
+                    feature = "stm32f415",
+                    feature = "stm32f417",
+                    feature = "stm32f427",
+                    feature = "stm32f429",
+                    feature = "stm32f437",
+                    feature = "stm32f439",
+                    feature = "stm32f469",
+                    feature = "stm32f479"
+                ))]
+                plli2s: None,
+                #[cfg(any(
+                    feature = "stm32f427",
+                    feature = "stm32f429",
+                    feature = "stm32f437",
+                    feature = "stm32f439",
+                    feature = "stm32f469",
+                    feature = "stm32f479"
+                ))]
+                pllsai: None,
             },
         }
     }
@@ -353,6 +480,67 @@ pub struct Rcc {
     pub cfgr: CFGR,
 }
 
+/// `RCC_CR` value with `HSITRIM` (bits 3-7) set to `trim`, clamped to 31
+const fn hsi_trim_cr(cr: u32, trim: u8) -> u32 {
+    let trim = if trim > 31 { 31 } else { trim as u32 };
+    (cr & !(0b11111 << 3)) | (trim << 3)
+}
+
+impl Rcc {
+    /// Sets the HSI trimming (`HSITRIM`), from 0 to 31, the reset value is 16
+    ///
+    /// Each step changes the HSI frequency by about 0.5% (around 80 kHz). This is meant for
+    /// small corrections against a reference, e.g. measured with
+    /// [`Timer::measure_hsi`](crate::timer::Timer::measure_hsi), so [`Clocks`] stays valid.
+    ///
+    /// Can be called after [`CFGR::freeze`]. Values above 31 are clamped to 31.
+    pub fn set_hsi_trim(trim: u8) {
+        // NOTE(unsafe) HSITRIM is only written here, CR is modified in a critical section
+        let rcc = unsafe { &*RCC::ptr() };
+        cortex_m::interrupt::free(|_| {
+            rcc.cr
+                .modify(|r, w| unsafe { w.bits(hsi_trim_cr(r.bits(), trim)) })
+        });
+    }
+
+    /// Returns the factory calibration of HSI (`HSICAL`), which `HSITRIM` is added to
+    pub fn hsi_calibration() -> u8 {
+        // NOTE(unsafe) atomic read with no side effects
+        let rcc = unsafe { &*RCC::ptr() };
+        (rcc.cr.read().bits() >> 8) as u8
+    }
+
+    /// Returns the HSI trimming (`HSITRIM`)
+    pub fn hsi_trim() -> u8 {
+        // NOTE(unsafe) atomic read with no side effects
+        let rcc = unsafe { &*RCC::ptr() };
+        ((rcc.cr.read().bits() >> 3) & 0b11111) as u8
+    }
+
+    /// Stops the clocks of all peripherals in Sleep mode, except the flash interface and
+    /// the SRAMs
+    ///
+    /// After reset all clocks keep running in Sleep mode. The peripherals needed to wake up
+    /// are enabled again with [`LPEnable`], e.g. with `Rx::enable_in_sleep` and
+    /// `StreamX::enable_in_sleep` for reception with DMA. The clocks of the peripherals
+    /// left running are what remains of the Sleep mode current, which can be compared
+    /// on the `IDD` jumper of a Nucleo board.
+    pub fn gate_sleep_clocks(_cs: &CriticalSection) {
+        // Flash interface, SRAM1, SRAM2, backup SRAM and SRAM3
+        const MEMORIES: u32 = 0b11111 << 15;
+        // NOTE(unsafe) the whole registers are owned in the critical section
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.ahb1lpenr
+            .modify(|r, w| unsafe { w.bits(r.bits() & MEMORIES) });
+        #[cfg(not(feature = "stm32f410"))]
+        rcc.ahb2lpenr.write(|w| unsafe { w.bits(0) });
+        #[cfg(feature = "fmc")]
+        rcc.ahb3lpenr.write(|w| unsafe { w.bits(0) });
+        rcc.apb1lpenr.write(|w| unsafe { w.bits(0) });
+        rcc.apb2lpenr.write(|w| unsafe { w.bits(0) });
+    }
+}
+
 /// Built-in high speed clock frequency
 pub const HSI: u32 = 16_000_000; // Hz
 
@@ -449,6 +637,10 @@ pub struct CFGR {
     pclk2: Option<u32>,
     sysclk: Option<u32>,
     pll48clk: bool,
+    flash_prefetch: bool,
+    flash_supply: VoltageRange,
+    flash_icache: bool,
+    flash_dcache: bool,
 
     i2s_ckin: Option<u32>,
     #[cfg(any(
@@ -505,6 +697,29 @@ pub struct CFGR {
         feature = "stm32f479",
     ))]
     sai2_clk: Option<u32>,
+    #[cfg(any(
+        feature = "stm32f401",
+        feature = "stm32f405",
+        feature = "stm32f407",
+        feature = "stm32f415",
+        feature = "stm32f417",
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+        feature = "stm32f469",
+        feature = "stm32f479"
+    ))]
+    plli2s: Option<PllI2sConfig>,
+    #[cfg(any(
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+        feature = "stm32f469",
+        feature = "stm32f479"
+    ))]
+    pllsai: Option<PllSaiConfig>,
 }
 
 impl CFGR {
@@ -554,6 +769,35 @@ impl CFGR {
         self
     }
 
+    /// Enables the flash prefetch buffer when freezing, the default
+    ///
+    /// See [`FlashExt::enable_prefetch`](crate::flash::FlashExt::enable_prefetch).
+    pub fn flash_prefetch(mut self, enable: bool) -> Self {
+        self.flash_prefetch = enable;
+        self
+    }
+
+    /// Declares the supply voltage range, 2.7 V to 3.6 V by default
+    ///
+    /// The prefetch buffer is kept disabled below 2.1 V. The flash wait states are still
+    /// computed for 2.7 V to 3.6 V.
+    pub fn flash_supply(mut self, supply: VoltageRange) -> Self {
+        self.flash_supply = supply;
+        self
+    }
+
+    /// Enables the flash instruction cache when freezing, the default
+    pub fn flash_icache(mut self, enable: bool) -> Self {
+        self.flash_icache = enable;
+        self
+    }
+
+    /// Enables the flash data cache when freezing, the default
+    pub fn flash_dcache(mut self, enable: bool) -> Self {
+        self.flash_dcache = enable;
+        self
+    }
+
     /// Declares that the selected frequency is available at the I2S clock input pin (I2S_CKIN).
     ///
     /// If this frequency matches the requested SAI or I2S frequencies, the external I2S clock is
@@ -653,6 +897,50 @@ impl CFGR {
         self.sai2_clk = Some(freq.raw());
         self
     }
+    /// Sets the PLLI2S dividers, instead of computing them from the requested I2S clock.
+    ///
+    /// The I2S clock is the PLLI2S R output, unless it's the same as `I2S_CKIN`. Returns an
+    /// error if a divider is out of range. The VCO frequencies depend on the PLL source clock,
+    /// [`freeze`](Self::freeze) panics if they are out of range.
+    #[cfg(any(
+        feature = "stm32f401",
+        feature = "stm32f405",
+        feature = "stm32f407",
+        feature = "stm32f415",
+        feature = "stm32f417",
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+        feature = "stm32f469",
+        feature = "stm32f479"
+    ))]
+    pub fn plli2s(mut self, config: PllI2sConfig) -> Result<Self, PllConfigError> {
+        config.check()?;
+        self.plli2s = Some(config);
+        Ok(self)
+    }
+
+    /// Sets the PLLSAI dividers, instead of computing them from the requested SAI clock.
+    ///
+    /// The SAI clock is the PLLSAI Q output divided by `div_q`, the LTDC pixel clock
+    /// is the R output divided by `div_r`. Returns an error if a divider is out of range.
+    /// The VCO frequencies depend on the PLL source clock, [`freeze`](Self::freeze) panics
+    /// if they are out of range.
+    #[cfg(any(
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+        feature = "stm32f469",
+        feature = "stm32f479"
+    ))]
+    pub fn pllsai(mut self, config: PllSaiConfig) -> Result<Self, PllConfigError> {
+        config.check()?;
+        self.pllsai = Some(config);
+        Ok(self)
+    }
+
     #[cfg(feature = "stm32f410")]
     #[inline(always)]
     fn pll_setup(&self, pllsrcclk: u32, pllsysclk: Option<u32>) -> PllSetup {
@@ -789,7 +1077,10 @@ impl CFGR {
 
         // We have separate PLLs, but they share the "M" divider.
         let main_pll = MainPll::fast_setup(pllsrcclk, self.hse.is_some(), pllsysclk, self.pll48clk);
-        let i2s_pll = I2sPll::setup_shared_m(pllsrcclk, main_pll.m, i2s_clocks.pll_i2s_clk);
+        let i2s_pll = match self.plli2s {
+            Some(config) => I2sPll::setup_explicit(pllsrcclk, main_pll.m, config),
+            None => I2sPll::setup_shared_m(pllsrcclk, main_pll.m, i2s_clocks.pll_i2s_clk),
+        };
         #[cfg(any(
             feature = "stm32f427",
             feature = "stm32f429",
@@ -798,8 +1089,12 @@ impl CFGR {
             feature = "stm32f469",
             feature = "stm32f479"
         ))]
-        let sai_pll =
-            SaiPll::setup_shared_m(pllsrcclk, main_pll.m.or(i2s_pll.m), sai_clocks.pll_sai_clk);
+        let sai_pll = match self.pllsai {
+            Some(config) => SaiPll::setup_explicit(pllsrcclk, main_pll.m.or(i2s_pll.m), config),
+            None => {
+                SaiPll::setup_shared_m(pllsrcclk, main_pll.m.or(i2s_pll.m), sai_clocks.pll_sai_clk)
+            }
+        };
 
         PllSetup {
             use_pll: main_pll.use_pll,
@@ -905,7 +1200,7 @@ impl CFGR {
         }
     }
 
-    fn flash_setup(sysclk: u32) {
+    fn flash_setup(&self, sysclk: u32) {
         use crate::pac::FLASH;
 
         #[cfg(any(
@@ -933,13 +1228,13 @@ impl CFGR {
         unsafe {
             let flash = &(*FLASH::ptr());
             // Adjust flash wait states
-            flash.acr.modify(|_, w| {
-                w.latency().bits(((sysclk - 1) / flash_latency_step) as u8);
-                w.prften().set_bit();
-                w.icen().set_bit();
-                w.dcen().set_bit()
-            })
+            flash
+                .acr
+                .modify(|_, w| w.latency().bits(((sysclk - 1) / flash_latency_step) as u8))
         }
+        crate::flash::set_prefetch(self.flash_prefetch, self.flash_supply);
+        crate::flash::set_icache(self.flash_icache);
+        crate::flash::set_dcache(self.flash_dcache);
     }
 
     /// Initialises the hardware according to CFGR state returning a Clocks instance.
@@ -1027,7 +1322,7 @@ impl CFGR {
 
         assert!(unchecked || pclk2 <= PCLK2_MAX);
 
-        Self::flash_setup(sysclk);
+        self.flash_setup(sysclk);
 
         if self.hse.is_some() {
             // enable HSE and wait for it to be ready
@@ -1672,3 +1967,25 @@ impl Clocks {
         self.sai2_clk
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hsi_trim_only_touches_hsitrim() {
+        // CR reset value: HSION, HSIRDY, HSITRIM = 16, HSICAL from the factory
+        let cr = 0x0000_ab83;
+        assert_eq!(hsi_trim_cr(cr, 16), cr);
+        assert_eq!(hsi_trim_cr(cr, 0), 0x0000_ab03);
+        assert_eq!(hsi_trim_cr(cr, 31), 0x0000_abfb);
+        // PLLON and the other bits around HSITRIM are kept
+        assert_eq!(hsi_trim_cr(0xffff_ffff, 0), 0xffff_ff07);
+    }
+
+    #[test]
+    fn hsi_trim_clamps() {
+        assert_eq!(hsi_trim_cr(0, 32), 31 << 3);
+        assert_eq!(hsi_trim_cr(0, u8::MAX), 31 << 3);
+    }
+}
@@ -206,7 +206,7 @@ impl MainPll {
         let min_div = u32::max(
             min_div,
             if max != 0 {
-                (vco_out + max - 1) / max
+                vco_out.saturating_add(max - 1) / max
             } else {
                 0
             },
@@ -222,6 +222,100 @@ impl MainPll {
     }
 }
 
+#[cfg(any(
+    feature = "stm32f401",
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479"
+))]
+/// Explicit PLL divider out of its range, see [`PllI2sConfig`] and [`PllSaiConfig`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PllConfigError {
+    /// `N` is not between 50 and 432
+    N,
+    /// `Q` is not between 2 and 15, or is given on a device without `PLLI2SQ`
+    Q,
+    /// `R` is not between 2 and 7
+    R,
+    /// `PLLSAIDIVQ` is not between 1 and 32
+    DivQ,
+    /// `PLLSAIDIVR` is not 2, 4, 8 or 16
+    DivR,
+}
+
+#[cfg(any(
+    feature = "stm32f401",
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479"
+))]
+/// PLLI2S dividers, see [`CFGR::plli2s`](super::CFGR::plli2s)
+///
+/// The VCO input is the PLL source clock divided by the `M` divider shared with the main PLL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PllI2sConfig {
+    /// VCO multiplier, from 50 to 432, the VCO output must be between 100 and 432 MHz
+    pub n: u16,
+    /// I2S clock divider, from 2 to 7
+    pub r: u8,
+    /// SAI clock divider, from 2 to 15, only on STM32F42x/F43x/F469/F479
+    pub q: Option<u8>,
+}
+
+#[cfg(any(
+    feature = "stm32f401",
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479"
+))]
+impl PllI2sConfig {
+    /// Checks the dividers against their register fields
+    pub(super) fn check(&self) -> Result<(), PllConfigError> {
+        if !(50..=432).contains(&self.n) {
+            return Err(PllConfigError::N);
+        }
+        if !(2..=7).contains(&self.r) {
+            return Err(PllConfigError::R);
+        }
+        match self.q {
+            #[cfg(any(
+                feature = "stm32f427",
+                feature = "stm32f429",
+                feature = "stm32f437",
+                feature = "stm32f439",
+                feature = "stm32f469",
+                feature = "stm32f479"
+            ))]
+            Some(q) if (2..=15).contains(&q) => Ok(()),
+            Some(_) => Err(PllConfigError::Q),
+            None => Ok(()),
+        }
+    }
+}
+
 #[cfg(not(feature = "stm32f410"))]
 pub struct I2sPll {
     pub use_pll: bool,
@@ -289,6 +383,48 @@ impl I2sPll {
         pll
     }
 
+    #[cfg(any(
+        feature = "stm32f401",
+        feature = "stm32f405",
+        feature = "stm32f407",
+        feature = "stm32f415",
+        feature = "stm32f417",
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+        feature = "stm32f469",
+        feature = "stm32f479"
+    ))]
+    pub fn setup_explicit(pllsrcclk: u32, m: Option<u32>, config: PllI2sConfig) -> I2sPll {
+        // "m" is None if the main PLL is not in use.
+        let m = m.unwrap_or_else(|| default_m(pllsrcclk));
+        // The dividers are checked by `CFGR::plli2s`
+        let vco = vco_output(pllsrcclk, m, config.n);
+
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.pllcfgr.modify(|_, w| unsafe { w.pllm().bits(m as u8) });
+        rcc.plli2scfgr
+            .modify(|_, w| unsafe { w.plli2sn().bits(config.n).plli2sr().bits(config.r) });
+        #[cfg(any(
+            feature = "stm32f427",
+            feature = "stm32f429",
+            feature = "stm32f437",
+            feature = "stm32f439",
+            feature = "stm32f469",
+            feature = "stm32f479"
+        ))]
+        if let Some(q) = config.q {
+            rcc.plli2scfgr.modify(|_, w| unsafe { w.plli2sq().bits(q) });
+        }
+
+        I2sPll {
+            use_pll: true,
+            m: Some(m),
+            plli2sclk: Some(vco / config.r as u32),
+        }
+    }
+
     fn optimize_fixed_m(pllsrcclk: u32, m: u32, plli2sclk: u32) -> (I2sPll, SingleOutputPll, u32) {
         let (config, real_plli2sclk, error) =
             SingleOutputPll::optimize(pllsrcclk, m, plli2sclk, 2, 7)
@@ -339,6 +475,73 @@ impl I2sPll {
     }
 }
 
+#[cfg(any(
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479"
+))]
+/// PLLSAI dividers, see [`CFGR::pllsai`](super::CFGR::pllsai)
+///
+/// The VCO input is the PLL source clock divided by the `M` divider shared with the main PLL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PllSaiConfig {
+    /// VCO multiplier, from 50 to 432, the VCO output must be between 100 and 432 MHz
+    pub n: u16,
+    /// SAI clock divider, from 2 to 15
+    pub q: u8,
+    /// LTDC clock divider, from 2 to 7
+    pub r: u8,
+    /// Second SAI clock divider (`PLLSAIDIVQ`), from 1 to 32
+    pub div_q: u8,
+    /// LTDC pixel clock divider (`PLLSAIDIVR`), 2, 4, 8 or 16
+    pub div_r: u8,
+}
+
+#[cfg(any(
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479"
+))]
+impl PllSaiConfig {
+    /// Checks the dividers against their register fields
+    pub(super) fn check(&self) -> Result<(), PllConfigError> {
+        if !(50..=432).contains(&self.n) {
+            Err(PllConfigError::N)
+        } else if !(2..=15).contains(&self.q) {
+            Err(PllConfigError::Q)
+        } else if !(2..=7).contains(&self.r) {
+            Err(PllConfigError::R)
+        } else if !(1..=32).contains(&self.div_q) {
+            Err(PllConfigError::DivQ)
+        } else {
+            self.div_r_bits().map(|_| ())
+        }
+    }
+
+    /// `PLLSAIDIVR` field value
+    fn div_r_bits(&self) -> Result<u8, PllConfigError> {
+        match self.div_r {
+            2 => Ok(0b00),
+            4 => Ok(0b01),
+            8 => Ok(0b10),
+            16 => Ok(0b11),
+            _ => Err(PllConfigError::DivR),
+        }
+    }
+
+    /// `PLLSAIDIVQ` field value
+    fn div_q_bits(&self) -> u8 {
+        self.div_q - 1
+    }
+}
+
 #[cfg(any(
     feature = "stm32f427",
     feature = "stm32f429",
@@ -414,6 +617,44 @@ impl SaiPll {
         pll
     }
 
+    #[cfg(any(
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+        feature = "stm32f469",
+        feature = "stm32f479"
+    ))]
+    pub fn setup_explicit(pllsrcclk: u32, m: Option<u32>, config: PllSaiConfig) -> SaiPll {
+        // "m" is None if both other PLLs are not in use.
+        let m = m.unwrap_or_else(|| default_m(pllsrcclk));
+        // The dividers are checked by `CFGR::pllsai`
+        let vco = vco_output(pllsrcclk, m, config.n);
+        let div_r = config.div_r_bits().unwrap_or(0);
+
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.dckcfgr.modify(|_, w| {
+            w.pllsaidivq()
+                .bits(config.div_q_bits())
+                .pllsaidivr()
+                .bits(div_r)
+        });
+        rcc.pllcfgr.modify(|_, w| unsafe { w.pllm().bits(m as u8) });
+        rcc.pllsaicfgr.modify(|_, w| unsafe {
+            w.pllsain()
+                .bits(config.n)
+                .pllsaiq()
+                .bits(config.q)
+                .pllsair()
+                .bits(config.r)
+        });
+
+        SaiPll {
+            use_pll: true,
+            sai_clk: Some(vco / config.q as u32 / config.div_q as u32),
+        }
+    }
+
     fn optimize_fixed_m(
         pllsrcclk: u32,
         m: u32,
@@ -468,6 +709,52 @@ impl SaiPll {
     }
 }
 
+#[cfg(any(
+    feature = "stm32f401",
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479"
+))]
+/// Smallest "M" divider giving a VCO input of at most 2 MHz
+fn default_m(pllsrcclk: u32) -> u32 {
+    (pllsrcclk + 1_999_999) / 2_000_000
+}
+
+#[cfg(any(
+    feature = "stm32f401",
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479"
+))]
+/// Checks the VCO input and output ranges and returns the VCO output frequency
+fn vco_output(pllsrcclk: u32, m: u32, n: u16) -> u32 {
+    let vco_in = pllsrcclk / m;
+    assert!(
+        (950_000..=2_100_000).contains(&vco_in),
+        "PLL VCO input must be between 0.95 and 2.1 MHz"
+    );
+    let vco_out = vco_in * n as u32;
+    assert!(
+        (100_000_000..=432_000_000).contains(&vco_out),
+        "PLL VCO output must be between 100 and 432 MHz"
+    );
+    vco_out
+}
+
 #[cfg(not(feature = "stm32f410"))]
 struct SingleOutputPll {
     m: u8,
@@ -515,3 +802,165 @@ impl SingleOutputPll {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(feature = "stm32f410"))]
+    #[test]
+    fn i2s_pll_exact_output() {
+        let (pll, config, error) = I2sPll::optimize_fixed_m(8_000_000, 8, 86_000_000);
+        assert_eq!(error, 0);
+        assert_eq!(pll.m, Some(8));
+        assert_eq!(pll.plli2sclk, Some(86_000_000));
+        assert_eq!(
+            1_000_000 * config.n as u32 / config.outdiv as u32,
+            86_000_000
+        );
+    }
+
+    #[cfg(feature = "stm32f410")]
+    #[test]
+    fn best_divider_respects_range() {
+        assert_eq!(
+            MainPll::best_divider(384_000_000, 47_880_000, 48_000_000, 48_120_000, 2, 15),
+            Some((8, 48_000_000, 0))
+        );
+        // Clamped to the largest allowed divider
+        assert_eq!(
+            MainPll::best_divider(432_000_000, 0, 10_000_000, u32::MAX, 2, 15),
+            Some((15, 28_800_000, 18_800_000))
+        );
+        // No divider lands within 0.25% of 48 MHz
+        assert_eq!(
+            MainPll::best_divider(100_000_000, 47_880_000, 48_000_000, 48_120_000, 2, 15),
+            None
+        );
+    }
+
+    #[cfg(any(
+        feature = "stm32f401",
+        feature = "stm32f405",
+        feature = "stm32f407",
+        feature = "stm32f415",
+        feature = "stm32f417",
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+        feature = "stm32f469",
+        feature = "stm32f479"
+    ))]
+    #[test]
+    fn plli2s_config_ranges() {
+        let config = PllI2sConfig {
+            n: 192,
+            r: 2,
+            q: None,
+        };
+        assert_eq!(config.check(), Ok(()));
+        assert_eq!(
+            PllI2sConfig { n: 49, ..config }.check(),
+            Err(PllConfigError::N)
+        );
+        assert_eq!(
+            PllI2sConfig { n: 433, ..config }.check(),
+            Err(PllConfigError::N)
+        );
+        assert_eq!(
+            PllI2sConfig { r: 1, ..config }.check(),
+            Err(PllConfigError::R)
+        );
+        assert_eq!(
+            PllI2sConfig { r: 8, ..config }.check(),
+            Err(PllConfigError::R)
+        );
+        assert_eq!(
+            PllI2sConfig {
+                q: Some(16),
+                ..config
+            }
+            .check(),
+            Err(PllConfigError::Q)
+        );
+    }
+
+    #[cfg(any(
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+        feature = "stm32f469",
+        feature = "stm32f479"
+    ))]
+    #[test]
+    fn pllsai_config_encoding() {
+        let config = PllSaiConfig {
+            n: 192,
+            q: 7,
+            r: 4,
+            div_q: 1,
+            div_r: 8,
+        };
+        assert_eq!(config.check(), Ok(()));
+        assert_eq!(config.div_q_bits(), 0);
+        assert_eq!(
+            PllSaiConfig {
+                div_q: 32,
+                ..config
+            }
+            .div_q_bits(),
+            31
+        );
+        for (div_r, bits) in [(2, 0b00), (4, 0b01), (8, 0b10), (16, 0b11)] {
+            assert_eq!(PllSaiConfig { div_r, ..config }.div_r_bits(), Ok(bits));
+        }
+        assert_eq!(
+            PllSaiConfig { div_r: 3, ..config }.check(),
+            Err(PllConfigError::DivR)
+        );
+        assert_eq!(
+            PllSaiConfig { div_q: 0, ..config }.check(),
+            Err(PllConfigError::DivQ)
+        );
+        assert_eq!(
+            PllSaiConfig { q: 1, ..config }.check(),
+            Err(PllConfigError::Q)
+        );
+        assert_eq!(
+            PllSaiConfig { r: 8, ..config }.check(),
+            Err(PllConfigError::R)
+        );
+        assert_eq!(
+            PllSaiConfig { n: 10, ..config }.check(),
+            Err(PllConfigError::N)
+        );
+    }
+
+    #[cfg(any(
+        feature = "stm32f401",
+        feature = "stm32f405",
+        feature = "stm32f407",
+        feature = "stm32f415",
+        feature = "stm32f417",
+        feature = "stm32f427",
+        feature = "stm32f429",
+        feature = "stm32f437",
+        feature = "stm32f439",
+        feature = "stm32f469",
+        feature = "stm32f479"
+    ))]
+    #[test]
+    fn default_m_keeps_vco_input_at_most_2mhz() {
+        assert_eq!(default_m(8_000_000), 4);
+        assert_eq!(default_m(25_000_000), 13);
+        assert_eq!(default_m(16_000_000), 8);
+        for clk in [
+            4_000_000, 8_000_000, 12_000_000, 16_000_000, 25_000_000, 26_000_000,
+        ] {
+            let vco_in = clk / default_m(clk);
+            assert!((950_000..=2_000_000).contains(&vco_in), "{}", clk);
+        }
+    }
+}
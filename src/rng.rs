@@ -7,6 +7,8 @@
 //! Notes:
 //! - It takes 40 periods of `RNG_CLK` to generate a new random value.
 //! - The RNG requires the `PLL48_CLK` to be active ([more details](RngExt::constrain))
+//! - Values can be gathered in the background with [`Rng::listen`] and [`Rng::on_interrupt`]
+//!   called from the `HASH_RNG` interrupt handler, [`Rng::try_read`] returns them first.
 //!
 //! For more details, see reference manual chapter 24.
 //!
@@ -34,6 +36,7 @@ use rand_core::RngCore;
 
 /// Random number generator specific errors
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ErrorKind {
     /// The RNG_CLK was not correctly detected (fRNG_CLK< fHCLK/16).
     /// See CECS in RNG peripheral documentation.
@@ -78,10 +81,26 @@ pub trait RngExt {
     ///
     /// This function will panic if `PLL48_CLK < 1/16 HCLK`.
     fn constrain(self, clocks: &Clocks) -> Rng;
+
+    /// Same as [`constrain`](Self::constrain), but returns [`ErrorKind::ClockError`]
+    /// instead of panicking if `PLL48_CLK < 1/16 HCLK`.
+    fn try_constrain(self, clocks: &Clocks) -> Result<Rng, ErrorKind>;
 }
 
 impl RngExt for RNG {
     fn constrain(self, clocks: &Clocks) -> Rng {
+        self.try_constrain(clocks)
+            .expect("PLL48_CLK must be at least 1/16 HCLK")
+    }
+
+    fn try_constrain(self, clocks: &Clocks) -> Result<Rng, ErrorKind> {
+        // verify the clock configuration is valid
+        let hclk = clocks.hclk();
+        let rng_clk = clocks.pll48clk().unwrap_or_else(|| 0.Hz());
+        if rng_clk < (hclk / 16) {
+            return Err(ErrorKind::ClockError);
+        }
+
         let rcc = unsafe { &*pac::RCC::ptr() };
 
         cortex_m::interrupt::free(|_| {
@@ -89,19 +108,23 @@ impl RngExt for RNG {
             RNG::enable(rcc);
             RNG::reset(rcc);
 
-            // verify the clock configuration is valid
-            let hclk = clocks.hclk();
-            let rng_clk = clocks.pll48clk().unwrap_or_else(|| 0.Hz());
-            assert!(rng_clk >= (hclk / 16));
-
             // enable the RNG peripheral
             self.cr.modify(|_, w| w.rngen().set_bit());
         });
 
-        Rng { rb: self }
+        Ok(Rng {
+            rb: self,
+            discard: false,
+            buffer: [0; BUFFER_SIZE],
+            buffered: 0,
+            listening: false,
+        })
     }
 }
 
+/// Number of values gathered in the background
+const BUFFER_SIZE: usize = 4;
+
 /// Random number provider which provides access to all [rand_core::RngCore]
 /// functions.
 ///
@@ -118,24 +141,93 @@ impl RngExt for RNG {
 /// ```
 pub struct Rng {
     rb: RNG,
+    /// Next value follows a seed error and must not be used
+    discard: bool,
+    buffer: [u32; BUFFER_SIZE],
+    buffered: usize,
+    listening: bool,
 }
 
 impl Rng {
     /// Returns 32 bits of random data from RNDATA, or error.
     /// May fail if, for example RNG_CLK is misconfigured.
     fn next_random_word(&mut self) -> Result<u32, ErrorKind> {
-        loop {
-            let status = self.rb.sr.read();
-            if status.cecs().bit() {
-                return Err(ErrorKind::ClockError);
-            }
-            if status.secs().bit() {
-                return Err(ErrorKind::SeedError);
+        nb::block!(self.try_read())
+    }
+
+    /// Returns 32 bits of random data, gathered in the background first
+    ///
+    /// A seed error restarts the generator and the value following it is discarded.
+    /// A clock error lasts until `RNG_CLK` is fixed.
+    pub fn try_read(&mut self) -> nb::Result<u32, ErrorKind> {
+        if self.buffered > 0 {
+            self.buffered -= 1;
+            let value = self.buffer[self.buffered];
+            if self.listening {
+                self.rb.cr.modify(|_, w| w.ie().set_bit());
             }
-            if status.drdy().bit() {
-                return Ok(self.rb.dr.read().rndata().bits());
+            return Ok(value);
+        }
+        self.read_data()
+    }
+
+    fn read_data(&mut self) -> nb::Result<u32, ErrorKind> {
+        let status = self.rb.sr.read();
+        if status.cecs().bit() {
+            self.rb.sr.modify(|_, w| w.ceis().clear_bit());
+            return Err(nb::Error::Other(ErrorKind::ClockError));
+        }
+        if status.secs().bit() {
+            // Reference manual recovery sequence: clear SEIS and restart the generator
+            self.rb.sr.modify(|_, w| w.seis().clear_bit());
+            self.rb.cr.modify(|_, w| w.rngen().clear_bit());
+            self.rb.cr.modify(|_, w| w.rngen().set_bit());
+            self.discard = true;
+            return Err(nb::Error::Other(ErrorKind::SeedError));
+        }
+        if !status.drdy().bit() {
+            return Err(nb::Error::WouldBlock);
+        }
+        let value = self.rb.dr.read().rndata().bits();
+        if self.discard {
+            self.discard = false;
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(value)
+    }
+
+    /// Enables the data ready and error interrupt to gather values in the background
+    ///
+    /// Note, you will also have to enable the `HASH_RNG` interrupt in the NVIC and call
+    /// [`Rng::on_interrupt`] from its handler.
+    pub fn listen(&mut self) {
+        self.listening = true;
+        self.rb.cr.modify(|_, w| w.ie().set_bit());
+    }
+
+    /// Disables the interrupt
+    pub fn unlisten(&mut self) {
+        self.listening = false;
+        self.rb.cr.modify(|_, w| w.ie().clear_bit());
+    }
+
+    /// Stores a new value, or recovers from an error, to be called from the interrupt handler
+    ///
+    /// The interrupt is disabled while the buffer is full, and enabled again by
+    /// [`Rng::try_read`]. Errors are returned, as `try_read` would.
+    pub fn on_interrupt(&mut self) -> Result<(), ErrorKind> {
+        while self.buffered < BUFFER_SIZE {
+            match self.read_data() {
+                Ok(value) => {
+                    self.buffer[self.buffered] = value;
+                    self.buffered += 1;
+                }
+                Err(nb::Error::WouldBlock) => return Ok(()),
+                Err(nb::Error::Other(e)) => return Err(e),
             }
         }
+        self.rb.cr.modify(|_, w| w.ie().clear_bit());
+        Ok(())
     }
 
     /// Releases ownership of the [RNG](crate::pac::RNG) peripheral object
@@ -154,8 +246,20 @@ impl rng::Read for Rng {
 }
 
 impl RngCore for Rng {
+    /// Returns 32 bits of random data, retrying after a seed error
+    ///
+    /// # Panics
+    ///
+    /// On a clock error, which lasts until `RNG_CLK` is fixed.
     fn next_u32(&mut self) -> u32 {
-        self.next_random_word().unwrap()
+        loop {
+            match self.next_random_word() {
+                Ok(value) => return value,
+                // The generator was restarted and the value following the error discarded
+                Err(ErrorKind::SeedError) => {}
+                Err(e) => panic!("RNG error: {:?}", e),
+            }
+        }
     }
 
     fn next_u64(&mut self) -> u64 {
@@ -182,3 +286,6 @@ impl RngCore for Rng {
         Ok(())
     }
 }
+
+#[cfg(feature = "crypto-rng")]
+impl rand_core::CryptoRng for Rng {}
@@ -96,7 +96,7 @@ impl Rtc<Lse> {
                 result.enable_lse(rcc, mode);
             }
             // Set clock source to LSE.
-            rcc.bdcr.modify(|_, w| w.rtcsel().lse());
+            cortex_m::interrupt::free(|_| rcc.bdcr.modify(|_, w| w.rtcsel().lse()));
             result.enable(rcc);
         }
 
@@ -169,7 +169,7 @@ impl Rtc<Lsi> {
                 result.enable_lsi(rcc);
             }
             // Set clock source to LSI.
-            rcc.bdcr.modify(|_, w| w.rtcsel().lsi());
+            cortex_m::interrupt::free(|_| rcc.bdcr.modify(|_, w| w.rtcsel().lsi()));
             result.enable(rcc);
         }
 
@@ -190,7 +190,7 @@ impl Rtc<Lsi> {
         // Force a reset of the backup domain.
         self.backup_reset(rcc);
         // Enable the LSI.
-        rcc.csr.modify(|_, w| w.lsion().on());
+        cortex_m::interrupt::free(|_| rcc.csr.modify(|_, w| w.lsion().on()));
         while rcc.csr.read().lsirdy().is_not_ready() {}
     }
 }
@@ -442,20 +442,148 @@ impl<CS> Rtc<CS> {
         // than 2 RTCCLK periods: RSF must be cleared by software after the first calendar read.
         self.regs.isr.modify(|_, w| w.rsf().clear_bit());
 
-        let seconds = decode_seconds(&tr);
-        let minutes = decode_minutes(&tr);
-        let hours = decode_hours(&tr);
-        let day = decode_day(&dr);
-        let month = decode_month(&dr);
-        let year = decode_year(&dr);
+        decode_datetime(&tr, &dr)
+    }
 
-        PrimitiveDateTime::new(
-            Date::from_calendar_date(year.into(), month.try_into().unwrap(), day).unwrap(),
-            Time::from_hms(hours, minutes, seconds).unwrap(),
-        )
+    /// Get the date and time with the microseconds elapsed in the current second
+    pub fn now_subsec(&mut self) -> (PrimitiveDateTime, u32) {
+        let prediv_s = u32::from(self.regs.prer.read().prediv_s().bits());
+        while self.regs.isr.read().rsf().bit_is_clear() {}
+
+        // Reading SSR locks TR and DR until DR is read, the three values belong together.
+        let ss = self.regs.ssr.read().ss().bits() as u32;
+        let tr = self.regs.tr.read();
+        let dr = self.regs.dr.read();
+        self.regs.isr.modify(|_, w| w.rsf().clear_bit());
+
+        let date = decode_datetime(&tr, &dr);
+        match subsec_micros(ss, prediv_s) {
+            (false, micros) => (date, micros),
+            (true, micros) => (date - time::Duration::SECOND, micros),
+        }
+    }
+
+    /// Set the date and time, `micros` being already elapsed in the current second
+    ///
+    /// The subsecond part is set with the shift control register, its resolution is one
+    /// period of the synchronous prescaler output.
+    pub fn set_time_precise(&mut self, date: &PrimitiveDateTime, micros: u32) -> Result<(), Error> {
+        if micros >= 1_000_000 {
+            return Err(Error::InvalidInputData);
+        }
+        self.set_datetime(date)?;
+        if micros > 0 {
+            self.shift_time(micros as i32)?;
+        }
+        Ok(())
+    }
+
+    /// Shifts the calendar by `micros` microseconds, forward when positive, without
+    /// stopping it
+    ///
+    /// The offset is rounded to periods of the synchronous prescaler output, and has to be
+    /// within one second.
+    pub fn shift_time(&mut self, micros: i32) -> Result<(), Error> {
+        if !(-999_999..=999_999).contains(&micros) {
+            return Err(Error::InvalidInputData);
+        }
+        // The shift is done in fractions of the synchronous prescaler, it needs a
+        // reference clock detection off.
+        if self.regs.cr.read().refckon().bit_is_set() {
+            return Err(Error::InvalidInputData);
+        }
+        let prediv_s = u32::from(self.regs.prer.read().prediv_s().bits());
+        let shiftr = match shift_bits(micros, prediv_s) {
+            Some(shiftr) => shiftr,
+            None => return Ok(()),
+        };
+
+        // A pending shift has to be done before writing SHIFTR
+        while self.regs.isr.read().shpf().bit_is_set() {}
+        // Unlike `modify`, the calendar keeps running
+        self.regs.wpr.write(|w| unsafe { w.bits(0xCA) });
+        self.regs.wpr.write(|w| unsafe { w.bits(0x53) });
+        self.regs.shiftr.write(|w| unsafe { w.bits(shiftr) });
+        self.regs.wpr.write(|w| unsafe { w.bits(0xFF) });
+        while self.regs.isr.read().shpf().bit_is_set() {}
+        Ok(())
+    }
+
+    /// Reads the backup register `index`, from 0 to 19
+    ///
+    /// The backup registers keep their value across resets and in Standby mode, as long as
+    /// `VBAT` or `VDD` is present.
+    pub fn read_backup_register(&self, index: usize) -> u32 {
+        self.regs.bkpr[index].read().bits()
+    }
+
+    /// Writes the backup register `index`, from 0 to 19
+    pub fn write_backup_register(&mut self, index: usize, value: u32) {
+        self.regs.bkpr[index].write(|w| w.bits(value));
+    }
+
+    /// Set the date and time using chrono::NaiveDateTime, the year from 1970 to 2069
+    ///
+    /// Nanoseconds are dropped.
+    #[cfg(feature = "chrono")]
+    pub fn set_naive_datetime(&mut self, date: &chrono::NaiveDateTime) -> Result<(), Error> {
+        self.set_datetime(&naive_to_primitive(date)?)
+    }
+
+    /// Get the date and time as chrono::NaiveDateTime
+    #[cfg(feature = "chrono")]
+    pub fn get_naive_datetime(&mut self) -> chrono::NaiveDateTime {
+        primitive_to_naive(&self.get_datetime(), 0)
+    }
+
+    /// Get the date and time as chrono::NaiveDateTime, with microseconds
+    #[cfg(feature = "chrono")]
+    pub fn now_naive_subsec(&mut self) -> chrono::NaiveDateTime {
+        let (date, micros) = self.now_subsec();
+        primitive_to_naive(&date, micros)
     }
 }
 
+#[cfg(feature = "chrono")]
+fn naive_to_primitive(date: &chrono::NaiveDateTime) -> Result<PrimitiveDateTime, Error> {
+    use chrono::{Datelike, Timelike};
+
+    if !(1970..=2069).contains(&date.year()) {
+        return Err(Error::InvalidInputData);
+    }
+    let month = (date.month() as u8)
+        .try_into()
+        .map_err(|_| Error::InvalidInputData)?;
+    let day = Date::from_calendar_date(date.year(), month, date.day() as u8)
+        .map_err(|_| Error::InvalidInputData)?;
+    // A leap second is kept in the last second of the minute
+    let time = Time::from_hms(
+        date.hour() as u8,
+        date.minute() as u8,
+        date.second().min(59) as u8,
+    )
+    .map_err(|_| Error::InvalidInputData)?;
+    Ok(PrimitiveDateTime::new(day, time))
+}
+
+#[cfg(feature = "chrono")]
+fn primitive_to_naive(date: &PrimitiveDateTime, micros: u32) -> chrono::NaiveDateTime {
+    chrono::NaiveDate::from_ymd_opt(
+        date.year(),
+        u8::from(date.month()).into(),
+        date.day().into(),
+    )
+    .and_then(|d| {
+        d.and_hms_micro_opt(
+            date.hour().into(),
+            date.minute().into(),
+            date.second().into(),
+            micros,
+        )
+    })
+    .unwrap()
+}
+
 // Two 32-bit registers (RTC_TR and RTC_DR) contain the seconds, minutes, hours (12- or 24-hour format), day (day
 // of week), date (day of month), month, and year, expressed in binary coded decimal format
 // (BCD). The sub-seconds value is also available in binary format.
@@ -509,8 +637,99 @@ fn decode_month(dr: &dr::R) -> u8 {
     bcd2_decode(mt, dr.mu().bits()) as u8
 }
 
+fn decode_datetime(tr: &tr::R, dr: &dr::R) -> PrimitiveDateTime {
+    let seconds = decode_seconds(tr);
+    let minutes = decode_minutes(tr);
+    let hours = decode_hours(tr);
+    let day = decode_day(dr);
+    let month = decode_month(dr);
+    let year = decode_year(dr);
+
+    PrimitiveDateTime::new(
+        Date::from_calendar_date(year.into(), month.try_into().unwrap(), day).unwrap(),
+        Time::from_hms(hours, minutes, seconds).unwrap(),
+    )
+}
+
+/// Microseconds elapsed in the current second for the subsecond counter `ss`, and
+/// whether the calendar is one second ahead
+///
+/// SS counts down from PREDIV_S. A shift delaying the clock adds SUBFS to SS, which can
+/// then be above PREDIV_S until the next second increment. The time is then one second
+/// less than TR and DR show.
+fn subsec_micros(ss: u32, prediv_s: u32) -> (bool, u32) {
+    let ticks = u64::from(prediv_s + 1);
+    let (ahead, elapsed) = if ss > prediv_s {
+        (true, u64::from(2 * prediv_s + 1 - ss))
+    } else {
+        (false, u64::from(prediv_s - ss))
+    };
+    (ahead, (elapsed * 1_000_000 / ticks) as u32)
+}
+
+/// `SHIFTR` value shifting the calendar by `micros`, `None` if it rounds to no shift
+fn shift_bits(micros: i32, prediv_s: u32) -> Option<u32> {
+    let ticks = (u64::from(micros.unsigned_abs()) * u64::from(prediv_s + 1) / 1_000_000) as u32;
+    // Advancing adds one second then delays by the rest of it
+    let (add1s, subfs) = if micros > 0 {
+        (true, prediv_s + 1 - ticks)
+    } else {
+        (false, ticks)
+    };
+    if subfs == 0 || subfs > prediv_s {
+        None
+    } else {
+        Some((u32::from(add1s) << 31) | subfs)
+    }
+}
+
 #[inline(always)]
 fn decode_year(dr: &dr::R) -> u16 {
     let year = bcd2_decode(dr.yt().bits(), dr.yu().bits()) + 1970; // 1970-01-01 is the epoch begin.
     year as u16
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subsec_micros_counts_down() {
+        assert_eq!(subsec_micros(255, 255), (false, 0));
+        assert_eq!(subsec_micros(127, 255), (false, 500_000));
+        assert_eq!(subsec_micros(0, 255), (false, 996_093));
+    }
+
+    #[test]
+    fn subsec_micros_after_delay_shift() {
+        // A shift of 64 ticks just after a second increment, the previous second is not over
+        assert_eq!(subsec_micros(255 + 64, 255), (true, 750_000));
+        assert_eq!(subsec_micros(256, 255), (true, 996_093));
+    }
+
+    #[test]
+    fn subsec_micros_large_prescaler() {
+        assert_eq!(subsec_micros(0, 32767), (false, 999_969));
+        assert_eq!(subsec_micros(16383, 32767), (false, 500_000));
+        assert_eq!(subsec_micros(32767 + 16384, 32767), (true, 500_000));
+    }
+
+    #[test]
+    fn shift_bits_delay_and_advance() {
+        // Delay by a quarter second
+        assert_eq!(shift_bits(-250_000, 255), Some(64));
+        // Advance by a quarter second, one second added then three quarters delayed
+        assert_eq!(shift_bits(250_000, 255), Some(1 << 31 | 192));
+        // Below one tick
+        assert_eq!(shift_bits(-1_000, 255), None);
+        assert_eq!(shift_bits(1_000, 255), None);
+    }
+
+    #[test]
+    fn shift_bits_large_prescaler() {
+        assert_eq!(shift_bits(-999_999, 32767), Some(32767));
+        assert_eq!(shift_bits(-500_000, 32767), Some(16384));
+        assert_eq!(shift_bits(999_999, 32767), Some(1 << 31 | 1));
+        assert_eq!(shift_bits(-999_999, 16383), Some(16383));
+    }
+}
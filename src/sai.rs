@@ -0,0 +1,768 @@
+//! Serial Audio Interface (SAI)
+//!
+//! Each SAI has two independent sub-blocks, A and B, each one a transmitter or a receiver
+//! for I2S, left/right justified, TDM or S/PDIF audio. A sub-block can be synchronous with
+//! the other one, sharing its bit clock and frame sync, for simultaneous playback and
+//! capture with a single set of clock pins.
+//!
+//! The SAI kernel clock comes from the PLLSAI or PLLI2S, set it up with
+//! `CFGR::saia_clk`/`saib_clk` (`sai1_clk`/`sai2_clk` on STM32F446). Sample rates are
+//! derived from it with a master clock of 256 × the sample rate, so the kernel clock should
+//! be a multiple of 256 × the sample rate, e.g. 49.152 MHz for 48 kHz.
+//!
+//! Pins are not checked, they have to be set to the SAI alternate function (AF6 on most
+//! devices) before enabling the sub-block.
+//!
+//! # Example
+//!
+//! 16-bit stereo playback with block A as master and capture with block B synchronous:
+//!
+//! ```ignore
+//! let (mut tx, mut rx) = Sai::new(dp.SAI).split();
+//! tx.configure(Config::default().sample_rate(48.kHz()), &clocks)?;
+//! rx.configure(
+//!     Config::default()
+//!         .mode(Mode::SlaveRx)
+//!         .synchronization(Synchronization::Internal),
+//!     &clocks,
+//! )?;
+//! // The synchronous slave is enabled first, it starts with the master's first frame
+//! rx.enable();
+//! tx.enable();
+//! ```
+
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+use crate::dma::traits::{DMASet, PeriAddress};
+use crate::dma::{MemoryToPeripheral, PeripheralToMemory, Stream1, Stream3, Stream4, Stream5};
+#[cfg(feature = "sai2")]
+use crate::dma::{Stream6, Stream7};
+// Some PACs number the first instance
+#[cfg(not(any(feature = "stm32f427", feature = "stm32f437", feature = "stm32f446")))]
+use crate::pac::{sai, SAI};
+#[cfg(any(feature = "stm32f427", feature = "stm32f437", feature = "stm32f446"))]
+use crate::pac::{sai1 as sai, SAI1 as SAI};
+use crate::pac::{DMA2, RCC};
+use crate::rcc::{Clocks, Enable, Reset};
+use crate::time::Hertz;
+
+/// Largest deviation of the generated sample rate from the requested one, in ‰
+const SAMPLE_RATE_TOLERANCE: u64 = 10;
+
+/// SAI errors
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The kernel clock of the sub-block was not set up in `CFGR`
+    NoKernelClock,
+    /// The sample rate cannot be derived from the kernel clock within 1 %, or is 0
+    SampleRate,
+    /// The frame is not 8 to 256 bits long, or not a power of two in master mode
+    FrameLength,
+    /// Only slaves can be synchronous with the other sub-block
+    SynchronousMaster,
+    /// The FIFO overflowed (receiver) or ran empty (transmitter)
+    OverrunUnderrun,
+    /// Frame sync came early or late, usually a clock configuration mismatch
+    FrameSync,
+    /// The frame length does not match the master clock divider
+    WrongClock,
+}
+
+/// Audio direction and clock ownership of a sub-block
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Mode {
+    /// Transmitter generating the clocks
+    MasterTx = 0b00,
+    /// Receiver generating the clocks
+    MasterRx = 0b01,
+    /// Transmitter clocked by the pins or the other sub-block
+    SlaveTx = 0b10,
+    /// Receiver clocked by the pins or the other sub-block
+    SlaveRx = 0b11,
+}
+
+impl Mode {
+    fn is_master(self) -> bool {
+        matches!(self, Self::MasterTx | Self::MasterRx)
+    }
+
+    fn is_tx(self) -> bool {
+        matches!(self, Self::MasterTx | Self::SlaveTx)
+    }
+}
+
+/// Audio protocol
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Protocol {
+    /// Philips I2S, 2 slots with frame sync low for the left one, one bit ahead of the data
+    I2s,
+    /// 2 slots with frame sync high for the left one, data aligned with the start of the slot
+    MsbJustified,
+    /// 2 slots with frame sync high for the left one, data aligned with the end of the slot
+    LsbJustified,
+    /// `slots` slots (1 to 16) after a one bit frame sync pulse, e.g. DSP/PCM or TDM
+    Tdm { slots: u8 },
+    /// S/PDIF output, transmitter only
+    ///
+    /// The frame and slot settings are ignored, samples are 24 bits.
+    Spdif,
+}
+
+/// Sample size
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataSize {
+    Bits8 = 0b010,
+    Bits10 = 0b011,
+    Bits16 = 0b100,
+    Bits20 = 0b101,
+    Bits24 = 0b110,
+    Bits32 = 0b111,
+}
+
+impl DataSize {
+    fn bits(self) -> u32 {
+        match self {
+            Self::Bits8 => 8,
+            Self::Bits10 => 10,
+            Self::Bits16 => 16,
+            Self::Bits20 => 20,
+            Self::Bits24 => 24,
+            Self::Bits32 => 32,
+        }
+    }
+}
+
+/// Slot size
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SlotSize {
+    /// Same as the data size
+    Data = 0b00,
+    Bits16 = 0b01,
+    Bits32 = 0b10,
+}
+
+/// FIFO level raising the FIFO request
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FifoThreshold {
+    Empty = 0b000,
+    Quarter = 0b001,
+    Half = 0b010,
+    ThreeQuarters = 0b011,
+    Full = 0b100,
+}
+
+/// Clock source of a sub-block
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Synchronization {
+    /// Own clocks, from the kernel clock or the pins
+    Asynchronous = 0b00,
+    /// Clocks of the other sub-block of the same SAI
+    Internal = 0b01,
+}
+
+/// Sub-block configuration
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    mode: Mode,
+    protocol: Protocol,
+    data_size: DataSize,
+    slot_size: SlotSize,
+    mono: bool,
+    sample_rate: Hertz,
+    fifo_threshold: FifoThreshold,
+    synchronization: Synchronization,
+}
+
+impl Default for Config {
+    /// 48 kHz 16-bit stereo I2S master transmitter
+    fn default() -> Self {
+        Self {
+            mode: Mode::MasterTx,
+            protocol: Protocol::I2s,
+            data_size: DataSize::Bits16,
+            slot_size: SlotSize::Data,
+            mono: false,
+            sample_rate: Hertz::from_raw(48_000),
+            fifo_threshold: FifoThreshold::Quarter,
+            synchronization: Synchronization::Asynchronous,
+        }
+    }
+}
+
+impl Config {
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn data_size(mut self, data_size: DataSize) -> Self {
+        self.data_size = data_size;
+        self
+    }
+
+    pub fn slot_size(mut self, slot_size: SlotSize) -> Self {
+        self.slot_size = slot_size;
+        self
+    }
+
+    /// Transmits each sample in both slots of a stereo frame, or receives only the first slot
+    pub fn mono(mut self, mono: bool) -> Self {
+        self.mono = mono;
+        self
+    }
+
+    /// Sample rate generated in master mode
+    pub fn sample_rate(mut self, sample_rate: Hertz) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn fifo_threshold(mut self, fifo_threshold: FifoThreshold) -> Self {
+        self.fifo_threshold = fifo_threshold;
+        self
+    }
+
+    pub fn synchronization(mut self, synchronization: Synchronization) -> Self {
+        self.synchronization = synchronization;
+        self
+    }
+}
+
+/// Sub-block interrupt events
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// FIFO overrun or underrun
+    OverrunUnderrun = 0,
+    /// A receiver got the configured number of silent frames
+    MuteDetected = 1,
+    /// Frame length mismatching the master clock divider
+    WrongClock = 2,
+    /// The FIFO level crossed the threshold, cleared by reading or writing data
+    FifoRequest = 3,
+    /// AC'97 codec not ready
+    CodecNotReady = 4,
+    /// Frame sync came before the end of the frame
+    AnticipatedFrameSync = 5,
+    /// Frame sync came after the end of the frame
+    LateFrameSync = 6,
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Sub-block A or B
+pub trait Block: sealed::Sealed {
+    #[doc(hidden)]
+    const IS_B: bool;
+}
+
+/// Sub-block A
+pub struct A;
+/// Sub-block B
+pub struct B;
+
+impl sealed::Sealed for A {}
+impl sealed::Sealed for B {}
+impl Block for A {
+    const IS_B: bool = false;
+}
+impl Block for B {
+    const IS_B: bool = true;
+}
+
+/// SAI peripheral
+pub trait Instance: crate::Sealed + Deref<Target = sai::RegisterBlock> + Enable + Reset {
+    #[doc(hidden)]
+    fn ptr() -> *const sai::RegisterBlock;
+
+    /// Kernel clock of sub-block A (`b == false`) or B
+    fn kernel_clock(clocks: &Clocks, b: bool) -> Option<Hertz>;
+}
+
+#[cfg(not(feature = "stm32f446"))]
+impl Instance for SAI {
+    fn ptr() -> *const sai::RegisterBlock {
+        SAI::ptr()
+    }
+
+    fn kernel_clock(clocks: &Clocks, b: bool) -> Option<Hertz> {
+        if b {
+            clocks.saib_clk()
+        } else {
+            clocks.saia_clk()
+        }
+    }
+}
+
+#[cfg(feature = "stm32f446")]
+impl Instance for SAI {
+    fn ptr() -> *const sai::RegisterBlock {
+        SAI::ptr()
+    }
+
+    fn kernel_clock(clocks: &Clocks, _b: bool) -> Option<Hertz> {
+        clocks.sai1_clk()
+    }
+}
+
+#[cfg(feature = "sai2")]
+impl Instance for crate::pac::SAI2 {
+    fn ptr() -> *const sai::RegisterBlock {
+        crate::pac::SAI2::ptr()
+    }
+
+    fn kernel_clock(clocks: &Clocks, _b: bool) -> Option<Hertz> {
+        clocks.sai2_clk()
+    }
+}
+
+/// SAI peripheral, to be split into its sub-blocks
+pub struct Sai<SAI> {
+    sai: SAI,
+}
+
+impl<SAI: Instance> Sai<SAI> {
+    /// Enables and resets the SAI
+    pub fn new(sai: SAI) -> Self {
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            SAI::enable(rcc);
+            SAI::reset(rcc);
+        }
+        Self { sai }
+    }
+
+    /// Splits the SAI into its two sub-blocks
+    pub fn split(self) -> (SubBlock<SAI, A>, SubBlock<SAI, B>) {
+        (SubBlock::new(Some(self.sai)), SubBlock::new(None))
+    }
+
+    /// Joins the sub-blocks back after [`split`](Self::split), e.g. to
+    /// [`release`](Self::release) the SAI
+    pub fn join(a: SubBlock<SAI, A>, b: SubBlock<SAI, B>) -> Self {
+        drop(b);
+        Self {
+            // Sub-block A always holds the peripheral
+            sai: a.sai.unwrap(),
+        }
+    }
+
+    /// Releases the SAI peripheral
+    pub fn release(self) -> SAI {
+        self.sai
+    }
+}
+
+/// One audio sub-block of `SAI`
+pub struct SubBlock<SAI, BLOCK> {
+    sai: Option<SAI>,
+    sample_rate: Option<Hertz>,
+    _block: PhantomData<BLOCK>,
+}
+
+impl<SAI: Instance, BLOCK: Block> SubBlock<SAI, BLOCK> {
+    fn new(sai: Option<SAI>) -> Self {
+        Self {
+            sai,
+            sample_rate: None,
+            _block: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    fn regs(&self) -> &sai::CH {
+        // NOTE(unsafe) the sub-block registers are only accessed through this sub-block
+        let sai = unsafe { &*SAI::ptr() };
+        if BLOCK::IS_B {
+            sai.chb()
+        } else {
+            sai.cha()
+        }
+    }
+
+    /// Disables the sub-block and applies `config`
+    ///
+    /// Returns the actual sample rate in master mode, which has to be within 1 % of
+    /// the requested one.
+    pub fn configure(&mut self, config: Config, clocks: &Clocks) -> Result<Option<Hertz>, Error> {
+        if config.synchronization == Synchronization::Internal && config.mode.is_master() {
+            return Err(Error::SynchronousMaster);
+        }
+        let spdif = config.protocol == Protocol::Spdif;
+
+        let data_bits = config.data_size.bits();
+        let slot_bits = match config.slot_size {
+            SlotSize::Data => data_bits,
+            SlotSize::Bits16 => 16,
+            SlotSize::Bits32 => 32,
+        };
+        if slot_bits < data_bits {
+            return Err(Error::FrameLength);
+        }
+        let slots = match config.protocol {
+            Protocol::Tdm { slots } if (1..=16).contains(&slots) => u32::from(slots),
+            Protocol::Tdm { .. } => return Err(Error::FrameLength),
+            _ => 2,
+        };
+        let frame_bits = slot_bits * slots;
+        if !spdif
+            && (!(8..=256).contains(&frame_bits)
+                || (config.mode.is_master() && !frame_bits.is_power_of_two()))
+        {
+            return Err(Error::FrameLength);
+        }
+
+        let (mckdiv, sample_rate) = if config.mode.is_master() {
+            let kernel = SAI::kernel_clock(clocks, BLOCK::IS_B).ok_or(Error::NoKernelClock)?;
+            // S/PDIF sends 2 × 32 biphase bits per sample, otherwise MCLK is 256 × FS
+            let per_sample = if spdif { 128 } else { 256 };
+            let (mckdiv, rate) =
+                master_clock_divider(kernel.raw(), config.sample_rate.raw(), per_sample)?;
+            (mckdiv, Some(Hertz::from_raw(rate)))
+        } else {
+            (0, None)
+        };
+
+        self.disable();
+
+        let data_size = if spdif {
+            DataSize::Bits24
+        } else {
+            config.data_size
+        };
+        let prtcfg = if spdif { 0b01 } else { 0b00 };
+        let regs = self.regs();
+        regs.cr1.write(|w| unsafe {
+            w.mode()
+                .bits(config.mode as u8)
+                .prtcfg()
+                .bits(prtcfg)
+                .ds()
+                .bits(data_size as u8)
+                // Transmitters drive on the rising edge, receivers sample on the falling edge
+                .ckstr()
+                .bit(!config.mode.is_tx())
+                .syncen()
+                .bits(config.synchronization as u8)
+                .mono()
+                .bit(config.mono)
+                .mckdiv()
+                .bits(mckdiv)
+        });
+        regs.cr2
+            .write(|w| unsafe { w.fth().bits(config.fifo_threshold as u8).fflush().set_bit() });
+
+        // (FSALL, FSDEF, FSPOL, FSOFF)
+        let (fsall, fsdef, fspol, fsoff) = match config.protocol {
+            Protocol::I2s => (slot_bits - 1, true, false, true),
+            Protocol::MsbJustified | Protocol::LsbJustified => (slot_bits - 1, true, true, false),
+            Protocol::Tdm { .. } => (0, false, true, true),
+            Protocol::Spdif => (0, false, false, false),
+        };
+        regs.frcr.write(|w| unsafe {
+            w.frl()
+                .bits((frame_bits - 1) as u8)
+                .fsall()
+                .bits(fsall as u8)
+                .fsdef()
+                .bit(fsdef)
+                .fspol()
+                .bit(fspol)
+                .fsoff()
+                .bit(fsoff)
+        });
+
+        let fboff = if config.protocol == Protocol::LsbJustified {
+            slot_bits - data_bits
+        } else {
+            0
+        };
+        let sloten = (1u32 << slots) - 1;
+        regs.slotr.write(|w| unsafe {
+            w.fboff()
+                .bits(fboff as u8)
+                .slotsz()
+                .bits(config.slot_size as u8)
+                .nbslot()
+                .bits((slots - 1) as u8)
+                .sloten()
+                .bits(sloten as u16)
+        });
+
+        self.sample_rate = sample_rate;
+        Ok(sample_rate)
+    }
+
+    /// Sample rate generated in master mode, after [`configure`](Self::configure)
+    pub fn sample_rate(&self) -> Option<Hertz> {
+        self.sample_rate
+    }
+
+    /// Starts the audio transfer
+    ///
+    /// A synchronous sub-block has to be enabled before the sub-block it follows.
+    pub fn enable(&mut self) {
+        self.regs().cr1.modify(|_, w| w.saien().set_bit());
+    }
+
+    /// Stops the audio transfer at the end of the current frame and waits for it
+    pub fn disable(&mut self) {
+        let regs = self.regs();
+        regs.cr1.modify(|_, w| w.saien().clear_bit());
+        while regs.cr1.read().saien().bit_is_set() {}
+    }
+
+    /// Returns `true` if the sub-block is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.regs().cr1.read().saien().bit_is_set()
+    }
+
+    /// Enables DMA requests at the FIFO threshold
+    pub fn enable_dma(&mut self, enable: bool) {
+        self.regs().cr1.modify(|_, w| w.dmaen().bit(enable));
+    }
+
+    /// Empties the FIFO, the sub-block has to be disabled
+    pub fn flush(&mut self) {
+        self.regs().cr2.modify(|_, w| w.fflush().set_bit());
+    }
+
+    /// Number of FIFO eighths in use: 0 for empty, 1 to 4 for up to a quarter, half,
+    /// three quarters or less than full, 5 for full
+    pub fn fifo_level(&self) -> u8 {
+        self.regs().sr.read().flvl().bits()
+    }
+
+    /// Transmits silence instead of the samples, either zeros or the last sample
+    /// (`last_value`)
+    pub fn set_mute(&mut self, mute: bool, last_value: bool) {
+        self.regs()
+            .cr2
+            .modify(|_, w| w.mute().bit(mute).muteval().bit(last_value));
+    }
+
+    /// Raises [`Event::MuteDetected`] after `frames` (up to 63) silent frames are received
+    pub fn set_mute_detection(&mut self, frames: u8) {
+        self.regs()
+            .cr2
+            .modify(|_, w| unsafe { w.mutecnt().bits(frames.min(63)) });
+    }
+
+    /// Starts listening for an interrupt `event`
+    pub fn listen(&mut self, event: Event) {
+        self.regs()
+            .im
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << event as u32)) });
+    }
+
+    /// Stops listening for an interrupt `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.regs()
+            .im
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << event as u32)) });
+    }
+
+    /// Returns `true` if the `event` flag is set
+    pub fn is_pending(&self, event: Event) -> bool {
+        self.regs().sr.read().bits() & (1 << event as u32) != 0
+    }
+
+    /// Clears the `event` flag
+    ///
+    /// [`Event::FifoRequest`] is cleared by reading or writing samples instead.
+    pub fn clear_interrupt(&mut self, event: Event) {
+        self.regs()
+            .clrfr
+            .write(|w| unsafe { w.bits(1 << event as u32) });
+    }
+
+    /// Returns the FIFO level if there is no error
+    fn check_errors(&mut self) -> Result<u8, Error> {
+        let regs = self.regs();
+        let sr = regs.sr.read();
+        if sr.ovrudr().bit_is_set() {
+            regs.clrfr.write(|w| w.covrudr().set_bit());
+            Err(Error::OverrunUnderrun)
+        } else if sr.afsdet().bit_is_set() || sr.lfsdet().bit_is_set() {
+            regs.clrfr
+                .write(|w| w.cafsdet().set_bit().clfsdet().set_bit());
+            Err(Error::FrameSync)
+        } else if sr.wckcfg().bit_is_set() {
+            regs.clrfr.write(|w| w.cwckcfg().set_bit());
+            Err(Error::WrongClock)
+        } else {
+            Ok(sr.flvl().bits())
+        }
+    }
+
+    /// Queues a sample for transmission, aligned on the least significant bit
+    pub fn write(&mut self, sample: u32) -> nb::Result<(), Error> {
+        if self.check_errors()? == 5 {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.regs().dr.write(|w| unsafe { w.bits(sample) });
+        Ok(())
+    }
+
+    /// Reads a received sample, aligned on the least significant bit
+    pub fn read(&mut self) -> nb::Result<u32, Error> {
+        if self.check_errors()? == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(self.regs().dr.read().bits())
+    }
+}
+
+/// Returns MCKDIV and the sample rate closest to `sample_rate`, with a master clock of
+/// `per_sample` × the sample rate derived from the `kernel` clock
+///
+/// MCKDIV of 0 divides by 1, other values by 2 × MCKDIV.
+fn master_clock_divider(
+    kernel: u32,
+    sample_rate: u32,
+    per_sample: u32,
+) -> Result<(u8, u32), Error> {
+    let target = u64::from(sample_rate) * u64::from(per_sample);
+    if target == 0 {
+        return Err(Error::SampleRate);
+    }
+    let kernel = u64::from(kernel);
+    let mut mckdiv = ((kernel + target) / (2 * target)).max(1);
+    if mckdiv > 15 {
+        return Err(Error::SampleRate);
+    }
+    if kernel.abs_diff(target) <= kernel.abs_diff(2 * mckdiv * target) {
+        mckdiv = 0;
+    }
+    let divider = if mckdiv == 0 { 1 } else { 2 * mckdiv };
+    let rate = kernel / divider / u64::from(per_sample);
+    if rate.abs_diff(u64::from(sample_rate)) * 1000 > u64::from(sample_rate) * SAMPLE_RATE_TOLERANCE
+    {
+        return Err(Error::SampleRate);
+    }
+    Ok((mckdiv as u8, rate as u32))
+}
+
+/// DMA reads and writes samples from and to the data register, `SAI_xDR`
+///
+/// Samples are 32-bit words aligned on the least significant bit.
+unsafe impl<SAI: Instance, BLOCK: Block> PeriAddress for SubBlock<SAI, BLOCK> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        &self.regs().dr as *const _ as u32
+    }
+
+    type MemSize = u32;
+}
+
+macro_rules! dma_map {
+    ($($SAI:ty, $BLOCK:ty => ($Stream:ident, $C:literal),)+) => {
+        $(
+            unsafe impl DMASet<$Stream<DMA2>, $C, MemoryToPeripheral> for SubBlock<$SAI, $BLOCK> {}
+            unsafe impl DMASet<$Stream<DMA2>, $C, PeripheralToMemory> for SubBlock<$SAI, $BLOCK> {}
+        )+
+    };
+}
+
+dma_map!(
+    SAI, A => (Stream1, 0),
+    SAI, A => (Stream3, 0),
+    SAI, B => (Stream4, 1),
+    SAI, B => (Stream5, 0),
+);
+
+#[cfg(feature = "sai2")]
+dma_map!(
+    crate::pac::SAI2, A => (Stream4, 3),
+    crate::pac::SAI2, B => (Stream6, 3),
+    crate::pac::SAI2, B => (Stream7, 0),
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_divider() {
+        // 49.152 MHz = 256 × 48 kHz × 4
+        assert_eq!(
+            master_clock_divider(49_152_000, 48_000, 256),
+            Ok((2, 48_000))
+        );
+        assert_eq!(
+            master_clock_divider(12_288_000, 48_000, 256),
+            Ok((0, 48_000))
+        );
+        assert_eq!(
+            master_clock_divider(49_152_000, 8_000, 256),
+            Ok((12, 8_000))
+        );
+        // S/PDIF
+        assert_eq!(
+            master_clock_divider(49_152_000, 48_000, 128),
+            Ok((4, 48_000))
+        );
+    }
+
+    #[test]
+    fn within_tolerance() {
+        // 11.29 MHz / 256 = 44.1 kHz - 0.003 %
+        assert_eq!(
+            master_clock_divider(11_289_000, 44_100, 256),
+            Ok((0, 44_097))
+        );
+    }
+
+    #[test]
+    fn beyond_tolerance() {
+        // 49.152 MHz / 4 / 256 = 48 kHz, 8.8 % above 44.1 kHz
+        assert_eq!(
+            master_clock_divider(49_152_000, 44_100, 256),
+            Err(Error::SampleRate)
+        );
+        // Kernel clock slower than the master clock
+        assert_eq!(
+            master_clock_divider(8_000_000, 48_000, 256),
+            Err(Error::SampleRate)
+        );
+        // Divider above 2 × 15
+        assert_eq!(
+            master_clock_divider(49_152_000, 4_000, 256),
+            Err(Error::SampleRate)
+        );
+    }
+
+    #[test]
+    fn zero_sample_rate() {
+        assert_eq!(
+            master_clock_divider(49_152_000, 0, 256),
+            Err(Error::SampleRate)
+        );
+    }
+
+    #[test]
+    fn huge_sample_rate() {
+        assert_eq!(
+            master_clock_divider(49_152_000, u32::MAX, 256),
+            Err(Error::SampleRate)
+        );
+    }
+}
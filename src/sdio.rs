@@ -162,6 +162,7 @@ pins! {
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Buswidth {
     Buswidth1 = 0,
     Buswidth4 = 1,
@@ -169,6 +170,8 @@ pub enum Buswidth {
 }
 
 /// Clock frequency of a SDIO bus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ClockFreq {
     F24Mhz = 0,
     F16Mhz = 1,
@@ -180,6 +183,7 @@ pub enum ClockFreq {
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     Timeout,
     SoftwareTimeout,
@@ -261,6 +265,15 @@ impl<P: SdioPeripheral> Sdio<P> {
         host
     }
 
+    /// Powers the card off and releases the SDIO peripheral
+    ///
+    /// The pins given to [`new`](Self::new) stay in their alternate mode, they can be
+    /// recovered with `Pin::steal` if needed.
+    pub fn release(mut self) -> SDIO {
+        self.power_card(false);
+        self.sdio
+    }
+
     fn power_card(&mut self, on: bool) {
         use crate::pac::sdio::power::PWRCTRL_A;
 
@@ -15,6 +15,19 @@
 //! the embedded-hal read and write traits with `u16` as the word type. You can use these
 //! implementations for 9-bit words.
 //!
+//! # RS-485
+//!
+//! [`Rs485`] wraps a `Tx` and drives the transceiver driver enable pin around each write.
+//!
+//! # Smartcard
+//!
+//! [`Serial::into_smartcard`] switches a USART to ISO 7816-3 smartcard mode, see [`Smartcard`].
+//!
+//! # Logging
+//!
+//! `Tx` implements [`core::fmt::Write`]. [`BlockingTx`] wraps it to wait until each string
+//! is completely sent and optionally translate `\n` to `\r\n`.
+//!
 
 use core::fmt;
 use core::marker::PhantomData;
@@ -23,8 +36,19 @@ use core::ops::{Deref, DerefMut};
 use crate::rcc;
 use nb::block;
 
+#[cfg(feature = "async")]
+#[clippy::msrv = "1.75"]
+pub mod asynch;
+mod blocking_tx;
+pub mod dma_queue;
 mod hal_02;
 mod hal_1;
+mod rs485;
+mod smartcard;
+pub use blocking_tx::BlockingTx;
+pub use dma_queue::{DmaWriter, DoubleBufferTx, QueueFull, TxDmaQueue};
+pub use rs485::Rs485;
+pub use smartcard::{Smartcard, SmartcardInstance};
 
 use crate::gpio::{Const, PinA, PushPull, SetAlternate};
 
@@ -36,9 +60,10 @@ use crate::rcc::Clocks;
 use crate::dma::traits::PeriAddress;
 
 /// Serial error
-pub use embedded_hal_one::serial::ErrorKind as Error;
+pub use embedded_hal_nb::serial::ErrorKind as Error;
 
 /// Interrupt event
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Event {
     /// New data has been received
     Rxne,
@@ -78,16 +103,19 @@ pub mod config {
     /// Stop Bit configuration parameter for serial.
     ///
     /// Wrapper around `STOP_A`
+    ///
+    /// 0.5 and 1.5 stop bits are meant for smartcard mode and are only available on USARTs,
+    /// UARTs reject them with [`InvalidConfig`].
     #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     pub enum StopBits {
         #[doc = "1 stop bit"]
         STOP1,
-        #[doc = "0.5 stop bits"]
+        #[doc = "0.5 stop bits, USART only"]
         STOP0P5,
         #[doc = "2 stop bits"]
         STOP2,
-        #[doc = "1.5 stop bits"]
+        #[doc = "1.5 stop bits, USART only"]
         STOP1P5,
     }
 
@@ -153,6 +181,7 @@ pub mod config {
     }
 
     #[derive(Debug)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub struct InvalidConfig;
 
     impl Default for Config {
@@ -237,6 +266,18 @@ impl<USART: Instance, WORD> Rx<USART, WORD> {
         }
     }
 
+    /// Keeps the USART clock running in Sleep mode, to wake up on reception, see
+    /// [`Rcc::gate_sleep_clocks`](crate::rcc::Rcc::gate_sleep_clocks)
+    pub fn enable_in_sleep(&mut self, enable: bool) {
+        // NOTE(unsafe) only the bit of this USART is written, with bit-banding
+        let rcc = unsafe { &*RCC::ptr() };
+        if enable {
+            USART::low_power_enable(rcc);
+        } else {
+            USART::low_power_disable(rcc);
+        }
+    }
+
     /// Start listening for an rx not empty interrupt event
     ///
     /// Note, you will also have to enable the corresponding interrupt
@@ -307,6 +348,12 @@ impl<USART: Instance, WORD> Tx<USART, WORD> {
     pub fn is_tx_empty(&self) -> bool {
         unsafe { (*USART::ptr()).sr.read().txe().bit_is_set() }
     }
+
+    /// Return true if the transmission is complete, i.e. both the tx register
+    /// and the shift register are empty
+    pub fn is_transmission_complete(&self) -> bool {
+        unsafe { (*USART::ptr()).sr.read().tc().bit_is_set() }
+    }
 }
 
 impl<USART, PINS, WORD> AsRef<Tx<USART, WORD>> for Serial<USART, PINS, WORD> {
@@ -527,11 +574,8 @@ where
     (TX, RX): Pins<USART>,
     USART: Instance,
 {
-    /*
-        StopBits::STOP0P5 and StopBits::STOP1P5 aren't supported when using UART
-
-        STOP_A::STOP1 and STOP_A::STOP2 will be used, respectively
-    */
+    /// Returns [`InvalidConfig`](config::InvalidConfig) if the baudrate cannot be reached, or
+    /// for 0.5 and 1.5 stop bits on a UART.
     pub fn new(
         usart: USART,
         mut pins: (TX, RX),
@@ -541,6 +585,7 @@ where
         use self::config::*;
 
         let config = config.into();
+        check_stop_bits(config.stopbits, USART::HALF_STOP_BITS)?;
         unsafe {
             // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
             let rcc = &(*RCC::ptr());
@@ -654,6 +699,7 @@ where
         .config_stop(config))
     }
 
+    /// Releases the USART peripheral and pins, set back to their mode before construction
     pub fn release(mut self) -> (USART, (TX, RX)) {
         self.pins.restore_mode();
 
@@ -723,6 +769,12 @@ impl<USART: Instance, PINS, WORD> Serial<USART, PINS, WORD> {
         unsafe { (*USART::ptr()).sr.read().txe().bit_is_set() }
     }
 
+    /// Return true if the transmission is complete, i.e. both the tx register
+    /// and the shift register are empty
+    pub fn is_transmission_complete(&self) -> bool {
+        unsafe { (*USART::ptr()).sr.read().tc().bit_is_set() }
+    }
+
     /// Return true if the rx register is not empty (and can be read)
     pub fn is_rx_not_empty(&self) -> bool {
         unsafe { (*USART::ptr()).sr.read().rxne().bit_is_set() }
@@ -736,6 +788,31 @@ impl<USART: Instance, PINS, WORD> Serial<USART, PINS, WORD> {
         }
     }
 
+    /// Resets the peripheral through its RCC reset line, keeping the current configuration
+    ///
+    /// A transmission in progress is aborted and received data is discarded.
+    pub fn reset(&mut self) {
+        let usart = unsafe { &*USART::ptr() };
+        let brr = usart.brr.read().bits();
+        let cr1 = usart.cr1.read().bits();
+        let cr2 = usart.cr2.read().bits();
+        let cr3 = usart.cr3.read().bits();
+
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            USART::reset(rcc);
+        }
+
+        unsafe {
+            usart.brr.write(|w| w.bits(brr));
+            usart.cr2.write(|w| w.bits(cr2));
+            usart.cr3.write(|w| w.bits(cr3));
+            // UE is in CR1, the USART is enabled last
+            usart.cr1.write(|w| w.bits(cr1));
+        }
+    }
+
     pub fn split(self) -> (Tx<USART, WORD>, Rx<USART, WORD>) {
         (self.tx, self.rx)
     }
@@ -793,6 +870,28 @@ unsafe impl<USART: Instance> PeriAddress for Tx<USART, u8> {
     type MemSize = u8;
 }
 
+/// `CR2.STOP` value of `bits`
+const fn cr2_stop(bits: config::StopBits) -> u8 {
+    match bits {
+        config::StopBits::STOP1 => 0b00,
+        config::StopBits::STOP0P5 => 0b01,
+        config::StopBits::STOP2 => 0b10,
+        config::StopBits::STOP1P5 => 0b11,
+    }
+}
+
+/// Rejects 0.5 and 1.5 stop bits without `half_stop_bits` support
+fn check_stop_bits(
+    bits: config::StopBits,
+    half_stop_bits: bool,
+) -> Result<(), config::InvalidConfig> {
+    use config::StopBits;
+    match bits {
+        StopBits::STOP0P5 | StopBits::STOP1P5 if !half_stop_bits => Err(config::InvalidConfig),
+        _ => Ok(()),
+    }
+}
+
 impl<USART: Instance, PINS, WORD> Serial<USART, PINS, WORD> {
     fn config_stop(self, config: config::Config) -> Self {
         self.usart.set_stopbits(config.stopbits);
@@ -831,15 +930,27 @@ use crate::pac::uart4 as uart_base;
 use crate::pac::usart1 as uart_base;
 
 // Implemented by all USART instances
-pub trait Instance: crate::Sealed + rcc::Enable + rcc::Reset + rcc::BusClock {
+pub trait Instance:
+    crate::Sealed + rcc::Enable + rcc::LPEnable + rcc::Reset + rcc::BusClock
+{
     #[doc(hidden)]
     fn ptr() -> *const uart_base::RegisterBlock;
     #[doc(hidden)]
     fn set_stopbits(&self, bits: config::StopBits);
+    /// Supports 0.5 and 1.5 stop bits
+    #[doc(hidden)]
+    const HALF_STOP_BITS: bool;
+    /// Receiver and transmitter wakers
+    #[cfg(feature = "async")]
+    #[doc(hidden)]
+    fn wakers() -> &'static [atomic_waker::AtomicWaker; 2];
 }
 
 macro_rules! halUsart {
     ($USART:ty, $Serial:ident, $Tx:ident, $Rx:ident) => {
+        halUsart!($USART, $Serial, $Tx, $Rx, half_stop_bits: true);
+    };
+    ($USART:ty, $Serial:ident, $Tx:ident, $Rx:ident, half_stop_bits: $half:literal) => {
         pub type $Serial<PINS, WORD = u8> = Serial<$USART, PINS, WORD>;
         pub type $Tx<WORD = u8> = Tx<$USART, WORD>;
         pub type $Rx<WORD = u8> = Rx<$USART, WORD>;
@@ -849,19 +960,20 @@ macro_rules! halUsart {
                 <$USART>::ptr() as *const _
             }
 
-            fn set_stopbits(&self, bits: config::StopBits) {
-                use crate::pac::usart1::cr2::STOP_A;
-                use config::StopBits;
+            #[cfg(feature = "async")]
+            fn wakers() -> &'static [atomic_waker::AtomicWaker; 2] {
+                static WAKERS: [atomic_waker::AtomicWaker; 2] = [
+                    atomic_waker::AtomicWaker::new(),
+                    atomic_waker::AtomicWaker::new(),
+                ];
+                &WAKERS
+            }
 
-                self.cr2.write(|w| {
-                    w.stop().variant(match bits {
-                        StopBits::STOP0P5 => STOP_A::Stop0p5,
-                        StopBits::STOP1 => STOP_A::Stop1,
-                        StopBits::STOP1P5 => STOP_A::Stop1p5,
-                        StopBits::STOP2 => STOP_A::Stop2,
-                    })
-                });
+            fn set_stopbits(&self, bits: config::StopBits) {
+                self.cr2.write(|w| w.stop().bits(cr2_stop(bits)));
             }
+
+            const HALF_STOP_BITS: bool = $half;
         }
     };
 }
@@ -887,19 +999,29 @@ macro_rules! halUart {
                 <$USART>::ptr() as *const _
             }
 
+            #[cfg(feature = "async")]
+            fn wakers() -> &'static [atomic_waker::AtomicWaker; 2] {
+                static WAKERS: [atomic_waker::AtomicWaker; 2] = [
+                    atomic_waker::AtomicWaker::new(),
+                    atomic_waker::AtomicWaker::new(),
+                ];
+                &WAKERS
+            }
+
             fn set_stopbits(&self, bits: config::StopBits) {
                 use crate::pac::uart4::cr2::STOP_A;
                 use config::StopBits;
 
                 self.cr2.write(|w| {
                     w.stop().variant(match bits {
-                        StopBits::STOP0P5 => STOP_A::Stop1,
-                        StopBits::STOP1 => STOP_A::Stop1,
-                        StopBits::STOP1P5 => STOP_A::Stop2,
                         StopBits::STOP2 => STOP_A::Stop2,
+                        // 0.5 and 1.5 stop bits are rejected by `Serial::new`
+                        _ => STOP_A::Stop1,
                     })
                 });
             }
+
+            const HALF_STOP_BITS: bool = false;
         }
     };
 }
@@ -923,16 +1045,16 @@ halUart! { pac::UART5, Serial5, Rx5, Tx5 }
 //halUsart! { pac::UART4, Serial4, Rx4, Tx4 }
 #[cfg(feature = "uart5")]
 #[cfg(any(feature = "stm32f413", feature = "stm32f423"))]
-halUsart! { pac::UART5, Serial5, Rx5, Tx5 }
+halUsart! { pac::UART5, Serial5, Rx5, Tx5, half_stop_bits: false }
 
 #[cfg(feature = "uart7")]
-halUsart! { pac::UART7, Serial7, Rx7, Tx7 }
+halUsart! { pac::UART7, Serial7, Rx7, Tx7, half_stop_bits: false }
 #[cfg(feature = "uart8")]
-halUsart! { pac::UART8, Serial8, Rx8, Tx8 }
+halUsart! { pac::UART8, Serial8, Rx8, Tx8, half_stop_bits: false }
 #[cfg(feature = "uart9")]
-halUsart! { pac::UART9, Serial9, Rx9, Tx9 }
+halUsart! { pac::UART9, Serial9, Rx9, Tx9, half_stop_bits: false }
 #[cfg(feature = "uart10")]
-halUsart! { pac::UART10, Serial10, Rx10, Tx10 }
+halUsart! { pac::UART10, Serial10, Rx10, Tx10, half_stop_bits: false }
 
 impl<USART: Instance, PINS> fmt::Write for Serial<USART, PINS> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
@@ -953,6 +1075,29 @@ impl<USART: Instance> Rx<USART, u8> {
         // Delegate to the Read<u16> implementation, then truncate to 8 bits
         Rx::<USART, u16>::new().read().map(|word16| word16 as u8)
     }
+
+    /// Receives bytes until the line stays idle for `timeout`, returns the number of bytes read
+    ///
+    /// `timer` is restarted on each received byte, the frame ends when it expires or when `buf`
+    /// is full. The first byte is awaited for `timeout` too, `Ok(0)` means nothing was received.
+    /// This delimits Modbus RTU frames, with a 3.5 character gap:
+    ///
+    /// ```ignore
+    /// let mut timer = dp.TIM2.counter_us(&clocks);
+    /// // 3.5 characters of 11 bits at 19200 bps
+    /// let n = rx.read_until_idle(&mut buf, &mut timer, 2005.micros())?;
+    /// ```
+    pub fn read_until_idle<TIMER, const FREQ: u32>(
+        &mut self,
+        buf: &mut [u8],
+        timer: &mut TIMER,
+        timeout: fugit::TimerDurationU32<FREQ>,
+    ) -> Result<usize, Error>
+    where
+        TIMER: fugit_timer::Timer<FREQ>,
+    {
+        read_until_idle(|| self.read(), buf, timer, timeout)
+    }
 }
 
 impl<USART: Instance> Rx<USART, u16> {
@@ -992,7 +1137,12 @@ impl<USART: Instance> Tx<USART, u8> {
         Tx::<USART, u16>::new().write(u16::from(word))
     }
 
-    fn flush(&mut self) -> nb::Result<(), Error> {
+    /// Waits for the end of transmission of the last written word
+    ///
+    /// Unlike [`Tx::is_tx_empty`], this checks the transmission complete (TC) flag,
+    /// which is only set once the shift register is empty too. Use it before disabling
+    /// the transmitter or switching an RS-485 transceiver back to receive.
+    pub fn flush(&mut self) -> nb::Result<(), Error> {
         // Delegate to u16 version
         Tx::<USART, u16>::new().flush()
     }
@@ -1004,7 +1154,8 @@ impl<USART: Instance> Tx<USART, u8> {
         Ok(())
     }
 
-    fn bflush(&mut self) -> Result<(), Error> {
+    /// Blocks until the last written word has been completely shifted out
+    pub fn bflush(&mut self) -> Result<(), Error> {
         nb::block!(self.flush())
     }
 }
@@ -1023,15 +1174,14 @@ impl<USART: Instance> Tx<USART, u16> {
         }
     }
 
-    fn flush(&mut self) -> nb::Result<(), Error> {
+    /// Waits for the end of transmission of the last written word
+    ///
+    /// Unlike [`Tx::is_tx_empty`], this checks the transmission complete (TC) flag,
+    /// which is only set once the shift register is empty too. Use it before disabling
+    /// the transmitter or switching an RS-485 transceiver back to receive.
+    pub fn flush(&mut self) -> nb::Result<(), Error> {
         // NOTE(unsafe) atomic read with no side effects
-        let sr = unsafe { (*USART::ptr()).sr.read() };
-
-        if sr.tc().bit_is_set() {
-            Ok(())
-        } else {
-            Err(nb::Error::WouldBlock)
-        }
+        flush_status(unsafe { (*USART::ptr()).sr.read().bits() })
     }
 
     fn bwrite_all(&mut self, buffer: &[u16]) -> Result<(), Error> {
@@ -1041,7 +1191,235 @@ impl<USART: Instance> Tx<USART, u16> {
         Ok(())
     }
 
-    fn bflush(&mut self) -> Result<(), Error> {
+    /// Blocks until the last written word has been completely shifted out
+    pub fn bflush(&mut self) -> Result<(), Error> {
         nb::block!(self.flush())
     }
 }
+
+/// Transmission complete flag of `SR`
+const SR_TC: u32 = 1 << 6;
+
+/// [`Tx::flush`] result for the status register value `sr`
+///
+/// TXE is set as soon as the last word moves to the shift register, only TC tells that it
+/// was shifted out.
+fn flush_status(sr: u32) -> nb::Result<(), Error> {
+    if sr & SR_TC != 0 {
+        Ok(())
+    } else {
+        Err(nb::Error::WouldBlock)
+    }
+}
+
+/// Receive loop of [`Rx::read_until_idle`]
+fn read_until_idle<TIMER, const FREQ: u32>(
+    mut read: impl FnMut() -> nb::Result<u8, Error>,
+    buf: &mut [u8],
+    timer: &mut TIMER,
+    timeout: fugit::TimerDurationU32<FREQ>,
+) -> Result<usize, Error>
+where
+    TIMER: fugit_timer::Timer<FREQ>,
+{
+    // Stop the timer and discard an expiry left over from its previous use before
+    // starting it, an expiry of this frame is then never lost
+    timer.cancel().ok();
+    timer.wait().ok();
+    timer.start(timeout).ok();
+    let mut received = 0;
+    while received < buf.len() {
+        match read() {
+            Ok(byte) => {
+                buf[received] = byte;
+                received += 1;
+                timer.start(timeout).ok();
+            }
+            Err(nb::Error::WouldBlock) => {
+                if timer.wait().is_ok() {
+                    break;
+                }
+            }
+            Err(nb::Error::Other(e)) => {
+                timer.cancel().ok();
+                return Err(e);
+            }
+        }
+    }
+    timer.cancel().ok();
+    Ok(received)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_bits_encoding() {
+        use config::StopBits;
+        assert_eq!(cr2_stop(StopBits::STOP1), 0b00);
+        assert_eq!(cr2_stop(StopBits::STOP0P5), 0b01);
+        assert_eq!(cr2_stop(StopBits::STOP2), 0b10);
+        assert_eq!(cr2_stop(StopBits::STOP1P5), 0b11);
+    }
+
+    #[test]
+    fn flush_waits_for_tc() {
+        const TXE: u32 = 1 << 7;
+        // Last word in the shift register
+        assert_eq!(flush_status(TXE), Err(nb::Error::WouldBlock));
+        assert_eq!(flush_status(0), Err(nb::Error::WouldBlock));
+        assert_eq!(flush_status(TXE | SR_TC), Ok(()));
+        // Reset value of SR
+        assert_eq!(flush_status(0x00c0), Ok(()));
+    }
+
+    #[test]
+    fn half_stop_bits_only_on_usarts() {
+        use config::StopBits;
+        for bits in [StopBits::STOP1, StopBits::STOP2] {
+            assert!(check_stop_bits(bits, false).is_ok());
+            assert!(check_stop_bits(bits, true).is_ok());
+        }
+        for bits in [StopBits::STOP0P5, StopBits::STOP1P5] {
+            assert!(check_stop_bits(bits, false).is_err());
+            assert!(check_stop_bits(bits, true).is_ok());
+        }
+    }
+
+    const TIMEOUT: fugit::TimerDurationU32<1_000_000> = fugit::TimerDurationU32::from_ticks(10);
+
+    /// Expires after `ticks` polls of `wait` following a start
+    struct FakeTimer {
+        ticks: u32,
+        remaining: Option<u32>,
+        /// Expiry not yet collected by `wait`
+        expired: bool,
+        starts: u32,
+    }
+
+    impl FakeTimer {
+        fn new(ticks: u32) -> Self {
+            Self {
+                ticks,
+                remaining: None,
+                expired: false,
+                starts: 0,
+            }
+        }
+    }
+
+    impl fugit_timer::Timer<1_000_000> for FakeTimer {
+        type Error = ();
+
+        fn now(&mut self) -> fugit::TimerInstantU32<1_000_000> {
+            fugit::TimerInstantU32::from_ticks(0)
+        }
+
+        fn start(&mut self, _duration: fugit::TimerDurationU32<1_000_000>) -> Result<(), ()> {
+            self.remaining = Some(self.ticks);
+            self.starts += 1;
+            Ok(())
+        }
+
+        fn cancel(&mut self) -> Result<(), ()> {
+            self.remaining = None;
+            Ok(())
+        }
+
+        fn wait(&mut self) -> nb::Result<(), ()> {
+            match self.remaining {
+                Some(0) => {
+                    self.remaining = Some(self.ticks);
+                    self.expired = true;
+                }
+                Some(n) => self.remaining = Some(n - 1),
+                None => {}
+            }
+            if core::mem::take(&mut self.expired) {
+                Ok(())
+            } else {
+                Err(nb::Error::WouldBlock)
+            }
+        }
+    }
+
+    /// Plays `script`, `None` is an empty receive register
+    fn reader(script: &[Option<u8>]) -> impl FnMut() -> nb::Result<u8, Error> + '_ {
+        let mut script = script.iter();
+        move || match script.next() {
+            Some(Some(byte)) => Ok(*byte),
+            _ => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    #[test]
+    fn frame_ends_on_idle_gap() {
+        let mut timer = FakeTimer::new(3);
+        let mut buf = [0; 8];
+        let script = [
+            Some(1),
+            None,
+            None,
+            Some(2),
+            Some(3),
+            None,
+            None,
+            None,
+            None,
+            Some(4),
+        ];
+        let n = read_until_idle(reader(&script), &mut buf, &mut timer, TIMEOUT).unwrap();
+        assert_eq!(buf[..n], [1, 2, 3]);
+        // Restarted on each byte
+        assert_eq!(timer.starts, 4);
+        assert_eq!(timer.remaining, None);
+    }
+
+    #[test]
+    fn nothing_received() {
+        let mut timer = FakeTimer::new(2);
+        let mut buf = [0; 4];
+        let n = read_until_idle(reader(&[]), &mut buf, &mut timer, TIMEOUT).unwrap();
+        assert_eq!(n, 0);
+    }
+
+    #[test]
+    fn stale_expiry_is_discarded() {
+        let mut timer = FakeTimer::new(2);
+        // Left over from a previous use
+        timer.expired = true;
+        let mut buf = [0; 4];
+        let script = [None, Some(7)];
+        let n = read_until_idle(reader(&script), &mut buf, &mut timer, TIMEOUT).unwrap();
+        assert_eq!(buf[..n], [7]);
+    }
+
+    #[test]
+    fn full_buffer_ends_frame() {
+        let mut timer = FakeTimer::new(5);
+        let mut buf = [0; 2];
+        let script = [Some(1), Some(2), Some(3)];
+        let n = read_until_idle(reader(&script), &mut buf, &mut timer, TIMEOUT).unwrap();
+        assert_eq!(buf[..n], [1, 2]);
+    }
+
+    #[test]
+    fn error_stops_the_timer() {
+        let mut timer = FakeTimer::new(5);
+        let mut buf = [0; 4];
+        let mut first = true;
+        let read = || {
+            if core::mem::take(&mut first) {
+                Ok(1)
+            } else {
+                Err(nb::Error::Other(Error::Overrun))
+            }
+        };
+        assert_eq!(
+            read_until_idle(read, &mut buf, &mut timer, TIMEOUT),
+            Err(Error::Overrun)
+        );
+        assert_eq!(timer.remaining, None);
+    }
+}
@@ -0,0 +1,471 @@
+//! Async serial receiver and transmitter over DMA
+//!
+//! Each half uses one DMA stream and a `'static` bounce buffer: data is copied between
+//! the user buffers and the bounce buffer, so the DMA never accesses memory that could
+//! be freed while the stream runs, even if a future is leaked.
+//!
+//! [`AsyncRx::read`] returns when its buffer is full or, once at least one byte was
+//! received, when the line becomes idle. Transfers shorter than [`DMA_THRESHOLD`] bytes
+//! skip the DMA setup and use the RXNE/TXE interrupts for each byte instead.
+//!
+//! The USART interrupt and the DMA stream interrupts have to be bound and unmasked.
+//! The async halves own the USART interrupt enables, [`Rx::listen`] and [`Tx::listen`]
+//! must not be used with them.
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn USART1() {
+//!     serial::asynch::on_interrupt::<pac::USART1>();
+//! }
+//!
+//! #[interrupt]
+//! fn DMA2_STREAM7() {
+//!     dma::asynch::on_interrupt::<Stream7<DMA2>>();
+//! }
+//! ```
+//!
+//! Dropping a future aborts its stream and disables the DMA request of the half.
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use super::{Error, Instance, Rx, Serial, Tx};
+use crate::dma::asynch::{AsyncStream, OneShot};
+use crate::dma::traits::{Channel, DMASet};
+use crate::dma::{ChannelX, MemoryToPeripheral, PeripheralToMemory};
+
+/// Transfers shorter than this use the RXNE/TXE interrupts instead of DMA
+pub const DMA_THRESHOLD: usize = 8;
+
+const RX: usize = 0;
+const TX: usize = 1;
+
+/// Handler for the `USARTx`/`UARTx` interrupt
+pub fn on_interrupt<USART: Instance>() {
+    // Mask the interrupts, the flags are handled by the waiting futures
+    let usart = unsafe { &*USART::ptr() };
+    usart.cr1.modify(|_, w| {
+        w.rxneie()
+            .clear_bit()
+            .idleie()
+            .clear_bit()
+            .txeie()
+            .clear_bit()
+            .tcie()
+            .clear_bit()
+            .peie()
+            .clear_bit()
+    });
+    usart.cr3.modify(|_, w| w.eie().clear_bit());
+    for waker in USART::wakers() {
+        waker.wake();
+    }
+}
+
+/// Serial error as an `embedded-io` error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IoError(pub Error);
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for IoError {
+    fn format(&self, f: defmt::Formatter) {
+        // `Error` comes from embedded-hal-nb, which has no defmt support
+        defmt::write!(f, "IoError({})", defmt::Debug2Format(&self.0));
+    }
+}
+
+impl embedded_io_async::Error for IoError {
+    fn kind(&self) -> embedded_io_async::ErrorKind {
+        match self.0 {
+            Error::FrameFormat | Error::Parity | Error::Noise => {
+                embedded_io_async::ErrorKind::InvalidData
+            }
+            _ => embedded_io_async::ErrorKind::Other,
+        }
+    }
+}
+
+impl From<Error> for IoError {
+    fn from(e: Error) -> Self {
+        Self(e)
+    }
+}
+
+/// Checks the receive error flags, clearing them with a read of `DR`
+fn check_errors<USART: Instance>() -> Result<(), Error> {
+    let usart = unsafe { &*USART::ptr() };
+    let sr = usart.sr.read();
+    let error = if sr.pe().bit_is_set() {
+        Error::Parity
+    } else if sr.fe().bit_is_set() {
+        Error::FrameFormat
+    } else if sr.nf().bit_is_set() {
+        Error::Noise
+    } else if sr.ore().bit_is_set() {
+        Error::Overrun
+    } else {
+        return Ok(());
+    };
+    let _ = usart.dr.read();
+    Err(error)
+}
+
+/// Disables the DMA request and the interrupts of a half when its transfer ends
+struct StopOnDrop<USART: Instance, const HALF: usize> {
+    _usart: PhantomData<USART>,
+}
+
+impl<USART: Instance, const HALF: usize> Drop for StopOnDrop<USART, HALF> {
+    fn drop(&mut self) {
+        let usart = unsafe { &*USART::ptr() };
+        if HALF == RX {
+            usart
+                .cr3
+                .modify(|_, w| w.dmar().clear_bit().eie().clear_bit());
+            usart.cr1.modify(|_, w| {
+                w.rxneie()
+                    .clear_bit()
+                    .idleie()
+                    .clear_bit()
+                    .peie()
+                    .clear_bit()
+            });
+        } else {
+            usart.cr3.modify(|_, w| w.dmat().clear_bit());
+            usart
+                .cr1
+                .modify(|_, w| w.txeie().clear_bit().tcie().clear_bit());
+        }
+    }
+}
+
+/// Serial transmitter implementing the `embedded-io-async` traits
+pub struct AsyncTx<USART: Instance, STREAM, const CHANNEL: u8> {
+    tx: Tx<USART, u8>,
+    stream: STREAM,
+    buffer: &'static mut [u8],
+}
+
+/// Serial receiver implementing the `embedded-io-async` traits
+pub struct AsyncRx<USART: Instance, STREAM, const CHANNEL: u8> {
+    rx: Rx<USART, u8>,
+    stream: STREAM,
+    buffer: &'static mut [u8],
+}
+
+impl<USART: Instance, PINS> Serial<USART, PINS, u8> {
+    /// Splits into an async transmitter and receiver, see [`Tx::into_async`] and
+    /// [`Rx::into_async`]
+    #[allow(clippy::type_complexity)]
+    pub fn into_async<TXS, RXS, const TXCH: u8, const RXCH: u8>(
+        self,
+        tx_stream: TXS,
+        rx_stream: RXS,
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+    ) -> (AsyncTx<USART, TXS, TXCH>, AsyncRx<USART, RXS, RXCH>)
+    where
+        TXS: AsyncStream,
+        RXS: AsyncStream,
+        Tx<USART, u8>: DMASet<TXS, TXCH, MemoryToPeripheral>,
+        Rx<USART, u8>: DMASet<RXS, RXCH, PeripheralToMemory>,
+    {
+        let (tx, rx) = self.split();
+        (
+            tx.into_async(tx_stream, tx_buffer),
+            rx.into_async(rx_stream, rx_buffer),
+        )
+    }
+}
+
+impl<USART: Instance> Tx<USART, u8> {
+    /// Converts into an async transmitter using `stream` for DMA
+    ///
+    /// `buffer` is the bounce buffer of the DMA transfers, it must not be empty.
+    pub fn into_async<STREAM, const CHANNEL: u8>(
+        self,
+        stream: STREAM,
+        buffer: &'static mut [u8],
+    ) -> AsyncTx<USART, STREAM, CHANNEL>
+    where
+        STREAM: AsyncStream,
+        Self: DMASet<STREAM, CHANNEL, MemoryToPeripheral>,
+    {
+        assert!(!buffer.is_empty());
+        AsyncTx {
+            tx: self,
+            stream,
+            buffer,
+        }
+    }
+}
+
+impl<USART: Instance> Rx<USART, u8> {
+    /// Converts into an async receiver using `stream` for DMA
+    ///
+    /// `buffer` is the bounce buffer of the DMA transfers, it must not be empty.
+    pub fn into_async<STREAM, const CHANNEL: u8>(
+        self,
+        stream: STREAM,
+        buffer: &'static mut [u8],
+    ) -> AsyncRx<USART, STREAM, CHANNEL>
+    where
+        STREAM: AsyncStream,
+        Self: DMASet<STREAM, CHANNEL, PeripheralToMemory>,
+    {
+        assert!(!buffer.is_empty());
+        AsyncRx {
+            rx: self,
+            stream,
+            buffer,
+        }
+    }
+}
+
+impl<USART: Instance, STREAM, const CHANNEL: u8> AsyncTx<USART, STREAM, CHANNEL>
+where
+    STREAM: AsyncStream,
+    Tx<USART, u8>: DMASet<STREAM, CHANNEL, MemoryToPeripheral>,
+    ChannelX<CHANNEL>: Channel,
+{
+    /// Releases the transmitter, the DMA stream and the bounce buffer
+    pub fn release(self) -> (Tx<USART, u8>, STREAM, &'static mut [u8]) {
+        (self.tx, self.stream, self.buffer)
+    }
+
+    /// Writes up to the bounce buffer length from `buf`, returns the number of bytes written
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let n = buf.len().min(self.buffer.len()).min(u16::MAX as usize);
+        self.buffer[..n].copy_from_slice(&buf[..n]);
+
+        let _stop = StopOnDrop::<USART, TX> {
+            _usart: PhantomData,
+        };
+        let usart = unsafe { &*USART::ptr() };
+
+        if n < DMA_THRESHOLD {
+            for i in 0..n {
+                let byte = self.buffer[i];
+                poll_fn(|cx| {
+                    USART::wakers()[TX].register(cx.waker());
+                    match self.tx.write(byte) {
+                        Ok(()) => Poll::Ready(Ok(())),
+                        Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                        Err(nb::Error::WouldBlock) => {
+                            usart.cr1.modify(|_, w| w.txeie().set_bit());
+                            Poll::Pending
+                        }
+                    }
+                })
+                .await?;
+            }
+        } else {
+            // SAFETY: the bounce buffer is `'static` and not accessed until the stream stops
+            let mut transfer = unsafe {
+                OneShot::start::<_, MemoryToPeripheral, CHANNEL>(
+                    &mut self.stream,
+                    &Tx::<USART, u8>::new(),
+                    self.buffer.as_ptr(),
+                    n as u16,
+                    true,
+                )
+            };
+            usart.cr3.modify(|_, w| w.dmat().set_bit());
+            transfer.wait().await.map_err(|_| Error::Other)?;
+        }
+
+        Ok(n)
+    }
+
+    /// Waits until the last written byte was sent
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        let _stop = StopOnDrop::<USART, TX> {
+            _usart: PhantomData,
+        };
+        let usart = unsafe { &*USART::ptr() };
+
+        poll_fn(|cx| {
+            USART::wakers()[TX].register(cx.waker());
+            if self.tx.is_transmission_complete() {
+                Poll::Ready(Ok(()))
+            } else {
+                usart.cr1.modify(|_, w| w.tcie().set_bit());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl<USART: Instance, STREAM, const CHANNEL: u8> AsyncRx<USART, STREAM, CHANNEL>
+where
+    STREAM: AsyncStream,
+    Rx<USART, u8>: DMASet<STREAM, CHANNEL, PeripheralToMemory>,
+    ChannelX<CHANNEL>: Channel,
+{
+    /// Releases the receiver, the DMA stream and the bounce buffer
+    pub fn release(self) -> (Rx<USART, u8>, STREAM, &'static mut [u8]) {
+        (self.rx, self.stream, self.buffer)
+    }
+
+    /// Reads into `buf` until it is full or the line becomes idle after at least one
+    /// byte, returns the number of bytes read
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let n = buf.len().min(self.buffer.len()).min(u16::MAX as usize);
+        if n == 0 {
+            return Ok(0);
+        }
+
+        let _stop = StopOnDrop::<USART, RX> {
+            _usart: PhantomData,
+        };
+        let usart = unsafe { &*USART::ptr() };
+
+        let received = if n < DMA_THRESHOLD {
+            // Wait for the first byte, then take what is already there
+            self.buffer[0] = poll_fn(|cx| {
+                USART::wakers()[RX].register(cx.waker());
+                match self.rx.read() {
+                    Ok(byte) => Poll::Ready(Ok(byte)),
+                    Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                    Err(nb::Error::WouldBlock) => {
+                        usart
+                            .cr1
+                            .modify(|_, w| w.rxneie().set_bit().peie().set_bit());
+                        usart.cr3.modify(|_, w| w.eie().set_bit());
+                        Poll::Pending
+                    }
+                }
+            })
+            .await?;
+
+            let mut received = 1;
+            while received < n {
+                match self.rx.read() {
+                    Ok(byte) => {
+                        self.buffer[received] = byte;
+                        received += 1;
+                    }
+                    Err(nb::Error::Other(e)) => return Err(e),
+                    Err(nb::Error::WouldBlock) => break,
+                }
+            }
+            received
+        } else {
+            self.read_dma(n).await?
+        };
+
+        buf[..received].copy_from_slice(&self.buffer[..received]);
+        Ok(received)
+    }
+
+    async fn read_dma(&mut self, n: usize) -> Result<usize, Error> {
+        let usart = unsafe { &*USART::ptr() };
+
+        // Clear an IDLE flag left from a previous frame, keeping a byte already received
+        let mut first = 0;
+        let sr = usart.sr.read();
+        if sr.idle().bit_is_set() {
+            let byte = usart.dr.read().dr().bits() as u8;
+            if sr.rxne().bit_is_set() {
+                self.buffer[0] = byte;
+                first = 1;
+            }
+        }
+
+        // SAFETY: the bounce buffer is `'static` and not accessed until the stream stops
+        let mut transfer = unsafe {
+            OneShot::start::<_, PeripheralToMemory, CHANNEL>(
+                &mut self.stream,
+                &Rx::<USART, u8>::new(),
+                self.buffer[first..].as_ptr(),
+                (n - first) as u16,
+                true,
+            )
+        };
+        usart.cr3.modify(|_, w| w.dmar().set_bit());
+
+        let complete = poll_fn(|cx| {
+            USART::wakers()[RX].register(cx.waker());
+
+            if let Err(e) = check_errors::<USART>() {
+                return Poll::Ready(Err(e));
+            }
+            match transfer.poll_complete(cx) {
+                Poll::Ready(Ok(())) => return Poll::Ready(Ok(true)),
+                Poll::Ready(Err(_)) => return Poll::Ready(Err(Error::Other)),
+                Poll::Pending => {}
+            }
+            if usart.sr.read().idle().bit_is_set() {
+                if first + (n - first - transfer.remaining() as usize) > 0 {
+                    return Poll::Ready(Ok(false));
+                }
+                // Idle before the first byte of this read, clear it and keep waiting
+                let _ = usart.dr.read();
+            }
+            usart
+                .cr1
+                .modify(|_, w| w.idleie().set_bit().peie().set_bit());
+            usart.cr3.modify(|_, w| w.eie().set_bit());
+            Poll::Pending
+        })
+        .await?;
+
+        if complete {
+            return Ok(n);
+        }
+
+        // Line idle: stop the stream, then clear IDLE with a read of DR, keeping a byte
+        // received in the meantime
+        let mut received = n - transfer.stop() as usize;
+        usart.cr3.modify(|_, w| w.dmar().clear_bit());
+        let sr = usart.sr.read();
+        let byte = usart.dr.read().dr().bits() as u8;
+        if sr.rxne().bit_is_set() && received < n {
+            self.buffer[received] = byte;
+            received += 1;
+        }
+        Ok(received)
+    }
+}
+
+impl<USART: Instance, STREAM, const CHANNEL: u8> embedded_io_async::ErrorType
+    for AsyncTx<USART, STREAM, CHANNEL>
+{
+    type Error = IoError;
+}
+
+impl<USART: Instance, STREAM, const CHANNEL: u8> embedded_io_async::Write
+    for AsyncTx<USART, STREAM, CHANNEL>
+where
+    STREAM: AsyncStream,
+    Tx<USART, u8>: DMASet<STREAM, CHANNEL, MemoryToPeripheral>,
+    ChannelX<CHANNEL>: Channel,
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(Self::write(self, buf).await?)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(Self::flush(self).await?)
+    }
+}
+
+impl<USART: Instance, STREAM, const CHANNEL: u8> embedded_io_async::ErrorType
+    for AsyncRx<USART, STREAM, CHANNEL>
+{
+    type Error = IoError;
+}
+
+impl<USART: Instance, STREAM, const CHANNEL: u8> embedded_io_async::Read
+    for AsyncRx<USART, STREAM, CHANNEL>
+where
+    STREAM: AsyncStream,
+    Rx<USART, u8>: DMASet<STREAM, CHANNEL, PeripheralToMemory>,
+    ChannelX<CHANNEL>: Channel,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(Self::read(self, buf).await?)
+    }
+}
@@ -0,0 +1,86 @@
+//! Transmitter for `write!` logging that never fails
+
+use core::fmt;
+
+use super::{Instance, Tx};
+
+/// 8-bit transmitter implementing [`fmt::Write`] for logging
+///
+/// Each `write_str` busy-waits until every byte is sent, including the last one on the wire
+/// (transmission complete, TC). Serial errors can't occur on transmit, so formatting errors
+/// are the only ones returned. Optionally, `\n` is sent as `\r\n` for terminals.
+pub struct BlockingTx<USART> {
+    tx: Tx<USART, u8>,
+    crlf: bool,
+}
+
+impl<USART: Instance> BlockingTx<USART> {
+    /// Wraps a transmitter, newlines are sent unchanged
+    pub fn new(tx: Tx<USART, u8>) -> Self {
+        Self { tx, crlf: false }
+    }
+
+    /// Sends each `\n` as `\r\n` if `crlf` is `true`
+    pub fn crlf(mut self, crlf: bool) -> Self {
+        self.crlf = crlf;
+        self
+    }
+
+    /// Returns the transmitter
+    pub fn release(self) -> Tx<USART, u8> {
+        self.tx
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        // Only `WouldBlock` is ever returned
+        nb::block!(self.tx.write(byte)).ok();
+    }
+}
+
+impl<USART: Instance> fmt::Write for BlockingTx<USART> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for_each_byte(s, self.crlf, |byte| self.write_byte(byte));
+        self.tx.bflush().ok();
+        Ok(())
+    }
+}
+
+/// Calls `f` with the bytes of `s`, each `\n` preceded by `\r` if `crlf` is `true`
+fn for_each_byte(s: &str, crlf: bool, mut f: impl FnMut(u8)) {
+    for byte in s.bytes() {
+        if crlf && byte == b'\n' {
+            f(b'\r');
+        }
+        f(byte);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent<'a>(s: &str, crlf: bool, buf: &'a mut [u8; 16]) -> &'a [u8] {
+        let mut len = 0;
+        for_each_byte(s, crlf, |byte| {
+            buf[len] = byte;
+            len += 1;
+        });
+        &buf[..len]
+    }
+
+    #[test]
+    fn crlf() {
+        let mut buf = [0; 16];
+        assert_eq!(sent("a\nb\n", true, &mut buf), b"a\r\nb\r\n");
+        assert_eq!(sent("\n\n", true, &mut buf), b"\r\n\r\n");
+        // A `\r` already there is kept
+        assert_eq!(sent("ok\r\n", true, &mut buf), b"ok\r\r\n");
+        assert_eq!(sent("", true, &mut buf), b"");
+    }
+
+    #[test]
+    fn newlines_unchanged() {
+        let mut buf = [0; 16];
+        assert_eq!(sent("a\nb\r\n", false, &mut buf), b"a\nb\r\n");
+    }
+}
@@ -0,0 +1,521 @@
+//! Serial transmitter sending a queue of buffers over DMA
+//!
+//! [`TxDmaQueue::try_enqueue`] never blocks: the buffer is sent right away if the stream is
+//! idle, queued otherwise. The transfer complete interrupt of the stream calls
+//! [`TxDmaQueue::on_interrupt`], which returns the finished buffer and starts the next
+//! one.
+//!
+//! ```ignore
+//! static QUEUE: Mutex<RefCell<Option<TxDmaQueue<USART1, Stream7<DMA2>, 4, &'static [u8], 8>>>> =
+//!     Mutex::new(RefCell::new(None));
+//!
+//! let queue = tx.dma_queue(dma2.7);
+//! queue.try_enqueue(&b"hello\r\n"[..]).ok();
+//!
+//! #[interrupt]
+//! fn DMA2_STREAM7() {
+//!     cortex_m::interrupt::free(|cs| {
+//!         if let Some(queue) = QUEUE.borrow(cs).borrow_mut().as_mut() {
+//!             queue.on_interrupt();
+//!         }
+//!     });
+//! }
+//! ```
+//!
+//! [`DmaWriter`] formats into a pool of buffers on top of a queue, as a logger that drops
+//! the output instead of blocking when all buffers are in use. [`DoubleBufferTx`] fills one
+//! buffer while the other one is sent.
+
+use core::fmt;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use embedded_dma::ReadBuffer;
+use heapless::Deque;
+
+use super::{Instance, Tx};
+use crate::dma::traits::{Channel, DMASet, Direction, PeriAddress, Stream};
+use crate::dma::{ChannelX, MemoryToPeripheral};
+
+/// The queue is full, the buffer is returned
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct QueueFull<BUF>(pub BUF);
+
+/// Serial transmitter with up to `N` buffers queued, including the one being sent
+pub struct TxDmaQueue<USART: Instance, STREAM, const CHANNEL: u8, BUF, const N: usize> {
+    tx: Tx<USART, u8>,
+    stream: STREAM,
+    // The front buffer is being sent
+    queue: Deque<(BUF, u16), N>,
+    queued: usize,
+}
+
+impl<USART: Instance> Tx<USART, u8> {
+    /// Sends queued buffers with `stream`
+    ///
+    /// The transfer complete and transfer error interrupts of the stream are enabled, the
+    /// stream interrupt has to call [`TxDmaQueue::on_interrupt`].
+    pub fn dma_queue<STREAM, const CHANNEL: u8, BUF, const N: usize>(
+        self,
+        stream: STREAM,
+    ) -> TxDmaQueue<USART, STREAM, CHANNEL, BUF, N>
+    where
+        STREAM: Stream,
+        ChannelX<CHANNEL>: Channel,
+        Self: DMASet<STREAM, CHANNEL, MemoryToPeripheral>,
+        BUF: ReadBuffer<Word = u8>,
+    {
+        TxDmaQueue::new(self, stream)
+    }
+}
+
+impl<USART: Instance, STREAM, const CHANNEL: u8, BUF, const N: usize>
+    TxDmaQueue<USART, STREAM, CHANNEL, BUF, N>
+where
+    STREAM: Stream,
+    ChannelX<CHANNEL>: Channel,
+    Tx<USART, u8>: DMASet<STREAM, CHANNEL, MemoryToPeripheral>,
+    BUF: ReadBuffer<Word = u8>,
+{
+    fn new(tx: Tx<USART, u8>, mut stream: STREAM) -> Self {
+        stream.disable();
+        stream.clear_interrupts();
+        stream.set_channel::<CHANNEL>();
+        stream.set_direction(MemoryToPeripheral::new());
+        stream.set_peripheral_address(tx.address());
+        // NOTE(unsafe) byte transfers match the data register access of `Tx<USART, u8>`
+        unsafe {
+            stream.set_memory_size(0);
+            stream.set_peripheral_size(0);
+        }
+        stream.set_memory_increment(true);
+        stream.set_peripheral_increment(false);
+        stream.set_double_buffer(false);
+        stream.set_fifo_enable(false);
+        stream.set_interrupts_enable(true, false, true, false);
+
+        let usart = unsafe { &*USART::ptr() };
+        usart.cr3.modify(|_, w| w.dmat().set_bit());
+
+        Self {
+            tx,
+            stream,
+            queue: Deque::new(),
+            queued: 0,
+        }
+    }
+
+    /// Sends `buf` once the previous buffers were sent, returns the number of bytes queued
+    /// in total
+    ///
+    /// # Panics
+    ///
+    /// When `buf` is longer than 65535 bytes.
+    pub fn try_enqueue(&mut self, buf: BUF) -> Result<usize, QueueFull<BUF>> {
+        // NOTE(unsafe) only the length is used here
+        let (_, len) = unsafe { buf.read_buffer() };
+        self.enqueue_len(buf, len)
+    }
+
+    /// Queues the first `len` bytes of `buf`
+    fn enqueue_len(&mut self, buf: BUF, len: usize) -> Result<usize, QueueFull<BUF>> {
+        assert!(len <= u16::MAX as usize);
+        let len = len as u16;
+        self.queue
+            .push_back((buf, len))
+            .map_err(|(buf, _)| QueueFull(buf))?;
+        self.queued += usize::from(len);
+        if self.queue.len() == 1 {
+            self.start();
+        }
+        Ok(self.queued)
+    }
+
+    /// Returns `true` if another buffer can be queued
+    pub fn has_space(&self) -> bool {
+        !self.queue.is_full()
+    }
+
+    /// Number of bytes queued or being sent
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    /// Returns `true` when all buffers were sent and the last byte left the shift register
+    pub fn is_idle(&self) -> bool {
+        self.queue.is_empty() && self.tx.is_transmission_complete()
+    }
+
+    /// Handler for the stream interrupt, returns the buffer that was sent and starts the
+    /// next one
+    ///
+    /// On a bus error the rest of the buffer is dropped and it is returned all the same.
+    pub fn on_interrupt(&mut self) -> Option<BUF> {
+        if !STREAM::get_transfer_complete_flag() && !STREAM::get_transfer_error_flag() {
+            return None;
+        }
+        self.stream.disable();
+        self.stream.clear_interrupts();
+
+        // "Subsequent reads and writes cannot be moved ahead of preceding reads"
+        compiler_fence(Ordering::Acquire);
+        let (done, len) = self.queue.pop_front()?;
+        self.queued -= usize::from(len);
+        if !self.queue.is_empty() {
+            self.start();
+        }
+        Some(done)
+    }
+
+    /// Stops the stream and releases the transmitter, the stream and the buffers not sent
+    /// completely with their length
+    ///
+    /// Waits for the last byte written by the stream to be shifted out.
+    pub fn release(mut self) -> (Tx<USART, u8>, STREAM, Deque<(BUF, u16), N>) {
+        self.stream.disable();
+        self.stream
+            .set_interrupts_enable(false, false, false, false);
+        self.stream.clear_interrupts();
+        while !self.tx.is_transmission_complete() {}
+        let usart = unsafe { &*USART::ptr() };
+        usart.cr3.modify(|_, w| w.dmat().clear_bit());
+        (self.tx, self.stream, self.queue)
+    }
+
+    /// Stops the stream and empties the queue, returns the buffers not sent completely
+    fn abort(&mut self) -> Deque<(BUF, u16), N> {
+        self.stream.disable();
+        self.stream.clear_interrupts();
+        // "Subsequent reads and writes cannot be moved ahead of preceding reads"
+        compiler_fence(Ordering::Acquire);
+        self.queued = 0;
+        core::mem::replace(&mut self.queue, Deque::new())
+    }
+
+    /// Starts sending the front buffer
+    fn start(&mut self) {
+        let (buf, len) = match self.queue.front() {
+            Some(front) => front,
+            None => return,
+        };
+        // NOTE(unsafe) the buffer stays in the queue until the stream finished with it
+        let (ptr, _) = unsafe { buf.read_buffer() };
+        self.stream.set_memory_address(ptr as u32);
+        self.stream.set_number_of_transfers(*len);
+
+        // TC is set again once the last byte of this buffer was sent
+        let usart = unsafe { &*USART::ptr() };
+        usart.sr.write(|w| unsafe { w.bits(!(1 << 6)) });
+
+        // "Preceding reads and writes cannot be moved past subsequent writes"
+        compiler_fence(Ordering::Release);
+        unsafe {
+            self.stream.enable();
+        }
+    }
+}
+
+/// Queue of `N` static byte buffers
+type SliceQueue<USART, STREAM, const CHANNEL: u8, const N: usize> =
+    TxDmaQueue<USART, STREAM, CHANNEL, &'static mut [u8], N>;
+
+/// Formats into a pool of `N` buffers sent by a [`TxDmaQueue`]
+///
+/// A buffer is sent once it is full, or right away if the stream is idle. When all the
+/// buffers are in use, the output is dropped and [`fmt::Error`] returned.
+///
+/// The writer is shared with the stream interrupt, which calls
+/// [`on_interrupt`](Self::on_interrupt), e.g. through a `Mutex`.
+pub struct DmaWriter<USART: Instance, STREAM, const CHANNEL: u8, const N: usize> {
+    queue: SliceQueue<USART, STREAM, CHANNEL, N>,
+    free: Deque<&'static mut [u8], N>,
+    fill: Option<(&'static mut [u8], usize)>,
+}
+
+impl<USART: Instance, STREAM, const CHANNEL: u8, const N: usize>
+    DmaWriter<USART, STREAM, CHANNEL, N>
+where
+    STREAM: Stream,
+    ChannelX<CHANNEL>: Channel,
+    Tx<USART, u8>: DMASet<STREAM, CHANNEL, MemoryToPeripheral>,
+{
+    /// Formats into up to `N` non-empty `buffers`, the extra ones are dropped
+    pub fn new(
+        queue: SliceQueue<USART, STREAM, CHANNEL, N>,
+        buffers: impl IntoIterator<Item = &'static mut [u8]>,
+    ) -> Self {
+        let mut free = Deque::new();
+        for buf in buffers {
+            assert!(!buf.is_empty());
+            if free.push_back(buf).is_err() {
+                break;
+            }
+        }
+        Self {
+            queue,
+            free,
+            fill: None,
+        }
+    }
+
+    /// Handler for the stream interrupt, recycles the buffer that was sent
+    pub fn on_interrupt(&mut self) {
+        if let Some(buf) = self.queue.on_interrupt() {
+            // The pool had this buffer before, it has room for it
+            let _ = self.free.push_back(buf);
+        }
+        if self.queue.queued() == 0 {
+            self.send_fill();
+        }
+    }
+
+    /// Returns `true` when all the output was sent
+    pub fn is_idle(&self) -> bool {
+        self.fill.is_none() && self.queue.is_idle()
+    }
+
+    /// Releases the queue and the buffers that are not queued
+    pub fn release(
+        mut self,
+    ) -> (
+        SliceQueue<USART, STREAM, CHANNEL, N>,
+        Deque<&'static mut [u8], N>,
+    ) {
+        if let Some((buf, _)) = self.fill.take() {
+            let _ = self.free.push_back(buf);
+        }
+        (self.queue, self.free)
+    }
+
+    fn send_fill(&mut self) {
+        if let Some((buf, len)) = self.fill.take() {
+            if len == 0 {
+                self.fill = Some((buf, len));
+            } else if let Err(QueueFull(buf)) = self.queue.enqueue_len(buf, len) {
+                self.fill = Some((buf, len));
+            }
+        }
+    }
+}
+
+impl<USART: Instance, STREAM, const CHANNEL: u8, const N: usize> fmt::Write
+    for DmaWriter<USART, STREAM, CHANNEL, N>
+where
+    STREAM: Stream,
+    ChannelX<CHANNEL>: Channel,
+    Tx<USART, u8>: DMASet<STREAM, CHANNEL, MemoryToPeripheral>,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut bytes = s.as_bytes();
+        while !bytes.is_empty() {
+            if self.fill.is_none() {
+                match self.free.pop_front() {
+                    Some(buf) => self.fill = Some((buf, 0)),
+                    None => return Err(fmt::Error),
+                }
+            }
+            if let Some((buf, len)) = self.fill.as_mut() {
+                let n = bytes.len().min(buf.len() - *len);
+                buf[*len..*len + n].copy_from_slice(&bytes[..n]);
+                *len += n;
+                bytes = &bytes[n..];
+                if *len == buf.len() {
+                    self.send_fill();
+                    if self.fill.is_some() {
+                        return Err(fmt::Error);
+                    }
+                }
+            }
+        }
+        if self.queue.queued() == 0 {
+            self.send_fill();
+        }
+        Ok(())
+    }
+}
+
+/// Sends two buffers in turn, one is filled while the other one is sent
+///
+/// A buffer passed to [`write_dma`](Self::write_dma) while the other one is being sent is
+/// started from the transfer complete interrupt, back to back with the previous one, as
+/// the data register still holds the last byte of the previous buffer.
+pub struct DoubleBufferTx<USART: Instance, STREAM, const CHANNEL: u8> {
+    queue: SliceQueue<USART, STREAM, CHANNEL, 2>,
+    buffers: Buffers<&'static mut [u8]>,
+    on_complete: Option<fn(usize)>,
+}
+
+impl<USART: Instance, STREAM, const CHANNEL: u8> DoubleBufferTx<USART, STREAM, CHANNEL>
+where
+    STREAM: Stream,
+    ChannelX<CHANNEL>: Channel,
+    Tx<USART, u8>: DMASet<STREAM, CHANNEL, MemoryToPeripheral>,
+{
+    /// Sends `buffers` in turn with `queue`
+    pub fn new(
+        queue: SliceQueue<USART, STREAM, CHANNEL, 2>,
+        buffers: [&'static mut [u8]; 2],
+    ) -> Self {
+        Self {
+            queue,
+            buffers: Buffers::new(buffers),
+            on_complete: None,
+        }
+    }
+
+    /// Calls `f` from the stream interrupt each time a buffer was sent, with the number of
+    /// bytes still queued
+    pub fn on_complete(&mut self, f: fn(usize)) {
+        self.on_complete = Some(f);
+    }
+
+    /// The buffer to fill next, `None` while both buffers are queued
+    pub fn back_buffer(&mut self) -> Option<&mut [u8]> {
+        self.buffers.back().map(|buf| &mut **buf)
+    }
+
+    /// Sends the first `len` bytes of the back buffer, after the other buffer if it is
+    /// being sent
+    ///
+    /// Returns `WouldBlock` while both buffers are queued.
+    ///
+    /// # Panics
+    ///
+    /// When `len` is zero or longer than the buffer.
+    pub fn write_dma(&mut self, len: usize) -> nb::Result<(), core::convert::Infallible> {
+        let buf = self.buffers.take_back().ok_or(nb::Error::WouldBlock)?;
+        assert!(len > 0 && len <= buf.len());
+        if let Err(QueueFull(buf)) = self.queue.enqueue_len(buf, len) {
+            // Never happens, the queue has room for both buffers
+            self.buffers.put(buf);
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(())
+    }
+
+    /// Handler for the stream interrupt, swaps the buffers and calls the completion
+    /// callback
+    pub fn on_interrupt(&mut self) {
+        if let Some(buf) = self.queue.on_interrupt() {
+            self.buffers.put(buf);
+            if let Some(f) = self.on_complete {
+                f(self.queue.queued());
+            }
+        }
+    }
+
+    /// Returns `true` when both buffers were sent and the last byte left the shift
+    /// register
+    pub fn is_idle(&self) -> bool {
+        self.queue.is_idle()
+    }
+
+    /// Stops sending and releases the queue and the buffers
+    ///
+    /// The data not sent yet is dropped, wait for [`is_idle`](Self::is_idle) first to send
+    /// everything.
+    pub fn release(
+        mut self,
+    ) -> (
+        SliceQueue<USART, STREAM, CHANNEL, 2>,
+        [&'static mut [u8]; 2],
+    ) {
+        let mut queued = self.queue.abort();
+        while let Some((buf, _)) = queued.pop_front() {
+            self.buffers.put(buf);
+        }
+        // Both buffers are back once the queue is empty
+        let buffers = self.buffers.take_all().unwrap();
+        (self.queue, buffers)
+    }
+}
+
+/// Buffers of a [`DoubleBufferTx`] that are not queued
+struct Buffers<B> {
+    back: Option<B>,
+    spare: Option<B>,
+}
+
+impl<B> Buffers<B> {
+    fn new([back, spare]: [B; 2]) -> Self {
+        Self {
+            back: Some(back),
+            spare: Some(spare),
+        }
+    }
+
+    /// The buffer to fill next, the spare one once the back buffer was queued
+    fn back(&mut self) -> Option<&mut B> {
+        if self.back.is_none() {
+            self.back = self.spare.take();
+        }
+        self.back.as_mut()
+    }
+
+    /// Takes the back buffer to queue it
+    fn take_back(&mut self) -> Option<B> {
+        self.back();
+        self.back.take()
+    }
+
+    /// Returns a buffer that was sent, it is filled next unless the back buffer is
+    /// already there
+    fn put(&mut self, buf: B) {
+        if self.back.is_none() {
+            self.back = Some(buf);
+        } else {
+            self.spare = Some(buf);
+        }
+    }
+
+    /// Both buffers, back buffer first, if none is queued
+    fn take_all(&mut self) -> Option<[B; 2]> {
+        match (self.back.take(), self.spare.take()) {
+            (Some(back), Some(spare)) => Some([back, spare]),
+            (back, spare) => {
+                self.back = back;
+                self.spare = spare;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Buffers;
+
+    #[test]
+    fn swap_on_transfer_complete() {
+        let mut buffers = Buffers::new([b'a', b'b']);
+        assert_eq!(buffers.back(), Some(&mut b'a'));
+
+        // `a` is sent, `b` is filled meanwhile
+        assert_eq!(buffers.take_back(), Some(b'a'));
+        assert_eq!(buffers.back(), Some(&mut b'b'));
+        // `b` is queued behind `a`
+        assert_eq!(buffers.take_back(), Some(b'b'));
+        assert_eq!(buffers.back(), None);
+        assert_eq!(buffers.take_back(), None);
+        assert_eq!(buffers.take_all(), None);
+
+        // TC of `a`, it is filled next
+        buffers.put(b'a');
+        assert_eq!(buffers.back(), Some(&mut b'a'));
+        // TC of `b` while `a` is being filled, `a` stays the back buffer
+        buffers.put(b'b');
+        assert_eq!(buffers.take_back(), Some(b'a'));
+        assert_eq!(buffers.back(), Some(&mut b'b'));
+    }
+
+    #[test]
+    fn take_all_once_both_are_back() {
+        let mut buffers = Buffers::new([1, 2]);
+        assert_eq!(buffers.take_back(), Some(1));
+        assert_eq!(buffers.take_all(), None);
+        // Nothing lost by the failed attempt
+        assert_eq!(buffers.back(), Some(&mut 2));
+        buffers.put(1);
+        assert_eq!(buffers.take_all(), Some([2, 1]));
+    }
+}
@@ -1,4 +1,4 @@
-use embedded_hal_one::serial::ErrorType;
+use embedded_hal_nb::serial::ErrorType;
 
 impl<USART, PINS, WORD> ErrorType for super::Serial<USART, PINS, WORD> {
     type Error = super::Error;
@@ -14,10 +14,7 @@ impl<USART, WORD> ErrorType for super::Tx<USART, WORD> {
 
 mod nb {
     use super::super::{Error, Instance, Rx, Serial, Tx};
-    use embedded_hal_one::serial::{
-        nb::{Read, Write},
-        ErrorType,
-    };
+    use embedded_hal_nb::serial::{ErrorType, Read, Write};
 
     impl<USART, PINS, WORD: Copy> Read<WORD> for Serial<USART, PINS, WORD>
     where
@@ -85,43 +82,3 @@ mod nb {
         }
     }
 }
-
-mod blocking {
-    use super::super::{Instance, Serial, Tx};
-    use super::ErrorType;
-    use embedded_hal_one::serial::blocking::Write;
-
-    impl<USART, PINS, WORD: Copy> Write<WORD> for Serial<USART, PINS, WORD>
-    where
-        USART: Instance,
-        Tx<USART, WORD>: Write<WORD> + ErrorType<Error = Self::Error>,
-    {
-        fn write(&mut self, slice: &[WORD]) -> Result<(), Self::Error> {
-            self.tx.write(slice)
-        }
-
-        fn flush(&mut self) -> Result<(), Self::Error> {
-            self.tx.flush()
-        }
-    }
-
-    impl<USART: Instance> Write<u8> for Tx<USART, u8> {
-        fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
-            self.bwrite_all(bytes)
-        }
-
-        fn flush(&mut self) -> Result<(), Self::Error> {
-            self.bflush()
-        }
-    }
-
-    impl<USART: Instance> Write<u16> for Tx<USART, u16> {
-        fn write(&mut self, slice: &[u16]) -> Result<(), Self::Error> {
-            self.bwrite_all(slice)
-        }
-
-        fn flush(&mut self) -> Result<(), Self::Error> {
-            self.bflush()
-        }
-    }
-}
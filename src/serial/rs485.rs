@@ -0,0 +1,293 @@
+//! RS-485 half-duplex transmitter with software driver enable
+//!
+//! F4 USARTs have no hardware DE output, so [`Rs485`] drives a GPIO connected to the DE
+//! (and usually /RE) input of the transceiver: DE is asserted before the first word, kept
+//! asserted until the transmission complete (TC) flag is set, then released after an
+//! optional guard time.
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::digital::v2::OutputPin;
+use fugit::MicrosDurationU32;
+
+use super::{Error, Instance, Tx};
+
+/// Transmitter driving an RS-485 driver enable pin around each write
+pub struct Rs485<USART, DE, DELAY, WORD = u8> {
+    tx: Tx<USART, WORD>,
+    de: DE,
+    delay: DELAY,
+    assertion_time: MicrosDurationU32,
+    deassertion_time: MicrosDurationU32,
+}
+
+impl<USART, DE, DELAY, WORD> Rs485<USART, DE, DELAY, WORD>
+where
+    USART: Instance,
+    DE: OutputPin,
+    DELAY: DelayUs<u32>,
+{
+    /// Wraps a transmitter, DE is released (driven low) immediately
+    pub fn new(tx: Tx<USART, WORD>, mut de: DE, delay: DELAY) -> Self {
+        de.set_low().ok();
+        Self {
+            tx,
+            de,
+            delay,
+            assertion_time: MicrosDurationU32::from_ticks(0),
+            deassertion_time: MicrosDurationU32::from_ticks(0),
+        }
+    }
+
+    /// Sets the time DE is asserted before the first word is sent
+    pub fn assertion_time(mut self, time: MicrosDurationU32) -> Self {
+        self.assertion_time = time;
+        self
+    }
+
+    /// Sets the time DE stays asserted after the last word is completely sent
+    pub fn deassertion_time(mut self, time: MicrosDurationU32) -> Self {
+        self.deassertion_time = time;
+        self
+    }
+
+    /// Releases the transmitter, DE pin and delay
+    pub fn release(self) -> (Tx<USART, WORD>, DE, DELAY) {
+        (self.tx, self.de, self.delay)
+    }
+
+    /// Asserts DE, runs `f`, waits for TC and de-asserts DE after the guard time
+    fn transmit(
+        &mut self,
+        f: impl FnOnce(&mut Tx<USART, WORD>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let tx = &mut self.tx;
+        drive_enable(
+            &mut self.de,
+            &mut self.delay,
+            self.assertion_time,
+            self.deassertion_time,
+            || f(tx),
+            || Tx::<USART, u16>::new().flush(),
+        )
+    }
+}
+
+/// Asserts `de`, runs `send`, polls `flush` until the transmission is complete and
+/// de-asserts `de`, with the guard times in between
+fn drive_enable<DE: OutputPin, DELAY: DelayUs<u32>>(
+    de: &mut DE,
+    delay: &mut DELAY,
+    assertion_time: MicrosDurationU32,
+    deassertion_time: MicrosDurationU32,
+    send: impl FnOnce() -> Result<(), Error>,
+    mut flush: impl FnMut() -> nb::Result<(), Error>,
+) -> Result<(), Error> {
+    de.set_high().ok();
+    if assertion_time.ticks() > 0 {
+        delay.delay_us(assertion_time.ticks());
+    }
+
+    let result = send().and_then(|_| nb::block!(flush()));
+
+    if deassertion_time.ticks() > 0 {
+        delay.delay_us(deassertion_time.ticks());
+    }
+    de.set_low().ok();
+    result
+}
+
+impl<USART, DE, DELAY> Rs485<USART, DE, DELAY, u8>
+where
+    USART: Instance,
+    DE: OutputPin,
+    DELAY: DelayUs<u32>,
+{
+    /// Sends `bytes` with DE asserted, returns once the line is released
+    pub fn bwrite_all(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.transmit(|tx| tx.bwrite_all(bytes))
+    }
+}
+
+impl<USART, DE, DELAY> Rs485<USART, DE, DELAY, u16>
+where
+    USART: Instance,
+    DE: OutputPin,
+    DELAY: DelayUs<u32>,
+{
+    /// Sends 9-bit `words` with DE asserted, returns once the line is released
+    pub fn bwrite_all(&mut self, words: &[u16]) -> Result<(), Error> {
+        self.transmit(|tx| tx.bwrite_all(words))
+    }
+}
+
+mod blocking {
+    use super::{Error, Instance, Rs485};
+    use embedded_hal::blocking::delay::DelayUs;
+    use embedded_hal::blocking::serial::Write;
+    use embedded_hal::digital::v2::OutputPin;
+
+    impl<USART, DE, DELAY> Write<u8> for Rs485<USART, DE, DELAY, u8>
+    where
+        USART: Instance,
+        DE: OutputPin,
+        DELAY: DelayUs<u32>,
+    {
+        type Error = Error;
+
+        fn bwrite_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+            self.bwrite_all(bytes)
+        }
+
+        fn bflush(&mut self) -> Result<(), Self::Error> {
+            // Every write already waits for the end of transmission
+            Ok(())
+        }
+    }
+
+    impl<USART, DE, DELAY> Write<u16> for Rs485<USART, DE, DELAY, u16>
+    where
+        USART: Instance,
+        DE: OutputPin,
+        DELAY: DelayUs<u32>,
+    {
+        type Error = Error;
+
+        fn bwrite_all(&mut self, words: &[u16]) -> Result<(), Self::Error> {
+            self.bwrite_all(words)
+        }
+
+        fn bflush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::RefCell;
+    use core::convert::Infallible;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Step {
+        DeHigh,
+        DeLow,
+        Delay(u32),
+        Send,
+        /// Status poll, with the TC flag read
+        Poll(bool),
+    }
+
+    struct Log {
+        steps: [Step; 16],
+        len: usize,
+    }
+
+    impl Log {
+        fn new() -> RefCell<Self> {
+            RefCell::new(Self {
+                steps: [Step::Send; 16],
+                len: 0,
+            })
+        }
+
+        fn push(&mut self, step: Step) {
+            self.steps[self.len] = step;
+            self.len += 1;
+        }
+
+        fn steps(&self) -> &[Step] {
+            &self.steps[..self.len]
+        }
+    }
+
+    struct DePin<'a>(&'a RefCell<Log>);
+
+    impl OutputPin for DePin<'_> {
+        type Error = Infallible;
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.0.borrow_mut().push(Step::DeHigh);
+            Ok(())
+        }
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.0.borrow_mut().push(Step::DeLow);
+            Ok(())
+        }
+    }
+
+    struct Delay<'a>(&'a RefCell<Log>);
+
+    impl DelayUs<u32> for Delay<'_> {
+        fn delay_us(&mut self, us: u32) {
+            self.0.borrow_mut().push(Step::Delay(us));
+        }
+    }
+
+    #[test]
+    fn de_released_after_tc() {
+        let log = Log::new();
+        let mut polls = 0;
+        let result = drive_enable(
+            &mut DePin(&log),
+            &mut Delay(&log),
+            MicrosDurationU32::from_ticks(10),
+            MicrosDurationU32::from_ticks(5),
+            || {
+                log.borrow_mut().push(Step::Send);
+                Ok(())
+            },
+            || {
+                // TXE is already set, TC only on the third poll
+                polls += 1;
+                let tc = polls == 3;
+                log.borrow_mut().push(Step::Poll(tc));
+                if tc {
+                    Ok(())
+                } else {
+                    Err(nb::Error::WouldBlock)
+                }
+            },
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            log.borrow().steps(),
+            [
+                Step::DeHigh,
+                Step::Delay(10),
+                Step::Send,
+                Step::Poll(false),
+                Step::Poll(false),
+                Step::Poll(true),
+                Step::Delay(5),
+                Step::DeLow,
+            ]
+        );
+    }
+
+    #[test]
+    fn de_released_on_error() {
+        let log = Log::new();
+        let result = drive_enable(
+            &mut DePin(&log),
+            &mut Delay(&log),
+            MicrosDurationU32::from_ticks(0),
+            MicrosDurationU32::from_ticks(0),
+            || {
+                log.borrow_mut().push(Step::Send);
+                Err(Error::Other)
+            },
+            || {
+                log.borrow_mut().push(Step::Poll(true));
+                Ok(())
+            },
+        );
+        assert_eq!(result, Err(Error::Other));
+        // No guard times, no TC wait after a failed write
+        assert_eq!(
+            log.borrow().steps(),
+            [Step::DeHigh, Step::Send, Step::DeLow]
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! ISO 7816-3 smartcard mode
+//!
+//! The card I/O line is the TX pin, which must be open-drain with a pull-up, the card
+//! clock is the `USARTx_CK` pin. As [`Serial`] sets its pins to push-pull, both are
+//! configured by hand and the serial is created without pins.
+//!
+//! Characters are 8 data bits with even parity and 1.5 stop bits, the baud rate set in the
+//! serial configuration must match the card clock divided by the card's Fi/Di ratio (372
+//! at reset).
+//!
+//! A receiver detecting a parity error signals it with a NACK, the card then repeats the
+//! character. F4 USARTs don't repeat a character NACKed by the card, [`Smartcard::write`]
+//! does it in software.
+//!
+//! ```ignore
+//! let _ck = gpioa.pa8.into_alternate::<7>();
+//! let _io = gpioa.pa9.into_alternate_open_drain::<7>();
+//! // 4.2 MHz card clock from 42 MHz APB2, 4.2 MHz / 372
+//! let serial: Serial1<_> = dp.USART1.serial((NoPin, NoPin), 11290.bps(), &clocks)?;
+//! let mut card = serial.into_smartcard(2);
+//! card.enable_clock(5);
+//! ```
+
+use super::{Error, Instance, Serial};
+use crate::pac;
+
+// CR3, CR2 and GTPR fields are not available on all register blocks used by `Instance`
+const CR1_PS: u32 = 1 << 9;
+const CR1_PCE: u32 = 1 << 10;
+const CR1_M: u32 = 1 << 12;
+const CR1_TE: u32 = 1 << 3;
+const CR1_RE: u32 = 1 << 2;
+const CR2_CLKEN: u32 = 1 << 11;
+const CR2_STOP_MASK: u32 = 0b11 << 12;
+const CR2_STOP_1P5: u32 = 0b11 << 12;
+const CR2_LINEN: u32 = 1 << 14;
+const CR3_IREN: u32 = 1 << 1;
+const CR3_HDSEL: u32 = 1 << 3;
+const CR3_NACK: u32 = 1 << 4;
+const CR3_SCEN: u32 = 1 << 5;
+const SR_PE: u32 = 1 << 0;
+const SR_FE: u32 = 1 << 1;
+const SR_ORE: u32 = 1 << 3;
+const SR_RXNE: u32 = 1 << 5;
+const SR_TC: u32 = 1 << 6;
+const SR_TXE: u32 = 1 << 7;
+const GTPR_OFFSET: usize = 0x18;
+const GTPR_GT_SHIFT: u32 = 8;
+
+/// USART with smartcard mode, UARTs have none
+pub trait SmartcardInstance: Instance {}
+
+impl SmartcardInstance for pac::USART1 {}
+impl SmartcardInstance for pac::USART2 {}
+impl SmartcardInstance for pac::USART6 {}
+#[cfg(feature = "usart3")]
+impl SmartcardInstance for pac::USART3 {}
+
+/// Serial in ISO 7816-3 smartcard mode
+pub struct Smartcard<USART, PINS> {
+    serial: Serial<USART, PINS, u8>,
+    retries: u8,
+}
+
+impl<USART: SmartcardInstance, PINS> Serial<USART, PINS, u8> {
+    /// Switches to smartcard mode with `guard_time` extra bit times after each character
+    ///
+    /// The frame is changed to 8 data bits, even parity and 1.5 stop bits, NACK on
+    /// parity errors is enabled. The card clock stays disabled until
+    /// [`Smartcard::enable_clock`].
+    pub fn into_smartcard(self, guard_time: u8) -> Smartcard<USART, PINS> {
+        let usart = unsafe { &*USART::ptr() };
+        // NOTE(unsafe) the USART is owned, the frame format and CK are only written with
+        // the transmitter and receiver disabled
+        unsafe {
+            let cr1 = usart.cr1.read().bits();
+            usart.cr1.write(|w| w.bits(cr1 & !(CR1_TE | CR1_RE)));
+            usart.cr2.modify(|r, w| w.bits(smartcard_cr2(r.bits())));
+            usart.cr3.modify(|r, w| w.bits(smartcard_cr3(r.bits())));
+            usart.cr1.write(|w| w.bits(smartcard_cr1(cr1)));
+        }
+
+        let mut smartcard = Smartcard {
+            serial: self,
+            retries: 3,
+        };
+        smartcard.set_guard_time(guard_time);
+        smartcard
+    }
+}
+
+impl<USART: SmartcardInstance, PINS> Smartcard<USART, PINS> {
+    fn gtpr() -> *mut u32 {
+        (USART::ptr() as *mut u8).wrapping_add(GTPR_OFFSET) as *mut u32
+    }
+
+    /// Sets the guard time, in bit times
+    pub fn set_guard_time(&mut self, guard_time: u8) {
+        // NOTE(unsafe) GTPR belongs to the owned USART
+        unsafe {
+            let gtpr = core::ptr::read_volatile(Self::gtpr());
+            core::ptr::write_volatile(Self::gtpr(), gtpr_guard_time(gtpr, guard_time));
+        }
+    }
+
+    /// Guard time, in bit times
+    pub fn guard_time(&self) -> u8 {
+        (unsafe { core::ptr::read_volatile(Self::gtpr()) } >> GTPR_GT_SHIFT) as u8
+    }
+
+    /// Outputs the card clock on `CK`, the bus clock divided by `2 * prescaler`
+    ///
+    /// # Panics
+    ///
+    /// When `prescaler` is not in `1..=31`.
+    pub fn enable_clock(&mut self, prescaler: u8) {
+        assert!((1..=31).contains(&prescaler));
+        self.with_disabled(|usart| unsafe {
+            let gtpr = core::ptr::read_volatile(Self::gtpr());
+            core::ptr::write_volatile(Self::gtpr(), gtpr_prescaler(gtpr, prescaler));
+            usart.cr2.modify(|r, w| w.bits(r.bits() | CR2_CLKEN));
+        });
+    }
+
+    /// Stops the card clock
+    pub fn disable_clock(&mut self) {
+        self.with_disabled(|usart| unsafe {
+            usart.cr2.modify(|r, w| w.bits(r.bits() & !CR2_CLKEN));
+        });
+    }
+
+    /// Sends a NACK on received parity errors, for the card to repeat the character
+    pub fn set_nack(&mut self, nack: bool) {
+        let usart = unsafe { &*USART::ptr() };
+        // NOTE(unsafe) CR3 belongs to the owned USART
+        unsafe {
+            usart.cr3.modify(|r, w| {
+                if nack {
+                    w.bits(r.bits() | CR3_NACK)
+                } else {
+                    w.bits(r.bits() & !CR3_NACK)
+                }
+            })
+        };
+    }
+
+    /// Is NACK on received parity errors enabled?
+    pub fn is_nack_enabled(&self) -> bool {
+        unsafe { (*USART::ptr()).cr3.read().bits() & CR3_NACK != 0 }
+    }
+
+    /// Sets how many times a character NACKed by the card is sent again, 3 by default
+    pub fn set_retries(&mut self, retries: u8) {
+        self.retries = retries;
+    }
+
+    /// Runs `f` with the transmitter and receiver disabled, for fields only writable then
+    fn with_disabled(&mut self, f: impl FnOnce(&super::uart_base::RegisterBlock)) {
+        let usart = unsafe { &*USART::ptr() };
+        let cr1 = usart.cr1.read().bits();
+        unsafe { usart.cr1.write(|w| w.bits(cr1 & !(CR1_TE | CR1_RE))) };
+        f(usart);
+        unsafe { usart.cr1.write(|w| w.bits(cr1)) };
+    }
+
+    /// Sends `bytes`, repeating each character NACKed by the card
+    ///
+    /// The receiver samples the NACK on the I/O line, so it stays enabled and each character
+    /// sent is also received and discarded. Returns [`Error::Parity`] when a character is
+    /// still NACKed after the configured retries.
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let usart = unsafe { &*USART::ptr() };
+        for &byte in bytes {
+            let mut acked = false;
+            for _ in 0..=self.retries {
+                while usart.sr.read().bits() & SR_TXE == 0 {}
+                unsafe { usart.dr.write(|w| w.bits(byte.into())) };
+                while usart.sr.read().bits() & SR_TC == 0 {}
+                // FE is set when the card NACKed the character, reading SR then DR discards
+                // the echo and clears the flags
+                let sr = usart.sr.read().bits();
+                if sr & (SR_RXNE | SR_FE | SR_ORE) != 0 {
+                    let _ = usart.dr.read();
+                }
+                if sr & SR_FE == 0 {
+                    acked = true;
+                    break;
+                }
+            }
+            if !acked {
+                return Err(Error::Parity);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a character
+    ///
+    /// [`Error::Parity`] is returned for a character with a wrong parity, which the card
+    /// repeats when NACK is enabled.
+    pub fn read(&mut self) -> nb::Result<u8, Error> {
+        let usart = unsafe { &*USART::ptr() };
+        let sr = usart.sr.read().bits();
+        if sr & (SR_PE | SR_ORE) != 0 {
+            // Reading DR clears the error flags
+            let _ = usart.dr.read();
+            Err(nb::Error::Other(if sr & SR_PE != 0 {
+                Error::Parity
+            } else {
+                Error::Overrun
+            }))
+        } else if sr & SR_RXNE != 0 {
+            Ok(usart.dr.read().bits() as u8)
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Leaves smartcard mode and stops the card clock
+    ///
+    /// The frame format set by [`Serial::into_smartcard`] is kept.
+    pub fn release(mut self) -> Serial<USART, PINS, u8> {
+        self.disable_clock();
+        let usart = unsafe { &*USART::ptr() };
+        unsafe {
+            usart
+                .cr3
+                .modify(|r, w| w.bits(r.bits() & !(CR3_SCEN | CR3_NACK)))
+        };
+        self.serial
+    }
+}
+
+/// CR1 for smartcard mode: 9 bits including even parity, transmitter and receiver enabled
+const fn smartcard_cr1(cr1: u32) -> u32 {
+    (cr1 & !CR1_PS) | CR1_M | CR1_PCE | CR1_TE | CR1_RE
+}
+
+/// CR2 for smartcard mode: 1.5 stop bits, no LIN, card clock off
+const fn smartcard_cr2(cr2: u32) -> u32 {
+    (cr2 & !(CR2_STOP_MASK | CR2_LINEN | CR2_CLKEN)) | CR2_STOP_1P5
+}
+
+/// CR3 for smartcard mode: SCEN and NACK, no half-duplex or IrDA
+const fn smartcard_cr3(cr3: u32) -> u32 {
+    (cr3 & !(CR3_HDSEL | CR3_IREN)) | CR3_SCEN | CR3_NACK
+}
+
+/// GTPR with the guard time `guard_time`, keeping the prescaler
+const fn gtpr_guard_time(gtpr: u32, guard_time: u8) -> u32 {
+    (gtpr & 0xff) | (guard_time as u32) << GTPR_GT_SHIFT
+}
+
+/// GTPR with the card clock prescaler `prescaler`, keeping the guard time
+const fn gtpr_prescaler(gtpr: u32, prescaler: u8) -> u32 {
+    (gtpr & !0xff) | prescaler as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cr3_enables_scen_and_nack() {
+        assert_eq!(smartcard_cr3(0), 1 << 5 | 1 << 4);
+        // Half-duplex and IrDA are cleared, DMA enables are kept
+        assert_eq!(
+            smartcard_cr3(CR3_HDSEL | CR3_IREN | 1 << 7),
+            1 << 7 | 1 << 5 | 1 << 4
+        );
+    }
+
+    #[test]
+    fn frame_is_9_bits_even_parity_1_5_stop() {
+        assert_eq!(smartcard_cr1(CR1_PS), 1 << 12 | 1 << 10 | 1 << 3 | 1 << 2);
+        // UE and interrupt enables are kept
+        assert_eq!(
+            smartcard_cr1(1 << 13 | 1 << 5) & (1 << 13 | 1 << 5),
+            1 << 13 | 1 << 5
+        );
+        assert_eq!(smartcard_cr2(0), 0b11 << 12);
+        assert_eq!(
+            smartcard_cr2(0b10 << 12 | CR2_LINEN | CR2_CLKEN | 0xf),
+            0b11 << 12 | 0xf
+        );
+    }
+
+    #[test]
+    fn gtpr_fields_are_independent() {
+        assert_eq!(gtpr_guard_time(0, 2), 0x0200);
+        assert_eq!(gtpr_guard_time(0xff05, 0x10), 0x1005);
+        assert_eq!(gtpr_prescaler(0x1000, 5), 0x1005);
+        assert_eq!(gtpr_prescaler(0x10ff, 31), 0x101f);
+    }
+}
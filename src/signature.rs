@@ -52,6 +52,45 @@ impl Uid {
     pub fn lot_num(&self) -> &str {
         unsafe { from_utf8_unchecked(&self.waf_lot[1..]) }
     }
+
+    /// The 96-bit ID as stored, X and Y coordinates first
+    pub fn as_bytes(&self) -> &[u8; 12] {
+        unsafe { &*(self as *const Self as *const [u8; 12]) }
+    }
+
+    /// Writes the ID bytes of [`as_bytes`](Self::as_bytes) in lowercase hexadecimal
+    ///
+    /// The 24 characters are ASCII, usable as a USB serial number string descriptor.
+    pub fn as_hex<'a>(&self, buf: &'a mut [u8; 24]) -> &'a str {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        for (byte, hex) in self.as_bytes().iter().zip(buf.chunks_exact_mut(2)) {
+            hex[0] = DIGITS[usize::from(byte >> 4)];
+            hex[1] = DIGITS[usize::from(byte & 0xf)];
+        }
+        unsafe { from_utf8_unchecked(buf) }
+    }
+
+    /// Hexadecimal ID as UTF-16 code units, for USB string descriptors
+    pub fn as_utf16(&self) -> [u16; 24] {
+        let mut hex = [0; 24];
+        self.as_hex(&mut hex);
+        hex.map(u16::from)
+    }
+
+    /// Derives a locally administered unicast MAC address from the ID
+    ///
+    /// Both halves of the ID are folded with XOR, so the address is stable but not
+    /// guaranteed to be unique.
+    pub fn mac_address(&self) -> [u8; 6] {
+        let bytes = self.as_bytes();
+        let mut mac = [0; 6];
+        for (i, b) in mac.iter_mut().enumerate() {
+            *b = bytes[i] ^ bytes[i + 6];
+        }
+        // Locally administered, unicast
+        mac[0] = (mac[0] | 0b10) & !0b1;
+        mac
+    }
 }
 
 /// Size of integrated flash
@@ -72,6 +111,46 @@ impl FlashSize {
     }
 }
 
+/// Package data register
+#[cfg(any(
+    feature = "stm32f410",
+    feature = "stm32f412",
+    feature = "stm32f413",
+    feature = "stm32f423",
+    feature = "stm32f446",
+    feature = "stm32f469",
+    feature = "stm32f479",
+))]
+#[derive(Debug)]
+#[repr(C)]
+pub struct PackageId(u16);
+#[cfg(any(
+    feature = "stm32f410",
+    feature = "stm32f412",
+    feature = "stm32f413",
+    feature = "stm32f423",
+    feature = "stm32f446",
+    feature = "stm32f469",
+    feature = "stm32f479",
+))]
+define_ptr_type!(PackageId, 0x1FFF_7BF0);
+
+#[cfg(any(
+    feature = "stm32f410",
+    feature = "stm32f412",
+    feature = "stm32f413",
+    feature = "stm32f423",
+    feature = "stm32f446",
+    feature = "stm32f469",
+    feature = "stm32f479",
+))]
+impl PackageId {
+    /// `PKG` field, the encoding of packages is given in the reference manual of each line
+    pub fn read(&self) -> u8 {
+        ((self.0 >> 8) & 0b111) as u8
+    }
+}
+
 /// ADC VREF calibration value is stored in at the factory
 #[derive(Debug)]
 #[repr(C)]
@@ -110,3 +189,54 @@ impl VtempCal110 {
         self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uid() -> Uid {
+        Uid {
+            x: 0x0021,
+            y: 0x003a,
+            waf_lot: [0x0b, b'P', b'Q', b'D', b'8', b'3', b'5', b'1'],
+        }
+    }
+
+    #[test]
+    fn uid_fields() {
+        let uid = uid();
+        assert_eq!(uid.x(), 0x21);
+        assert_eq!(uid.y(), 0x3a);
+        assert_eq!(uid.waf_num(), 11);
+        assert_eq!(uid.lot_num(), "PQD8351");
+        assert_eq!(
+            uid.as_bytes(),
+            &[0x21, 0x00, 0x3a, 0x00, 0x0b, 0x50, 0x51, 0x44, 0x38, 0x33, 0x35, 0x31]
+        );
+    }
+
+    #[test]
+    fn uid_hex() {
+        let mut buf = [0; 24];
+        assert_eq!(uid().as_hex(&mut buf), "21003a000b50514438333531");
+
+        let utf16 = uid().as_utf16();
+        assert_eq!(utf16[..4], [0x32, 0x31, 0x30, 0x30]);
+        assert!(utf16.iter().zip(buf).all(|(&c, b)| c == u16::from(b)));
+    }
+
+    #[test]
+    fn mac_address() {
+        let mac = uid().mac_address();
+        // 0x21 ^ 0x51 = 0x70, with the locally administered bit
+        assert_eq!(mac, [0x72, 0x44, 0x02, 0x33, 0x3e, 0x61]);
+
+        let uid = Uid {
+            x: 0x0001,
+            y: 0,
+            waf_lot: [0; 8],
+        };
+        // Multicast bit cleared, local bit set
+        assert_eq!(uid.mac_address(), [0x02, 0, 0, 0, 0, 0]);
+    }
+}
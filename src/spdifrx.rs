@@ -0,0 +1,479 @@
+//! S/PDIF receiver (SPDIFRX)
+//!
+//! The receiver decodes an IEC 60958 stream from one of four inputs, `SPDIF_RX0` to
+//! `SPDIF_RX3`, set to the SPDIFRX alternate function (AF8). It goes through three states:
+//!
+//! * idle, after [`Spdifrx::new`] or [`Spdifrx::stop`],
+//! * synchronization, started by [`Spdifrx::synchronize`], where the receiver measures the
+//!   symbol rate of the stream and raises [`Event::SynchronizationDone`],
+//! * reception, started by [`Spdifrx::receive`], where samples and channel status are read
+//!   with [`Spdifrx::read`] and [`Spdifrx::read_control`], or by DMA.
+//!
+//! A serial interface error (lost signal, timeout or failed synchronization) stops the
+//! receiver, it then has to be synchronized again.
+//!
+//! The SPDIFRX kernel clock is the main PLL R output by default, it has to be at least
+//! 704 × the sample rate, i.e. 33.8 MHz for 48 kHz.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut spdif = Spdifrx::new(dp.SPDIFRX, Config::default(), 90.MHz());
+//! spdif.synchronize();
+//! while !spdif.is_pending(Event::SynchronizationDone) {}
+//! spdif.receive();
+//! let rate = spdif.sample_rate();
+//! let sample = nb::block!(spdif.read())?;
+//! ```
+
+use crate::dma::traits::{DMASet, PeriAddress};
+use crate::dma::{PeripheralToMemory, Stream1, Stream6};
+use crate::pac::{DMA1, RCC, SPDIFRX};
+use crate::rcc::{Enable, Reset};
+use crate::time::Hertz;
+
+const CR_SPDIFRXEN: u32 = 0b11;
+const CR_RXDMAEN: u32 = 1 << 2;
+const CR_RXSTEO: u32 = 1 << 3;
+const CR_CBDMAEN: u32 = 1 << 10;
+const CR_WFA: u32 = 1 << 14;
+const SR_RXNE: u32 = 1 << 0;
+const SR_CSRNE: u32 = 1 << 1;
+const SR_PERR: u32 = 1 << 2;
+const SR_OVR: u32 = 1 << 3;
+const SR_FERR: u32 = 1 << 6;
+const SR_SERR: u32 = 1 << 7;
+const SR_TERR: u32 = 1 << 8;
+
+/// SPDIFRX errors
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// A sub-frame had a parity error
+    Parity,
+    /// A sample was received before the previous one was read
+    Overrun,
+    /// The signal was lost, or synchronization failed or timed out, the receiver stopped
+    SynchronizationLost,
+}
+
+/// Input line
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Input {
+    In0 = 0,
+    In1 = 1,
+    In2 = 2,
+    In3 = 3,
+}
+
+/// Synchronization retries before giving up with [`Error::SynchronizationLost`]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Retries {
+    None = 0b00,
+    Three = 0b01,
+    Fifteen = 0b10,
+    SixtyThree = 0b11,
+}
+
+/// Receiver state
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum State {
+    Idle,
+    Synchronizing,
+    Receiving,
+}
+
+/// Interrupt events
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// A sample can be read
+    SampleReceived = 0,
+    /// Channel status and user bits can be read
+    ControlReceived = 1,
+    /// Parity error
+    ParityError = 2,
+    /// Overrun
+    Overrun = 3,
+    /// Start of a 192 frame block
+    StartOfBlock = 4,
+    /// Synchronization completed
+    SynchronizationDone = 5,
+    /// Serial interface error, the receiver stopped
+    SynchronizationLost = 6,
+}
+
+/// Receiver configuration
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    input: Input,
+    retries: Retries,
+    wait_for_activity: bool,
+    stereo: bool,
+}
+
+impl Default for Config {
+    /// Stereo from `SPDIF_RX0`, waiting for activity, with 63 retries
+    fn default() -> Self {
+        Self {
+            input: Input::In0,
+            retries: Retries::SixtyThree,
+            wait_for_activity: true,
+            stereo: true,
+        }
+    }
+}
+
+impl Config {
+    pub fn input(mut self, input: Input) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn retries(mut self, retries: Retries) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Waits for transitions on the input before synchronizing
+    pub fn wait_for_activity(mut self, wait: bool) -> Self {
+        self.wait_for_activity = wait;
+        self
+    }
+
+    /// Keeps the two channels of a frame in order, a [`Sample::channel_b`] always follows
+    /// a channel A sample, after an overrun too
+    pub fn stereo(mut self, stereo: bool) -> Self {
+        self.stereo = stereo;
+        self
+    }
+}
+
+/// Preamble of a sub-frame
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Preamble {
+    /// Channel A, first frame of a block
+    B,
+    /// Channel A
+    M,
+    /// Channel B
+    W,
+}
+
+/// Received sub-frame, as read from `SPDIFRX_DR` in the right aligned format
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Sample(pub u32);
+
+impl Sample {
+    /// 24-bit audio sample, sign-extended
+    pub fn data(self) -> i32 {
+        ((self.0 << 8) as i32) >> 8
+    }
+
+    /// 16 most significant bits of the sample
+    pub fn data_i16(self) -> i16 {
+        (self.data() >> 8) as i16
+    }
+
+    /// The sub-frame had a parity error
+    pub fn parity_error(self) -> bool {
+        self.0 & (1 << 24) != 0
+    }
+
+    /// Validity bit, set when the sample is not suitable for conversion to analog
+    pub fn validity(self) -> bool {
+        self.0 & (1 << 25) != 0
+    }
+
+    /// User data bit
+    pub fn user(self) -> bool {
+        self.0 & (1 << 26) != 0
+    }
+
+    /// Channel status bit
+    pub fn channel_status(self) -> bool {
+        self.0 & (1 << 27) != 0
+    }
+
+    /// Preamble of the sub-frame
+    pub fn preamble(self) -> Option<Preamble> {
+        match (self.0 >> 28) & 0b11 {
+            0b01 => Some(Preamble::B),
+            0b10 => Some(Preamble::M),
+            0b11 => Some(Preamble::W),
+            _ => None,
+        }
+    }
+
+    /// The sample is from channel B, usually right
+    pub fn channel_b(self) -> bool {
+        self.preamble() == Some(Preamble::W)
+    }
+}
+
+/// Channel status and user bits, as read from `SPDIFRX_CSR`
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Control(pub u32);
+
+impl Control {
+    /// User bits of 16 consecutive sub-frames
+    pub fn user(self) -> u16 {
+        self.0 as u16
+    }
+
+    /// Channel status bits of 8 consecutive frames, channel A
+    pub fn channel_status(self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+
+    /// The first channel status bit is the start of a block
+    pub fn start_of_block(self) -> bool {
+        self.0 & (1 << 24) != 0
+    }
+}
+
+/// Error counts since [`Spdifrx::new`]
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ErrorCounters {
+    pub parity: u32,
+    pub overrun: u32,
+    pub synchronization_lost: u32,
+}
+
+/// S/PDIF receiver
+pub struct Spdifrx {
+    spdifrx: SPDIFRX,
+    kernel_clock: Hertz,
+    errors: ErrorCounters,
+}
+
+impl Spdifrx {
+    /// Enables and resets the receiver, left idle
+    ///
+    /// `kernel_clock` is the frequency of the SPDIFRX clock, used to compute the sample rate.
+    pub fn new(spdifrx: SPDIFRX, config: Config, kernel_clock: Hertz) -> Self {
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            SPDIFRX::enable(rcc);
+            SPDIFRX::reset(rcc);
+        }
+
+        let wfa = if config.wait_for_activity { CR_WFA } else { 0 };
+        let stereo = if config.stereo { CR_RXSTEO } else { 0 };
+        spdifrx.cr.write(|w| unsafe {
+            w.bits(stereo | ((config.retries as u32) << 12) | wfa | ((config.input as u32) << 16))
+        });
+
+        Self {
+            spdifrx,
+            kernel_clock,
+            errors: ErrorCounters::default(),
+        }
+    }
+
+    fn set_state(&mut self, bits: u32) {
+        self.spdifrx
+            .cr
+            .modify(|r, w| unsafe { w.bits((r.bits() & !CR_SPDIFRXEN) | bits) });
+    }
+
+    /// Starts synchronizing on the input
+    ///
+    /// [`Event::SynchronizationDone`] is raised when done, or
+    /// [`Event::SynchronizationLost`] after the configured retries.
+    pub fn synchronize(&mut self) {
+        self.set_state(0b00);
+        self.set_state(0b01);
+    }
+
+    /// Starts receiving samples, after synchronization
+    pub fn receive(&mut self) {
+        self.set_state(0b11);
+    }
+
+    /// Stops the receiver, which has to be synchronized again
+    pub fn stop(&mut self) {
+        self.set_state(0b00);
+    }
+
+    /// Current state of the receiver
+    pub fn state(&self) -> State {
+        match self.spdifrx.cr.read().bits() & CR_SPDIFRXEN {
+            0b00 => State::Idle,
+            0b01 => State::Synchronizing,
+            _ => State::Receiving,
+        }
+    }
+
+    /// Sample rate of the stream, measured during synchronization
+    pub fn sample_rate(&self) -> Option<Hertz> {
+        // WIDTH5 is the length of 5 symbols in kernel clock periods, a frame has 64 symbols
+        let width5 = (self.spdifrx.sr.read().bits() >> 16) & 0x7fff;
+        if width5 == 0 {
+            None
+        } else {
+            let rate = 5 * u64::from(self.kernel_clock.raw()) / (64 * u64::from(width5));
+            Some(Hertz::from_raw(rate as u32))
+        }
+    }
+
+    /// Starts listening for an interrupt `event`
+    pub fn listen(&mut self, event: Event) {
+        self.spdifrx
+            .imr
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << event as u32)) });
+    }
+
+    /// Stops listening for an interrupt `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.spdifrx
+            .imr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << event as u32)) });
+    }
+
+    /// Returns `true` if the `event` flag is set
+    pub fn is_pending(&self, event: Event) -> bool {
+        let sr = self.spdifrx.sr.read().bits();
+        match event {
+            Event::SynchronizationLost => sr & (SR_FERR | SR_SERR | SR_TERR) != 0,
+            _ => sr & (1 << event as u32) != 0,
+        }
+    }
+
+    /// Clears the `event` flag
+    ///
+    /// [`Event::SampleReceived`] and [`Event::ControlReceived`] are cleared by reading,
+    /// [`Event::SynchronizationLost`] by [`synchronize`](Self::synchronize) or
+    /// [`stop`](Self::stop).
+    pub fn clear_interrupt(&mut self, event: Event) {
+        match event {
+            Event::ParityError
+            | Event::Overrun
+            | Event::StartOfBlock
+            | Event::SynchronizationDone => self
+                .spdifrx
+                .ifcr
+                .write(|w| unsafe { w.bits(1 << event as u32) }),
+            _ => {}
+        }
+    }
+
+    /// Counts and clears the error flags, to be called from the interrupt handler when
+    /// listening for the error events
+    pub fn on_interrupt(&mut self) -> Result<(), Error> {
+        let sr = self.spdifrx.sr.read().bits();
+        let mut result = Ok(());
+        if sr & SR_PERR != 0 {
+            self.errors.parity += 1;
+            result = Err(Error::Parity);
+        }
+        if sr & SR_OVR != 0 {
+            self.errors.overrun += 1;
+            result = Err(Error::Overrun);
+        }
+        self.spdifrx
+            .ifcr
+            .write(|w| unsafe { w.bits(sr & (SR_PERR | SR_OVR)) });
+        if sr & (SR_FERR | SR_SERR | SR_TERR) != 0 {
+            self.errors.synchronization_lost += 1;
+            // The error flags are cleared when leaving the stopped state
+            self.stop();
+            result = Err(Error::SynchronizationLost);
+        }
+        result
+    }
+
+    /// Error counts
+    pub fn errors(&self) -> ErrorCounters {
+        self.errors
+    }
+
+    /// Reads a received sample
+    pub fn read(&mut self) -> nb::Result<Sample, Error> {
+        self.on_interrupt()?;
+        if self.spdifrx.sr.read().bits() & SR_RXNE == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(Sample(self.spdifrx.dr.read().bits()))
+    }
+
+    /// Reads received channel status and user bits
+    pub fn read_control(&mut self) -> nb::Result<Control, Error> {
+        self.on_interrupt()?;
+        if self.spdifrx.sr.read().bits() & SR_CSRNE == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(Control(self.spdifrx.csr.read().bits()))
+    }
+
+    /// Enables the DMA requests for samples and for channel status and user bits
+    pub fn enable_dma(&mut self, samples: bool, control: bool) {
+        self.spdifrx.cr.modify(|r, w| unsafe {
+            let mut bits = r.bits() & !(CR_RXDMAEN | CR_CBDMAEN);
+            if samples {
+                bits |= CR_RXDMAEN;
+            }
+            if control {
+                bits |= CR_CBDMAEN;
+            }
+            w.bits(bits)
+        });
+    }
+
+    /// Handles for DMA transfers of samples and of channel status and user bits
+    ///
+    /// The receiver state is still controlled through `self`, which must not read samples or
+    /// control bits while their DMA transfer runs.
+    pub fn dma_channels(&mut self) -> (SampleChannel, ControlChannel) {
+        (SampleChannel { _0: () }, ControlChannel { _0: () })
+    }
+
+    /// Releases the SPDIFRX peripheral
+    pub fn release(mut self) -> SPDIFRX {
+        self.stop();
+        self.spdifrx
+    }
+}
+
+/// DMA source of samples, `SPDIFRX_DR`
+pub struct SampleChannel {
+    _0: (),
+}
+
+/// DMA source of channel status and user bits, `SPDIFRX_CSR`
+pub struct ControlChannel {
+    _0: (),
+}
+
+unsafe impl PeriAddress for SampleChannel {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        // NOTE(unsafe) only the address is taken
+        unsafe { &(*SPDIFRX::ptr()).dr as *const _ as u32 }
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl PeriAddress for ControlChannel {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        // NOTE(unsafe) only the address is taken
+        unsafe { &(*SPDIFRX::ptr()).csr as *const _ as u32 }
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl DMASet<Stream1<DMA1>, 0, PeripheralToMemory> for SampleChannel {}
+unsafe impl DMASet<Stream6<DMA1>, 0, PeripheralToMemory> for ControlChannel {}
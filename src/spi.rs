@@ -8,6 +8,7 @@ use crate::pac;
 
 /// Clock polarity
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Polarity {
     /// Clock signal low when idle
     IdleLow,
@@ -17,6 +18,7 @@ pub enum Polarity {
 
 /// Clock phase
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Phase {
     /// Data in "captured" on the first clock transition
     CaptureOnFirstTransition,
@@ -26,6 +28,7 @@ pub enum Phase {
 
 /// SPI mode
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Mode {
     /// Clock polarity
     pub polarity: Polarity,
@@ -33,6 +36,9 @@ pub struct Mode {
     pub phase: Phase,
 }
 
+#[cfg(feature = "async")]
+#[clippy::msrv = "1.75"]
+pub mod asynch;
 mod hal_02;
 mod hal_1;
 
@@ -53,6 +59,8 @@ pub enum Error {
     ModeFault,
     /// CRC error
     Crc,
+    /// DMA transfer error
+    Dma,
 }
 
 pub struct Sck;
@@ -145,6 +153,7 @@ impl FrameSize for u16 {
 
 /// The bit format to send the data in
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum BitFormat {
     /// Least significant bit first
     LsbFirst,
@@ -165,6 +174,9 @@ pub trait Instance:
 {
     #[doc(hidden)]
     fn ptr() -> *const spi1::RegisterBlock;
+    #[cfg(feature = "async")]
+    #[doc(hidden)]
+    fn waker() -> &'static atomic_waker::AtomicWaker;
 }
 
 // Implemented by all SPI instances
@@ -177,6 +189,12 @@ macro_rules! spi {
             fn ptr() -> *const spi1::RegisterBlock {
                 <$SPI>::ptr() as *const _
             }
+
+            #[cfg(feature = "async")]
+            fn waker() -> &'static atomic_waker::AtomicWaker {
+                static WAKER: atomic_waker::AtomicWaker = atomic_waker::AtomicWaker::new();
+                &WAKER
+            }
         }
     };
 }
@@ -459,6 +477,7 @@ where
     SPI: Instance,
     (SCK, MISO, MOSI): Pins<SPI>,
 {
+    /// Releases the SPI peripheral and pins, set back to their mode before construction
     pub fn release(mut self) -> (SPI, (SCK, MISO, MOSI)) {
         self.pins.restore_mode();
 
@@ -492,6 +511,30 @@ impl<SPI: Instance, PINS, const BIDI: bool, W, OPERATION> Spi<SPI, PINS, BIDI, W
         });
     }
 
+    /// Resets the peripheral through its RCC reset line, keeping the current configuration
+    ///
+    /// A transfer in progress is aborted and received data is discarded.
+    pub fn reset(&mut self) {
+        // spe: enable the SPI bus
+        const SPE: u32 = 1 << 6;
+
+        let cr1 = self.spi.cr1.read().bits();
+        let cr2 = self.spi.cr2.read().bits();
+        let crcpr = self.spi.crcpr.read().bits();
+
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            SPI::reset(rcc);
+        }
+
+        self.spi.crcpr.write(|w| unsafe { w.bits(crcpr) });
+        self.spi.cr2.write(|w| unsafe { w.bits(cr2) });
+        // Configure first, then enable like `init`
+        self.spi.cr1.write(|w| unsafe { w.bits(cr1 & !SPE) });
+        self.spi.cr1.write(|w| unsafe { w.bits(cr1) });
+    }
+
     /// Pre initializing the SPI bus.
     fn pre_init(self, mode: Mode, freq: Hertz, clock: Hertz, is_master: bool) -> Self {
         // disable SS output
@@ -633,48 +676,173 @@ where
 impl<SPI: Instance, PINS, const BIDI: bool, W: FrameSize, OPERATION>
     Spi<SPI, PINS, BIDI, W, OPERATION>
 {
+    /// Reads the received word, or returns `WouldBlock` if none was received yet
+    ///
+    /// An overrun is reported as [`Error::Overrun`] and cleared, the lost words can't be
+    /// recovered. In bidirectional mode the output is disabled first.
+    pub fn read_nonblocking(&mut self) -> nb::Result<W, Error> {
+        if BIDI {
+            self.spi.cr1.modify(|_, w| w.bidioe().clear_bit());
+        }
+        self.check_read()
+    }
+
+    /// Writes `byte` to the transmit buffer, or returns `WouldBlock` if it is still full
+    ///
+    /// In bidirectional mode the output is enabled first.
+    pub fn write_nonblocking(&mut self, byte: W) -> nb::Result<(), Error> {
+        if BIDI {
+            self.spi.cr1.modify(|_, w| w.bidioe().set_bit());
+        }
+        self.check_send(byte)
+    }
+
+    /// Sends `words` and replaces each of them with the word received at the same time
+    ///
+    /// The next word is written to the transmit buffer before the current one is read
+    /// back, which keeps the bus busy without gaps. The received word must then be read
+    /// within one word time, an interrupt taking longer causes [`Error::Overrun`], so
+    /// unlike the `embedded-hal` `transfer_in_place`, which sends one word at a time, this
+    /// needs interrupts to be short or disabled. In bidirectional mode the words are sent
+    /// and received one at a time.
+    pub fn transfer_in_place_pipelined(&mut self, words: &mut [W]) -> Result<(), Error> {
+        if BIDI {
+            for word in words {
+                nb::block!(self.write_nonblocking(*word))?;
+                *word = nb::block!(self.read_nonblocking())?;
+            }
+            return Ok(());
+        }
+
+        if let Some(&first) = words.first() {
+            nb::block!(self.check_send(first))?;
+        }
+        for i in 0..words.len() {
+            // The word `i + 1` has not been overwritten yet
+            if let Some(&next) = words.get(i + 1) {
+                nb::block!(self.check_send(next))?;
+            }
+            words[i] = nb::block!(self.check_read())?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends `words` and drops the received ones, then waits for the bus to be idle
+    ///
+    /// Only the transmit buffer is checked between words, so the bus is kept busy
+    /// without gaps. The overrun caused by the words not read is cleared at the end.
+    /// The `spi-throughput` example compares it with a word-by-word full-duplex loop.
+    pub fn write(&mut self, words: &[W]) -> Result<(), Error> {
+        self.write_iter(words.iter().copied())
+    }
+
+    /// Same as [`write`](Self::write) for words from an iterator
+    pub fn write_iter(&mut self, words: impl IntoIterator<Item = W>) -> Result<(), Error> {
+        if BIDI {
+            self.spi.cr1.modify(|_, w| w.bidioe().set_bit());
+        }
+        for word in words {
+            loop {
+                let sr = self.spi.sr.read();
+                if sr.modf().bit_is_set() {
+                    // Write to CR1 to clear MODF
+                    self.spi.cr1.modify(|_r, w| w);
+                    return Err(Error::ModeFault);
+                } else if sr.txe().bit_is_set() {
+                    break;
+                }
+            }
+            self.write_data_reg(word);
+        }
+        self.wait_idle();
+        if !BIDI {
+            // Drop the last word received and clear the overrun, reading DR then SR
+            let _ = self.spi.dr.read();
+            let _ = self.spi.sr.read();
+        }
+        Ok(())
+    }
+
+    /// Sends `fill` for each word of `words` and stores the received words
+    ///
+    /// As with [`transfer_in_place_pipelined`](Self::transfer_in_place_pipelined), the next
+    /// word is sent before the current one is read. In bidirectional mode only the clock is
+    /// output, `fill` is not sent.
+    pub fn read_fill(&mut self, words: &mut [W], fill: W) -> Result<(), Error> {
+        if BIDI {
+            for word in words {
+                *word = nb::block!(self.read_nonblocking())?;
+            }
+            return Ok(());
+        }
+
+        if !words.is_empty() {
+            nb::block!(self.check_send(fill))?;
+        }
+        let len = words.len();
+        for (i, word) in words.iter_mut().enumerate() {
+            if i + 1 < len {
+                nb::block!(self.check_send(fill))?;
+            }
+            *word = nb::block!(self.check_read())?;
+        }
+
+        Ok(())
+    }
+
+    /// Waits for the last word to be sent completely
+    ///
+    /// The transmit buffer is empty first, then the busy flag is cleared once the last
+    /// word left the shift register, so the chip select can be released afterwards.
+    pub fn wait_idle(&mut self) {
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        while self.spi.sr.read().bsy().bit_is_set() {}
+    }
+
     #[inline(always)]
     fn check_read(&mut self) -> nb::Result<W, Error> {
-        let sr = self.spi.sr.read();
-
-        Err(if sr.ovr().bit_is_set() {
-            Error::Overrun.into()
-        } else if sr.modf().bit_is_set() {
-            Error::ModeFault.into()
-        } else if sr.crcerr().bit_is_set() {
-            Error::Crc.into()
-        } else if sr.rxne().bit_is_set() {
-            return Ok(self.read_data_reg());
-        } else {
-            nb::Error::WouldBlock
-        })
+        match status(self.spi.sr.read().bits(), SR_RXNE) {
+            Status::Ready => Ok(self.read_data_reg()),
+            Status::Busy => Err(nb::Error::WouldBlock),
+            Status::Error(e) => Err(self.clear_error(e).into()),
+        }
     }
 
     #[inline(always)]
     fn check_send(&mut self, byte: W) -> nb::Result<(), Error> {
-        let sr = self.spi.sr.read();
+        match status(self.spi.sr.read().bits(), SR_TXE) {
+            Status::Ready => {
+                self.write_data_reg(byte);
+                Ok(())
+            }
+            Status::Busy => Err(nb::Error::WouldBlock),
+            Status::Error(e) => Err(self.clear_error(e).into()),
+        }
+    }
 
-        Err(if sr.ovr().bit_is_set() {
-            // Read from the DR to clear the OVR bit
-            let _ = self.spi.dr.read();
-            Error::Overrun.into()
-        } else if sr.modf().bit_is_set() {
-            // Write to CR1 to clear MODF
-            self.spi.cr1.modify(|_r, w| w);
-            Error::ModeFault.into()
-        } else if sr.crcerr().bit_is_set() {
-            // Clear the CRCERR bit
-            self.spi.sr.modify(|_r, w| {
-                w.crcerr().clear_bit();
-                w
-            });
-            Error::Crc.into()
-        } else if sr.txe().bit_is_set() {
-            self.write_data_reg(byte);
-            return Ok(());
-        } else {
-            nb::Error::WouldBlock
-        })
+    /// Clears the flag of `error`, the status register was read just before
+    fn clear_error(&mut self, error: Error) -> Error {
+        match error {
+            Error::Overrun => {
+                // Read from the DR then the SR to clear the OVR bit
+                let _ = self.spi.dr.read();
+                let _ = self.spi.sr.read();
+            }
+            Error::ModeFault => {
+                // Write to CR1 to clear MODF
+                self.spi.cr1.modify(|_r, w| w);
+            }
+            Error::Crc => {
+                // Clear the CRCERR bit
+                self.spi.sr.modify(|_r, w| {
+                    w.crcerr().clear_bit();
+                    w
+                });
+            }
+            Error::Dma => {}
+        }
+        error
     }
 }
 
@@ -739,3 +907,69 @@ unsafe impl<SPI: Instance> PeriAddress for Tx<SPI> {
 
     type MemSize = u8;
 }
+
+const SR_RXNE: u32 = 1 << 0;
+const SR_TXE: u32 = 1 << 1;
+const SR_CRCERR: u32 = 1 << 4;
+const SR_MODF: u32 = 1 << 5;
+const SR_OVR: u32 = 1 << 6;
+
+/// Status of a data register access
+#[derive(Debug, PartialEq)]
+enum Status {
+    /// The `ready` flag is set
+    Ready,
+    /// The access would block
+    Busy,
+    /// An error flag is set, it has to be cleared
+    Error(Error),
+}
+
+/// Decodes the status register value `sr` for an access waiting on the `ready` flag,
+/// errors first
+fn status(sr: u32, ready: u32) -> Status {
+    if sr & SR_OVR != 0 {
+        Status::Error(Error::Overrun)
+    } else if sr & SR_MODF != 0 {
+        Status::Error(Error::ModeFault)
+    } else if sr & SR_CRCERR != 0 {
+        Status::Error(Error::Crc)
+    } else if sr & ready != 0 {
+        Status::Ready
+    } else {
+        Status::Busy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_would_block_without_rxne() {
+        assert_eq!(status(0, SR_RXNE), Status::Busy);
+        // TXE does not make a word available
+        assert_eq!(status(SR_TXE, SR_RXNE), Status::Busy);
+        assert_eq!(status(SR_RXNE, SR_RXNE), Status::Ready);
+    }
+
+    #[test]
+    fn send_would_block_without_txe() {
+        assert_eq!(status(0, SR_TXE), Status::Busy);
+        assert_eq!(status(SR_RXNE, SR_TXE), Status::Busy);
+        assert_eq!(status(SR_TXE, SR_TXE), Status::Ready);
+    }
+
+    #[test]
+    fn errors_come_first() {
+        assert_eq!(
+            status(SR_RXNE | SR_OVR, SR_RXNE),
+            Status::Error(Error::Overrun)
+        );
+        assert_eq!(
+            status(SR_TXE | SR_MODF | SR_CRCERR, SR_TXE),
+            Status::Error(Error::ModeFault)
+        );
+        assert_eq!(status(SR_CRCERR, SR_RXNE), Status::Error(Error::Crc));
+    }
+}
@@ -0,0 +1,317 @@
+//! Async SPI master over DMA
+//!
+//! Full-duplex transfers run on a TX and an RX DMA stream through a `'static` bounce
+//! buffer given to [`Spi::into_async`]: the user buffers are copied in and out, so the
+//! DMA never accesses memory that could be freed while the stream runs, even if a
+//! transfer future is leaked. Transfers longer than the bounce buffer are split.
+//!
+//! Transfers shorter than [`DMA_THRESHOLD`] bytes skip the DMA setup and use the RXNE
+//! interrupt for each byte instead. The SPI interrupt and both DMA stream interrupts
+//! have to be bound and unmasked:
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn SPI1() {
+//!     spi::asynch::on_interrupt::<pac::SPI1>();
+//! }
+//!
+//! #[interrupt]
+//! fn DMA2_STREAM2() {
+//!     dma::asynch::on_interrupt::<Stream2<DMA2>>();
+//! }
+//!
+//! #[interrupt]
+//! fn DMA2_STREAM3() {
+//!     dma::asynch::on_interrupt::<Stream3<DMA2>>();
+//! }
+//! ```
+//!
+//! Dropping a transfer future aborts both streams, then waits for the byte being shifted
+//! and discards received data, leaving the SPI idle.
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use super::{Error, Instance, Master, Rx, Spi, Tx};
+use crate::dma::asynch::{AsyncStream, OneShot};
+use crate::dma::traits::{Channel, DMASet};
+use crate::dma::{ChannelX, MemoryToPeripheral, PeripheralToMemory};
+
+/// Transfers shorter than this use the RXNE interrupt instead of DMA
+pub const DMA_THRESHOLD: usize = 8;
+
+/// Handler for the `SPIx` interrupt, used by transfers shorter than [`DMA_THRESHOLD`]
+pub fn on_interrupt<SPI: Instance>() {
+    // Mask the interrupts, the flags are handled by the waiting transfer
+    let spi = unsafe { &*SPI::ptr() };
+    spi.cr2.modify(|_, w| {
+        w.rxneie()
+            .clear_bit()
+            .txeie()
+            .clear_bit()
+            .errie()
+            .clear_bit()
+    });
+    SPI::waker().wake();
+}
+
+/// Disables the DMA requests and leaves the SPI idle, also when a transfer is dropped
+struct FlushOnDrop<SPI: Instance> {
+    _spi: PhantomData<SPI>,
+}
+
+impl<SPI: Instance> Drop for FlushOnDrop<SPI> {
+    fn drop(&mut self) {
+        let spi = unsafe { &*SPI::ptr() };
+        spi.cr2.modify(|_, w| {
+            w.txdmaen()
+                .clear_bit()
+                .rxdmaen()
+                .clear_bit()
+                .rxneie()
+                .clear_bit()
+                .errie()
+                .clear_bit()
+        });
+
+        // Wait for the byte being shifted, then discard received data and clear OVR
+        while spi.sr.read().bsy().bit_is_set() {}
+        while spi.sr.read().rxne().bit_is_set() {
+            let _ = spi.dr.read();
+        }
+        let _ = spi.sr.read();
+    }
+}
+
+/// SPI master implementing the `embedded-hal-async` traits
+pub struct AsyncSpi<SPI: Instance, PINS, TXS, RXS, const TXCH: u8, const RXCH: u8> {
+    spi: Spi<SPI, PINS, false, u8, Master>,
+    tx_stream: TXS,
+    rx_stream: RXS,
+    buffer: &'static mut [u8],
+}
+
+impl<SPI: Instance, PINS> Spi<SPI, PINS, false, u8, Master> {
+    /// Converts into an async SPI master using `tx_stream` and `rx_stream` for DMA
+    ///
+    /// `buffer` is the bounce buffer of the DMA transfers, it must not be empty.
+    pub fn into_async<TXS, RXS, const TXCH: u8, const RXCH: u8>(
+        self,
+        tx_stream: TXS,
+        rx_stream: RXS,
+        buffer: &'static mut [u8],
+    ) -> AsyncSpi<SPI, PINS, TXS, RXS, TXCH, RXCH>
+    where
+        TXS: AsyncStream,
+        RXS: AsyncStream,
+        Tx<SPI>: DMASet<TXS, TXCH, MemoryToPeripheral>,
+        Rx<SPI>: DMASet<RXS, RXCH, PeripheralToMemory>,
+    {
+        assert!(!buffer.is_empty());
+        AsyncSpi {
+            spi: self,
+            tx_stream,
+            rx_stream,
+            buffer,
+        }
+    }
+}
+
+impl<SPI, PINS, TXS, RXS, const TXCH: u8, const RXCH: u8> AsyncSpi<SPI, PINS, TXS, RXS, TXCH, RXCH>
+where
+    SPI: Instance,
+    TXS: AsyncStream,
+    RXS: AsyncStream,
+    Tx<SPI>: DMASet<TXS, TXCH, MemoryToPeripheral>,
+    Rx<SPI>: DMASet<RXS, RXCH, PeripheralToMemory>,
+    ChannelX<TXCH>: Channel,
+    ChannelX<RXCH>: Channel,
+{
+    /// Releases the SPI, the DMA streams and the bounce buffer
+    #[allow(clippy::type_complexity)]
+    pub fn release(
+        self,
+    ) -> (
+        Spi<SPI, PINS, false, u8, Master>,
+        TXS,
+        RXS,
+        &'static mut [u8],
+    ) {
+        (self.spi, self.tx_stream, self.rx_stream, self.buffer)
+    }
+
+    fn chunk_len(&self) -> usize {
+        self.buffer.len().min(u16::MAX as usize)
+    }
+
+    pub async fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        for words in words.chunks_mut(self.chunk_len()) {
+            self.buffer[..words.len()].fill(0);
+            self.exchange(words.len()).await?;
+            words.copy_from_slice(&self.buffer[..words.len()]);
+        }
+        Ok(())
+    }
+
+    pub async fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        for words in words.chunks(self.chunk_len()) {
+            self.buffer[..words.len()].copy_from_slice(words);
+            self.exchange(words.len()).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes `write` while reading into `read`
+    ///
+    /// If the lengths differ, the shorter buffer is padded with `0` words on write and
+    /// extra received words are discarded.
+    pub async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        let len = read.len().max(write.len());
+        let mut offset = 0;
+        while offset < len {
+            let n = self.chunk_len().min(len - offset);
+            for (i, word) in self.buffer[..n].iter_mut().enumerate() {
+                *word = write.get(offset + i).copied().unwrap_or(0);
+            }
+            self.exchange(n).await?;
+            if let Some(read) = read.get_mut(offset..) {
+                let m = read.len().min(n);
+                read[..m].copy_from_slice(&self.buffer[..m]);
+            }
+            offset += n;
+        }
+        Ok(())
+    }
+
+    pub async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        for words in words.chunks_mut(self.chunk_len()) {
+            self.buffer[..words.len()].copy_from_slice(words);
+            self.exchange(words.len()).await?;
+            words.copy_from_slice(&self.buffer[..words.len()]);
+        }
+        Ok(())
+    }
+
+    /// Waits until the last word was shifted out
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        while self.spi.is_busy() {}
+        Ok(())
+    }
+
+    /// Exchanges the first `len` bytes of the bounce buffer in place
+    async fn exchange(&mut self, len: usize) -> Result<(), Error> {
+        let flush = FlushOnDrop::<SPI> { _spi: PhantomData };
+
+        if len < DMA_THRESHOLD {
+            for i in 0..len {
+                let word = self.buffer[i];
+                self.buffer[i] = self.exchange_word(word).await?;
+            }
+        } else {
+            let memory = self.buffer.as_ptr();
+            // SAFETY: the bounce buffer is `'static` and not accessed until the streams stop
+            let mut rx = unsafe {
+                OneShot::start::<_, PeripheralToMemory, RXCH>(
+                    &mut self.rx_stream,
+                    &Rx::<SPI> { spi: PhantomData },
+                    memory,
+                    len as u16,
+                    true,
+                )
+            };
+            let mut tx = unsafe {
+                OneShot::start::<_, MemoryToPeripheral, TXCH>(
+                    &mut self.tx_stream,
+                    &Tx::<SPI> { spi: PhantomData },
+                    memory,
+                    len as u16,
+                    true,
+                )
+            };
+
+            // RX requests are enabled first, so no received byte is missed
+            self.spi.spi.cr2.modify(|_, w| w.rxdmaen().set_bit());
+            self.spi.spi.cr2.modify(|_, w| w.txdmaen().set_bit());
+
+            // The last byte is received after it was sent, RX completes last
+            rx.wait().await.map_err(|_| Error::Dma)?;
+            tx.wait().await.map_err(|_| Error::Dma)?;
+        }
+
+        // Nothing left to flush on success
+        core::mem::forget(flush);
+        let spi = &self.spi.spi;
+        spi.cr2
+            .modify(|_, w| w.txdmaen().clear_bit().rxdmaen().clear_bit());
+        Ok(())
+    }
+
+    /// Sends `word` and waits on the RXNE interrupt for the received word
+    async fn exchange_word(&mut self, word: u8) -> Result<u8, Error> {
+        loop {
+            match self.spi.check_send(word) {
+                Ok(()) => break,
+                Err(nb::Error::Other(e)) => return Err(e),
+                Err(nb::Error::WouldBlock) => {}
+            }
+        }
+
+        poll_fn(|cx| {
+            SPI::waker().register(cx.waker());
+
+            match self.spi.check_read() {
+                Ok(word) => Poll::Ready(Ok(word)),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => {
+                    self.spi
+                        .spi
+                        .cr2
+                        .modify(|_, w| w.rxneie().set_bit().errie().set_bit());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl<SPI, PINS, TXS, RXS, const TXCH: u8, const RXCH: u8> embedded_hal_async::spi::ErrorType
+    for AsyncSpi<SPI, PINS, TXS, RXS, TXCH, RXCH>
+where
+    SPI: Instance,
+{
+    type Error = Error;
+}
+
+impl<SPI, PINS, TXS, RXS, const TXCH: u8, const RXCH: u8> embedded_hal_async::spi::SpiBus
+    for AsyncSpi<SPI, PINS, TXS, RXS, TXCH, RXCH>
+where
+    SPI: Instance,
+    TXS: AsyncStream,
+    RXS: AsyncStream,
+    Tx<SPI>: DMASet<TXS, TXCH, MemoryToPeripheral>,
+    Rx<SPI>: DMASet<RXS, RXCH, PeripheralToMemory>,
+    ChannelX<TXCH>: Channel,
+    ChannelX<RXCH>: Channel,
+{
+    async fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        Self::read(self, words).await
+    }
+
+    async fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        Self::write(self, words).await
+    }
+
+    async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        Self::transfer(self, read, write).await
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        Self::transfer_in_place(self, words).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Self::flush(self).await
+    }
+}
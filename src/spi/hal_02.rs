@@ -38,17 +38,11 @@ mod nb {
         type Error = Error;
 
         fn read(&mut self) -> nb::Result<W, Error> {
-            if BIDI {
-                self.spi.cr1.modify(|_, w| w.bidioe().clear_bit());
-            }
-            self.check_read()
+            self.read_nonblocking()
         }
 
         fn send(&mut self, byte: W) -> nb::Result<(), Error> {
-            if BIDI {
-                self.spi.cr1.modify(|_, w| w.bidioe().set_bit());
-            }
-            self.check_send(byte)
+            self.write_nonblocking(byte)
         }
     }
 }
@@ -97,7 +91,7 @@ mod blocking {
         type Error = Error;
 
         fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
-            self.write_iter(words.iter().copied())
+            Self::write(self, words)
         }
     }
 
@@ -111,14 +105,7 @@ mod blocking {
         where
             WI: IntoIterator<Item = u8>,
         {
-            for word in words.into_iter() {
-                nb::block!(self.send(word))?;
-                if !BIDI {
-                    nb::block!(self.read())?;
-                }
-            }
-
-            Ok(())
+            Self::write_iter(self, words)
         }
     }
 
@@ -129,7 +116,7 @@ mod blocking {
         type Error = Error;
 
         fn write(&mut self, words: &[u16]) -> Result<(), Self::Error> {
-            self.write_iter(words.iter().copied())
+            Self::write(self, words)
         }
     }
 
@@ -143,14 +130,7 @@ mod blocking {
         where
             WI: IntoIterator<Item = u16>,
         {
-            for word in words.into_iter() {
-                nb::block!(self.send(word))?;
-                if !BIDI {
-                    nb::block!(self.read())?;
-                }
-            }
-
-            Ok(())
+            Self::write_iter(self, words)
         }
     }
 
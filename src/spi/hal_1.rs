@@ -32,7 +32,7 @@ impl Error for super::Error {
         match self {
             Self::Overrun => ErrorKind::Overrun,
             Self::ModeFault => ErrorKind::ModeFault,
-            Self::Crc => ErrorKind::Other,
+            Self::Crc | Self::Dma => ErrorKind::Other,
         }
     }
 }
@@ -43,34 +43,26 @@ impl<SPI, PINS, const BIDI: bool, W> ErrorType for super::Spi<SPI, PINS, BIDI, W
 
 mod nb {
     use super::super::{Error, FrameSize, Instance, Spi};
-    use embedded_hal_one::spi::nb::FullDuplex;
+    use embedded_hal_nb::spi::FullDuplex;
 
     impl<SPI, PINS, const BIDI: bool, W: FrameSize> FullDuplex<W> for Spi<SPI, PINS, BIDI, W>
     where
         SPI: Instance,
     {
         fn read(&mut self) -> nb::Result<W, Error> {
-            if BIDI {
-                self.spi.cr1.modify(|_, w| w.bidioe().clear_bit());
-            }
-            self.check_read()
+            self.read_nonblocking()
         }
 
         fn write(&mut self, byte: W) -> nb::Result<(), Error> {
-            if BIDI {
-                self.spi.cr1.modify(|_, w| w.bidioe().set_bit());
-            }
-            self.check_send(byte)
+            self.write_nonblocking(byte)
         }
     }
 }
 
 mod blocking {
     use super::super::{FrameSize, Instance, Spi};
-    use embedded_hal_one::spi::{
-        blocking::{SpiBus, SpiBusFlush, SpiBusRead, SpiBusWrite},
-        nb::FullDuplex,
-    };
+    use embedded_hal_nb::spi::FullDuplex;
+    use embedded_hal_one::spi::SpiBus;
 
     impl<SPI, PINS, const BIDI: bool, W: FrameSize + 'static> SpiBus<W> for Spi<SPI, PINS, BIDI, W>
     where
@@ -85,53 +77,32 @@ mod blocking {
             Ok(())
         }
 
-        fn transfer(&mut self, buff: &mut [W], data: &[W]) -> Result<(), Self::Error> {
-            assert_eq!(data.len(), buff.len());
-
-            for (d, b) in data.iter().cloned().zip(buff.iter_mut()) {
-                nb::block!(<Self as FullDuplex<W>>::write(self, d))?;
-                *b = nb::block!(<Self as FullDuplex<W>>::read(self))?;
+        /// Transfers `max(read.len(), write.len())` words, the extra words written are
+        /// zeros, the extra words read are dropped
+        fn transfer(&mut self, read: &mut [W], write: &[W]) -> Result<(), Self::Error> {
+            let len = read.len().max(write.len());
+            for i in 0..len {
+                let word = write.get(i).copied().unwrap_or_default();
+                nb::block!(<Self as FullDuplex<W>>::write(self, word))?;
+                let word = nb::block!(<Self as FullDuplex<W>>::read(self))?;
+                if let Some(r) = read.get_mut(i) {
+                    *r = word;
+                }
             }
 
             Ok(())
         }
-    }
-
-    impl<SPI, PINS, const BIDI: bool, W> SpiBusFlush for Spi<SPI, PINS, BIDI, W>
-    where
-        SPI: Instance,
-    {
-        fn flush(&mut self) -> Result<(), Self::Error> {
-            Ok(())
-        }
-    }
 
-    impl<SPI, PINS, const BIDI: bool, W: FrameSize + 'static> SpiBusWrite<W> for Spi<SPI, PINS, BIDI, W>
-    where
-        SPI: Instance,
-    {
         fn write(&mut self, words: &[W]) -> Result<(), Self::Error> {
-            for word in words {
-                nb::block!(<Self as FullDuplex<W>>::write(self, *word))?;
-                if !BIDI {
-                    nb::block!(<Self as FullDuplex<W>>::read(self))?;
-                }
-            }
-
-            Ok(())
+            Self::write(self, words)
         }
-    }
 
-    impl<SPI, PINS, const BIDI: bool, W: FrameSize + 'static> SpiBusRead<W> for Spi<SPI, PINS, BIDI, W>
-    where
-        SPI: Instance,
-    {
         fn read(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
-            for word in words {
-                nb::block!(<Self as FullDuplex<W>>::write(self, W::default()))?;
-                *word = nb::block!(<Self as FullDuplex<W>>::read(self))?;
-            }
+            Self::read_fill(self, words, W::default())
+        }
 
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Self::wait_idle(self);
             Ok(())
         }
     }
@@ -19,10 +19,20 @@ pub mod counter;
 pub use counter::*;
 pub mod delay;
 pub use delay::*;
+pub mod ext_clock;
+pub use ext_clock::{Etr, ExtClockMode, ExternalClock};
+pub mod output_compare;
+pub use output_compare::{OcChannel, OcMode};
 mod pins;
 pub use pins::*;
 pub mod pwm;
 pub use pwm::*;
+pub mod soft_pwm;
+pub use soft_pwm::SoftPwm;
+pub mod tick;
+pub use tick::SysTickTimer;
+pub mod remap;
+pub use remap::{CaptureError, Ti1Input, Ti4Input};
 #[cfg(not(feature = "stm32f410"))]
 pub mod pwm_input;
 #[cfg(not(feature = "stm32f410"))]
@@ -66,6 +76,28 @@ bitflags::bitflags! {
         const C2 = 1 << 2;
         const C3 = 1 << 3;
         const C4 = 1 << 4;
+        /// Trigger input event, not available on TIM6, TIM7, TIM10, TIM11, TIM13 and TIM14
+        const Trigger = 1 << 6;
+    }
+}
+
+/// Events of a timer with `channels` capture/compare channels
+///
+/// Timers with one channel and the basic timers have no trigger input.
+const fn timer_events(channels: u8) -> Event {
+    let ccr = match channels {
+        0 => 0,
+        1 => Event::C1.bits(),
+        2 => Event::C1.bits() | Event::C2.bits() | Event::Trigger.bits(),
+        _ => Event::all().bits(),
+    };
+    Event::from_bits_truncate(Event::Update.bits() | ccr)
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Event {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Event({=u32:#b})", self.bits());
     }
 }
 
@@ -75,6 +107,8 @@ pub enum Error {
     /// Timer is disabled
     Disabled,
     WrongAutoReload,
+    /// Repetition count of 0 or over the 8-bit repetition counter
+    WrongRepetition,
 }
 
 pub trait TimerExt: Sized {
@@ -215,6 +249,88 @@ pub enum Ocm {
     PwmMode2 = 7,
 }
 
+/// External trigger (ETR) input polarity
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EtrPolarity {
+    /// ETR is active on high level or rising edge
+    NonInverted = 0,
+    /// ETR is active on low level or falling edge
+    Inverted = 1,
+}
+
+/// External trigger (ETR) input prescaler
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum EtrPrescaler {
+    Div1 = 0,
+    Div2 = 1,
+    Div4 = 2,
+    Div8 = 3,
+}
+
+/// External trigger (ETR) input configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EtrConfig {
+    pub polarity: EtrPolarity,
+    pub prescaler: EtrPrescaler,
+    /// Digital filter (`ETF`), `0` disables filtering, up to `15`
+    pub filter: u8,
+}
+
+impl Default for EtrConfig {
+    fn default() -> Self {
+        Self {
+            polarity: EtrPolarity::NonInverted,
+            prescaler: EtrPrescaler::Div1,
+            filter: 0,
+        }
+    }
+}
+
+impl EtrConfig {
+    /// `SMCR` bits for this configuration (`ETF`, `ETPS` and `ETP` fields)
+    #[inline(always)]
+    pub(crate) const fn smcr_bits(&self) -> u32 {
+        ((self.filter as u32 & 0xf) << 8)
+            | ((self.prescaler as u32) << 12)
+            | ((self.polarity as u32) << 15)
+    }
+}
+
+/// `OCxCE` bit position of `channel` in its `CCMRx` register
+#[inline(always)]
+pub(crate) const fn ocxce_offset(channel: Channel) -> u8 {
+    (channel as u8 % 2) * 8 + 7
+}
+
+/// `URS` bit of `CR1`, only counter overflows raise the update flag
+const CR1_URS: u32 = 1 << 2;
+/// `ARPE` bit of `CR1`
+const CR1_ARPE: u32 = 1 << 7;
+/// `UG` bit of `EGR`
+const EGR_UG: u32 = 1 << 0;
+
+/// `CR1` with the auto-reload preload set to `preload`
+#[inline(always)]
+const fn arpe_cr1(cr1: u32, preload: bool) -> u32 {
+    if preload {
+        cr1 | CR1_ARPE
+    } else {
+        cr1 & !CR1_ARPE
+    }
+}
+
+/// `CR1`, `EGR` and `CR1` values written in turn to generate an update event from `cr1`
+///
+/// `URS` is set around the `UG` write so that no update interrupt or DMA request is raised.
+#[inline(always)]
+const fn update_event_writes(cr1: u32) -> [u32; 3] {
+    [cr1 | CR1_URS, EGR_UG, cr1 & !CR1_URS]
+}
+
 /// Wrapper type that indicates which register of the contained timer to use for DMA.
 pub struct CCR<T, const C: u8>(T);
 pub type CCR1<T> = CCR<T, 0>;
@@ -222,13 +338,34 @@ pub type CCR2<T> = CCR<T, 1>;
 pub type CCR3<T> = CCR<T, 2>;
 pub type CCR4<T> = CCR<T, 3>;
 
+/// Update event control shared by [`Timer`] and [`FTimer`]
+macro_rules! update_control {
+    () => {
+        /// Generates an update event, loading the preloaded prescaler and auto-reload
+        /// values right away instead of at the next counter overflow
+        ///
+        /// The counter is reset. No update interrupt or DMA request is generated.
+        pub fn apply_config_now(&mut self) {
+            self.tim.trigger_update();
+        }
+
+        /// Buffers auto-reload writes (`ARPE`) until the next update event,
+        /// otherwise they take effect immediately
+        pub fn set_auto_reload_preload(&mut self, preload: bool) {
+            self.tim.enable_preload(preload);
+        }
+    };
+}
+
 /// Wrapper type that indicates which register of the contained timer to use for DMA.
 pub struct DMAR<T>(T);
 
 mod sealed {
-    use super::{Channel, Event, Ocm};
+    use super::{Channel, EtrConfig, Event, Ocm};
     pub trait General {
         type Width: Into<u32> + From<u16>;
+        /// Events the timer has, the other DIER and SR bits are reserved
+        const EVENTS: Event;
         fn max_auto_reload() -> u32;
         unsafe fn set_auto_reload_unchecked(&mut self, arr: u32);
         fn set_auto_reload(&mut self, arr: u32) -> Result<(), super::Error>;
@@ -255,16 +392,28 @@ mod sealed {
         fn read_cc_value(channel: u8) -> u32;
         fn set_cc_value(channel: u8, value: u32);
         fn preload_output_channel_in_mode(&mut self, channel: Channel, mode: Ocm);
+        fn output_channel_in_mode(channel: Channel, mode: Ocm);
         fn start_pwm(&mut self);
         fn enable_channel(channel: u8, b: bool);
     }
 
+    pub trait WithEtr: WithPwm {
+        fn configure_etr(&mut self, etr: EtrConfig);
+        fn enable_ocref_clear(&mut self, channel: Channel, b: bool);
+        fn set_slave_mode(&mut self, mask: u32, smcr: u32);
+        fn configure_ti_input(&mut self, channel: u8, filter: u8, inverted: bool);
+    }
+
     pub trait MasterTimer: General {
         type Mms;
         fn master_mode(&mut self, mode: Self::Mms);
     }
+
+    pub trait WithRcr: General {
+        fn set_repetition_counter(&mut self, rcr: u8);
+    }
 }
-pub(crate) use sealed::{General, MasterTimer, WithPwm};
+pub(crate) use sealed::{General, MasterTimer, WithEtr, WithPwm, WithRcr};
 
 pub trait Instance:
     crate::Sealed + rcc::Enable + rcc::Reset + rcc::BusTimerClock + General
@@ -285,6 +434,7 @@ macro_rules! hal {
 
             impl General for $TIM {
                 type Width = $bits;
+                const EVENTS: Event = timer_events(0 $(+ channels!($cnum))?);
 
                 #[inline(always)]
                 fn max_auto_reload() -> u32 {
@@ -311,7 +461,7 @@ macro_rules! hal {
                 }
                 #[inline(always)]
                 fn enable_preload(&mut self, b: bool) {
-                    self.cr1.modify(|_, w| w.arpe().bit(b));
+                    self.cr1.modify(|r, w| unsafe { w.bits(arpe_cr1(r.bits(), b)) });
                 }
                 #[inline(always)]
                 fn enable_counter(&mut self) {
@@ -339,9 +489,10 @@ macro_rules! hal {
                 }
                 #[inline(always)]
                 fn trigger_update(&mut self) {
-                    self.cr1.modify(|_, w| w.urs().set_bit());
-                    self.egr.write(|w| w.ug().set_bit());
-                    self.cr1.modify(|_, w| w.urs().clear_bit());
+                    let [urs, ug, restore] = update_event_writes(self.cr1.read().bits());
+                    self.cr1.write(|w| unsafe { w.bits(urs) });
+                    self.egr.write(|w| unsafe { w.bits(ug) });
+                    self.cr1.write(|w| unsafe { w.bits(restore) });
                 }
                 #[inline(always)]
                 fn clear_interrupt_flag(&mut self, event: Event) {
@@ -349,6 +500,7 @@ macro_rules! hal {
                 }
                 #[inline(always)]
                 fn listen_interrupt(&mut self, event: Event, b: bool) {
+                    let event = event & Self::EVENTS;
                     if b {
                         self.dier.modify(|r, w| unsafe { w.bits(r.bits() | event.bits()) });
                     } else {
@@ -357,7 +509,7 @@ macro_rules! hal {
                 }
                 #[inline(always)]
                 fn get_interrupt_flag(&self) -> Event {
-                    Event::from_bits_truncate(self.sr.read().bits())
+                    Event::from_bits_truncate(self.sr.read().bits()) & Self::EVENTS
                 }
                 #[inline(always)]
                 fn read_count(&self) -> Self::Width {
@@ -401,6 +553,18 @@ macro_rules! hal {
     }
 }
 
+macro_rules! channels {
+    (CH1) => {
+        1
+    };
+    (CH2) => {
+        2
+    };
+    (CH4) => {
+        4
+    };
+}
+
 macro_rules! with_dmar {
     ($TIM:ty, $memsize:ty) => {
         unsafe impl PeriAddress for DMAR<$TIM> {
@@ -414,6 +578,70 @@ macro_rules! with_dmar {
     };
 }
 
+macro_rules! with_rcr {
+    ($($TIM:ty),+) => {
+        $(
+            impl WithRcr for $TIM {
+                #[inline(always)]
+                fn set_repetition_counter(&mut self, rcr: u8) {
+                    self.rcr.write(|w| unsafe { w.bits(rcr.into()) });
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! with_etr {
+    ($($TIM:ty),+) => {
+        $(
+            impl WithEtr for $TIM {
+                #[inline(always)]
+                fn configure_etr(&mut self, etr: EtrConfig) {
+                    // ETF, ETPS, ETP; ECE is left untouched
+                    self.smcr.modify(|r, w| unsafe {
+                        w.bits((r.bits() & !0xbf00) | etr.smcr_bits())
+                    });
+                }
+
+                #[inline(always)]
+                fn enable_ocref_clear(&mut self, channel: Channel, b: bool) {
+                    let offset = ocxce_offset(channel);
+                    let bits = |r: u32| if b { r | (1 << offset) } else { r & !(1 << offset) };
+                    match channel {
+                        Channel::C1 | Channel::C2 => self
+                            .ccmr1_output()
+                            .modify(|r, w| unsafe { w.bits(bits(r.bits())) }),
+                        Channel::C3 | Channel::C4 => self
+                            .ccmr2_output()
+                            .modify(|r, w| unsafe { w.bits(bits(r.bits())) }),
+                    }
+                }
+
+                #[inline(always)]
+                fn set_slave_mode(&mut self, mask: u32, smcr: u32) {
+                    // Only the `mask` fields, MSM is left untouched
+                    self.smcr.modify(|r, w| unsafe { w.bits((r.bits() & !mask) | (smcr & mask)) });
+                }
+
+                #[inline(always)]
+                fn configure_ti_input(&mut self, channel: u8, filter: u8, inverted: bool) {
+                    // CCxS = 01 (input mapped on TIx), ICxF
+                    let offset = channel * 8;
+                    let ccmr = (0b01 | ((filter as u32 & 0xf) << 4)) << offset;
+                    self.ccmr1_input().modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(0xf3 << offset)) | ccmr)
+                    });
+                    // CCxP selects the active edge or level, CCxNP is kept cleared
+                    let offset = channel * 4;
+                    self.ccer.modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(0b1011 << offset)) | ((inverted as u32) << (offset + 1)))
+                    });
+                }
+            }
+        )+
+    };
+}
+
 macro_rules! with_pwm {
     ($TIM:ty: CH1) => {
         impl WithPwm for $TIM {
@@ -443,6 +671,18 @@ macro_rules! with_pwm {
                 }
             }
 
+            #[inline(always)]
+            fn output_channel_in_mode(channel: Channel, mode: Ocm) {
+                let tim = unsafe { &*<$TIM>::ptr() };
+                match channel {
+                    Channel::C1 => {
+                        tim.ccmr1_output()
+                        .modify(|_, w| w.oc1pe().clear_bit().oc1m().bits(mode as _) );
+                    }
+                    _ => {},
+                }
+            }
+
             #[inline(always)]
             fn start_pwm(&mut self) {
                 self.cr1.modify(|_, w| w.cen().set_bit());
@@ -489,6 +729,22 @@ macro_rules! with_pwm {
                 }
             }
 
+            #[inline(always)]
+            fn output_channel_in_mode(channel: Channel, mode: Ocm) {
+                let tim = unsafe { &*<$TIM>::ptr() };
+                match channel {
+                    Channel::C1 => {
+                        tim.ccmr1_output()
+                        .modify(|_, w| w.oc1pe().clear_bit().oc1m().bits(mode as _) );
+                    }
+                    Channel::C2 => {
+                        tim.ccmr1_output()
+                        .modify(|_, w| w.oc2pe().clear_bit().oc2m().bits(mode as _) );
+                    }
+                    _ => {},
+                }
+            }
+
             #[inline(always)]
             fn start_pwm(&mut self) {
                 self.cr1.modify(|_, w| w.cen().set_bit());
@@ -542,6 +798,30 @@ macro_rules! with_pwm {
                 }
             }
 
+            #[inline(always)]
+            fn output_channel_in_mode(channel: Channel, mode: Ocm) {
+                let tim = unsafe { &*<$TIM>::ptr() };
+                $(let $aoe = tim.bdtr.modify(|_, w| w.moe().set_bit());)?
+                match channel {
+                    Channel::C1 => {
+                        tim.ccmr1_output()
+                        .modify(|_, w| w.oc1pe().clear_bit().oc1m().bits(mode as _) );
+                    }
+                    Channel::C2 => {
+                        tim.ccmr1_output()
+                        .modify(|_, w| w.oc2pe().clear_bit().oc2m().bits(mode as _) );
+                    }
+                    Channel::C3 => {
+                        tim.ccmr2_output()
+                        .modify(|_, w| w.oc3pe().clear_bit().oc3m().bits(mode as _) );
+                    }
+                    Channel::C4 => {
+                        tim.ccmr2_output()
+                        .modify(|_, w| w.oc4pe().clear_bit().oc4m().bits(mode as _) );
+                    }
+                }
+            }
+
             #[inline(always)]
             fn start_pwm(&mut self) {
                 $(let $aoe = self.bdtr.modify(|_, w| w.aoe().set_bit());)?
@@ -590,6 +870,9 @@ impl<TIM: Instance> Timer<TIM> {
 
     /// Starts listening for an `event`
     ///
+    /// Events the timer does not have, like [`Event::Trigger`] on TIM6 and TIM7, are
+    /// ignored.
+    ///
     /// Note, you will also have to enable the TIM2 interrupt in the NVIC to start
     /// receiving events.
     pub fn listen(&mut self, event: Event) {
@@ -604,10 +887,22 @@ impl<TIM: Instance> Timer<TIM> {
         self.tim.clear_interrupt_flag(event);
     }
 
+    /// Returns the pending events, whether they are listened for or not
+    pub fn get_interrupt(&mut self) -> Event {
+        self.tim.get_interrupt_flag()
+    }
+
+    /// Returns `true` if any of the `event` flags is set
+    pub fn is_pending(&self, event: Event) -> bool {
+        self.tim.get_interrupt_flag().intersects(event)
+    }
+
     /// Stops listening for an `event`
     pub fn unlisten(&mut self, event: Event) {
         self.tim.listen_interrupt(event, false);
     }
+
+    update_control!();
 }
 
 impl<TIM: Instance + MasterTimer> Timer<TIM> {
@@ -616,6 +911,34 @@ impl<TIM: Instance + MasterTimer> Timer<TIM> {
     }
 }
 
+impl<TIM: Instance + WithRcr> Timer<TIM> {
+    /// Generates the update event only every `updates` counter overflows, on the
+    /// advanced timers TIM1 and TIM8
+    ///
+    /// `updates` goes from 1, an update at every overflow, to 256. Returns
+    /// [`Error::WrongRepetition`] out of this range.
+    ///
+    /// The repetition counter is preloaded like the auto-reload: a new value takes effect
+    /// at the next update event, or right away with an update generated by software. The
+    /// preloaded prescaler and auto-reload values are also only loaded at these update
+    /// events. In center-aligned mode the counter counts both underflows and overflows, so
+    /// an even `updates` updates at the same end of the period every time.
+    pub fn set_repetition(&mut self, updates: u16) -> Result<(), Error> {
+        let rcr = repetition_rcr(updates).ok_or(Error::WrongRepetition)?;
+        self.tim.set_repetition_counter(rcr);
+        Ok(())
+    }
+}
+
+/// `RCR` value for an update every `updates` counter overflows
+#[inline(always)]
+const fn repetition_rcr(updates: u16) -> Option<u8> {
+    match updates {
+        1..=256 => Some((updates - 1) as u8),
+        _ => None,
+    }
+}
+
 /// Timer wrapper for fixed precision timers.
 ///
 /// Uses `fugit::TimerDurationU32` for most of operations
@@ -672,6 +995,9 @@ impl<TIM: Instance, const FREQ: u32> FTimer<TIM, FREQ> {
 
     /// Starts listening for an `event`
     ///
+    /// Events the timer does not have, like [`Event::Trigger`] on TIM6 and TIM7, are
+    /// ignored.
+    ///
     /// Note, you will also have to enable the TIM2 interrupt in the NVIC to start
     /// receiving events.
     pub fn listen(&mut self, event: Event) {
@@ -686,14 +1012,22 @@ impl<TIM: Instance, const FREQ: u32> FTimer<TIM, FREQ> {
         self.tim.clear_interrupt_flag(event);
     }
 
+    /// Returns the pending events, whether they are listened for or not
     pub fn get_interrupt(&mut self) -> Event {
         self.tim.get_interrupt_flag()
     }
 
+    /// Returns `true` if any of the `event` flags is set
+    pub fn is_pending(&self, event: Event) -> bool {
+        self.tim.get_interrupt_flag().intersects(event)
+    }
+
     /// Stops listening for an `event`
     pub fn unlisten(&mut self, event: Event) {
         self.tim.listen_interrupt(event, false);
     }
+
+    update_control!();
 }
 
 impl<TIM: Instance + MasterTimer, const FREQ: u32> FTimer<TIM, FREQ> {
@@ -702,6 +1036,17 @@ impl<TIM: Instance + MasterTimer, const FREQ: u32> FTimer<TIM, FREQ> {
     }
 }
 
+impl<TIM: Instance + WithRcr, const FREQ: u32> FTimer<TIM, FREQ> {
+    /// Generates the update event only every `updates` counter overflows
+    ///
+    /// See [`Timer::set_repetition`].
+    pub fn set_repetition(&mut self, updates: u16) -> Result<(), Error> {
+        let rcr = repetition_rcr(updates).ok_or(Error::WrongRepetition)?;
+        self.tim.set_repetition_counter(rcr);
+        Ok(())
+    }
+}
+
 #[inline(always)]
 pub(crate) const fn compute_arr_presc(freq: u32, clock: u32) -> (u16, u32) {
     let ticks = clock / freq;
@@ -726,6 +1071,9 @@ hal!(
     pac::TIM4: [Timer4, u16, dmar: u16, c: (CH4), m: tim3,],
     pac::TIM10: [Timer10, u16, c: (CH1),],
 );
+#[cfg(not(feature = "stm32f410"))]
+with_etr!(pac::TIM1, pac::TIM2, pac::TIM3, pac::TIM4, pac::TIM5);
+with_rcr!(pac::TIM1);
 
 // TIM5 on F410 is 16-bit
 #[cfg(feature = "stm32f410")]
@@ -733,6 +1081,8 @@ hal!(
     pac::TIM1: [Timer1, u16, dmar: u16, c: (CH4, _aoe), m: tim1,],
     pac::TIM5: [Timer5, u16, dmar: u16, c: (CH4), m: tim5,],
 );
+#[cfg(feature = "stm32f410")]
+with_etr!(pac::TIM1, pac::TIM5);
 
 // All parts except F401 and F411.
 #[cfg(not(any(feature = "stm32f401", feature = "stm32f411",)))]
@@ -747,3 +1097,151 @@ hal!(
     pac::TIM13: [Timer13, u16, c: (CH1),],
     pac::TIM14: [Timer14, u16, c: (CH1),],
 );
+#[cfg(not(any(feature = "stm32f401", feature = "stm32f410", feature = "stm32f411",)))]
+with_etr!(pac::TIM8);
+#[cfg(not(any(feature = "stm32f401", feature = "stm32f410", feature = "stm32f411",)))]
+with_rcr!(pac::TIM8);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_bits_match_dier_and_sr() {
+        // UIE/UIF, CCxIE/CCxIF and TIE/TIF
+        assert_eq!(Event::Update.bits(), 1 << 0);
+        assert_eq!(Event::C1.bits(), 1 << 1);
+        assert_eq!(Event::C2.bits(), 1 << 2);
+        assert_eq!(Event::C3.bits(), 1 << 3);
+        assert_eq!(Event::C4.bits(), 1 << 4);
+        assert_eq!(Event::Trigger.bits(), 1 << 6);
+    }
+
+    #[test]
+    fn status_drops_other_flags() {
+        // COMIF, BIF and the CCxOF overcapture flags are not events
+        let sr = (1 << 0) | (1 << 5) | (1 << 7) | (1 << 9) | (1 << 12);
+        assert_eq!(Event::from_bits_truncate(sr), Event::Update);
+    }
+
+    #[test]
+    fn events_per_timer() {
+        // TIM6, TIM7
+        assert_eq!(timer_events(0), Event::Update);
+        // TIM10, TIM11, TIM13, TIM14
+        assert_eq!(timer_events(1), Event::Update | Event::C1);
+        // TIM9, TIM12
+        assert_eq!(
+            timer_events(2),
+            Event::Update | Event::C1 | Event::C2 | Event::Trigger
+        );
+        assert_eq!(timer_events(4), Event::all());
+    }
+
+    #[test]
+    fn ocxce_bits() {
+        // OC1CE and OC3CE in the low half of CCMR1/CCMR2, OC2CE and OC4CE in the high one
+        assert_eq!(ocxce_offset(Channel::C1), 7);
+        assert_eq!(ocxce_offset(Channel::C2), 15);
+        assert_eq!(ocxce_offset(Channel::C3), 7);
+        assert_eq!(ocxce_offset(Channel::C4), 15);
+    }
+
+    #[test]
+    fn etr_smcr_fields() {
+        assert_eq!(EtrConfig::default().smcr_bits(), 0);
+        // ETPS, bits 13:12
+        for (prescaler, bits) in [
+            (EtrPrescaler::Div1, 0b00),
+            (EtrPrescaler::Div2, 0b01),
+            (EtrPrescaler::Div4, 0b10),
+            (EtrPrescaler::Div8, 0b11),
+        ] {
+            let etr = EtrConfig {
+                prescaler,
+                ..EtrConfig::default()
+            };
+            assert_eq!(etr.smcr_bits(), bits << 12);
+        }
+        // ETP, bit 15
+        let etr = EtrConfig {
+            polarity: EtrPolarity::Inverted,
+            ..EtrConfig::default()
+        };
+        assert_eq!(etr.smcr_bits(), 1 << 15);
+        // ETF, bits 11:8, truncated to 4 bits
+        for (filter, bits) in [(1, 0b0001), (0b1010, 0b1010), (15, 0b1111), (0x13, 0b0011)] {
+            let etr = EtrConfig {
+                filter,
+                ..EtrConfig::default()
+            };
+            assert_eq!(etr.smcr_bits(), bits << 8);
+        }
+        // ECE and the slave mode fields are never written
+        let etr = EtrConfig {
+            polarity: EtrPolarity::Inverted,
+            prescaler: EtrPrescaler::Div8,
+            filter: 0xff,
+        };
+        assert_eq!(etr.smcr_bits(), 0xbf00);
+    }
+
+    #[test]
+    fn auto_reload_preload_toggles_arpe() {
+        // CEN, URS and CKD left alone
+        let cr1 = 1 | CR1_URS | (0b10 << 8);
+        assert_eq!(arpe_cr1(cr1, true), cr1 | (1 << 7));
+        assert_eq!(arpe_cr1(cr1 | (1 << 7), false), cr1);
+        assert_eq!(arpe_cr1(cr1 | (1 << 7), true), cr1 | (1 << 7));
+        assert_eq!(arpe_cr1(0, false), 0);
+    }
+
+    #[test]
+    fn update_event_sets_ug_in_egr() {
+        // CEN and ARPE
+        let cr1 = 1 | (1 << 7);
+        let [urs, egr, restore] = update_event_writes(cr1);
+        assert_eq!(urs, cr1 | (1 << 2));
+        // UG, the other EGR bits generate capture/compare and trigger events
+        assert_eq!(egr, 1);
+        assert_eq!(restore, cr1);
+        // URS is cleared afterwards even if it was set
+        assert_eq!(update_event_writes(cr1 | (1 << 2))[2], cr1);
+    }
+
+    #[test]
+    fn repetition_counter_value() {
+        // An update every RCR + 1 overflows
+        for (updates, rcr) in [(1, 0), (2, 1), (3, 2), (100, 99), (255, 254), (256, 255)] {
+            assert_eq!(repetition_rcr(updates), Some(rcr));
+            assert_eq!(u16::from(rcr) + 1, updates);
+        }
+    }
+
+    #[test]
+    fn repetition_counter_range() {
+        // RCR is 8 bits on TIM1 and TIM8
+        assert_eq!(repetition_rcr(0), None);
+        assert_eq!(repetition_rcr(257), None);
+        assert_eq!(repetition_rcr(u16::MAX), None);
+    }
+
+    fn has_rcr<TIM: WithRcr>() {}
+
+    /// Resolves to a single impl, and compiles, only if `TIM` has no repetition counter
+    trait NoRcr<A> {
+        fn check() {}
+    }
+    impl<TIM> NoRcr<()> for TIM {}
+    impl<TIM: WithRcr> NoRcr<u8> for TIM {}
+
+    #[test]
+    fn repetition_counter_on_advanced_timers() {
+        has_rcr::<pac::TIM1>();
+        #[cfg(not(any(feature = "stm32f401", feature = "stm32f410", feature = "stm32f411",)))]
+        has_rcr::<pac::TIM8>();
+        // General purpose timers have no RCR
+        <pac::TIM5 as NoRcr<_>>::check();
+        <pac::TIM9 as NoRcr<_>>::check();
+    }
+}
@@ -35,22 +35,22 @@ impl Timer<SYST> {
 }
 
 impl SysDelay {
+    /// Sleep for given time
     pub fn delay(&mut self, us: MicrosDurationU32) {
-        // The SysTick Reload Value register supports values between 1 and 0x00FFFFFF.
-        const MAX_RVR: u32 = 0x00FF_FFFF;
-
-        let mut total_rvr = us.ticks() * (self.clk.raw() / 1_000_000);
+        self.delay_ticks(syst_ticks(us.ticks(), self.clk.raw(), 1_000_000));
+    }
 
-        while total_rvr != 0 {
-            let current_rvr = total_rvr.min(MAX_RVR);
+    /// Sleep for given number of milliseconds, up to `u32::MAX`
+    pub(super) fn delay_ms_u32(&mut self, ms: u32) {
+        self.delay_ticks(syst_ticks(ms, self.clk.raw(), 1_000));
+    }
 
-            self.tim.set_reload(current_rvr);
+    fn delay_ticks(&mut self, ticks: u64) {
+        for rvr in ReloadChunks::new(ticks) {
+            self.tim.set_reload(rvr);
             self.tim.clear_current();
             self.tim.enable_counter();
 
-            // Update the tracking variable while we are waiting...
-            total_rvr -= current_rvr;
-
             while !self.tim.has_wrapped() {}
 
             self.tim.disable_counter();
@@ -58,6 +58,48 @@ impl SysDelay {
     }
 }
 
+/// SysTick reload values of the chunks of a `ticks` long delay
+///
+/// The SysTick Reload Value register supports values between 1 and 0x00FFFFFF.
+/// Counter period is RVR + 1 ticks, a last single tick is dropped.
+struct ReloadChunks {
+    ticks: u64,
+}
+
+impl ReloadChunks {
+    const MAX_RVR: u32 = 0x00FF_FFFF;
+
+    #[inline(always)]
+    fn new(ticks: u64) -> Self {
+        Self { ticks }
+    }
+}
+
+impl Iterator for ReloadChunks {
+    type Item = u32;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<u32> {
+        if self.ticks > 1 {
+            let rvr = self.ticks.min(Self::MAX_RVR as u64 + 1) as u32 - 1;
+            self.ticks -= rvr as u64 + 1;
+            Some(rvr)
+        } else {
+            None
+        }
+    }
+}
+
+/// Number of SysTick ticks in `time` expressed in `1 / unit` seconds.
+///
+/// Computed in 64 bits, so `u32::MAX` of `time` doesn't overflow even at the highest
+/// core clock, and with `HCLK / 8` clock source a non-integer number of ticks
+/// per unit is not truncated.
+#[inline(always)]
+const fn syst_ticks(time: u32, clk: u32, unit: u32) -> u64 {
+    time as u64 * clk as u64 / unit as u64
+}
+
 /// Periodic non-blocking timer that imlements [embedded_hal::blocking::delay] traits
 pub struct Delay<TIM, const FREQ: u32>(pub(super) FTimer<TIM, FREQ>);
 
@@ -138,3 +180,68 @@ impl fugit_timer::Delay<1_000_000> for SysDelay {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_do_not_overflow() {
+        assert_eq!(syst_ticks(1, 168_000_000, 1_000_000), 168);
+        assert_eq!(
+            syst_ticks(u32::MAX, 168_000_000, 1_000),
+            721_554_505_560_000
+        );
+        // HCLK / 8 at 84 MHz is 10.5 ticks per microsecond
+        assert_eq!(syst_ticks(2, 10_500_000, 1_000_000), 21);
+    }
+
+    #[test]
+    fn chunks_add_up() {
+        for ticks in [2, 168, 0x0100_0000, 0x0300_0000, 1 << 40] {
+            let mut total = 0;
+            for rvr in ReloadChunks::new(ticks) {
+                assert!((1..=0x00FF_FFFF).contains(&rvr));
+                total += rvr as u64 + 1;
+            }
+            assert_eq!(total, ticks);
+        }
+    }
+
+    #[test]
+    fn chunks_of_short_delays() {
+        assert!(ReloadChunks::new(0).eq([]));
+        assert!(ReloadChunks::new(1).eq([]));
+        assert!(ReloadChunks::new(2).eq([1]));
+        assert!(ReloadChunks::new(168).eq([167]));
+        assert!(ReloadChunks::new(0x0100_0000).eq([0x00FF_FFFF]));
+        assert!(ReloadChunks::new(0x0100_0002).eq([0x00FF_FFFF, 1]));
+    }
+
+    #[test]
+    fn chunks_never_reload_zero() {
+        // One tick left over is dropped, the reload value can't be 0
+        assert!(ReloadChunks::new(0x0100_0001).eq([0x00FF_FFFF]));
+    }
+
+    #[test]
+    fn longest_delay_ticks() {
+        // u32::MAX ms even at a u32::MAX Hz clock fits in u64
+        let ticks = syst_ticks(u32::MAX, u32::MAX, 1_000);
+        assert_eq!(ticks, u32::MAX as u64 * u32::MAX as u64 / 1_000);
+        let mut chunks = ReloadChunks::new(ticks);
+        assert_eq!(chunks.next(), Some(0x00FF_FFFF));
+        assert_eq!(chunks.ticks, ticks - 0x0100_0000);
+    }
+
+    #[test]
+    fn chunks_from_u64_max() {
+        let mut chunks = ReloadChunks::new(u64::MAX);
+        assert_eq!(chunks.next(), Some(0x00FF_FFFF));
+        assert_eq!(chunks.next(), Some(0x00FF_FFFF));
+        assert_eq!(chunks.ticks, u64::MAX - 0x0200_0000);
+        // Last chunks of u64::MAX, which is 0x00FF_FFFF past a multiple of the longest period
+        chunks.ticks = 0x01FF_FFFF;
+        assert!(chunks.eq([0x00FF_FFFF, 0x00FF_FFFE]));
+    }
+}
@@ -0,0 +1,212 @@
+//! External clock and gated counting: the counter is clocked from a pin instead of the
+//! internal clock, or counts the internal clock only while a gate input is active
+//!
+//! ```ignore
+//! // Count rising edges on PA0 (TIM2_ETR), e.g. for a frequency counter
+//! let timer = Timer::new(dp.TIM2, &clocks);
+//! let mut counter = timer.external_clock::<Etr, _>(
+//!     gpioa.pa0.into_alternate(),
+//!     ExtClockMode::Clock(EtrConfig::default()),
+//! );
+//! counter.start();
+//! // ... wait for the gate time
+//! let pulses = counter.count();
+//! ```
+
+use super::Instance;
+use super::{CPin, Ch, EtrConfig, EtrPin, EtrPolarity, Timer, WithEtr};
+
+/// External trigger input (`ETR`) marker
+pub struct Etr;
+
+/// Input of the slave mode controller: [`Ch<C1>`](Ch) (`TI1`), [`Ch<C2>`](Ch) (`TI2`) or [`Etr`]
+pub trait ExtClockInput: crate::Sealed {
+    #[doc(hidden)]
+    const TS: u8;
+}
+
+impl crate::Sealed for Ch<0> {}
+impl ExtClockInput for Ch<0> {
+    // TI1FP1
+    const TS: u8 = 0b101;
+}
+
+impl crate::Sealed for Ch<1> {}
+impl ExtClockInput for Ch<1> {
+    // TI2FP2
+    const TS: u8 = 0b110;
+}
+
+impl crate::Sealed for Etr {}
+impl ExtClockInput for Etr {
+    // ETRF
+    const TS: u8 = 0b111;
+}
+
+/// Pin connected to `INPUT` of `TIM`
+pub trait ExtClockPin<TIM, INPUT> {}
+
+impl<TIM, PIN: CPin<TIM, 0>> ExtClockPin<TIM, Ch<0>> for PIN {}
+impl<TIM, PIN: CPin<TIM, 1>> ExtClockPin<TIM, Ch<1>> for PIN {}
+impl<TIM, PIN: EtrPin<TIM>> ExtClockPin<TIM, Etr> for PIN {}
+
+/// Slave mode of [`Timer::external_clock`]
+///
+/// The polarity selects the counted edge or the active gate level, and the filter is
+/// applied to the input. The prescaler is only available on the ETR input and is ignored
+/// for `TI1` and `TI2`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ExtClockMode {
+    /// Counts the edges of the input: external clock mode 1 for `TI1`/`TI2`, external
+    /// clock mode 2 (`ECE`) for `ETR`
+    Clock(EtrConfig),
+    /// Counts the internal clock while the input is at its active level
+    Gated(EtrConfig),
+}
+
+impl ExtClockMode {
+    /// `SMCR` fields set by [`Timer::external_clock`]: SMS, TS, ETF, ETPS, ECE and ETP
+    pub(crate) const SMCR_MASK: u32 = 0xff77;
+
+    /// `SMCR` value for this mode on the input selected by `ts`
+    pub(crate) const fn smcr_bits(&self, ts: u8) -> u32 {
+        const SMS_GATED: u32 = 0b101;
+        const SMS_EXT_CLOCK_1: u32 = 0b111;
+        const ECE: u32 = 1 << 14;
+
+        let etr = ts == Etr::TS;
+        match self {
+            Self::Clock(config) if etr => ECE | config.smcr_bits(),
+            Self::Clock(_) => ((ts as u32) << 4) | SMS_EXT_CLOCK_1,
+            Self::Gated(config) if etr => ((ts as u32) << 4) | SMS_GATED | config.smcr_bits(),
+            Self::Gated(_) => ((ts as u32) << 4) | SMS_GATED,
+        }
+    }
+
+    fn config(&self) -> &EtrConfig {
+        match self {
+            Self::Clock(config) | Self::Gated(config) => config,
+        }
+    }
+}
+
+/// Counter of `TIM` clocked or gated by `PIN`
+pub struct ExternalClock<TIM: Instance, PIN> {
+    timer: Timer<TIM>,
+    pin: PIN,
+}
+
+impl<TIM: Instance + WithEtr> Timer<TIM> {
+    /// Counts from `INPUT` on `pin` according to `mode`, the counter is stopped
+    ///
+    /// The counter runs over its full range, without prescaler.
+    pub fn external_clock<INPUT, PIN>(
+        mut self,
+        pin: PIN,
+        mode: ExtClockMode,
+    ) -> ExternalClock<TIM, PIN>
+    where
+        INPUT: ExtClockInput,
+        PIN: ExtClockPin<TIM, INPUT>,
+    {
+        self.tim.disable_counter();
+
+        let config = mode.config();
+        if INPUT::TS != Etr::TS {
+            self.tim.configure_ti_input(
+                INPUT::TS - Ch::<0>::TS,
+                config.filter,
+                config.polarity == EtrPolarity::Inverted,
+            );
+        }
+        self.tim
+            .set_slave_mode(ExtClockMode::SMCR_MASK, mode.smcr_bits(INPUT::TS));
+
+        self.tim.set_prescaler(0);
+        self.tim.set_auto_reload(TIM::max_auto_reload()).unwrap();
+        self.tim.trigger_update();
+        self.tim.reset_counter();
+
+        ExternalClock { timer: self, pin }
+    }
+}
+
+impl<TIM: Instance + WithEtr, PIN> ExternalClock<TIM, PIN> {
+    /// Starts counting
+    pub fn start(&mut self) {
+        self.timer.tim.enable_counter();
+    }
+
+    /// Stops counting, the count is kept
+    pub fn pause(&mut self) {
+        self.timer.tim.disable_counter();
+    }
+
+    /// Returns the current count
+    pub fn count(&self) -> u32 {
+        self.timer.tim.read_count().into()
+    }
+
+    /// Resets the count to zero
+    pub fn reset(&mut self) {
+        self.timer.tim.reset_counter();
+    }
+
+    /// Stops counting and releases the timer, in internal clock mode, and the pin
+    ///
+    /// Only the slave mode and external trigger fields are reset, `MSM` is kept.
+    pub fn release(mut self) -> (Timer<TIM>, PIN) {
+        self.timer.tim.disable_counter();
+        self.timer.tim.set_slave_mode(ExtClockMode::SMCR_MASK, 0);
+        (self.timer, self.pin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timer::EtrPrescaler;
+
+    #[test]
+    fn smcr_bits() {
+        let config = EtrConfig {
+            filter: 15,
+            prescaler: EtrPrescaler::Div4,
+            polarity: EtrPolarity::Inverted,
+        };
+        // External clock mode 2: ECE and the ETR configuration, SMS is left at 0
+        assert_eq!(
+            ExtClockMode::Clock(config).smcr_bits(Etr::TS),
+            1 << 15 | 1 << 14 | 0b10 << 12 | 0xf << 8
+        );
+        // Gated by ETRF
+        assert_eq!(
+            ExtClockMode::Gated(config).smcr_bits(Etr::TS),
+            1 << 15 | 0b10 << 12 | 0xf << 8 | 0b111 << 4 | 0b101
+        );
+        // External clock mode 1 from TI1FP1 and TI2FP2, the filter is in CCMR1
+        assert_eq!(
+            ExtClockMode::Clock(config).smcr_bits(Ch::<0>::TS),
+            0b101 << 4 | 0b111
+        );
+        assert_eq!(
+            ExtClockMode::Gated(config).smcr_bits(Ch::<1>::TS),
+            0b110 << 4 | 0b101
+        );
+    }
+
+    #[test]
+    fn smcr_mask_keeps_msm() {
+        assert_eq!(ExtClockMode::SMCR_MASK & (1 << 7), 0);
+        for ts in [Ch::<0>::TS, Ch::<1>::TS, Etr::TS] {
+            for mode in [
+                ExtClockMode::Clock(EtrConfig::default()),
+                ExtClockMode::Gated(EtrConfig::default()),
+            ] {
+                let bits = mode.smcr_bits(ts);
+                assert_eq!(bits & !ExtClockMode::SMCR_MASK, 0);
+            }
+        }
+    }
+}
@@ -12,7 +12,7 @@ use void::Void;
 
 use super::{
     Channel, Counter, CounterHz, Delay, Error, Instance, Pins, Pwm, PwmChannel, PwmHz, SysCounter,
-    SysCounterHz, SysDelay, WithPwm,
+    SysCounterHz, SysDelay, SysTickTimer, WithPwm,
 };
 
 impl DelayUs<u32> for SysDelay {
@@ -23,7 +23,7 @@ impl DelayUs<u32> for SysDelay {
 
 impl DelayMs<u32> for SysDelay {
     fn delay_ms(&mut self, ms: u32) {
-        self.delay_us(ms * 1_000);
+        self.delay_ms_u32(ms);
     }
 }
 
@@ -51,6 +51,12 @@ impl DelayMs<u8> for SysDelay {
     }
 }
 
+impl DelayMs<u32> for SysTickTimer {
+    fn delay_ms(&mut self, ms: u32) {
+        Self::delay_ms(self, ms);
+    }
+}
+
 impl<TIM> Periodic for CounterHz<TIM> {}
 impl Periodic for SysCounterHz {}
 impl<const FREQ: u32> Periodic for SysCounter<FREQ> {}
@@ -178,7 +184,6 @@ where
         self.set_duty(channel, duty)
     }
 
-    /// If `0` returned means max_duty is 2^16
     fn get_max_duty(&self) -> Self::Duty {
         self.get_max_duty()
     }
@@ -288,7 +293,6 @@ where
         self.set_duty(channel, duty)
     }
 
-    /// If `0` returned means max_duty is 2^16
     fn get_max_duty(&self) -> Self::Duty {
         self.get_max_duty()
     }
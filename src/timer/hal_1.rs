@@ -1,38 +1,77 @@
-//! Delay implementation based on general-purpose 32 bit timers and System timer (SysTick).
+//! Delay implementation based on general-purpose 32 bit timers and System timer (SysTick),
+//! and PWM channel duty cycle.
 //!
 //! TIM2 and TIM5 are a general purpose 32-bit auto-reload up/downcounter with
 //! a 16-bit prescaler.
 
 use core::convert::Infallible;
-use embedded_hal_one::delay::blocking::DelayUs;
+use embedded_hal_one::{
+    delay::DelayNs,
+    pwm::{ErrorType, SetDutyCycle},
+};
 
-use super::{Delay, Error, Instance, SysDelay};
+use super::{Delay, Instance, PwmChannel, SysDelay, SysTickTimer, WithPwm};
 use fugit::ExtU32;
 
-impl DelayUs for SysDelay {
-    type Error = Infallible;
+/// Nanoseconds rounded up to microseconds, the resolution of delays
+fn ns_to_us(ns: u32) -> u32 {
+    ns / 1_000 + u32::from(ns % 1_000 != 0)
+}
+
+impl DelayNs for SysDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.delay(ns_to_us(ns).micros());
+    }
 
-    fn delay_us(&mut self, us: u32) -> Result<(), Self::Error> {
+    fn delay_us(&mut self, us: u32) {
         self.delay(us.micros());
+    }
 
-        Ok(())
+    fn delay_ms(&mut self, ms: u32) {
+        self.delay_ms_u32(ms);
+    }
+}
+
+/// Delays are rounded up to milliseconds
+impl DelayNs for SysTickTimer {
+    fn delay_ns(&mut self, ns: u32) {
+        self.delay_us(ns_to_us(ns));
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        Self::delay_ms(self, us / 1_000 + u32::from(us % 1_000 != 0));
     }
 
-    fn delay_ms(&mut self, ms: u32) -> Result<(), Self::Error> {
-        self.delay_us(ms * 1_000)
+    fn delay_ms(&mut self, ms: u32) {
+        Self::delay_ms(self, ms);
     }
 }
 
-impl<TIM: Instance, const FREQ: u32> DelayUs for Delay<TIM, FREQ> {
-    type Error = Error;
+impl<TIM: Instance, const FREQ: u32> DelayNs for Delay<TIM, FREQ> {
+    fn delay_ns(&mut self, ns: u32) {
+        self.delay(ns_to_us(ns).micros());
+    }
 
-    fn delay_us(&mut self, us: u32) -> Result<(), Self::Error> {
+    fn delay_us(&mut self, us: u32) {
         self.delay(us.micros());
-        Ok(())
     }
 
-    fn delay_ms(&mut self, ms: u32) -> Result<(), Self::Error> {
+    fn delay_ms(&mut self, ms: u32) {
         self.delay(ms.millis());
+    }
+}
+
+impl<TIM: Instance + WithPwm, const C: u8> ErrorType for PwmChannel<TIM, C> {
+    type Error = Infallible;
+}
+
+impl<TIM: Instance + WithPwm, const C: u8> SetDutyCycle for PwmChannel<TIM, C> {
+    fn max_duty_cycle(&self) -> u16 {
+        self.get_max_duty()
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        self.set_duty(duty);
         Ok(())
     }
 }
@@ -0,0 +1,154 @@
+//! Output compare: a channel output changing level when the counter matches its compare value
+//!
+//! Unlike PWM, the compare value isn't preloaded, a new value applies immediately. This allows
+//! moving the next match from the capture/compare interrupt, e.g. to toggle a pin at precise
+//! times.
+//!
+//! ```ignore
+//! // 1 kHz square wave on PA6: the counter overflows at 2 kHz, the output toggles each time
+//! let mut counter = dp.TIM3.counter_hz(&clocks);
+//! let mut channel = counter.output_compare(Ch::<C1>, gpioa.pa6.into_alternate(), OcMode::Toggle);
+//! channel.set_compare_value(0);
+//! channel.enable();
+//! counter.start(2.kHz()).unwrap();
+//! ```
+
+use super::{CPin, Ch, Channel, Instance, Ocm, Timer, WithPwm};
+use core::marker::PhantomData;
+
+/// Output compare mode (`OCxM`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum OcMode {
+    /// The output keeps its level
+    Frozen = 0,
+    /// The output goes high on match
+    ActiveOnMatch = 1,
+    /// The output goes low on match
+    InactiveOnMatch = 2,
+    /// The output toggles on match
+    Toggle = 3,
+    /// The output is forced low
+    ForceInactive = 4,
+    /// The output is forced high
+    ForceActive = 5,
+}
+
+impl From<OcMode> for Ocm {
+    fn from(mode: OcMode) -> Self {
+        match mode {
+            OcMode::Frozen => Ocm::Frozen,
+            OcMode::ActiveOnMatch => Ocm::ActiveOnMatch,
+            OcMode::InactiveOnMatch => Ocm::InactiveOnMatch,
+            OcMode::Toggle => Ocm::Toggle,
+            OcMode::ForceInactive => Ocm::ForceInactive,
+            OcMode::ForceActive => Ocm::ForceActive,
+        }
+    }
+}
+
+/// Channel `C` of `TIM` in output compare mode, driving `PIN`
+pub struct OcChannel<TIM, const C: u8, PIN> {
+    pin: PIN,
+    _tim: PhantomData<TIM>,
+}
+
+impl<TIM: Instance + WithPwm> Timer<TIM> {
+    /// Puts channel `C` in output compare `mode`, the output is disabled
+    ///
+    /// The counter is started separately, e.g. with [`CounterHz::start`](super::CounterHz::start).
+    pub fn output_compare<PIN, const C: u8>(
+        &mut self,
+        _channel: Ch<C>,
+        pin: PIN,
+        mode: OcMode,
+    ) -> OcChannel<TIM, C, PIN>
+    where
+        PIN: CPin<TIM, C>,
+    {
+        TIM::enable_channel(C, false);
+        TIM::output_channel_in_mode(channel::<C>(), mode.into());
+        OcChannel {
+            pin,
+            _tim: PhantomData,
+        }
+    }
+}
+
+impl<TIM: Instance + WithPwm, const C: u8, PIN> OcChannel<TIM, C, PIN> {
+    /// Enables the output
+    #[inline]
+    pub fn enable(&mut self) {
+        TIM::enable_channel(C, true);
+    }
+
+    /// Disables the output, the pin floats
+    #[inline]
+    pub fn disable(&mut self) {
+        TIM::enable_channel(C, false);
+    }
+
+    /// Changes the output compare mode
+    ///
+    /// With the forced modes, the output level is set immediately.
+    pub fn set_mode(&mut self, mode: OcMode) {
+        TIM::output_channel_in_mode(channel::<C>(), mode.into());
+    }
+
+    /// Compare value
+    #[inline]
+    pub fn get_compare_value(&self) -> u32 {
+        TIM::read_cc_value(C)
+    }
+
+    /// Sets the compare value, applied immediately
+    #[inline]
+    pub fn set_compare_value(&mut self, value: u32) {
+        TIM::set_cc_value(C, value)
+    }
+
+    /// Disables the output and releases the pin, the channel keeps its mode
+    pub fn release(mut self) -> PIN {
+        self.disable();
+        self.pin
+    }
+}
+
+const fn channel<const C: u8>() -> Channel {
+    match C {
+        0 => Channel::C1,
+        1 => Channel::C2,
+        2 => Channel::C3,
+        _ => Channel::C4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ocxm_encoding() {
+        let modes = [
+            (OcMode::Frozen, 0b000),
+            (OcMode::ActiveOnMatch, 0b001),
+            (OcMode::InactiveOnMatch, 0b010),
+            (OcMode::Toggle, 0b011),
+            (OcMode::ForceInactive, 0b100),
+            (OcMode::ForceActive, 0b101),
+        ];
+        for (mode, ocxm) in modes {
+            assert_eq!(Ocm::from(mode) as u8, ocxm);
+            assert_eq!(mode as u8, ocxm);
+        }
+    }
+
+    #[test]
+    fn channel_index() {
+        assert_eq!(channel::<0>() as u8, Channel::C1 as u8);
+        assert_eq!(channel::<1>() as u8, Channel::C2 as u8);
+        assert_eq!(channel::<2>() as u8, Channel::C3 as u8);
+        assert_eq!(channel::<3>() as u8, Channel::C4 as u8);
+    }
+}
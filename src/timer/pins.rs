@@ -8,6 +8,17 @@ pub const C2: u8 = 1;
 pub const C3: u8 = 2;
 pub const C4: u8 = 3;
 
+// External trigger input marker
+pub trait EtrPin<TIM> {}
+
+macro_rules! etr_impl {
+    ( $( $TIM:ident, $PINX:ident, $AF:literal; )+ ) => {
+        $(
+            impl<Otype> EtrPin<crate::pac::$TIM> for gpio::$PINX<Alternate<$AF, Otype>> { }
+        )+
+    };
+}
+
 macro_rules! channel_impl {
     ( $( $TIM:ident, $C:ident, $PINX:ident, $AF:literal; )+ ) => {
         $(
@@ -218,3 +229,51 @@ channel_impl!(
 
     TIM2, C4, PB2, 1;
 );
+
+// External trigger inputs, grouped like the channels above.
+etr_impl!(
+    TIM1, PA12, 1;
+);
+
+// All parts except F410.
+#[cfg(not(feature = "stm32f410"))]
+etr_impl!(
+    TIM1, PE7, 1;
+
+    TIM2, PA0, 1;
+    TIM2, PA5, 1;
+    TIM2, PA15, 1;
+
+    TIM3, PD2, 2;
+
+    TIM4, PE0, 2;
+);
+
+// All parts except F401, F410, and F411.
+#[cfg(not(any(feature = "stm32f401", feature = "stm32f410", feature = "stm32f411")))]
+etr_impl!(
+    TIM8, PA0, 3;
+);
+
+// PB8 is TIM2_CH1/TIM2_ETR on F446 only.
+#[cfg(feature = "stm32f446")]
+etr_impl!(
+    TIM2, PB8, 1;
+);
+
+// STM's "advanced and foundation" lines except F446.
+#[cfg(any(
+    feature = "stm32f405",
+    feature = "stm32f407",
+    feature = "stm32f415",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f429",
+    feature = "stm32f437",
+    feature = "stm32f439",
+    feature = "stm32f469",
+    feature = "stm32f479"
+))]
+etr_impl!(
+    TIM8, PI3, 3;
+);
@@ -1,9 +1,36 @@
-use super::{compute_arr_presc, Channel, FTimer, Instance, Ocm, Timer, WithPwm};
+use super::{Channel, EtrConfig, FTimer, Instance, Ocm, Timer, WithEtr, WithPwm};
 use crate::rcc::Clocks;
 use core::marker::PhantomData;
 use core::ops::{Deref, DerefMut};
 use fugit::{HertzU32 as Hertz, TimerDurationU32};
 
+/// Largest auto-reload value for PWM
+///
+/// In PWM mode 1 the output is held high when `CCR > ARR`, in both edge- and center-aligned
+/// modes, so `ARR + 1` must fit the 16-bit duty for 100% duty to be reachable.
+const MAX_PWM_ARR: u32 = u16::MAX as u32 - 1;
+
+/// Same as `compute_arr_presc`, but keeps ARR at most [`MAX_PWM_ARR`]
+#[inline(always)]
+const fn compute_pwm_arr_presc(freq: u32, clock: u32) -> (u16, u32) {
+    let ticks = clock / freq;
+    let psc = (ticks - 1) / (MAX_PWM_ARR + 1);
+    let arr = ticks / (psc + 1) - 1;
+    (psc as u16, arr)
+}
+
+/// `ARR + 1`, saturated for periods longer than the 16-bit duty allows
+#[inline(always)]
+fn max_duty<TIM: Instance>() -> u16 {
+    duty_limit(TIM::read_auto_reload())
+}
+
+/// `arr + 1`, saturated to the 16-bit duty
+#[inline(always)]
+fn duty_limit(arr: u32) -> u16 {
+    arr.saturating_add(1).min(u32::from(u16::MAX)) as u16
+}
+
 pub trait Pins<TIM, P> {
     const C1: bool = false;
     const C2: bool = false;
@@ -168,12 +195,14 @@ impl<TIM: Instance + WithPwm, const C: u8> PwmChannel<TIM, C> {
         TIM::read_cc_value(C) as u16
     }
 
-    /// If `0` returned means max_duty is 2^16
+    /// Duty giving a constantly high output, `ARR + 1`
     #[inline]
     pub fn get_max_duty(&self) -> u16 {
-        (TIM::read_auto_reload() as u16).wrapping_add(1)
+        max_duty::<TIM>()
     }
 
+    /// Sets the duty, `0` gives a constantly low output and [`get_max_duty`](Self::get_max_duty)
+    /// (or more) a constantly high output
     #[inline]
     pub fn set_duty(&mut self, duty: u16) {
         TIM::set_cc_value(C, duty as u32)
@@ -186,7 +215,8 @@ where
     PINS: Pins<TIM, P>,
 {
     timer: Timer<TIM>,
-    _pins: PhantomData<(P, PINS)>,
+    pins: PINS,
+    _p: PhantomData<P>,
 }
 
 impl<TIM, P, PINS> PwmHz<TIM, P, PINS>
@@ -194,10 +224,16 @@ where
     TIM: Instance + WithPwm,
     PINS: Pins<TIM, P>,
 {
-    pub fn release(mut self) -> Timer<TIM> {
+    pub fn release(self) -> Timer<TIM> {
+        self.release_with_pins().0
+    }
+
+    /// Stops the timer and releases it with the channel pins, in the alternate mode they
+    /// were given in
+    pub fn release_with_pins(mut self) -> (Timer<TIM>, PINS) {
         // stop timer
         self.tim.cr1_reset();
-        self.timer
+        (self.timer, self.pins)
     }
 
     pub fn split(self) -> PINS::Channels {
@@ -227,7 +263,7 @@ where
 }
 
 impl<TIM: Instance + WithPwm> Timer<TIM> {
-    pub fn pwm_hz<P, PINS>(mut self, _pins: PINS, freq: Hertz) -> PwmHz<TIM, P, PINS>
+    pub fn pwm_hz<P, PINS>(mut self, pins: PINS, freq: Hertz) -> PwmHz<TIM, P, PINS>
     where
         PINS: Pins<TIM, P>,
     {
@@ -253,7 +289,7 @@ impl<TIM: Instance + WithPwm> Timer<TIM> {
         // might as well enable for the auto-reload too
         self.tim.enable_preload(true);
 
-        let (psc, arr) = compute_arr_presc(freq.raw(), self.clk.raw());
+        let (psc, arr) = compute_pwm_arr_presc(freq.raw(), self.clk.raw());
         self.tim.set_prescaler(psc);
         self.tim.set_auto_reload(arr).unwrap();
 
@@ -264,7 +300,8 @@ impl<TIM: Instance + WithPwm> Timer<TIM> {
 
         PwmHz {
             timer: self,
-            _pins: PhantomData,
+            pins,
+            _p: PhantomData,
         }
     }
 }
@@ -286,13 +323,15 @@ where
         TIM::read_cc_value(PINS::check_used(channel) as u8) as u16
     }
 
+    /// Sets the duty of `channel`, `0` gives a constantly low output and
+    /// [`get_max_duty`](Self::get_max_duty) (or more) a constantly high output
     pub fn set_duty(&mut self, channel: Channel, duty: u16) {
         TIM::set_cc_value(PINS::check_used(channel) as u8, duty as u32)
     }
 
-    /// If `0` returned means max_duty is 2^16
+    /// Duty giving a constantly high output, `ARR + 1`
     pub fn get_max_duty(&self) -> u16 {
-        (TIM::read_auto_reload() as u16).wrapping_add(1)
+        max_duty::<TIM>()
     }
 
     pub fn get_period(&self) -> Hertz {
@@ -307,19 +346,43 @@ where
     pub fn set_period(&mut self, period: Hertz) {
         let clk = self.clk;
 
-        let (psc, arr) = compute_arr_presc(period.raw(), clk.raw());
+        let (psc, arr) = compute_pwm_arr_presc(period.raw(), clk.raw());
         self.tim.set_prescaler(psc);
         self.tim.set_auto_reload(arr).unwrap();
     }
 }
 
+impl<TIM, P, PINS> PwmHz<TIM, P, PINS>
+where
+    TIM: Instance + WithEtr,
+    PINS: Pins<TIM, P>,
+{
+    /// Clears `OCxREF` of `channel` while the external trigger input is high.
+    ///
+    /// The reference stays low until the next update event, which can be used for
+    /// cycle-by-cycle current limiting. On STM32F4 the `ETRF` signal is the only
+    /// `OCREF_CLR` source, so `etr` configures the shared ETR filter, prescaler and polarity.
+    pub fn enable_ocref_clear(&mut self, channel: Channel, etr: EtrConfig) {
+        let channel = PINS::check_used(channel);
+        self.tim.configure_etr(etr);
+        self.tim.enable_ocref_clear(channel, true);
+    }
+
+    /// `OCxREF` of `channel` is no longer affected by the external trigger input
+    pub fn disable_ocref_clear(&mut self, channel: Channel) {
+        self.tim
+            .enable_ocref_clear(PINS::check_used(channel), false);
+    }
+}
+
 pub struct Pwm<TIM, P, PINS, const FREQ: u32>
 where
     TIM: Instance + WithPwm,
     PINS: Pins<TIM, P>,
 {
     timer: FTimer<TIM, FREQ>,
-    _pins: PhantomData<(P, PINS)>,
+    pins: PINS,
+    _p: PhantomData<P>,
 }
 
 impl<TIM, P, PINS, const FREQ: u32> Pwm<TIM, P, PINS, FREQ>
@@ -331,10 +394,16 @@ where
         PINS::split()
     }
 
-    pub fn release(mut self) -> FTimer<TIM, FREQ> {
+    pub fn release(self) -> FTimer<TIM, FREQ> {
+        self.release_with_pins().0
+    }
+
+    /// Stops the counter and releases it with the channel pins, in the alternate mode they
+    /// were given in
+    pub fn release_with_pins(mut self) -> (FTimer<TIM, FREQ>, PINS) {
         // stop counter
         self.tim.cr1_reset();
-        self.timer
+        (self.timer, self.pins)
     }
 }
 
@@ -360,9 +429,13 @@ where
 }
 
 impl<TIM: Instance + WithPwm, const FREQ: u32> FTimer<TIM, FREQ> {
+    /// Starts PWM with period `time`
+    ///
+    /// The period must be at most 65535 ticks for the maximum duty to give a constantly high
+    /// output.
     pub fn pwm<P, PINS>(
         mut self,
-        _pins: PINS,
+        pins: PINS,
         time: TimerDurationU32<FREQ>,
     ) -> Pwm<TIM, P, PINS, FREQ>
     where
@@ -399,7 +472,8 @@ impl<TIM: Instance + WithPwm, const FREQ: u32> FTimer<TIM, FREQ> {
 
         Pwm {
             timer: self,
-            _pins: PhantomData,
+            pins,
+            _p: PhantomData,
         }
     }
 }
@@ -425,6 +499,8 @@ where
         TimerDurationU32::from_ticks(TIM::read_cc_value(PINS::check_used(channel) as u8))
     }
 
+    /// Sets the duty of `channel`, `0` gives a constantly low output and
+    /// [`get_max_duty`](Self::get_max_duty) (or more) a constantly high output
     pub fn set_duty(&mut self, channel: Channel, duty: u16) {
         TIM::set_cc_value(PINS::check_used(channel) as u8, duty.into())
     }
@@ -433,16 +509,79 @@ where
         TIM::set_cc_value(PINS::check_used(channel) as u8, duty.ticks())
     }
 
-    /// If `0` returned means max_duty is 2^16
+    /// Duty giving a constantly high output, `ARR + 1`
     pub fn get_max_duty(&self) -> u16 {
-        (TIM::read_auto_reload() as u16).wrapping_add(1)
+        max_duty::<TIM>()
     }
 
     pub fn get_period(&self) -> TimerDurationU32<FREQ> {
         TimerDurationU32::from_ticks(TIM::read_auto_reload() + 1)
     }
 
+    /// Sets the period, which must be at most 65535 ticks for the maximum duty to give a
+    /// constantly high output
     pub fn set_period(&mut self, period: TimerDurationU32<FREQ>) {
         self.tim.set_auto_reload(period.ticks() - 1).unwrap();
     }
 }
+
+impl<TIM, P, PINS, const FREQ: u32> Pwm<TIM, P, PINS, FREQ>
+where
+    TIM: Instance + WithEtr,
+    PINS: Pins<TIM, P>,
+{
+    /// Clears `OCxREF` of `channel` while the external trigger input is high.
+    ///
+    /// The reference stays low until the next update event, which can be used for
+    /// cycle-by-cycle current limiting. On STM32F4 the `ETRF` signal is the only
+    /// `OCREF_CLR` source, so `etr` configures the shared ETR filter, prescaler and polarity.
+    pub fn enable_ocref_clear(&mut self, channel: Channel, etr: EtrConfig) {
+        let channel = PINS::check_used(channel);
+        self.tim.configure_etr(etr);
+        self.tim.enable_ocref_clear(channel, true);
+    }
+
+    /// `OCxREF` of `channel` is no longer affected by the external trigger input
+    pub fn disable_ocref_clear(&mut self, channel: Channel) {
+        self.tim
+            .enable_ocref_clear(PINS::check_used(channel), false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PWM mode 1 output level
+    fn pwm1_high(cnt: u32, ccr: u32) -> bool {
+        cnt < ccr
+    }
+
+    #[test]
+    fn arr_below_16_bit_duty() {
+        // 1 kHz at 84 MHz
+        assert_eq!(compute_pwm_arr_presc(1_000, 84_000_000), (1, 41_999));
+        // 65535 ticks still fit a single prescaler step
+        assert_eq!(compute_pwm_arr_presc(1_000, 65_535_000), (0, MAX_PWM_ARR));
+        // 65536 ticks would need ARR = 0xFFFF
+        assert_eq!(compute_pwm_arr_presc(1_000, 65_536_000), (1, 32_767));
+        for ticks in [2, 1_000, 65_534, 65_535, 65_536, 131_071, 1_000_000] {
+            let (_, arr) = compute_pwm_arr_presc(1, ticks);
+            assert!(arr <= MAX_PWM_ARR);
+        }
+    }
+
+    #[test]
+    fn duty_at_zero_and_max() {
+        for arr in [1, 999, 41_999, MAX_PWM_ARR] {
+            let max = u32::from(duty_limit(arr));
+            assert_eq!(max, arr + 1);
+            // Counting from 0 to ARR
+            assert!((0..=arr).all(|cnt| pwm1_high(cnt, max)));
+            assert!((0..=arr).all(|cnt| !pwm1_high(cnt, 0)));
+        }
+        // Saturated for 32-bit timers with a longer period
+        assert_eq!(duty_limit(0xFFFF), u16::MAX);
+        assert_eq!(duty_limit(u32::MAX), u16::MAX);
+    }
+}
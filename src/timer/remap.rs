@@ -0,0 +1,346 @@
+//! Internal clock inputs of TIM5 channel 4 and TIM11 channel 1, for measuring oscillators
+//!
+//! `TIM5_OR.TI4_RMP` connects TIM5 channel 4 to LSI, LSE or the RTC wakeup interrupt
+//! instead of its pin, and `TIM11_OR.TI1_RMP` connects TIM11 channel 1 to `HSE_RTC`.
+//! Capturing the edges of these clocks with the timer clock measures either of them
+//! against the other:
+//!
+//! * [`Timer::measure_lsi`] for an accurate IWDG timeout, see
+//!   [`IndependentWatchdog::set_lsi_frequency`](crate::watchdog::IndependentWatchdog::set_lsi_frequency)
+//! * [`Timer::measure_hsi`] to correct HSI with
+//!   [`Rcc::set_hsi_trim`](crate::rcc::Rcc::set_hsi_trim)
+//! * [`Timer::measure_hse_rtc`] to check the HSE frequency
+//!
+//! ```ignore
+//! let mut timer = Timer::new(dp.TIM5, &clocks);
+//! loop {
+//!     let hsi = timer.measure_hsi(32).unwrap();
+//!     let trim = Rcc::hsi_trim();
+//!     if hsi.raw() > 16_040_000 && trim > 0 {
+//!         Rcc::set_hsi_trim(trim - 1);
+//!     } else if hsi.raw() < 15_960_000 && trim < 31 {
+//!         Rcc::set_hsi_trim(trim + 1);
+//!     }
+//! }
+//! ```
+
+use super::{General, Timer};
+use crate::pac::{RCC, TIM11, TIM5};
+use crate::rcc;
+use fugit::HertzU32 as Hertz;
+
+/// Input of TIM5 channel 4
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Ti4Input {
+    /// The channel 4 pin
+    Gpio = 0b00,
+    /// LSI oscillator
+    Lsi = 0b01,
+    /// LSE oscillator
+    Lse = 0b10,
+    /// RTC wakeup interrupt
+    RtcWakeup = 0b11,
+}
+
+/// Input of TIM11 channel 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Ti1Input {
+    /// The channel 1 pin
+    Gpio = 0b00,
+    /// HSE divided by `RTCPRE`
+    HseRtc = 0b10,
+}
+
+/// Measurement error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CaptureError {
+    /// No edge within 10 ms or half the counter range, the clock is not running
+    Timeout,
+    /// An edge was captured before the previous one was read (`CCxOF`)
+    Overcapture,
+}
+
+// SR bits
+const CC1IF: u32 = 1 << 1;
+const CC4IF: u32 = 1 << 4;
+const CC1OF: u32 = 1 << 9;
+const CC4OF: u32 = 1 << 12;
+// CCER bits of channel 1, shifted by 4 per channel
+const CCE: u32 = 1 << 0;
+const CCP: u32 = 1 << 1;
+const CCNP: u32 = 1 << 3;
+// CCMR: CCxS = 01, input capture on TIx, no filter
+const IC: u32 = 0b01;
+// ICxPSC = 11, a capture every 8 edges
+const IC_PSC8: u32 = 0b11 << 2;
+
+const LSE: u64 = 32_768;
+
+/// Sums up the timer cycles between `captures + 1` captures
+fn count_cycles(
+    mask: u32,
+    captures: u16,
+    mut capture: impl FnMut() -> Result<u32, CaptureError>,
+) -> Result<u64, CaptureError> {
+    assert!(captures > 0);
+    let mut last = capture()?;
+    let mut cycles: u64 = 0;
+    for _ in 0..captures {
+        let c = capture()?;
+        cycles += u64::from(c.wrapping_sub(last) & mask);
+        last = c;
+    }
+    Ok(cycles)
+}
+
+/// Waits for `ccif` in the status register for at most `limit` counter cycles
+fn wait_capture(
+    mask: u32,
+    limit: u32,
+    ccif: u32,
+    ccof: u32,
+    mut sr: impl FnMut() -> u32,
+    mut cnt: impl FnMut() -> u32,
+) -> Result<(), CaptureError> {
+    let start = cnt();
+    loop {
+        let flags = sr();
+        if flags & ccof != 0 {
+            return Err(CaptureError::Overcapture);
+        } else if flags & ccif != 0 {
+            return Ok(());
+        } else if cnt().wrapping_sub(start) & mask > limit {
+            return Err(CaptureError::Timeout);
+        }
+    }
+}
+
+impl Timer<TIM5> {
+    /// Connects channel 4 to `input`
+    pub fn set_ti4_input(&mut self, input: Ti4Input) {
+        // TI4_RMP
+        self.tim
+            .or
+            .modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << 6)) | ((input as u32) << 6)) });
+    }
+
+    /// Measures the LSI frequency over `periods` periods of LSI, starting LSI if needed
+    ///
+    /// LSI can run anywhere from 17 to 47 kHz. Blocks for about `periods` × 31 µs.
+    pub fn measure_lsi(&mut self, periods: u16) -> Result<Hertz, CaptureError> {
+        unsafe {
+            // NOTE(unsafe) this reference will only be used for atomic writes with no side effects.
+            let rcc = &(*RCC::ptr());
+            cortex_m::interrupt::free(|_| rcc.csr.modify(|_, w| w.lsion().on()));
+            while rcc.csr.read().lsirdy().bit_is_clear() {}
+        }
+
+        let cycles = self.capture_ch4(Ti4Input::Lsi, periods)?;
+        let lsi = u64::from(self.clk.raw()) * u64::from(periods) / cycles.max(1);
+        Ok(Hertz::from_raw(lsi as u32))
+    }
+
+    /// Measures the HSI frequency by counting timer clock cycles over `lse_periods`
+    /// periods of LSE, which has to be running already
+    ///
+    /// The system clock must be derived from HSI, not HSE, for the result to be
+    /// meaningful. Blocks for `lse_periods` × 30.5 µs, or returns
+    /// [`CaptureError::Timeout`] when LSE is not ready or stops.
+    pub fn measure_hsi(&mut self, lse_periods: u16) -> Result<Hertz, CaptureError> {
+        // NOTE(unsafe) atomic read with no side effects
+        let rcc = unsafe { &(*RCC::ptr()) };
+        if rcc.bdcr.read().lserdy().bit_is_clear() {
+            return Err(CaptureError::Timeout);
+        }
+        let cycles = self.capture_ch4(Ti4Input::Lse, lse_periods)?;
+        let timer_clock = cycles * LSE / u64::from(lse_periods);
+        let hsi = timer_clock * u64::from(rcc::HSI) / u64::from(self.clk.raw());
+        Ok(Hertz::from_raw(hsi as u32))
+    }
+
+    /// Captures rising edges of `source` on channel 4, which is connected back to its pin
+    /// afterwards
+    fn capture_ch4(&mut self, input: Ti4Input, periods: u16) -> Result<u64, CaptureError> {
+        let mask = TIM5::max_auto_reload();
+        self.tim.disable_counter();
+        self.tim.set_prescaler(0);
+        unsafe {
+            self.tim.set_auto_reload_unchecked(mask);
+        }
+        self.tim.trigger_update();
+        self.set_ti4_input(input);
+        self.tim
+            .ccmr2_input()
+            .modify(|r, w| unsafe { w.bits((r.bits() & !(0xff << 8)) | (IC << 8)) });
+        self.tim
+            .ccer
+            .modify(|r, w| unsafe { w.bits((r.bits() & !((CCP | CCNP) << 12)) | (CCE << 12)) });
+        self.tim.sr.write(|w| unsafe { w.bits(!(CC4IF | CC4OF)) });
+        self.tim.enable_counter();
+
+        let limit = (self.clk.raw() / 100).min(mask / 2);
+        let tim = &self.tim;
+        let cycles = count_cycles(mask, periods, || {
+            wait_capture(
+                mask,
+                limit,
+                CC4IF,
+                CC4OF,
+                || tim.sr.read().bits(),
+                || tim.cnt.read().bits(),
+            )?;
+            // Reading CCR4 clears CC4IF
+            Ok(tim.ccr[3].read().bits())
+        });
+
+        self.tim.disable_counter();
+        self.tim
+            .ccer
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(CCE << 12)) });
+        self.set_ti4_input(Ti4Input::Gpio);
+        cycles
+    }
+}
+
+impl Timer<TIM11> {
+    /// Connects channel 1 to `input`
+    pub fn set_ti1_input(&mut self, input: Ti1Input) {
+        // TI1_RMP
+        self.tim
+            .or
+            .modify(|r, w| unsafe { w.bits((r.bits() & !0b11) | input as u32) });
+    }
+
+    /// Measures the `HSE_RTC` frequency, HSE divided by `RTCPRE`, over `periods` × 8 of its
+    /// periods
+    ///
+    /// HSE and `RTCPRE` have to be configured already. Each capture spans 8 periods, which
+    /// leaves enough time to read the captures up to about 1 MHz with a timer clock of 84 MHz.
+    pub fn measure_hse_rtc(&mut self, periods: u16) -> Result<Hertz, CaptureError> {
+        let mask = TIM11::max_auto_reload();
+        self.tim.disable_counter();
+        self.tim.set_prescaler(0);
+        unsafe {
+            self.tim.set_auto_reload_unchecked(mask);
+        }
+        self.tim.trigger_update();
+        self.set_ti1_input(Ti1Input::HseRtc);
+        self.tim
+            .ccmr1_input()
+            .modify(|r, w| unsafe { w.bits((r.bits() & !0xff) | IC | IC_PSC8) });
+        self.tim
+            .ccer
+            .modify(|r, w| unsafe { w.bits((r.bits() & !(CCP | CCNP)) | CCE) });
+        self.tim.sr.write(|w| unsafe { w.bits(!(CC1IF | CC1OF)) });
+        self.tim.enable_counter();
+
+        let limit = (self.clk.raw() / 100).min(mask / 2);
+        let tim = &self.tim;
+        let cycles = count_cycles(mask, periods, || {
+            wait_capture(
+                mask,
+                limit,
+                CC1IF,
+                CC1OF,
+                || tim.sr.read().bits(),
+                || tim.cnt.read().bits(),
+            )?;
+            // Reading CCR1 clears CC1IF
+            Ok(tim.ccr[0].read().bits())
+        });
+
+        self.tim.disable_counter();
+        self.tim
+            .ccer
+            .modify(|r, w| unsafe { w.bits(r.bits() & !CCE) });
+        self.set_ti1_input(Ti1Input::Gpio);
+
+        let hse_rtc = u64::from(self.clk.raw()) * u64::from(periods) * 8 / cycles?.max(1);
+        Ok(Hertz::from_raw(hse_rtc as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_cycles_across_wrap() {
+        let mut captures = [0xffff_fff0, 0x10, 0x30].into_iter();
+        let cycles = count_cycles(u32::MAX, 2, || Ok(captures.next().unwrap()));
+        assert_eq!(cycles, Ok(0x40));
+
+        // 16-bit counter
+        let mut captures = [0xfff0, 0x0010, 0x0030].into_iter();
+        let cycles = count_cycles(0xffff, 2, || Ok(captures.next().unwrap()));
+        assert_eq!(cycles, Ok(0x40));
+    }
+
+    #[test]
+    fn count_cycles_stops_at_error() {
+        let mut n = 0;
+        let cycles = count_cycles(u32::MAX, 4, || {
+            n += 1;
+            if n == 3 {
+                Err(CaptureError::Overcapture)
+            } else {
+                Ok(n * 100)
+            }
+        });
+        assert_eq!(cycles, Err(CaptureError::Overcapture));
+        assert_eq!(n, 3);
+    }
+
+    #[test]
+    fn wait_capture_times_out_without_clock() {
+        let mut cnt = 0xffff_ff00u32;
+        let result = wait_capture(
+            u32::MAX,
+            1000,
+            CC4IF,
+            CC4OF,
+            || 0,
+            || {
+                cnt = cnt.wrapping_add(10);
+                cnt
+            },
+        );
+        assert_eq!(result, Err(CaptureError::Timeout));
+        // 1010 cycles after the first read of the counter
+        assert_eq!(cnt, 0xffff_ff00u32.wrapping_add(10 + 1010));
+    }
+
+    #[test]
+    fn wait_capture_flags() {
+        let cnt = || 0;
+        assert_eq!(
+            wait_capture(0xffff, 10, CC1IF, CC1OF, || CC1IF, cnt),
+            Ok(())
+        );
+        assert_eq!(
+            wait_capture(0xffff, 10, CC1IF, CC1OF, || CC1IF | CC1OF, cnt),
+            Err(CaptureError::Overcapture)
+        );
+        // Flags of the other channels are ignored
+        let mut polls = 0;
+        let result = wait_capture(
+            0xffff,
+            10,
+            CC4IF,
+            CC4OF,
+            || {
+                polls += 1;
+                if polls < 5 {
+                    CC1IF | CC1OF
+                } else {
+                    CC4IF
+                }
+            },
+            cnt,
+        );
+        assert_eq!(result, Ok(()));
+    }
+}
@@ -0,0 +1,118 @@
+//! Millisecond tick counter driven by the SysTick exception
+//!
+//! The SysTick exception handler has to call [`SysTickTimer::on_tick`]:
+//!
+//! ```ignore
+//! use cortex_m_rt::exception;
+//! use stm32f4xx_hal::timer::SysTickTimer;
+//!
+//! let mut ticker = SysTickTimer::new(cp.SYST, &clocks);
+//! let start = SysTickTimer::millis();
+//! ticker.delay_ms(10);
+//!
+//! #[exception]
+//! fn SysTick() {
+//!     SysTickTimer::on_tick();
+//! }
+//! ```
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m::peripheral::SYST;
+
+use crate::rcc::Clocks;
+
+/// Milliseconds since the tick counter was started
+static TICKS: AtomicU32 = AtomicU32::new(0);
+
+/// SysTick reload value giving `tick_hz` ticks per second from the core clock `hclk`
+///
+/// # Panics
+///
+/// When the period does not fit in the 24-bit counter.
+pub const fn reload_value(hclk: u32, tick_hz: u32) -> u32 {
+    let cycles = hclk / tick_hz;
+    assert!(cycles > 0 && cycles <= 1 << 24);
+    cycles - 1
+}
+
+/// SysTick running a 1 ms tick from HCLK
+pub struct SysTickTimer {
+    syst: SYST,
+}
+
+impl SysTickTimer {
+    /// Starts the 1 ms tick from the core clock, with the SysTick exception enabled
+    pub fn new(mut syst: SYST, clocks: &Clocks) -> Self {
+        syst.disable_counter();
+        // The reload value is computed for HCLK, not HCLK / 8
+        syst.set_clock_source(SystClkSource::Core);
+        syst.set_reload(reload_value(clocks.hclk().raw(), 1_000));
+        syst.clear_current();
+        TICKS.store(0, Ordering::Relaxed);
+        syst.enable_interrupt();
+        syst.enable_counter();
+        Self { syst }
+    }
+
+    /// Handler for the SysTick exception
+    #[inline]
+    pub fn on_tick() {
+        TICKS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Milliseconds since the timer was started, wrapping around after 49 days
+    #[inline]
+    pub fn millis() -> u32 {
+        TICKS.load(Ordering::Relaxed)
+    }
+
+    /// Waits for at least `ms` milliseconds
+    ///
+    /// The exception must be able to preempt the caller.
+    pub fn delay_ms(&mut self, ms: u32) {
+        // The first tick may come right away, start counting from it
+        let first = Self::millis();
+        while Self::millis() == first {}
+        let start = Self::millis();
+        while Self::millis().wrapping_sub(start) < ms {}
+    }
+
+    /// Stops the timer and releases the SysTick peripheral
+    pub fn release(mut self) -> SYST {
+        self.syst.disable_interrupt();
+        self.syst.disable_counter();
+        self.syst
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_for_1ms() {
+        assert_eq!(reload_value(168_000_000, 1_000), 167_999);
+        assert_eq!(reload_value(16_000_000, 1_000), 15_999);
+        // Truncated to whole cycles
+        assert_eq!(reload_value(16_000_500, 1_000), 15_999);
+    }
+
+    #[test]
+    fn reload_fills_24_bits() {
+        assert_eq!(reload_value(1 << 24, 1), 0xff_ffff);
+    }
+
+    #[test]
+    #[should_panic]
+    fn reload_too_long() {
+        reload_value((1 << 24) + 1, 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn reload_too_short() {
+        reload_value(999, 1_000);
+    }
+}
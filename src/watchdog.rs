@@ -3,11 +3,15 @@
 use crate::pac::{DBGMCU, IWDG};
 use core::fmt;
 use embedded_hal::watchdog::{Watchdog, WatchdogEnable};
-use fugit::MillisDurationU32 as MilliSeconds;
+use fugit::{HertzU32 as Hertz, MillisDurationU32 as MilliSeconds};
+
+mod monitor;
+pub use monitor::{CheckIns, Monitor, TaskId};
 
 /// Wraps the Independent Watchdog (IWDG) peripheral
 pub struct IndependentWatchdog {
     iwdg: IWDG,
+    lsi: Hertz,
 }
 
 #[cfg(feature = "defmt")]
@@ -28,11 +32,24 @@ const MAX_RL: u16 = 0xFFF;
 const KR_ACCESS: u16 = 0x5555;
 const KR_RELOAD: u16 = 0xAAAA;
 const KR_START: u16 = 0xCCCC;
+const LSI_TYPICAL: u32 = 32_000;
 
 impl IndependentWatchdog {
     /// Wrap and start the watchdog
     pub fn new(iwdg: IWDG) -> Self {
-        IndependentWatchdog { iwdg }
+        IndependentWatchdog {
+            iwdg,
+            lsi: Hertz::from_raw(LSI_TYPICAL),
+        }
+    }
+
+    /// Uses `lsi` instead of the typical 32 kHz to compute the reload values
+    ///
+    /// LSI varies from 17 to 47 kHz between parts, measuring it with
+    /// [`Timer::measure_lsi`](crate::timer::Timer::measure_lsi) makes the timeout accurate.
+    /// Applies to the next [`start`](Self::start).
+    pub fn set_lsi_frequency(&mut self, lsi: Hertz) {
+        self.lsi = lsi;
     }
 
     /// Debug independent watchdog stopped when core is halted
@@ -42,11 +59,11 @@ impl IndependentWatchdog {
 
     fn setup(&self, timeout_ms: u32) {
         let mut pr = 0;
-        while pr < MAX_PR && Self::timeout_period(pr, MAX_RL) < timeout_ms {
+        while pr < MAX_PR && self.timeout_period(pr, MAX_RL) < timeout_ms {
             pr += 1;
         }
 
-        let max_period = Self::timeout_period(pr, MAX_RL);
+        let max_period = self.timeout_period(pr, MAX_RL);
         let max_rl = u32::from(MAX_RL);
         let rl = (timeout_ms * max_rl / max_period).min(max_rl) as u16;
 
@@ -66,14 +83,14 @@ impl IndependentWatchdog {
 
         let pr = self.iwdg.pr.read().pr().bits();
         let rl = self.iwdg.rlr.read().rl().bits();
-        let ms = Self::timeout_period(pr, rl);
+        let ms = self.timeout_period(pr, rl);
         MilliSeconds::from_ticks(ms)
     }
 
     /// pr: Prescaler divider bits, rl: reload value
     ///
     /// Returns ms
-    fn timeout_period(pr: u8, rl: u16) -> u32 {
+    fn timeout_period(&self, pr: u8, rl: u16) -> u32 {
         let divider: u32 = match pr {
             0b000 => 4,
             0b001 => 8,
@@ -85,7 +102,8 @@ impl IndependentWatchdog {
             0b111 => 256,
             _ => unreachable!(),
         };
-        (u32::from(rl) + 1) * divider / 32
+        let ms = (u64::from(rl) + 1) * u64::from(divider) * 1000 / u64::from(self.lsi.raw());
+        ms as u32
     }
 
     fn access_registers<A, F: FnMut(&IWDG) -> A>(&self, mut f: F) -> A {
@@ -0,0 +1,160 @@
+//! Task monitor feeding the IWDG only while all tasks are alive
+//!
+//! Each task checks in regularly through a shared [`CheckIns`], from thread mode or from
+//! interrupts. The idle loop calls [`Monitor::service`], which feeds the watchdog only when
+//! every task checked in within its own window. Otherwise the watchdog resets the device,
+//! and the starved task can be recorded in an RTC backup register to be read after the
+//! reset.
+//!
+//! ```ignore
+//! static CHECKINS: CheckIns = CheckIns::new();
+//!
+//! let mut iwdg = IndependentWatchdog::new(dp.IWDG);
+//! iwdg.start(500.millis());
+//! let mut monitor = Monitor::<2>::new(iwdg, &CHECKINS);
+//! let sensor = monitor.register(100, now_ms());
+//! let comms = monitor.register(1000, now_ms());
+//!
+//! if let Some(task) = Monitor::<2>::take_starved(&mut rtc, 0) {
+//!     // `task` starved before the last reset
+//! }
+//!
+//! // In the sensor interrupt
+//! CHECKINS.checkin(sensor);
+//!
+//! // In the idle loop
+//! let _ = monitor.service_recording(now_ms(), &mut rtc, 0);
+//! ```
+
+use super::IndependentWatchdog;
+use crate::rtc::Rtc;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Marker of a starved task in the upper half of the backup register
+const STARVED: u32 = 0x57A4_0000;
+
+/// Handle of a task registered in a [`Monitor`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TaskId(u8);
+
+impl TaskId {
+    /// Index of the task, in registration order
+    pub fn index(self) -> u8 {
+        self.0
+    }
+}
+
+/// Check-ins of the tasks since the last [`Monitor::service`], shared with the tasks
+pub struct CheckIns(AtomicU32);
+
+impl CheckIns {
+    /// No task checked in
+    pub const fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+
+    /// Records that `task` is alive, can be called from any context
+    #[inline]
+    pub fn checkin(&self, task: TaskId) {
+        self.0.fetch_or(1 << task.0, Ordering::Release);
+    }
+}
+
+impl Default for CheckIns {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Monitor of up to `N` tasks, at most 32, each with its own check-in window
+pub struct Monitor<const N: usize> {
+    iwdg: IndependentWatchdog,
+    checkins: &'static CheckIns,
+    windows: [u32; N],
+    last_seen: [u32; N],
+    len: usize,
+}
+
+impl<const N: usize> Monitor<N> {
+    /// Monitors tasks through `checkins`, `iwdg` has to be started already
+    pub fn new(iwdg: IndependentWatchdog, checkins: &'static CheckIns) -> Self {
+        assert!(N <= 32);
+        Self {
+            iwdg,
+            checkins,
+            windows: [0; N],
+            last_seen: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Registers a task, which has to check in every `window` time units, `now` being the
+    /// current time in the same units as passed to [`service`](Self::service)
+    ///
+    /// # Panics
+    ///
+    /// When `N` tasks are registered already.
+    pub fn register(&mut self, window: u32, now: u32) -> TaskId {
+        assert!(self.len < N, "Too many tasks.");
+        let id = self.len;
+        self.windows[id] = window;
+        self.last_seen[id] = now;
+        self.len += 1;
+        TaskId(id as u8)
+    }
+
+    /// Feeds the watchdog if every task checked in within its window, otherwise returns
+    /// the first starved task and lets the watchdog expire
+    ///
+    /// `now` is a free-running time, e.g. in milliseconds, wrapping around is supported.
+    pub fn service(&mut self, now: u32) -> Result<(), TaskId> {
+        let seen = self.checkins.0.swap(0, Ordering::Acquire);
+        let mut starved = None;
+        for i in 0..self.len {
+            if seen & (1 << i) != 0 {
+                self.last_seen[i] = now;
+            } else if now.wrapping_sub(self.last_seen[i]) > self.windows[i] && starved.is_none() {
+                starved = Some(TaskId(i as u8));
+            }
+        }
+
+        match starved {
+            Some(task) => Err(task),
+            None => {
+                self.iwdg.feed();
+                Ok(())
+            }
+        }
+    }
+
+    /// Same as [`service`](Self::service), also recording a starved task in the backup
+    /// register `index` of `rtc`
+    pub fn service_recording<CS>(
+        &mut self,
+        now: u32,
+        rtc: &mut Rtc<CS>,
+        index: usize,
+    ) -> Result<(), TaskId> {
+        self.service(now).map_err(|task| {
+            rtc.write_backup_register(index, STARVED | u32::from(task.0));
+            task
+        })
+    }
+
+    /// Returns the task recorded by [`service_recording`](Self::service_recording) before the
+    /// last reset, and clears the record
+    pub fn take_starved<CS>(rtc: &mut Rtc<CS>, index: usize) -> Option<TaskId> {
+        let value = rtc.read_backup_register(index);
+        if value & 0xffff_0000 != STARVED {
+            return None;
+        }
+        rtc.write_backup_register(index, 0);
+        Some(TaskId(value as u8))
+    }
+
+    /// Releases the watchdog, which keeps running
+    pub fn release(self) -> IndependentWatchdog {
+        self.iwdg
+    }
+}